@@ -0,0 +1,171 @@
+//! This module contains code that generates shadowsocks-rust ACL (access control list) files
+//! from per-profile proxy and bypass rules.
+//!
+//! See https://github.com/shadowsocks/shadowsocks-rust#acl.
+
+use std::{fmt, net::IpAddr};
+
+use ipnet::IpNet;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{io::gfwlist, pac::PacRules};
+
+/// A single rule, matching either a network, or a domain and all its subdomains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum AclEntry {
+    Network(IpNet),
+    Domain(String),
+}
+
+impl fmt::Display for AclEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(net) => write!(f, "{}", net),
+            Self::Domain(domain) => write!(f, "{}", domain),
+        }
+    }
+}
+
+impl TryFrom<String> for AclEntry {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let s = s.trim();
+        if let Ok(net) = s.parse() {
+            return Ok(Self::Network(net));
+        }
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::Network(ip.into()));
+        }
+        // wildcards are implied
+        let domain = s.trim_start_matches("*.").trim_start_matches('.').to_ascii_lowercase();
+        let is_domain = !domain.is_empty()
+            && !domain.ends_with('.')
+            && !domain.contains("..")
+            && domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        match is_domain {
+            true => Ok(Self::Domain(domain)),
+            false => Err(format!("\"{}\" is neither a network nor a domain", s)),
+        }
+    }
+}
+
+impl From<AclEntry> for String {
+    fn from(entry: AclEntry) -> Self {
+        entry.to_string()
+    }
+}
+
+impl AclEntry {
+    /// The line of this entry in an ACL file.
+    fn to_acl_line(&self) -> String {
+        match self {
+            Self::Network(net) => net.to_string(),
+            // domains are matched with regular expressions
+            Self::Domain(domain) => format!(r"(^|\.){}$", domain.replace('.', r"\.")),
+        }
+    }
+}
+
+/// What to do with traffic that matches no rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AclMode {
+    /// Proxy everything except the bypass list.
+    #[default]
+    ProxyAll,
+    /// Bypass everything except the proxy list.
+    BypassAll,
+}
+
+/// The routing rules of a profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclRules {
+    #[serde(default)]
+    pub mode: AclMode,
+    /// Traffic matching these always goes through the proxy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy: Vec<AclEntry>,
+    /// Traffic matching these always goes direct. Takes precedence over `proxy`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bypass: Vec<AclEntry>,
+    /// Also use the domains of the cached gfwlist, which is kept up to date.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub gfwlist: bool,
+}
+
+impl AclRules {
+    /// Add the domains of gfwlist-style rules, which lists what needs to be proxied.
+    ///
+    /// Domains already present are skipped.
+    pub fn import_pac_rules(&mut self, rules: PacRules) {
+        let add = |list: &mut Vec<AclEntry>, domains| {
+            for domain in domains {
+                let entry = AclEntry::Domain(domain);
+                if !list.contains(&entry) {
+                    list.push(entry);
+                }
+            }
+        };
+        add(&mut self.proxy, rules.proxy_domains);
+        add(&mut self.bypass, rules.direct_domains);
+    }
+
+    /// Get a copy of these rules with the domains of the cached gfwlist added, if `gfwlist` is set.
+    pub fn with_gfwlist(&self) -> Self {
+        let mut rules = self.clone();
+        if self.gfwlist {
+            match gfwlist::cached_rules() {
+                Some(pac_rules) => rules.import_pac_rules(pac_rules),
+                None => warn!("gfwlist has not been fetched yet; only the listed rules are used"),
+            }
+        }
+        rules
+    }
+
+    /// Generate the content of an ACL file.
+    pub fn to_acl(&self) -> String {
+        let mut acl = format!("# Generated by {}\n\n", crate::consts::APP_NAME);
+        acl += match self.mode {
+            AclMode::ProxyAll => "[proxy_all]\n",
+            AclMode::BypassAll => "[bypass_all]\n",
+        };
+        for (header, list) in [("[bypass_list]", &self.bypass), ("[proxy_list]", &self.proxy)] {
+            if list.is_empty() {
+                continue;
+            }
+            acl += &format!("\n{}\n", header);
+            for entry in list {
+                acl += &entry.to_acl_line();
+                acl += "\n";
+            }
+        }
+        acl
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AclEntry, AclMode, AclRules};
+    use crate::pac::PacRules;
+
+    #[test]
+    fn generate_acl() {
+        let yaml = "mode: bypass-all\nproxy: [\"*.Google.com\", 8.8.8.8]\nbypass: [192.168.0.0/16]\n";
+        let mut rules: AclRules = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(rules.mode, AclMode::BypassAll);
+        assert_eq!(rules.proxy[0], AclEntry::Domain("google.com".into()));
+        assert_eq!(rules.proxy[1], AclEntry::Network("8.8.8.8/32".parse().unwrap()));
+        assert!(AclEntry::try_from("not a domain".to_string()).is_err());
+
+        rules.import_pac_rules(PacRules::parse("||google.com\n||twitter.com\n@@||cn.bing.com"));
+        let acl = rules.to_acl();
+        assert!(acl.contains("[bypass_all]\n"));
+        assert!(acl.contains("\n[bypass_list]\n192.168.0.0/16\n(^|\\.)cn\\.bing\\.com$\n"));
+        assert!(acl.ends_with("\n[proxy_list]\n(^|\\.)google\\.com$\n8.8.8.8/32\n(^|\\.)twitter\\.com$\n"));
+        assert!(AclRules::default().to_acl().ends_with("[proxy_all]\n"));
+    }
+}
@@ -0,0 +1,184 @@
+//! This module contains predefined shared constants.
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+
+// Static strings
+// ========================================
+
+/// The application's name.
+///
+/// For now, the display name and the OS name (no-space-name used for directories)
+/// are identical.
+pub const APP_NAME: &str = "shadowsocks-gtk-rs";
+
+/// The default name of the directory under the XDG config directory
+/// which contains all profiles.
+pub const PROFILES_DIR_NAME_DEFAULT: &str = "profiles";
+
+/// The default name of the directory under the XDG config directory
+/// which contains profiles to create new profiles from.
+pub const TEMPLATES_DIR_NAME_DEFAULT: &str = "templates";
+
+/// The default name of the state file under the XDG state directory.
+pub const STATE_FILE_NAME_DEFAULT: &str = "app-state.yaml";
+
+/// The default name of the settings file under the XDG config directory.
+pub const SETTINGS_FILE_NAME_DEFAULT: &str = "app-settings.yaml";
+
+/// The default name of the socket file under the XDG runtime directory
+/// used for the runtime API.
+#[cfg(feature = "runtime-api")]
+pub const RUNTIME_API_SOCKET_NAME_DEFAULT: &str = "shadowsocks-gtk-rs.sock";
+
+/// The existence of this file in a directory indicates that
+/// this directory is a launch profile.
+///
+/// This is also the file written for new profiles.
+pub const PROFILE_CONFIG_FILE_NAME: &str = "profile.yaml";
+
+/// Same as `PROFILE_CONFIG_FILE_NAME`, but in TOML.
+pub const PROFILE_CONFIG_TOML_FILE_NAME: &str = "profile.toml";
+
+/// Same as `PROFILE_CONFIG_FILE_NAME`, but in JSON (or JSON5).
+pub const PROFILE_CONFIG_JSON_FILE_NAME: &str = "profile.json";
+
+/// Appended to the name of a profile config file encrypted with the master passphrase,
+/// e.g. `profile.yaml.enc`.
+pub const ENCRYPTED_CONFIG_FILE_SUFFIX: &str = ".enc";
+
+/// Appended to the name of a profile config file in the legacy format when it is migrated,
+/// e.g. `profile.yaml.legacy`, to keep the original.
+pub const LEGACY_CONFIG_BACKUP_SUFFIX: &str = ".legacy";
+
+/// The existence of this file in a directory marks the directory
+/// as ignored during the loading process.
+pub const PROFILE_IGNORE_FILE_NAME: &str = ".ss_ignore";
+
+/// Profiles deleted from the GUI can be archived into this directory under the profiles directory,
+/// which is ignored during the loading process.
+pub const ARCHIVED_PROFILES_DIR_NAME: &str = "archived";
+
+/// The existence of this file in a group directory marks the group
+/// as a failover set, whose profiles are tried in turn when one fails.
+pub const PROFILE_FAILOVER_FILE_NAME: &str = ".ss_failover";
+
+/// The existence of this file in a group directory defines
+/// the order of its entries, and which ones are pinned to the top.
+pub const PROFILE_ORDER_FILE_NAME: &str = "order.yaml";
+
+/// The existence of this file in the base profiles directory defines
+/// additional groups and profiles, all in a single file.
+pub const PROFILE_MANIFEST_FILE_NAME: &str = "profiles.yaml";
+
+/// The existence of this file in the base profiles directory defines
+/// a list of SIP008 online config subscriptions.
+pub const SUBSCRIPTION_FILE_NAME: &str = "subscription.yaml";
+
+/// The name of the directory under the XDG cache directory
+/// where fetched subscriptions are stored.
+pub const SUBSCRIPTION_CACHE_DIR_NAME: &str = "subscriptions";
+
+/// The name under which the PAC file is served by the PAC server.
+pub const PAC_FILE_NAME: &str = "proxy.pac";
+
+/// The name of the file under the XDG cache directory
+/// where the fetched gfwlist is stored.
+pub const GFWLIST_CACHE_FILE_NAME: &str = "gfwlist.txt";
+
+/// The name of the directory under the XDG state directory
+/// where per-profile log files are written.
+pub const LOG_FILES_DIR_NAME: &str = "logs";
+
+/// The name of the directory under the XDG runtime directory
+/// where `sslocal` config files generated for "Balanced"-type profiles are written.
+pub const BALANCED_CONFIGS_DIR_NAME: &str = "balanced";
+
+/// The name of the directory under the XDG runtime directory
+/// where `sslocal` config files adding an HTTP proxy to "Proxy"-type profiles are written.
+pub const HTTP_LOCAL_CONFIGS_DIR_NAME: &str = "http-local";
+
+/// The name of the directory under the XDG runtime directory
+/// where ACL files generated from the `acl` field of profiles are written.
+pub const ACL_FILES_DIR_NAME: &str = "acl";
+
+/// The default binary to lookup in $PATH, if not overridden by profile.
+pub const SSLOCAL_LOOKUP_NAME_DEFAULT: &str = "sslocal";
+
+/// The `shadowsocks-libev` binary to lookup in $PATH, if not overridden by profile.
+pub const SS_LIBEV_LOOKUP_NAME_DEFAULT: &str = "ss-local";
+
+/// The `go-shadowsocks2` binary to lookup in $PATH, if not overridden by profile.
+pub const GO_SS2_LOOKUP_NAME_DEFAULT: &str = "go-shadowsocks2";
+
+/// The privileged helper binary, looked up next to `ssgtk`, then in $PATH.
+pub const PRIVILEGED_HELPER_LOOKUP_NAME: &str = "ssgtk-helper";
+
+/// The ID the app is registered with as a `GtkApplication`, which is also its bus name.
+pub const APP_ID: &str = "io.github.shadowsocks_gtk_rs.ssgtk";
+
+/// The name of the desktop entry written into the XDG autostart directory.
+pub const AUTOSTART_FILE_NAME: &str = "shadowsocks-gtk-rs.desktop";
+
+/// The name of the systemd user unit written by `ssgtk --install-service`.
+pub const USER_SERVICE_FILE_NAME: &str = "ssgtk.service";
+
+// Hard-coded constants
+// ========================================
+
+/// The well-known D-Bus name of the StatusNotifierItem host that tray icons register with.
+pub const SNI_WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+
+/// Default logging level for the CLI logger.
+///
+/// 0: `Error`, 1: `Warn`, 2: `Info`, 3: `Debug`, 4: `Trace`
+pub const DEFAULT_LOG_LEVEL: i32 = 2;
+
+/// Timeout for all outgoing HTTP requests.
+pub const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Default buffer size for a `bus::Bus`.
+pub const BUS_BUFFER_SIZE: usize = 20;
+
+/// The local address assigned to profiles imported from sources
+/// that do not specify one, e.g. a SIP002 `ss://` URL.
+pub const IMPORT_LOCAL_ADDR_DEFAULT: (IpAddr, u16) = (IpAddr::V4(Ipv4Addr::LOCALHOST), 1080);
+
+/// How many levels of groups are loaded below the profiles directory,
+/// unless set otherwise in the app settings.
+pub const PROFILE_LOAD_DEPTH_DEFAULT: usize = 16;
+
+// Static runtime paths
+// ========================================
+
+lazy_static! {
+    pub static ref XDG_DIRS: xdg::BaseDirectories = xdg::BaseDirectories::with_prefix(APP_NAME).expect("XDG error");
+    pub static ref PROFILES_DIR_PATH_DEFAULT: PathBuf = XDG_DIRS.get_config_file(PROFILES_DIR_NAME_DEFAULT);
+    pub static ref TEMPLATES_DIR_PATH_DEFAULT: PathBuf = XDG_DIRS.get_config_file(TEMPLATES_DIR_NAME_DEFAULT);
+    pub static ref STATE_FILE_PATH_DEFAULT: PathBuf = XDG_DIRS.get_state_file(STATE_FILE_NAME_DEFAULT);
+    pub static ref SETTINGS_FILE_PATH_DEFAULT: PathBuf = XDG_DIRS.get_config_file(SETTINGS_FILE_NAME_DEFAULT);
+    /// See https://specifications.freedesktop.org/autostart-spec/autostart-spec-latest.html.
+    pub static ref AUTOSTART_FILE_PATH: PathBuf = xdg::BaseDirectories::new()
+        .expect("XDG error")
+        .get_config_home()
+        .join("autostart")
+        .join(AUTOSTART_FILE_NAME);
+    /// See https://www.freedesktop.org/software/systemd/man/systemd.unit.html#User%20Unit%20Search%20Path.
+    pub static ref USER_SERVICE_FILE_PATH: PathBuf = xdg::BaseDirectories::new()
+        .expect("XDG error")
+        .get_config_home()
+        .join("systemd/user")
+        .join(USER_SERVICE_FILE_NAME);
+}
+
+#[cfg(feature = "runtime-api")]
+lazy_static! {
+    pub static ref RUNTIME_API_SOCKET_PATH_DEFAULT: PathBuf = XDG_DIRS
+        .get_runtime_file(RUNTIME_API_SOCKET_NAME_DEFAULT)
+        .expect("Error accessing XDG runtime directory");
+}
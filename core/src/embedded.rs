@@ -0,0 +1,426 @@
+//! This module contains code that runs a shadowsocks client in-process,
+//! for profiles using `Backend::Embedded`, so that no `sslocal` binary is needed.
+//!
+//! Only a SOCKS5 proxy with the `CONNECT` command is served. Log lines are written
+//! like `sslocal`'s output, so that they are handled the same way.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    net::SocketAddr,
+    os::unix::{net::UnixStream, process::ExitStatusExt},
+    pin::Pin,
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, RwLock,
+    },
+    task::{Context as TaskContext, Poll},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::util::{self, mutex_lock};
+use log::{debug, trace, warn};
+use shadowsocks::{
+    config::ServerType,
+    context::{Context, SharedContext},
+    relay::socks5::{
+        Address, Command, HandshakeRequest, HandshakeResponse, Reply, TcpRequestHeader, TcpResponseHeader,
+        SOCKS5_AUTH_METHOD_NONE, SOCKS5_AUTH_METHOD_NOT_ACCEPTABLE,
+    },
+    ProxyClientStream, ServerConfig,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    runtime,
+    sync::oneshot,
+};
+
+use crate::stats::TrafficStats;
+
+/// How often traffic is recorded into the stats sink, same as `sslocal`'s reports.
+const STATS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Used to tell instances apart, since they all share the PID of `ssgtk`.
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// What is needed to run an embedded client, see `Profile::embedded_config`.
+#[derive(Debug, Clone)]
+pub struct EmbeddedConfig {
+    pub local_addr: SocketAddr,
+    pub server: ServerConfig,
+}
+
+/// Counters shared by all connections of a client.
+#[derive(Debug, Default)]
+struct Counters {
+    tx_bytes: AtomicU64,
+    rx_bytes: AtomicU64,
+    connections: AtomicUsize,
+}
+
+/// A shadowsocks client running on a dedicated thread.
+///
+/// Unlike a subprocess, it is not stopped when dropped; call `Self::stop` first.
+#[derive(Debug)]
+pub struct EmbeddedClient {
+    id: u32,
+    counters: Arc<Counters>,
+    /// Set once the client has exited.
+    exit_status: Arc<(Mutex<Option<ExitStatus>>, Condvar)>,
+    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl fmt::Display for EmbeddedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "embedded #{}", self.id)
+    }
+}
+
+impl EmbeddedClient {
+    /// Start serving on `config.local_addr`.
+    ///
+    /// Log lines are written into `log`. If `stats_sink` is set, traffic is recorded into it,
+    /// and it is reset when the client exits.
+    pub fn start(
+        config: EmbeddedConfig,
+        log: UnixStream,
+        stats_sink: Option<Arc<RwLock<TrafficStats>>>,
+    ) -> io::Result<Self> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let rt = runtime::Builder::new_current_thread().enable_all().build()?;
+        // bind now, so that the caller is told if it fails
+        let listener = rt.block_on(TcpListener::bind(config.local_addr))?;
+
+        let counters = Arc::new(Counters::default());
+        let exit_status = Arc::new((Mutex::new(None), Condvar::new()));
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let counters_mv = Arc::clone(&counters);
+        let exit_status_mv = Arc::clone(&exit_status);
+        let thread = thread::Builder::new()
+            .name(format!("embedded client #{}", id))
+            .spawn(move || {
+                let logger = Logger(Arc::new(Mutex::new(log)));
+                logger.log(
+                    "INFO",
+                    format_args!("shadowsocks local listening on {}", config.local_addr),
+                );
+                let res = rt.block_on(async {
+                    if let Some(sink) = &stats_sink {
+                        *util::rwlock_write(sink) = TrafficStats::default();
+                        tokio::spawn(record_stats(Arc::clone(&counters_mv), Arc::clone(sink)));
+                    }
+                    let server = Arc::new(config.server);
+                    tokio::select! {
+                        res = serve(listener, server, Arc::clone(&counters_mv), logger.clone()) => res,
+                        _ = shutdown_rx => Ok(()),
+                    }
+                });
+                // cancels all connections
+                drop(rt);
+                if let Some(sink) = &stats_sink {
+                    *util::rwlock_write(sink) = TrafficStats::default();
+                }
+                let status = match res {
+                    Ok(_) => {
+                        logger.log("INFO", format_args!("shadowsocks local stopped"));
+                        ExitStatus::from_raw(0)
+                    }
+                    Err(err) => {
+                        logger.log("ERROR", format_args!("shadowsocks local failed: {}", err));
+                        ExitStatus::from_raw(1 << 8) // exit code 1
+                    }
+                };
+                let (lock, cvar) = &*exit_status_mv;
+                *mutex_lock(lock) = Some(status);
+                cvar.notify_all();
+            })?;
+
+        debug!("Embedded client #{} started on {}", id, config.local_addr);
+        Ok(Self {
+            id,
+            counters,
+            exit_status,
+            shutdown_tx: Mutex::new(Some(shutdown_tx)),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Ask the client to stop, closing all connections. Does not wait for it.
+    pub fn stop(&self) {
+        if let Some(tx) = mutex_lock(&self.shutdown_tx).take() {
+            // fails if the client has already exited
+            let _ = tx.send(());
+        }
+    }
+
+    /// Get the exit status if the client has exited.
+    pub fn try_wait(&self) -> Option<ExitStatus> {
+        *mutex_lock(&self.exit_status.0)
+    }
+
+    /// Wait for the client to exit, then clean up its thread.
+    pub fn wait(&self) -> ExitStatus {
+        let status = {
+            let (lock, cvar) = &*self.exit_status;
+            let mut guard = mutex_lock(lock);
+            loop {
+                match *guard {
+                    Some(s) => break s,
+                    None => guard = cvar.wait(guard).unwrap(),
+                }
+            }
+        };
+        if let Some(handle) = mutex_lock(&self.thread).take() {
+            if let Err(err) = handle.join() {
+                warn!("Embedded client #{} panicked: {:?}", self.id, err);
+            }
+        }
+        status
+    }
+
+    /// How many connections are currently open.
+    pub fn connections(&self) -> usize {
+        self.counters.connections.load(Ordering::Relaxed)
+    }
+}
+
+/// Writes log lines with a level like `sslocal`'s, e.g. `ERROR connect to ... failed`.
+#[derive(Debug, Clone)]
+struct Logger(Arc<Mutex<UnixStream>>);
+
+impl Logger {
+    fn log(&self, level: &str, msg: fmt::Arguments) {
+        let line = format!("{:<5} {}\n", level, msg);
+        trace!("Embedded client: {}", line.trim_end());
+        if let Err(err) = mutex_lock(&self.0).write_all(line.as_bytes()) {
+            trace!("Cannot write log of embedded client: {}", err);
+        }
+    }
+}
+
+/// Accept and handle SOCKS5 connections until an error occurs.
+async fn serve(
+    listener: TcpListener,
+    server: Arc<ServerConfig>,
+    counters: Arc<Counters>,
+    logger: Logger,
+) -> io::Result<()> {
+    let context = Context::new_shared(ServerType::Local);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let context = Arc::clone(&context);
+        let server = Arc::clone(&server);
+        let counters = Arc::clone(&counters);
+        let logger = logger.clone();
+        tokio::spawn(async move {
+            counters.connections.fetch_add(1, Ordering::Relaxed);
+            if let Err(err) = handle_socks5(stream, context, &server, &counters).await {
+                logger.log("ERROR", format_args!("socks5 connection from {} failed: {}", peer, err));
+            }
+            counters.connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Serve one SOCKS5 connection, relaying it through the shadowsocks server.
+async fn handle_socks5(
+    mut stream: TcpStream,
+    context: SharedContext,
+    server: &ServerConfig,
+    counters: &Arc<Counters>,
+) -> io::Result<()> {
+    let handshake = HandshakeRequest::read_from(&mut stream).await?;
+    if !handshake.methods.contains(&SOCKS5_AUTH_METHOD_NONE) {
+        HandshakeResponse::new(SOCKS5_AUTH_METHOD_NOT_ACCEPTABLE)
+            .write_to(&mut stream)
+            .await?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "client requires authentication",
+        ));
+    }
+    HandshakeResponse::new(SOCKS5_AUTH_METHOD_NONE)
+        .write_to(&mut stream)
+        .await?;
+
+    let request = TcpRequestHeader::read_from(&mut stream).await?;
+    let unspecified = Address::SocketAddress(SocketAddr::from(([0, 0, 0, 0], 0)));
+    if !matches!(request.command, Command::TcpConnect) {
+        TcpResponseHeader::new(Reply::CommandNotSupported, unspecified)
+            .write_to(&mut stream)
+            .await?;
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("command {:?} is not supported", request.command),
+        ));
+    }
+
+    let target = request.address;
+    let mut remote = match ProxyClientStream::connect(context, server, target.clone()).await {
+        Ok(remote) => remote,
+        Err(err) => {
+            TcpResponseHeader::new(Reply::HostUnreachable, unspecified)
+                .write_to(&mut stream)
+                .await?;
+            return Err(io::Error::new(
+                err.kind(),
+                format!("connect to {} failed: {}", target, err),
+            ));
+        }
+    };
+    TcpResponseHeader::new(Reply::Succeeded, unspecified)
+        .write_to(&mut stream)
+        .await?;
+
+    let mut local = Counted {
+        inner: stream,
+        counters: Arc::clone(counters),
+    };
+    tokio::io::copy_bidirectional(&mut local, &mut remote).await?;
+    Ok(())
+}
+
+/// Periodically record the traffic counters into `sink`.
+async fn record_stats(counters: Arc<Counters>, sink: Arc<RwLock<TrafficStats>>) {
+    let mut interval = tokio::time::interval(STATS_INTERVAL);
+    loop {
+        interval.tick().await;
+        let tx = counters.tx_bytes.load(Ordering::Relaxed);
+        let rx = counters.rx_bytes.load(Ordering::Relaxed);
+        util::rwlock_write(&sink).record(tx, rx);
+    }
+}
+
+/// A local stream that counts what the client has sent and received.
+struct Counted {
+    inner: TcpStream,
+    counters: Arc<Counters>,
+}
+
+impl AsyncRead for Counted {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - before) as u64;
+        self.counters.tx_bytes.fetch_add(read, Ordering::Relaxed);
+        res
+    }
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = res {
+            self.counters.rx_bytes.fetch_add(written as u64, Ordering::Relaxed);
+        }
+        res
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{self, SocketAddr},
+        os::unix::net::UnixStream,
+        sync::{Arc, RwLock},
+        thread,
+        time::Duration,
+    };
+
+    use shadowsocks::{config::ServerType, context::Context, crypto::CipherKind, ProxyListener, ServerConfig};
+    use tokio::{net::TcpStream, runtime};
+
+    use super::{EmbeddedClient, EmbeddedConfig};
+    use crate::stats::TrafficStats;
+
+    /// Find a free local port.
+    fn free_addr() -> SocketAddr {
+        net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    /// Start a shadowsocks server serving one connection, and an echo server as its target.
+    fn start_servers(server: &ServerConfig) -> SocketAddr {
+        let echo = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = echo.accept().unwrap();
+            let mut buf = [0; 64];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&buf[..n]).unwrap();
+        });
+
+        let server = server.clone();
+        let rt = runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let context = Context::new_shared(ServerType::Server);
+        let listener = rt.block_on(ProxyListener::bind(context, &server)).unwrap();
+        thread::spawn(move || {
+            rt.block_on(async {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let target = stream.handshake().await.unwrap();
+                let mut remote = TcpStream::connect(target.to_string()).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut stream, &mut remote).await;
+            });
+        });
+        echo_addr
+    }
+
+    #[test]
+    fn socks5_relay() {
+        let server = ServerConfig::new(free_addr(), "pw", CipherKind::AES_128_GCM).unwrap();
+        let echo_addr = start_servers(&server);
+
+        let local_addr = free_addr();
+        let (log_tx, log_rx) = UnixStream::pair().unwrap();
+        let sink = Arc::new(RwLock::new(TrafficStats::default()));
+        let config = EmbeddedConfig { local_addr, server };
+        let client = EmbeddedClient::start(config, log_tx, Some(Arc::clone(&sink))).unwrap();
+
+        // SOCKS5 handshake, then CONNECT to the echo server
+        let mut stream = net::TcpStream::connect(local_addr).unwrap();
+        stream.write_all(&[5, 1, 0]).unwrap();
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, [5, 0]);
+        let ip = match echo_addr {
+            SocketAddr::V4(v4) => v4.ip().octets(),
+            _ => unreachable!(),
+        };
+        let mut request = vec![5, 1, 0, 1];
+        request.extend_from_slice(&ip);
+        request.extend_from_slice(&echo_addr.port().to_be_bytes());
+        stream.write_all(&request).unwrap();
+        let mut reply = [0; 10];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[1], 0, "SOCKS5 reply is not \"succeeded\"");
+
+        stream.write_all(b"hello").unwrap();
+        let mut echoed = [0; 5];
+        stream.read_exact(&mut echoed).unwrap();
+        assert_eq!(&echoed, b"hello");
+        assert_eq!(client.connections(), 1);
+
+        thread::sleep(Duration::from_millis(600));
+        let stats = *sink.read().unwrap();
+        assert_eq!((stats.tx_bytes, stats.rx_bytes), (5, 5));
+
+        drop(stream);
+        client.stop();
+        assert!(client.wait().success());
+        assert!(sink.read().unwrap().updated_at.is_none());
+        let lines: Vec<_> = BufReader::new(log_rx).lines().map(Result::unwrap).collect();
+        assert!(lines[0].starts_with("INFO  shadowsocks local listening on"));
+        assert!(lines.last().unwrap().contains("stopped"));
+    }
+}
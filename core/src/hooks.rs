@@ -0,0 +1,155 @@
+//! This module contains code that runs the hook scripts of a profile around the lifecycle of `sslocal`,
+//! e.g. to set up routes, notify other tools, or mount things.
+//!
+//! Hooks run as the user, in the profile's working directory, with the profile described in `SSGTK_*` variables.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use duct::cmd;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::io::profile_loader::Profile;
+
+/// Kill a hook that has not finished after this long.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running hook is checked for exit.
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// When a hook is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// Before `sslocal` is launched; if it fails, the profile is not connected to.
+    PreStart,
+    /// After `sslocal` has been launched.
+    PostStart,
+    /// Before `sslocal` is stopped.
+    PreStop,
+    /// After `sslocal` has exited.
+    PostStop,
+}
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::PreStart => "pre_start",
+            Self::PostStart => "post_start",
+            Self::PreStop => "pre_stop",
+            Self::PostStop => "post_stop",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The scripts to run around the lifecycle of `sslocal`, as set in a profile.
+///
+/// Relative paths are resolved against the profile directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_start: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_start: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_stop: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_stop: Option<PathBuf>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Resolve relative paths against `base_dir`.
+    pub fn resolved(self, base_dir: &Path) -> Self {
+        let resolve = |p: Option<PathBuf>| p.map(|p| base_dir.join(p));
+        Self {
+            pre_start: resolve(self.pre_start),
+            post_start: resolve(self.post_start),
+            pre_stop: resolve(self.pre_stop),
+            post_stop: resolve(self.post_stop),
+        }
+    }
+
+    pub fn get(&self, kind: HookKind) -> Option<&Path> {
+        match kind {
+            HookKind::PreStart => self.pre_start.as_deref(),
+            HookKind::PostStart => self.post_start.as_deref(),
+            HookKind::PreStop => self.pre_stop.as_deref(),
+            HookKind::PostStop => self.post_stop.as_deref(),
+        }
+    }
+}
+
+/// Run the `kind` hook of `profile` if it has one, and wait for it to finish.
+///
+/// Errors if it cannot be run, exits unsuccessfully, or is killed after `HOOK_TIMEOUT`.
+pub fn run(profile: &Profile, kind: HookKind) -> io::Result<()> {
+    let script = match profile.metadata.hooks.get(kind) {
+        Some(script) => script,
+        None => return Ok(()),
+    };
+    let name = &profile.metadata.display_name;
+    info!("Running {} hook of profile \"{}\": {}", kind, name, script.display());
+
+    let mut expr = cmd!(script)
+        .dir(profile.metadata.pwd())
+        .env("SSGTK_HOOK", kind.to_string())
+        .env("SSGTK_PROFILE", name)
+        .stdin_null()
+        .stderr_to_stdout()
+        .stdout_capture()
+        .unchecked();
+    if let Some(dir) = &profile.metadata.config_dir {
+        expr = expr.env("SSGTK_PROFILE_DIR", dir);
+    }
+    if let Some(addr) = profile.config().local_proxy_addr() {
+        expr = expr.env("SSGTK_LOCAL_ADDR", addr.to_string());
+    }
+    let handle = expr.start()?;
+
+    let started_at = Instant::now();
+    let output = loop {
+        if let Some(output) = handle.try_wait()? {
+            break output.clone();
+        }
+        if started_at.elapsed() > HOOK_TIMEOUT {
+            handle.kill()?;
+            let msg = format!("{} hook has not finished after {:?}", kind, HOOK_TIMEOUT);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+        }
+        thread::sleep(HOOK_POLL_INTERVAL);
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        debug!("{} hook: {}", kind, line);
+    }
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "{} hook has failed with {}",
+            kind, output.status
+        ))),
+    }
+}
+
+/// Run the `kind` hook of `profile` in the background if it has one, only logging failures.
+pub fn spawn(profile: &Profile, kind: HookKind) {
+    if profile.metadata.hooks.get(kind).is_none() {
+        return;
+    }
+    let profile = profile.clone();
+    let res = thread::Builder::new().name(format!("{} hook", kind)).spawn(move || {
+        if let Err(err) = run(&profile, kind) {
+            warn!("Profile \"{}\": {}", profile.metadata.display_name, err);
+        }
+    });
+    if let Err(err) = res {
+        warn!("Cannot run {} hook: {}", kind, err);
+    }
+}
@@ -0,0 +1,346 @@
+//! This module contains code that encrypts profile config files at rest
+//! with a master passphrase, enabled behind the "encryption" feature.
+//!
+//! Files are encrypted with AES-256-GCM, using a key derived from the passphrase
+//! with PBKDF2-HMAC-SHA256. The passphrase is taken from the keyring entry
+//! `MASTER_PASSPHRASE_KEYRING_ENTRY` if present, or else prompted for.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead},
+    ops::RangeInclusive,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use crate::{
+    consts::ENCRYPTED_CONFIG_FILE_SUFFIX,
+    util::{self, mutex_lock},
+};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Nonce, Payload},
+    Aes256Gcm,
+};
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use sha2::Sha256;
+
+use crate::{
+    io::{keyring, profile_loader::ConfigFormat},
+    redact,
+};
+
+/// The keyring entry the master passphrase is looked up from, see `ssgtk --store-secret`.
+pub const MASTER_PASSPHRASE_KEYRING_ENTRY: &str = "master-passphrase";
+
+/// Identifies an encrypted file and the version of its format.
+const MAGIC: &[u8; 9] = b"SSGTKENC1";
+const PBKDF2_ROUNDS: u32 = 600_000;
+/// The rounds accepted in the header of a file, so that a crafted one cannot stall decryption.
+const PBKDF2_ROUNDS_ACCEPTED: RangeInclusive<u32> = 10_000..=10_000_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN + NONCE_LEN;
+
+type Key = [u8; KEY_LEN];
+
+lazy_static! {
+    static ref PASSPHRASE: RwLock<Option<String>> = RwLock::new(None);
+    /// Derived keys by salt and rounds, since deriving is slow on purpose.
+    static ref KEYS: Mutex<HashMap<([u8; SALT_LEN], u32), Key>> = Mutex::new(HashMap::new());
+}
+
+/// Use `passphrase` to encrypt and decrypt from now on.
+pub fn set_passphrase(passphrase: String) {
+    redact::register(&passphrase);
+    mutex_lock(&KEYS).clear();
+    *util::rwlock_write(&PASSPHRASE) = Some(passphrase);
+}
+
+/// Get the master passphrase, looking it up in the keyring if not set yet.
+fn passphrase() -> io::Result<String> {
+    if let Some(passphrase) = util::rwlock_read(&PASSPHRASE).as_ref() {
+        return Ok(passphrase.clone());
+    }
+    let passphrase = keyring::lookup(MASTER_PASSPHRASE_KEYRING_ENTRY).map_err(|err| {
+        let msg = format!("no master passphrase is given, nor found in the keyring: {}", err);
+        io::Error::new(io::ErrorKind::PermissionDenied, msg)
+    })?;
+    set_passphrase(passphrase.clone());
+    Ok(passphrase)
+}
+
+/// Try to get the master passphrase from the keyring, returning whether it is available.
+pub fn has_passphrase() -> bool {
+    passphrase().is_ok()
+}
+
+/// Derive a key from `passphrase` with PBKDF2-HMAC-SHA256.
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Key {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, KEY_LEN>(passphrase.as_bytes(), salt, rounds)
+}
+
+/// Get the key for `salt` and `rounds`, deriving it from the master passphrase if not done yet.
+fn key_for(salt: [u8; SALT_LEN], rounds: u32) -> io::Result<Key> {
+    if let Some(key) = mutex_lock(&KEYS).get(&(salt, rounds)) {
+        return Ok(*key);
+    }
+    debug!("Deriving a key from the master passphrase");
+    let key = derive_key(&passphrase()?, &salt, rounds);
+    mutex_lock(&KEYS).insert((salt, rounds), key);
+    Ok(key)
+}
+
+/// Whether `data` is in the encrypted format.
+fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt `plain` with the master passphrase.
+pub fn encrypt(plain: &[u8]) -> io::Result<Vec<u8>> {
+    // reuse a salt whose key is known, so that the key is only derived once per session
+    let cached = mutex_lock(&KEYS)
+        .iter()
+        .find(|((_, rounds), _)| *rounds == PBKDF2_ROUNDS)
+        .map(|((salt, _), key)| (*salt, *key));
+    let (salt, key) = match cached {
+        Some(cached) => cached,
+        None => {
+            let mut salt = [0; SALT_LEN];
+            getrandom::getrandom(&mut salt).map_err(io::Error::other)?;
+            (salt, key_for(salt, PBKDF2_ROUNDS)?)
+        }
+    };
+    encrypt_with(plain, &key, salt, PBKDF2_ROUNDS)
+}
+
+fn encrypt_with(plain: &[u8], key: &Key, salt: [u8; SALT_LEN], rounds: u32) -> io::Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(io::Error::other)?;
+    let mut data = Vec::with_capacity(HEADER_LEN + plain.len() + 16);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&rounds.to_be_bytes());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    let cipher = Aes256Gcm::new_from_slice(key).expect("key has the right length");
+    // the header is authenticated too, so that it cannot be tampered with
+    let payload = Payload { msg: plain, aad: &data };
+    let encrypted = (cipher.encrypt(&Nonce::<Aes256Gcm>::from(nonce), payload))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file is too large to encrypt"))?;
+    data.extend_from_slice(&encrypted);
+    Ok(data)
+}
+
+/// Decrypt `data` with the master passphrase.
+pub fn decrypt(data: &[u8]) -> io::Result<Vec<u8>> {
+    let (salt, rounds) = parse_header(data)?;
+    decrypt_with(data, &key_for(salt, rounds)?)
+}
+
+/// Get the salt and rounds in the header of `data`.
+fn parse_header(data: &[u8]) -> io::Result<([u8; SALT_LEN], u32)> {
+    if !is_encrypted(data) || data.len() < HEADER_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an encrypted profile config",
+        ));
+    }
+    let rounds_at = MAGIC.len();
+    let salt_at = rounds_at + 4;
+    let rounds = u32::from_be_bytes(data[rounds_at..salt_at].try_into().expect("length is checked"));
+    if !PBKDF2_ROUNDS_ACCEPTED.contains(&rounds) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "PBKDF2 rounds {} is out of the accepted range {:?}",
+                rounds, PBKDF2_ROUNDS_ACCEPTED
+            ),
+        ));
+    }
+    let salt = data[salt_at..salt_at + SALT_LEN].try_into().expect("length is checked");
+    Ok((salt, rounds))
+}
+
+fn decrypt_with(data: &[u8], key: &Key) -> io::Result<Vec<u8>> {
+    let (header, encrypted) = data.split_at(HEADER_LEN);
+    let nonce: [u8; NONCE_LEN] = header[HEADER_LEN - NONCE_LEN..].try_into().expect("length is checked");
+    let cipher = Aes256Gcm::new_from_slice(key).expect("key has the right length");
+    let payload = Payload {
+        msg: encrypted,
+        aad: header,
+    };
+    cipher.decrypt(&Nonce::<Aes256Gcm>::from(nonce), payload).map_err(|_| {
+        let msg = "cannot decrypt; either the master passphrase is wrong, or the file is corrupted";
+        io::Error::new(io::ErrorKind::PermissionDenied, msg)
+    })
+}
+
+/// Read and decrypt the file at `path`.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let plain = decrypt(&fs::read(path)?)?;
+    String::from_utf8(plain).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Encrypt `content` and write it to the file at `path`, readable only by the current user.
+pub fn write(path: &Path, content: &str) -> io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let data = encrypt(content.as_bytes())?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(&data)
+}
+
+/// Find an encrypted config file under `dir`, recursively.
+pub fn find_encrypted_file(dir: &Path) -> Option<PathBuf> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Cannot look for encrypted profiles in {:?}: {}", dir, err);
+            return None;
+        }
+    };
+    let mut subdirs = vec![];
+    for ent in entries.flatten() {
+        let path = ent.path();
+        match ent.file_type() {
+            Ok(t) if t.is_dir() => subdirs.push(path),
+            Ok(_) if path.to_string_lossy().ends_with(ENCRYPTED_CONFIG_FILE_SUFFIX) => return Some(path),
+            _ => {}
+        }
+    }
+    subdirs.iter().find_map(|subdir| find_encrypted_file(subdir))
+}
+
+/// Prompt for the master passphrase on the terminal without echoing it,
+/// asking for it twice if `confirm` is set.
+pub fn prompt_tty(confirm: bool) -> io::Result<String> {
+    use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+
+    let stdin = io::stdin();
+    let fd = stdin.as_raw_fd();
+    let read_line = |prompt: &str| -> io::Result<String> {
+        eprint!("{}", prompt);
+        // stdin may not be a terminal, in which case it is read as is
+        let original = tcgetattr(fd).ok();
+        if let Some(original) = &original {
+            let mut no_echo = original.clone();
+            no_echo.local_flags.remove(LocalFlags::ECHO);
+            let _ = tcsetattr(fd, SetArg::TCSANOW, &no_echo);
+        }
+        let mut line = String::new();
+        let res = stdin.lock().read_line(&mut line);
+        if let Some(original) = &original {
+            let _ = tcsetattr(fd, SetArg::TCSANOW, original);
+            eprintln!();
+        }
+        res?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    };
+    let passphrase = read_line("Master passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the passphrase is empty"));
+    }
+    if confirm && read_line("Repeat master passphrase: ")? != passphrase {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the passphrases do not match",
+        ));
+    }
+    Ok(passphrase)
+}
+
+/// Encrypt the profile config file in `dir` in place, or decrypt it if `encrypt` is unset.
+///
+/// The master passphrase is prompted for on the terminal if not in the keyring.
+pub fn convert_profile(dir: &Path, encrypt: bool) -> io::Result<()> {
+    let format = match ConfigFormat::find_all(dir)[..] {
+        [format] => format,
+        [] => {
+            let msg = format!("{:?} contains no profile config file", dir);
+            return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+        }
+        _ => {
+            let msg = format!("{:?} contains more than one profile config file", dir);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+    };
+    let plain_path = dir.join(format.file_name());
+    let encrypted_path = dir.join(format.encrypted_file_name());
+    if format.is_encrypted_in(dir) == encrypt {
+        let state = if encrypt { "encrypted" } else { "not encrypted" };
+        info!("{:?} is already {}", dir, state);
+        return Ok(());
+    }
+    if !has_passphrase() {
+        set_passphrase(prompt_tty(encrypt)?);
+    }
+    if encrypt {
+        write(&encrypted_path, &fs::read_to_string(&plain_path)?)?;
+        fs::remove_file(&plain_path)?;
+        info!("Encrypted {:?}", encrypted_path);
+    } else {
+        fs::write(&plain_path, read_to_string(&encrypted_path)?)?;
+        fs::remove_file(&encrypted_path)?;
+        info!("Decrypted {:?}", plain_path);
+    }
+    Ok(())
+}
+
+/// Check whether `passphrase` decrypts the file at `path`.
+pub fn check_passphrase(passphrase: &str, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let (salt, rounds) = parse_header(&data)?;
+    decrypt_with(&data, &derive_key(passphrase, &salt, rounds)).map(drop)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decrypt_with, derive_key, encrypt_with, parse_header};
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn pbkdf2_vectors() {
+        // RFC 7914, section 11
+        assert_eq!(
+            to_hex(&derive_key("passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        assert_eq!(
+            to_hex(&derive_key("password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let salt = [7; 16];
+        let key = derive_key("correct horse", &salt, 10_000);
+        let data = encrypt_with(b"mode: proxy\n", &key, salt, 10_000).unwrap();
+        assert_eq!(parse_header(&data).unwrap(), (salt, 10_000));
+        assert_eq!(decrypt_with(&data, &key).unwrap(), b"mode: proxy\n");
+
+        let wrong_key = derive_key("wrong horse", &salt, 10_000);
+        assert!(decrypt_with(&data, &wrong_key).is_err());
+        // the header is authenticated
+        let mut tampered = data.clone();
+        tampered[10] ^= 1;
+        assert!(decrypt_with(&tampered, &key).is_err());
+        assert!(parse_header(b"mode: proxy\n").is_err());
+        // rounds out of range are rejected before deriving
+        for rounds in [0, 1, u32::MAX] {
+            let data = encrypt_with(b"mode: proxy\n", &key, salt, rounds).unwrap();
+            assert!(parse_header(&data).is_err());
+        }
+    }
+}
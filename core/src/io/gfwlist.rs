@@ -0,0 +1,116 @@
+//! This module contains code that keeps a copy of gfwlist in the XDG cache directory,
+//! which is converted into PAC rules by the PAC server, and into ACL rules for profiles that use it.
+//!
+//! See https://github.com/gfwlist/gfwlist.
+
+use std::{
+    fmt, fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, info, warn};
+
+use crate::{consts::*, pac::PacRules};
+
+/// The cached gfwlist is refreshed when it is older than this.
+const GFWLIST_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+pub enum GfwlistError {
+    /// No URL to fetch gfwlist from is configured.
+    NoUrl,
+    /// Fetching gfwlist has failed.
+    FetchError(Box<ureq::Error>),
+    /// The fetched gfwlist is not valid.
+    BadGfwlist,
+    /// The filesystem or network encountered an IOError.
+    IOError(io::Error),
+}
+
+impl fmt::Display for GfwlistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use GfwlistError::*;
+        let prefix = "GfwlistError";
+        match self {
+            NoUrl => write!(f, "{}-NoUrl: gfwlist is disabled", prefix),
+            FetchError(e) => write!(f, "{}-FetchError: {}", prefix, e),
+            BadGfwlist => write!(f, "{}-BadGfwlist: not valid base64", prefix),
+            IOError(e) => write!(f, "{}-IOError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<ureq::Error> for GfwlistError {
+    fn from(err: ureq::Error) -> Self {
+        Self::FetchError(Box::new(err))
+    }
+}
+impl From<io::Error> for GfwlistError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+/// The path of the cached gfwlist.
+fn cache_path() -> PathBuf {
+    XDG_DIRS.get_cache_home().join(GFWLIST_CACHE_FILE_NAME)
+}
+
+/// Whether the cached gfwlist is missing or too old.
+pub fn is_stale() -> bool {
+    let age = fs::metadata(cache_path())
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok());
+    age.is_none_or(|age| age >= GFWLIST_MAX_AGE)
+}
+
+/// Load the rules of the cached gfwlist, if any.
+///
+/// A broken cache is skipped with a warning.
+pub fn cached_rules() -> Option<PacRules> {
+    match fs::read_to_string(cache_path()).map(|s| PacRules::parse_base64(&s)) {
+        Ok(Some(rules)) => Some(rules),
+        Ok(None) => {
+            warn!("Cached gfwlist is not valid base64; ignored");
+            None
+        }
+        Err(err) => {
+            debug!("Cannot read cached gfwlist: {}", err);
+            None
+        }
+    }
+}
+
+/// Fetch gfwlist into the cache directory from the first of `urls` that works.
+///
+/// Returns the number of domains listed.
+pub fn fetch(urls: &[String]) -> Result<usize, GfwlistError> {
+    let mut last_err = GfwlistError::NoUrl;
+    for url in urls {
+        info!("Fetching gfwlist from {}", url);
+        let res = ureq::get(url)
+            .timeout(HTTP_TIMEOUT)
+            .call()
+            .map_err(GfwlistError::from)
+            .and_then(|resp| Ok(resp.into_string()?));
+        let (content, rules) = match res.map(|c| (PacRules::parse_base64(&c), c)) {
+            Ok((Some(rules), content)) => (content, rules),
+            Ok((None, _)) => {
+                warn!("gfwlist fetched from {} is not valid base64", url);
+                last_err = GfwlistError::BadGfwlist;
+                continue;
+            }
+            Err(err) => {
+                warn!("Cannot fetch gfwlist from {}: {}", url, err);
+                last_err = err;
+                continue;
+            }
+        };
+        let path = XDG_DIRS.place_cache_file(GFWLIST_CACHE_FILE_NAME)?;
+        fs::write(path, content)?;
+        return Ok(rules.proxy_domains.len() + rules.direct_domains.len());
+    }
+    Err(last_err)
+}
@@ -0,0 +1,276 @@
+//! This module contains code that reads the ignore file (`.ss_ignore`) of a directory.
+//!
+//! An ignore file without any patterns ignores its directory and all its children.
+//! Otherwise, each line is a gitignore-style glob pattern, which ignores the matching files
+//! and directories in the subtree of its directory:
+//!
+//! - Blank lines and lines starting with `#` are skipped.
+//! - `*` matches anything but `/`, `?` matches any one character but `/`, and `[a-z]` matches a range.
+//! - `**` matches any number of directories, e.g. `**/tmp` or `old/**`.
+//! - A pattern containing `/` (other than at its end) is relative to the directory of the ignore file;
+//!   otherwise it matches at any depth.
+//! - A pattern ending with `/` only matches directories.
+//! - A pattern starting with `!` re-includes what an earlier pattern has ignored,
+//!   unless a parent directory is ignored.
+//!
+//! See https://git-scm.com/docs/gitignore#_pattern_format.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::consts::*;
+use log::warn;
+use regex::Regex;
+
+/// A pattern in an ignore file.
+#[derive(Debug, Clone)]
+struct Pattern {
+    /// Matches the path relative to the directory of the ignore file, with `/` as the separator.
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Pattern {
+    /// Parse a line of an ignore file; `None` if it is blank, a comment or invalid.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            // a leading `\!` or `\#` is literal
+            None if line.starts_with("\\!") || line.starts_with("\\#") => (false, &line[1..]),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        let regex_str = format!("{}{}$", prefix, glob_to_regex(glob));
+        match Regex::new(&regex_str) {
+            Ok(regex) => Some(Self {
+                regex,
+                negated,
+                dir_only,
+            }),
+            Err(err) => {
+                warn!("Skipped invalid ignore pattern \"{}\": {}", line, err);
+                None
+            }
+        }
+    }
+
+    /// Whether this pattern matches `rel_path`, which is relative to the directory of the ignore file.
+    fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.regex.is_match(rel_path)
+    }
+}
+
+/// Translate a glob (without the leading `/`, the trailing `/` and the leading `!`) into a regex.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<_> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_segment_start = i == 0 || chars[i - 1] == '/';
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_segment_end = matches!(chars.get(i + 2), None | Some('/'));
+                match (at_segment_start, chars.get(i + 2)) {
+                    // `**/` matches zero or more directories
+                    (true, Some('/')) => {
+                        regex.push_str("(?:.*/)?");
+                        i += 3;
+                        continue;
+                    }
+                    // `/**` matches everything inside
+                    _ if at_segment_start && at_segment_end => regex.push_str(".*"),
+                    // otherwise it is like `*`
+                    _ => regex.push_str("[^/]*"),
+                }
+                i += 2;
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i + 1..].iter().position(|&c| c == ']') {
+                Some(len) if len > 0 => {
+                    let class: String = chars[i + 1..i + 1 + len].iter().collect();
+                    let class = match class.strip_prefix('!') {
+                        Some(rest) => format!("^{}", rest),
+                        None => class,
+                    };
+                    regex.push('[');
+                    regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                    i += len + 2;
+                    continue;
+                }
+                _ => regex.push_str("\\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+/// The content of an ignore file.
+#[derive(Debug, Clone)]
+pub enum IgnoreFile {
+    /// The ignore file has no patterns, so its directory is ignored.
+    All,
+    /// The patterns applied to the subtree of the ignore file's directory.
+    Patterns(IgnoreRules),
+}
+
+impl IgnoreFile {
+    /// Read the ignore file in `dir`; `None` if there is none.
+    pub fn read(dir: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        let dir = dir.as_ref();
+        let path = dir.join(PROFILE_IGNORE_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let patterns: Vec<_> = fs::read_to_string(path)?.lines().filter_map(Pattern::parse).collect();
+        Ok(Some(match patterns.is_empty() {
+            true => Self::All,
+            false => Self::Patterns(IgnoreRules {
+                base: dir.to_path_buf(),
+                patterns,
+            }),
+        }))
+    }
+}
+
+/// The patterns in the ignore file of a directory.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    /// The directory of the ignore file.
+    base: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreRules {
+    /// Whether `path` is ignored (`Some(true)`) or re-included (`Some(false)`) by the last pattern matching it;
+    /// `None` if no pattern matches, or it is not under the directory of the ignore file.
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let rel_path = path.strip_prefix(&self.base).ok()?;
+        let rel_path = (rel_path.components())
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if rel_path.is_empty() {
+            return None; // the directory itself
+        }
+        (self.patterns.iter().rev())
+            .find(|p| p.matches(&rel_path, is_dir))
+            .map(|p| !p.negated)
+    }
+}
+
+/// Whether `path` is ignored by any of `rules`, which are ordered from the outermost directory inwards,
+/// so that the ignore files of inner directories take precedence.
+///
+/// This does not consider whether any parent directory of `path` is ignored.
+pub fn is_ignored_by(rules: &[IgnoreRules], path: &Path, is_dir: bool) -> bool {
+    (rules.iter().rev())
+        .find_map(|r| r.decide(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Whether `path` is ignored by the ignore files in the directories from `root` down to it,
+/// including because one of its parent directories is ignored.
+pub fn is_ignored(root: impl AsRef<Path>, path: impl AsRef<Path>) -> bool {
+    let (root, path) = (root.as_ref(), path.as_ref());
+    let rel_path = match path.strip_prefix(root) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let mut rules = vec![];
+    let mut dir = root.to_path_buf();
+    for component in rel_path.components() {
+        match IgnoreFile::read(&dir) {
+            Ok(Some(IgnoreFile::All)) => return true,
+            Ok(Some(IgnoreFile::Patterns(r))) => rules.push(r),
+            Ok(None) => {}
+            Err(err) => warn!("Cannot read the ignore file in {:?}: {}", dir, err),
+        }
+        dir.push(component);
+        let is_dir = dir != path || path.is_dir();
+        if is_ignored_by(&rules, &dir, is_dir) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, slice};
+
+    use super::{is_ignored_by, IgnoreRules, Pattern};
+
+    fn rules(base: &str, lines: &[&str]) -> IgnoreRules {
+        IgnoreRules {
+            base: base.into(),
+            patterns: lines.iter().filter_map(|l| Pattern::parse(l)).collect(),
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let outer = rules(
+            "/p",
+            &[
+                "# comment",
+                "",
+                "*.tmp",
+                "/Old*/",
+                "drafts/**",
+                "**/cache",
+                "Group/b?",
+                "[!a-m]*.bak",
+                "!keep.tmp",
+            ],
+        );
+        let ignored = |path: &str, is_dir: bool| is_ignored_by(slice::from_ref(&outer), Path::new(path), is_dir);
+
+        assert!(ignored("/p/foo.tmp", false));
+        assert!(ignored("/p/Group/foo.tmp", false));
+        assert!(!ignored("/p/keep.tmp", false)); // re-included
+        assert!(ignored("/p/Old-profiles", true));
+        assert!(!ignored("/p/Old-profiles", false)); // only directories
+        assert!(!ignored("/p/Group/Old-profiles", true)); // anchored
+        assert!(ignored("/p/drafts/A", true));
+        assert!(!ignored("/p/drafts", true));
+        assert!(ignored("/p/cache", true));
+        assert!(ignored("/p/Group/Sub/cache", true));
+        assert!(ignored("/p/Group/b1", true));
+        assert!(!ignored("/p/Group/b12", true));
+        assert!(ignored("/p/x.bak", false));
+        assert!(!ignored("/p/a.bak", false));
+        assert!(!ignored("/p", true)); // the directory itself
+        assert!(!ignored("/elsewhere/foo.tmp", false));
+
+        // inner ignore files take precedence
+        let inner = rules("/p/Group", &["!*.tmp"]);
+        assert!(!is_ignored_by(&[outer, inner], Path::new("/p/Group/foo.tmp"), false));
+    }
+}
@@ -0,0 +1,55 @@
+//! This module contains code that stores and looks up passwords in the
+//! freedesktop Secret Service (e.g. GNOME Keyring or KWallet),
+//! using `secret-tool` from libsecret.
+
+use std::io;
+
+use crate::consts::APP_NAME;
+use duct::cmd;
+use log::debug;
+
+/// The `secret-tool` binary, looked up in $PATH.
+const SECRET_TOOL_LOOKUP_NAME: &str = "secret-tool";
+
+/// Look up the password stored under `entry`.
+pub fn lookup(entry: &str) -> io::Result<String> {
+    debug!("Looking up keyring entry \"{}\"", entry);
+    let secret = cmd!(
+        SECRET_TOOL_LOOKUP_NAME,
+        "lookup",
+        "application",
+        APP_NAME,
+        "entry",
+        entry
+    )
+    .stderr_null()
+    .unchecked()
+    .read()?;
+    // `secret-tool` exits with an error without printing anything if there is no such entry
+    if secret.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no password is stored in keyring entry \"{}\"", entry),
+        ));
+    }
+    Ok(secret)
+}
+
+/// Store a password under `entry`, replacing the existing one if present.
+///
+/// The password is prompted for by `secret-tool`, or read from stdin if it is not a terminal.
+pub fn store(entry: &str) -> io::Result<()> {
+    let label = format!("{} ({})", APP_NAME, entry);
+    cmd!(
+        SECRET_TOOL_LOOKUP_NAME,
+        "store",
+        "--label",
+        label,
+        "application",
+        APP_NAME,
+        "entry",
+        entry
+    )
+    .run()?;
+    Ok(())
+}
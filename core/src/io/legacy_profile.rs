@@ -0,0 +1,300 @@
+//! This module contains code that migrates profile config files in the legacy format,
+//! written before profiles were tagged with `mode`, into the current one.
+//!
+//! A legacy `profile.yaml` could only launch `sslocal` with a config file:
+//! ```yaml
+//! ss_bin_path: "/usr/bin/sslocal" # optional
+//! ss_config_path: "./ss.json"
+//! ```
+//! which is now written as:
+//! ```yaml
+//! mode: "config-file"
+//! bin_path: "/usr/bin/sslocal"
+//! config_path: "./ss.json"
+//! ```
+//! All other fields are kept as they are.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde_yaml::Value;
+
+use crate::{consts::*, io::profile_loader::ProfileConfig};
+
+/// The legacy keys, and what they have been renamed to.
+const RENAMED_KEYS: [(&str, &str); 2] = [("ss_bin_path", "bin_path"), ("ss_config_path", "config_path")];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The config has legacy keys, but cannot be expressed in the current format.
+    Unsupported(String),
+    /// The migrated config does not parse, e.g. because of other invalid fields.
+    ParseError(serde_yaml::Error),
+    /// A backup of the original config already exists, which would be overwritten.
+    BackupExists(PathBuf),
+    IOError(io::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use MigrationError::*;
+
+        let prefix = "MigrationError";
+        match self {
+            Unsupported(s) => write!(f, "{}-Unsupported: {}", prefix, s),
+            ParseError(e) => write!(f, "{}-ParseError: {}", prefix, e),
+            BackupExists(p) => write!(f, "{}-BackupExists: {:?}", prefix, p),
+            IOError(e) => write!(f, "{}-IOError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for MigrationError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+impl From<io::Error> for MigrationError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+/// Whether the content of a `profile.yaml` is in the legacy format,
+/// i.e. it has no `mode` but has a legacy key.
+pub fn is_legacy(content: &str) -> bool {
+    match serde_yaml::from_str::<Value>(content) {
+        Ok(Value::Mapping(map)) => {
+            !map.contains_key(&"mode".into())
+                && RENAMED_KEYS
+                    .iter()
+                    .any(|(legacy, _)| map.contains_key(&(*legacy).into()))
+        }
+        _ => false,
+    }
+}
+
+/// Rewrite the content of a legacy `profile.yaml` into the current format.
+///
+/// Keys are renamed line by line, so that comments and formatting are kept.
+/// The result is checked to parse before it is returned.
+pub fn migrate(content: &str) -> Result<String, MigrationError> {
+    if !is_legacy(content) {
+        return Err(MigrationError::Unsupported("not in the legacy format".into()));
+    }
+    if !content.lines().any(|line| line.starts_with("ss_config_path:")) {
+        // without a config file, there is nothing that a legacy profile could have launched
+        return Err(MigrationError::Unsupported(
+            "ss_config_path is required, and has to be a top-level key in block style".into(),
+        ));
+    }
+
+    let mut migrated = String::with_capacity(content.len() + 32);
+    let mut mode_written = false;
+    for line in content.split_inclusive('\n') {
+        let is_top_level_key = line.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '"');
+        if is_top_level_key && !mode_written {
+            migrated.push_str("mode: \"config-file\"\n");
+            mode_written = true;
+        }
+        match RENAMED_KEYS
+            .iter()
+            .find_map(|(legacy, new)| line.strip_prefix(legacy).map(|rest| (new, rest)))
+        {
+            Some((new, rest)) if rest.starts_with(':') => {
+                migrated.push_str(new);
+                migrated.push_str(rest);
+            }
+            _ => migrated.push_str(line),
+        }
+    }
+
+    // make sure nothing has been missed, e.g. keys in flow style
+    serde_yaml::from_str::<ProfileConfig>(&migrated)?;
+    if is_legacy(&migrated) {
+        return Err(MigrationError::Unsupported(
+            "legacy keys have to be top-level keys in block style".into(),
+        ));
+    }
+    Ok(migrated)
+}
+
+/// Migrate the `profile.yaml` in `dir` in place if it is in the legacy format,
+/// keeping the original with `LEGACY_CONFIG_BACKUP_SUFFIX` appended to its name.
+///
+/// Returns the path of the backup if migrated, or `None` if there is nothing to migrate.
+/// Nothing is written if `dry_run` is set.
+pub fn migrate_dir(dir: &Path, dry_run: bool) -> Result<Option<PathBuf>, MigrationError> {
+    let path = dir.join(PROFILE_CONFIG_FILE_NAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if !is_legacy(&content) {
+        return Ok(None);
+    }
+    let migrated = migrate(&content)?;
+    let backup_path = dir.join(format!("{}{}", PROFILE_CONFIG_FILE_NAME, LEGACY_CONFIG_BACKUP_SUFFIX));
+    if backup_path.exists() {
+        return Err(MigrationError::BackupExists(backup_path));
+    }
+    if dry_run {
+        return Ok(Some(backup_path));
+    }
+
+    fs::copy(&path, &backup_path)?;
+    // write to a temporary file first, so that the config is never left half-written
+    let tmp_path = dir.join(format!("{}.tmp", PROFILE_CONFIG_FILE_NAME));
+    fs::write(&tmp_path, migrated)?;
+    fs::rename(&tmp_path, &path)?;
+    info!("Migrated {:?}; the original is kept as {:?}", path, backup_path);
+    Ok(Some(backup_path))
+}
+
+/// The outcome of migrating all profiles under a directory.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// The configs migrated, with the paths of their backups.
+    pub migrated: Vec<(PathBuf, PathBuf)>,
+    /// The legacy configs that cannot be migrated, and why.
+    pub failed: Vec<(PathBuf, MigrationError)>,
+}
+
+/// Migrate every legacy `profile.yaml` in `base_dir` and its subdirectories, following `migrate_dir`.
+///
+/// Symlinked subdirectories are not followed.
+pub fn migrate_all(base_dir: &Path, dry_run: bool) -> io::Result<MigrationReport> {
+    let mut report = MigrationReport::default();
+    let mut dirs = vec![base_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let path = dir.join(PROFILE_CONFIG_FILE_NAME);
+        match migrate_dir(&dir, dry_run) {
+            Ok(Some(backup_path)) => report.migrated.push((path, backup_path)),
+            Ok(None) => {}
+            Err(err) => {
+                warn!("Cannot migrate {:?}: {}", path, err);
+                report.failed.push((path, err));
+            }
+        }
+        let mut subdirs = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                subdirs.push(entry.path());
+            }
+        }
+        // visited in order, for a tidy report
+        subdirs.sort();
+        dirs.extend(subdirs.into_iter().rev());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+    use crate::io::profile_loader::ProfileLoadError;
+
+    const LEGACY: &str = "---
+# my server
+display_name: \"Tokyo\"
+ss_bin_path: \"/bin/sh\"
+ss_config_path: \"./ss.json\" # relative
+extra_args:
+  - \"--single-threaded\"
+";
+
+    #[test]
+    fn migrate_legacy_yaml() {
+        assert!(is_legacy(LEGACY));
+        let migrated = migrate(LEGACY).unwrap();
+        assert_eq!(
+            migrated,
+            "---
+# my server
+mode: \"config-file\"
+display_name: \"Tokyo\"
+bin_path: \"/bin/sh\"
+config_path: \"./ss.json\" # relative
+extra_args:
+  - \"--single-threaded\"
+"
+        );
+        assert!(!is_legacy(&migrated));
+        assert!(matches!(
+            serde_yaml::from_str(&migrated).unwrap(),
+            ProfileConfig::ConfigFile { .. }
+        ));
+
+        // not legacy, or cannot be migrated
+        assert!(!is_legacy(
+            "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json\"\n"
+        ));
+        assert!(matches!(
+            migrate("ss_bin_path: \"/bin/sh\"\n"),
+            Err(MigrationError::Unsupported(_))
+        ));
+        assert!(matches!(
+            migrate("{ss_config_path: \"./ss.json\"}\n"),
+            Err(MigrationError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn migrate_all_in_place() {
+        let root = env::temp_dir().join(format!("ssgtk-legacy-migration-test-{}", process::id()));
+        let current = "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json\"\n";
+        for (dir, content) in [
+            ("Group/Tokyo", LEGACY),
+            ("Osaka", current),
+            ("Broken", "ss_bin_path: \"/bin/sh\"\n"),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(PROFILE_CONFIG_FILE_NAME), content).unwrap();
+        }
+        let tokyo = root.join("Group/Tokyo");
+
+        // migrated in memory by the loader, while the file is left alone
+        assert!(matches!(
+            ProfileConfig::read_from_dir(&tokyo),
+            Ok(ProfileConfig::ConfigFile { .. })
+        ));
+        assert!(matches!(
+            ProfileConfig::read_from_dir(root.join("Broken")),
+            Err(ProfileLoadError::LegacyConfig(_))
+        ));
+
+        // dry run changes nothing
+        let report = migrate_all(&root, true).unwrap();
+        assert_eq!(report.migrated.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            fs::read_to_string(tokyo.join(PROFILE_CONFIG_FILE_NAME)).unwrap(),
+            LEGACY
+        );
+
+        let report = migrate_all(&root, false).unwrap();
+        assert_eq!(
+            report.migrated,
+            [(tokyo.join("profile.yaml"), tokyo.join("profile.yaml.legacy"))]
+        );
+        assert_eq!(report.failed[0].0, root.join("Broken/profile.yaml"));
+        assert_eq!(fs::read_to_string(tokyo.join("profile.yaml.legacy")).unwrap(), LEGACY);
+        assert!(ProfileConfig::read_from_dir(&tokyo).is_ok());
+        assert_eq!(
+            fs::read_to_string(root.join("Osaka").join(PROFILE_CONFIG_FILE_NAME)).unwrap(),
+            current
+        );
+
+        // migrated configs are left alone afterwards
+        let report = migrate_all(&root, false).unwrap();
+        assert!(report.migrated.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
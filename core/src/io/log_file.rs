@@ -0,0 +1,151 @@
+//! This module contains code that writes `sslocal`'s output into per-profile
+//! log files under the XDG state directory, rotating them by size.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::consts::*;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+
+/// User-configurable settings of the log files.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFileConfig {
+    /// Whether to write `sslocal`'s output into log files.
+    pub enabled: bool,
+    /// A log file is rotated once it grows beyond this size.
+    pub max_size_kib: u64,
+    /// How many rotated log files to keep per profile, in addition to the current one.
+    pub retention: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size_kib: 1024,
+            retention: 5,
+        }
+    }
+}
+
+/// The path of the current log file of a profile.
+///
+/// Rotated log files are suffixed with `.1`, `.2`, etc., from newest to oldest.
+pub fn log_file_path(profile_name: &str) -> PathBuf {
+    let file_name: String = profile_name
+        .trim_start_matches('.')
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '-',
+            c => c,
+        })
+        .collect();
+    XDG_DIRS
+        .get_state_home()
+        .join(LOG_FILES_DIR_NAME)
+        .join(format!("{}.log", file_name))
+}
+
+/// The path of the `n`th rotated log file.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    rotated.into()
+}
+
+/// A log file that is appended to, and rotated when it grows too large.
+#[derive(Debug)]
+pub struct RotatingLogFile {
+    path: PathBuf,
+    file: File,
+    /// The current size of `file`.
+    size: u64,
+    config: LogFileConfig,
+}
+
+impl RotatingLogFile {
+    /// Open the log file at `path` for appending, creating it and its parent directories if necessary.
+    pub fn open(path: impl Into<PathBuf>, config: LogFileConfig) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        debug!("Opened log file at {:?}", path);
+        Ok(Self {
+            path,
+            file,
+            size,
+            config,
+        })
+    }
+
+    /// Append some output, rotating first if it would grow too large.
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        let max_size = self.config.max_size_kib * 1024;
+        if self.size > 0 && self.size + text.len() as u64 > max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(text.as_bytes())?;
+        self.size += text.len() as u64;
+        Ok(())
+    }
+
+    /// Shift all rotated log files by one, discarding the oldest,
+    /// then start a new log file.
+    fn rotate(&mut self) -> io::Result<()> {
+        trace!("Rotating log file at {:?}", self.path);
+        let retention = self.config.retention;
+        if retention == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // renaming overwrites, so the oldest is discarded implicitly
+            for n in (1..retention).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        *self = Self::open(self.path.clone(), self.config)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::{rotated_path, LogFileConfig, RotatingLogFile};
+
+    #[test]
+    fn rotation() {
+        let dir = env::temp_dir().join(format!("ssgtk-log-file-test-{}", process::id()));
+        let path = dir.join("profile.log");
+        let config = LogFileConfig {
+            enabled: true,
+            max_size_kib: 1,
+            retention: 2,
+        };
+        let line = format!("{}\n", "x".repeat(511));
+
+        let mut log = RotatingLogFile::open(&path, config).unwrap();
+        for _ in 0..7 {
+            log.write(&line).unwrap(); // 2 lines per file
+        }
+        let size = |n| fs::metadata(rotated_path(&path, n)).map(|m| m.len()).ok();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 512);
+        assert_eq!(size(1), Some(1024));
+        assert_eq!(size(2), Some(1024));
+        assert_eq!(size(3), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,20 @@
+//! This module contains code relating to IO.
+
+// public members
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod gfwlist;
+pub mod ignore_file;
+pub mod keyring;
+pub mod legacy_profile;
+pub mod log_file;
+pub mod port_check;
+pub mod privileged;
+pub mod profile_loader;
+#[cfg(feature = "runtime-api")]
+pub mod runtime_api;
+pub mod sslocal_compat;
+pub mod sslocal_pids;
+pub mod systemd_scope;
+
+// private members with re-export
@@ -0,0 +1,125 @@
+//! This module contains code that checks whether a local port is free before
+//! launching `sslocal` on it, and finds out which process is holding it if not.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener},
+};
+
+use log::trace;
+
+/// A process listening on a TCP port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// Check whether `addr` can be listened on.
+///
+/// Returns an `ErrorKind::AddrInUse` error naming the conflicting process, if it can be found.
+/// Other errors, e.g. lacking permission for a privileged port, are ignored,
+/// because `sslocal` may be launched differently.
+pub fn ensure_free(addr: SocketAddr) -> io::Result<()> {
+    match TcpListener::bind(addr) {
+        Ok(_) => Ok(()), // closed immediately
+        Err(err) if err.kind() == ErrorKind::AddrInUse => {
+            let msg = match find_owner(addr.port()) {
+                Some(owner) => format!("{} is already in use by {} (PID {})", addr, owner.name, owner.pid),
+                None => format!("{} is already in use by another program", addr),
+            };
+            Err(io::Error::new(ErrorKind::AddrInUse, msg))
+        }
+        Err(err) => {
+            trace!("Cannot check whether {} is free; skipped: {}", addr, err);
+            Ok(())
+        }
+    }
+}
+
+/// Find the process listening on a TCP `port`, on any address.
+///
+/// Returns `None` if there is none, or if it belongs to another user.
+pub fn find_owner(port: u16) -> Option<PortOwner> {
+    let inodes: Vec<_> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| listening_inodes(&table, port))
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+    let targets: Vec<_> = inodes.iter().map(|inode| format!("socket:[{}]", inode)).collect();
+
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .find(|pid| {
+            // fails for processes of other users
+            let fds = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+                Ok(fds) => fds,
+                Err(_) => return false,
+            };
+            fds.filter_map(Result::ok)
+                .filter_map(|fd| fs::read_link(fd.path()).ok())
+                .any(|link| targets.iter().any(|t| link.as_os_str() == t.as_str()))
+        })
+        .map(|pid| {
+            let name = fs::read_to_string(format!("/proc/{}/comm", pid))
+                .map(|s| s.trim_end().to_string())
+                .unwrap_or_else(|_| "unknown".into());
+            PortOwner { pid, name }
+        })
+}
+
+/// Parse a `/proc/net/tcp` or `/proc/net/tcp6` table for the inodes
+/// of sockets listening on `port`.
+fn listening_inodes(table: &str, port: u16) -> Vec<u64> {
+    const LISTEN: &str = "0A";
+    table
+        .lines()
+        .skip(1) // header
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+            let listening = u16::from_str_radix(local_port, 16).ok()? == port && *fields.get(3)? == LISTEN;
+            listening.then(|| fields.get(9)?.parse().ok()).flatten()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::ErrorKind, net::TcpListener, process};
+
+    use super::{ensure_free, find_owner, listening_inodes};
+
+    #[test]
+    fn parse_table() {
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0438 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0 100 0 0 10 0
+   1: 0100007F:0438 0100007F:A1B2 01 00000000:00000000 00:00000000 00000000  1000        0 4343 1 0 20 4 30 10 -1
+   2: 00000000:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4444 1 0 100 0 0 10 0";
+        assert_eq!(listening_inodes(table, 1080), vec![4242]);
+        assert_eq!(listening_inodes(table, 80), vec![4444]);
+        assert!(listening_inodes(table, 8080).is_empty());
+    }
+
+    #[test]
+    fn own_listener_found() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let owner = find_owner(addr.port()).unwrap();
+        assert_eq!(owner.pid, process::id());
+        let err = ensure_free(addr).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AddrInUse);
+        assert!(err.to_string().contains(&process::id().to_string()));
+
+        drop(listener);
+        ensure_free(addr).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! This module contains code that launches `sslocal` with root privileges
+//! by running `ssgtk-helper` via `pkexec`, for profiles that need to manage
+//! network interfaces or bind privileged ports.
+//!
+//! `ssgtk` cannot signal a process running as root, so the helper
+//! instead stops `sslocal` once its stdin is closed. See `stdin_pipe`.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs::File,
+    io,
+    net::IpAddr,
+    os::unix::io::FromRawFd,
+    path::{Path, PathBuf},
+};
+
+use crate::consts::PRIVILEGED_HELPER_LOOKUP_NAME;
+use duct::cmd;
+use ipnet::IpNet;
+use log::{debug, trace};
+use nix::{fcntl::OFlag, unistd};
+
+/// The `pkexec` binary, looked up in $PATH.
+const PKEXEC_LOOKUP_NAME: &str = "pkexec";
+
+/// Routes for the helper to set up through the tun interface after `sslocal` has created it.
+#[derive(Debug, Clone, Copy)]
+pub struct TunRoutes<'a> {
+    pub if_name: &'a str,
+    pub table: Option<u32>,
+    pub routes: &'a [IpNet],
+}
+
+/// Whether this process is running as root.
+pub fn is_root() -> bool {
+    unistd::geteuid().is_root()
+}
+
+/// Whether the binary at `bin_path` has been granted `CAP_NET_ADMIN`, as reported by `getcap`.
+///
+/// Returns false if `getcap` is unavailable.
+pub fn has_cap_net_admin(bin_path: &Path) -> bool {
+    match cmd!("getcap", bin_path).stderr_null().read() {
+        Ok(caps) => {
+            trace!("Capabilities of {:?}: {}", bin_path, caps);
+            caps.to_lowercase().contains("cap_net_admin")
+        }
+        Err(err) => {
+            debug!("Cannot read capabilities of {:?}: {}", bin_path, err);
+            false
+        }
+    }
+}
+
+/// Find the privileged helper, preferring the one next to the running binary.
+///
+/// `pkexec` requires an absolute path.
+fn helper_path() -> io::Result<PathBuf> {
+    let sibling = env::current_exe()?.with_file_name(PRIVILEGED_HELPER_LOOKUP_NAME);
+    if sibling.is_file() {
+        return Ok(sibling);
+    }
+    which::which(PRIVILEGED_HELPER_LOOKUP_NAME).map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))
+}
+
+/// Build the command line that runs `bin_path` with `args` as root in `pwd`,
+/// setting up `routes` if specified.
+///
+/// Returns the program to run and its arguments.
+pub fn wrap_command(
+    pwd: &Path,
+    bin_path: &Path,
+    args: Vec<OsString>,
+    routes: Option<TunRoutes<'_>>,
+) -> io::Result<(PathBuf, Vec<OsString>)> {
+    let pkexec = which::which(PKEXEC_LOOKUP_NAME).map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+    let helper = helper_path()?;
+    debug!("Using privileged helper at {:?}", helper);
+    // `pkexec` does not keep our working directory
+    let mut wrapped: Vec<OsString> = vec![helper.into(), "--pwd".into(), pwd.canonicalize()?.into()];
+    if let Some(r) = routes {
+        wrapped.extend_from_slice(&["--if-name".into(), r.if_name.into()]);
+        for route in r.routes {
+            wrapped.extend_from_slice(&["--route".into(), route.to_string().into()]);
+        }
+        if let Some(table) = r.table {
+            wrapped.extend_from_slice(&["--route-table".into(), table.to_string().into()]);
+        }
+    }
+    wrapped.push("--".into());
+    wrapped.push(bin_path.into());
+    wrapped.extend(args);
+    Ok((pkexec, wrapped))
+}
+
+/// Install the kill switch firewall rules via the helper, letting through `allow`'s addresses and interfaces,
+/// or remove them if `allow` is `None`.
+///
+/// Blocks until the user has authenticated and the helper has exited.
+pub fn set_kill_switch(allow: Option<(&[IpAddr], &[String])>) -> io::Result<()> {
+    let pkexec = which::which(PKEXEC_LOOKUP_NAME).map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+    let mut args: Vec<OsString> = vec![helper_path()?.into(), "--kill-switch".into()];
+    match allow {
+        Some((addrs, ifs)) => {
+            args.push("on".into());
+            for addr in addrs {
+                args.extend_from_slice(&["--allow-addr".into(), addr.to_string().into()]);
+            }
+            for if_name in ifs {
+                args.extend_from_slice(&["--allow-if".into(), if_name.into()]);
+            }
+        }
+        None => args.push("off".into()),
+    }
+    debug!("Setting kill switch: {:?}", args);
+    cmd(pkexec, args).run().map(|_| ())
+}
+
+/// Create a pipe for the stdin of a privileged launch.
+///
+/// Returns the read end (for the child) and the write end (to keep);
+/// dropping the write end stops `sslocal`.
+pub fn stdin_pipe() -> io::Result<(File, File)> {
+    // the write end must not leak into the child, or stdin is never closed;
+    // the read end is duplicated onto the child's stdin without this flag
+    let (read_fd, write_fd) = unistd::pipe2(OFlag::O_CLOEXEC)?;
+    // SAFETY: both file descriptors are freshly created and owned by nobody else
+    Ok(unsafe { (File::from_raw_fd(read_fd), File::from_raw_fd(write_fd)) })
+}
@@ -0,0 +1,3562 @@
+//! This module contains code that handles profile loading.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    ffi::{OsStr, OsString},
+    fmt,
+    fs::{self, read_to_string, File},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::{
+        fs::{MetadataExt, OpenOptionsExt},
+        prelude::IntoRawFd,
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::consts::*;
+use derivative::Derivative;
+use duct::{cmd, Handle};
+use ipnet::IpNet;
+use itertools::Itertools;
+use log::{debug, error, info, warn};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::{Host, Url};
+use which::which;
+
+#[cfg(feature = "embedded")]
+use shadowsocks::{config::ServerAddr, crypto::CipherKind, ServerConfig};
+
+#[cfg(feature = "embedded")]
+use crate::embedded::EmbeddedConfig;
+#[cfg(feature = "encryption")]
+use crate::io::encryption;
+use crate::{
+    acl::AclRules,
+    hooks::Hooks,
+    io::{
+        ignore_file::{self, IgnoreFile, IgnoreRules},
+        keyring, legacy_profile,
+        privileged::{self, TunRoutes},
+        sslocal_compat::{self, SslocalInfo},
+        systemd_scope::{self, ScopeConfig},
+    },
+    redact::{self, PASSWORD_MASK},
+};
+
+/// Optional fields which allow a config to override its profile's default metadata.
+///
+/// Relative paths are resolved against the profile directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pwd: Option<PathBuf>,
+    /// Either a path, or a name to look up in $PATH.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bin_path: Option<PathBuf>,
+    /// Overrides the global setting of whether to set the system proxy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_proxy: Option<bool>,
+    /// Whether `sslocal` must always run as root, e.g. to bind a privileged port.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requires_privilege: Option<bool>,
+    /// The implementation to launch; defaults to `shadowsocks-rust`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<Backend>,
+    /// Either a path to an image, or a name to look up in the icon theme.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    /// A color tag shown next to the profile's name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<ProfileColor>,
+    /// Free-form labels to filter profiles by, e.g. a region or a provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Environment variables to set for `sslocal`, e.g. `RUST_LOG`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// How verbosely `sslocal` logs; defaults to `info`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_level: Option<LogLevel>,
+    /// Scripts to run around the lifecycle of `sslocal`.
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    hooks: Hooks,
+}
+
+/// How verbosely `sslocal` logs, passed as its `-v` flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    #[default]
+    Info,
+    /// `-v`
+    Debug,
+    /// `-vv`
+    Trace,
+    /// `-vvv`, which also traces the libraries `sslocal` uses.
+    TraceAll,
+}
+impl LogLevel {
+    /// The next more verbose level, if any.
+    pub fn bumped(self) -> Self {
+        match self {
+            Self::Info => Self::Debug,
+            Self::Debug => Self::Trace,
+            Self::Trace | Self::TraceAll => Self::TraceAll,
+        }
+    }
+
+    /// The flags to launch `backend` with; other backends only have a single verbose level.
+    fn launch_args(self, backend: Backend) -> Vec<OsString> {
+        match (backend, self) {
+            (_, Self::Info) | (Backend::Embedded, _) => vec![],
+            (Backend::ShadowsocksRust, Self::Debug) => vec!["-v".into()],
+            (Backend::ShadowsocksRust, Self::Trace) => vec!["-vv".into()],
+            (Backend::ShadowsocksRust, Self::TraceAll) => vec!["-vvv".into()],
+            (Backend::ShadowsocksLibev, _) => vec!["-v".into()],
+            (Backend::GoShadowsocks2, _) => vec!["-verbose".into()],
+        }
+    }
+}
+
+/// A color tag to tell profiles apart at a glance, e.g. by region.
+///
+/// Tray menus cannot be styled, so each color is shown as a colored circle character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Brown,
+    Black,
+    White,
+}
+
+impl ProfileColor {
+    /// The character shown for this color.
+    pub fn symbol(&self) -> &'static str {
+        use ProfileColor::*;
+        match self {
+            Red => "🔴",
+            Orange => "🟠",
+            Yellow => "🟡",
+            Green => "🟢",
+            Blue => "🔵",
+            Purple => "🟣",
+            Brown => "🟤",
+            Black => "⚫",
+            White => "⚪",
+        }
+    }
+}
+
+/// A shadowsocks client implementation that a profile can be launched with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// `sslocal` from shadowsocks-rust, which supports all modes and features.
+    #[default]
+    ShadowsocksRust,
+    /// `ss-local` from shadowsocks-libev; only "Proxy" and "Config file"-type profiles.
+    ShadowsocksLibev,
+    /// go-shadowsocks2; only "Proxy"-type profiles with AEAD ciphers.
+    GoShadowsocks2,
+    /// A client running inside `ssgtk`, so no binary is needed;
+    /// only "Proxy"-type profiles without plugins. Requires the `embedded` feature.
+    Embedded,
+}
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::ShadowsocksRust => "shadowsocks-rust",
+            Self::ShadowsocksLibev => "shadowsocks-libev",
+            Self::GoShadowsocks2 => "go-shadowsocks2",
+            Self::Embedded => "embedded",
+        };
+        write!(f, "{}", name)
+    }
+}
+impl Backend {
+    /// The binary to lookup in $PATH, if not overridden by profile.
+    ///
+    /// `None` if this backend runs in-process.
+    fn lookup_name(self) -> Option<&'static str> {
+        match self {
+            Self::ShadowsocksRust => Some(SSLOCAL_LOOKUP_NAME_DEFAULT),
+            Self::ShadowsocksLibev => Some(SS_LIBEV_LOOKUP_NAME_DEFAULT),
+            Self::GoShadowsocks2 => Some(GO_SS2_LOOKUP_NAME_DEFAULT),
+            Self::Embedded => None,
+        }
+    }
+
+    /// Check that this backend can launch `config`.
+    fn check_supported(self, config: &ProfileConfig) -> Result<(), String> {
+        use ProfileConfig::*;
+        let unsupported = |what: &str| Err(format!("{} is not supported by {}", what, self));
+        match (self, config) {
+            (Self::ShadowsocksRust, _) => Ok(()),
+            (Self::Embedded, _) if !cfg!(feature = "embedded") => {
+                Err("ssgtk has been built without the `embedded` feature".into())
+            }
+            (Self::Embedded, _) if config.get_metadata_override().requires_privilege == Some(true) => {
+                unsupported("`requires_privilege`")
+            }
+            (_, _) if config.get_advanced_options().acl.is_some() => unsupported("`acl`"),
+            (_, Tun { .. }) => unsupported("tun mode"),
+            (_, Balanced { .. }) => unsupported("balanced mode"),
+            (Self::GoShadowsocks2 | Self::Embedded, ConfigFile { .. }) => unsupported("config-file mode"),
+            (_, Proxy { opts, .. }) if opts.http_port.is_some() => unsupported("`http_port`"),
+            (Self::Embedded, Proxy { opts, .. }) if opts.plugin.is_some() => unsupported("`plugin`"),
+            (Self::Embedded, Proxy { adv_opts, .. }) if adv_opts.extra_args.is_some() => unsupported("`extra_args`"),
+            (Self::Embedded, Proxy { conn_opts, .. }) if !embedded_supports_method(&conn_opts.encrypt_method) => {
+                unsupported(&format!("encrypt method \"{}\"", conn_opts.encrypt_method))
+            }
+            (Self::GoShadowsocks2, Proxy { conn_opts, .. }) => {
+                let method = conn_opts.encrypt_method.to_ascii_uppercase();
+                match GO_SS2_ENCRYPT_METHODS
+                    .iter()
+                    .any(|(m, alias)| method == *m || method == *alias)
+                {
+                    true => Ok(()),
+                    false => unsupported(&format!("encrypt method \"{}\"", conn_opts.encrypt_method)),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the embedded client supports an encrypt method.
+#[cfg(feature = "embedded")]
+fn embedded_supports_method(method: &str) -> bool {
+    method.parse::<CipherKind>().is_ok()
+}
+#[cfg(not(feature = "embedded"))]
+fn embedded_supports_method(_: &str) -> bool {
+    false
+}
+
+/// The ciphers supported by go-shadowsocks2, as `(name, alias)`, case-insensitive.
+const GO_SS2_ENCRYPT_METHODS: &[(&str, &str)] = &[
+    ("AEAD_AES_128_GCM", "AES-128-GCM"),
+    ("AEAD_AES_256_GCM", "AES-256-GCM"),
+    ("AEAD_CHACHA20_POLY1305", "CHACHA20-IETF-POLY1305"),
+];
+
+trait ToLaunchArgs {
+    fn to_launch_args(&self) -> Vec<OsString>;
+}
+
+/// Fields for a "Config file"-type ProfileConfig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigFileOptions {
+    config_path: PathBuf,
+}
+impl ToLaunchArgs for ConfigFileOptions {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        vec!["--config".into(), (&self.config_path).into()]
+    }
+}
+impl ConfigFileOptions {
+    /// Parse the referenced `sslocal` config file (resolved relative to `base_dir`), and check that
+    /// it defines at least one server and one local, unless they are given in `extra_args`.
+    fn validate(&self, base_dir: &Path, extra_args: &[String]) -> Result<(), String> {
+        let path = base_dir.join(&self.config_path);
+        let content = read_to_string(&path).map_err(|err| format!("cannot read {:?}: {}", path, err))?;
+        let config: serde_json::Value =
+            json5::from_str(&content).map_err(|err| format!("cannot parse {:?}: {}", path, err))?;
+        let config = config
+            .as_object()
+            .ok_or_else(|| format!("{:?} is not a JSON object", path))?;
+        // `sslocal` may echo them
+        let servers = (config.get("servers").and_then(|s| s.as_array()).into_iter().flatten())
+            .filter_map(|server| server.as_object());
+        for server in std::iter::once(config).chain(servers) {
+            if let Some(password) = server.get("password").and_then(|p| p.as_str()) {
+                redact::register(password);
+            }
+        }
+
+        let has_arg = |names: &[&str]| {
+            extra_args
+                .iter()
+                .any(|arg| names.iter().any(|n| arg == n || arg.starts_with(&format!("{}=", n))))
+        };
+        if !has_arg(&["-s", "--server-addr", "--server-url"]) && !config.contains_key("online_config") {
+            match config.get("servers") {
+                Some(servers) => {
+                    let servers = servers.as_array().ok_or("`servers` is not an array")?;
+                    if servers.is_empty() {
+                        return Err("`servers` is empty".into());
+                    }
+                    for (i, server) in servers.iter().enumerate() {
+                        let server = server
+                            .as_object()
+                            .ok_or_else(|| format!("`servers[{}]` is not an object", i))?;
+                        // entries of `servers` also accept `address` & `port`
+                        check_keys(server, &[&["server", "address"], &["server_port", "port"], &["method"]])
+                            .map_err(|err| format!("`servers[{}]`: {}", i, err))?;
+                    }
+                }
+                None => check_keys(config, &[&["server"], &["server_port"], &["method"]])?,
+            }
+        }
+        if !has_arg(&["-b", "--local-addr"]) {
+            match config.get("locals").map(|l| l.as_array()) {
+                Some(Some(locals)) if !locals.is_empty() => {}
+                Some(_) => return Err("`locals` is not a non-empty array".into()),
+                None => check_keys(config, &[&["local_port"]])?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that each of `keys` is present in `obj`, under any of its aliases.
+fn check_keys(obj: &serde_json::Map<String, serde_json::Value>, keys: &[&[&str]]) -> Result<(), String> {
+    match keys
+        .iter()
+        .find(|aliases| !aliases.iter().any(|k| obj.contains_key(*k)))
+    {
+        Some(aliases) => Err(format!("missing `{}`", aliases[0])),
+        None => Ok(()),
+    }
+}
+
+/// Common fields for ProfileConfig types that do not use a config file.
+#[derive(Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Debug)]
+pub struct ConnectOptions {
+    local_addr: (IpAddr, u16),
+    server_addr: (String, u16),
+    #[derivative(Debug(format_with = "password_omit"))]
+    password: Password,
+    encrypt_method: String,
+}
+impl ToLaunchArgs for ConnectOptions {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        let mut args = vec![];
+        // local address
+        let local_addr = {
+            let (a, p) = self.local_addr;
+            match a {
+                IpAddr::V4(v4) => format!("{}:{}", v4, p),
+                IpAddr::V6(v6) => format!("[{}]:{}", v6, p),
+            }
+        };
+        args.extend_from_slice(&["--local-addr".into(), local_addr.into()]);
+        // server address
+        let server_addr = {
+            let (a, p) = &self.server_addr;
+            match a.parse::<Ipv6Addr>() {
+                Ok(_) => format!("[{}]:{}", a, p), // IPv6
+                Err(_) => format!("{}:{}", a, p),  // Domain or IPv4
+            }
+        };
+        args.extend_from_slice(&["--server-addr".into(), server_addr.into()]);
+        // password; those in the keyring are resolved by `Profile::run_sslocal`
+        if let Some(password) = self.password.as_plain() {
+            args.extend_from_slice(&["--password".into(), password.into()]);
+        }
+        // encrypt_method
+        args.extend_from_slice(&["--encrypt-method".into(), (&self.encrypt_method).into()]);
+        args
+    }
+}
+
+/// Fields for a single server of a "Balanced"-type ProfileConfig.
+#[derive(Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Debug)]
+pub struct ServerOptions {
+    /// Shown in `sslocal`'s output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    server_addr: (String, u16),
+    #[derivative(Debug(format_with = "password_omit"))]
+    password: Password,
+    encrypt_method: String,
+}
+
+/// Fields for a "Balanced"-type ProfileConfig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancedOptions {
+    local_addr: (IpAddr, u16),
+    servers: Vec<ServerOptions>,
+}
+impl BalancedOptions {
+    /// Generate an `sslocal` config file with all servers,
+    /// so that `sslocal`'s built-in load balancer is used.
+    ///
+    /// See https://github.com/shadowsocks/shadowsocks-rust#configuration.
+    fn to_sslocal_config(&self) -> serde_json::Value {
+        let (local_address, local_port) = self.local_addr;
+        let servers: Vec<_> = self
+            .servers
+            .iter()
+            .map(|s| {
+                let mut server = json!({
+                    "server": s.server_addr.0,
+                    "server_port": s.server_addr.1,
+                    "password": s.password.as_plain(),
+                    "method": s.encrypt_method,
+                });
+                if let Some(name) = &s.name {
+                    server["remarks"] = name.as_str().into();
+                }
+                server
+            })
+            .collect();
+        json!({
+            "locals": [{ "local_address": local_address, "local_port": local_port }],
+            "servers": servers,
+        })
+    }
+
+    /// Write the generated `sslocal` config file to `path`, readable only by the current user.
+    fn write_sslocal_config(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(&self.to_sslocal_config())?;
+        write_private_file(path, &content)
+    }
+}
+
+/// Write a file only readable by the current user, since it contains passwords.
+fn write_private_file(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(content.as_bytes())
+}
+
+/// Replace characters unfit for a file or directory name.
+fn to_file_name(raw: &str) -> String {
+    raw.trim_start_matches('.')
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// Recursively copy the content of directory `from` into a new directory `to`.
+fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    for ent in from.read_dir()? {
+        let ent = ent?;
+        let target = to.join(ent.file_name());
+        if ent.file_type()?.is_dir() {
+            copy_dir_all(&ent.path(), &target)?;
+        } else {
+            fs::copy(ent.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the profile directory `src` from elsewhere into a new directory of the same name under `parent`,
+/// along with everything in it, so that files referenced by relative paths still resolve.
+///
+/// Fails if the new directory already exists.
+pub fn copy_profile_dir(src: impl AsRef<Path>, parent: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let src = src.as_ref();
+    let name = src.file_name().ok_or_else(|| {
+        let msg = format!("{:?} has no directory name", src);
+        io::Error::new(io::ErrorKind::InvalidInput, msg)
+    })?;
+    let dir = parent.as_ref().join(name);
+    if dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} already exists", dir),
+        ));
+    }
+    copy_dir_all(src, &dir)?;
+    Ok(dir)
+}
+
+/// Move the profile directory `dir` into the archive directory under `profiles_dir`,
+/// creating it with an ignore file if necessary, so that the profile is no longer loaded.
+///
+/// A number is appended to the name if an archived profile of the same name exists.
+/// Returns the directory the profile is moved to.
+pub fn archive_profile_dir(dir: impl AsRef<Path>, profiles_dir: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let dir = dir.as_ref();
+    let name = dir.file_name().ok_or_else(|| {
+        let msg = format!("{:?} has no directory name", dir);
+        io::Error::new(io::ErrorKind::InvalidInput, msg)
+    })?;
+    let archive = profiles_dir.as_ref().join(ARCHIVED_PROFILES_DIR_NAME);
+    fs::create_dir_all(&archive)?;
+    let ignore_file = archive.join(PROFILE_IGNORE_FILE_NAME);
+    if !ignore_file.is_file() {
+        fs::write(ignore_file, "")?;
+    }
+    let target = (1..)
+        .map(|n| match n {
+            1 => archive.join(name),
+            n => archive.join(format!("{} ({})", name.to_string_lossy(), n)),
+        })
+        .find(|target| !target.exists())
+        .expect("there are unused names");
+    fs::rename(dir, &target)?;
+    Ok(target)
+}
+
+/// Percent-decode a string, replacing invalid UTF-8 sequences.
+/// Characters to percent-encode in a URL component, i.e. all but RFC 3986 unreserved characters.
+const URL_COMPONENT_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+fn percent_encode(s: &str) -> String {
+    utf8_percent_encode(s, URL_COMPONENT_ENCODE_SET).to_string()
+}
+
+fn percent_decode_lossy(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+/// Decode a base64 string that may be URL-safe and/or unpadded.
+///
+/// Returns `None` if the string is not valid base64 or does not decode to UTF-8.
+fn decode_base64_lossy(s: &str) -> Option<String> {
+    let s = s.trim_end_matches('=');
+    let bytes = base64::decode_config(s, base64::URL_SAFE_NO_PAD)
+        .or_else(|_| base64::decode_config(s, base64::STANDARD_NO_PAD))
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Quote `word` for a POSIX shell, unless it only contains characters that need no quoting.
+fn shell_quote(word: &OsStr) -> String {
+    let word = word.to_string_lossy();
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.into_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Helper function for `derivative(Debug)`.
+fn password_omit(_: &Password, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(fmt, "*hidden*")
+}
+
+/// A password, either in plain text or stored in the keyring.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Password {
+    Plain(String),
+    /// The name of an entry in the freedesktop Secret Service, looked up at launch time.
+    Keyring {
+        keyring: String,
+    },
+}
+impl From<String> for Password {
+    fn from(password: String) -> Self {
+        Self::Plain(password)
+    }
+}
+impl Password {
+    /// Get the password in plain text, unless it is stored in the keyring.
+    pub fn as_plain(&self) -> Option<&str> {
+        match self {
+            Self::Plain(password) => Some(password),
+            Self::Keyring { .. } => None,
+        }
+    }
+    /// Look up the password in the keyring if necessary.
+    fn resolve(&mut self) -> io::Result<()> {
+        if let Self::Keyring { keyring } = self {
+            *self = Self::Plain(keyring::lookup(keyring)?);
+            self.register();
+        }
+        Ok(())
+    }
+    /// Mask this password wherever it appears if it is in plain text, see `crate::redact`.
+    fn register(&self) {
+        if let Self::Plain(password) = self {
+            redact::register(password);
+            // `go-shadowsocks2` takes it percent-encoded in a URL
+            redact::register(&percent_encode(password));
+        }
+    }
+}
+
+/// Fields for a "Proxy"-type ProfileConfig
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyOptions {
+    // TODO: Add protocol selection
+    /// A SIP003 plugin, e.g. `v2ray-plugin`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plugin: Option<String>,
+    /// Options passed to the plugin via `SS_PLUGIN_OPTIONS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plugin_opts: Option<String>,
+    /// Also serve an HTTP proxy on this port, at the same address as the SOCKS5 proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    http_port: Option<u16>,
+}
+impl ProxyOptions {
+    /// Generate an `sslocal` config file with only an HTTP local at `http_port`,
+    /// which `sslocal` serves in addition to the one set with `--local-addr`.
+    fn to_http_local_config(&self, local_ip: IpAddr) -> Option<serde_json::Value> {
+        self.http_port.map(|port| {
+            json!({
+                "locals": [{ "local_address": local_ip, "local_port": port, "protocol": "http" }],
+            })
+        })
+    }
+}
+impl ToLaunchArgs for ProxyOptions {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        let mut args = vec![];
+        // plugin
+        if let Some(plugin) = &self.plugin {
+            args.extend_from_slice(&["--plugin".into(), plugin.into()]);
+            // plugin options
+            if let Some(opts) = &self.plugin_opts {
+                args.extend_from_slice(&["--plugin-opts".into(), opts.into()]);
+            }
+        }
+        args
+    }
+}
+
+/// Fields for a "Tun"-type ProfileConfig.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    if_name: Option<String>,
+    /// The address and netmask of the interface.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    if_addr: Option<IpNet>,
+    /// The address and netmask of the other end, for point-to-point interfaces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    if_destination: Option<IpNet>,
+    /// Routes through the interface, set up after it has been created.
+    ///
+    /// Requires `if_name`, and a privileged launch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    routes: Vec<IpNet>,
+    /// The routing table to add `routes` to, instead of the main table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route_table: Option<u32>,
+}
+impl ToLaunchArgs for TunOptions {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        // protocol
+        let mut args = vec!["--protocol".into(), "tun".into()];
+        // interface name
+        if let Some(if_name) = &self.if_name {
+            args.extend_from_slice(&["--tun-interface-name".into(), if_name.into()]);
+        }
+        // interface address
+        if let Some(if_addr) = &self.if_addr {
+            args.extend_from_slice(&["--tun-interface-address".into(), if_addr.to_string().into()]);
+        }
+        // interface destination
+        if let Some(if_dest) = &self.if_destination {
+            args.extend_from_slice(&["--tun-interface-destination".into(), if_dest.to_string().into()]);
+        }
+        args
+    }
+}
+impl TunOptions {
+    /// The routes to set up, if any.
+    fn tun_routes(&self) -> Option<TunRoutes<'_>> {
+        match (&self.if_name, self.routes.is_empty()) {
+            (Some(if_name), false) => Some(TunRoutes {
+                if_name,
+                table: self.route_table,
+                routes: &self.routes,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Extra configs for advanced users.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvancedOptions {
+    // IMPRV: more to come
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra_args: Option<Vec<String>>,
+    /// Routing rules, passed to `sslocal` as a generated ACL file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    acl: Option<AclRules>,
+}
+impl ToLaunchArgs for AdvancedOptions {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        let mut args = vec![];
+        // extra args
+        if let Some(extra) = &self.extra_args {
+            args.append(&mut extra.iter().map_into().collect())
+        }
+        args
+    }
+}
+
+/// Builds `ss-local` arguments for a config supported by `Backend::ShadowsocksLibev`.
+///
+/// See `man ss-local`.
+struct LibevArgs<'a>(&'a ProfileConfig);
+impl ToLaunchArgs for LibevArgs<'_> {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![];
+        match self.0 {
+            ProfileConfig::ConfigFile { opts, adv_opts, .. } => {
+                args.extend_from_slice(&["-c".into(), (&opts.config_path).into()]);
+                args.append(&mut adv_opts.to_launch_args());
+            }
+            ProfileConfig::Proxy {
+                conn_opts,
+                opts,
+                adv_opts,
+                ..
+            } => {
+                let (local_ip, local_port) = conn_opts.local_addr;
+                let (server_host, server_port) = &conn_opts.server_addr;
+                args.extend_from_slice(&[
+                    "-s".into(),
+                    server_host.into(),
+                    "-p".into(),
+                    server_port.to_string().into(),
+                    "-b".into(),
+                    local_ip.to_string().into(),
+                    "-l".into(),
+                    local_port.to_string().into(),
+                ]);
+                if let Some(password) = conn_opts.password.as_plain() {
+                    args.extend_from_slice(&["-k".into(), password.into()]);
+                }
+                args.extend_from_slice(&["-m".into(), (&conn_opts.encrypt_method).into()]);
+                // same as shadowsocks-rust
+                args.append(&mut opts.to_launch_args());
+                args.append(&mut adv_opts.to_launch_args());
+            }
+            // rejected by `Backend::check_supported`
+            ProfileConfig::Tun { .. } | ProfileConfig::Balanced { .. } => {}
+        }
+        args
+    }
+}
+
+/// Builds `go-shadowsocks2` arguments for a config supported by `Backend::GoShadowsocks2`.
+///
+/// See https://github.com/shadowsocks/go-shadowsocks2#client.
+struct GoShadowsocks2Args<'a>(&'a ProfileConfig);
+impl ToLaunchArgs for GoShadowsocks2Args<'_> {
+    fn to_launch_args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![];
+        if let ProfileConfig::Proxy {
+            conn_opts,
+            opts,
+            adv_opts,
+            ..
+        } = self.0
+        {
+            let (host, port) = &conn_opts.server_addr;
+            let host = match host.parse::<Ipv6Addr>() {
+                Ok(v6) => format!("[{}]", v6),
+                Err(_) => host.clone(),
+            };
+            let password = conn_opts.password.as_plain().unwrap_or_default();
+            let server_url = format!(
+                "ss://{}:{}@{}:{}",
+                percent_encode(&conn_opts.encrypt_method),
+                percent_encode(password),
+                host,
+                port
+            );
+            args.extend_from_slice(&[
+                "-c".into(),
+                server_url.into(),
+                "-socks".into(),
+                SocketAddr::from(conn_opts.local_addr).to_string().into(),
+            ]);
+            if let Some(plugin) = &opts.plugin {
+                args.extend_from_slice(&["-plugin".into(), plugin.into()]);
+                if let Some(plugin_opts) = &opts.plugin_opts {
+                    args.extend_from_slice(&["-plugin-opts".into(), plugin_opts.into()]);
+                }
+            }
+            args.append(&mut adv_opts.to_launch_args());
+        }
+        args
+    }
+}
+
+/// The static configuration for a profile. Represents the file on disk faithfully.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "kebab-case")] // See https://serde.rs/enum-representations.html#internally-tagged
+pub enum ProfileConfig {
+    /// Profile launches `sslocal` with arbitrary config file using `sslocal --config <CONFIG>`.
+    ConfigFile {
+        #[serde(flatten)]
+        metadata: MetadataOverride,
+        #[serde(flatten)]
+        opts: ConfigFileOptions,
+        #[serde(flatten)]
+        adv_opts: AdvancedOptions,
+    },
+    /// Profile launches `sslocal` in proxy mode.
+    Proxy {
+        #[serde(flatten)]
+        metadata: MetadataOverride,
+        #[serde(flatten)]
+        conn_opts: ConnectOptions,
+        #[serde(flatten)]
+        opts: ProxyOptions,
+        #[serde(flatten)]
+        adv_opts: AdvancedOptions,
+    },
+    /// Profile launches `sslocal` in tun mode.
+    Tun {
+        #[serde(flatten)]
+        metadata: MetadataOverride,
+        #[serde(flatten)]
+        conn_opts: ConnectOptions,
+        #[serde(flatten)]
+        opts: TunOptions,
+        #[serde(flatten)]
+        adv_opts: AdvancedOptions,
+    },
+    /// Profile launches `sslocal` in proxy mode with multiple servers,
+    /// using a generated config file so that `sslocal` balances between them.
+    Balanced {
+        #[serde(flatten)]
+        metadata: MetadataOverride,
+        #[serde(flatten)]
+        opts: BalancedOptions,
+        #[serde(flatten)]
+        adv_opts: AdvancedOptions,
+    },
+}
+
+#[derive(Debug)]
+pub enum SsUrlError {
+    /// The string is not a valid URL.
+    BadUrl(url::ParseError),
+    /// The URL does not use the `ss` scheme.
+    WrongScheme(String),
+    /// The user info section cannot be decoded into an encrypt method and a password.
+    BadUserInfo(String),
+    /// A mandatory component of the URL is missing.
+    MissingComponent(&'static str),
+}
+
+impl fmt::Display for SsUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SsUrlError::*;
+
+        let prefix = "SsUrlError";
+        match self {
+            BadUrl(e) => write!(f, "{}-BadUrl: {}", prefix, e),
+            WrongScheme(s) => write!(f, "{}-WrongScheme: expected \"ss\", got \"{}\"", prefix, s),
+            BadUserInfo(s) => write!(f, "{}-BadUserInfo: {}", prefix, s),
+            MissingComponent(s) => write!(f, "{}-MissingComponent: {}", prefix, s),
+        }
+    }
+}
+
+impl From<url::ParseError> for SsUrlError {
+    fn from(err: url::ParseError) -> Self {
+        Self::BadUrl(err)
+    }
+}
+
+impl ProfileConfig {
+    /// Parse a SIP002 `ss://` URL into a "Proxy"-type `ProfileConfig`.
+    ///
+    /// The URL's tag becomes the display name, and the plugin (if any)
+    /// is set as the config's plugin. Because a URL does not
+    /// specify a local address, `IMPORT_LOCAL_ADDR_DEFAULT` is used.
+    ///
+    /// The legacy `ss://BASE64(method:password@host:port)` format is also accepted.
+    ///
+    /// See https://shadowsocks.org/guide/sip002.html.
+    pub fn from_ss_url(url_str: &str) -> Result<Self, SsUrlError> {
+        let url_str = url_str.trim();
+
+        // legacy format has no user info section
+        if let Some(body) = url_str.strip_prefix("ss://") {
+            let (main, tag) = match body.split_once('#') {
+                Some((m, t)) => (m, Some(t)),
+                None => (body, None),
+            };
+            if !main.contains('@') {
+                return Self::from_legacy_ss_url(main, tag);
+            }
+        }
+
+        let url = Url::parse(url_str)?;
+        if url.scheme() != "ss" {
+            return Err(SsUrlError::WrongScheme(url.scheme().into()));
+        }
+
+        // user info
+        let (encrypt_method, password) = match url.password() {
+            // plain user info, used by SIP022 AEAD-2022 ciphers
+            Some(pw) => (percent_decode_lossy(url.username()), percent_decode_lossy(pw)),
+            // base64 encoded user info
+            None => {
+                let decoded = decode_base64_lossy(&percent_decode_lossy(url.username()))
+                    .ok_or_else(|| SsUrlError::BadUserInfo("user info is not valid base64".into()))?;
+                let (m, p) = decoded
+                    .split_once(':')
+                    .ok_or_else(|| SsUrlError::BadUserInfo("user info has no ':' separator".into()))?;
+                (m.to_string(), p.to_string())
+            }
+        };
+        if encrypt_method.is_empty() {
+            return Err(SsUrlError::BadUserInfo("encrypt method is empty".into()));
+        }
+
+        // server address
+        let host = match url.host().ok_or(SsUrlError::MissingComponent("host"))? {
+            Host::Domain(d) => percent_decode_lossy(d),
+            Host::Ipv4(a) => a.to_string(),
+            Host::Ipv6(a) => a.to_string(),
+        };
+        let port = url.port().ok_or(SsUrlError::MissingComponent("port"))?;
+
+        // plugin, in the format of `plugin-name;plugin-opts`
+        let (plugin, plugin_opts) = match url.query_pairs().find(|(k, _)| k == "plugin") {
+            Some((_, plugin)) => match plugin.split_once(';') {
+                Some((name, opts)) => (Some(name.into()), Some(opts.into())),
+                None => (Some(plugin.into_owned()), None),
+            },
+            None => (None, None),
+        };
+
+        let display_name = url.fragment().map(percent_decode_lossy).filter(|s| !s.is_empty());
+
+        Ok(Self::new_proxy(
+            display_name,
+            (host, port),
+            password,
+            encrypt_method,
+            plugin,
+            plugin_opts,
+        ))
+    }
+
+    /// Parse the body (without scheme and tag) of a legacy `ss://` URL.
+    fn from_legacy_ss_url(body: &str, tag: Option<&str>) -> Result<Self, SsUrlError> {
+        let decoded = decode_base64_lossy(body)
+            .ok_or_else(|| SsUrlError::BadUserInfo("legacy URL body is not valid base64".into()))?;
+        let (user_info, host_port) = decoded.rsplit_once('@').ok_or(SsUrlError::MissingComponent("host"))?;
+        let (encrypt_method, password) = user_info
+            .split_once(':')
+            .ok_or_else(|| SsUrlError::BadUserInfo("user info has no ':' separator".into()))?;
+        let (host, port) = host_port.rsplit_once(':').ok_or(SsUrlError::MissingComponent("port"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port.parse().map_err(|_| SsUrlError::MissingComponent("port"))?;
+
+        let display_name = tag.map(percent_decode_lossy).filter(|s| !s.is_empty());
+
+        Ok(Self::new_proxy(
+            display_name,
+            (host.into(), port),
+            password.into(),
+            encrypt_method.into(),
+            None,
+            None,
+        ))
+    }
+
+    /// Encode a "Proxy"-type `ProfileConfig` as a SIP002 `ss://` URL, with `tag` as its name.
+    ///
+    /// The plugin (if any) is taken from the config's plugin, or recovered from `extra_args`;
+    /// all other extra arguments, as well as the local address, are not representable and therefore dropped.
+    /// Returns `None` for other types of config, or if the password is in the keyring
+    /// (see `Self::resolve_passwords`).
+    ///
+    /// See https://shadowsocks.org/guide/sip002.html.
+    pub fn to_ss_url(&self, tag: &str) -> Option<String> {
+        let (conn_opts, opts, adv_opts) = match self {
+            Self::Proxy {
+                conn_opts,
+                opts,
+                adv_opts,
+                ..
+            } => (conn_opts, opts, adv_opts),
+            _ => return None,
+        };
+        let password = conn_opts.password.as_plain()?;
+
+        // SIP022 AEAD-2022 ciphers must use plain user info; others should use base64
+        let user_info = if conn_opts.encrypt_method.starts_with("2022-") {
+            format!(
+                "{}:{}",
+                percent_encode(&conn_opts.encrypt_method),
+                percent_encode(password)
+            )
+        } else {
+            let plain = format!("{}:{}", conn_opts.encrypt_method, password);
+            base64::encode_config(plain, base64::URL_SAFE_NO_PAD)
+        };
+
+        let (host, port) = &conn_opts.server_addr;
+        let host = match host.parse::<Ipv6Addr>() {
+            Ok(v6) => format!("[{}]", v6),
+            Err(_) => host.clone(),
+        };
+
+        // plugin, in the format of `plugin-name;plugin-opts`
+        // older profiles may specify the plugin in `extra_args` instead
+        let args = adv_opts.extra_args.as_deref().unwrap_or_default();
+        let arg_value = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+        let plugin = match &opts.plugin {
+            Some(name) => Some((name, opts.plugin_opts.as_ref())),
+            None => arg_value("--plugin").map(|name| (name, arg_value("--plugin-opts"))),
+        };
+        let query = match plugin {
+            Some((name, Some(opts))) => format!("/?plugin={}", percent_encode(&format!("{};{}", name, opts))),
+            Some((name, None)) => format!("/?plugin={}", percent_encode(name)),
+            None => "".into(),
+        };
+
+        Some(format!(
+            "ss://{}@{}:{}{}#{}",
+            user_info,
+            host,
+            port,
+            query,
+            percent_encode(tag)
+        ))
+    }
+
+    /// Construct a "Proxy"-type `ProfileConfig` bound to `IMPORT_LOCAL_ADDR_DEFAULT`.
+    pub fn new_proxy(
+        display_name: Option<String>,
+        server_addr: (String, u16),
+        password: String,
+        encrypt_method: String,
+        plugin: Option<String>,
+        plugin_opts: Option<String>,
+    ) -> Self {
+        Self::Proxy {
+            metadata: MetadataOverride {
+                display_name,
+                pwd: None,
+                bin_path: None,
+                system_proxy: None,
+                requires_privilege: None,
+                backend: None,
+                icon: None,
+                color: None,
+                tags: vec![],
+                env: BTreeMap::new(),
+                log_level: None,
+                hooks: Hooks::default(),
+            },
+            conn_opts: ConnectOptions {
+                local_addr: IMPORT_LOCAL_ADDR_DEFAULT,
+                server_addr,
+                password: password.into(),
+                encrypt_method,
+            },
+            opts: ProxyOptions {
+                plugin,
+                plugin_opts,
+                http_port: None,
+            },
+            adv_opts: AdvancedOptions {
+                extra_args: None,
+                acl: None,
+            },
+        }
+    }
+
+    /// Get the mode of this config, as written in the config file.
+    pub fn mode(&self) -> &'static str {
+        match self {
+            Self::ConfigFile { .. } => "config-file",
+            Self::Proxy { .. } => "proxy",
+            Self::Tun { .. } => "tun",
+            Self::Balanced { .. } => "balanced",
+        }
+    }
+
+    /// Get the display name override of this config, if set.
+    pub fn display_name(&self) -> Option<&str> {
+        self.get_metadata_override().display_name.as_deref()
+    }
+
+    /// Get the local address of this config, if it is a "Proxy" or "Balanced"-type config.
+    pub fn local_addr(&self) -> Option<(IpAddr, u16)> {
+        match self {
+            Self::Proxy { conn_opts, .. } => Some(conn_opts.local_addr),
+            Self::Balanced { opts, .. } => Some(opts.local_addr),
+            _ => None,
+        }
+    }
+
+    /// Get the address of the HTTP proxy of this config, if it is a "Proxy"-type config with `http_port` set.
+    pub fn http_local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Proxy { conn_opts, opts, .. } => {
+                opts.http_port.map(|port| SocketAddr::new(conn_opts.local_addr.0, port))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the address at which the local proxy of this config can be reached,
+    /// if it is a "Proxy" or "Balanced"-type config.
+    ///
+    /// Unlike `local_addr`, wildcard addresses are replaced with localhost.
+    pub fn local_proxy_addr(&self) -> Option<SocketAddr> {
+        self.local_addr().map(|(ip, port)| {
+            let ip = match ip {
+                IpAddr::V4(v4) if v4.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+                IpAddr::V6(v6) if v6.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+                ip => ip,
+            };
+            SocketAddr::new(ip, port)
+        })
+    }
+
+    /// Get the server address of this config, if it has exactly one known server.
+    pub fn server_addr(&self) -> Option<(&str, u16)> {
+        use ProfileConfig::*;
+        match self {
+            Proxy { conn_opts, .. } | Tun { conn_opts, .. } => {
+                let (host, port) = &conn_opts.server_addr;
+                Some((host, *port))
+            }
+            ConfigFile { .. } | Balanced { .. } => None,
+        }
+    }
+
+    /// Get the addresses of all known servers of this config.
+    pub fn server_addrs(&self) -> Vec<(&str, u16)> {
+        match self {
+            Self::Balanced { opts, .. } => opts
+                .servers
+                .iter()
+                .map(|s| (s.server_addr.0.as_str(), s.server_addr.1))
+                .collect(),
+            _ => self.server_addr().into_iter().collect(),
+        }
+    }
+
+    /// Get the tun interface name of this config, if it is a "Tun"-type config that sets one.
+    pub fn tun_if_name(&self) -> Option<&str> {
+        match self {
+            Self::Tun { opts, .. } => opts.if_name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// A reasonable directory name for this config, derived from its display name
+    /// or its server address, with characters unfit for a directory name replaced.
+    pub fn suggested_dir_name(&self) -> String {
+        use ProfileConfig::*;
+        let raw = match (self.display_name(), self) {
+            (Some(name), _) => name.to_string(),
+            (None, Proxy { conn_opts, .. } | Tun { conn_opts, .. }) => {
+                let (host, port) = &conn_opts.server_addr;
+                format!("{}-{}", host, port)
+            }
+            (None, ConfigFile { .. }) => "Imported-profile".into(),
+            (None, Balanced { .. }) => "Balanced-profile".into(),
+        };
+        to_file_name(&raw)
+    }
+
+    /// Create a new profile directory named `dir_name` under `parent`,
+    /// and write this config into it as the profile's config file.
+    ///
+    /// Fails if the directory already exists.
+    pub fn write_to_new_dir(&self, parent: impl AsRef<Path>, dir_name: &str) -> io::Result<PathBuf> {
+        let dir = parent.as_ref().join(dir_name);
+        if dir.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", dir),
+            ));
+        }
+        fs::create_dir_all(&dir)?;
+        self.write_to_dir(&dir)?;
+        Ok(dir)
+    }
+
+    /// Write this config into an existing profile directory as the profile's config file,
+    /// replacing the existing one if present and keeping its format.
+    pub fn write_to_dir(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let format = ConfigFormat::find_all(&dir)
+            .first()
+            .copied()
+            .unwrap_or(ConfigFormat::Yaml);
+        let content = format.serialize(self)?;
+        format.write_to(dir.as_ref(), &content)
+    }
+
+    /// Read the config of the profile directory `dir`, decrypting it if encrypted.
+    pub fn read_from_dir(dir: impl AsRef<Path>) -> Result<Self, ProfileLoadError> {
+        let dir = dir.as_ref();
+        match ConfigFormat::find_all(dir)[..] {
+            [format] => format.parse(&format.read_from(dir)?),
+            [] => Err(ProfileLoadError::InvalidConfig(format!(
+                "{:?} contains no profile config file",
+                dir
+            ))),
+            _ => Err(ProfileLoadError::InvalidConfig(format!(
+                "{:?} contains more than one profile config file",
+                dir
+            ))),
+        }
+    }
+
+    /// Set the display name override of this config.
+    pub fn set_display_name(&mut self, name: impl Into<String>) {
+        self.get_metadata_override_mut().display_name = Some(name.into());
+    }
+
+    /// Whether this config's routing rules use gfwlist.
+    pub fn uses_gfwlist(&self) -> bool {
+        self.get_advanced_options().acl.as_ref().is_some_and(|acl| acl.gfwlist)
+    }
+    fn get_advanced_options(&self) -> &AdvancedOptions {
+        use ProfileConfig::*;
+        match self {
+            ConfigFile { adv_opts, .. } => adv_opts,
+            Proxy { adv_opts, .. } => adv_opts,
+            Tun { adv_opts, .. } => adv_opts,
+            Balanced { adv_opts, .. } => adv_opts,
+        }
+    }
+    fn get_metadata_override(&self) -> &MetadataOverride {
+        use ProfileConfig::*;
+        match self {
+            ConfigFile { metadata, .. } => metadata,
+            Proxy { metadata, .. } => metadata,
+            Tun { metadata, .. } => metadata,
+            Balanced { metadata, .. } => metadata,
+        }
+    }
+    /// Get a copy of this config with all passwords in the keyring looked up.
+    pub fn resolve_passwords(&self) -> io::Result<Self> {
+        let mut config = self.clone();
+        match &mut config {
+            Self::Proxy { conn_opts, .. } | Self::Tun { conn_opts, .. } => conn_opts.password.resolve()?,
+            Self::Balanced { opts, .. } => {
+                for server in opts.servers.iter_mut() {
+                    server.password.resolve()?;
+                }
+            }
+            Self::ConfigFile { .. } => {}
+        }
+        Ok(config)
+    }
+    /// Get a copy of this config with all passwords, including those in the keyring,
+    /// replaced by `PASSWORD_MASK`.
+    pub fn mask_passwords(&self) -> Self {
+        let mut config = self.clone();
+        config.for_each_password(|password| *password = Password::Plain(PASSWORD_MASK.into()));
+        config
+    }
+    /// Mask the plain-text passwords of this config wherever they appear, see `crate::redact`.
+    fn register_passwords(&self) {
+        match self {
+            Self::Proxy { conn_opts, .. } | Self::Tun { conn_opts, .. } => conn_opts.password.register(),
+            Self::Balanced { opts, .. } => opts.servers.iter().for_each(|server| server.password.register()),
+            Self::ConfigFile { .. } => {}
+        }
+    }
+    /// Call `f` on each password of this config.
+    fn for_each_password(&mut self, mut f: impl FnMut(&mut Password)) {
+        match self {
+            Self::Proxy { conn_opts, .. } | Self::Tun { conn_opts, .. } => f(&mut conn_opts.password),
+            Self::Balanced { opts, .. } => opts.servers.iter_mut().for_each(|server| f(&mut server.password)),
+            Self::ConfigFile { .. } => {}
+        }
+    }
+    fn get_metadata_override_mut(&mut self) -> &mut MetadataOverride {
+        use ProfileConfig::*;
+        match self {
+            ConfigFile { metadata, .. } => metadata,
+            Proxy { metadata, .. } => metadata,
+            Tun { metadata, .. } => metadata,
+            Balanced { metadata, .. } => metadata,
+        }
+    }
+    fn to_launch_args(&self) -> Vec<OsString> {
+        use ProfileConfig::*;
+        match self {
+            ConfigFile { opts, adv_opts, .. } => {
+                let mut args = opts.to_launch_args();
+                args.append(&mut adv_opts.to_launch_args());
+                args
+            }
+            Proxy {
+                conn_opts,
+                opts,
+                adv_opts,
+                ..
+            } => {
+                let mut args = conn_opts.to_launch_args();
+                args.append(&mut opts.to_launch_args());
+                args.append(&mut adv_opts.to_launch_args());
+                args
+            }
+            Tun {
+                conn_opts,
+                opts,
+                adv_opts,
+                ..
+            } => {
+                let mut args = conn_opts.to_launch_args();
+                args.append(&mut opts.to_launch_args());
+                args.append(&mut adv_opts.to_launch_args());
+                args
+            }
+            // the generated config file is passed by `Profile::run_sslocal`
+            Balanced { adv_opts, .. } => adv_opts.to_launch_args(),
+        }
+    }
+}
+
+/// A supported format of the profile config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    /// Parsed as JSON5, so comments are allowed.
+    Json,
+}
+impl ConfigFormat {
+    pub const ALL: [Self; 3] = [Self::Yaml, Self::Toml, Self::Json];
+
+    /// The name of the profile config file in this format.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Yaml => PROFILE_CONFIG_FILE_NAME,
+            Self::Toml => PROFILE_CONFIG_TOML_FILE_NAME,
+            Self::Json => PROFILE_CONFIG_JSON_FILE_NAME,
+        }
+    }
+
+    /// The name of the profile config file in this format, encrypted with the master passphrase.
+    pub fn encrypted_file_name(self) -> String {
+        format!("{}{}", self.file_name(), ENCRYPTED_CONFIG_FILE_SUFFIX)
+    }
+
+    /// Find the formats of all profile config files present in a directory, encrypted or not.
+    ///
+    /// A format is listed twice if both its encrypted and plain files are present.
+    pub fn find_all(dir: impl AsRef<Path>) -> Vec<Self> {
+        let dir = dir.as_ref();
+        Self::ALL
+            .into_iter()
+            .flat_map(|f| {
+                let plain = dir.join(f.file_name()).is_file();
+                let encrypted = dir.join(f.encrypted_file_name()).is_file();
+                [plain, encrypted]
+                    .into_iter()
+                    .filter(|&present| present)
+                    .map(move |_| f)
+            })
+            .collect()
+    }
+
+    /// Whether the profile config file in this format is encrypted in a directory.
+    pub fn is_encrypted_in(self, dir: impl AsRef<Path>) -> bool {
+        dir.as_ref().join(self.encrypted_file_name()).is_file()
+    }
+
+    /// Read the profile config file in this format from a directory, decrypting it if encrypted.
+    fn read_from(self, dir: &Path) -> io::Result<String> {
+        if !self.is_encrypted_in(dir) {
+            return read_to_string(dir.join(self.file_name()));
+        }
+        #[cfg(feature = "encryption")]
+        return encryption::read_to_string(&dir.join(self.encrypted_file_name()));
+        #[cfg(not(feature = "encryption"))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "encrypted profiles require the \"encryption\" feature",
+        ))
+    }
+
+    /// Write `content` as the profile config file in this format into a directory,
+    /// encrypting it if the existing one is encrypted.
+    fn write_to(self, dir: &Path, content: &str) -> io::Result<()> {
+        if !self.is_encrypted_in(dir) {
+            return fs::write(dir.join(self.file_name()), content);
+        }
+        #[cfg(feature = "encryption")]
+        return encryption::write(&dir.join(self.encrypted_file_name()), content);
+        #[cfg(not(feature = "encryption"))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "encrypted profiles require the \"encryption\" feature",
+        ))
+    }
+
+    fn parse(self, content: &str) -> Result<ProfileConfig, ProfileLoadError> {
+        let config = match self {
+            Self::Yaml if legacy_profile::is_legacy(content) => {
+                // still loaded, so that nothing breaks before the user gets round to migrating
+                let migrated =
+                    legacy_profile::migrate(content).map_err(|err| ProfileLoadError::LegacyConfig(err.to_string()))?;
+                warn!(
+                    "ss_bin_path and ss_config_path are deprecated; \
+                    run `ssgtk migrate-profiles` to upgrade legacy profiles"
+                );
+                serde_yaml::from_str(&migrated)?
+            }
+            Self::Yaml => serde_yaml::from_str(content)?,
+            Self::Toml => toml::from_str(content)?,
+            Self::Json => json5::from_str(content)?,
+        };
+        Ok(config)
+    }
+
+    fn serialize(self, config: &ProfileConfig) -> io::Result<String> {
+        let to_io_err = |err: Box<dyn std::error::Error + Send + Sync>| io::Error::new(io::ErrorKind::InvalidData, err);
+        match self {
+            Self::Yaml => serde_yaml::to_string(config).map_err(|err| to_io_err(err.into())),
+            // going through a `Value` puts plain values before tables, as TOML requires
+            Self::Toml => toml::Value::try_from(config)
+                .and_then(|value| toml::to_string_pretty(&value))
+                .map_err(|err| to_io_err(err.into())),
+            Self::Json => serde_json::to_string_pretty(config).map_err(|err| to_io_err(err.into())),
+        }
+    }
+}
+
+/// Dynamically generated and patched metadata for a profile.
+#[derive(Debug, Clone)]
+pub struct ProfileMetadata {
+    pub display_name: String,
+    /// The directory this profile is loaded from.
+    ///
+    /// `None` if this profile is not backed by a directory, e.g. from a subscription.
+    pub config_dir: Option<PathBuf>,
+    /// The directory relative paths in the config are resolved against.
+    ///
+    /// This is the profile directory, or for profiles not backed by one,
+    /// the directory of their manifest or subscription.
+    base_dir: PathBuf,
+    pwd: PathBuf,
+    bin_path: PathBuf,
+    /// Whether to set the system proxy when this profile is active.
+    ///
+    /// `None` means following the global setting.
+    pub system_proxy: Option<bool>,
+    /// Whether `sslocal` must always run as root.
+    requires_privilege: bool,
+    pub backend: Backend,
+    /// The problem found with the `sslocal` config file when loaded, if any.
+    config_error: Option<String>,
+    /// Whether this profile is pinned to the top of its group by the group's order file.
+    pub pinned: bool,
+    /// An icon name, or the resolved path to an image.
+    pub icon: Option<String>,
+    pub color: Option<ProfileColor>,
+    pub tags: Vec<String>,
+    /// Environment variables set for `sslocal`, on top of those inherited from `ssgtk`.
+    env: BTreeMap<String, String>,
+    pub log_level: LogLevel,
+    /// With paths resolved.
+    pub hooks: Hooks,
+}
+
+impl ProfileMetadata {
+    /// The display name, prefixed with the color tag if set.
+    pub fn label(&self) -> String {
+        match self.color {
+            Some(color) => format!("{} {}", color.symbol(), self.display_name),
+            None => self.display_name.clone(),
+        }
+    }
+
+    /// The resolved binary this profile is launched with.
+    pub fn bin_path(&self) -> &Path {
+        &self.bin_path
+    }
+
+    /// The working directory of `sslocal` and hooks.
+    pub fn pwd(&self) -> &Path {
+        &self.pwd
+    }
+}
+
+/// A complete `sslocal` launch profile.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub metadata: ProfileMetadata,
+    config: ProfileConfig,
+}
+
+impl Profile {
+    /// Construct a `Profile` from its config, resolving all metadata.
+    ///
+    /// `default_display_name` and `default_bin_path` are used unless overridden by the config;
+    /// without either, `sslocal` is looked up in $PATH.
+    /// Relative paths in the config are resolved against `base_dir`, which is also the default working directory.
+    pub fn from_config(
+        config: ProfileConfig,
+        default_display_name: String,
+        base_dir: PathBuf,
+        default_bin_path: Option<&Path>,
+    ) -> Result<Self, ProfileLoadError> {
+        let mo = config.get_metadata_override().clone();
+        config.register_passwords();
+
+        let display_name = mo.display_name.unwrap_or(default_display_name);
+        let pwd = mo.pwd.map_or_else(|| base_dir.clone(), |p| base_dir.join(p));
+        let backend = mo.backend.unwrap_or_default();
+        // the default binary is an `sslocal`
+        let default_bin_path = default_bin_path.filter(|_| backend == Backend::ShadowsocksRust);
+        let bin_path = match (backend.lookup_name(), mo.bin_path, default_bin_path) {
+            // runs in-process
+            (None, _, _) => env::current_exe()?,
+            // a bare name is looked up in $PATH, like the default
+            (_, Some(p), _) if p.components().count() == 1 => which(p)?,
+            (_, Some(p), _) => which(base_dir.join(p))?,
+            (_, None, Some(p)) => which(p)?,
+            (Some(name), None, None) => which(name)?,
+        };
+        backend
+            .check_supported(&config)
+            .map_err(ProfileLoadError::InvalidConfig)?;
+        if let ProfileConfig::Tun { opts, .. } = &config {
+            if opts.if_name.is_none() && !opts.routes.is_empty() {
+                return Err(ProfileLoadError::InvalidConfig("`routes` requires `if_name`".into()));
+            }
+        }
+        if let ProfileConfig::Proxy { conn_opts, opts, .. } = &config {
+            if opts.http_port == Some(conn_opts.local_addr.1) {
+                return Err(ProfileLoadError::InvalidConfig(
+                    "`http_port` must differ from the port of `local_addr`".into(),
+                ));
+            }
+        }
+        // `sslocal` looks up the plugin from its working directory
+        if let ProfileConfig::Proxy {
+            opts: ProxyOptions {
+                plugin: Some(plugin), ..
+            },
+            ..
+        } = &config
+        {
+            which::which_in(plugin, env::var_os("PATH"), &pwd)
+                .map_err(|err| ProfileLoadError::BadPlugin(format!("\"{}\": {}", plugin, err)))?;
+        }
+
+        // report a bad `sslocal` config file when switched to, instead of failing the whole folder
+        let config_error = match &config {
+            ProfileConfig::ConfigFile { opts, adv_opts, .. } => opts
+                .validate(&base_dir, adv_opts.extra_args.as_deref().unwrap_or_default())
+                .err(),
+            _ => None,
+        };
+        if let Some(err) = &config_error {
+            warn!("Profile \"{}\" has a bad sslocal config file: {}", display_name, err);
+        }
+
+        // anything with a slash is a path, otherwise an icon name
+        let icon = mo.icon.map(|icon| match icon.contains('/') {
+            true => base_dir.join(icon).to_string_lossy().into_owned(),
+            false => icon,
+        });
+
+        let hooks = mo.hooks.resolved(&base_dir);
+
+        let metadata = ProfileMetadata {
+            display_name,
+            config_dir: None,
+            base_dir,
+            pwd,
+            bin_path,
+            system_proxy: mo.system_proxy,
+            requires_privilege: mo.requires_privilege.unwrap_or(false),
+            backend,
+            config_error,
+            pinned: false,
+            icon,
+            color: mo.color,
+            tags: mo.tags,
+            env: mo.env,
+            log_level: mo.log_level.unwrap_or_default(),
+            hooks,
+        };
+        Ok(Self { metadata, config })
+    }
+
+    /// Get the static configuration of this profile.
+    pub fn config(&self) -> &ProfileConfig {
+        &self.config
+    }
+
+    /// Copy this profile into a new profile directory under `parent`, named `display_name`.
+    ///
+    /// If this profile has a directory of its own, everything in it is copied along,
+    /// so that files referenced by relative paths (e.g. `config_path`) still resolve.
+    /// Fails if the new directory already exists.
+    pub fn duplicate_into(&self, parent: impl AsRef<Path>, display_name: &str) -> io::Result<PathBuf> {
+        let mut config = self.config.clone();
+        config.set_display_name(display_name);
+        let dir = parent.as_ref().join(config.suggested_dir_name());
+        if dir.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", dir),
+            ));
+        }
+        match self.own_dir() {
+            Some(src) => copy_dir_all(src, &dir)?,
+            None => fs::create_dir_all(&dir)?,
+        }
+        config.write_to_dir(&dir)?;
+        Ok(dir)
+    }
+
+    /// The profile directory this profile is loaded from, if it has one of its own.
+    ///
+    /// `None` for profiles from a manifest or subscription, which share the directory with others.
+    pub fn own_dir(&self) -> Option<&Path> {
+        (self.metadata.config_dir.as_deref()).filter(|dir| !ConfigFormat::find_all(dir).is_empty())
+    }
+
+    /// Get what is known about the `sslocal` binary of this profile, probing it if not done yet.
+    ///
+    /// Returns `None` unless this profile uses `Backend::ShadowsocksRust`.
+    pub fn sslocal_info(&self) -> Option<Arc<SslocalInfo>> {
+        (self.metadata.backend == Backend::ShadowsocksRust).then(|| sslocal_compat::info(&self.metadata.bin_path))
+    }
+
+    /// Whether the backend of this profile can report its traffic, see `crate::stats`.
+    pub fn supports_stats(&self) -> bool {
+        matches!(self.metadata.backend, Backend::ShadowsocksRust | Backend::Embedded)
+    }
+
+    /// Get what is needed to run this profile with `Backend::Embedded`, with its password looked up.
+    #[cfg(feature = "embedded")]
+    pub fn embedded_config(&self) -> io::Result<EmbeddedConfig> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let config = self.config.resolve_passwords()?;
+        let conn_opts = match &config {
+            ProfileConfig::Proxy { conn_opts, .. } => conn_opts,
+            _ => return Err(invalid("only proxy mode profiles can be run embedded".into())),
+        };
+        let method = conn_opts
+            .encrypt_method
+            .parse::<CipherKind>()
+            .map_err(|_| invalid(format!("unknown encrypt method \"{}\"", conn_opts.encrypt_method)))?;
+        let (host, port) = &conn_opts.server_addr;
+        let server_addr = match host.parse::<IpAddr>() {
+            Ok(ip) => ServerAddr::SocketAddr(SocketAddr::new(ip, *port)),
+            Err(_) => ServerAddr::DomainName(host.clone(), *port),
+        };
+        let password = conn_opts.password.as_plain().unwrap_or_default();
+        let server = ServerConfig::new(server_addr, password, method).map_err(|err| invalid(err.to_string()))?;
+        Ok(EmbeddedConfig {
+            local_addr: conn_opts.local_addr.into(),
+            server,
+        })
+    }
+
+    /// Check whether this profile can be launched,
+    /// returning the problem found when it was loaded if not.
+    pub fn preflight(&self) -> Result<(), &str> {
+        match &self.metadata.config_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the config of this profile as it should be exported into a manifest,
+    /// optionally with all passwords emptied.
+    fn to_exported_config(&self, strip_passwords: bool) -> ProfileConfig {
+        let mut config = self.config.clone();
+        // the manifest has no directory names to fall back on
+        let mo = config.get_metadata_override_mut();
+        mo.display_name = Some(self.metadata.display_name.clone());
+        // relative paths would be resolved against the manifest's directory instead
+        if mo.pwd.is_some() {
+            mo.pwd = Some(self.metadata.pwd.clone());
+        }
+        if mo.bin_path.as_ref().is_some_and(|p| p.components().count() > 1) {
+            mo.bin_path = Some(self.metadata.bin_path.clone());
+        }
+        if mo.icon.is_some() {
+            mo.icon = self.metadata.icon.clone();
+        }
+        mo.hooks = self.metadata.hooks.clone();
+        // the manifest is in a different directory
+        if let ProfileConfig::ConfigFile { opts, .. } = &mut config {
+            opts.config_path = self.metadata.base_dir.join(&opts.config_path);
+        }
+        if strip_passwords {
+            // keyring entry names are not secret
+            config.for_each_password(|password| {
+                if let Password::Plain(p) = password {
+                    p.clear()
+                }
+            });
+        }
+        config
+    }
+
+    /// Whether `sslocal` needs to be launched as root for this profile, i.e. if it is marked
+    /// as such, or in tun mode when it either has not been granted `CAP_NET_ADMIN`,
+    /// or has routes to set up.
+    pub fn needs_privilege(&self) -> bool {
+        if privileged::is_root() {
+            return false;
+        }
+        match &self.config {
+            _ if self.metadata.requires_privilege => true,
+            ProfileConfig::Tun { opts, .. } => {
+                !opts.routes.is_empty() || !privileged::has_cap_net_admin(&self.metadata.bin_path)
+            }
+            _ => false,
+        }
+    }
+
+    /// Run `sslocal` using the settings specified by this profile.
+    ///
+    /// `runtime_args` are appended after the arguments generated from the config.
+    /// They are `sslocal` arguments, so they are ignored for other backends.
+    /// `log_level` overrides that of this profile, e.g. to debug the running instance.
+    /// If `scope` is set, `sslocal` runs in a new systemd scope unit, unless launched as root
+    /// or `systemd-run` is unavailable.
+    ///
+    /// If `stdout` or `stderr` is `None`, the corresponding output
+    /// is redirected to`/dev/null` (discarded) by default.
+    ///
+    /// If launched as root via `pkexec` (see `Self::needs_privilege`),
+    /// `sslocal` cannot be signalled; instead, drop the returned `File` to stop it.
+    /// Nor does it get the environment variables of this profile, since `pkexec` resets them.
+    pub fn run_sslocal(
+        &self,
+        runtime_args: Vec<OsString>,
+        log_level: LogLevel,
+        scope: Option<&ScopeConfig>,
+        stdout: Option<impl IntoRawFd>,
+        stderr: Option<impl IntoRawFd>,
+    ) -> io::Result<(Handle, Option<File>)> {
+        let config = self.config.resolve_passwords()?;
+        let (mut program, mut args, as_root) = self.launch_command(config, runtime_args, log_level, true)?;
+        let name = &self.metadata.display_name;
+        match scope {
+            Some(_) if as_root => warn!("Profile \"{}\" is launched as root; not running it in a scope", name),
+            Some(scope) => match systemd_scope::wrap_command(scope, name, program.clone(), args.clone()) {
+                Ok(wrapped) => (program, args) = wrapped,
+                Err(err) => warn!("Cannot run profile \"{}\" in a scope: {}", name, err),
+            },
+            None => {}
+        }
+        // passwords are masked by the logger, see `crate::redact`
+        debug!(
+            "Launching {} {}",
+            program.display(),
+            args.iter().map(|arg| arg.to_string_lossy()).join(" ")
+        );
+        let (mut expr, stdin_guard) = if as_root {
+            info!(
+                "Profile \"{}\" needs root privileges; launching via ssgtk-helper",
+                self.metadata.display_name
+            );
+            if !self.metadata.env.is_empty() {
+                warn!(
+                    "Profile \"{}\" is launched as root; its environment variables are ignored",
+                    self.metadata.display_name
+                );
+            }
+            let (stdin_rx, stdin_tx) = privileged::stdin_pipe()?;
+            (cmd(program, args).stdin_file(stdin_rx), Some(stdin_tx))
+        } else {
+            let expr = cmd(program, args).dir(&self.metadata.pwd).stdin_null();
+            let expr = self.metadata.env.iter().fold(expr, |expr, (k, v)| expr.env(k, v));
+            (expr, None)
+        };
+        expr = match stdout {
+            Some(fd) => expr.stdout_file(fd),
+            None => expr.stdout_null(),
+        };
+        expr = match stderr {
+            Some(fd) => expr.stderr_file(fd),
+            None => expr.stderr_null(),
+        };
+        let handle = expr
+            .unchecked() // check for abnormal termination elsewhere
+            .start()?;
+        Ok((handle, stdin_guard))
+    }
+
+    /// Get what this profile would be launched with, without launching it,
+    /// so that users can check why `sslocal` misbehaves.
+    ///
+    /// Passwords are masked unless `reveal` is set, in which case those in the keyring are looked up.
+    /// No generated config file is written, and arguments added at launch time,
+    /// e.g. for traffic stats, are not included.
+    pub fn launch_preview(&self, reveal: bool) -> io::Result<LaunchPreview> {
+        let config = match reveal {
+            true => self.config.resolve_passwords()?,
+            false => self.config.mask_passwords(),
+        };
+        let (program, args, as_root) = self.launch_command(config, vec![], self.metadata.log_level, false)?;
+        // `go-shadowsocks2` takes the password percent-encoded in a URL
+        let encoded_mask = percent_encode(PASSWORD_MASK);
+        let args = args
+            .into_iter()
+            .map(|arg| match arg.to_str() {
+                Some(s) if s.contains(&encoded_mask) => s.replace(&encoded_mask, PASSWORD_MASK).into(),
+                _ => arg,
+            })
+            .collect();
+        Ok(LaunchPreview {
+            program,
+            args,
+            pwd: self.metadata.pwd.clone(),
+            env: if as_root { vec![] } else { self.launch_env() },
+            as_root,
+        })
+    }
+
+    /// The environment variables `sslocal` is launched with, unless launched as root:
+    /// those inherited from `ssgtk`, overridden by those of this profile.
+    fn launch_env(&self) -> Vec<(OsString, OsString)> {
+        let profile_env = self.metadata.env.iter().map(|(k, v)| (k.into(), v.into()));
+        let env: BTreeMap<OsString, OsString> = env::vars_os().chain(profile_env).collect();
+        env.into_iter().collect()
+    }
+
+    /// Get the program and arguments to launch this profile with `config` at `log_level`,
+    /// wrapped to run as root if needed (see `Self::needs_privilege`), and whether it is.
+    ///
+    /// Generated config files are only written if `write_files` is set.
+    fn launch_command(
+        &self,
+        mut config: ProfileConfig,
+        runtime_args: Vec<OsString>,
+        log_level: LogLevel,
+        write_files: bool,
+    ) -> io::Result<(PathBuf, Vec<OsString>, bool)> {
+        let ProfileMetadata {
+            base_dir,
+            pwd,
+            bin_path,
+            backend,
+            ..
+        } = &self.metadata;
+        // `sslocal` would resolve it relative to its working directory instead
+        if let ProfileConfig::ConfigFile { opts, .. } = &mut config {
+            opts.config_path = base_dir.join(&opts.config_path);
+        }
+        let mut args = match backend {
+            Backend::ShadowsocksRust => self.sslocal_launch_args(&config, runtime_args, write_files)?,
+            Backend::ShadowsocksLibev => LibevArgs(&config).to_launch_args(),
+            Backend::GoShadowsocks2 => GoShadowsocks2Args(&config).to_launch_args(),
+            Backend::Embedded => {
+                let msg = "embedded profiles are not launched as a process";
+                return Err(io::Error::new(io::ErrorKind::Unsupported, msg));
+            }
+        };
+        args.extend(log_level.launch_args(*backend));
+        if self.needs_privilege() {
+            let routes = match &self.config {
+                ProfileConfig::Tun { opts, .. } => opts.tun_routes(),
+                _ => None,
+            };
+            let (program, wrapped) = privileged::wrap_command(pwd, bin_path, args, routes)?;
+            Ok((program, wrapped, true))
+        } else {
+            Ok((bin_path.clone(), args, false))
+        }
+    }
+
+    /// Generate the `sslocal` arguments for `config`,
+    /// writing a generated config file if necessary and `write_files` is set.
+    fn sslocal_launch_args(
+        &self,
+        config: &ProfileConfig,
+        runtime_args: Vec<OsString>,
+        write_files: bool,
+    ) -> io::Result<Vec<OsString>> {
+        let ProfileMetadata {
+            display_name, bin_path, ..
+        } = &self.metadata;
+        let runtime_file = |dir_name: &str, extension: &str| {
+            let path = Path::new(dir_name).join(format!("{}.{}", to_file_name(display_name), extension));
+            if write_files {
+                XDG_DIRS.place_runtime_file(path)
+            } else {
+                XDG_DIRS.get_runtime_file(path)
+            }
+        };
+        let mut args = match config {
+            ProfileConfig::Balanced { opts, .. } => {
+                let path = runtime_file(BALANCED_CONFIGS_DIR_NAME, "json")?;
+                if write_files {
+                    opts.write_sslocal_config(&path)?;
+                }
+                vec!["--config".into(), path.into()]
+            }
+            ProfileConfig::Proxy { conn_opts, opts, .. } => match opts.to_http_local_config(conn_opts.local_addr.0) {
+                Some(http_config) => {
+                    let path = runtime_file(HTTP_LOCAL_CONFIGS_DIR_NAME, "json")?;
+                    if write_files {
+                        write_private_file(&path, &serde_json::to_string_pretty(&http_config)?)?;
+                    }
+                    vec!["--config".into(), path.into()]
+                }
+                None => vec![],
+            },
+            _ => vec![],
+        };
+        args.append(&mut config.to_launch_args());
+        if let Some(acl) = &config.get_advanced_options().acl {
+            let path = runtime_file(ACL_FILES_DIR_NAME, "acl")?;
+            if write_files {
+                fs::write(&path, acl.with_gfwlist().to_acl())?;
+            }
+            args.extend_from_slice(&["--acl".into(), path.into()]);
+        }
+        args.extend(runtime_args);
+        Ok(sslocal_compat::info(bin_path).adapt_args(args))
+    }
+}
+
+/// What a profile would be launched with, as returned by `Profile::launch_preview`.
+#[derive(Debug, Clone)]
+pub struct LaunchPreview {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+    /// The working directory of `sslocal`.
+    pub pwd: PathBuf,
+    /// The environment variables inherited from `ssgtk` and set by the profile,
+    /// or none if launched as root, since `pkexec` resets them.
+    pub env: Vec<(OsString, OsString)>,
+    /// Whether it is launched as root via `pkexec` and `ssgtk-helper`.
+    pub as_root: bool,
+}
+impl LaunchPreview {
+    /// Format the program and arguments as a shell command, e.g. to paste into a terminal.
+    pub fn to_shell_command(&self) -> String {
+        let program = shell_quote(self.program.as_os_str());
+        let args = self.args.iter().map(|arg| shell_quote(arg));
+        format!(
+            "cd {} && {}",
+            shell_quote(self.pwd.as_os_str()),
+            (Some(program).into_iter().chain(args)).join(" ")
+        )
+    }
+}
+
+/// A group containing multiple profiles and/or subgroups.
+#[derive(Debug, Clone)]
+pub struct ProfileGroup {
+    pub display_name: String,
+    pub content: Vec<ProfileFolder>,
+    /// Whether to automatically switch to the next profile in this group
+    /// when the active one fails.
+    pub failover: bool,
+    /// The directory of this group, if it is loaded lazily and its content has not been loaded yet.
+    pub unloaded: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ProfileLoadError {
+    /// Each profile should be its own directory, which can be placed under other directories to form groups.
+    NotDirectory(String),
+    /// The profile's config file cannot be parsed.
+    ConfigParseError(Box<dyn std::error::Error + Send + Sync>),
+    /// The profile's config file is in the legacy format, and cannot be migrated.
+    LegacyConfig(String),
+    /// Cannot resolve a binary for this profile.
+    BadBinary(which::Error),
+    /// Cannot resolve the SIP003 plugin binary for this profile.
+    BadPlugin(String),
+    /// At least two profiles share the same name.
+    NameConflict(String),
+    /// The config can be parsed, but some of its values contradict each other.
+    InvalidConfig(String),
+    /// The directory contains files (which means it's considered a profile folder),
+    /// but there's no config file.
+    NoConfigFile(String),
+    /// The directory contains neither files nor other valid profiles.
+    EmptyGroup(String),
+    /// The directory is nested too deeply below the profiles directory.
+    TooDeep(String),
+    /// The filesystem encountered an IOError.
+    IOError(io::Error),
+}
+
+impl fmt::Display for ProfileLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ProfileLoadError::*;
+
+        let prefix = "ProfileLoadError";
+        match self {
+            NotDirectory(s) => write!(f, "{}-NotDirectory: {}", prefix, s),
+            ConfigParseError(e) => write!(f, "{}-ConfigParseError: {}", prefix, e),
+            LegacyConfig(s) => write!(f, "{}-LegacyConfig: {}", prefix, s),
+            BadBinary(e) => write!(f, "{}-BadBinary: {}", prefix, e),
+            BadPlugin(s) => write!(f, "{}-BadPlugin: {}", prefix, s),
+            NameConflict(s) => write!(f, "{}-NameConflict: {}", prefix, s),
+            InvalidConfig(s) => write!(f, "{}-InvalidConfig: {}", prefix, s),
+            NoConfigFile(s) => write!(f, "{}-NoConfigFile: {}", prefix, s),
+            EmptyGroup(s) => write!(f, "{}-EmptyGroup: {}", prefix, s),
+            TooDeep(s) => write!(f, "{}-TooDeep: {}", prefix, s),
+            IOError(e) => write!(f, "{}-IOError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for ProfileLoadError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::ConfigParseError(err.into())
+    }
+}
+impl From<toml::de::Error> for ProfileLoadError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::ConfigParseError(err.into())
+    }
+}
+impl From<json5::Error> for ProfileLoadError {
+    fn from(err: json5::Error) -> Self {
+        Self::ConfigParseError(err.into())
+    }
+}
+impl From<which::Error> for ProfileLoadError {
+    fn from(err: which::Error) -> Self {
+        Self::BadBinary(err)
+    }
+}
+impl From<io::Error> for ProfileLoadError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+#[allow(clippy::large_enum_variant)] // groups are few, and never moved around in bulk
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub enum ProfileFolder {
+    #[derivative(Debug = "transparent")]
+    Profile(Profile),
+    #[derivative(Debug = "transparent")]
+    Group(ProfileGroup),
+}
+
+/// The order of a group's entries, as defined in its order file.
+///
+/// Entries are referred to by their directory names. Those not listed
+/// come last, sorted by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct GroupOrder {
+    /// Entries shown first, in this order.
+    ///
+    /// Pinned profiles also stay on top when sorted by latency.
+    pinned: Vec<String>,
+    /// Entries shown after the pinned ones, in this order.
+    order: Vec<String>,
+}
+impl GroupOrder {
+    /// Sort entries of a group, and mark the pinned profiles.
+    fn apply(&self, entries: &mut [(String, ProfileFolder)]) {
+        for name in self.pinned.iter().chain(self.order.iter()) {
+            if !entries.iter().any(|(n, _)| n == name) {
+                warn!("Cannot order \"{}\": no such profile or group", name);
+            }
+        }
+        let rank = |name: &str| match self.pinned.iter().position(|n| n == name) {
+            Some(i) => (0, i),
+            None => match self.order.iter().position(|n| n == name) {
+                Some(i) => (1, i),
+                None => (2, 0),
+            },
+        };
+        entries.sort_by(|(a, _), (b, _)| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+        for (name, entry) in entries.iter_mut() {
+            if let ProfileFolder::Profile(p) = entry {
+                p.metadata.pinned = self.pinned.contains(name);
+            }
+        }
+    }
+}
+
+/// An entry of the profiles manifest, which is either a group or a profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ManifestEntry {
+    Group {
+        group: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        failover: bool,
+        content: Vec<ManifestEntry>,
+    },
+    Profile(Box<ProfileConfig>),
+}
+impl ManifestEntry {
+    /// Resolve this entry into a `ProfileFolder`, with `pwd` as the default working directory,
+    /// which is also where the manifest is.
+    ///
+    /// `group` is the name of the group containing this entry, if any, used to disambiguate profile names.
+    /// Profiles that cannot be loaded are skipped and added to `problems`; returns `None` if this is one.
+    fn into_folder(
+        self,
+        pwd: &Path,
+        group: Option<&str>,
+        opts: &LoadOptions,
+        seen_names: &mut HashSet<String>,
+        problems: &mut Vec<LoadProblem>,
+    ) -> Option<ProfileFolder> {
+        match self {
+            Self::Group {
+                group,
+                failover,
+                content,
+            } => {
+                let content = content
+                    .into_iter()
+                    .filter_map(|entry| entry.into_folder(pwd, Some(&group), opts, seen_names, problems))
+                    .collect();
+                Some(ProfileFolder::Group(ProfileGroup {
+                    display_name: group,
+                    content,
+                    failover,
+                    unloaded: None,
+                }))
+            }
+            Self::Profile(config) => {
+                let name = config.get_metadata_override().display_name.clone();
+                let res = || {
+                    // there is no directory name to fall back on
+                    let display_name = name.clone().ok_or_else(|| {
+                        ProfileLoadError::InvalidConfig("profiles in the manifest require `display_name`".into())
+                    })?;
+                    let display_name = claim_name(seen_names, display_name, group, opts.name_conflicts)?;
+                    Profile::from_config(*config, display_name, pwd.into(), opts.default_bin_path.as_deref())
+                };
+                match res() {
+                    Ok(profile) => Some(ProfileFolder::Profile(profile)),
+                    Err(error) => {
+                        let problem = LoadProblem {
+                            path: pwd.join(PROFILE_MANIFEST_FILE_NAME),
+                            name,
+                            error,
+                        };
+                        warn!("Skipped a profile that cannot be loaded: {}", problem);
+                        problems.push(problem);
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What to do when a profile's display name is already taken by another profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameConflictPolicy {
+    /// Fail to load the later profile with `ProfileLoadError::NameConflict`.
+    Fail,
+    /// Append the name of the profile's group, e.g. "Tokyo (Provider-A)",
+    /// or a number if that is taken too, or if the profile is not in a group.
+    #[default]
+    AppendGroup,
+    /// Append a number, e.g. "Tokyo (2)".
+    AppendNumber,
+}
+
+/// Options that apply to all profiles being loaded.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Whether to load symlinked subdirectories.
+    pub follow_symlinks: bool,
+    /// The `sslocal` binary used by profiles that do not set `bin_path`.
+    pub default_bin_path: Option<PathBuf>,
+    pub name_conflicts: NameConflictPolicy,
+    /// How many levels of groups to load below the base path;
+    /// `PROFILE_LOAD_DEPTH_DEFAULT` if unset.
+    pub max_depth: Option<usize>,
+    /// Updated as profiles are found and parsed, if set.
+    pub progress: Option<Arc<LoadProgress>>,
+    /// Whether to leave the content of groups in the base path unloaded, until `load_group` is called.
+    pub lazy: bool,
+}
+
+/// How far a profile loading has got, which can be read from another thread.
+#[derive(Debug, Default)]
+pub struct LoadProgress {
+    /// How many profiles have been found so far.
+    pub total: AtomicUsize,
+    /// How many of them have been parsed, successfully or not.
+    pub done: AtomicUsize,
+}
+
+impl LoadProgress {
+    /// The fraction of profiles found that have been parsed; `None` if none have been found yet.
+    pub fn fraction(&self) -> Option<f64> {
+        let total = self.total.load(Ordering::Relaxed);
+        (total > 0).then(|| self.done.load(Ordering::Relaxed) as f64 / total as f64)
+    }
+}
+
+/// A profile or group that cannot be loaded, and is skipped so that the others can be.
+#[derive(Debug)]
+pub struct LoadProblem {
+    /// The directory of the profile or group, or the manifest it is defined in.
+    pub path: PathBuf,
+    /// The name of the profile, if defined in a manifest.
+    pub name: Option<String>,
+    pub error: ProfileLoadError,
+}
+
+impl fmt::Display for LoadProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "\"{}\" in {:?}: {}", name, self.path, self.error),
+            None => write!(f, "{:?}: {}", self.path, self.error),
+        }
+    }
+}
+
+/// State shared across a single recursive profile loading.
+#[derive(Debug)]
+struct LoadContext<'a> {
+    opts: &'a LoadOptions,
+    problems: &'a mut Vec<LoadProblem>,
+    /// The canonicalized directory being loaded, whose name is not shown as a group.
+    root: PathBuf,
+    seen_names: HashSet<String>,
+    /// The device and inode numbers of the directories loaded so far,
+    /// which also tell apart the same directory reached via a bind mount.
+    visited_dirs: HashSet<(u64, u64)>,
+    /// How many levels below the root the directory being loaded is.
+    depth: usize,
+    /// The patterns in the ignore files of the directories being loaded, from the outermost inwards.
+    ignore_rules: Vec<IgnoreRules>,
+}
+
+/// A profile or group found while loading, before any profile configs are parsed.
+#[derive(Debug)]
+enum DirNode {
+    /// A profile, whose parsed config is at `index` of the profiles found.
+    Profile { path: PathBuf, index: usize },
+    /// A group, with its subdirectories as found in the directory listing,
+    /// and those that cannot be loaded, which are reported when the group is assembled.
+    Group {
+        path: PathBuf,
+        order: GroupOrder,
+        subdirs: Vec<(PathBuf, Result<DirNode, ProfileLoadError>)>,
+    },
+    /// A group whose content is left unloaded.
+    Unloaded { path: PathBuf },
+}
+
+/// Take `name` for a profile in `group` (if any), unless it is in `seen_names` already,
+/// in which case it is disambiguated according to `policy`.
+///
+/// Returns the name taken, which is added to `seen_names`.
+fn claim_name(
+    seen_names: &mut HashSet<String>,
+    name: String,
+    group: Option<&str>,
+    policy: NameConflictPolicy,
+) -> Result<String, ProfileLoadError> {
+    if seen_names.insert(name.clone()) {
+        return Ok(name);
+    }
+    let with_group = group.map(|g| format!("{} ({})", name, g));
+    let unique = match (policy, with_group) {
+        (NameConflictPolicy::Fail, _) => return Err(ProfileLoadError::NameConflict(name)),
+        (NameConflictPolicy::AppendGroup, Some(with_group)) if !seen_names.contains(&with_group) => with_group,
+        _ => (2..)
+            .map(|n| format!("{} ({})", name, n))
+            .find(|n| !seen_names.contains(n))
+            .unwrap(), // there are only so many names
+    };
+    warn!("Profile name \"{}\" is already taken; renamed to \"{}\"", name, unique);
+    seen_names.insert(unique.clone());
+    Ok(unique)
+}
+
+impl ProfileFolder {
+    /// Recursively loads all nested profiles within the specified directory.
+    ///
+    /// Symlinked subdirectories are skipped unless `opts.follow_symlinks` is set.
+    /// Each directory is loaded at most once to break symlink and bind mount loops,
+    /// and directories nested deeper than `opts.max_depth` are not loaded.
+    ///
+    /// If `opts.lazy` is set, the groups directly within are left unloaded.
+    ///
+    /// Profiles and groups within that cannot be loaded are skipped and added to `problems`.
+    /// Only problems with the base path itself are returned as errors.
+    ///
+    /// If a call to this function with the user-specified base path fails,
+    /// then run the program as if there are no existing configs.
+    pub fn from_path_recurse(
+        path: impl AsRef<Path>,
+        opts: &LoadOptions,
+        problems: &mut Vec<LoadProblem>,
+    ) -> Result<Self, ProfileLoadError> {
+        let mut ctx = LoadContext {
+            opts,
+            problems,
+            root: path.as_ref().canonicalize()?,
+            seen_names: HashSet::new(),
+            visited_dirs: HashSet::new(),
+            depth: 0,
+            ignore_rules: vec![],
+        };
+        Self::load_tree(path.as_ref(), &mut ctx)?
+            .ok_or(ProfileLoadError::EmptyGroup(path.as_ref().to_string_lossy().into()))
+    }
+
+    /// Load the content of the unloaded group in `dir`, which has been loaded lazily from `root`
+    /// by `from_path_recurse`. Profile names are kept unique across this whole `ProfileFolder`.
+    ///
+    /// Profiles and groups within that cannot be loaded are skipped and added to `problems`.
+    /// Returns `Ok(false)` if there is no such unloaded group, e.g. if it has been loaded already.
+    pub fn load_group(
+        &mut self,
+        dir: impl AsRef<Path>,
+        root: impl AsRef<Path>,
+        opts: &LoadOptions,
+        problems: &mut Vec<LoadProblem>,
+    ) -> Result<bool, ProfileLoadError> {
+        let dir = dir.as_ref();
+        let seen_names = (self.get_profiles().into_iter())
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        let group = match self.find_unloaded_mut(dir) {
+            Some(g) => g,
+            None => return Ok(false),
+        };
+        let root = root.as_ref().canonicalize()?;
+        // a symlink loop back to the root is not loaded again
+        let root_metadata = root.metadata()?;
+        let ignore_rules = match IgnoreFile::read(&root)? {
+            Some(IgnoreFile::Patterns(rules)) => vec![rules],
+            _ => vec![],
+        };
+        let opts = LoadOptions {
+            lazy: false,
+            ..opts.clone()
+        };
+        let mut ctx = LoadContext {
+            opts: &opts,
+            problems,
+            root,
+            seen_names,
+            visited_dirs: HashSet::from([(root_metadata.dev(), root_metadata.ino())]),
+            depth: 1,
+            ignore_rules,
+        };
+        debug!("Loading the content of group {:?}", dir);
+        group.unloaded = None;
+        group.content = match Self::load_tree(dir, &mut ctx)? {
+            Some(ProfileFolder::Group(g)) => g.content,
+            // the directory has become a profile, which is picked up on the next reload
+            Some(ProfileFolder::Profile(_)) => vec![],
+            None => vec![],
+        };
+        Ok(true)
+    }
+
+    /// Recursively find the unloaded group in `dir`.
+    fn find_unloaded_mut(&mut self, dir: &Path) -> Option<&mut ProfileGroup> {
+        match self {
+            ProfileFolder::Profile(_) => None,
+            ProfileFolder::Group(g) => match g.unloaded.as_deref() == Some(dir) {
+                true => Some(g),
+                false => g.content.iter_mut().find_map(|pf| pf.find_unloaded_mut(dir)),
+            },
+        }
+    }
+
+    /// Load the profiles and groups in a directory.
+    ///
+    /// Returns Ok(None) when this directory is ignored, or is a group with nothing loaded.
+    fn load_tree(path: &Path, ctx: &mut LoadContext) -> Result<Option<Self>, ProfileLoadError> {
+        // find all profiles first, so that their configs can be parsed in parallel
+        let mut profile_dirs = vec![];
+        let tree = Self::walk(path, ctx, &mut profile_dirs)?;
+        let opts = ctx.opts;
+        let mut parsed: Vec<_> = (profile_dirs.par_iter())
+            .map(|(dir, format)| {
+                let res = Self::parse_profile(dir, *format, opts);
+                if let Some(progress) = &opts.progress {
+                    progress.done.fetch_add(1, Ordering::Relaxed);
+                }
+                Some(res)
+            })
+            .collect();
+        match tree {
+            Some(tree) => Self::assemble(tree, ctx, &mut parsed),
+            None => Ok(None), // ignored
+        }
+    }
+
+    /// Find the profiles and groups in a directory, without parsing any profile configs,
+    /// which are added to `profile_dirs` instead.
+    ///
+    /// Returns Ok(None) when this directory is ignored.
+    fn walk(
+        path: impl AsRef<Path>,
+        ctx: &mut LoadContext,
+        profile_dirs: &mut Vec<(PathBuf, ConfigFormat)>,
+    ) -> Result<Option<DirNode>, ProfileLoadError> {
+        let path = path.as_ref().canonicalize()?;
+        let full_path_str = path.to_string_lossy();
+
+        // make sure path is a directory
+        if !path.is_dir() {
+            return Err(ProfileLoadError::NotDirectory(full_path_str.into()));
+        }
+        // a pathologically deep tree could otherwise overflow the stack
+        let max_depth = ctx.opts.max_depth.unwrap_or(PROFILE_LOAD_DEPTH_DEFAULT);
+        if ctx.depth > max_depth {
+            let msg = format!("{:?} is nested more than {} levels deep", path, max_depth);
+            return Err(ProfileLoadError::TooDeep(msg));
+        }
+        // a directory reachable via symlinks or bind mounts may be visited more than once
+        let metadata = path.metadata()?;
+        if !ctx.visited_dirs.insert((metadata.dev(), metadata.ino())) {
+            warn!(
+                "Directory {:?} has already been loaded; skipped to avoid a symlink or bind mount loop",
+                path
+            );
+            return Ok(None);
+        }
+        // make sure directory isn't ignored by its ignore file; if it only ignores some of its children,
+        // the patterns apply until the caller is done with this directory
+        match IgnoreFile::read(&path)? {
+            Some(IgnoreFile::All) => return Ok(None),
+            Some(IgnoreFile::Patterns(rules)) => ctx.ignore_rules.push(rules),
+            None => {}
+        }
+
+        // if directory contains a config file, then consider it a profile
+        let format = match ConfigFormat::find_all(&path)[..] {
+            [] => None,
+            [format] => Some(format),
+            _ => {
+                let msg = format!("{:?} contains more than one profile config file", path);
+                return Err(ProfileLoadError::InvalidConfig(msg));
+            }
+        };
+        if let Some(format) = format {
+            // parsed later
+            profile_dirs.push((path.clone(), format));
+            if let Some(progress) = &ctx.opts.progress {
+                progress.total.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(Some(DirNode::Profile {
+                path,
+                index: profile_dirs.len() - 1,
+            }));
+        }
+
+        // otherwise, check if it contains files at all
+        // if so consider it a profile that's missing the config file.
+        let has_files = path.read_dir()?.any(|ent_res| match ent_res {
+            // the subscription file, the manifest, the failover marker, the order file,
+            // the ignore file and the files it ignores are not part of any profile
+            Ok(ent) => {
+                ent.path().is_file()
+                    && ent.file_name() != SUBSCRIPTION_FILE_NAME
+                    && ent.file_name() != PROFILE_MANIFEST_FILE_NAME
+                    && ent.file_name() != PROFILE_FAILOVER_FILE_NAME
+                    && ent.file_name() != PROFILE_ORDER_FILE_NAME
+                    && ent.file_name() != PROFILE_IGNORE_FILE_NAME
+                    && !ignore_file::is_ignored_by(&ctx.ignore_rules, &ent.path(), false)
+            }
+            Err(err) => {
+                warn!("Cannot open a file or directory: {}", err);
+                false
+            }
+        });
+        if has_files {
+            return Err(ProfileLoadError::NoConfigFile(full_path_str.into()));
+        }
+
+        // otherwise, consider it a group, whose content may be loaded later
+        if ctx.opts.lazy && ctx.depth > 0 {
+            return Ok(Some(DirNode::Unloaded { path }));
+        }
+        let order_path = path.join(PROFILE_ORDER_FILE_NAME);
+        let order: GroupOrder = if order_path.is_file() {
+            serde_yaml::from_str(&read_to_string(order_path)?)?
+        } else {
+            GroupOrder::default()
+        };
+        // sorted, so that which of two profiles with the same name gets renamed does not vary
+        let mut subdir_paths = (path.read_dir()?)
+            .map(|ent_res| ent_res.map(|ent| ent.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        subdir_paths.sort_unstable();
+        let mut subdirs = vec![];
+        for subdir_path in subdir_paths {
+            // recursively load all subdirectories
+            if !subdir_path.is_dir() {
+                continue; // the subscription file, the manifest, the failover marker or the order file
+            }
+            if subdir_path.is_symlink() && !ctx.opts.follow_symlinks {
+                info!("Skipped a symlinked directory: {:?}", subdir_path);
+                continue;
+            }
+            if ignore_file::is_ignored_by(&ctx.ignore_rules, &subdir_path, true) {
+                info!("Ignored a directory and its children: {:?}", subdir_path);
+                continue;
+            }
+            let ignore_rules_len = ctx.ignore_rules.len();
+            ctx.depth += 1;
+            let res = Self::walk(&subdir_path, ctx, profile_dirs);
+            ctx.depth -= 1;
+            // forget the ignore file of the subdirectory, if any
+            ctx.ignore_rules.truncate(ignore_rules_len);
+            match res.transpose() {
+                Some(node) => subdirs.push((subdir_path, node)),
+                None => info!("Ignored a directory and its children: {:?}", subdir_path),
+            };
+        }
+        Ok(Some(DirNode::Group { path, order, subdirs }))
+    }
+
+    /// Parse the config of the profile in `dir`, which is thread-safe.
+    fn parse_profile(dir: &Path, format: ConfigFormat, opts: &LoadOptions) -> Result<Profile, ProfileLoadError> {
+        // use directory name as profile's default display name
+        let default_display_name = dir
+            .file_name()
+            .unwrap() // path has already been canonicalized
+            .to_str()
+            .unwrap() // UTF-8 has already been verified
+            .to_string();
+        let content = format.read_from(dir)?;
+        let config = format.parse(&content)?;
+        let mut profile = Profile::from_config(
+            config,
+            default_display_name,
+            dir.into(),
+            opts.default_bin_path.as_deref(),
+        )?;
+        profile.metadata.config_dir = Some(dir.into());
+        Ok(profile)
+    }
+
+    /// Build the profiles and groups found by `walk`, taking their parsed configs out of `parsed`.
+    ///
+    /// Returns Ok(None) when this directory is a group with nothing loaded.
+    fn assemble(
+        node: DirNode,
+        ctx: &mut LoadContext,
+        parsed: &mut [Option<Result<Profile, ProfileLoadError>>],
+    ) -> Result<Option<Self>, ProfileLoadError> {
+        let (path, order, subdirs) = match node {
+            DirNode::Profile { path, index } => {
+                let mut profile = parsed[index].take().unwrap()?; // each profile is assembled once
+
+                // profiles directly under the root are not in a group, since the tray menu unrolls it
+                let group = (path.parent())
+                    .filter(|parent| *parent != ctx.root)
+                    .and_then(|parent| parent.file_name())
+                    .map(|name| name.to_string_lossy());
+                profile.metadata.display_name = claim_name(
+                    &mut ctx.seen_names,
+                    profile.metadata.display_name,
+                    group.as_deref(),
+                    ctx.opts.name_conflicts,
+                )?;
+                return Ok(Some(Self::Profile(profile)));
+            }
+            DirNode::Group { path, order, subdirs } => (path, order, subdirs),
+            DirNode::Unloaded { path } => {
+                return Ok(Some(Self::Group(ProfileGroup {
+                    display_name: path.file_name().unwrap_or_default().to_string_lossy().into(),
+                    content: vec![],
+                    failover: path.join(PROFILE_FAILOVER_FILE_NAME).is_file(),
+                    unloaded: Some(path),
+                })));
+            }
+        };
+
+        let mut entries = vec![];
+        for (subdir_path, node) in subdirs {
+            match node.and_then(|node| Self::assemble(node, ctx, parsed)) {
+                Ok(Some(cf)) => {
+                    let dir_name = subdir_path.file_name().unwrap_or_default().to_string_lossy();
+                    entries.push((dir_name.into_owned(), cf));
+                }
+                Ok(None) => info!("Ignored a directory and its children: {:?}", subdir_path),
+                Err(error) => {
+                    let problem = LoadProblem {
+                        path: subdir_path,
+                        name: None,
+                        error,
+                    };
+                    warn!("Skipped a profile or group that cannot be loaded: {}", problem);
+                    ctx.problems.push(problem);
+                }
+            }
+        }
+        order.apply(&mut entries);
+        if entries.is_empty() && path != ctx.root {
+            // any profiles within that cannot be loaded have been reported already
+            Ok(None)
+        } else if entries.is_empty() {
+            error!(
+                "The specified profile directory is empty; \
+                please read Q&A for a guide on creating a configuration"
+            );
+            error!("See https://github.com/spyophobia/shadowsocks-gtk-rs/blob/master/res/QnA.md");
+            Err(ProfileLoadError::EmptyGroup(path.to_string_lossy().into()))
+        } else {
+            Ok(Some(ProfileFolder::Group(ProfileGroup {
+                // use directory name as folder's display name
+                display_name: path.file_name().unwrap_or_default().to_string_lossy().into(),
+                content: entries.into_iter().map(|(_, cf)| cf).collect(),
+                failover: path.join(PROFILE_FAILOVER_FILE_NAME).is_file(),
+                unloaded: None,
+            })))
+        }
+    }
+
+    /// Append all groups and profiles defined in the manifest in `dir`, if present.
+    ///
+    /// Profiles in the manifest that share names with existing profiles, or with each other,
+    /// are handled according to `opts.name_conflicts`.
+    /// Profiles that cannot be loaded are skipped and added to `problems`.
+    pub fn merge_manifest(
+        &mut self,
+        dir: impl AsRef<Path>,
+        opts: &LoadOptions,
+        problems: &mut Vec<LoadProblem>,
+    ) -> Result<(), ProfileLoadError> {
+        let manifest_path = dir.as_ref().join(PROFILE_MANIFEST_FILE_NAME);
+        if !manifest_path.is_file() {
+            return Ok(());
+        }
+        let entries: Vec<ManifestEntry> = serde_yaml::from_str(&read_to_string(manifest_path)?)?;
+        let mut seen_names = self
+            .get_profiles()
+            .into_iter()
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        for entry in entries {
+            if let Some(child) = entry.into_folder(dir.as_ref(), None, opts, &mut seen_names, problems) {
+                self.push(child);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write all groups and profiles within this `ProfileFolder` into a manifest at `path`,
+    /// which can be loaded as a `PROFILE_MANIFEST_FILE_NAME`.
+    ///
+    /// The topmost layer of nesting is removed. If `strip_passwords` is set, all passwords are emptied.
+    pub fn export_manifest(&self, path: impl AsRef<Path>, strip_passwords: bool) -> io::Result<()> {
+        let entries: Vec<_> = match self {
+            ProfileFolder::Group(g) => g
+                .content
+                .iter()
+                .map(|pf| pf.to_manifest_entry(strip_passwords))
+                .collect(),
+            profile => vec![profile.to_manifest_entry(strip_passwords)],
+        };
+        let content = serde_yaml::to_string(&entries).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_private_file(path.as_ref(), &content)
+    }
+    fn to_manifest_entry(&self, strip_passwords: bool) -> ManifestEntry {
+        match self {
+            ProfileFolder::Profile(p) => ManifestEntry::Profile(Box::new(p.to_exported_config(strip_passwords))),
+            ProfileFolder::Group(g) => ManifestEntry::Group {
+                group: g.display_name.clone(),
+                failover: g.failover,
+                content: g
+                    .content
+                    .iter()
+                    .map(|pf| pf.to_manifest_entry(strip_passwords))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Append a child under this `ProfileFolder`.
+    ///
+    /// If this is a single profile, it is first converted into a group containing itself.
+    pub fn push(&mut self, child: ProfileFolder) {
+        if let ProfileFolder::Profile(p) = self {
+            let group = ProfileGroup {
+                display_name: p.metadata.display_name.clone(),
+                content: vec![ProfileFolder::Profile(p.clone())],
+                failover: false,
+                unloaded: None,
+            };
+            *self = ProfileFolder::Group(group);
+        }
+        if let ProfileFolder::Group(g) = self {
+            g.content.push(child);
+        }
+    }
+
+    /// Recursively count the number of nested profiles within this `ConfigFolder`.
+    pub fn profile_count(&self) -> usize {
+        use ProfileFolder::*;
+        match self {
+            Profile(_) => 1,
+            Group(g) => g.content.iter().map(|pf| pf.profile_count()).sum(),
+        }
+    }
+
+    /// Recursively get all the nested profiles within this `ProfileFolder`,
+    /// flattened and returned by reference.
+    #[allow(dead_code)]
+    pub fn get_profiles(&self) -> Vec<&Profile> {
+        use ProfileFolder::*;
+        match self {
+            Profile(p) => vec![p],
+            Group(g) => g.content.iter().flat_map(|pf| pf.get_profiles()).collect(),
+        }
+    }
+
+    /// Recursively collect the tags of all nested profiles, sorted and deduplicated.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<_> = self
+            .get_profiles()
+            .into_iter()
+            .flat_map(|p| p.metadata.tags.iter().cloned())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    /// Get a copy of this `ProfileFolder` with only the profiles tagged with `tag`,
+    /// and the one named `keep` (e.g. the active profile), if any.
+    ///
+    /// Groups left empty are dropped, unless they are unloaded; returns `None` if nothing is left.
+    pub fn filter_by_tag(&self, tag: &str, keep: &str) -> Option<Self> {
+        match self {
+            ProfileFolder::Profile(p) => {
+                let is_shown = p.metadata.tags.iter().any(|t| t == tag) || p.metadata.display_name == keep;
+                is_shown.then(|| self.clone())
+            }
+            ProfileFolder::Group(g) => {
+                let content: Vec<_> = g.content.iter().filter_map(|pf| pf.filter_by_tag(tag, keep)).collect();
+                (!content.is_empty() || g.unloaded.is_some()).then(|| {
+                    ProfileFolder::Group(ProfileGroup {
+                        display_name: g.display_name.clone(),
+                        content,
+                        failover: g.failover,
+                        unloaded: g.unloaded.clone(),
+                    })
+                })
+            }
+        }
+    }
+
+    /// Recursively searches all the nested profiles within this `ProfileFolder`
+    /// for a `Profile` with a matching name.
+    pub fn lookup(&self, name: impl AsRef<str>) -> Option<&Profile> {
+        use ProfileFolder::*;
+        match self {
+            Profile(p) if p.metadata.display_name == name.as_ref() => Some(p),
+            Profile(_) => None,
+            Group(g) => g.content.iter().find_map(|pf| pf.lookup(name.as_ref())),
+        }
+    }
+
+    /// Like `lookup`, but falls back to treating `selector` as
+    /// the 1-based index of a profile, in the order of `get_profiles`.
+    pub fn lookup_selector(&self, selector: impl AsRef<str>) -> Option<&Profile> {
+        let selector = selector.as_ref();
+        self.lookup(selector).or_else(|| {
+            let index = selector.parse::<usize>().ok()?.checked_sub(1)?;
+            self.get_profiles().get(index).copied()
+        })
+    }
+
+    /// If the profile with a matching name belongs to a failover group,
+    /// returns the other profiles of the innermost such group,
+    /// in order, starting from the one after it and wrapping around.
+    pub fn failover_candidates(&self, name: impl AsRef<str>) -> Option<Vec<&Profile>> {
+        let group = match self {
+            ProfileFolder::Profile(_) => return None,
+            ProfileFolder::Group(g) => g,
+        };
+        let inner = group
+            .content
+            .iter()
+            .find_map(|pf| pf.failover_candidates(name.as_ref()));
+        if inner.is_some() || !group.failover {
+            return inner;
+        }
+        let profiles = self.get_profiles();
+        let idx = profiles.iter().position(|p| p.metadata.display_name == name.as_ref())?;
+        Some(profiles[idx + 1..].iter().chain(&profiles[..idx]).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        ffi::OsString,
+        fs, io,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    use crate::hooks::HookKind;
+
+    use super::{
+        archive_profile_dir, Backend, ConfigFileOptions, ConfigFormat, GoShadowsocks2Args, GroupOrder, LibevArgs,
+        LoadOptions, LogLevel, NameConflictPolicy, Password, Profile, ProfileConfig, ProfileFolder, ProfileGroup,
+        ProfileLoadError, SsUrlError, ToLaunchArgs, PASSWORD_MASK,
+    };
+
+    /// The example profiles shipped in the repository.
+    fn example_profiles() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../example-profiles")
+    }
+
+    /// Display name, server address, password, encrypt method, and plugin launch args.
+    type ProxyFields = (Option<String>, (String, u16), String, String, Vec<OsString>);
+
+    fn unwrap_proxy(config: ProfileConfig) -> ProxyFields {
+        match config {
+            ProfileConfig::Proxy {
+                metadata,
+                conn_opts,
+                opts,
+                ..
+            } => (
+                metadata.display_name,
+                conn_opts.server_addr,
+                conn_opts.password.as_plain().unwrap().into(),
+                conn_opts.encrypt_method,
+                opts.to_launch_args(),
+            ),
+            other => panic!("Expected proxy-mode config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ss_url_base64_user_info() {
+        let config = ProfileConfig::from_ss_url("ss://YWVzLTEyOC1nY206dGVzdA@192.168.100.1:8888#Example1").unwrap();
+        let (name, server, password, method, plugin_args) = unwrap_proxy(config);
+        assert_eq!(name.as_deref(), Some("Example1"));
+        assert_eq!(server, ("192.168.100.1".into(), 8888));
+        assert_eq!(password, "test");
+        assert_eq!(method, "aes-128-gcm");
+        assert!(plugin_args.is_empty());
+    }
+    #[test]
+    fn ss_url_plugin() {
+        let config = ProfileConfig::from_ss_url(
+            "ss://cmM0LW1kNTpwYXNzd2Q@192.168.100.1:8888/?plugin=obfs-local%3Bobfs%3Dhttp#Example2",
+        )
+        .unwrap();
+        let (_, _, password, method, plugin_args) = unwrap_proxy(config);
+        assert_eq!(password, "passwd");
+        assert_eq!(method, "rc4-md5");
+        assert_eq!(
+            plugin_args,
+            vec!["--plugin", "obfs-local", "--plugin-opts", "obfs=http"]
+        );
+    }
+    #[test]
+    fn ss_url_plain_user_info() {
+        let config = ProfileConfig::from_ss_url(
+            "ss://2022-blake3-aes-256-gcm:YctPZ6U7xPPcU%2Bgp3u%2BA%3D@[::1]:8888#My%20Server",
+        )
+        .unwrap();
+        let (name, server, password, method, _) = unwrap_proxy(config);
+        assert_eq!(name.as_deref(), Some("My Server"));
+        assert_eq!(server, ("::1".into(), 8888));
+        assert_eq!(password, "YctPZ6U7xPPcU+gp3u+A=");
+        assert_eq!(method, "2022-blake3-aes-256-gcm");
+    }
+    #[test]
+    fn ss_url_legacy() {
+        // base64 of "bf-cfb:test@192.168.100.1:8888"
+        let config = ProfileConfig::from_ss_url("ss://YmYtY2ZiOnRlc3RAMTkyLjE2OC4xMDAuMTo4ODg4#Legacy").unwrap();
+        let (name, server, password, method, _) = unwrap_proxy(config);
+        assert_eq!(name.as_deref(), Some("Legacy"));
+        assert_eq!(server, ("192.168.100.1".into(), 8888));
+        assert_eq!(password, "test");
+        assert_eq!(method, "bf-cfb");
+    }
+    #[test]
+    fn ss_url_bad() {
+        assert!(matches!(
+            ProfileConfig::from_ss_url("http://foo@bar:80"),
+            Err(SsUrlError::WrongScheme(_))
+        ));
+        assert!(matches!(
+            ProfileConfig::from_ss_url("ss://YWVzLTEyOC1nY206dGVzdA@192.168.100.1"),
+            Err(SsUrlError::MissingComponent("port"))
+        ));
+        assert!(ProfileConfig::from_ss_url("ss://!!!@foo:1").is_err());
+    }
+    #[test]
+    fn ss_url_round_trip() {
+        let urls = [
+            "ss://YWVzLTEyOC1nY206dGVzdA@192.168.100.1:8888#Example1",
+            "ss://cmM0LW1kNTpwYXNzd2Q@192.168.100.1:8888/?plugin=obfs-local%3Bobfs%3Dhttp#Example2",
+            "ss://2022-blake3-aes-256-gcm:YctPZ6U7xPPcU%2Bgp3u%2BA%3D@[::1]:8888#My%20Server",
+        ];
+        for url in urls {
+            let config = ProfileConfig::from_ss_url(url).unwrap();
+            let name = config.display_name().unwrap().to_string();
+            assert_eq!(config.to_ss_url(&name).as_deref(), Some(url));
+        }
+    }
+    #[test]
+    fn balanced_sslocal_config() {
+        let yaml = r#"
+mode: balanced
+local_addr: [127.0.0.1, 1080]
+servers:
+  - name: "A"
+    server_addr: [a.example.org, 8388]
+    password: "pw-a"
+    encrypt_method: "aes-128-gcm"
+  - server_addr: ["::1", 8389]
+    password: "pw-b"
+    encrypt_method: "chacha20-ietf-poly1305"
+"#;
+        let config: ProfileConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.server_addrs(), vec![("a.example.org", 8388), ("::1", 8389)]);
+        assert!(config.server_addr().is_none());
+        assert_eq!(config.local_proxy_addr(), Some("127.0.0.1:1080".parse().unwrap()));
+
+        let opts = match config {
+            ProfileConfig::Balanced { opts, .. } => opts,
+            other => panic!("Expected balanced-mode config, got {:?}", other),
+        };
+        let generated = opts.to_sslocal_config();
+        assert_eq!(generated["locals"][0]["local_port"], 1080);
+        assert_eq!(generated["servers"][0]["remarks"], "A");
+        assert_eq!(generated["servers"][1]["server"], "::1");
+        assert_eq!(generated["servers"][1]["method"], "chacha20-ietf-poly1305");
+        assert!(generated["servers"][1].get("remarks").is_none());
+    }
+    #[test]
+    fn http_local_config() {
+        let yaml = r#"
+mode: proxy
+local_addr: ["::1", 1080]
+server_addr: [example.org, 8388]
+password: "pw"
+encrypt_method: "aes-128-gcm"
+http_port: 8118
+"#;
+        let config: ProfileConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.http_local_addr(), Some("[::1]:8118".parse().unwrap()));
+
+        let (conn_opts, opts) = match config {
+            ProfileConfig::Proxy { conn_opts, opts, .. } => (conn_opts, opts),
+            other => panic!("Expected proxy-mode config, got {:?}", other),
+        };
+        let generated = opts.to_http_local_config(conn_opts.local_addr.0).unwrap();
+        assert_eq!(generated["locals"][0]["local_address"], "::1");
+        assert_eq!(generated["locals"][0]["local_port"], 8118);
+        assert_eq!(generated["locals"][0]["protocol"], "http");
+        // the SOCKS5 proxy is still set with `--local-addr`
+        assert!(conn_opts.to_launch_args().contains(&"[::1]:1080".into()));
+    }
+    #[test]
+    fn alternate_backends() {
+        let yaml = |backend: &str, method: &str| {
+            format!(
+                r#"
+mode: proxy
+bin_path: /bin/sh
+backend: {}
+local_addr: [127.0.0.1, 1080]
+server_addr: ["::1", 8388]
+password: "p@ss"
+encrypt_method: "{}"
+plugin: sh
+"#,
+                backend, method
+            )
+        };
+        let load =
+            |yaml: &str| Profile::from_config(serde_yaml::from_str(yaml).unwrap(), "Foo".into(), ".".into(), None);
+
+        let libev = load(&yaml("shadowsocks-libev", "aes-128-gcm")).unwrap();
+        assert_eq!(libev.metadata.backend, Backend::ShadowsocksLibev);
+        assert!(libev.sslocal_info().is_none());
+        assert_eq!(
+            LibevArgs(libev.config()).to_launch_args(),
+            vec![
+                "-s",
+                "::1",
+                "-p",
+                "8388",
+                "-b",
+                "127.0.0.1",
+                "-l",
+                "1080",
+                "-k",
+                "p@ss",
+                "-m",
+                "aes-128-gcm",
+                "--plugin",
+                "sh",
+            ]
+        );
+
+        let go = load(&yaml("go-shadowsocks2", "chacha20-ietf-poly1305")).unwrap();
+        assert_eq!(
+            GoShadowsocks2Args(go.config()).to_launch_args(),
+            vec![
+                "-c",
+                "ss://chacha20-ietf-poly1305:p%40ss@[::1]:8388",
+                "-socks",
+                "127.0.0.1:1080",
+                "-plugin",
+                "sh",
+            ]
+        );
+        assert!(load(&yaml("go-shadowsocks2", "aes-128-cfb")).is_err());
+
+        // no binary is needed, but plugins are not supported
+        assert!(load(&yaml("embedded", "aes-128-gcm")).is_err());
+        let embedded = yaml("embedded", "aes-128-gcm").replace("plugin: sh\n", "");
+        assert_eq!(load(&embedded).is_ok(), cfg!(feature = "embedded"));
+
+        let tun = "mode: tun\nbin_path: /bin/sh\nbackend: shadowsocks-libev\nlocal_addr: [127.0.0.1, 1080]\n\
+                   server_addr: [example.org, 8388]\npassword: pw\nencrypt_method: aes-128-gcm\n";
+        assert!(load(tun).is_err());
+        assert!(load(&tun.replace("shadowsocks-libev", "shadowsocks-rust")).is_ok());
+    }
+    #[test]
+    fn launch_preview() {
+        let yaml = |backend: &str| {
+            format!(
+                "mode: proxy\nbin_path: /bin/sh\nbackend: {}\nlocal_addr: [127.0.0.1, 1080]\n\
+                 server_addr: [example.org, 8388]\npassword: \"it's secret\"\nencrypt_method: aes-256-gcm\n",
+                backend
+            )
+        };
+        let load = |yaml: &str| {
+            Profile::from_config(serde_yaml::from_str(yaml).unwrap(), "Foo".into(), ".".into(), None).unwrap()
+        };
+        for backend in ["shadowsocks-libev", "go-shadowsocks2"] {
+            let preview = load(&yaml(backend)).launch_preview(false).unwrap();
+            assert_eq!(preview.program, Path::new("/bin/sh"));
+            let command = preview.to_shell_command();
+            assert!(!command.contains("secret"), "{}", command);
+            assert!(command.contains(PASSWORD_MASK), "{}", command);
+        }
+        let preview = load(&yaml("shadowsocks-libev")).launch_preview(false).unwrap();
+        assert!(preview.to_shell_command().ends_with("-k '********' -m aes-256-gcm"));
+
+        // passwords in the keyring are not looked up
+        let keyring = yaml("shadowsocks-libev").replace("\"it's secret\"", "{ keyring: foo }");
+        let preview = load(&keyring).launch_preview(false).unwrap();
+        assert!(preview.args.contains(&PASSWORD_MASK.into()));
+
+        // the profile's environment variables override inherited ones
+        let with_env = yaml("shadowsocks-libev") + "env:\n  PATH: /nowhere\n  RUST_LOG: debug\n";
+        let preview = load(&with_env).launch_preview(false).unwrap();
+        let var = |key: &str| preview.env.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        assert_eq!(var("PATH"), Some("/nowhere".into()));
+        assert_eq!(var("RUST_LOG"), Some("debug".into()));
+
+        let verbose = yaml("go-shadowsocks2") + "log_level: trace\n";
+        let preview = load(&verbose).launch_preview(false).unwrap();
+        assert_eq!(preview.args.last(), Some(&"-verbose".into()));
+        assert_eq!(LogLevel::Trace.launch_args(Backend::ShadowsocksRust), ["-vv"]);
+        assert_eq!(LogLevel::Trace.bumped(), LogLevel::TraceAll);
+        assert_eq!(LogLevel::TraceAll.bumped(), LogLevel::TraceAll);
+    }
+    #[test]
+    fn relative_paths() {
+        let yaml = r#"
+mode: "config-file"
+pwd: ".."
+bin_path: "/bin/sh"
+config_path: "./ss.json5"
+hooks:
+  pre_start: "./up.sh"
+  post_stop: "/usr/local/bin/down"
+"#;
+        let base_dir = &example_profiles().join("Group-of-good-profiles/Config-file-mode");
+        let config = serde_yaml::from_str(yaml).unwrap();
+        let profile = Profile::from_config(config, "Foo".into(), base_dir.into(), None).unwrap();
+        assert_eq!(profile.metadata.pwd, base_dir.join(".."));
+        let hooks = &profile.metadata.hooks;
+        assert_eq!(hooks.get(HookKind::PreStart), Some(&*base_dir.join("./up.sh")));
+        assert_eq!(hooks.get(HookKind::PostStop), Some(Path::new("/usr/local/bin/down")));
+        assert_eq!(hooks.get(HookKind::PostStart), None);
+        // resolved against the profile directory, not `pwd`
+        assert!(profile.preflight().is_ok());
+        let exported = profile.to_exported_config(false);
+        assert_eq!(
+            exported.get_metadata_override().pwd.as_deref(),
+            Some(&*base_dir.join(".."))
+        );
+    }
+    #[test]
+    fn icon_and_color() {
+        let yaml = r#"
+mode: "config-file"
+bin_path: "/bin/sh"
+config_path: "./ss.json5"
+icon: "./flags/jp.svg"
+color: "red"
+"#;
+        let base_dir = &example_profiles().join("Group-of-good-profiles/Config-file-mode");
+        let config = serde_yaml::from_str(yaml).unwrap();
+        let profile = Profile::from_config(config, "Tokyo".into(), base_dir.into(), None).unwrap();
+        assert_eq!(profile.metadata.label(), "🔴 Tokyo");
+        // paths are resolved, names are kept
+        assert_eq!(
+            profile.metadata.icon.as_deref(),
+            base_dir.join("./flags/jp.svg").to_str()
+        );
+        let config = serde_yaml::from_str(&yaml.replace("./flags/jp.svg", "flag-jp")).unwrap();
+        let profile = Profile::from_config(config, "Tokyo".into(), base_dir.into(), None).unwrap();
+        assert_eq!(profile.metadata.icon.as_deref(), Some("flag-jp"));
+    }
+    #[test]
+    fn filter_by_tag() {
+        let base_dir = &example_profiles().join("Group-of-good-profiles/Config-file-mode");
+        let profile = |name: &str, tags: &str| {
+            let yaml = format!(
+                "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json5\"\ntags: [{}]\n",
+                tags
+            );
+            let config = serde_yaml::from_str(&yaml).unwrap();
+            ProfileFolder::Profile(Profile::from_config(config, name.into(), base_dir.into(), None).unwrap())
+        };
+        let group = |name: &str, content| {
+            ProfileFolder::Group(ProfileGroup {
+                display_name: name.into(),
+                content,
+                failover: false,
+                unloaded: None,
+            })
+        };
+        let folder = group(
+            "root",
+            vec![
+                group("Japan", vec![profile("Tokyo", "jp, fast"), profile("Osaka", "jp")]),
+                group("US", vec![profile("Seattle", "us, fast"), profile("Dallas", "")]),
+            ],
+        );
+        assert_eq!(folder.tags(), ["fast", "jp", "us"]);
+
+        let names = |pf: &ProfileFolder| -> Vec<String> {
+            pf.get_profiles()
+                .into_iter()
+                .map(|p| p.metadata.display_name.clone())
+                .collect()
+        };
+        let fast = folder.filter_by_tag("fast", "").unwrap();
+        assert_eq!(names(&fast), ["Tokyo", "Seattle"]);
+        // the kept profile is shown even if untagged, and empty groups are dropped
+        let jp = folder.filter_by_tag("jp", "Dallas").unwrap();
+        assert_eq!(names(&jp), ["Tokyo", "Osaka", "Dallas"]);
+        let jp = folder.filter_by_tag("jp", "").unwrap();
+        assert!(matches!(&jp, ProfileFolder::Group(g) if g.content.len() == 1));
+        assert!(folder.filter_by_tag("eu", "").is_none());
+    }
+
+    #[test]
+    fn name_conflicts() {
+        let root = env::temp_dir().join(format!("ssgtk-name-conflict-test-{}", process::id()));
+        let yaml = "mode: \"proxy\"\ndisplay_name: \"Tokyo\"\nbin_path: \"/bin/sh\"\n\
+            local_addr: [\"127.0.0.1\", 1080]\nserver_addr: [\"example.org\", 443]\n\
+            password: \"foobar\"\nencrypt_method: \"aes-256-gcm\"\n";
+        for dir in ["Provider-A/Tokyo", "Provider-B/Tokyo", "Tokyo"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("profile.yaml"), yaml).unwrap();
+        }
+
+        let load = |policy| {
+            let opts = LoadOptions {
+                name_conflicts: policy,
+                ..Default::default()
+            };
+            let mut problems = vec![];
+            let pf = ProfileFolder::from_path_recurse(&root, &opts, &mut problems).unwrap();
+            let names: Vec<_> = (pf.get_profiles().into_iter())
+                .map(|p| p.metadata.display_name.clone())
+                .collect();
+            (names, problems)
+        };
+        // profiles directly under the root are not in a group
+        let (names, problems) = load(NameConflictPolicy::AppendGroup);
+        assert_eq!(names, ["Tokyo", "Tokyo (Provider-B)", "Tokyo (2)"]);
+        assert!(problems.is_empty());
+        let (names, _) = load(NameConflictPolicy::AppendNumber);
+        assert_eq!(names, ["Tokyo", "Tokyo (2)", "Tokyo (3)"]);
+        let (names, problems) = load(NameConflictPolicy::Fail);
+        assert_eq!(names, ["Tokyo"]);
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(&problems[0].error, ProfileLoadError::NameConflict(name) if name == "Tokyo"));
+        fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn partial_load() {
+        let root = env::temp_dir().join(format!("ssgtk-partial-load-test-{}", process::id()));
+        let good = "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json5\"\n";
+        for (dir, file, content) in [
+            ("Group/Good", "profile.yaml", good),
+            ("Group/Good", "ss.json5", "{}"),
+            ("Group/Malformed", "profile.yaml", "mode: [\n"),
+            ("No-config", "notes.txt", ""),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(file), content).unwrap();
+        }
+        fs::create_dir_all(root.join("Empty-group/Empty")).unwrap();
+
+        let opts = LoadOptions {
+            progress: Some(Default::default()),
+            ..Default::default()
+        };
+        let mut problems = vec![];
+        let pf = ProfileFolder::from_path_recurse(&root, &opts, &mut problems).unwrap();
+        assert_eq!(pf.profile_count(), 1);
+        // the malformed profile counts as parsed too
+        assert_eq!(opts.progress.unwrap().fraction(), Some(1.0));
+        let mut paths: Vec<_> = problems.iter().map(|p| p.path.strip_prefix(&root).unwrap()).collect();
+        paths.sort_unstable();
+        // empty groups are ignored
+        assert_eq!(paths, [Path::new("Group/Malformed"), Path::new("No-config")]);
+        assert!(matches!(problems[0].error, ProfileLoadError::ConfigParseError(_)));
+
+        // with nothing left, the base path itself fails to load
+        fs::remove_dir_all(root.join("Group/Good")).unwrap();
+        let mut problems = vec![];
+        let res = ProfileFolder::from_path_recurse(&root, &Default::default(), &mut problems);
+        assert!(matches!(res, Err(ProfileLoadError::EmptyGroup(_))));
+        assert_eq!(problems.len(), 2);
+        fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn ignore_patterns() {
+        let root = env::temp_dir().join(format!("ssgtk-ignore-patterns-test-{}", process::id()));
+        let good = "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json5\"\n";
+        for (dir, file, content) in [
+            ("", ".ss_ignore", "# drafts and editor leftovers\nDraft*/\n*.tmp\n"),
+            ("Group", ".ss_ignore", "!Draft-kept/\n"),
+            ("Group/Good", "profile.yaml", good),
+            ("Group/Good", "ss.json5", "{}"),
+            ("Group/Good", "profile.yaml.tmp", ""),
+            ("Group/Draft-malformed", "profile.yaml", "mode: [\n"),
+            ("Group/Draft-kept", "profile.yaml", good),
+            ("Group/Draft-kept", "ss.json5", "{}"),
+            ("Group", "notes.tmp", ""),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(file), content).unwrap();
+        }
+
+        let mut problems = vec![];
+        let pf = ProfileFolder::from_path_recurse(&root, &Default::default(), &mut problems).unwrap();
+        let names: Vec<_> = pf
+            .get_profiles()
+            .iter()
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        assert_eq!(names, ["Draft-kept", "Good"]);
+        assert!(problems.is_empty());
+
+        // an ignore file without patterns still ignores the whole directory
+        fs::write(root.join("Group/.ss_ignore"), "# nothing to see here\n").unwrap();
+        let mut problems = vec![];
+        let res = ProfileFolder::from_path_recurse(&root, &Default::default(), &mut problems);
+        assert!(matches!(res, Err(ProfileLoadError::EmptyGroup(_))));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn depth_limit_and_loops() {
+        let root = env::temp_dir().join(format!("ssgtk-depth-limit-test-{}", process::id()));
+        let good = "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json5\"\n";
+        for dir in ["Shallow", "A/B/Deep"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("profile.yaml"), good).unwrap();
+            fs::write(root.join(dir).join("ss.json5"), "{}").unwrap();
+        }
+        std::os::unix::fs::symlink(&root, root.join("A/Loop")).unwrap();
+
+        let opts = LoadOptions {
+            follow_symlinks: true,
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let mut problems = vec![];
+        let pf = ProfileFolder::from_path_recurse(&root, &opts, &mut problems).unwrap();
+        let names: Vec<_> = (pf.get_profiles().into_iter())
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        assert_eq!(names, ["Shallow"]);
+        assert!(matches!(&problems[..], [p] if matches!(p.error, ProfileLoadError::TooDeep(_))));
+
+        // the loop back to the root is skipped
+        let opts = LoadOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let mut problems = vec![];
+        let pf = ProfileFolder::from_path_recurse(&root, &opts, &mut problems).unwrap();
+        assert_eq!(pf.get_profiles().len(), 2);
+        assert!(problems.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn lazy_load() {
+        let root = env::temp_dir().join(format!("ssgtk-lazy-load-test-{}", process::id()));
+        let good = "mode: \"config-file\"\nbin_path: \"/bin/sh\"\nconfig_path: \"./ss.json5\"\n";
+        for dir in ["Top", "Group/A", "Group/Sub/Top"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("profile.yaml"), good).unwrap();
+            fs::write(root.join(dir).join("ss.json5"), "{}").unwrap();
+        }
+
+        let opts = LoadOptions {
+            lazy: true,
+            ..Default::default()
+        };
+        let mut problems = vec![];
+        let mut pf = ProfileFolder::from_path_recurse(&root, &opts, &mut problems).unwrap();
+        assert_eq!(pf.profile_count(), 1);
+        let group_dir = root.canonicalize().unwrap().join("Group");
+        assert!(pf.load_group(&group_dir, &root, &opts, &mut problems).unwrap());
+        let names: Vec<_> = (pf.get_profiles().into_iter())
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        // names are unique across the groups loaded later too
+        assert_eq!(names, ["A", "Top (Sub)", "Top"]);
+        assert!(problems.is_empty());
+        // already loaded
+        assert!(!pf.load_group(&group_dir, &root, &opts, &mut problems).unwrap());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn duplicate_profile() {
+        let src_dir = &example_profiles().join("Group-of-good-profiles/Config-file-mode");
+        let config = ConfigFormat::Yaml
+            .parse(&fs::read_to_string(src_dir.join("profile.yaml")).unwrap())
+            .unwrap();
+        let mut profile =
+            Profile::from_config(config, "Foo".into(), src_dir.into(), Some(Path::new("/bin/sh"))).unwrap();
+        profile.metadata.config_dir = Some(src_dir.into());
+
+        let parent = env::temp_dir().join(format!("ssgtk-duplicate-test-{}", process::id()));
+        fs::create_dir_all(&parent).unwrap();
+        let dir = profile.duplicate_into(&parent, "Copy/of Example").unwrap();
+        assert_eq!(dir, parent.join("Copy-of Example"));
+        // referenced files are copied along
+        assert!(dir.join("ss.json5").is_file());
+        let copied = ConfigFormat::Yaml
+            .parse(&fs::read_to_string(dir.join("profile.yaml")).unwrap())
+            .unwrap();
+        assert_eq!(copied.display_name(), Some("Copy/of Example"));
+        assert_eq!(
+            profile.duplicate_into(&parent, "Copy/of Example").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        fs::remove_dir_all(parent).unwrap();
+    }
+    #[test]
+    fn archive_profile() {
+        let root = env::temp_dir().join(format!("ssgtk-archive-test-{}", process::id()));
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        fs::write(
+            root.join("Foo/profile.yaml"),
+            "mode: config-file\nconfig_path: ss.json\n",
+        )
+        .unwrap();
+        let first = archive_profile_dir(root.join("Foo"), &root).unwrap();
+        fs::create_dir_all(root.join("Foo")).unwrap();
+        let second = archive_profile_dir(root.join("Foo"), &root).unwrap();
+
+        assert_eq!(first, root.join("archived/Foo"));
+        assert_eq!(second, root.join("archived/Foo (2)"));
+        assert!(first.join("profile.yaml").is_file());
+        assert!(!root.join("Foo").exists());
+        // archived profiles are not loaded
+        let mut problems = vec![];
+        let res = ProfileFolder::from_path_recurse(&root, &Default::default(), &mut problems);
+        assert!(matches!(res, Err(ProfileLoadError::EmptyGroup(_))));
+        assert!(problems.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn sslocal_config_file_validation() {
+        let validate = |dir: &str, extra_args: &[String]| {
+            let opts = ConfigFileOptions {
+                config_path: "./ss.json".into(),
+            };
+            opts.validate(&example_profiles().join(dir), extra_args)
+        };
+        let good = ConfigFileOptions {
+            config_path: "./ss.json5".into(),
+        };
+        assert!(good
+            .validate(&example_profiles().join("Group-of-good-profiles/Config-file-mode"), &[])
+            .is_ok());
+        assert!(validate("Group-of-bad-profiles/Bad-plugin", &[]).is_ok());
+        assert_eq!(
+            validate("Group-of-bad-profiles/Bad-sslocal-config", &[]),
+            Err("`servers[0]`: missing `server_port`".into())
+        );
+        // servers given on the command line take precedence
+        let args = ["--server-url".into(), "ss://foo".into()];
+        assert!(validate("Group-of-bad-profiles/Bad-sslocal-config", &args).is_ok());
+        assert!(validate("Group-of-bad-profiles/No-config", &[]).is_err());
+    }
+    #[test]
+    fn group_order() {
+        let group = |name: &str| {
+            let g = ProfileGroup {
+                display_name: name.into(),
+                content: vec![],
+                failover: false,
+                unloaded: None,
+            };
+            (name.to_string(), ProfileFolder::Group(g))
+        };
+        let mut entries = vec![group("d"), group("c"), group("b"), group("a"), group("e")];
+        let order = GroupOrder {
+            pinned: vec!["e".into()],
+            order: vec!["c".into(), "missing".into()],
+        };
+        order.apply(&mut entries);
+        let names: Vec<_> = entries.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["e", "c", "a", "b", "d"]);
+    }
+    #[test]
+    fn config_formats() {
+        let yaml = r#"
+mode: "proxy"
+display_name: "Foo"
+local_addr: ["127.0.0.1", 1080]
+server_addr: ["foo.bar", 8388]
+password: "foobar"
+encrypt_method: "aes-256-gcm"
+"#;
+        let toml = r#"
+mode = "proxy"
+display_name = "Foo"
+local_addr = ["127.0.0.1", 1080]
+server_addr = ["foo.bar", 8388]
+password = "foobar"
+encrypt_method = "aes-256-gcm"
+"#;
+        let json = r#"{
+  // comments are allowed
+  "mode": "proxy",
+  "display_name": "Foo",
+  "local_addr": ["127.0.0.1", 1080],
+  "server_addr": ["foo.bar", 8388],
+  "password": "foobar",
+  "encrypt_method": "aes-256-gcm",
+}"#;
+        let expected = unwrap_proxy(ConfigFormat::Yaml.parse(yaml).unwrap());
+        for (format, content) in [(ConfigFormat::Toml, toml), (ConfigFormat::Json, json)] {
+            let config = format.parse(content).unwrap();
+            // round trip
+            let config = format.parse(&format.serialize(&config).unwrap()).unwrap();
+            assert_eq!(unwrap_proxy(config), expected, "{:?}", format);
+        }
+
+        // TOML requires plain values to come before tables, whatever the order of the fields
+        let tomls = [
+            r#"
+mode = "proxy"
+local_addr = ["127.0.0.1", 1080]
+server_addr = ["foo.bar", 8388]
+encrypt_method = "aes-256-gcm"
+log_level = "debug"
+extra_args = ["--single-threaded"]
+
+[password]
+keyring = "foo"
+
+[env]
+RUST_LOG = "debug"
+
+[hooks]
+post_start = "./up.sh"
+"#,
+            r#"
+mode = "balanced"
+local_addr = ["127.0.0.1", 1080]
+extra_args = ["--single-threaded"]
+
+[[servers]]
+name = "Server A"
+server_addr = ["a.example.org", 443]
+password = "foobar"
+encrypt_method = "aes-256-gcm"
+
+[[servers]]
+server_addr = ["b.example.org", 8388]
+password = { keyring = "bar" }
+encrypt_method = "chacha20-ietf-poly1305"
+"#,
+        ];
+        for toml in tomls {
+            let config = ConfigFormat::Toml.parse(toml).unwrap();
+            let serialized = ConfigFormat::Toml.serialize(&config).unwrap();
+            let round_tripped = ConfigFormat::Toml.parse(&serialized).unwrap();
+            assert_eq!(
+                serde_json::to_value(round_tripped).unwrap(),
+                serde_json::to_value(config).unwrap()
+            );
+        }
+    }
+    #[test]
+    fn keyring_password() {
+        let yaml = r#"
+mode: "proxy"
+local_addr: ["127.0.0.1", 1080]
+server_addr: ["foo.bar", 8388]
+password:
+  keyring: "foo"
+encrypt_method: "aes-256-gcm"
+"#;
+        let config: ProfileConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(
+            &config,
+            ProfileConfig::Proxy { conn_opts, .. } if conn_opts.password == Password::Keyring { keyring: "foo".into() }
+        ));
+        assert!(!config.to_launch_args().contains(&"--password".into()));
+        assert!(config.to_ss_url("Foo").is_none());
+        // the entry name is kept on export
+        let exported = serde_yaml::to_value(&config).unwrap();
+        assert_eq!(exported["password"]["keyring"], "foo");
+    }
+}
@@ -0,0 +1,650 @@
+//! This module defines a way to interact with the application via CLI in runtime,
+//! enabled behind the "runtime-api" feature.
+//!
+//! This is useful if you want to, say for example,
+//! bind a system shortcut to a particular action.
+//!
+//! Optionally, commands can also be accepted over TCP from other machines,
+//! after authenticating with a shared token. See `runtime_api_msg::tcp` for the protocol.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    runtime_api_msg::{
+        jsonrpc, tcp::*, APICommand, APICommandKind, APILaunchCommand, APIProfileTree, APIStateEvent, APIStatus,
+        APITrafficStats,
+    },
+    util::{self, mutex_lock},
+};
+use bus::{Bus, BusReader};
+use crossbeam_channel::{bounded as bounded_channel, Sender};
+use fs2::FileExt;
+use log::{debug, error, trace, warn};
+
+use crate::{
+    io::profile_loader::{LaunchPreview, ProfileFolder},
+    profile_manager::ProfileManager,
+};
+
+/// How long to wait for the application to handle a JSON-RPC request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often a streamer checks whether the listener has halted.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many TCP clients can be connected at once without having authenticated.
+/// Any more are turned away, so that they cannot exhaust the threads of the application.
+const TCP_MAX_PENDING_CLIENTS: usize = 8;
+
+/// The broadcast of `sslocal` output, which `FollowBacklog` clients subscribe to.
+pub type LogsBroadcast = Arc<Mutex<Bus<String>>>;
+
+/// The broadcast of changes of state, which `Subscribe` clients subscribe to.
+pub type StateEventsBroadcast = Arc<Mutex<Bus<APIStateEvent>>>;
+
+/// What is streamed to a client after the response to its request.
+enum Follower {
+    Backlog(BusReader<String>),
+    StateEvents(BusReader<APIStateEvent>),
+}
+
+/// The result of handling a command, sent back to JSON-RPC clients.
+pub type APIReply = Result<serde_json::Value, String>;
+
+/// A command received by the runtime API.
+#[derive(Debug)]
+pub struct APIRequest {
+    pub cmd: APICommand,
+    /// Set if the client is waiting for a reply.
+    pub reply_tx: Option<Sender<APIReply>>,
+}
+
+#[derive(Debug)]
+enum CmdError {
+    IOError(io::Error),
+    ParseError(json5::Error),
+    AuthError,
+    SendError,
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CmdError::*;
+        match self {
+            IOError(e) => write!(f, "CmdError-IOError: {}", e),
+            ParseError(e) => write!(f, "CmdError-ParseError: {}", e),
+            AuthError => write!(f, "CmdError-AuthError: Client failed to authenticate"),
+            SendError => write!(f, "CmdError-SendError: Command receiver has hung up"),
+        }
+    }
+}
+
+impl From<io::Error> for CmdError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+impl From<json5::Error> for CmdError {
+    fn from(err: json5::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+
+/// Settings for accepting commands over TCP.
+#[derive(Debug, Clone)]
+pub struct TcpAPIConfig {
+    pub bind_addr: SocketAddr,
+    /// The shared secret that clients must prove they know.
+    pub token: String,
+    /// The kinds of commands accepted; all others are rejected.
+    pub allowlist: Vec<APICommandKind>,
+}
+
+impl TcpAPIConfig {
+    /// Read the token from `token_file`, if accepting commands over TCP at `bind_addr`.
+    ///
+    /// An empty `allowlist` means `TCP_ALLOWLIST_DEFAULT`.
+    pub fn load(
+        bind_addr: Option<SocketAddr>,
+        token_file: Option<&Path>,
+        allowlist: &[APICommandKind],
+    ) -> io::Result<Option<Self>> {
+        let (bind_addr, token_file) = match (bind_addr, token_file) {
+            (Some(addr), Some(file)) => (addr, file),
+            _ => return Ok(None),
+        };
+        let token = fs::read_to_string(token_file)?.trim().to_string();
+        if token.is_empty() {
+            let msg = format!("runtime API token file {:?} is empty", token_file);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+        let allowlist = match allowlist.is_empty() {
+            true => TCP_ALLOWLIST_DEFAULT.to_vec(),
+            false => allowlist.to_vec(),
+        };
+        Ok(Some(Self {
+            bind_addr,
+            token,
+            allowlist,
+        }))
+    }
+}
+
+/// An active listener on a unix socket (and optionally a TCP port) that handles
+/// incoming connections and commands.
+///
+/// Terminates the underlying listener thread when dropped.
+#[derive(Debug)]
+pub struct APIListener {
+    /// Saved so that we can remove it on drop.
+    lock_file_path: PathBuf,
+    /// Saved so that we can unlock on drop.
+    lock_file: File,
+    /// Saved so that we can remove it on drop.
+    socket_path: PathBuf,
+    /// Default: false. Set to true to halt the listener on next poll.
+    halt_flag: Arc<RwLock<bool>>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    listener_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for APIListener {
+    fn drop(&mut self) {
+        trace!("Runtime API listener is getting dropped");
+
+        // notify listener halt
+        *util::rwlock_write(&self.halt_flag) = true;
+
+        // wait for daemon threads to finish
+        if let Some(handle) = self.listener_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!(
+                    "Runtime API's listener daemon thread has panicked unexpectedly: {:?}",
+                    err
+                );
+            };
+        }
+
+        // remove socket file
+        match fs::remove_file(&self.socket_path) {
+            Ok(_) => debug!("Removed socket file at {:?}", &self.socket_path),
+            Err(err) => error!(
+                "Failed to cleanup runtime API's socket file at {:?}: {}",
+                &self.socket_path, err
+            ),
+        }
+
+        // unlock and remove lock file
+        match self.lock_file.unlock() {
+            Ok(_) => trace!("Unlocked lock file at {:?}", &self.lock_file_path),
+            Err(err) => error!("Failed to unlock lock file at {:?}: {}", &self.lock_file_path, err),
+        }
+        match fs::remove_file(&self.lock_file_path) {
+            Ok(_) => trace!("Removed lock file at {:?}", &self.lock_file_path),
+            Err(err) => warn!("Failed to remove lock file at {:?}: {}", &self.lock_file_path, err),
+        }
+    }
+}
+
+impl APIListener {
+    pub fn start(
+        bind_addr: impl AsRef<Path>,
+        tcp_config: Option<TcpAPIConfig>,
+        cmds_tx: Sender<APIRequest>,
+        logs_brd: LogsBroadcast,
+        events_brd: StateEventsBroadcast,
+    ) -> io::Result<Self> {
+        // try to lock lock file
+        let lock_file_path = {
+            let mut path = bind_addr.as_ref().as_os_str().to_owned();
+            path.push(".lock");
+            PathBuf::from(path)
+        };
+        trace!("Creating and locking lock file at {:?}", lock_file_path);
+        let lock_file = File::create(&lock_file_path)?;
+        if let Err(err) = lock_file.try_lock_exclusive() {
+            error!("Failed to obtain lock on lock file {:?}: {}", lock_file_path, err);
+            return Err(err);
+        }
+
+        let socket_path = bind_addr.as_ref().to_path_buf();
+        let listener = {
+            if bind_addr.as_ref().exists() {
+                // since lock was successful, this means the application
+                // has panicked last time and hasn't performed cleanup
+                // therefore it's safe to remove the listener
+                fs::remove_file(&bind_addr)?;
+            }
+            debug!("Binding runtime API listener to {:?}", bind_addr.as_ref());
+            let bind_res = UnixListener::bind(&bind_addr);
+            if let Err(err) = &bind_res {
+                error!("Runtime API cannot bind to {:?}: {}", bind_addr.as_ref(), err);
+            }
+            let listener = bind_res?;
+            listener.set_nonblocking(true)?;
+            listener
+        };
+        let tcp_listener = match tcp_config {
+            Some(config) => {
+                debug!("Binding runtime API TCP listener to {}", config.bind_addr);
+                let bind_res = TcpListener::bind(config.bind_addr);
+                if let Err(err) = &bind_res {
+                    error!("Runtime API cannot bind to {}: {}", config.bind_addr, err);
+                }
+                let listener = bind_res?;
+                listener.set_nonblocking(true)?;
+                Some((listener, Arc::new(config)))
+            }
+            None => None,
+        };
+        let tcp_pending = Arc::new(AtomicUsize::new(0));
+        let halt_flag = RwLock::new(false).into();
+        let halt_flag_clone = Arc::clone(&halt_flag);
+
+        let listener_handle = thread::Builder::new()
+            .name("Runtime API Listener".into())
+            .spawn(move || loop {
+                thread::sleep(Duration::from_millis(10)); // 100fps
+
+                // check for halt
+                if *util::rwlock_read(&halt_flag_clone) {
+                    trace!("Runtime API halt flag has been set; daemon exiting");
+                    break;
+                }
+
+                match listener.accept() {
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {} // no connections, skip
+                    Err(err) => warn!("Runtime API connection error: {}", err),
+                    Ok((stream, peer_addr)) => {
+                        trace!("Accepted an incoming connection from {:?}", peer_addr);
+                        let res = handle_client(stream, &cmds_tx, &logs_brd, &events_brd, &halt_flag_clone);
+                        if let Err(err) = res {
+                            warn!("Runtime API command error: {}", err);
+                        }
+                    }
+                }
+
+                if let Some((tcp_listener, config)) = &tcp_listener {
+                    match tcp_listener.accept() {
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {} // no connections, skip
+                        Err(err) => warn!("Runtime API TCP connection error: {}", err),
+                        Ok((stream, peer_addr)) => match PendingSlot::acquire(&tcp_pending) {
+                            // each client is handled on its own thread, so that a slow one cannot block the others
+                            Some(slot) => {
+                                debug!("Accepted an incoming TCP connection from {}", peer_addr);
+                                let config = Arc::clone(config);
+                                let cmds_tx = cmds_tx.clone();
+                                let logs_brd = Arc::clone(&logs_brd);
+                                let events_brd = Arc::clone(&events_brd);
+                                let halt_flag = Arc::clone(&halt_flag_clone);
+                                let spawn_res = thread::Builder::new()
+                                    .name(format!("Runtime API TCP client {}", peer_addr))
+                                    .spawn(move || {
+                                        let res = handle_tcp_client(
+                                            stream,
+                                            &config,
+                                            slot,
+                                            &cmds_tx,
+                                            &logs_brd,
+                                            &events_brd,
+                                            &halt_flag,
+                                        );
+                                        if let Err(err) = res {
+                                            warn!("Runtime API command error from {}: {}", peer_addr, err);
+                                        }
+                                    });
+                                if let Err(err) = spawn_res {
+                                    error!("Cannot spawn thread for TCP client {}: {}", peer_addr, err);
+                                }
+                            }
+                            None => warn!(
+                                "Runtime API turned away {}: too many unauthenticated TCP connections",
+                                peer_addr
+                            ),
+                        },
+                    }
+                }
+            })?
+            .into();
+
+        let ret = Self {
+            lock_file_path,
+            lock_file,
+            socket_path,
+            halt_flag,
+            listener_handle,
+        };
+        Ok(ret)
+    }
+}
+
+/// Handles a single client connect request.
+///
+/// Both JSON-RPC requests and plain JSON5 commands are accepted;
+/// only the former get a response.
+fn handle_client(
+    stream: UnixStream,
+    cmds_tx: &Sender<APIRequest>,
+    logs_brd: &LogsBroadcast,
+    events_brd: &StateEventsBroadcast,
+    halt_flag: &Arc<RwLock<bool>>,
+) -> Result<(), CmdError> {
+    stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(3)))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    if is_jsonrpc(&line) {
+        if let Some((response, follower)) = handle_jsonrpc(&line, None, cmds_tx, logs_brd, events_brd) {
+            write_response(&stream, &response)?;
+            if let Some(follower) = follower {
+                follower.spawn_streamer(stream, Arc::clone(halt_flag))?;
+            }
+        }
+        return Ok(());
+    }
+
+    let cmd = json5::from_str::<APICommand>(&line)?;
+    debug!("Runtime API received a command: {}", cmd);
+    let req = APIRequest { cmd, reply_tx: None };
+    cmds_tx.send(req).map_err(|_| CmdError::SendError)
+}
+
+/// A connection counted towards `TCP_MAX_PENDING_CLIENTS`, which is released when dropped.
+#[derive(Debug)]
+struct PendingSlot(Arc<AtomicUsize>);
+
+impl PendingSlot {
+    /// Returns `None` if there are too many pending connections already.
+    fn acquire(count: &Arc<AtomicUsize>) -> Option<Self> {
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < TCP_MAX_PENDING_CLIENTS).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Self(Arc::clone(count)))
+    }
+}
+
+impl Drop for PendingSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Handles a single client connect request over TCP, including authentication.
+///
+/// `slot` is released as soon as the client has authenticated.
+/// Only JSON-RPC requests are accepted.
+fn handle_tcp_client(
+    stream: TcpStream,
+    config: &TcpAPIConfig,
+    slot: PendingSlot,
+    cmds_tx: &Sender<APIRequest>,
+    logs_brd: &LogsBroadcast,
+    events_brd: &StateEventsBroadcast,
+    halt_flag: &Arc<RwLock<bool>>,
+) -> Result<(), CmdError> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(3)))?;
+    let mut writer = &stream;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+
+    // handshake
+    let nonce = generate_nonce()?;
+    writeln!(writer, "{} {}", TCP_GREETING, nonce)?;
+    reader.read_line(&mut line)?;
+    if !tcp_auth_verify(&config.token, &nonce, &line) {
+        writeln!(writer, "{}", TCP_REPLY_DENIED)?;
+        return Err(CmdError::AuthError);
+    }
+    writeln!(writer, "{}", TCP_REPLY_OK)?;
+    drop(slot);
+
+    // command
+    line.clear();
+    reader.read_line(&mut line)?;
+    let allowlist = Some(&config.allowlist[..]);
+    if let Some((response, follower)) = handle_jsonrpc(&line, allowlist, cmds_tx, logs_brd, events_brd) {
+        write_response(&stream, &response)?;
+        if let Some(follower) = follower {
+            follower.spawn_streamer(stream, Arc::clone(halt_flag))?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a line looks like a JSON-RPC request rather than a plain command.
+fn is_jsonrpc(line: &str) -> bool {
+    matches!(
+        serde_json::from_str::<serde_json::Value>(line),
+        Ok(serde_json::Value::Object(map)) if map.contains_key("jsonrpc")
+    )
+}
+
+/// Handles a single JSON-RPC request, and waits for the application to handle the command.
+///
+/// Returns the response to send, which is `None` for notifications,
+/// along with a subscription to `logs_brd` or `events_brd`
+/// if the client has successfully requested to follow the backlog or to subscribe.
+fn handle_jsonrpc(
+    line: &str,
+    allowlist: Option<&[APICommandKind]>,
+    cmds_tx: &Sender<APIRequest>,
+    logs_brd: &LogsBroadcast,
+    events_brd: &StateEventsBroadcast,
+) -> Option<(jsonrpc::Response, Option<Follower>)> {
+    use jsonrpc::*;
+
+    let req = match serde_json::from_str::<Request>(line) {
+        Ok(req) => req,
+        Err(err) => {
+            let err = ErrorObject::new(PARSE_ERROR, err.to_string());
+            return Some((Response::new(serde_json::Value::Null, Err(err)), None));
+        }
+    };
+    let mut follower = None;
+    let res = req.to_command().and_then(|cmd| {
+        if allowlist.is_some_and(|list| !list.contains(&cmd.kind())) {
+            warn!("Runtime API rejected a command not in allowlist: {}", cmd);
+            return Err(ErrorObject::new(FORBIDDEN, "command is not allowed"));
+        }
+        debug!("Runtime API received a JSON-RPC command: {}", cmd);
+        // subscribe before the backlog or the status is read, so that nothing is missed in between
+        follower = match cmd {
+            _ if req.id.is_none() => None,
+            APICommand::FollowBacklog(_) => Some(Follower::Backlog(mutex_lock(logs_brd).add_rx())),
+            APICommand::Subscribe => Some(Follower::StateEvents(mutex_lock(events_brd).add_rx())),
+            _ => None,
+        };
+
+        // notifications do not wait for a reply
+        let (reply_tx, reply_rx) = bounded_channel(1);
+        let reply_tx = req.id.is_some().then_some(reply_tx);
+        cmds_tx
+            .send(APIRequest { cmd, reply_tx })
+            .map_err(|_| ErrorObject::new(INTERNAL_ERROR, "application is shutting down"))?;
+        if req.id.is_none() {
+            return Ok(serde_json::Value::Null);
+        }
+        match reply_rx.recv_timeout(REPLY_TIMEOUT) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(msg)) => Err(ErrorObject::new(COMMAND_FAILED, msg)),
+            Err(_) => Err(ErrorObject::new(
+                INTERNAL_ERROR,
+                "timed out waiting for the application",
+            )),
+        }
+    });
+    let follower = follower.filter(|_| res.is_ok());
+    req.id.map(|id| (Response::new(id, res), follower))
+}
+
+impl Follower {
+    /// Stream each new line of `sslocal` output, or each change of state, to a client
+    /// as a JSON-RPC notification, until the client disconnects or the listener halts.
+    fn spawn_streamer(self, writer: impl Write + Send + 'static, halt_flag: Arc<RwLock<bool>>) -> io::Result<()> {
+        match self {
+            Follower::Backlog(lines) => spawn_streamer(
+                "Runtime API backlog streamer",
+                writer,
+                lines,
+                |line| serde_json::json!([line.trim_end()]),
+                jsonrpc::BACKLOG_LINE_METHOD,
+                halt_flag,
+            ),
+            Follower::StateEvents(events) => spawn_streamer(
+                "Runtime API state event streamer",
+                writer,
+                events,
+                |event| serde_json::json!([event]),
+                jsonrpc::STATE_EVENT_METHOD,
+                halt_flag,
+            ),
+        }
+    }
+}
+
+/// Stream each item received from `items` to a client as a JSON-RPC notification of `method`,
+/// with the `params` made by `to_params`, until the client disconnects or the listener halts.
+fn spawn_streamer<T: Clone + Sync + Send + 'static>(
+    thread_name: &str,
+    mut writer: impl Write + Send + 'static,
+    mut items: BusReader<T>,
+    to_params: fn(T) -> serde_json::Value,
+    method: &'static str,
+    halt_flag: Arc<RwLock<bool>>,
+) -> io::Result<()> {
+    thread::Builder::new().name(thread_name.into()).spawn(move || loop {
+        match items.recv_timeout(FOLLOW_POLL_INTERVAL) {
+            Ok(item) => {
+                let notification = jsonrpc::Request::notification(method, to_params(item));
+                let json = serde_json::to_string(&notification).expect("serialising a JSON-RPC request is infallible");
+                if let Err(err) = writeln!(writer, "{}", json).and_then(|_| writer.flush()) {
+                    debug!("A runtime API follower has disconnected: {}", err);
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if *util::rwlock_read(&halt_flag) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    })?;
+    Ok(())
+}
+
+/// Write a JSON-RPC response as a single line.
+fn write_response(mut writer: impl Write, response: &jsonrpc::Response) -> io::Result<()> {
+    let json = serde_json::to_string(response).expect("serialising a JSON-RPC response is infallible");
+    writeln!(writer, "{}", json)?;
+    writer.flush()
+}
+
+/// The last `lines` lines of the backlog of `pm`.
+pub fn backlog_tail(pm: &ProfileManager, lines: usize) -> Vec<String> {
+    let backlog = mutex_lock(&pm.backlog);
+    backlog.tail(lines).map(|line| line.trim_end().to_string()).collect()
+}
+
+/// The current status of `pm`, with the profiles in `alongside` running alongside it,
+/// as reported to runtime API clients.
+pub fn api_status(pm: &ProfileManager, alongside: Vec<String>) -> APIStatus {
+    let stats = pm.collect_stats.then(|| {
+        let stats = util::rwlock_read(&pm.stats);
+        APITrafficStats {
+            tx_bytes: stats.tx_bytes,
+            rx_bytes: stats.rx_bytes,
+            tx_rate: stats.tx_rate,
+            rx_rate: stats.rx_rate,
+        }
+    });
+    let instance = pm.instance_status();
+    APIStatus {
+        running: pm.is_active(),
+        profile: pm.current_profile().map(|p| p.metadata.display_name),
+        stats,
+        pid: instance.as_ref().and_then(|i| i.pids.first().copied()),
+        uptime_secs: instance.as_ref().map(|i| i.uptime.as_secs()),
+        restarts: instance.as_ref().map(|i| i.restarts),
+        connected_secs: instance.as_ref().map(|i| i.connected_for.as_secs()),
+        connections: instance.as_ref().and_then(|i| i.connections),
+        last_error: instance.and_then(|i| i.last_error),
+        alongside,
+        headless: false,
+    }
+}
+
+/// All profiles in tray menu order, marking the one `pm` is running, as reported to runtime API clients.
+pub fn api_profile_tree(profile_folder: &ProfileFolder, pm: &ProfileManager) -> Vec<APIProfileTree> {
+    let current = pm.current_profile();
+    let active = current.as_ref().map(|p| p.metadata.display_name.as_str());
+    // the root group is not shown, like in the tray
+    match to_api_profile_tree(profile_folder, active) {
+        APIProfileTree::Group { content, .. } => content,
+        profile => vec![profile],
+    }
+}
+
+/// Recursively convert a `ProfileFolder` for runtime API clients,
+/// marking the profile named `active`.
+fn to_api_profile_tree(profile_folder: &ProfileFolder, active: Option<&str>) -> APIProfileTree {
+    match profile_folder {
+        ProfileFolder::Profile(p) => APIProfileTree::Profile {
+            profile: p.metadata.display_name.clone(),
+            active: active == Some(p.metadata.display_name.as_str()),
+            tags: p.metadata.tags.clone(),
+        },
+        ProfileFolder::Group(g) => APIProfileTree::Group {
+            group: g.display_name.clone(),
+            content: g.content.iter().map(|pf| to_api_profile_tree(pf, active)).collect(),
+        },
+    }
+}
+
+/// Convert a `LaunchPreview` for runtime API clients.
+pub fn to_api_launch_command(preview: LaunchPreview) -> APILaunchCommand {
+    let lossy = |s: &std::ffi::OsStr| s.to_string_lossy().into_owned();
+    APILaunchCommand {
+        shell_command: preview.to_shell_command(),
+        program: lossy(preview.program.as_os_str()),
+        args: preview.args.iter().map(|arg| lossy(arg)).collect(),
+        pwd: lossy(preview.pwd.as_os_str()),
+        env: preview.env.iter().map(|(k, v)| (lossy(k), lossy(v))).collect(),
+        as_root: preview.as_root,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cap_pending_tcp_clients() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let slots = (0..TCP_MAX_PENDING_CLIENTS)
+            .map(|_| PendingSlot::acquire(&count).unwrap())
+            .collect::<Vec<_>>();
+        assert!(PendingSlot::acquire(&count).is_none());
+
+        // released once dropped, e.g. after authenticating
+        drop(slots);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        assert!(PendingSlot::acquire(&count).is_some());
+    }
+}
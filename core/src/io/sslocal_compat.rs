@@ -0,0 +1,284 @@
+//! This module contains code that detects the version of an `sslocal` binary and
+//! the options it supports, so that the launch arguments can be adapted to it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use crate::util::mutex_lock;
+use duct::cmd;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use regex::Regex;
+
+/// The oldest version of `sslocal` known to work with all supported profile modes.
+pub const SSLOCAL_VERSION_MIN: SslocalVersion = SslocalVersion {
+    major: 1,
+    minor: 9,
+    patch: 0,
+};
+
+/// The options generated by `ssgtk` that take a value, with their short aliases if any.
+///
+/// Short aliases have been kept stable across `shadowsocks-rust` releases,
+/// so they are used if the long option is not listed by `sslocal --help`.
+const GENERATED_OPTIONS: &[(&str, Option<&str>)] = &[
+    ("--config", Some("-c")),
+    ("--local-addr", Some("-b")),
+    ("--server-addr", Some("-s")),
+    ("--password", Some("-k")),
+    ("--encrypt-method", Some("-m")),
+    ("--plugin", None),
+    ("--plugin-opts", None),
+    ("--protocol", None),
+    ("--tun-interface-name", None),
+    ("--tun-interface-address", None),
+    ("--tun-interface-destination", None),
+    ("--stat-path", None),
+    ("--acl", None),
+];
+
+/// A `shadowsocks-rust` release version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SslocalVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl fmt::Display for SslocalVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for SslocalVersion {
+    type Err = String;
+    /// Parse the output of `sslocal --version`, e.g. `shadowsocks 1.15.3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = s
+            .split_whitespace()
+            .last()
+            .ok_or_else(|| "empty version string".to_string())?;
+        let mut parts = raw.trim_start_matches('v').splitn(3, '.').map(|part| {
+            // ignore pre-release and build suffixes, e.g. `0-alpha.1`
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        });
+        match (parts.next().flatten(), parts.next().flatten(), parts.next().flatten()) {
+            (Some(major), Some(minor), patch) => Ok(Self {
+                major,
+                minor,
+                patch: patch.unwrap_or(0),
+            }),
+            _ => Err(format!("\"{}\" is not a version", raw)),
+        }
+    }
+}
+
+/// What is known about an `sslocal` binary.
+#[derive(Debug)]
+pub struct SslocalInfo {
+    /// `None` if it cannot be detected.
+    pub version: Option<SslocalVersion>,
+    /// All options listed by `sslocal --help`; empty if unknown.
+    options: HashSet<String>,
+    /// Whether the user has been warned about this binary.
+    warned: AtomicBool,
+}
+
+impl SslocalInfo {
+    /// Run `sslocal --version` and `sslocal --help` to find out about it.
+    fn probe(bin_path: &Path) -> Self {
+        let run = |arg: &str| {
+            cmd(bin_path, [arg])
+                .stdout_capture()
+                .stderr_to_stdout()
+                .stdin_null()
+                .unchecked()
+                .read()
+        };
+        let version = match run("--version").map(|out| out.parse::<SslocalVersion>()) {
+            Ok(Ok(v)) => Some(v),
+            Ok(Err(err)) => {
+                warn!("Cannot parse the version of {:?}: {}", bin_path, err);
+                None
+            }
+            Err(err) => {
+                warn!("Cannot run {:?} to detect its version: {}", bin_path, err);
+                None
+            }
+        };
+        let options = run("--help").map(|help| parse_options(&help)).unwrap_or_default();
+        debug!(
+            "Detected sslocal {:?} with {} options at {:?}",
+            version,
+            options.len(),
+            bin_path
+        );
+        Self {
+            version,
+            options,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Describe why this binary may not work, if so.
+    ///
+    /// Only returns `Some` the first time, so that the user is warned once per binary.
+    pub fn take_warning(&self) -> Option<String> {
+        let warning = match self.version {
+            Some(v) if v < SSLOCAL_VERSION_MIN => Some(format!(
+                "sslocal {} is older than the oldest supported version {}; please upgrade",
+                v, SSLOCAL_VERSION_MIN
+            )),
+            Some(_) => None,
+            None => Some("Cannot detect the version of sslocal; it may not be shadowsocks-rust".into()),
+        };
+        warning.filter(|_| !self.warned.swap(true, Ordering::Relaxed))
+    }
+
+    /// Adapt launch arguments to the options this binary supports.
+    ///
+    /// Generated options that are not listed are replaced with their short aliases,
+    /// or dropped along with their values if there are none. Other arguments are kept as-is,
+    /// as are all of them if the supported options are unknown.
+    pub fn adapt_args(&self, args: Vec<OsString>) -> Vec<OsString> {
+        if self.options.is_empty() {
+            return args;
+        }
+        let mut adapted = Vec::with_capacity(args.len());
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let known = GENERATED_OPTIONS.iter().find(|(long, _)| arg == **long);
+            match known {
+                Some((long, _)) if self.options.contains(*long) => adapted.push(arg),
+                Some((long, Some(short))) if self.options.contains(*short) => {
+                    debug!("sslocal does not list \"{}\"; using \"{}\" instead", long, short);
+                    adapted.push(short.into());
+                }
+                Some((long, _)) => {
+                    let value = args.next();
+                    warn!(
+                        "sslocal does not support \"{}\"; dropped it with value {:?}",
+                        long, value
+                    );
+                }
+                None => adapted.push(arg),
+            }
+        }
+        adapted
+    }
+}
+
+/// Parse all options listed in the output of `sslocal --help`.
+fn parse_options(help: &str) -> HashSet<String> {
+    lazy_static! {
+        static ref OPTION_RE: Regex = Regex::new(r"(?:^|[\s,\[])(--?[A-Za-z0-9][A-Za-z0-9-]*)").unwrap();
+    }
+    OPTION_RE.captures_iter(help).map(|caps| caps[1].to_string()).collect()
+}
+
+/// Identifies a binary by its path and modification time, so that upgrades are noticed.
+type ProbeKey = (PathBuf, Option<SystemTime>);
+
+lazy_static! {
+    /// Binaries that have been probed.
+    static ref PROBED: Mutex<HashMap<ProbeKey, Arc<SslocalInfo>>> = Mutex::new(HashMap::new());
+}
+
+/// Get what is known about the `sslocal` binary at `bin_path`, probing it if not done yet.
+pub fn info(bin_path: &Path) -> Arc<SslocalInfo> {
+    let modified = fs::metadata(bin_path).and_then(|m| m.modified()).ok();
+    let key = (bin_path.to_path_buf(), modified);
+    let mut probed = mutex_lock(&PROBED);
+    let info = probed
+        .entry(key)
+        .or_insert_with(|| Arc::new(SslocalInfo::probe(bin_path)));
+    Arc::clone(info)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, ffi::OsString, sync::atomic::AtomicBool};
+
+    use super::{parse_options, SslocalInfo, SslocalVersion};
+
+    #[test]
+    fn parse_version() {
+        let v = |major, minor, patch| SslocalVersion { major, minor, patch };
+        assert_eq!("shadowsocks 1.15.3".parse(), Ok(v(1, 15, 3)));
+        assert_eq!("shadowsocks v1.16.0-alpha.1\n".parse(), Ok(v(1, 16, 0)));
+        assert_eq!("sslocal 1.9".parse(), Ok(v(1, 9, 0)));
+        assert!("".parse::<SslocalVersion>().is_err());
+        assert!("shadowsocks unknown".parse::<SslocalVersion>().is_err());
+        assert!(v(1, 9, 0) < v(1, 10, 0));
+    }
+
+    #[test]
+    fn adapt_to_help() {
+        let help = "\
+Options:
+  -c, --config <CONFIG>            Shadowsocks configuration file
+  -b <LOCAL_ADDR>                  Local address
+  -s, --server-addr <SERVER_ADDR>  Server address
+      --protocol=<PROTOCOL>        [possible values: socks, http, tun]
+  -h, --help                       Print help";
+        let options = parse_options(help);
+        for opt in [
+            "-c",
+            "--config",
+            "-b",
+            "-s",
+            "--server-addr",
+            "--protocol",
+            "-h",
+            "--help",
+        ] {
+            assert!(options.contains(opt), "missing {}", opt);
+        }
+        assert!(!options.contains("--local-addr"));
+
+        let info = SslocalInfo {
+            version: None,
+            options,
+            warned: AtomicBool::new(false),
+        };
+        let args: Vec<OsString> = [
+            "--local-addr",
+            "127.0.0.1:1080",
+            "--server-addr",
+            "example.org:8388",
+            "--stat-path",
+            "/tmp/stat",
+            "--extra",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        let expected: Vec<OsString> = ["-b", "127.0.0.1:1080", "--server-addr", "example.org:8388", "--extra"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        assert_eq!(info.adapt_args(args.clone()), expected);
+
+        // nothing is known, so nothing is changed
+        let unknown = SslocalInfo {
+            version: None,
+            options: HashSet::new(),
+            warned: AtomicBool::new(false),
+        };
+        assert_eq!(unknown.adapt_args(args.clone()), args);
+        assert!(unknown.take_warning().is_some());
+        assert!(unknown.take_warning().is_none());
+    }
+}
@@ -0,0 +1,208 @@
+//! This module contains code that records the PID of each `sslocal` launched,
+//! so that those left running by a crashed session can be found on the next startup.
+//!
+//! Records are kept in `$XDG_RUNTIME_DIR/shadowsocks-gtk-rs/sslocal-pids/`,
+//! one file per `sslocal`, which is removed once it has exited.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use crate::consts::XDG_DIRS;
+use log::{debug, info, trace, warn};
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+/// The name of the directory records are kept in, under the runtime directory.
+const PIDS_DIR_NAME: &str = "sslocal-pids";
+
+/// How long to wait for a terminated orphan to exit, so that its port is free.
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What is known about an `sslocal` launched by `ssgtk`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PidRecord {
+    pub pid: u32,
+    /// The `ssgtk` process that launched it.
+    pub ssgtk_pid: u32,
+    /// The binary of the process that launched it, which may be another frontend, e.g. `ssgtk-tui`.
+    ///
+    /// Missing from records written by older versions.
+    #[serde(default)]
+    pub ssgtk_exe: Option<PathBuf>,
+    /// The display name of the profile.
+    pub profile: String,
+    /// The resolved binary, to tell it apart from an unrelated process that reused its PID.
+    pub bin_path: PathBuf,
+}
+
+/// The record of a running `sslocal`. Removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Cannot remove sslocal PID file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+impl PidFile {
+    /// Record that `sslocal` of the profile `profile` has been launched from `bin_path` as `pid`.
+    pub fn create(pid: u32, profile: &str, bin_path: &Path) -> io::Result<Self> {
+        let record = PidRecord {
+            pid,
+            ssgtk_pid: process::id(),
+            ssgtk_exe: exe_of(process::id()),
+            profile: profile.into(),
+            bin_path: fs::canonicalize(bin_path)?,
+        };
+        let path = XDG_DIRS.place_runtime_file(Path::new(PIDS_DIR_NAME).join(format!("{}.json", pid)))?;
+        fs::write(&path, serde_json::to_string(&record)?)?;
+        trace!("Recorded sslocal PID {} in {:?}", pid, path);
+        Ok(Self { path })
+    }
+}
+
+/// An `sslocal` left running by an `ssgtk` process that is no longer running.
+#[derive(Debug, Clone)]
+pub struct Orphan {
+    pub record: PidRecord,
+    path: PathBuf,
+}
+
+impl Orphan {
+    /// Stop the orphan with `SIGTERM`, and forget it.
+    ///
+    /// Waits up to `TERMINATE_TIMEOUT` for it to exit.
+    pub fn terminate(self) -> io::Result<()> {
+        info!(
+            "Terminating sslocal {} of profile \"{}\" left running by a previous session",
+            self.record.pid, self.record.profile
+        );
+        let pid = Pid::from_raw(self.record.pid as i32);
+        signal::kill(pid, Signal::SIGTERM).map_err(io::Error::from)?;
+        let started_at = Instant::now();
+        while exe_of(self.record.pid).is_some() && started_at.elapsed() < TERMINATE_TIMEOUT {
+            thread::sleep(Duration::from_millis(50));
+        }
+        self.forget();
+        Ok(())
+    }
+
+    /// Leave the orphan running, and do not report it again.
+    pub fn forget(self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Cannot remove sslocal PID file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// Find the `sslocal` processes left running by previous sessions.
+///
+/// Records of those that have exited are removed along the way.
+pub fn find_orphans() -> Vec<Orphan> {
+    let dir = match XDG_DIRS.find_runtime_file(PIDS_DIR_NAME) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Cannot read sslocal PID files in {:?}: {}", dir, err);
+            return vec![];
+        }
+    };
+    let own_exe = exe_of(process::id());
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|ent| {
+            let path = ent.path();
+            let record: PidRecord = match fs::read_to_string(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(record)) => record,
+                _ => {
+                    debug!("Removing unreadable sslocal PID file {:?}", path);
+                    let _ = fs::remove_file(&path);
+                    return None;
+                }
+            };
+            if is_managed(&record, own_exe.as_ref()) {
+                return None;
+            }
+            if exe_of(record.pid).as_ref() != Some(&record.bin_path) {
+                trace!("sslocal {} of profile \"{}\" has exited", record.pid, record.profile);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+            Some(Orphan { record, path })
+        })
+        .collect()
+}
+
+/// Whether the process that launched the `sslocal` of `record` is still running, e.g. this one.
+///
+/// Records without the launcher's binary are assumed to be launched by the same binary as `own_exe`.
+fn is_managed(record: &PidRecord, own_exe: Option<&PathBuf>) -> bool {
+    let launcher_exe = exe_of(record.ssgtk_pid);
+    launcher_exe.is_some() && launcher_exe.as_ref() == record.ssgtk_exe.as_ref().or(own_exe)
+}
+
+/// The binary a process is running, if it is running and visible to us.
+fn exe_of(pid: u32) -> Option<PathBuf> {
+    let exe = fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+    // the binary may have been replaced, e.g. by an upgrade
+    match exe.to_str().and_then(|s| s.strip_suffix(" (deleted)")) {
+        Some(s) => Some(s.into()),
+        None => Some(exe),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exe_of_self() {
+        let exe = fs::canonicalize(std::env::current_exe().unwrap()).unwrap();
+        assert_eq!(exe_of(process::id()), Some(exe));
+        assert_eq!(exe_of(u32::MAX), None);
+    }
+
+    #[test]
+    fn managed_by_another_frontend() {
+        // stands in for another frontend, e.g. `ssgtk-tui`
+        let mut launcher = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let launcher_exe = exe_of(launcher.id()).unwrap();
+        let own_exe = exe_of(process::id());
+        assert_ne!(Some(&launcher_exe), own_exe.as_ref());
+
+        let mut record = PidRecord {
+            pid: u32::MAX,
+            ssgtk_pid: launcher.id(),
+            ssgtk_exe: Some(launcher_exe),
+            profile: "Foo".into(),
+            bin_path: "/usr/bin/sslocal".into(),
+        };
+        assert!(is_managed(&record, own_exe.as_ref()));
+        // the PID has been reused by an unrelated process
+        record.ssgtk_exe = own_exe.clone();
+        assert!(!is_managed(&record, own_exe.as_ref()));
+        // records from older versions
+        record.ssgtk_exe = None;
+        assert!(!is_managed(&record, own_exe.as_ref()));
+
+        launcher.kill().unwrap();
+        launcher.wait().unwrap();
+        record.ssgtk_exe = exe_of(process::id());
+        assert!(!is_managed(&record, own_exe.as_ref()));
+    }
+}
@@ -0,0 +1,96 @@
+//! This module contains code that launches `sslocal` inside a transient systemd scope
+//! via `systemd-run --user --scope`, optionally with memory and CPU limits,
+//! so that it is tracked by the service manager.
+//!
+//! `systemd-run` execs the command after setting up the scope,
+//! so `sslocal` keeps the PID of the spawned process and can be signalled as usual.
+
+use std::{
+    ffi::OsString,
+    io,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// The `systemd-run` binary, looked up in $PATH.
+const SYSTEMD_RUN_LOOKUP_NAME: &str = "systemd-run";
+
+/// The prefix of the names of the scope units, e.g. to find them with `systemctl --user list-units`.
+pub const SCOPE_UNIT_PREFIX: &str = "ssgtk-sslocal";
+
+/// Scope units must have unique names, even when several profiles run at once.
+static SCOPE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// How to set up the scope `sslocal` runs in, as set in the app settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeConfig {
+    /// The `MemoryMax` of the scope, e.g. `256M` or `10%`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<String>,
+    /// The `CPUQuota` of the scope, e.g. `50%` for half of one CPU.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_quota: Option<String>,
+}
+
+impl ScopeConfig {
+    /// The unit properties to set, as `systemd-run -p` arguments.
+    fn properties(&self) -> Vec<String> {
+        let memory_max = self.memory_max.as_ref().map(|v| format!("MemoryMax={}", v));
+        let cpu_quota = self.cpu_quota.as_ref().map(|v| format!("CPUQuota={}", v));
+        memory_max.into_iter().chain(cpu_quota).collect()
+    }
+}
+
+/// Wrap the command to run `program` with `args` in a new scope unit for `profile_name`,
+/// returning the program and arguments to launch instead.
+///
+/// Errors if `systemd-run` cannot be found.
+pub fn wrap_command(
+    config: &ScopeConfig,
+    profile_name: &str,
+    program: PathBuf,
+    args: Vec<OsString>,
+) -> io::Result<(PathBuf, Vec<OsString>)> {
+    let systemd_run =
+        which::which(SYSTEMD_RUN_LOOKUP_NAME).map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+    let unit = format!(
+        "{}-{}-{}.scope",
+        SCOPE_UNIT_PREFIX,
+        process::id(),
+        SCOPE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    debug!("Launching sslocal in scope unit {}", unit);
+
+    let mut wrapped: Vec<OsString> = vec![
+        "--user".into(),
+        "--scope".into(),
+        "--quiet".into(),
+        // do not keep failed units around
+        "--collect".into(),
+        format!("--unit={}", unit).into(),
+        format!("--description=sslocal of ssgtk profile \"{}\"", profile_name).into(),
+    ];
+    for property in config.properties() {
+        wrapped.extend(["-p".into(), property.into()]);
+    }
+    wrapped.push("--".into());
+    wrapped.push(program.into());
+    wrapped.extend(args);
+    Ok((systemd_run, wrapped))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn properties() {
+        assert!(ScopeConfig::default().properties().is_empty());
+        let config: ScopeConfig = serde_yaml::from_str("memory_max: 256M\ncpu_quota: 50%").unwrap();
+        assert_eq!(config.properties(), ["MemoryMax=256M", "CPUQuota=50%"]);
+    }
+}
@@ -0,0 +1,26 @@
+//! The core of shadowsocks-gtk-rs, without any GUI: loading profiles, running and restarting `sslocal`,
+//! and serving the runtime API, so that alternative frontends (e.g. Qt or a TUI) can be built on top of it.
+//!
+//! Nothing here depends on GTK. The main entry points are:
+//! - `io::profile_loader::ProfileFolder`, which loads the profiles in a directory;
+//! - `profile_manager::ProfileManager`, which runs a profile's `sslocal`, sending `ManagerEvent`s through an `EventSink`;
+//! - `io::runtime_api::APIListener` (with the `runtime-api` feature), which receives `runtime_api_msg::APICommand`s.
+
+// public members
+pub mod acl;
+pub mod consts;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod hooks;
+pub mod io;
+pub mod log_parser;
+pub mod notify_method;
+pub mod pac;
+pub mod profile_manager;
+pub mod redact;
+#[cfg(feature = "runtime-api")]
+pub mod runtime_api_msg;
+pub mod stats;
+pub mod util;
+
+// private members with re-export
@@ -0,0 +1,342 @@
+//! This module contains code that parses the logs emitted by `sslocal`,
+//! classifying each line by severity and by the kind of problem it reports,
+//! and interpreting the ANSI escape sequences used to color them.
+
+use std::borrow::Cow;
+
+use lazy_static::lazy_static;
+use log::Level;
+use regex::Regex;
+
+/// The kind of problem reported by a line of `sslocal` output.
+///
+/// The classification is a heuristic based on keywords, so it may be wrong
+/// for messages that are phrased unusually.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogEventKind {
+    /// A connection to the server or the target could not be established or was dropped.
+    ConnectionError,
+    /// The server's response could not be decrypted, which usually means a wrong password or method.
+    AuthFailure,
+    /// A hostname could not be resolved.
+    DnsFailure,
+    /// Anything else.
+    Other,
+}
+
+impl LogEventKind {
+    /// Classify a log message, checked in order of specificity.
+    fn classify(message: &str) -> Self {
+        const AUTH_KEYWORDS: [&str; 6] = [
+            "decrypt",
+            "password",
+            "authenticat",
+            "tag mismatch",
+            "invalid salt",
+            "repeated salt",
+        ];
+        const DNS_KEYWORDS: [&str; 6] = [
+            "dns",
+            "resolve",
+            "lookup",
+            "name or service not known",
+            "no such host",
+            "temporary failure in name resolution",
+        ];
+        const CONNECTION_KEYWORDS: [&str; 8] = [
+            "connection refused",
+            "connection reset",
+            "connection aborted",
+            "timed out",
+            "unreachable",
+            "broken pipe",
+            "connect to",
+            "failed to connect",
+        ];
+
+        let message = message.to_lowercase();
+        let contains_any = |keywords: &[&str]| keywords.iter().any(|k| message.contains(k));
+        if contains_any(&AUTH_KEYWORDS) {
+            Self::AuthFailure
+        } else if contains_any(&DNS_KEYWORDS) {
+            Self::DnsFailure
+        } else if contains_any(&CONNECTION_KEYWORDS) {
+            Self::ConnectionError
+        } else {
+            Self::Other
+        }
+    }
+
+    /// A short guess at the cause, to be shown to the user.
+    pub fn hint(&self) -> Option<&'static str> {
+        use LogEventKind::*;
+        match self {
+            ConnectionError => Some("Server unreachable?"),
+            AuthFailure => Some("Wrong password or method?"),
+            DnsFailure => Some("Cannot resolve hostname"),
+            Other => None,
+        }
+    }
+}
+
+/// A line of `sslocal` output, parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub level: Level,
+    pub kind: LogEventKind,
+    /// The text after the level, trimmed.
+    pub message: String,
+}
+
+/// An RGB color.
+pub type Rgb = (u8, u8, u8);
+
+/// The 16 basic colors, from the Tango palette.
+const BASIC_COLORS: [Rgb; 16] = [
+    (0x2e, 0x34, 0x36),
+    (0xcc, 0x00, 0x00),
+    (0x4e, 0x9a, 0x06),
+    (0xc4, 0xa0, 0x00),
+    (0x34, 0x65, 0xa4),
+    (0x75, 0x50, 0x7b),
+    (0x06, 0x98, 0x9a),
+    (0xd3, 0xd7, 0xcf),
+    (0x55, 0x57, 0x53),
+    (0xef, 0x29, 0x29),
+    (0x8a, 0xe2, 0x34),
+    (0xfc, 0xe9, 0x4f),
+    (0x72, 0x9f, 0xcf),
+    (0xad, 0x7f, 0xa8),
+    (0x34, 0xe2, 0xe2),
+    (0xee, 0xee, 0xec),
+];
+
+/// The text style set by ANSI SGR ("Select Graphic Rendition") sequences, e.g. `\x1b[1;31m`.
+///
+/// Only the attributes that can be rendered in a text view are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SgrStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+}
+
+impl SgrStyle {
+    /// Whether no attributes are set.
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the parameters of an SGR sequence, e.g. `1;31` (an empty string resets).
+    ///
+    /// Unsupported parameters are ignored.
+    fn apply(&mut self, params: &str) {
+        let mut params = params.split(';').map(|p| p.parse::<u16>().unwrap_or(0));
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.foreground = Some(BASIC_COLORS[param as usize - 30]),
+                38 => self.foreground = extended_color(&mut params),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(BASIC_COLORS[param as usize - 40]),
+                48 => self.background = extended_color(&mut params),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(BASIC_COLORS[param as usize - 90 + 8]),
+                100..=107 => self.background = Some(BASIC_COLORS[param as usize - 100 + 8]),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Read the rest of a 256-color (`5;n`) or true color (`2;r;g;b`) parameter.
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Rgb> {
+    let mut next = || params.next().map(|n| n.min(255) as u8);
+    match next()? {
+        5 => {
+            let n = next()?;
+            Some(match n {
+                0..=15 => BASIC_COLORS[n as usize],
+                // 6x6x6 color cube
+                16..=231 => {
+                    let level = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+                    let n = n - 16;
+                    (level(n / 36), level(n / 6 % 6), level(n % 6))
+                }
+                // grayscale ramp
+                232..=255 => {
+                    let gray = 8 + (n - 232) * 10;
+                    (gray, gray, gray)
+                }
+            })
+        }
+        2 => Some((next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+/// Split text into segments of the same style, interpreting ANSI SGR sequences
+/// and dropping all other escape sequences.
+///
+/// `style` is the style in effect at the start of the text, and is updated to the one in effect
+/// at its end, so that a style can carry over to the next line.
+pub fn parse_ansi<'a>(text: &'a str, style: &mut SgrStyle) -> Vec<(&'a str, SgrStyle)> {
+    lazy_static! {
+        // CSI sequences: ESC [, parameter bytes, intermediate bytes, then a final byte
+        static ref CSI_RE: Regex = Regex::new(r"\x1b\[([0-?]*)[ -/]*([@-~])").unwrap();
+    }
+    let mut segments = vec![];
+    let mut last = 0;
+    for captures in CSI_RE.captures_iter(text) {
+        let whole = captures.get(0).unwrap(); // group 0 always exists
+        if whole.start() > last {
+            segments.push((&text[last..whole.start()], *style));
+        }
+        if &captures[2] == "m" {
+            style.apply(&captures[1]);
+        }
+        last = whole.end();
+    }
+    if last < text.len() {
+        segments.push((&text[last..], *style));
+    }
+    segments
+}
+
+/// Remove all ANSI escape sequences from text.
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let segments = parse_ansi(text, &mut SgrStyle::default());
+    Cow::Owned(segments.into_iter().map(|(s, _)| s).collect())
+}
+
+/// Parse a line of `sslocal` output, e.g.
+/// `[stderr] 2022-07-01T12:00:00.000+08:00 ERROR connect to ... failed`.
+///
+/// Returns `None` if the line has no level, e.g. the continuation of a multi-line message.
+pub fn parse_line(line: &str) -> Option<LogEvent> {
+    lazy_static! {
+        // the level comes after the output kind and the timestamp, so only look at the first few words;
+        // the same word later in the line is just part of the message
+        static ref LEVEL_RE: Regex =
+            Regex::new(r"^\s*(?:\S+\s+){0,3}?(ERROR|WARN|INFO|DEBUG|TRACE)(?:\s|$)").unwrap();
+    }
+    let line = strip_ansi(line);
+    let captures = LEVEL_RE.captures(&line)?;
+    let level_match = captures.get(1)?;
+    let level = level_match.as_str().parse().ok()?;
+    let message = line[level_match.end()..].trim().to_string();
+    let kind = match level {
+        Level::Error | Level::Warn => LogEventKind::classify(&message),
+        _ => LogEventKind::Other,
+    };
+    Some(LogEvent { level, kind, message })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_levels() {
+        let event = parse_line("[stdout] 2022-07-01T12:00:00.000+08:00 INFO shadowsocks local 1.14.3\n").unwrap();
+        assert_eq!(event.level, Level::Info);
+        assert_eq!(event.kind, LogEventKind::Other);
+        assert_eq!(event.message, "shadowsocks local 1.14.3");
+
+        let event = parse_line("ERROR  connect to example.com:443 failed").unwrap();
+        assert_eq!(event.level, Level::Error);
+
+        assert!(parse_line("[stderr] a message mentioning an ERROR later on\n").is_none());
+        assert!(parse_line("    at some continuation line").is_none());
+    }
+
+    #[test]
+    fn detect_line_level() {
+        let level = |line: &str| parse_line(line).map(|e| e.level);
+        let line = "2022-07-01T12:00:00.000+08:00 WARN  udp relay 127.0.0.1:1080 -> example.org:443 timed out";
+        assert_eq!(level(line), Some(Level::Warn));
+        assert_eq!(
+            level("2022-07-01T12:00:00.000+08:00 ERROR [tcp] connection reset"),
+            Some(Level::Error)
+        );
+        assert_eq!(level("  stack backtrace:"), None);
+        assert_eq!(level("INFORMATION"), None);
+    }
+
+    #[test]
+    fn ansi_colors() {
+        let mut style = SgrStyle::default();
+        let segments = parse_ansi(
+            "\x1b[2mtime\x1b[0m \x1b[1;31mERROR\x1b[0m oops \x1b[38;5;196mred",
+            &mut style,
+        );
+        let red = SgrStyle {
+            foreground: Some((0xff, 0, 0)),
+            ..Default::default()
+        };
+        assert_eq!(
+            segments,
+            vec![
+                ("time", SgrStyle::default()),
+                (" ", SgrStyle::default()),
+                (
+                    "ERROR",
+                    SgrStyle {
+                        bold: true,
+                        foreground: Some(BASIC_COLORS[1]),
+                        ..Default::default()
+                    }
+                ),
+                (" oops ", SgrStyle::default()),
+                ("red", red),
+            ]
+        );
+        // the style carries over
+        assert_eq!(parse_ansi("still red\x1b[39m", &mut style), vec![("still red", red)]);
+        assert!(style.is_plain());
+
+        assert_eq!(strip_ansi("\x1b[32mINFO\x1b[0m \x1b[Kdone"), "INFO done");
+        let event = parse_line("\x1b[2m2022-07-01T12:00:00.000+08:00\x1b[0m \x1b[31mERROR\x1b[0m failed").unwrap();
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.message, "failed");
+    }
+
+    #[test]
+    fn classify_errors() {
+        let kind = |line: &str| parse_line(line).unwrap().kind;
+        assert_eq!(
+            kind("[stderr] 2022-07-01T12:00:00.000+08:00 ERROR tcp tunnel error, remote: 1.2.3.4:8388, error: Connection refused (os error 111)"),
+            LogEventKind::ConnectionError
+        );
+        assert_eq!(
+            kind("[stderr] 2022-07-01T12:00:00.000+08:00 ERROR failed to decrypt response, invalid tag"),
+            LogEventKind::AuthFailure
+        );
+        assert_eq!(
+            kind(
+                "[stderr] 2022-07-01T12:00:00.000+08:00 WARN  failed to resolve example.com: Name or service not known"
+            ),
+            LogEventKind::DnsFailure
+        );
+        assert_eq!(
+            kind("[stderr] 2022-07-01T12:00:00.000+08:00 ERROR something unexpected"),
+            LogEventKind::Other
+        );
+        // only errors and warnings are classified
+        assert_eq!(
+            kind("[stdout] 2022-07-01T12:00:00.000+08:00 DEBUG connection refused, retrying"),
+            LogEventKind::Other
+        );
+    }
+}
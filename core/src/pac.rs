@@ -0,0 +1,165 @@
+//! This module contains code that generates PAC (proxy auto-config) files
+//! from gfwlist-style rules.
+//!
+//! Only domain-based rules are supported; URL patterns and regular expressions
+//! are ignored, since they are rarely necessary and expensive to evaluate.
+//!
+//! See https://github.com/gfwlist/gfwlist.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+};
+
+/// Domains extracted from a rule list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacRules {
+    /// Requests to these domains (and their subdomains) go through the proxy.
+    pub proxy_domains: BTreeSet<String>,
+    /// Requests to these domains (and their subdomains) always go direct.
+    /// Takes precedence over `proxy_domains`.
+    pub direct_domains: BTreeSet<String>,
+}
+
+impl PacRules {
+    /// Parse an Adblock Plus-style rule list, as used by gfwlist.
+    pub fn parse(text: &str) -> Self {
+        let mut rules = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+                continue; // comment or header
+            }
+            let (rule, set) = match line.strip_prefix("@@") {
+                Some(rule) => (rule, &mut rules.direct_domains),
+                None => (line, &mut rules.proxy_domains),
+            };
+            if let Some(domain) = rule_domain(rule) {
+                set.insert(domain);
+            }
+        }
+        rules
+    }
+
+    /// Parse a base64-encoded rule list, which is how gfwlist is distributed.
+    ///
+    /// Returns `None` if the content is not valid base64.
+    pub fn parse_base64(text: &str) -> Option<Self> {
+        let stripped: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let decoded = base64::decode(stripped).ok()?;
+        Some(Self::parse(&String::from_utf8_lossy(&decoded)))
+    }
+
+    /// Merge another set of rules into this one.
+    pub fn extend(&mut self, other: Self) {
+        self.proxy_domains.extend(other.proxy_domains);
+        self.direct_domains.extend(other.direct_domains);
+    }
+
+    /// Generate a PAC file that sends matching requests to the SOCKS5 proxy at `proxy`.
+    ///
+    /// If `proxy` is `None`, all requests go direct.
+    pub fn to_pac(&self, proxy: Option<SocketAddr>) -> String {
+        let proxy = match proxy {
+            Some(addr) => format!("SOCKS5 {0}; SOCKS {0}; DIRECT", addr),
+            None => "DIRECT".into(),
+        };
+        let to_js_set = |domains: &BTreeSet<String>| {
+            let map: BTreeMap<_, _> = domains.iter().map(|d| (d.as_str(), 1)).collect();
+            serde_json::to_string(&map).unwrap() // string keys always serialize
+        };
+        format!(
+            r#"// Generated by {app}
+
+var proxy = {proxy};
+var proxyDomains = {proxy_domains};
+var directDomains = {direct_domains};
+
+function matchDomain(host, domains) {{
+    var suffix = host;
+    while (true) {{
+        if (Object.prototype.hasOwnProperty.call(domains, suffix)) {{
+            return true;
+        }}
+        var dot = suffix.indexOf(".");
+        if (dot < 0) {{
+            return false;
+        }}
+        suffix = suffix.substring(dot + 1);
+    }}
+}}
+
+function FindProxyForURL(url, host) {{
+    host = host.toLowerCase();
+    if (matchDomain(host, directDomains)) {{
+        return "DIRECT";
+    }}
+    if (matchDomain(host, proxyDomains)) {{
+        return proxy;
+    }}
+    return "DIRECT";
+}}
+"#,
+            app = crate::consts::APP_NAME,
+            proxy = serde_json::to_string(&proxy).unwrap(), // strings always serialize
+            proxy_domains = to_js_set(&self.proxy_domains),
+            direct_domains = to_js_set(&self.direct_domains),
+        )
+    }
+}
+
+/// Extract the domain from a single rule, if it is a domain-based rule.
+fn rule_domain(rule: &str) -> Option<String> {
+    if rule.starts_with('/') {
+        return None; // regular expression
+    }
+    let rule = rule.trim_start_matches('|').trim_start_matches('.');
+    let rule = rule
+        .strip_prefix("http://")
+        .or_else(|| rule.strip_prefix("https://"))
+        .unwrap_or(rule);
+    let domain: String = rule
+        .chars()
+        .take_while(|c| !matches!(c, '/' | '^' | ':' | '?'))
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let is_domain = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    is_domain.then_some(domain)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PacRules;
+
+    #[test]
+    fn parse_rules() {
+        let text = "[AutoProxy 0.2.9]\n\
+                    ! comment\n\
+                    ||google.com\n\
+                    .twitter.com\n\
+                    |https://Example.org/path\n\
+                    plain.net\n\
+                    @@||cn.google.com\n\
+                    /^https?:\\/\\/[^\\/]+blogspot\\.(.*)/\n\
+                    *.wildcard.com\n\
+                    keyword";
+        let rules = PacRules::parse(text);
+        let proxy: Vec<_> = rules.proxy_domains.iter().map(String::as_str).collect();
+        let direct: Vec<_> = rules.direct_domains.iter().map(String::as_str).collect();
+        assert_eq!(proxy, vec!["example.org", "google.com", "plain.net", "twitter.com"]);
+        assert_eq!(direct, vec!["cn.google.com"]);
+    }
+
+    #[test]
+    fn generate_pac() {
+        let rules = PacRules::parse("||google.com");
+        let pac = rules.to_pac(Some("127.0.0.1:1080".parse().unwrap()));
+        assert!(pac.contains(r#"var proxy = "SOCKS5 127.0.0.1:1080; SOCKS 127.0.0.1:1080; DIRECT";"#));
+        assert!(pac.contains(r#"var proxyDomains = {"google.com":1};"#));
+        assert!(rules.to_pac(None).contains(r#"var proxy = "DIRECT";"#));
+    }
+}
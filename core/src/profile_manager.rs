@@ -0,0 +1,1050 @@
+//! This module contains code that handles profile switching and automatic restarting.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    net::SocketAddr,
+    os::unix::net::UnixStream,
+    process::ExitStatus,
+    sync::{Arc, Mutex, RwLock},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use bus::{Bus, BusReader};
+use crossbeam_channel::{unbounded as unbounded_channel, Receiver, SendError, Sender};
+use derivative::Derivative;
+use duct::{unix::HandleExt, Handle};
+use itertools::Itertools;
+use log::{debug, error, info, trace, warn, Level};
+use nix::sys::signal::Signal;
+
+use crate::{
+    consts::*,
+    hooks::{self, HookKind},
+    io::{
+        log_file::{self, LogFileConfig, RotatingLogFile},
+        port_check,
+        profile_loader::Profile,
+        sslocal_pids::PidFile,
+        systemd_scope::ScopeConfig,
+    },
+    log_parser::{self, LogEvent, LogEventKind},
+    redact,
+    stats::{StatsCollector, TrafficStats},
+    util::{
+        self,
+        backoff::{ExponentialBackoff, ExponentialBackoffConfig},
+        leaky_bucket::{NaiveLeakyBucket, NaiveLeakyBucketConfig},
+        mutex_lock,
+        ring_buffer::{LineRingBuffer, LineRingBufferConfig},
+        rwlock_read, OutputKind,
+    },
+};
+#[cfg(feature = "embedded")]
+use crate::{embedded::EmbeddedClient, io::profile_loader::Backend};
+
+/// An event sent by `ProfileManager` when its `sslocal` instance changes state on its own.
+#[derive(Debug, Clone)]
+pub enum ManagerEvent {
+    OkStop {
+        instance_name: Option<String>,
+    },
+    ErrorStop {
+        instance_name: Option<String>,
+        err: String,
+    },
+    RestartScheduled {
+        profile_name: String,
+        attempt: usize,
+        delay: Duration,
+    },
+    /// An error logged by `sslocal`.
+    SslocalError {
+        profile_name: String,
+        line: String,
+        kind: LogEventKind,
+    },
+}
+
+/// Where `ProfileManager` sends its `ManagerEvent`s,
+/// converted into the event type of whichever channel it wraps.
+#[derive(Clone)]
+pub struct EventSink(Arc<dyn Fn(ManagerEvent) -> Result<(), SendError<()>> + Send + Sync>);
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+impl EventSink {
+    pub fn new<E>(tx: Sender<E>) -> Self
+    where
+        E: From<ManagerEvent> + Send + 'static,
+    {
+        Self(Arc::new(move |event| tx.send(event.into()).map_err(|_| SendError(()))))
+    }
+
+    /// Send an event, erroring if all receivers have hung up.
+    pub fn send(&self, event: ManagerEvent) -> Result<(), SendError<()>> {
+        (self.0)(event)
+    }
+}
+
+/// If a `sslocal` instance stays up for at least this long before failing,
+/// it is considered to have been running stably, and the restart backoff is reset.
+const BACKOFF_RESET_AFTER: Duration = Duration::from_secs(60);
+
+/// How often a pending restart checks whether it has been cancelled.
+const BACKOFF_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for `sslocal` to exit after `SIGTERM` by default, before killing it.
+pub const STOP_GRACE_PERIOD_DEFAULT: Duration = Duration::from_secs(3);
+
+/// How often a stopping `sslocal` is checked for exit.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A running `sslocal`, either as a subprocess, or in-process with `Backend::Embedded`.
+#[derive(Debug)]
+enum SslocalProcess {
+    External(Handle),
+    #[cfg(feature = "embedded")]
+    Embedded(EmbeddedClient),
+}
+
+impl fmt::Display for SslocalProcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::External(handle) => {
+                let pids_repr = handle.pids().iter().map(u32::to_string).join(", ");
+                write!(f, "PIDs: [{}]", pids_repr)
+            }
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => write!(f, "{}", client),
+        }
+    }
+}
+
+impl SslocalProcess {
+    /// The PIDs of the subprocess(es); empty if in-process.
+    fn pids(&self) -> Vec<u32> {
+        match self {
+            Self::External(handle) => handle.pids(),
+            #[cfg(feature = "embedded")]
+            Self::Embedded(_) => vec![],
+        }
+    }
+
+    /// How many connections are open, if known.
+    fn connections(&self) -> Option<usize> {
+        match self {
+            Self::External(_) => None,
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => Some(client.connections()),
+        }
+    }
+
+    /// Ask it to stop gracefully, i.e. send `SIGTERM` to a subprocess.
+    fn terminate(&self) -> io::Result<()> {
+        match self {
+            Self::External(handle) => handle.send_signal(Signal::SIGTERM as i32),
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => {
+                client.stop();
+                Ok(())
+            }
+        }
+    }
+
+    /// Stop it forcefully, and wait for it to exit.
+    fn kill(&self) -> io::Result<()> {
+        match self {
+            Self::External(handle) => handle.kill(),
+            // stopping is never blocked
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => {
+                client.stop();
+                client.wait();
+                Ok(())
+            }
+        }
+    }
+
+    fn try_wait(&self) -> io::Result<Option<ExitStatus>> {
+        match self {
+            Self::External(handle) => handle.try_wait().map(|out| out.map(|o| o.status)),
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => Ok(client.try_wait()),
+        }
+    }
+
+    fn wait(&self) -> io::Result<ExitStatus> {
+        match self {
+            Self::External(handle) => handle.wait().map(|out| out.status),
+            #[cfg(feature = "embedded")]
+            Self::Embedded(client) => Ok(client.wait()),
+        }
+    }
+}
+
+/// Represents a currently running `sslocal` instance, storing the relevant information
+/// for its subprocess(es).
+///
+/// Automatically stops `sslocal` when dropped.
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct ActiveSSInstance {
+    /// Ownership instead of reference due to need for restart.
+    profile: Profile,
+    /// The handle of the subprocess, or of the in-process client.
+    sslocal_process: Arc<SslocalProcess>,
+    /// Set if `sslocal` runs as root and cannot be signalled; drop to stop it.
+    privileged_stdin: Option<File>,
+    /// The record of the `sslocal` process, unless it runs in-process or as root.
+    pid_file: Option<PidFile>,
+    /// Subscribe to me to handle `sslocal`'s `stdout`.
+    #[derivative(Debug(format_with = "crate::util::hacks::omit_bus"))]
+    stdout_brd: Arc<Mutex<Bus<String>>>,
+    /// Subscribe to me to handle `sslocal`'s `stderr`.
+    #[derivative(Debug(format_with = "crate::util::hacks::omit_bus"))]
+    stderr_brd: Arc<Mutex<Bus<String>>>,
+    /// The daemon threads that need to be cleanup up when deactivating.
+    daemon_handles: Vec<JoinHandle<()>>,
+    /// Collects traffic reports from `sslocal`, if enabled.
+    stats_collector: Option<StatsCollector>,
+    /// When this instance has started.
+    started_at: Instant,
+    /// How many times the profile has been restarted automatically before this instance.
+    restarts: u32,
+    /// When the profile was first connected to; kept across automatic restarts.
+    connected_at: Instant,
+    /// Why the previous instance of the profile has failed, if restarted automatically.
+    last_error: Option<String>,
+    /// How long to wait for `sslocal` to exit after `SIGTERM`, before killing it.
+    stop_grace_period: Duration,
+}
+
+/// Information about the running `sslocal` instance.
+#[derive(Debug, Clone)]
+pub struct InstanceStatus {
+    pub pids: Vec<u32>,
+    /// Time since the instance has started, which is reset on automatic restarts.
+    pub uptime: Duration,
+    /// How many times the profile has been restarted automatically.
+    pub restarts: u32,
+    /// Time since the profile was connected to, which is kept across automatic restarts.
+    pub connected_for: Duration,
+    /// Why `sslocal` last failed with the profile, if it has.
+    pub last_error: Option<String>,
+    /// How many connections are open; only known for `Backend::Embedded`.
+    pub connections: Option<usize>,
+}
+
+impl fmt::Display for ActiveSSInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ActiveSSInstance(Profile: {}, {})",
+            self.profile.metadata.display_name, self.sslocal_process
+        )
+    }
+}
+
+impl Drop for ActiveSSInstance {
+    /// Stop the `sslocal` child process when going out of scope,
+    /// killing it if it does not exit within the grace period.
+    ///
+    /// Only returns once it has been reaped, so that its port is free again.
+    /// Also cleans up all daemon threads.
+    fn drop(&mut self) {
+        let self_name = self.to_string();
+
+        trace!("{} is getting dropped", self_name);
+
+        // send stop signal to `sslocal` process
+        if self.privileged_stdin.take().is_some() {
+            trace!("Closed stdin of {} to stop it", self_name);
+        } else if let Err(err) = self.sslocal_process.terminate() {
+            trace!("{}'s underlying process has already exited: {}", self_name, err);
+        }
+
+        // wait for `sslocal` to exit fully, or kill it
+        if !wait_for_exit(&self.sslocal_process, self.stop_grace_period) {
+            warn!(
+                "{} has not exited within {:?}; killing it",
+                self_name, self.stop_grace_period
+            );
+            // also waits for it to be reaped
+            if let Err(err) = self.sslocal_process.kill() {
+                error!("Cannot kill {}: {}", self_name, err);
+            }
+        }
+
+        // make sure all daemon threads finish
+        for handle in self.daemon_handles.drain(..) {
+            if let Err(err) = handle.join() {
+                warn!("A daemon of {} panicked unexpectedly: {:?}", self_name, err);
+            };
+        }
+    }
+}
+
+impl ActiveSSInstance {
+    /// Start a new instance of `sslocal`.
+    ///
+    /// If `stats_sink` is set and the backend supports it, `sslocal` is asked to report its traffic into it.
+    /// If `verbose`, `sslocal` logs one level more verbosely than the profile's `log_level`.
+    /// If `scope` is set, `sslocal` runs in a new systemd scope unit.
+    fn new(
+        profile: Profile,
+        stats_sink: Option<Arc<RwLock<TrafficStats>>>,
+        verbose: bool,
+        scope: Option<&ScopeConfig>,
+    ) -> io::Result<Self> {
+        let (stdout_stream_tx, stdout_stream_rx) = UnixStream::pair()?;
+        let (stderr_stream_tx, stderr_stream_rx) = UnixStream::pair()?;
+
+        // start instance
+        let stats_sink = stats_sink.filter(|_| profile.supports_stats());
+        let (proc, privileged_stdin, pid_file, stats_collector) = match profile.metadata.backend {
+            #[cfg(feature = "embedded")]
+            Backend::Embedded => {
+                drop(stdout_stream_tx); // only logs are written
+                let client = EmbeddedClient::start(profile.embedded_config()?, stderr_stream_tx, stats_sink)?;
+                (SslocalProcess::Embedded(client), None, None, None)
+            }
+            _ => {
+                // start stats collection
+                let stats_collector = stats_sink.map(StatsCollector::start).transpose()?;
+                let runtime_args = stats_collector
+                    .as_ref()
+                    .map(StatsCollector::sslocal_args)
+                    .unwrap_or_default();
+                let log_level = match verbose {
+                    true => profile.metadata.log_level.bumped(),
+                    false => profile.metadata.log_level,
+                };
+                let (handle, privileged_stdin) = profile.run_sslocal(
+                    runtime_args,
+                    log_level,
+                    scope,
+                    Some(stdout_stream_tx),
+                    Some(stderr_stream_tx),
+                )?;
+                // so that it can be cleaned up if `ssgtk` crashes; not fatal
+                let pid = handle.pids().first().copied().filter(|_| privileged_stdin.is_none());
+                let pid_file = pid.and_then(|pid| {
+                    PidFile::create(pid, &profile.metadata.display_name, profile.metadata.bin_path())
+                        .map_err(|err| warn!("Cannot record the PID of sslocal: {}", err))
+                        .ok()
+                });
+                (
+                    SslocalProcess::External(handle),
+                    privileged_stdin,
+                    pid_file,
+                    stats_collector,
+                )
+            }
+        };
+        let mut instance = Self {
+            profile,
+            sslocal_process: proc.into(),
+            privileged_stdin,
+            pid_file,
+            stdout_brd: Mutex::new(Bus::new(BUS_BUFFER_SIZE)).into(),
+            stderr_brd: Mutex::new(Bus::new(BUS_BUFFER_SIZE)).into(),
+            daemon_handles: vec![],
+            stats_collector,
+            started_at: Instant::now(),
+            restarts: 0,
+            connected_at: Instant::now(),
+            last_error: None,
+            stop_grace_period: STOP_GRACE_PERIOD_DEFAULT,
+        };
+
+        // pipe output
+        instance.pipe_to_broadcast(stdout_stream_rx, OutputKind::Stdout)?;
+        instance.pipe_to_broadcast(stderr_stream_rx, OutputKind::Stderr)?;
+
+        Ok(instance)
+    }
+
+    /// Start a daemon to pipe output from a readable source to a broadcasting channel.
+    fn pipe_to_broadcast<R>(&mut self, source: R, output_kind: OutputKind) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+    {
+        let self_name = self.to_string();
+        let source = BufReader::new(source);
+        let brd = match output_kind {
+            OutputKind::Stdout => Arc::clone(&self.stdout_brd),
+            OutputKind::Stderr => Arc::clone(&self.stderr_brd),
+        };
+        let handle = thread::Builder::new()
+            .name(format!("{} piper daemon for {}", output_kind, self_name))
+            .spawn(move || {
+                trace!("{} piper daemon for {} started", output_kind, self_name);
+                for line_res in source.lines() {
+                    let line = {
+                        let raw = line_res.unwrap_or_else(|err| format!("Error reading {}: {}", &output_kind, err));
+                        // `sslocal` may echo passwords, e.g. in its config
+                        format!("[{}] {}\n", output_kind, redact::scrub(&raw))
+                    };
+                    trace!("Broadcasting: {}", line);
+                    // try to send through channel
+                    if let Err(_) = mutex_lock(&brd).try_broadcast(line) {
+                        warn!(
+                            "{} wrote to {}, but the broadcasting channel is full.",
+                            self_name, output_kind
+                        );
+                    }
+                }
+                // thread exits when the source is closed
+            })?;
+        self.daemon_handles.push(handle);
+        Ok(())
+    }
+
+    /// Convenience function to create a new broadcast listener.
+    fn new_listener(&self, output_kind: OutputKind) -> BusReader<String> {
+        let brd = match output_kind {
+            OutputKind::Stdout => &self.stdout_brd,
+            OutputKind::Stderr => &self.stderr_brd,
+        };
+        mutex_lock(brd).add_rx()
+    }
+
+    /// Starts a monitoring thread that waits for the underlying `sslocal`
+    /// to terminate, when it will emit its `ExitStatus` via the returned channel.
+    fn alert_on_exit(&mut self) -> io::Result<Receiver<ExitStatus>> {
+        let self_name = self.to_string();
+        let proc = Arc::clone(&self.sslocal_process);
+        let (exit_tx, exit_rx) = unbounded_channel();
+        let handle = thread::Builder::new()
+            .name(format!("exit alert daemon for instance {}", self_name))
+            .spawn(move || {
+                let status = proc.wait().unwrap(); // process already running for sure
+                if let Err(err) = exit_tx.send(status) {
+                    warn!("{} exit detected: {}, but the receiver has hung up.", self_name, err.0);
+                }
+            })?;
+        self.daemon_handles.push(handle);
+        Ok(exit_rx)
+    }
+}
+
+/// A daemon that manages profile-switching and restarts.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ProfileManager {
+    /// Attempt to restart `sslocal` up to this limit before
+    /// setting `ProfileManager` to inactive state.
+    /// What to do when a `sslocal` instance fails with a non-0 exit code.
+    ///
+    /// Scenarios in which a restart will not be attempted:
+    /// - Limit reached
+    /// - `sslocal` instance terminated by a signal
+    /// - Various errors which make it impossible for monitoring to continue
+    pub restart_limit: NaiveLeakyBucketConfig,
+    /// How long to wait before each restart attempt,
+    /// and how many consecutive attempts to make before giving up.
+    pub restart_backoff: ExponentialBackoffConfig,
+    /// How long to wait for `sslocal` to exit gracefully when stopping it, before killing it.
+    /// Takes effect when the next instance starts.
+    pub stop_grace_period: Duration,
+    /// Whether to ask `sslocal` to report its traffic.
+    /// Takes effect when the next instance starts.
+    pub collect_stats: bool,
+    /// Whether `sslocal` logs one level more verbosely than the profile's `log_level`.
+    /// Takes effect when the next instance starts.
+    pub verbose: bool,
+    /// The systemd scope to run `sslocal` in, if any.
+    /// Takes effect when the next instance starts.
+    pub scope: Option<ScopeConfig>,
+    /// How `sslocal`'s output is written into log files.
+    /// Use `Self::set_log_file_enabled` to toggle.
+    pub log_file_config: LogFileConfig,
+    events_tx: EventSink,
+    /// Inner value of `None` means `Self` is inactive.
+    active_instance: Arc<RwLock<Option<ActiveSSInstance>>>,
+
+    /// The combined backlog history of `stdout` & `stderr`, bounded to limit memory usage.
+    pub backlog: Arc<Mutex<LineRingBuffer>>,
+    /// The log file of the active profile; `None` if disabled or inactive.
+    log_file: Arc<Mutex<Option<RotatingLogFile>>>,
+    /// A channel that broadcasts the combined logs of `stdout` & `stderr`.
+    #[derivative(Debug(format_with = "crate::util::hacks::omit_bus"))]
+    pub logs_brd: Arc<Mutex<Bus<String>>>,
+    /// The traffic of the active instance; only updated if `Self::collect_stats` is set.
+    pub stats: Arc<RwLock<TrafficStats>>,
+
+    /// The daemon threads that need to be cleanup up when deactivating.
+    daemon_handles: Vec<JoinHandle<()>>,
+}
+
+impl Drop for ProfileManager {
+    /// Halts any active `sslocal` instance when going out of scope.
+    ///
+    /// Also cleans up all daemon threads.
+    fn drop(&mut self) {
+        trace!("ProfileManager is getting dropped");
+
+        // deactivate `sslocal` instance
+        let _ = self.try_stop();
+
+        // make sure all daemon threads finish
+        for handle in self.daemon_handles.drain(..) {
+            if let Err(err) = handle.join() {
+                warn!("A daemon of ProfileManager panicked unexpectedly: {:?}", err);
+            };
+        }
+    }
+}
+
+impl ProfileManager {
+    pub fn new(
+        restart_limit: NaiveLeakyBucketConfig,
+        restart_backoff: ExponentialBackoffConfig,
+        events_tx: EventSink,
+    ) -> Self {
+        Self {
+            restart_limit,
+            restart_backoff,
+            stop_grace_period: STOP_GRACE_PERIOD_DEFAULT,
+            collect_stats: false,
+            verbose: false,
+            scope: None,
+            log_file_config: LogFileConfig::default(),
+            events_tx,
+            active_instance: RwLock::new(None).into(),
+            backlog: Mutex::new(LineRingBufferConfig::default().into()).into(),
+            log_file: Mutex::new(None).into(),
+            logs_brd: Mutex::new(Bus::new(BUS_BUFFER_SIZE)).into(),
+            stats: RwLock::new(TrafficStats::default()).into(),
+            daemon_handles: vec![],
+        }
+    }
+
+    /// Indicate whether a `sslocal` instance is currently running.
+    pub fn is_active(&self) -> bool {
+        util::rwlock_read(&self.active_instance).is_some()
+    }
+
+    /// Get the profile of the currently active instance.
+    pub fn current_profile(&self) -> Option<Profile> {
+        util::rwlock_read(&self.active_instance)
+            .as_ref()
+            .map(|instance| instance.profile.clone())
+    }
+
+    /// Get information about the currently active instance.
+    pub fn instance_status(&self) -> Option<InstanceStatus> {
+        util::rwlock_read(&self.active_instance)
+            .as_ref()
+            .map(|instance| InstanceStatus {
+                pids: instance.sslocal_process.pids(),
+                uptime: instance.started_at.elapsed(),
+                restarts: instance.restarts,
+                connected_for: instance.connected_at.elapsed(),
+                last_error: instance.last_error.clone(),
+                connections: instance.sslocal_process.connections(),
+            })
+    }
+
+    /// Start a `sslocal` instance with a new profile, replacing the old one if necessary.
+    ///
+    /// Returns `Ok(())` if and only if the new instance starts successfully and the old one is cleaned up.
+    ///
+    /// If the new instance fails to start, this `ProfileManager` will be left in deactivated state.
+    /// This includes when its local port is already in use, which is reported as `ErrorKind::AddrInUse`.
+    pub fn switch_to(&mut self, profile: Profile) -> io::Result<()> {
+        // deactivate the old instance
+        let _ = self.try_stop();
+
+        // `sslocal` would fail, but not tell us why clearly
+        if let Some((ip, port)) = profile.config().local_addr() {
+            port_check::ensure_free(SocketAddr::new(ip, port))?;
+        }
+        if let Some(addr) = profile.config().http_local_addr() {
+            port_check::ensure_free(addr)?;
+        }
+
+        // a failed `pre_start` hook may have left things unprepared for `sslocal`
+        hooks::run(&profile, HookKind::PreStart)?;
+
+        // open log file; not fatal
+        self.open_log_file(&profile.metadata.display_name);
+
+        // activate the new instance
+        let mut new_instance =
+            ActiveSSInstance::new(profile.clone(), self.stats_sink(), self.verbose, self.scope.as_ref())?;
+        new_instance.stop_grace_period = self.stop_grace_period;
+
+        // monitor for failure
+        let exit_alert_rx = new_instance.alert_on_exit()?;
+
+        // set
+        *util::rwlock_write(&self.active_instance) = Some(new_instance);
+
+        // pipe output
+        self.log_piping_setup(OutputKind::Stdout)?;
+        self.log_piping_setup(OutputKind::Stderr)?;
+
+        // monitor
+        self.handle_fail(exit_alert_rx)?;
+
+        hooks::spawn(&profile, HookKind::PostStart);
+
+        Ok(())
+    }
+
+    /// The sink for traffic reports to pass to a new instance, if enabled.
+    fn stats_sink(&self) -> Option<Arc<RwLock<TrafficStats>>> {
+        self.collect_stats.then(|| Arc::clone(&self.stats))
+    }
+
+    /// Open the log file of a profile if enabled, replacing the previous one.
+    fn open_log_file(&mut self, profile_name: &str) {
+        let log_file = self
+            .log_file_config
+            .enabled
+            .then(|| RotatingLogFile::open(log_file::log_file_path(profile_name), self.log_file_config))
+            .and_then(|res| res.map_err(|err| error!("Cannot open log file: {}", err)).ok());
+        *mutex_lock(&self.log_file) = log_file;
+    }
+
+    /// Enable or disable writing `sslocal`'s output into log files.
+    ///
+    /// Takes effect immediately, without restarting `sslocal`.
+    pub fn set_log_file_enabled(&mut self, enable: bool) {
+        self.log_file_config.enabled = enable;
+        match self.current_profile() {
+            Some(profile) => self.open_log_file(&profile.metadata.display_name),
+            None => *mutex_lock(&self.log_file) = None,
+        }
+    }
+
+    /// Convenience function to create a new broadcast listener.
+    pub fn new_listener(&self) -> BusReader<String> {
+        mutex_lock(&self.logs_brd).add_rx()
+    }
+
+    /// Stop the `sslocal` instance if active.
+    ///
+    /// Returns `Err(())` if already inactive.
+    ///
+    /// The `pre_stop` and `post_stop` hooks of its profile are run around it; failures are only logged.
+    #[allow(clippy::result_unit_err)]
+    pub fn try_stop(&mut self) -> Result<(), ()> {
+        let profile = self.current_profile();
+        if let Some(profile) = &profile {
+            if let Err(err) = hooks::run(profile, HookKind::PreStop) {
+                warn!("Profile \"{}\": {}", profile.metadata.display_name, err);
+            }
+        }
+        let instance = util::rwlock_write(&self.active_instance).take();
+        let res = instance.map(drop).ok_or(());
+        // close log file after `sslocal` has exited, so that its last words are kept
+        *mutex_lock(&self.log_file) = None;
+        if let Some(profile) = &profile {
+            hooks::spawn(profile, HookKind::PostStop);
+        }
+        res
+    }
+
+    /// Start a daemon that subscribes to an output broadcast of
+    /// the underlying `sslocal` instance, then re-broadcasts the logs
+    /// and appends them to the backlog.
+    fn log_piping_setup(&mut self, output_kind: OutputKind) -> io::Result<()> {
+        let instance_opt = rwlock_read(&self.active_instance);
+        let instance = instance_opt
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Not active"))?;
+        let re_brd = Arc::clone(&self.logs_brd);
+        let backlog = Arc::clone(&self.backlog);
+        let log_file = Arc::clone(&self.log_file);
+        let events_tx = self.events_tx.clone();
+
+        // create thread
+        let handle = log_piping_setup_impl(&instance, output_kind, re_brd, backlog, log_file, events_tx)?;
+        self.daemon_handles.push(handle);
+
+        Ok(())
+    }
+
+    /// Starts a monitoring thread that waits for the underlying `sslocal` instance
+    /// to fail, when it will attempt to perform a restart as specified by
+    /// `Self::restart_limit` and `Self::restart_backoff`.
+    fn handle_fail(&mut self, listener: Receiver<ExitStatus>) -> io::Result<()> {
+        // variables that need to be moved into thread
+        let restart_limit = self.restart_limit;
+        let restart_backoff = self.restart_backoff;
+        let stats_sink = self.stats_sink();
+        let verbose = self.verbose;
+        let scope = self.scope.clone();
+        let stop_grace_period = self.stop_grace_period;
+        let events_tx = self.events_tx.clone();
+        let instance = Arc::clone(&self.active_instance);
+        let profile = self
+            .current_profile()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Not active"))?;
+        let logs_brd = Arc::clone(&self.logs_brd);
+        let backlog = Arc::clone(&self.backlog);
+        let log_file = Arc::clone(&self.log_file);
+
+        // create thread
+        let handle = thread::Builder::new()
+            .name("ProfileManager failure monitor daemon".into())
+            .spawn(move || {
+                // profile stays the same across restarts, therefore outside of loop
+                let profile_name = profile.metadata.display_name.clone();
+                let mut exit_listener = listener; // is set to new listener in every iteration
+                let mut restart_counter: NaiveLeakyBucket = restart_limit.into();
+                let mut backoff: ExponentialBackoff = restart_backoff.into();
+                let mut started_at = Instant::now(); // is reset on every restart
+                let mut restarts = 0; // for reporting only
+                let connected_at = rwlock_read(&instance)
+                    .as_ref()
+                    .map_or_else(Instant::now, |inst| inst.connected_at);
+                let mut superseded = false; // set if another instance has taken over
+
+                // restart loop can exit for a variety of reasons; see code
+                loop {
+                    let instance_name = match &*util::rwlock_read(&instance) {
+                        Some(inst) => inst.to_string(),
+                        None => {
+                            debug!("ProfileManager has been set to inactive; auto-restart stopped");
+                            if let Err(_) = events_tx.send(ManagerEvent::OkStop { instance_name: None }) {
+                                error!("Trying to send OkStop event, but all receivers have hung up.");
+                            }
+                            break;
+                        }
+                    };
+
+                    // wait for `sslocal` instance exit signal
+                    let last_error = match exit_listener.recv() {
+                        Ok(status) if status.success() => {
+                            // most likely because `ActiveInstance` gets dropped
+                            // causing `sslocal` to exit gracefully,
+                            // or if the user calls `sslocal --version` or something
+                            debug!("{} has exited successfully; auto-restart stopped", instance_name);
+                            if let Err(_) = events_tx.send(ManagerEvent::OkStop {
+                                instance_name: Some(instance_name),
+                            }) {
+                                error!("Trying to send OkStop event, but all receivers have hung up.");
+                            }
+                            break;
+                        }
+                        Err(err) => {
+                            // we no longer know the status of `sslocal`, so fail fast
+                            error!(
+                                "The exit alert daemon for {} has hung up: {}; auto-restart stopped",
+                                instance_name, err
+                            );
+                            if let Err(_) = events_tx.send(ManagerEvent::ErrorStop {
+                                instance_name: Some(instance_name),
+                                err: err.to_string(),
+                            }) {
+                                error!("Trying to send ErrorStop event, but all receivers have hung up.");
+                            }
+                            break;
+                        }
+                        Ok(bad_status) => {
+                            // do restart
+                            warn!("{} has failed; restarting", instance_name);
+                            warn!("Exit status: {}", bad_status);
+                            format!("sslocal has exited with {}", bad_status)
+                        }
+                    };
+
+                    // Check if restart counter has overflowed
+                    if let Err(err) = restart_counter.push() {
+                        error!(
+                            "sslocal exits excessively with profile \"{}\"; auto-restart stopped",
+                            profile_name
+                        );
+                        error!("{}", err);
+                        if let Err(_) = events_tx.send(ManagerEvent::ErrorStop {
+                            instance_name: Some(instance_name),
+                            err: err.to_string(),
+                        }) {
+                            error!("Trying to send ErrorStop event, but all receivers have hung up.");
+                        }
+                        break;
+                    }
+
+                    // Check if backoff is exhausted
+                    if started_at.elapsed() >= BACKOFF_RESET_AFTER {
+                        backoff.reset();
+                    }
+                    let delay = match backoff.next_delay() {
+                        Ok(d) => d,
+                        Err(err) => {
+                            error!(
+                                "sslocal keeps failing with profile \"{}\"; auto-restart stopped",
+                                profile_name
+                            );
+                            error!("{}", err);
+                            if events_tx
+                                .send(ManagerEvent::ErrorStop {
+                                    instance_name: Some(instance_name),
+                                    err: err.to_string(),
+                                })
+                                .is_err()
+                            {
+                                error!("Trying to send ErrorStop event, but all receivers have hung up.");
+                            }
+                            break;
+                        }
+                    };
+
+                    // Wait before restarting
+                    info!(
+                        "Restarting profile \"{}\" in {:?} (attempt {})",
+                        profile_name,
+                        delay,
+                        backoff.retries()
+                    );
+                    let scheduled = ManagerEvent::RestartScheduled {
+                        profile_name: profile_name.clone(),
+                        attempt: backoff.retries(),
+                        delay,
+                    };
+                    if events_tx.send(scheduled).is_err() {
+                        error!("Trying to send RestartScheduled event, but all receivers have hung up.");
+                    }
+                    if !wait_while_current(&instance, &instance_name, delay) {
+                        debug!(
+                            "{} has been stopped or replaced; pending restart cancelled",
+                            instance_name
+                        );
+                        superseded = rwlock_read(&instance).is_some();
+                        if !superseded && events_tx.send(ManagerEvent::OkStop { instance_name: None }).is_err() {
+                            error!("Trying to send OkStop event, but all receivers have hung up.");
+                        }
+                        break;
+                    }
+
+                    // Restart
+                    /// Temporary helper builder function to simplify error handling.
+                    fn start_pipe_alert(
+                        mut instance: ActiveSSInstance,
+                        re_brd: Arc<Mutex<Bus<String>>>,
+                        backlog: Arc<Mutex<LineRingBuffer>>,
+                        log_file: Arc<Mutex<Option<RotatingLogFile>>>,
+                        events_tx: EventSink,
+                        exit_listener: &mut Receiver<ExitStatus>,
+                    ) -> io::Result<ActiveSSInstance> {
+                        log_piping_setup_impl(
+                            &instance,
+                            OutputKind::Stdout,
+                            Arc::clone(&re_brd),
+                            Arc::clone(&backlog),
+                            Arc::clone(&log_file),
+                            events_tx.clone(),
+                        )?;
+                        log_piping_setup_impl(&instance, OutputKind::Stderr, re_brd, backlog, log_file, events_tx)?;
+                        *exit_listener = instance.alert_on_exit()?;
+                        Ok(instance)
+                    }
+
+                    let mut new_instance = {
+                        let start_res =
+                            ActiveSSInstance::new(profile.clone(), stats_sink.clone(), verbose, scope.as_ref())
+                                .and_then(|instance| {
+                                    start_pipe_alert(
+                                        instance,
+                                        Arc::clone(&logs_brd),
+                                        Arc::clone(&backlog),
+                                        Arc::clone(&log_file),
+                                        events_tx.clone(),
+                                        &mut exit_listener,
+                                    )
+                                });
+                        match start_res {
+                            Ok(p) => p,
+                            Err(err) => {
+                                error!(
+                                    "Failed to restart with profile \"{}\": {}. Failure monitor daemon stopping",
+                                    profile_name, err
+                                );
+                                if let Err(_) = events_tx.send(ManagerEvent::ErrorStop {
+                                    instance_name: Some(instance_name),
+                                    err: err.to_string(),
+                                }) {
+                                    error!("Trying to send ErrorStop event, but all receivers have hung up.");
+                                }
+                                break;
+                            }
+                        }
+                    };
+
+                    // Set new active instance
+                    restarts += 1;
+                    new_instance.restarts = restarts;
+                    new_instance.connected_at = connected_at;
+                    new_instance.last_error = Some(last_error);
+                    new_instance.stop_grace_period = stop_grace_period;
+                    *util::rwlock_write(&instance) = Some(new_instance);
+                    started_at = Instant::now();
+                }
+                // loop exit means we should leave ProfileManager inactive,
+                // unless another instance has taken over in the meantime
+                if !superseded {
+                    drop(util::rwlock_write(&instance).take());
+                }
+            })?;
+        self.daemon_handles.push(handle);
+
+        Ok(())
+    }
+}
+
+/// Wait up to `timeout` for `proc` to exit, returning whether it has.
+fn wait_for_exit(proc: &SslocalProcess, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match proc.try_wait() {
+            Ok(Some(_)) => return true,
+            Ok(None) => {}
+            Err(err) => {
+                // the status is no longer known; it has exited for sure
+                trace!("Cannot get the status of sslocal: {}", err);
+                return true;
+            }
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        thread::sleep(STOP_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Sleep for `delay`, waking up periodically to check whether the instance
+/// named `instance_name` is still the active one.
+///
+/// Returns `false` early if it has been stopped or replaced in the meantime.
+fn wait_while_current(instance: &RwLock<Option<ActiveSSInstance>>, instance_name: &str, delay: Duration) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        let is_current = matches!(&*rwlock_read(instance), Some(inst) if inst.to_string() == instance_name);
+        if !is_current {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep(BACKOFF_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// This is not an associated function because it has to be called by
+/// threads created by `ProfileManager::handle_fail`.
+///
+/// Also sends a `ManagerEvent::SslocalError` for each error logged by `sslocal`.
+fn log_piping_setup_impl(
+    instance: &ActiveSSInstance,
+    output_kind: OutputKind,
+    re_brd: Arc<Mutex<Bus<String>>>,
+    backlog: Arc<Mutex<LineRingBuffer>>,
+    log_file: Arc<Mutex<Option<RotatingLogFile>>>,
+    events_tx: EventSink,
+) -> io::Result<JoinHandle<()>> {
+    // variables that need to be moved into thread
+    let instance_name = instance.to_string();
+    let profile_name = instance.profile.metadata.display_name.clone();
+    let mut listener = instance.new_listener(output_kind);
+    // create thread
+    thread::Builder::new()
+        .name(format!("{} log porter daemon for {}", output_kind, instance_name))
+        .spawn(move || {
+            trace!("{} log porter daemon for {} started", output_kind, instance_name);
+            for line in listener.iter() {
+                // write to log file; stop writing on error to avoid flooding
+                let mut log_file_opt = mutex_lock(&log_file);
+                if let Some(Err(err)) = log_file_opt.as_mut().map(|f| f.write(&line)) {
+                    error!("Cannot write to log file; disabling: {}", err);
+                    *log_file_opt = None;
+                }
+                drop(log_file_opt);
+                if let Some(LogEvent {
+                    level: Level::Error,
+                    kind,
+                    ..
+                }) = log_parser::parse_line(&line)
+                {
+                    let event = ManagerEvent::SslocalError {
+                        profile_name: profile_name.clone(),
+                        line: log_parser::strip_ansi(line.trim_end()).into_owned(),
+                        kind,
+                    };
+                    if events_tx.send(event).is_err() {
+                        error!("Trying to send SslocalError event, but all receivers have hung up.");
+                    }
+                }
+                // append to backlog
+                mutex_lock(&backlog).push(line.clone());
+                // rebroadcast, moving `line` to avoid another clone
+                mutex_lock(&re_brd).broadcast(line);
+            }
+            // thread exits when broadcast stops
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        thread::{self, sleep},
+        time::Duration,
+    };
+
+    use crossbeam_channel::unbounded as unbounded_channel;
+    use std::path::Path;
+
+    use log::{debug, LevelFilter};
+    use simplelog::{Config, SimpleLogger};
+
+    use super::*;
+    use crate::io::profile_loader::ProfileFolder;
+
+    /// This test will always pass. You need to examine the outputs manually.
+    ///
+    /// `cargo test example_profiles_test_run -- --nocapture`
+    #[test]
+    fn example_profiles_test_run() {
+        SimpleLogger::init(LevelFilter::Trace, Config::default()).unwrap();
+
+        // parse example configs
+        let eg_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../example-profiles");
+        let eg_configs = ProfileFolder::from_path_recurse(eg_dir, &Default::default(), &mut vec![]).unwrap();
+        let profile_list = eg_configs.get_profiles();
+        debug!("Loaded {} profiles.", profile_list.len());
+
+        // setup ProfileManager
+        let restart_limit = NaiveLeakyBucketConfig::new(3, Duration::from_secs(10));
+        let (events_tx, _) = unbounded_channel::<ManagerEvent>();
+        let mut mgr = ProfileManager::new(restart_limit, Default::default(), EventSink::new(events_tx));
+
+        // run through all example profiles
+        for p in profile_list {
+            println!();
+            mgr.switch_to(p.clone()).unwrap();
+            let (mut stdout_listener, mut stderr_listener) = {
+                let instance_opt = rwlock_read(&mgr.active_instance);
+                let instance = instance_opt.as_ref().unwrap();
+                (
+                    instance.new_listener(OutputKind::Stdout),
+                    instance.new_listener(OutputKind::Stderr),
+                )
+            };
+            thread::spawn(move || stdout_listener.iter().for_each(|s| println!("stdout: {}", s)));
+            thread::spawn(move || stderr_listener.iter().for_each(|s| println!("stderr: {}", s)));
+            sleep(Duration::from_millis(3000));
+        }
+        let _ = mgr.try_stop();
+    }
+}
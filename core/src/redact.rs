@@ -0,0 +1,91 @@
+//! This module contains code that keeps passwords out of logs, notifications,
+//! error messages and `sslocal`'s output.
+//!
+//! Passwords are registered as profiles are loaded and keyring entries are looked up,
+//! after which every occurrence of them is masked.
+
+use std::{borrow::Cow, cmp::Reverse, collections::BTreeSet, sync::RwLock};
+
+use crate::util;
+use lazy_static::lazy_static;
+use log::{Log, Metadata, Record};
+
+/// Shown in place of passwords, e.g. in `crate::io::profile_loader::LaunchPreview`.
+pub const PASSWORD_MASK: &str = "********";
+
+/// Shorter secrets are not masked, since they would also mask ordinary words.
+const SECRET_LEN_MIN: usize = 4;
+
+lazy_static! {
+    /// Secrets to mask, longest first so that a secret containing another is masked whole.
+    static ref SECRETS: RwLock<BTreeSet<(Reverse<usize>, String)>> = RwLock::new(BTreeSet::new());
+}
+
+/// Mask `secret` wherever it appears from now on.
+pub fn register(secret: &str) {
+    if secret.chars().count() < SECRET_LEN_MIN || secret == PASSWORD_MASK {
+        return;
+    }
+    let entry = (Reverse(secret.len()), secret.to_string());
+    if !util::rwlock_read(&SECRETS).contains(&entry) {
+        util::rwlock_write(&SECRETS).insert(entry);
+    }
+}
+
+/// Replace every registered secret in `text` with `PASSWORD_MASK`.
+pub fn scrub(text: &str) -> Cow<'_, str> {
+    let secrets = util::rwlock_read(&SECRETS);
+    let mut text = Cow::Borrowed(text);
+    for (_, secret) in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), PASSWORD_MASK));
+        }
+    }
+    text
+}
+
+/// A logger that scrubs every message before passing it on to the inner logger.
+pub struct RedactingLogger<L>(pub L);
+
+impl<L: Log> Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let msg = record.args().to_string();
+        self.0.log(
+            &Record::builder()
+                .args(format_args!("{}", scrub(&msg)))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{register, scrub, PASSWORD_MASK};
+
+    #[test]
+    fn scrub_registered() {
+        register("redact-test-secret");
+        register("redact-test-secret-longer");
+        register("abc"); // too short
+        assert_eq!(
+            scrub("--password redact-test-secret-longer -k redact-test-secret"),
+            format!("--password {} -k {}", PASSWORD_MASK, PASSWORD_MASK)
+        );
+        assert_eq!(scrub("abc"), "abc");
+    }
+}
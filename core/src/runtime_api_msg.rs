@@ -0,0 +1,502 @@
+//! This module defines the messages passed to and from the
+//! runtime API, enabled behind the "runtime-api" feature.
+
+use std::{collections::BTreeMap, fmt};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::notify_method::NotifyMethod;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum APICommand {
+    // GUI
+    LogViewerShow,
+    LogViewerHide,
+    SetNotify(NotifyMethod),
+
+    // core
+    Restart,
+    SwitchProfile(String),
+    /// Switch to a profile by its display name, or else by its 1-based index in `ListProfiles` order.
+    Connect(String),
+    Stop,
+    Quit,
+
+    // queries; only useful with JSON-RPC, which can return a result
+    GetStatus,
+    GetBacklog(usize),
+    /// Like `GetBacklog`, then keep streaming new lines; see `jsonrpc::BACKLOG_LINE_METHOD`.
+    FollowBacklog(usize),
+    ListProfiles,
+    /// Get what a profile would be launched with, selected like `Connect`.
+    GetLaunchCommand(String),
+    /// Like `GetStatus`, then keep streaming changes of state; see `jsonrpc::STATE_EVENT_METHOD`.
+    Subscribe,
+}
+
+impl fmt::Display for APICommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use APICommand::*;
+        let msg = match self {
+            LogViewerShow => "Show log viewer".into(),
+            LogViewerHide => "Hide log viewer".into(),
+            SetNotify(method) => format!("Set notification method to {}", method),
+
+            Restart => "Restart current profile".into(),
+            SwitchProfile(name) => format!("Switch Profile to {}", name),
+            Connect(selector) => format!("Connect to profile {}", selector),
+            Stop => "Stop current profile".into(),
+            Quit => "Quit application".into(),
+
+            GetStatus => "Get status".into(),
+            GetBacklog(lines) => format!("Get last {} lines of backlog", lines),
+            FollowBacklog(lines) => format!("Follow backlog from last {} lines", lines),
+            ListProfiles => "List profiles".into(),
+            GetLaunchCommand(selector) => format!("Get launch command of profile {}", selector),
+            Subscribe => "Subscribe to changes of state".into(),
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+/// The kind of an `APICommand`, without its arguments.
+///
+/// Used to restrict which commands are accepted over TCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[clap(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum APICommandKind {
+    LogViewerShow,
+    LogViewerHide,
+    SetNotify,
+    Restart,
+    SwitchProfile,
+    Connect,
+    Stop,
+    Quit,
+    GetStatus,
+    GetBacklog,
+    FollowBacklog,
+    ListProfiles,
+    GetLaunchCommand,
+    Subscribe,
+}
+
+impl APICommand {
+    pub fn kind(&self) -> APICommandKind {
+        use APICommand::*;
+        match self {
+            LogViewerShow => APICommandKind::LogViewerShow,
+            LogViewerHide => APICommandKind::LogViewerHide,
+            SetNotify(_) => APICommandKind::SetNotify,
+            Restart => APICommandKind::Restart,
+            SwitchProfile(_) => APICommandKind::SwitchProfile,
+            Connect(_) => APICommandKind::Connect,
+            Stop => APICommandKind::Stop,
+            Quit => APICommandKind::Quit,
+            GetStatus => APICommandKind::GetStatus,
+            GetBacklog(_) => APICommandKind::GetBacklog,
+            FollowBacklog(_) => APICommandKind::FollowBacklog,
+            ListProfiles => APICommandKind::ListProfiles,
+            GetLaunchCommand(_) => APICommandKind::GetLaunchCommand,
+            Subscribe => APICommandKind::Subscribe,
+        }
+    }
+}
+
+/// The result of `APICommand::GetStatus`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct APIStatus {
+    /// Whether an `sslocal` instance is running.
+    pub running: bool,
+    /// The display name of the current profile, if any.
+    pub profile: Option<String>,
+    /// Traffic statistics of the running instance, if being collected.
+    pub stats: Option<APITrafficStats>,
+    /// The PID of the running `sslocal` (or of the privileged helper running it).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Seconds since the running instance has started, which is reset on automatic restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uptime_secs: Option<u64>,
+    /// How many times the current profile has been restarted automatically.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restarts: Option<u32>,
+    /// Seconds since the current profile has been connected to, which is kept across automatic restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connected_secs: Option<u64>,
+    /// Why `sslocal` last failed with the current profile, if it has.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// How many connections are open, if known, i.e. if the client runs inside `ssgtk`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<usize>,
+    /// The display names of the profiles running alongside the current one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alongside: Vec<String>,
+    /// Whether this is `ssgtk --service`, which has no GUI of its own.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub headless: bool,
+}
+
+/// Traffic statistics, as returned in `APIStatus`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct APITrafficStats {
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+    /// Bytes per second.
+    pub tx_rate: f64,
+    /// Bytes per second.
+    pub rx_rate: f64,
+}
+
+/// A profile or a group of profiles, as returned by `APICommand::ListProfiles`
+/// in the same order as the tray menu.
+///
+/// Profiles are numbered from 1 in depth-first order, for `APICommand::Connect`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum APIProfileTree {
+    Group {
+        group: String,
+        content: Vec<APIProfileTree>,
+    },
+    Profile {
+        profile: String,
+        /// Whether this is the profile currently running.
+        active: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+    },
+}
+
+/// The result of `APICommand::GetLaunchCommand`, with passwords masked.
+///
+/// No process is launched, and no generated config file is written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct APILaunchCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// The working directory of `sslocal`.
+    pub pwd: String,
+    /// The environment variables inherited from `ssgtk`, or none if run as root.
+    pub env: BTreeMap<String, String>,
+    /// Whether it is run as root via `pkexec`.
+    pub as_root: bool,
+    /// The program and arguments as a shell command.
+    pub shell_command: String,
+}
+
+/// A change of state, streamed after `APICommand::Subscribe`,
+/// e.g. `{"event": "connected", "profile": "Example Profile"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum APIStateEvent {
+    /// A profile has been connected to.
+    Connected { profile: String },
+    /// `sslocal` has stopped, and will not be restarted.
+    Disconnected,
+    /// `sslocal` has failed, and will be restarted.
+    Restarting { profile: String, attempt: usize },
+    /// `sslocal` has failed for good, or has logged an error.
+    Error {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<String>,
+        message: String,
+    },
+}
+
+/// JSON-RPC 2.0 messages, which wrap `APICommand`s with structured responses.
+///
+/// The method name is the kebab-case name of the command,
+/// and its argument (if any) is the only element of `params`, e.g.
+/// `{"jsonrpc": "2.0", "method": "switch-profile", "params": ["Example Profile"], "id": 1}`.
+///
+/// See https://www.jsonrpc.org/specification.
+pub mod jsonrpc {
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    use super::{APICommand, APICommandKind};
+
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// The command was understood, but has failed.
+    pub const COMMAND_FAILED: i64 = -32000;
+    /// The command is not allowed on this connection.
+    pub const FORBIDDEN: i64 = -32001;
+
+    /// After responding to `follow-backlog`, the server sends each new line of `sslocal` output
+    /// as a notification of this method, with the line as the only element of `params`.
+    pub const BACKLOG_LINE_METHOD: &str = "backlog-line";
+
+    /// After responding to `subscribe`, the server sends each change of state
+    /// as a notification of this method, with an `APIStateEvent` as the only element of `params`.
+    pub const STATE_EVENT_METHOD: &str = "state-event";
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Request {
+        pub jsonrpc: String,
+        pub method: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub params: Option<Value>,
+        /// `None` for notifications, which get no response.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub id: Option<Value>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Response {
+        pub jsonrpc: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub result: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub error: Option<ErrorObject>,
+        pub id: Value,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ErrorObject {
+        pub code: i64,
+        pub message: String,
+    }
+
+    impl ErrorObject {
+        pub fn new(code: i64, message: impl Into<String>) -> Self {
+            Self {
+                code,
+                message: message.into(),
+            }
+        }
+    }
+
+    impl Request {
+        /// Wrap a command in a request.
+        pub fn new(cmd: &APICommand, id: impl Into<Value>) -> Self {
+            let (method, params) = match serde_json::to_value(cmd).expect("serialising APICommand is infallible") {
+                Value::String(method) => (method, None),
+                Value::Object(map) => {
+                    let (method, param) = map.into_iter().next().expect("a variant with data has one key");
+                    (method, Some(json!([param])))
+                }
+                other => unreachable!("APICommand serialised to {}", other),
+            };
+            Self {
+                jsonrpc: "2.0".into(),
+                method,
+                params,
+                id: Some(id.into()),
+            }
+        }
+
+        /// Create a notification, which expects no response.
+        pub fn notification(method: impl Into<String>, params: Value) -> Self {
+            Self {
+                jsonrpc: "2.0".into(),
+                method: method.into(),
+                params: Some(params),
+                id: None,
+            }
+        }
+
+        /// Unwrap the command in this request.
+        pub fn to_command(&self) -> Result<APICommand, ErrorObject> {
+            if self.jsonrpc != "2.0" {
+                return Err(ErrorObject::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+            }
+            let known = serde_json::from_value::<APICommandKind>(Value::String(self.method.clone())).is_ok();
+            if !known {
+                return Err(ErrorObject::new(
+                    METHOD_NOT_FOUND,
+                    format!("unknown method \"{}\"", self.method),
+                ));
+            }
+            let with_param =
+                |param: &Value| Value::Object([(self.method.clone(), param.clone())].into_iter().collect());
+            let value = match &self.params {
+                None | Some(Value::Null) => Value::String(self.method.clone()),
+                Some(Value::Array(params)) if params.is_empty() => Value::String(self.method.clone()),
+                Some(Value::Array(params)) if params.len() == 1 => with_param(&params[0]),
+                Some(params) => with_param(params),
+            };
+            serde_json::from_value(value).map_err(|err| ErrorObject::new(INVALID_PARAMS, err.to_string()))
+        }
+    }
+
+    impl Response {
+        pub fn new(id: Value, res: Result<Value, ErrorObject>) -> Self {
+            let (result, error) = match res {
+                Ok(v) => (Some(v), None),
+                Err(e) => (None, Some(e)),
+            };
+            Self {
+                jsonrpc: "2.0".into(),
+                result,
+                error,
+                id,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::runtime_api_msg::APIStateEvent;
+
+        #[test]
+        fn command_round_trip() {
+            let cmds = [
+                APICommand::Restart,
+                APICommand::SwitchProfile("Example Profile".into()),
+                APICommand::GetBacklog(10),
+                APICommand::Subscribe,
+                APICommand::ListProfiles,
+            ];
+            for cmd in cmds {
+                let req = Request::new(&cmd, 1);
+                assert_eq!(req.to_command().unwrap().kind(), cmd.kind());
+            }
+        }
+
+        #[test]
+        fn state_event_notification() {
+            let event = APIStateEvent::Restarting {
+                profile: "Foo".into(),
+                attempt: 2,
+            };
+            let notification = Request::notification(STATE_EVENT_METHOD, json!([event]));
+            assert_eq!(
+                serde_json::to_string(&notification).unwrap(),
+                r#"{"jsonrpc":"2.0","method":"state-event","params":[{"attempt":2,"event":"restarting","profile":"Foo"}]}"#
+            );
+            let event = APIStateEvent::Error {
+                profile: None,
+                message: "oops".into(),
+            };
+            assert_eq!(
+                serde_json::to_string(&event).unwrap(),
+                r#"{"event":"error","message":"oops"}"#
+            );
+        }
+
+        #[test]
+        fn bad_requests() {
+            let parse = |s: &str| serde_json::from_str::<Request>(s).unwrap().to_command();
+            let code = |s: &str| parse(s).unwrap_err().code;
+            assert!(matches!(
+                parse(r#"{"jsonrpc": "2.0", "method": "switch-profile", "params": ["Foo"], "id": 1}"#),
+                Ok(APICommand::SwitchProfile(name)) if name == "Foo"
+            ));
+            assert_eq!(
+                code(r#"{"jsonrpc": "1.0", "method": "stop", "id": 1}"#),
+                INVALID_REQUEST
+            );
+            assert_eq!(
+                code(r#"{"jsonrpc": "2.0", "method": "fly", "id": 1}"#),
+                METHOD_NOT_FOUND
+            );
+            assert_eq!(
+                code(r#"{"jsonrpc": "2.0", "method": "switch-profile", "id": 1}"#),
+                INVALID_PARAMS
+            );
+        }
+    }
+}
+
+/// The TCP variant of the runtime API is a line-based protocol:
+///
+/// 1. The server greets with `<TCP_GREETING> <nonce>`.
+/// 2. The client responds with `tcp_auth_response(token, nonce)`.
+/// 3. The server replies `TCP_REPLY_OK`, or `TCP_REPLY_DENIED` and disconnects.
+/// 4. The client sends a single JSON-RPC request (see `jsonrpc`).
+/// 5. The server sends the JSON-RPC response, with `jsonrpc::FORBIDDEN` if the command is not allowed.
+///
+/// The token itself never goes over the wire, but the commands are not encrypted.
+pub mod tcp {
+    use std::{fmt::Write, io};
+
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use super::APICommandKind;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    /// Sent by the server upon connection, followed by a space and the nonce.
+    pub const TCP_GREETING: &str = "ssgtk-api-v1";
+    pub const TCP_REPLY_OK: &str = "ok";
+    pub const TCP_REPLY_DENIED: &str = "denied";
+
+    /// The commands accepted over TCP, unless overridden.
+    ///
+    /// Commands that only make sense locally, or that cannot be undone remotely, are excluded.
+    pub const TCP_ALLOWLIST_DEFAULT: [APICommandKind; 4] = [
+        APICommandKind::Restart,
+        APICommandKind::SwitchProfile,
+        APICommandKind::Connect,
+        APICommandKind::Stop,
+    ];
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().fold(String::new(), |mut s, b| {
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+    }
+
+    fn from_hex(s: &str) -> Option<Vec<u8>> {
+        if !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return None;
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+            .collect()
+    }
+
+    /// Generate a random nonce for a new connection.
+    pub fn generate_nonce() -> io::Result<String> {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).map_err(io::Error::other)?;
+        Ok(to_hex(&bytes))
+    }
+
+    fn hmac(token: &str, nonce: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(token.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(nonce.as_bytes());
+        mac
+    }
+
+    /// The response the client should send to prove it knows `token`.
+    pub fn tcp_auth_response(token: &str, nonce: &str) -> String {
+        to_hex(&hmac(token, nonce).finalize().into_bytes())
+    }
+
+    /// Check the client's response in constant time.
+    pub fn tcp_auth_verify(token: &str, nonce: &str, response: &str) -> bool {
+        match from_hex(response.trim()) {
+            Some(bytes) => hmac(token, nonce).verify_slice(&bytes).is_ok(),
+            None => false,
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn auth_round_trip() {
+            let nonce = generate_nonce().unwrap();
+            let response = tcp_auth_response("secret", &nonce);
+            assert!(tcp_auth_verify("secret", &nonce, &response));
+            assert!(!tcp_auth_verify("wrong", &nonce, &response));
+            assert!(!tcp_auth_verify("secret", "other nonce", &response));
+            assert!(!tcp_auth_verify("secret", &nonce, "not hex"));
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! This module contains code that collects traffic statistics from `sslocal`.
+//!
+//! `sslocal` reports its cumulative traffic by connecting to the unix socket
+//! specified by `--stat-path` every 500ms, and writing two native-endian `u64`s:
+//! bytes sent, followed by bytes received.
+//!
+//! Note that `sslocal` only supports this if it is built with the `local-flow-stat` feature.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    os::unix::net::UnixListener,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{consts::*, util};
+use log::{debug, trace, warn};
+
+/// How often the collector daemon checks for new reports, or whether it should halt.
+const COLLECTOR_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for a connected `sslocal` to finish writing a report.
+const REPORT_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Used to generate a unique socket name for each collector.
+static COLLECTOR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A snapshot of the traffic of the active `sslocal` instance.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrafficStats {
+    /// Total bytes sent since `sslocal` started.
+    pub tx_bytes: u64,
+    /// Total bytes received since `sslocal` started.
+    pub rx_bytes: u64,
+    /// Bytes sent per second, as of the latest report.
+    pub tx_rate: f64,
+    /// Bytes received per second, as of the latest report.
+    pub rx_rate: f64,
+    /// When the latest report was received; `None` if no report has been received.
+    pub updated_at: Option<Instant>,
+}
+
+impl TrafficStats {
+    /// Record a new report of cumulative counters, updating the rates.
+    pub fn record(&mut self, tx_bytes: u64, rx_bytes: u64) {
+        let now = Instant::now();
+        if let Some(prev) = self.updated_at {
+            let secs = now.saturating_duration_since(prev).as_secs_f64();
+            if secs > 0.0 {
+                // counters restart from 0 if `sslocal` restarts
+                self.tx_rate = tx_bytes.saturating_sub(self.tx_bytes) as f64 / secs;
+                self.rx_rate = rx_bytes.saturating_sub(self.rx_bytes) as f64 / secs;
+            }
+        }
+        self.tx_bytes = tx_bytes;
+        self.rx_bytes = rx_bytes;
+        self.updated_at = Some(now);
+    }
+
+    /// A short one-line summary of the current throughput.
+    pub fn rate_summary(&self) -> String {
+        format!(
+            "↑ {}/s ↓ {}/s",
+            format_bytes(self.tx_rate as u64),
+            format_bytes(self.rx_rate as u64)
+        )
+    }
+}
+
+/// Format a number of bytes in human-readable binary units.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Listens on a unix socket for traffic reports from a single `sslocal` instance,
+/// and writes them into a shared `TrafficStats`.
+///
+/// The shared stats are reset and the socket file is removed when dropped.
+#[derive(Debug)]
+pub struct StatsCollector {
+    socket_path: PathBuf,
+    sink: Arc<RwLock<TrafficStats>>,
+    halt: Arc<AtomicBool>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for StatsCollector {
+    fn drop(&mut self) {
+        trace!("StatsCollector for {:?} is getting dropped", self.socket_path);
+
+        // notify daemon halt
+        self.halt.store(true, Ordering::Relaxed);
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Stats collector daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+
+        // cleanup
+        if let Err(err) = fs::remove_file(&self.socket_path) {
+            warn!("Cannot remove stats socket {:?}: {}", self.socket_path, err);
+        }
+        *util::rwlock_write(&self.sink) = TrafficStats::default();
+    }
+}
+
+impl StatsCollector {
+    /// Start listening on a new socket in the XDG runtime directory.
+    pub fn start(sink: Arc<RwLock<TrafficStats>>) -> io::Result<Self> {
+        let socket_name = format!(
+            "stat-{}-{}.sock",
+            process::id(),
+            COLLECTOR_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let socket_path = XDG_DIRS.place_runtime_file(socket_name)?;
+        let _ = fs::remove_file(&socket_path); // in case of stale socket
+        let listener = UnixListener::bind(&socket_path)?;
+        listener.set_nonblocking(true)?;
+        debug!("Listening for sslocal traffic reports on {:?}", socket_path);
+
+        *util::rwlock_write(&sink) = TrafficStats::default();
+        let halt = Arc::new(AtomicBool::new(false));
+
+        let sink_mv = Arc::clone(&sink);
+        let halt_mv = Arc::clone(&halt);
+        let handle = thread::Builder::new()
+            .name(format!("Stats collector daemon for {:?}", socket_path))
+            .spawn(move || {
+                while !halt_mv.load(Ordering::Relaxed) {
+                    let mut stream = match listener.accept() {
+                        Ok((s, _)) => s,
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(COLLECTOR_POLL_INTERVAL);
+                            continue;
+                        }
+                        Err(err) => {
+                            warn!("Error accepting sslocal traffic report: {}", err);
+                            thread::sleep(COLLECTOR_POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+                    let mut buf = [0u8; 16];
+                    let read_res = stream
+                        .set_nonblocking(false)
+                        .and_then(|_| stream.set_read_timeout(Some(REPORT_READ_TIMEOUT)))
+                        .and_then(|_| stream.read_exact(&mut buf));
+                    if let Err(err) = read_res {
+                        warn!("Error reading sslocal traffic report: {}", err);
+                        continue;
+                    }
+                    let (tx, rx) = buf.split_at(8);
+                    let tx = u64::from_ne_bytes(tx.try_into().unwrap()); // exactly 8 bytes
+                    let rx = u64::from_ne_bytes(rx.try_into().unwrap()); // exactly 8 bytes
+                    trace!("Traffic report: tx {}, rx {}", tx, rx);
+                    util::rwlock_write(&sink_mv).record(tx, rx);
+                }
+            })?;
+
+        Ok(Self {
+            socket_path,
+            sink,
+            halt,
+            daemon_handle: Some(handle),
+        })
+    }
+
+    /// The arguments to pass to `sslocal` so that it reports to this collector.
+    pub fn sslocal_args(&self) -> Vec<OsString> {
+        vec!["--stat-path".into(), self.socket_path.clone().into()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_bytes, TrafficStats};
+
+    #[test]
+    fn format_bytes_units() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+    #[test]
+    fn record_tolerates_counter_reset() {
+        let mut stats = TrafficStats::default();
+        stats.record(1000, 2000);
+        stats.record(10, 20); // `sslocal` restarted
+        assert_eq!((stats.tx_bytes, stats.rx_bytes), (10, 20));
+        assert_eq!((stats.tx_rate, stats.rx_rate), (0.0, 0.0));
+    }
+}
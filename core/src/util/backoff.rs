@@ -0,0 +1,119 @@
+//! This module contains an implementation of exponential backoff,
+//! used to space out retries of a failing operation.
+
+use std::{fmt, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct BackoffExhausted {
+    max_retries: usize,
+}
+
+impl fmt::Display for BackoffExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ExponentialBackoff exhausted: more than {} retries",
+            self.max_retries
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ExponentialBackoffConfig {
+    /// The delay before the first retry.
+    pub initial_delay: Duration,
+    /// The delay will never exceed this value.
+    pub max_delay: Duration,
+    /// The delay is multiplied by this factor after every retry.
+    pub multiplier: f64,
+    /// Give up after this many consecutive retries.
+    pub max_retries: usize,
+}
+
+impl Default for ExponentialBackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            max_retries: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    config: ExponentialBackoffConfig,
+    /// The number of retries performed since the last reset.
+    retries: usize,
+}
+
+impl From<ExponentialBackoffConfig> for ExponentialBackoff {
+    fn from(config: ExponentialBackoffConfig) -> Self {
+        Self { config, retries: 0 }
+    }
+}
+
+impl ExponentialBackoff {
+    /// Register a retry, and get the delay to wait before performing it.
+    pub fn next_delay(&mut self) -> Result<Duration, BackoffExhausted> {
+        let ExponentialBackoffConfig {
+            initial_delay,
+            max_delay,
+            multiplier,
+            max_retries,
+        } = self.config;
+        if self.retries >= max_retries {
+            return Err(BackoffExhausted { max_retries });
+        }
+        let exp = i32::try_from(self.retries).unwrap_or(i32::MAX);
+        let delay = initial_delay.as_secs_f64() * multiplier.max(1.0).powi(exp);
+        self.retries += 1;
+        // `min` before converting back so that huge values do not overflow
+        Ok(Duration::from_secs_f64(delay.min(max_delay.as_secs_f64())))
+    }
+
+    /// The number of retries performed since the last reset.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// Start counting from scratch, e.g. after the operation succeeds.
+    pub fn reset(&mut self) {
+        self.retries = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{ExponentialBackoff, ExponentialBackoffConfig};
+
+    fn config(max_retries: usize) -> ExponentialBackoffConfig {
+        ExponentialBackoffConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_retries,
+        }
+    }
+
+    #[test]
+    fn grows_and_caps() {
+        let mut bo: ExponentialBackoff = config(10).into();
+        let delays: Vec<_> = (0..5).map(|_| bo.next_delay().unwrap().as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+    }
+    #[test]
+    fn exhausts_and_resets() {
+        let mut bo: ExponentialBackoff = config(2).into();
+        assert!(bo.next_delay().is_ok());
+        assert!(bo.next_delay().is_ok());
+        assert!(bo.next_delay().is_err());
+        bo.reset();
+        assert_eq!(bo.next_delay().unwrap(), Duration::from_millis(100));
+    }
+}
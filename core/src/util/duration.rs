@@ -0,0 +1,30 @@
+//! This module contains helpers for displaying durations to the user.
+
+/// Format a number of seconds compactly, e.g. `1h 2m 3s`, omitting leading zero units.
+pub fn format_duration(secs: u64) -> String {
+    let units = [
+        (secs / 86400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let parts: Vec<_> = units
+        .iter()
+        .skip_while(|(n, unit)| *n == 0 && *unit != "s")
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect();
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::format_duration;
+
+    #[test]
+    fn leading_zeros_omitted() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(59), "59s");
+        assert_eq!(format_duration(3600), "1h 0m 0s");
+        assert_eq!(format_duration(90061), "1d 1h 1m 1s");
+    }
+}
@@ -0,0 +1,17 @@
+//! This module contains miscellaneous helper structs and functions.
+
+// public members
+pub mod backoff;
+pub mod hacks;
+pub mod leaky_bucket;
+pub mod ring_buffer;
+
+// private members with re-export
+mod duration;
+pub use duration::*;
+
+mod output_kind;
+pub use output_kind::*;
+
+mod sync;
+pub use sync::*;
@@ -0,0 +1,140 @@
+//! This module contains a bounded buffer of text lines,
+//! which drops the oldest lines when full.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LineRingBufferConfig {
+    /// Keep at most this many lines.
+    pub max_lines: usize,
+    /// Keep at most this many bytes in total.
+    pub max_bytes: usize,
+}
+
+impl Default for LineRingBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: 10_000,
+            max_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineRingBuffer {
+    config: LineRingBufferConfig,
+    lines: VecDeque<String>,
+    /// The total length of `lines`.
+    bytes: usize,
+    /// The number of lines dropped to stay within limits.
+    dropped: usize,
+}
+
+impl From<LineRingBufferConfig> for LineRingBuffer {
+    fn from(config: LineRingBufferConfig) -> Self {
+        Self {
+            config,
+            lines: VecDeque::new(),
+            bytes: 0,
+            dropped: 0,
+        }
+    }
+}
+
+impl LineRingBuffer {
+    /// Append a line, dropping the oldest lines if either limit is exceeded.
+    ///
+    /// The newest line is always kept, even if it alone exceeds `max_bytes`.
+    pub fn push(&mut self, line: String) {
+        self.bytes += line.len();
+        self.lines.push_back(line);
+        self.enforce_limits();
+    }
+
+    /// Drop the oldest lines until both limits are respected, always keeping the newest line.
+    fn enforce_limits(&mut self) {
+        while self.lines.len() > 1 && (self.lines.len() > self.config.max_lines || self.bytes > self.config.max_bytes) {
+            if let Some(dropped) = self.lines.pop_front() {
+                self.bytes -= dropped.len();
+                self.dropped += 1;
+            }
+        }
+    }
+
+    /// The limits of this buffer.
+    pub fn config(&self) -> LineRingBufferConfig {
+        self.config
+    }
+
+    /// Change the limits of this buffer, dropping the oldest lines if necessary.
+    pub fn set_config(&mut self, config: LineRingBufferConfig) {
+        self.config = config;
+        self.enforce_limits();
+    }
+
+    /// The number of lines dropped since creation.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Iterate over the last `n` lines, from oldest to newest.
+    pub fn tail(&self, n: usize) -> impl Iterator<Item = &str> {
+        self.lines
+            .iter()
+            .skip(self.lines.len().saturating_sub(n))
+            .map(String::as_str)
+    }
+
+    /// All lines concatenated, from oldest to newest.
+    pub fn contents(&self) -> String {
+        let mut contents = String::with_capacity(self.bytes);
+        self.lines.iter().for_each(|line| contents.push_str(line));
+        contents
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LineRingBuffer, LineRingBufferConfig};
+
+    fn buffer(max_lines: usize, max_bytes: usize) -> LineRingBuffer {
+        LineRingBufferConfig { max_lines, max_bytes }.into()
+    }
+
+    #[test]
+    fn drops_by_lines() {
+        let mut buf = buffer(2, 1024);
+        for line in ["a\n", "b\n", "c\n"] {
+            buf.push(line.into());
+        }
+        assert_eq!(buf.contents(), "b\nc\n");
+        assert_eq!(buf.dropped(), 1);
+        assert_eq!(buf.tail(1).collect::<Vec<_>>(), vec!["c\n"]);
+        assert_eq!(buf.tail(5).count(), 2);
+    }
+    #[test]
+    fn drops_by_bytes() {
+        let mut buf = buffer(100, 5);
+        buf.push("abc\n".into());
+        buf.push("de\n".into());
+        assert_eq!(buf.contents(), "de\n");
+        buf.push("too long\n".into());
+        assert_eq!(buf.contents(), "too long\n");
+        assert_eq!(buf.dropped(), 2);
+    }
+    #[test]
+    fn shrinks_on_set_config() {
+        let mut buf = buffer(3, 1024);
+        for line in ["a\n", "b\n", "c\n"] {
+            buf.push(line.into());
+        }
+        buf.set_config(LineRingBufferConfig {
+            max_lines: 1,
+            max_bytes: 1024,
+        });
+        assert_eq!(buf.contents(), "c\n");
+        assert_eq!(buf.dropped(), 2);
+    }
+}
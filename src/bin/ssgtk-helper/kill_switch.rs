@@ -0,0 +1,164 @@
+//! This module contains code that installs and removes the kill switch firewall rules,
+//! which block all outgoing traffic except to the shadowsocks servers.
+//!
+//! DNS is let through, so that `sslocal` can still resolve server hostnames when switching profiles.
+//! nftables is used if available, otherwise iptables.
+
+use std::{
+    io::{self, Write},
+    net::IpAddr,
+    process::{Command, Stdio},
+};
+
+/// The nftables table holding the rules.
+const NFT_TABLE: &str = "ssgtk_kill_switch";
+
+/// The iptables chain holding the rules, which is jumped to from `OUTPUT`.
+const IPTABLES_CHAIN: &str = "SSGTK_KILL_SWITCH";
+
+/// Whether `name` is a valid network interface name.
+///
+/// Checked strictly, since interface names end up in firewall rules.
+pub fn is_valid_if_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < 16
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Run a command, failing if it exits unsuccessfully.
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if !status.success() {
+        let msg = format!("{:?} exited with {}", command, status);
+        return Err(io::Error::other(msg));
+    }
+    Ok(())
+}
+
+/// Apply an nftables script.
+fn nft(script: &str) -> io::Result<()> {
+    let mut child = Command::new("nft").args(["-f", "-"]).stdin(Stdio::piped()).spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin should be piped")
+        .write_all(script.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        let msg = format!("nft exited with {}", status);
+        return Err(io::Error::other(msg));
+    }
+    Ok(())
+}
+
+/// Build the nftables script that replaces the kill switch table.
+fn nft_script(allow_addrs: &[IpAddr], allow_ifs: &[String]) -> String {
+    let join = |addrs: Vec<&IpAddr>| addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ");
+    let (v4, v6): (Vec<_>, Vec<_>) = allow_addrs.iter().partition(|a| a.is_ipv4());
+
+    // declaring the table first makes deleting it succeed even if it does not exist yet
+    let mut script = format!("table inet {0}\ndelete table inet {0}\n", NFT_TABLE);
+    script += &format!("table inet {} {{\n", NFT_TABLE);
+    script += "  chain output {\n";
+    script += "    type filter hook output priority 0; policy drop;\n";
+    script += "    oifname \"lo\" accept\n";
+    script += "    udp dport { 67, 68, 546, 547 } accept\n"; // DHCP, or the lease cannot be renewed
+    script += "    meta l4proto { tcp, udp } th dport 53 accept\n";
+    if !v4.is_empty() {
+        script += &format!("    ip daddr {{ {} }} accept\n", join(v4));
+    }
+    if !v6.is_empty() {
+        script += &format!("    ip6 daddr {{ {} }} accept\n", join(v6));
+    }
+    for if_name in allow_ifs {
+        script += &format!("    oifname \"{}\" accept\n", if_name);
+    }
+    script += "    reject\n"; // fail fast instead of timing out
+    script += "  }\n}\n";
+    script
+}
+
+/// Remove the iptables chain of one address family, ignoring what does not exist.
+fn iptables_remove(bin: &str) {
+    let _ = Command::new(bin)
+        .args(["-D", "OUTPUT", "-j", IPTABLES_CHAIN])
+        .stderr(Stdio::null())
+        .status();
+    let _ = Command::new(bin)
+        .args(["-F", IPTABLES_CHAIN])
+        .stderr(Stdio::null())
+        .status();
+    let _ = Command::new(bin)
+        .args(["-X", IPTABLES_CHAIN])
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Install the iptables chain of one address family.
+fn iptables_install(bin: &str, v6: bool, allow_addrs: &[IpAddr], allow_ifs: &[String]) -> io::Result<()> {
+    iptables_remove(bin);
+    let append = |rule: &[&str]| run(Command::new(bin).args(["-A", IPTABLES_CHAIN]).args(rule));
+    run(Command::new(bin).args(["-N", IPTABLES_CHAIN]))?;
+    append(&["-o", "lo", "-j", "ACCEPT"])?;
+    let dhcp_ports = if v6 { "546:547" } else { "67:68" };
+    append(&["-p", "udp", "--dport", dhcp_ports, "-j", "ACCEPT"])?;
+    append(&["-p", "udp", "--dport", "53", "-j", "ACCEPT"])?;
+    append(&["-p", "tcp", "--dport", "53", "-j", "ACCEPT"])?;
+    for addr in allow_addrs.iter().filter(|a| a.is_ipv6() == v6) {
+        append(&["-d", &addr.to_string(), "-j", "ACCEPT"])?;
+    }
+    for if_name in allow_ifs {
+        append(&["-o", if_name, "-j", "ACCEPT"])?;
+    }
+    append(&["-j", "REJECT"])?;
+    run(Command::new(bin).args(["-I", "OUTPUT", "-j", IPTABLES_CHAIN]))
+}
+
+/// Block all outgoing traffic, except to loopback, DHCP, DNS, `allow_addrs` and through `allow_ifs`.
+///
+/// Replaces the rules installed before, if any.
+pub fn enable(allow_addrs: &[IpAddr], allow_ifs: &[String]) -> io::Result<()> {
+    match nft(&nft_script(allow_addrs, allow_ifs)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            iptables_install("iptables", false, allow_addrs, allow_ifs)?;
+            iptables_install("ip6tables", true, allow_addrs, allow_ifs)
+        }
+        res => res,
+    }
+}
+
+/// Remove the rules installed by `enable`, if any.
+pub fn disable() -> io::Result<()> {
+    let script = format!("table inet {0}\ndelete table inet {0}\n", NFT_TABLE);
+    match nft(&script) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            iptables_remove("iptables");
+            iptables_remove("ip6tables");
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_nft_script() {
+        let addrs = ["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        let script = nft_script(&addrs, &["tun0".into()]);
+        assert!(script.starts_with("table inet ssgtk_kill_switch\ndelete table inet ssgtk_kill_switch\n"));
+        assert!(script.contains("    ip daddr { 192.0.2.1 } accept\n"));
+        assert!(script.contains("    ip6 daddr { 2001:db8::1 } accept\n"));
+        assert!(script.contains("    oifname \"tun0\" accept\n"));
+        // empty sets are a syntax error
+        assert!(!nft_script(&[], &[]).contains("daddr"));
+
+        assert!(is_valid_if_name("wg-home.0"));
+        assert!(!is_valid_if_name("tun0\" accept"));
+        assert!(!is_valid_if_name("a-very-long-interface-name"));
+    }
+}
@@ -0,0 +1,259 @@
+//! A small helper that runs `sslocal` as root on behalf of `ssgtk`,
+//! so that the GUI itself never needs to run as root.
+//! It also installs and removes the kill switch firewall rules; see `--kill-switch`.
+//!
+//! It is launched via `pkexec`; see `res/com.github.spyophobia.shadowsocks-gtk-rs.policy`.
+//! Since `ssgtk` cannot signal a process running as root,
+//! `sslocal` is instead stopped once this helper's stdin is closed.
+//!
+//! Only a known `sslocal` binary that the user cannot tamper with is launched; see `check_sslocal`.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    net::IpAddr,
+    os::unix::{fs::MetadataExt, process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+use ipnet::IpNet;
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use shadowsocks_gtk_rs::consts::{
+    GO_SS2_LOOKUP_NAME_DEFAULT, SSLOCAL_LOOKUP_NAME_DEFAULT, SS_LIBEV_LOOKUP_NAME_DEFAULT,
+};
+
+mod kill_switch;
+
+/// How long to wait for the tun interface to come up before giving up on routes.
+const INTERFACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to check whether the tun interface has come up.
+const INTERFACE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The names of the binaries this helper is willing to run as root, one per backend.
+const SSLOCAL_NAMES: [&str; 3] = [
+    SSLOCAL_LOOKUP_NAME_DEFAULT,
+    SS_LIBEV_LOOKUP_NAME_DEFAULT,
+    GO_SS2_LOOKUP_NAME_DEFAULT,
+];
+
+#[derive(Debug, Clone, Parser)]
+#[clap(
+    name = "ssgtk-helper",
+    author,
+    version,
+    about = "A privileged helper for ssgtk that runs sslocal as root. Not meant to be run directly."
+)]
+struct CliArgs {
+    /// The working directory of `sslocal`.
+    #[clap(long = "pwd", value_name = "DIR", required_unless_present = "kill-switch")]
+    pwd: Option<PathBuf>,
+
+    /// The tun interface to set up routes through.
+    #[clap(long = "if-name", value_name = "NAME", requires = "routes")]
+    if_name: Option<String>,
+
+    /// A route to set up through the tun interface. This is a repeatable flag.
+    #[clap(
+        long = "route",
+        value_name = "NET",
+        requires = "if-name",
+        multiple_occurrences = true
+    )]
+    routes: Vec<IpNet>,
+
+    /// The routing table to add routes to, instead of the main table.
+    #[clap(long = "route-table", value_name = "TABLE", requires = "routes")]
+    route_table: Option<u32>,
+
+    /// Install or remove the kill switch firewall rules, instead of running `sslocal`.
+    #[clap(long = "kill-switch", value_name = "MODE", value_enum, conflicts_with = "command")]
+    kill_switch: Option<KillSwitchMode>,
+
+    /// An address the kill switch lets traffic through to. This is a repeatable flag.
+    #[clap(
+        long = "allow-addr",
+        value_name = "IP",
+        requires = "kill-switch",
+        multiple_occurrences = true
+    )]
+    allow_addrs: Vec<IpAddr>,
+
+    /// An interface the kill switch lets traffic through. This is a repeatable flag.
+    #[clap(
+        long = "allow-if",
+        value_name = "NAME",
+        requires = "kill-switch",
+        multiple_occurrences = true
+    )]
+    allow_ifs: Vec<String>,
+
+    /// The `sslocal` binary, followed by its arguments.
+    #[clap(required_unless_present = "kill-switch", last = true, value_name = "SSLOCAL")]
+    command: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum KillSwitchMode {
+    On,
+    Off,
+}
+
+fn main() {
+    let args = CliArgs::parse();
+    if let Some(mode) = args.kill_switch {
+        let res = match mode {
+            KillSwitchMode::On => match args.allow_ifs.iter().find(|n| !kill_switch::is_valid_if_name(n)) {
+                Some(name) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid interface name {:?}", name),
+                )),
+                None => kill_switch::enable(&args.allow_addrs, &args.allow_ifs),
+            },
+            KillSwitchMode::Off => kill_switch::disable(),
+        };
+        if let Err(err) = res {
+            eprintln!("ssgtk-helper: cannot set kill switch {:?}: {}", mode, err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let bin_path = match check_sslocal(Path::new(&args.command[0])) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("ssgtk-helper: refusing to run {:?}: {}", args.command[0], err);
+            process::exit(1);
+        }
+    };
+    let pwd = args.pwd.expect("required unless setting the kill switch");
+    if let Err(err) = env::set_current_dir(&pwd) {
+        eprintln!("ssgtk-helper: cannot enter {:?}: {}", pwd, err);
+        process::exit(1);
+    }
+
+    let mut sslocal = match Command::new(&bin_path)
+        .args(&args.command[1..])
+        .stdin(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("ssgtk-helper: cannot start {:?}: {}", args.command[0], err);
+            process::exit(1);
+        }
+    };
+
+    // stop `sslocal` once `ssgtk` closes our stdin
+    let pid = Pid::from_raw(sslocal.id() as i32);
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin(), &mut io::sink());
+        let _ = kill(pid, Signal::SIGINT);
+    });
+
+    if let Some(if_name) = &args.if_name {
+        if let Err(err) = setup_routes(if_name, &args.routes, args.route_table) {
+            eprintln!("ssgtk-helper: cannot set up routes: {}", err);
+        }
+    }
+
+    let code = match sslocal.wait() {
+        // mimic the shell for deaths by signal
+        Ok(status) => status.code().or_else(|| status.signal().map(|s| 128 + s)).unwrap_or(1),
+        Err(err) => {
+            eprintln!("ssgtk-helper: cannot wait for sslocal: {}", err);
+            1
+        }
+    };
+    process::exit(code);
+}
+
+/// Check that `bin_path` is a binary this helper may run as root, returning the path to run it by.
+///
+/// It has to be an absolute path to one of `SSLOCAL_NAMES` once symlinks are resolved.
+/// The binary and every directory above it have to be owned by root and not writable by anyone else,
+/// so that the user cannot swap it for something else.
+fn check_sslocal(bin_path: &Path) -> io::Result<PathBuf> {
+    let deny = |msg: String| Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+    if !bin_path.is_absolute() {
+        return deny("not an absolute path".into());
+    }
+    let resolved = bin_path.canonicalize()?;
+    let name = resolved.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    if !SSLOCAL_NAMES.contains(&name) {
+        return deny(format!("{:?} is not one of {:?}", resolved, SSLOCAL_NAMES));
+    }
+    if !resolved.is_file() {
+        return deny(format!("{:?} is not a file", resolved));
+    }
+    for path in resolved.ancestors() {
+        let meta = fs::metadata(path)?;
+        if meta.uid() != 0 {
+            return deny(format!("{:?} is not owned by root", path));
+        }
+        if meta.mode() & 0o022 != 0 {
+            return deny(format!("{:?} is writable by users other than root", path));
+        }
+    }
+    Ok(resolved)
+}
+
+/// Wait for the tun interface to come up, then route `routes` through it.
+fn setup_routes(if_name: &str, routes: &[IpNet], table: Option<u32>) -> io::Result<()> {
+    let deadline = Instant::now() + INTERFACE_TIMEOUT;
+    while !Path::new("/sys/class/net").join(if_name).exists() {
+        if Instant::now() >= deadline {
+            let msg = format!("tun interface {} did not come up", if_name);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+        }
+        thread::sleep(INTERFACE_POLL_INTERVAL);
+    }
+    for route in routes {
+        let mut ip = Command::new("ip");
+        ip.args(["route", "replace", &route.to_string(), "dev", if_name]);
+        if let Some(table) = table {
+            ip.args(["table", &table.to_string()]);
+        }
+        let status = ip.status()?;
+        if !status.success() {
+            eprintln!("ssgtk-helper: cannot add route {}: ip exited with {}", route, status);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn reject_untrusted_sslocal() {
+        // not an sslocal, though owned by root
+        assert!(check_sslocal(Path::new("/bin/sh")).is_err());
+        assert!(check_sslocal(Path::new("sslocal")).is_err());
+
+        let dir = env::temp_dir().join(format!("ssgtk-helper-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fake = dir.join(SSLOCAL_LOOKUP_NAME_DEFAULT);
+        fs::write(&fake, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&fake, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(check_sslocal(&fake).is_err());
+        // symlinks are resolved before the name is checked
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("/bin/sh", &link).unwrap();
+        let renamed = dir.join(SS_LIBEV_LOOKUP_NAME_DEFAULT);
+        fs::rename(&link, &renamed).unwrap();
+        assert!(check_sslocal(&renamed).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
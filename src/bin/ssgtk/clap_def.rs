@@ -1,9 +1,11 @@
 //! This module contains code that define the CLI API.
 
-use std::{fs, path::PathBuf};
+use std::{fs, net::SocketAddr, path::PathBuf};
 
-use clap::{ArgAction, IntoApp, Parser};
+use clap::{ArgAction, IntoApp, Parser, Subcommand};
 use shadowsocks_gtk_rs::consts::*;
+#[cfg(feature = "runtime-api")]
+use shadowsocks_gtk_rs::runtime_api_msg::APICommandKind;
 
 #[derive(Debug, Clone, Parser)]
 #[clap(name = "ssgtk", author, version, about, disable_help_subcommand = true)]
@@ -12,12 +14,51 @@ pub struct CliArgs {
     #[clap(short = 'p', long = "profiles-dir", value_name = "DIR", default_value_os = PROFILES_DIR_PATH_DEFAULT.as_os_str())]
     pub profiles_dir: PathBuf,
 
+    /// The directory from which to load profile templates for "New from Template…".
+    ///
+    /// Templates are laid out like profiles, but are never connected to.
+    #[clap(long = "templates-dir", value_name = "DIR", default_value_os = TEMPLATES_DIR_PATH_DEFAULT.as_os_str())]
+    pub templates_dir: PathBuf,
+
+    /// Follow symlinked directories when loading profiles.
+    ///
+    /// Useful if you share profiles between machines, e.g. in a dotfiles repository.
+    #[clap(long = "follow-symlinks")]
+    pub follow_symlinks: bool,
+
     /// Load and store app state from&to a custom file path.
     ///
     /// Useful if you want to run multiple instances".
     #[clap(long = "app-state", value_name = "PATH", default_value_os = STATE_FILE_PATH_DEFAULT.as_os_str())]
     pub app_state_path: PathBuf,
 
+    /// Load and save app settings from&to a custom file path.
+    ///
+    /// Unlike the app state, this file is meant to be edited by hand, or from the "Preferences…" dialog.
+    #[clap(long = "app-settings", value_name = "PATH", default_value_os = SETTINGS_FILE_PATH_DEFAULT.as_os_str())]
+    pub app_settings_path: PathBuf,
+
+    /// Start with only the tray icon, regardless of the settings.
+    ///
+    /// Notifications are sent as toasts instead of dialogs, until a window is opened from the tray.
+    /// Useful for autostart.
+    #[clap(long = "minimized")]
+    pub minimized: bool,
+
+    /// Connect on startup, regardless of the settings.
+    #[clap(long = "auto-connect", conflicts_with = "no-auto-connect")]
+    pub auto_connect: bool,
+
+    /// Do not connect to any profile on startup, regardless of the settings.
+    #[clap(long = "no-auto-connect")]
+    pub no_auto_connect: bool,
+
+    /// Connect to this profile on startup, by its name or its 1-based index in the tray menu.
+    ///
+    /// If another instance is already running, it is told to connect instead.
+    #[clap(long = "connect", value_name = "PROFILE", conflicts_with = "no-auto-connect")]
+    pub connect: Option<String>,
+
     /// Search for a custom image to use for the tray icon.
     #[clap(long = "icon-name", value_name = "NAME", default_value = "shadowsocks-gtk-rs")]
     pub tray_icon_filename: String,
@@ -29,6 +70,25 @@ pub struct CliArgs {
     #[clap(long = "icon-theme-dir", value_name = "DIR")]
     pub icon_theme_dir: Option<PathBuf>,
 
+    /// Serve Prometheus metrics at `http://<ADDR>/metrics`, e.g. `127.0.0.1:9154`.
+    ///
+    /// Includes the connection state, uptime, restarts, traffic if collected, and latency test results.
+    #[clap(long = "metrics-addr", value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Load all profiles in this directory (or `--profiles-dir`) without starting the app,
+    /// print a YAML report of each profile and each problem found, then exit.
+    ///
+    /// Exits with 1 if any profile cannot be loaded or launched. Useful for checking profiles in CI.
+    #[clap(long = "validate", value_name = "DIR")]
+    pub validate: Option<Option<PathBuf>>,
+
+    /// Store a password in the keyring under this entry name, then exit.
+    ///
+    /// Profiles can then use it with `password: { keyring: "<ENTRY>" }`.
+    #[clap(long = "store-secret", value_name = "ENTRY")]
+    pub store_secret: Option<String>,
+
     /// Increase the verbosity level of output.
     /// This is a repeatable flag.
     #[clap(short = 'v', long = "verbose", action = ArgAction::Count)]
@@ -45,6 +105,84 @@ pub struct CliArgs {
     #[cfg(feature = "runtime-api")]
     #[clap(long = "api-socket", value_name = "PATH", default_value_os = RUNTIME_API_SOCKET_PATH_DEFAULT.as_os_str())]
     pub runtime_api_socket_path: PathBuf,
+
+    /// Also accept runtime API commands over TCP at this address, e.g. `0.0.0.0:1090`.
+    ///
+    /// Clients must authenticate with the token in `--api-tcp-token-file`.
+    #[cfg(feature = "runtime-api")]
+    #[clap(long = "api-tcp", value_name = "ADDR", requires = "runtime-api-tcp-token-file")]
+    pub runtime_api_tcp_addr: Option<SocketAddr>,
+
+    /// Read the shared token for TCP runtime API clients from this file.
+    #[cfg(feature = "runtime-api")]
+    #[clap(long = "api-tcp-token-file", value_name = "PATH", requires = "runtime-api-tcp-addr")]
+    pub runtime_api_tcp_token_file: Option<PathBuf>,
+
+    /// The commands accepted over TCP, comma-separated.
+    ///
+    /// Defaults to `restart,switch-profile,connect,stop`.
+    #[cfg(feature = "runtime-api")]
+    #[clap(
+        long = "api-tcp-allow",
+        value_name = "CMDS",
+        value_enum,
+        use_value_delimiter = true,
+        requires = "runtime-api-tcp-addr"
+    )]
+    pub runtime_api_tcp_allowlist: Vec<APICommandKind>,
+
+    /// Run only the core, without any GUI, controlled over the runtime API.
+    ///
+    /// `sslocal` then keeps running when the GUI exits; `ssgtk` started afterwards attaches to it as a frontend.
+    #[cfg(feature = "runtime-api")]
+    #[clap(long = "service", conflicts_with = "install-service")]
+    pub service: bool,
+
+    /// Like `--service`, but also send notifications via libnotify, for desktops without a tray.
+    ///
+    /// GTK is not initialized, so no display is needed.
+    #[cfg(feature = "runtime-api")]
+    #[clap(long = "headless", conflicts_with_all = &["service", "install-service"])]
+    pub headless: bool,
+
+    /// Install and start a systemd user service running `ssgtk --service` with the same paths, then exit.
+    #[cfg(feature = "runtime-api")]
+    #[clap(long = "install-service")]
+    pub install_service: bool,
+
+    /// Encrypt the profile config file in this directory with the master passphrase, then exit.
+    ///
+    /// The passphrase is looked up from the keyring entry "master-passphrase", or else prompted for.
+    #[cfg(feature = "encryption")]
+    #[clap(long = "encrypt-profile", value_name = "DIR", conflicts_with = "decrypt-profile")]
+    pub encrypt_profile: Option<PathBuf>,
+
+    /// Decrypt the profile config file in this directory, then exit.
+    #[cfg(feature = "encryption")]
+    #[clap(long = "decrypt-profile", value_name = "DIR")]
+    pub decrypt_profile: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub sub_cmd: Option<SubCmd>,
+}
+
+/// Maintenance tasks that run instead of the app.
+#[derive(Debug, Clone, Subcommand)]
+#[clap(rename_all = "kebab-case")]
+pub enum SubCmd {
+    /// Rewrite profiles in the legacy format (with `ss_bin_path` and `ss_config_path`) into the current one,
+    /// in this directory (or `--profiles-dir`) and all directories below it, then exit.
+    ///
+    /// Each original is kept next to it as `profile.yaml.legacy`.
+    /// Exits with 1 if any legacy profile cannot be migrated.
+    MigrateProfiles {
+        #[clap(value_name = "DIR")]
+        dir: Option<PathBuf>,
+
+        /// Only print what would be migrated.
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+    },
 }
 
 /// Build a clap app and return matches. Only call once.
@@ -70,6 +208,13 @@ fn validate_impl(mut args: CliArgs) -> Result<CliArgs, clap::Error> {
         XDG_DIRS.place_state_file(STATE_FILE_NAME_DEFAULT)?;
     }
 
+    // validate app_settings_path
+    let app_settings_path = &args.app_settings_path;
+    if SETTINGS_FILE_PATH_DEFAULT.eq(app_settings_path) {
+        // if default, then mkdir if absent
+        XDG_DIRS.place_config_file(SETTINGS_FILE_NAME_DEFAULT)?;
+    }
+
     // validate and canonicalize icon_theme_dir
     if let Some(theme_dir) = &args.icon_theme_dir {
         // AppIndicator requires an absolute path
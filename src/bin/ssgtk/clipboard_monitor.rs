@@ -0,0 +1,90 @@
+//! This module contains code that watches the clipboard for copied `ss://` URLs,
+//! so that they can be offered for import, like shadowsocks-android does.
+//!
+//! Clipboard contents are never logged, since they may contain passwords.
+
+use std::{cell::RefCell, collections::HashSet, fmt, rc::Rc};
+
+use crossbeam_channel::Sender;
+use glib::SignalHandlerId;
+use gtk::{gdk, prelude::*, Clipboard};
+use log::{debug, error};
+
+use crate::{event::AppEvent, io::profile_import::find_ss_urls};
+
+/// Watches the clipboard while enabled, sending an `AppEvent::ClipboardUrlsFound` event
+/// whenever `ss://` URLs not seen before are copied.
+pub struct ClipboardMonitor {
+    clipboard: Clipboard,
+    handler: Option<SignalHandlerId>,
+    events_tx: Sender<AppEvent>,
+    /// URLs already offered, so that copying one again does not offer it again.
+    seen: Rc<RefCell<HashSet<String>>>,
+}
+
+// the URLs seen contain passwords
+impl fmt::Debug for ClipboardMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClipboardMonitor")
+            .field("enabled", &self.is_enabled())
+            .field("seen", &self.seen.borrow().len())
+            .finish()
+    }
+}
+
+impl ClipboardMonitor {
+    /// Create a monitor, watching the clipboard right away if `enable` is set.
+    ///
+    /// Must be called after GTK is initialised.
+    pub fn new(enable: bool, events_tx: Sender<AppEvent>) -> Self {
+        let mut monitor = Self {
+            clipboard: Clipboard::get(&gdk::SELECTION_CLIPBOARD),
+            handler: None,
+            events_tx,
+            seen: Default::default(),
+        };
+        monitor.set_enabled(enable);
+        monitor
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    /// Start or stop watching the clipboard.
+    ///
+    /// Whatever is already in the clipboard is not looked at, only what is copied afterwards.
+    pub fn set_enabled(&mut self, enable: bool) {
+        match (enable, self.handler.take()) {
+            (true, None) => {
+                debug!("Watching the clipboard for ss:// URLs");
+                let events_tx = self.events_tx.clone();
+                let seen = Rc::clone(&self.seen);
+                // `owner-change` has no binding in gtk-rs, since its event type is not supported
+                let handler = self.clipboard.connect_local("owner-change", false, move |_| {
+                    let (events_tx, seen) = (events_tx.clone(), Rc::clone(&seen));
+                    Clipboard::get(&gdk::SELECTION_CLIPBOARD).request_text(move |_, text| {
+                        let urls: Vec<_> = find_ss_urls(text.unwrap_or_default())
+                            .into_iter()
+                            .filter(|url| seen.borrow_mut().insert(url.clone()))
+                            .collect();
+                        if urls.is_empty() {
+                            return;
+                        }
+                        debug!("Found {} new ss:// URLs in the clipboard", urls.len());
+                        if events_tx.send(AppEvent::ClipboardUrlsFound(urls)).is_err() {
+                            error!("Trying to send ClipboardUrlsFound event, but all receivers have hung up.");
+                        }
+                    });
+                    None
+                });
+                self.handler = Some(handler);
+            }
+            (false, Some(handler)) => {
+                debug!("Stopped watching the clipboard");
+                self.clipboard.disconnect(handler);
+            }
+            (_, handler) => self.handler = handler, // unchanged
+        }
+    }
+}
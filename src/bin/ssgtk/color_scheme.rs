@@ -0,0 +1,98 @@
+//! This module contains code that reads the desktop's preferred color scheme via xdg-desktop-portal,
+//! and watches it for changes.
+//!
+//! See https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.Settings.
+
+use std::{io, thread};
+
+use crossbeam_channel::Sender;
+use log::{debug, error};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedValue, Value},
+};
+
+use crate::event::AppEvent;
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+/// The color scheme preferred by the desktop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    PreferDark,
+    PreferLight,
+}
+
+impl ColorScheme {
+    /// Whether a dark look should be used.
+    pub fn is_dark(&self) -> bool {
+        *self == Self::PreferDark
+    }
+
+    /// Parse the value of `org.freedesktop.appearance color-scheme`.
+    ///
+    /// Some portals wrap the value in extra variants, which are unwrapped here.
+    /// Unknown values are treated as having no preference, as the spec requires.
+    fn from_value(mut value: &Value) -> Option<Self> {
+        while let Value::Value(inner) = value {
+            value = inner;
+        }
+        let scheme = match value.downcast_ref::<u32>()? {
+            1 => Self::PreferDark,
+            2 => Self::PreferLight,
+            _ => Self::NoPreference,
+        };
+        Some(scheme)
+    }
+}
+
+/// Read the current color scheme, then keep sending its changes as `AppEvent::ColorSchemeChanged` events.
+fn listen(events_tx: &Sender<AppEvent>) -> zbus::Result<()> {
+    let conn = Connection::session()?;
+    let portal = Proxy::new(&conn, PORTAL_DESTINATION, PORTAL_PATH, PORTAL_INTERFACE)?;
+    // subscribe before reading to avoid missing a change in between
+    let changes = portal.receive_signal("SettingChanged")?;
+
+    let value: OwnedValue = portal.call("Read", &(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY))?;
+    let scheme = ColorScheme::from_value(&value).unwrap_or_default();
+    debug!("Desktop color scheme is {:?}", scheme);
+    if events_tx.send(AppEvent::ColorSchemeChanged(scheme)).is_err() {
+        error!("Trying to send ColorSchemeChanged event, but all receivers have hung up.");
+        return Ok(());
+    }
+
+    for msg in changes {
+        let (namespace, key, value): (String, String, OwnedValue) = msg.body()?;
+        if namespace != APPEARANCE_NAMESPACE || key != COLOR_SCHEME_KEY {
+            continue;
+        }
+        let scheme = ColorScheme::from_value(&value).unwrap_or_default();
+        debug!("Desktop color scheme changed to {:?}", scheme);
+        if events_tx.send(AppEvent::ColorSchemeChanged(scheme)).is_err() {
+            error!("Trying to send ColorSchemeChanged event, but all receivers have hung up.");
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Watch the desktop's color scheme in the background.
+///
+/// If the portal is unavailable or too old to know the setting, the app keeps its default look,
+/// so the failure is only logged.
+pub fn spawn_watcher(events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("Color scheme daemon".into())
+        .spawn(move || {
+            if let Err(err) = listen(&events_tx) {
+                debug!("Cannot watch the desktop color scheme: {}", err);
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,280 @@
+//! This module contains code that diagnoses whether traffic actually goes through the proxy,
+//! by comparing what remote services see of requests made directly and through the proxy.
+//!
+//! The DNS leak check uses the probe service at https://bash.ws/dnsleak.
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::Sender;
+use log::{debug, error, trace};
+use serde::Deserialize;
+
+use crate::event::AppEvent;
+
+/// An endpoint that responds with the requester's IP address as plain text.
+const IP_ECHO_URL: &str = "https://api.ipify.org";
+
+/// The domain of the DNS leak probe service, whose subdomains are answered by its own name servers.
+const DNS_LEAK_DOMAIN: &str = "bash.ws";
+
+/// Resolve this many probe hostnames per check, to catch resolvers that are used in rotation.
+const DNS_PROBE_COUNT: usize = 3;
+
+/// Give up on each request after this long.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The external IP addresses seen by the IP echo endpoint.
+#[derive(Debug, Clone)]
+pub struct IpReport {
+    /// Seen when connecting directly, or the reason of failure.
+    pub direct: Result<IpAddr, String>,
+    /// Seen when connecting through the proxy.
+    pub proxied: IpAddr,
+}
+
+impl IpReport {
+    /// Whether the proxy exposes the same address as a direct connection,
+    /// i.e. traffic is not actually relayed through the remote server.
+    pub fn is_leaking(&self) -> bool {
+        matches!(&self.direct, Ok(direct) if *direct == self.proxied)
+    }
+}
+
+/// A DNS resolver that has queried the probe service.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Resolver {
+    pub ip: String,
+    #[serde(default)]
+    pub country_name: String,
+    #[serde(default)]
+    pub asn: String,
+}
+
+impl fmt::Display for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ip)?;
+        let details: Vec<_> = [&self.asn, &self.country_name]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(String::as_str)
+            .collect();
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the probe service's test result.
+#[derive(Debug, Deserialize)]
+struct ProbeEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(flatten)]
+    resolver: Resolver,
+}
+
+/// The resolvers seen by the DNS leak probe service.
+#[derive(Debug, Clone)]
+pub struct DnsReport {
+    /// Seen when resolving through the proxy.
+    pub proxied: Vec<Resolver>,
+    /// Seen when resolving with the system resolver, or the reason of failure.
+    pub system: Result<Vec<Resolver>, String>,
+}
+
+impl DnsReport {
+    /// The resolvers used through the proxy that are also used by the system,
+    /// i.e. lookups that are not actually made from the remote server's side.
+    pub fn leaking_resolvers(&self) -> Vec<&Resolver> {
+        match &self.system {
+            Ok(system) => self.proxied.iter().filter(|r| system.contains(r)).collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+/// Build an agent that sends requests through the SOCKS5 proxy at `proxy_addr`.
+///
+/// Hostnames are resolved by the proxy.
+fn proxied_agent(proxy_addr: SocketAddr) -> Result<ureq::Agent, String> {
+    let proxy = ureq::Proxy::new(format!("socks5://{}", proxy_addr)).map_err(|err| err.to_string())?;
+    let agent = ureq::AgentBuilder::new()
+        .proxy(proxy)
+        .timeout_connect(REQUEST_TIMEOUT) // also covers the SOCKS handshake
+        .timeout(REQUEST_TIMEOUT)
+        .build();
+    Ok(agent)
+}
+
+/// Build an agent that sends requests directly.
+fn direct_agent() -> ureq::Agent {
+    // ureq does not pick up proxy environment variables unless asked to
+    ureq::AgentBuilder::new()
+        .timeout_connect(REQUEST_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+}
+
+/// Request the IP echo endpoint with `agent`, and parse the address in its response.
+fn fetch_ip(agent: &ureq::Agent) -> Result<IpAddr, String> {
+    let body = agent
+        .get(IP_ECHO_URL)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    body.trim()
+        .parse()
+        .map_err(|_| format!("Unexpected response from {}: {:?}", IP_ECHO_URL, body.trim()))
+}
+
+/// Fetch the external IP address both directly and through the SOCKS5 proxy at `proxy_addr`.
+///
+/// Fails only if the proxied request fails, since the check is meaningless without it.
+pub fn check_ip(proxy_addr: SocketAddr) -> Result<IpReport, String> {
+    let proxied = fetch_ip(&proxied_agent(proxy_addr)?).map_err(|err| format!("Through the proxy: {}", err))?;
+    let direct = fetch_ip(&direct_agent());
+    Ok(IpReport { direct, proxied })
+}
+
+/// Get a new test ID from the probe service, which identifies the probe hostnames of a test.
+fn new_probe_id(agent: &ureq::Agent) -> Result<String, String> {
+    let id = agent
+        .get(&format!("https://{}/id", DNS_LEAK_DOMAIN))
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    Ok(id.trim().to_string())
+}
+
+/// The unique hostnames of the test identified by `id`.
+fn probe_hostnames(id: &str) -> impl Iterator<Item = String> + '_ {
+    (1..=DNS_PROBE_COUNT).map(move |n| format!("{}.{}.{}", n, id, DNS_LEAK_DOMAIN))
+}
+
+/// Get the resolvers that have looked up the probe hostnames of the test identified by `id`.
+fn probe_result(agent: &ureq::Agent, id: &str) -> Result<Vec<Resolver>, String> {
+    let body = agent
+        .get(&format!("https://{}/dnsleak/test/{}?json", DNS_LEAK_DOMAIN, id))
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    let entries: Vec<ProbeEntry> = serde_json::from_str(&body).map_err(|err| err.to_string())?;
+    Ok(parse_resolvers(entries))
+}
+
+/// Keep only the resolvers of a test result, which also lists the requester's IP and a conclusion.
+fn parse_resolvers(entries: Vec<ProbeEntry>) -> Vec<Resolver> {
+    entries
+        .into_iter()
+        .filter(|e| e.kind == "dns")
+        .map(|e| e.resolver)
+        .collect()
+}
+
+/// Find the resolvers used when looking up unique hostnames through the SOCKS5 proxy at `proxy_addr`,
+/// and those used by the system resolver.
+///
+/// Fails only if the lookups through the proxy fail, since the check is meaningless without them.
+pub fn check_dns(proxy_addr: SocketAddr) -> Result<DnsReport, String> {
+    let agent = proxied_agent(proxy_addr)?;
+    let proxied_id = new_probe_id(&agent).map_err(|err| format!("Through the proxy: {}", err))?;
+    for hostname in probe_hostnames(&proxied_id) {
+        // only the lookup matters; the probe hostnames do not serve anything
+        if let Err(err) = agent.head(&format!("http://{}", hostname)).call() {
+            trace!("Probe request to {} has failed as expected: {}", hostname, err);
+        }
+    }
+    let proxied = probe_result(&agent, &proxied_id).map_err(|err| format!("Through the proxy: {}", err))?;
+
+    let system = new_probe_id(&agent).and_then(|id| {
+        for hostname in probe_hostnames(&id) {
+            if let Err(err) = (hostname.as_str(), 80).to_socket_addrs() {
+                trace!("Probe lookup of {} has failed as expected: {}", hostname, err);
+            }
+        }
+        probe_result(&agent, &id)
+    });
+    Ok(DnsReport { proxied, system })
+}
+
+/// Check the external IP address of the profile named `profile_name` in the background,
+/// then send the result as an `AppEvent::IpChecked` event.
+pub fn spawn_ip_check(profile_name: String, proxy_addr: SocketAddr, events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new().name("IP check daemon".into()).spawn(move || {
+        debug!("Checking external IP address of profile \"{}\"", profile_name);
+        let result = check_ip(proxy_addr);
+        let event = AppEvent::IpChecked { profile_name, result };
+        if events_tx.send(event).is_err() {
+            error!("Trying to send IpChecked event, but all receivers have hung up.");
+        }
+    })?;
+    Ok(())
+}
+
+/// Check for DNS leaks through the profile named `profile_name` in the background,
+/// then send the result as an `AppEvent::DnsChecked` event.
+pub fn spawn_dns_check(profile_name: String, proxy_addr: SocketAddr, events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new().name("DNS check daemon".into()).spawn(move || {
+        debug!("Checking DNS leaks of profile \"{}\"", profile_name);
+        let result = check_dns(proxy_addr);
+        let event = AppEvent::DnsChecked { profile_name, result };
+        if events_tx.send(event).is_err() {
+            error!("Trying to send DnsChecked event, but all receivers have hung up.");
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_resolvers, DnsReport, IpReport, ProbeEntry};
+
+    #[test]
+    fn detect_leak() {
+        let proxied = "203.0.113.7".parse().unwrap();
+        let report = |direct: Result<&str, &str>| IpReport {
+            direct: direct.map(|ip| ip.parse().unwrap()).map_err(String::from),
+            proxied,
+        };
+        assert!(report(Ok("203.0.113.7")).is_leaking());
+        assert!(!report(Ok("198.51.100.1")).is_leaking());
+        // e.g. when the direct route is blocked, which proves nothing
+        assert!(!report(Err("timed out")).is_leaking());
+    }
+
+    #[test]
+    fn detect_dns_leak() {
+        let result = r#"[
+            {"ip": "198.51.100.1", "country": "NL", "country_name": "Netherlands", "asn": "AS64500 Example", "type": "ip"},
+            {"ip": "192.0.2.53", "country": "NL", "country_name": "Netherlands", "asn": "AS64500 Example", "type": "dns"},
+            {"ip": "203.0.113.53", "type": "dns"},
+            {"ip": "", "type": "conclusion"}
+        ]"#;
+        let entries: Vec<ProbeEntry> = serde_json::from_str(result).unwrap();
+        let proxied = parse_resolvers(entries);
+        assert_eq!(proxied.len(), 2);
+        assert_eq!(proxied[0].to_string(), "192.0.2.53 (AS64500 Example, Netherlands)");
+        assert_eq!(proxied[1].to_string(), "203.0.113.53");
+
+        let system = vec![proxied[1].clone()];
+        let report = DnsReport {
+            proxied: proxied.clone(),
+            system: Ok(system),
+        };
+        assert_eq!(report.leaking_resolvers(), vec![&proxied[1]]);
+        let report = DnsReport {
+            proxied: proxied.clone(),
+            system: Err("no network".into()),
+        };
+        assert!(report.leaking_resolvers().is_empty());
+    }
+}
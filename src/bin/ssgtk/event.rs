@@ -1,20 +1,243 @@
 //! This module defines events passed between core and GUI elements.
 
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use crossbeam_channel::Sender;
+
 use shadowsocks_gtk_rs::notify_method::NotifyMethod;
 
-use crate::io::profile_loader::Profile;
+use crate::{
+    color_scheme::ColorScheme,
+    diagnostics::{DnsReport, IpReport},
+    hotkey::HotkeyAction,
+    io::{
+        app_settings::AppSettings,
+        profile_loader::{Profile, ProfileConfig},
+    },
+    latency::Latency,
+    log_parser::LogEventKind,
+    profile_manager::ManagerEvent,
+};
 
 #[derive(Debug, Clone)]
 pub enum AppEvent {
     // from GUI
     LogViewerShow,
     LogViewerHide,
+    /// Save the `sslocal` output kept in the backlog to a file.
+    SaveBacklog(PathBuf),
+    StatsWindowShow,
+    StatsWindowHide,
+    UrlImportShow,
+    ImportFromUrl(String),
+    FileImportShow,
+    ImportFromFile(PathBuf),
+    /// Files, directories or text dropped onto a window.
+    DropReceived {
+        paths: Vec<PathBuf>,
+        text: Option<String>,
+    },
+    /// Copy a profile directory from elsewhere into the profiles directory.
+    ImportProfileDir(PathBuf),
+    ScanQrFromScreen,
+    /// Let the user pick which of these `ss://` URLs found in the clipboard to import.
+    ClipboardImportShow(Vec<String>),
+    QrExportShow,
+    LaunchPreviewShow,
+    ProfileExportShow,
+    ExportProfiles {
+        path: PathBuf,
+        strip_passwords: bool,
+    },
+    PreferencesShow,
+    ProfileFilterShow,
+    LoadProblemsShow,
+    SavePreferences {
+        settings: AppSettings,
+        /// Whether to launch on login.
+        autostart: bool,
+    },
+    RefreshSubscriptions,
+    /// Fetch gfwlist now, regardless of how old the cached copy is.
+    UpdateRules,
+    TestLatencies,
+    /// Compare the external IP address seen directly and through the current profile.
+    CheckIp,
+    /// Look for DNS lookups through the current profile that are made by the system resolver.
+    CheckDns,
+    ProfileEditorShow {
+        edit_current: bool,
+    },
+    SaveProfile {
+        dir: PathBuf,
+        config: ProfileConfig,
+        is_new: bool,
+    },
+    /// Show the dialog for copying the current profile, or a template if `from_template` is set.
+    ProfileDeleteShow,
+    /// Delete a profile by moving its directory to the trash, or into the archive if `archive` is set.
+    DeleteProfile {
+        profile: Profile,
+        archive: bool,
+    },
+    ProfileDuplicateShow {
+        from_template: bool,
+    },
+    /// Copy a profile into a new profile directory with a new name.
+    DuplicateProfile {
+        source: Profile,
+        display_name: String,
+    },
     SwitchProfile(Profile),
+    /// Start or stop running a profile alongside the current one.
+    SetProfileAlongside {
+        profile: Profile,
+        enable: bool,
+    },
     ManualStop,
     SetNotify(NotifyMethod),
+    SetCollectStats(bool),
+    /// Restart `sslocal` logging one level more verbosely than the profile's `log_level`, or back.
+    SetVerboseLogs(bool),
+    SetLogFile(bool),
+    OpenLogFile,
+    SetSystemProxy(bool),
+    SetPacServer(bool),
+    SetSystemProxyPac(bool),
+    SetHealthCheck(bool),
+    SetKillSwitch(bool),
+    SetClipboardMonitor(bool),
+    SetKeepAwake(bool),
+    /// Only list profiles with this tag in the tray menu, or all of them if `None`.
+    SetTagFilter(Option<String>),
+    Hotkey(HotkeyAction),
     Quit,
 
+    // from D-Bus, application actions or the scheduler
+    ConnectByName(String),
+    Disconnect,
+    Restart,
+
     // from core
-    OkStop { instance_name: Option<String> },
-    ErrorStop { instance_name: Option<String>, err: String },
+    OkStop {
+        instance_name: Option<String>,
+    },
+    ErrorStop {
+        instance_name: Option<String>,
+        err: String,
+    },
+    RestartScheduled {
+        profile_name: String,
+        attempt: usize,
+        delay: Duration,
+    },
+    /// The result of verifying a newly switched profile.
+    HealthChecked {
+        profile_name: String,
+        /// The profile to roll back to on failure; `None` if stopped before the switch.
+        previous: Option<Profile>,
+        result: Result<Duration, String>,
+    },
+    ProfilesChanged,
+    /// Load the content of the unloaded group in this directory, when it is opened in the tray menu.
+    LoadGroup(PathBuf),
+    LatenciesTested(HashMap<String, Latency>),
+    /// The result of `CheckIp`.
+    IpChecked {
+        profile_name: String,
+        result: Result<IpReport, String>,
+    },
+    /// The result of `CheckDns`.
+    DnsChecked {
+        profile_name: String,
+        result: Result<DnsReport, String>,
+    },
+    /// The `ss://` URLs found on screen, or the reason the scan has failed.
+    QrScanned(Result<Vec<String>, String>),
+    /// The `ss://` URLs newly copied to the clipboard.
+    ClipboardUrlsFound(Vec<String>),
+    /// An error logged by `sslocal`.
+    SslocalError {
+        profile_name: String,
+        line: String,
+        kind: LogEventKind,
+    },
+    /// The reason the kill switch cannot be engaged or released.
+    KillSwitchFailed(String),
+    /// The reason global hotkeys cannot be registered.
+    HotkeysFailed(String),
+    /// The name of the active network, initially and whenever it changes; `None` if offline.
+    NetworkChanged {
+        network: Option<String>,
+        initial: bool,
+    },
+    /// The system is about to sleep, which is held off until `done_tx` is sent to or dropped.
+    PrepareForSleep {
+        done_tx: Sender<()>,
+    },
+    ResumedFromSleep,
+    /// The desktop's preferred color scheme, initially and whenever it changes.
+    ColorSchemeChanged(ColorScheme),
+    SubscriptionsRefreshed {
+        updated: usize,
+        errors: Vec<String>,
+    },
+    /// The number of domains in the newly fetched gfwlist, or the reason the update has failed.
+    GfwlistUpdated {
+        result: Result<usize, String>,
+        /// Whether the update has been requested with `UpdateRules`.
+        forced: bool,
+    },
+}
+
+impl From<ManagerEvent> for AppEvent {
+    fn from(event: ManagerEvent) -> Self {
+        match event {
+            ManagerEvent::OkStop { instance_name } => Self::OkStop { instance_name },
+            ManagerEvent::ErrorStop { instance_name, err } => Self::ErrorStop { instance_name, err },
+            ManagerEvent::RestartScheduled {
+                profile_name,
+                attempt,
+                delay,
+            } => Self::RestartScheduled {
+                profile_name,
+                attempt,
+                delay,
+            },
+            ManagerEvent::SslocalError {
+                profile_name,
+                line,
+                kind,
+            } => Self::SslocalError {
+                profile_name,
+                line,
+                kind,
+            },
+        }
+    }
+}
+
+impl AppEvent {
+    /// Whether this event opens a window or dialog at the user's request.
+    pub fn opens_window(&self) -> bool {
+        use AppEvent::*;
+        matches!(
+            self,
+            LogViewerShow
+                | StatsWindowShow
+                | UrlImportShow
+                | FileImportShow
+                | ClipboardImportShow(_)
+                | QrExportShow
+                | LaunchPreviewShow
+                | ProfileExportShow
+                | PreferencesShow
+                | ProfileFilterShow
+                | LoadProblemsShow
+                | ProfileEditorShow { .. }
+                | ProfileDeleteShow
+                | ProfileDuplicateShow { .. }
+                | Hotkey(HotkeyAction::ShowBacklog)
+        )
+    }
 }
@@ -2,42 +2,105 @@
 //! and holds all the GUI components.
 
 use std::{
-    fmt, io,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
     process,
     sync::{Arc, Mutex, RwLock},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "runtime-api")]
+use bus::Bus;
 use crossbeam_channel::{unbounded as unbounded_channel, Receiver, Sender};
-use gtk::prelude::*;
+use derivative::Derivative;
+use gtk::{gio, prelude::*};
 use log::{debug, error, info, trace, warn};
 
 #[cfg(feature = "runtime-api")]
-use shadowsocks_gtk_rs::runtime_api_msg::APICommand;
+use shadowsocks_gtk_rs::consts::BUS_BUFFER_SIZE;
+#[cfg(feature = "runtime-api")]
+use shadowsocks_gtk_rs::runtime_api_msg::{APICommand, APIProfileTree, APIStateEvent, APIStatus};
 use shadowsocks_gtk_rs::{
+    consts::{
+        PROFILES_DIR_PATH_DEFAULT, PROFILE_MANIFEST_FILE_NAME, SETTINGS_FILE_PATH_DEFAULT, STATE_FILE_PATH_DEFAULT,
+    },
     notify_method::NotifyMethod,
-    util::{self, mutex_lock},
+    util::{self, format_duration, mutex_lock},
 };
 
+#[cfg(feature = "encryption")]
+use super::passphrase_prompt::unlock_encrypted_profiles;
+#[cfg(feature = "dbus-api")]
+use crate::io::dbus_service::DBusService;
 #[cfg(feature = "runtime-api")]
-use crate::io::runtime_api::APIListener;
+use crate::io::runtime_api::{self, APIListener, APIReply, APIRequest, StateEventsBroadcast, TcpAPIConfig};
 use crate::{
     clap_def::CliArgs,
+    clipboard_monitor::ClipboardMonitor,
+    color_scheme::{self, ColorScheme},
+    diagnostics::{self, DnsReport, IpReport},
     event::AppEvent,
+    health_check::{self, HealthCheckConfig},
+    hotkey::{self, HotkeyAction},
     io::{
-        app_state::AppState,
-        profile_loader::{Profile, ProfileFolder, ProfileLoadError},
+        app_settings::AppSettings,
+        app_state::{AppState, WindowGeometry},
+        autostart,
+        gfwlist::GfwlistUpdater,
+        log_file::log_file_path,
+        metrics_server::MetricsServer,
+        pac_server::{PacServer, PacServerConfig},
+        profile_import::{self, Dropped},
+        profile_loader::{
+            self, ConfigFormat, LoadOptions, LoadProblem, LoadProgress, Profile, ProfileConfig, ProfileFolder,
+            ProfileGroup, ProfileLoadError,
+        },
+        profile_watcher::ProfileWatcher,
+        subscription::{self, Subscription, SubscriptionUpdater},
+        system_proxy::{ProxyTarget, SystemProxy},
     },
-    profile_manager::ProfileManager,
+    kill_switch::{KillSwitch, KillSwitchAllow},
+    latency::{self, Latency},
+    log_parser::{self, LogEventKind},
+    network_monitor,
+    profile_manager::{EventSink, ProfileManager},
+    qr_scan, region,
+    scheduler::Scheduler,
+    sleep_monitor::{self, KeepAwake},
 };
 
 use super::{
+    application,
+    launch_preview::show_launch_preview_dialog,
+    load_problems::show_load_problems_dialog,
+    load_progress::load_with_progress,
     log_viewer::LogViewerWindow,
-    notification::{notify, Level},
+    notification::{notify, notify_log, notify_toast_with_action, Level},
+    orphan_prompt::terminate_orphans_prompt,
+    preferences::show_preferences_dialog,
+    profile_delete::show_profile_delete_dialog,
+    profile_duplicate::show_profile_duplicate_dialog,
+    profile_editor::show_profile_editor,
+    profile_export::show_profile_export_dialog,
+    profile_filter::show_profile_filter_dialog,
+    qr_export::show_qr_export_dialog,
+    stats_window::StatsWindow,
     tray::TrayItem,
+    url_import::{
+        describe_ss_url, show_file_import_dialog, show_import_choices_dialog, show_url_import_dialog,
+        show_urls_import_dialog,
+    },
+    DisplayServer,
 };
 
+/// How often the throughput shown next to the tray icon is refreshed.
+const STATS_LABEL_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the user can be notified of errors logged by `sslocal`.
+pub const SSLOCAL_ERROR_NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 pub enum AppStartError {
     ProfileLoadError(ProfileLoadError),
@@ -87,28 +150,94 @@ impl From<io::Error> for AppStartError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 struct GTKApp {
     // core
     app_state_path: PathBuf,
+    app_settings_path: PathBuf,
+    profiles_dir: PathBuf,
+    templates_dir: PathBuf,
+    load_opts: LoadOptions,
     profile_folder: ProfileFolder,
     profile_manager: Arc<RwLock<ProfileManager>>,
+    /// Profiles running alongside the current one, each on its own local port.
+    ///
+    /// Only the current profile is used for the system proxy, traffic stats and failover.
+    alongside: BTreeMap<String, ProfileManager>,
     events_tx: Sender<AppEvent>,
     events_rx: Receiver<AppEvent>,
+    /// Events of the profiles in `Self::alongside`, kept apart from those of the current profile.
+    alongside_events_tx: Sender<AppEvent>,
+    alongside_events_rx: Receiver<AppEvent>,
+    subscription_updater: SubscriptionUpdater,
+    gfwlist_updater: GfwlistUpdater,
+    #[allow(dead_code)]
+    scheduler: Scheduler, // this needs to be stored to be kept alive
+    kill_switch: KillSwitch,
+    clipboard_monitor: ClipboardMonitor,
+    keep_awake: KeepAwake,
+    #[allow(dead_code)]
+    profile_watcher: Option<ProfileWatcher>, // this needs to be stored to be kept alive
 
     // runtime API
     #[cfg(feature = "runtime-api")]
     #[allow(dead_code)]
     api_listener: APIListener, // this needs to be stored to be kept alive
     #[cfg(feature = "runtime-api")]
-    api_cmds_rx: Receiver<APICommand>,
+    api_cmds_rx: Receiver<APIRequest>,
+    #[cfg(feature = "runtime-api")]
+    #[derivative(Debug(format_with = "shadowsocks_gtk_rs::util::hacks::omit_bus"))]
+    api_events_brd: StateEventsBroadcast,
+
+    // D-Bus service
+    #[cfg(feature = "dbus-api")]
+    dbus_service: Option<DBusService>,
 
     // GUI components
     tray: TrayItem,
     log_viewer_window: Option<LogViewerWindow>,
+    stats_window: Option<StatsWindow>,
+    /// Where the windows were last placed, used when they are opened again.
+    log_viewer_geometry: Option<WindowGeometry>,
+    stats_window_geometry: Option<WindowGeometry>,
 
     // misc
+    settings: AppSettings,
     notify_method: NotifyMethod,
+    /// Set while started minimized and no window has been opened since,
+    /// during which notifications never open dialogs.
+    tray_only: bool,
+    /// Whether to set the system proxy for profiles that do not override it.
+    system_proxy_enabled: bool,
+    /// Set while the system proxy points to `sslocal`; restores the original settings on drop.
+    system_proxy: Option<SystemProxy>,
+    /// Settings of the PAC server, kept even when it is not running.
+    pac_config: PacServerConfig,
+    /// Set while the PAC server is running; stops it on drop.
+    pac_server: Option<PacServer>,
+    /// Set if the metrics server has been started with `--metrics-addr`.
+    metrics_server: Option<MetricsServer>,
+    /// Profiles that could not be loaded last time, and why.
+    load_problems: Vec<LoadProblem>,
+    /// The groups whose content has been loaded on demand, which are loaded again on reload.
+    loaded_groups: HashSet<PathBuf>,
+    /// Results of the latest latency test, kept so that they survive menu rebuilds.
+    latencies: HashMap<String, Latency>,
+    /// The tag profiles listed in the tray menu are filtered by, if any.
+    tag_filter: Option<String>,
+    /// Settings of the connectivity check performed when switching profiles.
+    health_check: HealthCheckConfig,
+    /// How many times in a row the check of the current profile has failed.
+    health_check_failures: u32,
+    /// Profiles that have failed since the last manual switch, so that failover does not loop.
+    failover_tried: HashSet<String>,
+    /// The profile last switched to, which the toggle connection hotkey reconnects to.
+    last_profile: Option<String>,
+    /// The profile stopped before the system went to sleep, which is restored on resume.
+    slept_profile: Option<String>,
+    /// When the user was last notified of an error logged by `sslocal`.
+    last_sslocal_error_notified: Option<Instant>,
 }
 
 impl GTKApp {
@@ -116,57 +245,253 @@ impl GTKApp {
     fn new(args: &CliArgs) -> Result<Self, AppStartError> {
         let CliArgs {
             profiles_dir,
+            templates_dir,
+            follow_symlinks,
             app_state_path,
+            app_settings_path,
+            minimized,
+            auto_connect,
+            no_auto_connect,
+            connect,
             tray_icon_filename,
             icon_theme_dir,
+            metrics_addr,
+            validate: _,
+            store_secret: _,
             verbose: _,
             quiet: _,
             #[cfg(feature = "runtime-api")]
             runtime_api_socket_path,
+            #[cfg(feature = "runtime-api")]
+            runtime_api_tcp_addr,
+            #[cfg(feature = "runtime-api")]
+            runtime_api_tcp_token_file,
+            #[cfg(feature = "runtime-api")]
+            runtime_api_tcp_allowlist,
+            #[cfg(feature = "runtime-api")]
+                service: _,
+            #[cfg(feature = "runtime-api")]
+                install_service: _,
+            #[cfg(feature = "runtime-api")]
+                headless: _,
+            #[cfg(feature = "encryption")]
+                encrypt_profile: _,
+            #[cfg(feature = "encryption")]
+                decrypt_profile: _,
+            sub_cmd: _,
         } = args;
 
         // init GTK
         gtk::init()?;
+        let display_server = DisplayServer::detect();
+        info!("Running on {} display server", display_server);
+
+        // encrypted profiles cannot be loaded without the master passphrase
+        #[cfg(feature = "encryption")]
+        unlock_encrypted_profiles(profiles_dir);
 
-        // load profiles
-        let profile_folder = ProfileFolder::from_path_recurse(profiles_dir)?;
+        // load app settings
+        let settings = AppSettings::from_file_or_default(app_settings_path);
+
+        // load profiles, showing progress if there are many
+        let load_opts = LoadOptions {
+            follow_symlinks: *follow_symlinks,
+            default_bin_path: settings.bin_path.clone(),
+            name_conflicts: settings.name_conflicts,
+            max_depth: settings.max_load_depth,
+            progress: None,
+            lazy: settings.lazy_load,
+        };
+        let progress = Arc::new(LoadProgress::default());
+        let (profile_folder, load_problems) = {
+            let opts = LoadOptions {
+                progress: Some(Arc::clone(&progress)),
+                ..load_opts.clone()
+            };
+            load_with_progress(&progress, || load_profile_folder(profiles_dir, &opts, true))?
+        };
         debug!(
             "Successfully loaded {} profiles in total",
             profile_folder.profile_count()
         );
 
-        // load app state
-        let previous_state = {
+        // load app state, overridden by settings
+        let (previous_state, last_profile, restore_session) = {
             let state_res = AppState::from_file(app_state_path);
             if let Err(ref err) = state_res {
                 warn!("Failed to load saved app state: {}", err);
             }
-            state_res.unwrap_or_default()
+            let mut state = state_res.unwrap_or_default();
+            settings.apply_to(&mut state);
+            // e.g. after a crash, reconnect to what was connected even if not connecting on startup
+            let restore_session = !state.clean_exit;
+            if restore_session {
+                warn!("The app did not quit cleanly last time; restoring the session");
+            }
+            // a profile given on the command line takes precedence over all
+            let connect_profile = connect.as_ref().and_then(|selector| {
+                let profile = profile_folder.lookup_selector(selector);
+                if profile.is_none() {
+                    warn!("Cannot find a profile named or numbered \"{}\"; ignored", selector);
+                }
+                profile.map(|p| p.metadata.display_name.clone())
+            });
+            if let Some(name) = &connect_profile {
+                state.most_recent_profile = name.clone();
+            }
+            // still remembered for the toggle connection hotkey
+            let last_profile = Some(state.most_recent_profile.clone()).filter(|name| !name.is_empty());
+            // the CLI flags take precedence over settings
+            let connect_on_startup = match (*auto_connect || connect_profile.is_some(), no_auto_connect) {
+                (true, _) => true,
+                (_, true) => false,
+                _ => settings.connect_on_startup || restore_session,
+            };
+            if !connect_on_startup {
+                debug!("Connecting on startup is disabled");
+                state.most_recent_profile.clear();
+                state.alongside_profiles.clear();
+            }
+            (state, last_profile, restore_session)
         };
 
+        // `sslocal` left running by a crashed session would hold on to its port
+        terminate_orphans_prompt();
+
         // resume core
         let (events_tx, events_rx) = unbounded_channel();
         let pm_arc = {
-            let pm = ProfileManager::resume_from(&previous_state, &profile_folder, events_tx.clone());
+            let scope = settings.systemd_scope.clone();
+            let pm = previous_state.resume_profile_manager(scope, &profile_folder, EventSink::new(events_tx.clone()));
             Arc::new(RwLock::new(pm))
         };
 
+        // start subscription updater
+        let subscription_updater = SubscriptionUpdater::start(profiles_dir, events_tx.clone())?;
+
+        // start gfwlist updater; told whether gfwlist is used once the app is constructed
+        let gfwlist_updater = GfwlistUpdater::start(previous_state.pac.gfwlist_urls(), events_tx.clone())?;
+
+        // start scheduler
+        let scheduler = Scheduler::start(settings.schedule.clone(), events_tx.clone())?;
+
+        // start kill switch; engaged once a profile is running
+        let kill_switch = KillSwitch::start(previous_state.kill_switch, events_tx.clone())?;
+
+        // watch clipboard for ss:// URLs, if opted in
+        let clipboard_monitor = ClipboardMonitor::new(previous_state.clipboard_monitor, events_tx.clone());
+        let keep_awake = KeepAwake::new(previous_state.keep_awake);
+
+        // watch profiles directory for changes; not fatal if unavailable
+        let profile_watcher = match ProfileWatcher::start(profiles_dir, events_tx.clone()) {
+            Ok(w) => Some(w),
+            Err(err) => {
+                warn!(
+                    "Cannot watch profiles directory; profiles will not reload automatically: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        // start PAC server; not fatal if the port is taken
+        let pac_server = previous_state
+            .pac
+            .enabled
+            .then(|| PacServer::start(previous_state.pac.clone(), None))
+            .and_then(|res| res.map_err(|err| error!("Cannot start PAC server: {}", err)).ok());
+
+        // start metrics server; not fatal if the address is taken
+        let metrics_server = metrics_addr
+            .map(|addr| MetricsServer::start(addr, Arc::clone(&pm_arc)))
+            .and_then(|res| res.map_err(|err| error!("Cannot start metrics server: {}", err)).ok());
+
         // start runtime API
         #[cfg(feature = "runtime-api")]
-        let (api_listener, api_cmds_rx) = {
+        let (api_listener, api_cmds_rx, api_events_brd) = {
             let (tx, rx) = unbounded_channel();
-            let listener = APIListener::start(runtime_api_socket_path, tx)?;
-            (listener, rx)
+            let tcp_config = TcpAPIConfig::load(
+                *runtime_api_tcp_addr,
+                runtime_api_tcp_token_file.as_deref(),
+                runtime_api_tcp_allowlist,
+            )?;
+            let logs_brd = Arc::clone(&util::rwlock_read(&pm_arc).logs_brd);
+            let events_brd: StateEventsBroadcast = Mutex::new(Bus::new(BUS_BUFFER_SIZE)).into();
+            let listener = APIListener::start(
+                runtime_api_socket_path,
+                tcp_config,
+                tx,
+                logs_brd,
+                Arc::clone(&events_brd),
+            )?;
+            (listener, rx, events_brd)
+        };
+
+        // start D-Bus service; not fatal if unavailable
+        #[cfg(feature = "dbus-api")]
+        let dbus_service = {
+            let profile_names = profile_folder
+                .get_profiles()
+                .into_iter()
+                .map(|p| p.metadata.display_name.clone())
+                .collect();
+            match DBusService::start(events_tx.clone(), profile_names) {
+                Ok(s) => Some(s),
+                Err(err) => {
+                    warn!("Cannot start D-Bus service: {}", err);
+                    None
+                }
+            }
         };
 
+        // register global hotkeys; not fatal if unavailable
+        if !settings.hotkeys.is_empty() {
+            if let Err(err) = hotkey::spawn_listener(settings.hotkeys.clone(), events_tx.clone()) {
+                warn!("Cannot start hotkey listener: {}", err);
+            }
+        }
+
+        // follow the desktop's color scheme; not fatal if unavailable
+        if let Err(err) = color_scheme::spawn_watcher(events_tx.clone()) {
+            warn!("Cannot start color scheme watcher: {}", err);
+        }
+
+        // stop sslocal cleanly during sleep; not fatal if unavailable
+        if let Err(err) = sleep_monitor::spawn_watcher(events_tx.clone()) {
+            warn!("Cannot start sleep monitor: {}", err);
+        }
+
+        // act on network changes; not fatal if unavailable
+        if let Err(err) = network_monitor::spawn_watcher(events_tx.clone()) {
+            warn!("Cannot start network monitor: {}", err);
+        }
+
         // build permanent GUI components
+        // the legacy fallback is an XEmbed icon, which cannot be shown at all without X11
+        let mut tray_unavailable = false;
+        match TrayItem::sni_host_available() {
+            Ok(true) => debug!("StatusNotifierItem host found; the tray icon uses it"),
+            Ok(false) if !display_server.supports_xembed_tray() => {
+                warn!(
+                    "No StatusNotifierItem host found on the session bus, and a legacy tray icon \
+                    cannot be shown on {}; use the desktop actions or the runtime API instead",
+                    display_server
+                );
+                tray_unavailable = true;
+            }
+            Ok(false) => warn!(
+                "No StatusNotifierItem host found on the session bus; falling back to a legacy tray icon, \
+                which may be invisible (e.g. on GNOME without the AppIndicator extension)"
+            ),
+            Err(err) => warn!("Cannot check for a StatusNotifierItem host: {}", err),
+        }
         let tray = {
             let mut tray = TrayItem::build_and_show(
                 &tray_icon_filename,
                 icon_theme_dir.as_deref(),
                 events_tx.clone(),
                 &profile_folder,
-                previous_state.notify_method,
+                &previous_state,
             );
             // set tray state to match profile manager state
             match util::rwlock_read(&pm_arc).current_profile() {
@@ -176,36 +501,139 @@ impl GTKApp {
             tray
         };
 
-        Ok(Self {
+        let (alongside_events_tx, alongside_events_rx) = unbounded_channel();
+        let mut app = Self {
             app_state_path: app_state_path.clone(),
+            app_settings_path: app_settings_path.clone(),
+            profiles_dir: profiles_dir.clone(),
+            templates_dir: templates_dir.clone(),
+            load_opts,
             profile_folder,
             profile_manager: pm_arc,
+            alongside: BTreeMap::new(),
             events_tx,
             events_rx,
+            alongside_events_tx,
+            alongside_events_rx,
+            subscription_updater,
+            gfwlist_updater,
+            scheduler,
+            kill_switch,
+            clipboard_monitor,
+            keep_awake,
+            profile_watcher,
 
             #[cfg(feature = "runtime-api")]
             api_listener,
             #[cfg(feature = "runtime-api")]
             api_cmds_rx,
+            #[cfg(feature = "runtime-api")]
+            api_events_brd,
+
+            #[cfg(feature = "dbus-api")]
+            dbus_service,
 
             tray,
             log_viewer_window: None,
+            stats_window: None,
+            log_viewer_geometry: previous_state.log_viewer_geometry,
+            stats_window_geometry: previous_state.stats_window_geometry,
 
+            tray_only: *minimized || settings.start_minimized,
+            settings,
             notify_method: previous_state.notify_method,
-        })
+            system_proxy_enabled: previous_state.system_proxy,
+            system_proxy: None, // synced after construction
+            pac_config: previous_state.pac,
+            pac_server,
+            metrics_server,
+            load_problems: vec![], // set after construction
+            loaded_groups: HashSet::new(),
+            latencies: HashMap::new(),
+            tag_filter: previous_state.tag_filter,
+            health_check: previous_state.health_check,
+            health_check_failures: 0,
+            failover_tried: HashSet::new(),
+            last_profile,
+            slept_profile: None,
+            last_sslocal_error_notified: None,
+        };
+        for name in previous_state.alongside_profiles.iter() {
+            match app.profile_folder.lookup(name).cloned() {
+                Some(p) => app.set_alongside(p, true),
+                None => warn!("Cannot find profile \"{}\" to run alongside; skipped", name),
+            }
+        }
+        if restore_session {
+            if previous_state.log_viewer_open && !app.tray_only {
+                app.show_log_viewer();
+            }
+            app.notify(
+                Level::Info,
+                "Session Restored",
+                "ssgtk did not quit cleanly last time; reconnected to the last used profile",
+            );
+        }
+        if tray_unavailable {
+            app.notify(
+                Level::Warn,
+                "No Tray Available",
+                "The desktop has no StatusNotifierItem host; open ssgtk from the app launcher to show its output",
+            );
+        }
+        app.set_load_problems(load_problems);
+        app.sync_gfwlist_wanted();
+        // mark the app as running, so that an unclean exit can be detected on the next startup
+        app.save_state();
+        Ok(app)
     }
 
     /// Export the current application state.
     pub fn snapshot(&self) -> AppState {
         let pm = util::rwlock_read(&self.profile_manager);
         let most_recent_profile = pm.current_profile().map_or("".into(), |p| p.metadata.display_name);
+        let backlog_limit = mutex_lock(&pm.backlog).config();
         AppState {
             most_recent_profile,
             restart_limit: pm.restart_limit,
+            restart_backoff: pm.restart_backoff,
+            alongside_profiles: self.alongside.keys().cloned().collect(),
+            tag_filter: self.tag_filter.clone(),
+            stop_grace_period: pm.stop_grace_period,
+            collect_stats: pm.collect_stats,
+            log_file: pm.log_file_config,
+            backlog_limit,
+            system_proxy: self.system_proxy_enabled,
+            pac: self.pac_config.clone(),
+            health_check: self.health_check.clone(),
+            kill_switch: self.kill_switch.is_enabled(),
+            clipboard_monitor: self.clipboard_monitor.is_enabled(),
+            keep_awake: self.keep_awake.is_enabled(),
             notify_method: self.notify_method,
+            clean_exit: false,
+            log_viewer_open: self.log_viewer_window.is_some(),
+            log_viewer_geometry: (self.log_viewer_window.as_ref())
+                .and_then(|w| w.geometry())
+                .or(self.log_viewer_geometry),
+            stats_window_geometry: (self.stats_window.as_ref())
+                .and_then(|w| w.geometry())
+                .or(self.stats_window_geometry),
         }
     }
 
+    /// Save the current application state to disk.
+    ///
+    /// This is done on every change, so that it survives an unclean exit.
+    fn save_state(&self) {
+        self.write_state(self.snapshot());
+    }
+    fn write_state(&self, state: AppState) {
+        match state.write_to_file(&self.app_state_path) {
+            Ok(_) => info!("App state saved to {:?}", self.app_state_path),
+            Err(err) => error!("Failed to save app state: {}", err),
+        };
+    }
+
     /// Show the log viewer window, if not already shown.
     fn show_log_viewer(&mut self) {
         match self.log_viewer_window.as_ref() {
@@ -216,14 +644,26 @@ impl GTKApp {
             None => {
                 let pm_inner = util::rwlock_read(&self.profile_manager);
                 let events_tx = self.events_tx.clone();
-                let backlog = mutex_lock(&pm_inner.backlog).clone();
+                let (backlog, dropped_lines) = {
+                    let backlog = mutex_lock(&pm_inner.backlog);
+                    (backlog.contents(), backlog.dropped())
+                };
                 let log_listener = pm_inner.new_listener();
 
                 debug!("Opening log viewer window.");
-                let window = LogViewerWindow::new(events_tx, backlog, log_listener);
+                let window = LogViewerWindow::new(
+                    events_tx,
+                    backlog,
+                    dropped_lines,
+                    log_listener,
+                    self.settings.strip_log_colors,
+                    self.log_viewer_geometry,
+                );
                 window.show();
+                drop(pm_inner);
 
                 self.log_viewer_window = Some(window);
+                self.save_state();
             }
         }
     }
@@ -234,9 +674,11 @@ impl GTKApp {
     fn drop_log_viewer(&mut self) {
         match self.log_viewer_window.take() {
             None => debug!("Log viewer window is None; nothing to drop"),
-            some => {
+            Some(w) => {
                 debug!("Dropping log viewer window");
-                drop(some);
+                self.log_viewer_geometry = w.geometry();
+                drop(w);
+                self.save_state();
             }
         }
     }
@@ -246,173 +688,2168 @@ impl GTKApp {
             None => debug!("Log viewer window is None; nothing to close"),
             Some(w) => {
                 debug!("Closing log viewer window");
+                self.log_viewer_geometry = w.geometry();
                 w.close();
                 drop(w);
+                self.save_state();
             }
         }
     }
-    /// Set the notification method.
-    fn set_notify_method(&mut self, method: NotifyMethod) {
-        info!("Setting notify method to {}", method);
-        self.notify_method = method;
+    /// Save the backlog to a file, without ANSI colors, and notify the user of the result.
+    fn save_backlog(&self, path: PathBuf) {
+        let contents = {
+            let pm = util::rwlock_read(&self.profile_manager);
+            let backlog = mutex_lock(&pm.backlog);
+            log_parser::strip_ansi(&backlog.contents()).into_owned()
+        };
+        match fs::write(&path, contents) {
+            Ok(_) => {
+                info!("Saved sslocal logs to {:?}", path);
+                let text_2 = format!("Saved sslocal logs to {}", path.display());
+                self.notify(Level::Info, "Logs Saved", text_2);
+            }
+            Err(err) => {
+                error!("Cannot save sslocal logs to {:?}: {}", path, err);
+                self.notify(Level::Error, "Cannot Save Logs", err.to_string());
+            }
+        }
     }
-    /// Restart the `sslocal` instance with the current profile.
-    fn restart(&mut self) {
-        match util::rwlock_read(&self.profile_manager).current_profile() {
-            Some(p) => {
-                let name = p.metadata.display_name.clone();
-                info!("Restarting profile \"{}\"", name);
-                let switch_res = util::rwlock_write(&self.profile_manager).switch_to(p);
-                if let Err(err) = switch_res {
-                    error!("Failed to restart profile \"{}\": {}", name, err);
-                }
+    /// Show the traffic stats window, if not already shown.
+    fn show_stats_window(&mut self) {
+        match self.stats_window.as_ref() {
+            Some(w) => {
+                debug!("Stats window already showing; bringing to foreground");
+                w.show();
+            }
+            None => {
+                let pm_inner = util::rwlock_read(&self.profile_manager);
+                let events_tx = self.events_tx.clone();
+                let stats = Arc::clone(&pm_inner.stats);
+
+                debug!("Opening stats window.");
+                let window = StatsWindow::new(events_tx, stats, pm_inner.collect_stats, self.stats_window_geometry);
+                window.show();
+
+                self.stats_window = Some(window);
             }
-            None => warn!("Cannot restart because no sslocal instance is running"),
         }
     }
-    /// Switch to the specified profile.
-    fn switch_profile(&mut self, profile: Profile) {
-        let name = profile.metadata.display_name.clone();
-        info!("Switching profile to \"{}\"", name);
-        let switch_res = util::rwlock_write(&self.profile_manager).switch_to(profile);
-        if let Err(err) = switch_res {
-            error!("Cannot switch to profile \"{}\": {}", name, err);
+    /// Drop the stats window without emitting an extra close event.
+    fn drop_stats_window(&mut self) {
+        match self.stats_window.take() {
+            None => debug!("Stats window is None; nothing to drop"),
+            Some(w) => {
+                debug!("Dropping stats window");
+                self.stats_window_geometry = w.geometry();
+                drop(w);
+                self.save_state();
+            }
         }
     }
-    /// Stop the current `sslocal` instance.
-    fn stop(&mut self) {
-        let mut pm_inner = util::rwlock_write(&self.profile_manager);
-        if pm_inner.is_active() {
-            info!("Sending stop signal to sslocal");
-            let _ = pm_inner.try_stop();
-        } else {
-            info!("sslocal is not running; nothing to stop");
+    /// Enable or disable traffic stats collection, restarting `sslocal` if necessary.
+    fn set_collect_stats(&mut self, enable: bool) {
+        info!("Setting traffic stats collection to {}", enable);
+        let is_active = {
+            let mut pm_inner = util::rwlock_write(&self.profile_manager);
+            pm_inner.collect_stats = enable;
+            pm_inner.is_active()
+        };
+        if is_active {
+            self.restart();
+        }
+        if !enable {
+            self.tray.set_label("");
         }
     }
-    /// Quit the application.
-    fn quit(&mut self) {
-        info!("Quit");
-
-        // cleanup
-        // save app state
-        match self.snapshot().write_to_file(&self.app_state_path) {
-            Ok(_) => info!("App state saved to {:?}", self.app_state_path),
-            Err(err) => error!("Failed to save app state: {}", err),
+    /// Make `sslocal` log one level more verbosely or not, restarting it if necessary.
+    fn set_verbose_logs(&mut self, enable: bool) {
+        info!("Setting verbose sslocal logs to {}", enable);
+        let is_active = {
+            let mut pm_inner = util::rwlock_write(&self.profile_manager);
+            pm_inner.verbose = enable;
+            pm_inner.is_active()
         };
-        // stop any running `sslocal` process
-        let _ = util::rwlock_write(&self.profile_manager).try_stop();
-
-        // drop all optional windows
-        debug!("Closing all optional windows");
-        drop(self.log_viewer_window.take());
-
-        gtk::main_quit();
+        if is_active {
+            self.restart();
+        }
     }
-
-    /// Handles the queued incoming app events.
-    fn handle_app_events(&mut self) {
-        use AppEvent::*;
-        // using `while let` rather than `for` due to borrow checker issue
-        while let Some(event) = self.events_rx.try_iter().next() {
-            trace!("Received an AppEvent: {:?}", event);
-            match event {
-                LogViewerShow => self.show_log_viewer(),
-                LogViewerHide => self.drop_log_viewer(),
-                SwitchProfile(p) => self.switch_profile(p),
-                ManualStop => self.stop(),
-                SetNotify(method) => self.set_notify_method(method),
-                Quit => self.quit(),
-
-                OkStop { instance_name } => {
-                    // this event could be received because an old instance is stopped
-                    // and a new one is started, therefore we first check for active instance
-                    if !util::rwlock_read(&self.profile_manager).is_active() {
-                        self.tray.notify_sslocal_stop();
-                        let text_2 = format!("An instance has stopped: {}", instance_name.unwrap_or("None".into()));
-                        notify(self.notify_method, Level::Warn, "Auto-restart Stopped", text_2);
-                    }
+    /// Refresh the throughput shown next to the tray icon.
+    fn update_stats_label(&mut self) {
+        let pm_inner = util::rwlock_read(&self.profile_manager);
+        if !pm_inner.collect_stats {
+            return;
+        }
+        let stats = *util::rwlock_read(&pm_inner.stats);
+        drop(pm_inner);
+        match stats.updated_at {
+            Some(_) => self.tray.set_label(&stats.rate_summary()),
+            None => self.tray.set_label(""),
+        }
+    }
+    /// Refresh the connection summary shown when hovering over the tray icon.
+    fn update_tray_tooltip(&mut self) {
+        let pm_inner = util::rwlock_read(&self.profile_manager);
+        let mut tooltip = match (pm_inner.current_profile(), pm_inner.instance_status()) {
+            (Some(profile), Some(status)) => {
+                let mut tooltip = format!(
+                    "{}: connected for {}, {} restarts",
+                    profile.metadata.display_name,
+                    format_duration(status.connected_for.as_secs()),
+                    status.restarts
+                );
+                if let Some(connections) = status.connections {
+                    tooltip += &format!(", {} open connections", connections);
                 }
-                ErrorStop { instance_name, err } => {
-                    self.tray.notify_sslocal_stop();
-                    let text_2 = format!(
-                        "An instance has errored: {}\n{}",
-                        instance_name.unwrap_or("None".into()),
-                        err
-                    );
-                    notify(self.notify_method, Level::Error, "Auto-restart Stopped", text_2);
+                if let Some(err) = status.last_error {
+                    tooltip += &format!("\nLast error: {}", err);
                 }
+                tooltip
             }
+            _ => "Not connected".into(),
+        };
+        drop(pm_inner);
+        if !self.alongside.is_empty() {
+            let names: Vec<_> = self.alongside.keys().map(String::as_str).collect();
+            tooltip += &format!("\nAlso running: {}", names.join(", "));
         }
+        self.tray.set_tooltip(&tooltip);
     }
-
-    /// Handles the queued incoming runtime API commands.
-    #[cfg(feature = "runtime-api")]
-    fn handle_api_commands(&mut self) {
-        use APICommand::*;
-        // using `while let` rather than `for` due to borrow checker issue
-        while let Some(cmd) = self.api_cmds_rx.try_iter().next() {
-            match cmd {
-                LogViewerShow => self.show_log_viewer(),
-                LogViewerHide => self.close_log_viewer(),
-                SetNotify(method) => {
-                    self.set_notify_method(method);
-                    self.tray.notify_notify_method_change(method);
+    /// Import a profile from a SIP002 `ss://` URL into the profiles directory,
+    /// then reload all profiles.
+    fn import_from_url(&mut self, url: String) {
+        let config = match ProfileConfig::from_ss_url(&url) {
+            Ok(c) => c,
+            Err(err) => {
+                error!("Cannot import profile from URL: {}", err);
+                self.notify(Level::Error, "Import Failed", err.to_string());
+                return;
+            }
+        };
+        match self.write_imported_profile(&config, &self.profiles_dir) {
+            Ok(display_name) => {
+                let text_2 = format!("Imported profile \"{}\"", display_name);
+                self.notify(Level::Info, "Import Succeeded", text_2);
+                self.reload_profiles();
+            }
+            Err(text_2) => self.notify(Level::Error, "Import Failed", text_2),
+        }
+    }
+    /// Import all servers in a file exported by another client as new profiles,
+    /// skipping those that cannot be written.
+    fn import_from_file(&mut self, path: PathBuf) {
+        let configs = match profile_import::import_file(&path) {
+            Ok(c) => c,
+            Err(err) => {
+                error!("Cannot import profiles from {:?}: {}", path, err);
+                self.notify(Level::Error, "Import Failed", err.to_string());
+                return;
+            }
+        };
+        let total = configs.len();
+        let names: Vec<_> = configs
+            .iter()
+            .map(|config| {
+                config
+                    .display_name()
+                    .map_or_else(|| config.suggested_dir_name(), str::to_string)
+            })
+            .collect();
+        let regions = match self.settings.group_imports_by_region {
+            true => region::assign_regions(names.iter().map(String::as_str)),
+            false => None,
+        };
+        let imported = configs
+            .iter()
+            .enumerate()
+            .filter(|(i, config)| {
+                let parent = match regions.as_ref().map(|r| self.profiles_dir.join(r[*i])) {
+                    // a profile that happens to be named after the region cannot hold other profiles
+                    Some(dir) if ConfigFormat::find_all(&dir).is_empty() => dir,
+                    _ => self.profiles_dir.clone(),
+                };
+                self.write_imported_profile(config, &parent).is_ok()
+            })
+            .count();
+        if imported == 0 {
+            let text_2 = format!("None of the {} profiles can be imported; see logs for details", total);
+            self.notify(Level::Error, "Import Failed", text_2);
+            return;
+        }
+        let text_2 = match total - imported {
+            0 => format!("Imported {} profiles", imported),
+            skipped => format!(
+                "Imported {} profiles; skipped {}, see logs for details",
+                imported, skipped
+            ),
+        };
+        self.notify(Level::Info, "Import Succeeded", text_2);
+        self.reload_profiles();
+    }
+    /// Write an imported profile into a new directory under `parent`, unless its name is taken.
+    ///
+    /// Returns its display name, or the reason of failure.
+    fn write_imported_profile(&self, config: &ProfileConfig, parent: &Path) -> Result<String, String> {
+        let dir_name = config.suggested_dir_name();
+        let display_name = config.display_name().unwrap_or(&dir_name).to_string();
+        if self.profile_folder.lookup(&display_name).is_some() {
+            error!(
+                "Cannot import profile: a profile named \"{}\" already exists",
+                display_name
+            );
+            return Err(format!("A profile named \"{}\" already exists", display_name));
+        }
+        match config.write_to_new_dir(parent, &dir_name) {
+            Ok(dir) => {
+                info!("Imported profile \"{}\" into {:?}", display_name, dir);
+                Ok(display_name)
+            }
+            Err(err) => {
+                error!("Cannot write imported profile \"{}\": {}", display_name, err);
+                Err(err.to_string())
+            }
+        }
+    }
+    /// Work out what the files, directories or text dropped onto a window can be imported as,
+    /// and let the user choose which ones to import.
+    fn on_drop_received(&mut self, paths: Vec<PathBuf>, text: Option<String>) {
+        let url_choice = |url: String| (describe_ss_url(&url), AppEvent::ImportFromUrl(url));
+        let mut choices = vec![];
+        let mut problems = vec![];
+        for path in paths {
+            match profile_import::inspect_dropped(&path) {
+                Ok(Dropped::ProfileDir(dir)) => {
+                    let desc = format!("Profile directory {}", dir.display());
+                    choices.push((desc, AppEvent::ImportProfileDir(dir)));
                 }
-
-                Restart => self.restart(),
-                SwitchProfile(name) => match self.profile_folder.lookup(&name).cloned() {
-                    Some(p) => {
-                        self.switch_profile(p);
-                        self.tray.notify_profile_switch(&name);
-                    }
-                    None => error!("Cannot find a profile named \"{}\"; did nothing", name),
-                },
-                Stop => {
-                    self.stop();
-                    self.tray.notify_sslocal_stop();
+                Ok(Dropped::Servers(path, count)) => {
+                    let desc = format!("{} servers in {}", count, path.display());
+                    choices.push((desc, AppEvent::ImportFromFile(path)));
                 }
-                Quit => self.quit(),
+                Ok(Dropped::Urls(urls)) => choices.extend(urls.into_iter().map(url_choice)),
+                Err(err) => {
+                    warn!("Cannot import dropped {:?}: {}", path, err);
+                    problems.push(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+        if let Some(text) = text {
+            let urls = profile_import::find_ss_urls(&text);
+            if urls.is_empty() {
+                warn!("Cannot import dropped text: no ss:// URLs found");
+                problems.push("The dropped text contains no ss:// URLs".into());
             }
+            choices.extend(urls.into_iter().map(url_choice));
+        }
+        if choices.is_empty() {
+            self.notify(Level::Warn, "Nothing to Import", problems.join("\n"));
+            return;
         }
+        show_import_choices_dialog(self.events_tx.clone(), "Import Dropped Items", choices);
     }
-}
-
-/// Initialize all components and start the GTK main loop.
-pub fn run(args: &CliArgs) -> Result<(), AppStartError> {
-    // init app
-    let mut app = GTKApp::new(args)?;
-
-    // catch signals for soft shutdown
-    let shutdown_trigger_count = Arc::new(Mutex::new(0usize));
-    let events_tx = app.events_tx.clone();
-    ctrlc::set_handler(move || {
-        let mut count = util::mutex_lock(&shutdown_trigger_count);
-        match *count {
-            0 => {
-                info!("Signal received, sending Quit event");
-                if let Err(_) = events_tx.send(AppEvent::Quit) {
-                    error!("Trying to send Quit event for soft shutdown, but all receivers have hung up");
-                    error!("Performing hard shutdown; the app state may be unsaved");
-                    process::exit(0);
-                }
+    /// Copy a profile directory from elsewhere into the profiles directory, then load it.
+    fn import_profile_dir(&mut self, src: PathBuf) {
+        match self.copy_profile_dir(&src) {
+            Ok(display_name) => {
+                let text_2 = format!("Imported profile \"{}\"", display_name);
+                self.notify(Level::Info, "Import Succeeded", text_2);
+                self.reload_profiles();
             }
-            1 => warn!("Send one more signal for hard shutdown"),
-            _ => {
-                warn!("Performing hard shutdown; the app state may be unsaved");
-                process::exit(0);
+            Err(text_2) => self.notify(Level::Error, "Import Failed", text_2),
+        }
+    }
+    /// Copy a profile directory into the profiles directory, unless it is already in there,
+    /// or its name is taken.
+    ///
+    /// Returns its display name, or the reason of failure.
+    fn copy_profile_dir(&self, src: &Path) -> Result<String, String> {
+        let in_profiles_dir = match (src.canonicalize(), self.profiles_dir.canonicalize()) {
+            (Ok(src), Ok(profiles_dir)) => src.starts_with(profiles_dir),
+            (Err(err), _) | (_, Err(err)) => {
+                error!("Cannot import profile directory {:?}: {}", src, err);
+                return Err(err.to_string());
             }
+        };
+        if in_profiles_dir {
+            warn!("Profile directory {:?} is already in the profiles directory", src);
+            return Err(format!("{} is already in the profiles directory", src.display()));
         }
-        *count += 1;
-    })?;
-
-    // starts looping event listeners
-    let loop_action_id = glib::timeout_add_local(
-        Duration::from_millis(10), // 100fps
-        move || {
-            app.handle_app_events();
-
-            #[cfg(feature = "runtime-api")]
-            app.handle_api_commands();
+        let config = ProfileConfig::read_from_dir(src).map_err(|err| {
+            error!("Cannot import profile directory {:?}: {}", src, err);
+            err.to_string()
+        })?;
+        let dir_name = src.file_name().unwrap_or_default().to_string_lossy();
+        let display_name = config.display_name().unwrap_or(&dir_name).to_string();
+        if self.profile_folder.lookup(&display_name).is_some() {
+            error!(
+                "Cannot import profile: a profile named \"{}\" already exists",
+                display_name
+            );
+            return Err(format!("A profile named \"{}\" already exists", display_name));
+        }
+        match profile_loader::copy_profile_dir(src, &self.profiles_dir) {
+            Ok(dir) => {
+                info!("Imported profile \"{}\" into {:?}", display_name, dir);
+                Ok(display_name)
+            }
+            Err(err) => {
+                error!("Cannot copy profile directory {:?}: {}", src, err);
+                Err(err.to_string())
+            }
+        }
+    }
+    /// Export all profiles as a manifest, for backup or migration.
+    fn export_profiles(&mut self, path: PathBuf, strip_passwords: bool) {
+        // subscriptions are left out, since they are regenerated from the subscription file
+        // profiles that cannot be loaded are left out too
+        let res = load_profile_folder(&self.profiles_dir, &self.load_opts, false)
+            .map_err(|err| err.to_string())
+            .and_then(|(pf, _)| {
+                pf.export_manifest(&path, strip_passwords)
+                    .map_err(|err| err.to_string())?;
+                Ok(pf.profile_count())
+            });
+        match res {
+            Ok(count) => {
+                info!("Exported {} profiles to {:?}", count, path);
+                let text_2 = format!("Exported {} profiles to {}", count, path.display());
+                self.notify(Level::Info, "Export Succeeded", text_2);
+            }
+            Err(err) => {
+                error!("Cannot export profiles to {:?}: {}", path, err);
+                self.notify(Level::Error, "Export Failed", err);
+            }
+        }
+    }
+    /// Scan the screen for `ss://` QR codes in the background.
+    fn scan_qr_from_screen(&mut self) {
+        info!("Scanning screen for QR codes");
+        if let Err(err) = qr_scan::spawn_scan(self.events_tx.clone()) {
+            error!("Cannot start QR scan: {}", err);
+            self.notify(Level::Error, "QR Scan Failed", err.to_string());
+        }
+    }
+    /// Stop `sslocal` before the system sleeps, so that no connections are left half-open on resume.
+    ///
+    /// Unlike `Self::stop`, the profile is still remembered in the app state,
+    /// in case the system does not wake up properly.
+    fn prepare_for_sleep(&mut self) {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let name = match current {
+            Some(p) => p.metadata.display_name,
+            None => return,
+        };
+        info!("Stopping profile \"{}\" before sleep", name);
+        let _ = util::rwlock_write(&self.profile_manager).try_stop();
+        self.tray.notify_sslocal_stop();
+        self.sync_system_proxy();
+        self.sync_keep_awake();
+        self.slept_profile = Some(name);
+    }
+    /// Restore the profile stopped before the system went to sleep.
+    fn resume_from_sleep(&mut self) {
+        let name = match self.slept_profile.take() {
+            Some(name) => name,
+            None => return,
+        };
+        // the user may have connected in between, e.g. if the sleep monitor gave up waiting
+        if util::rwlock_read(&self.profile_manager).is_active() {
+            return;
+        }
+        match self.profile_folder.lookup(&name).cloned() {
+            Some(p) => {
+                info!("Restoring profile \"{}\" after sleep", name);
+                self.switch_profile(p);
+                self.tray.notify_profile_switch(&name);
+            }
+            None => warn!("Profile \"{}\" no longer exists; cannot restore it after sleep", name),
+        }
+    }
+    /// Carry out the rule for the newly active network if there is one,
+    /// otherwise restart `sslocal` so that it does not hold on to connections of the previous network.
+    ///
+    /// Only rules are considered for the network found on startup.
+    fn on_network_changed(&mut self, network: Option<String>, initial: bool) {
+        let network = match network {
+            Some(n) => n,
+            None => {
+                info!("No network is active");
+                return;
+            }
+        };
+        let rule = self.settings.network_rules.iter().find(|r| r.network == network);
+        if let Some(rule) = rule {
+            info!("Network \"{}\" is active; carrying out {:?}", network, rule.action);
+            if self.events_tx.send(rule.action.to_event()).is_err() {
+                error!("Trying to send network rule event, but all receivers have hung up.");
+            }
+            return;
+        }
+        if initial || !self.settings.restart_on_network_change {
+            return;
+        }
+        if util::rwlock_read(&self.profile_manager).current_profile().is_some() {
+            info!("Network changed to \"{}\"; restarting sslocal", network);
+            self.restart();
+        }
+    }
+    /// Match the widgets and the tray icon to the desktop's color scheme.
+    fn apply_color_scheme(&mut self, scheme: ColorScheme) {
+        use gtk::traits::SettingsExt; // not in the prelude because it clashes with `gio::prelude::SettingsExt`
+
+        debug!("Applying color scheme {:?}", scheme);
+        match gtk::Settings::default() {
+            Some(settings) => settings.set_gtk_application_prefer_dark_theme(scheme.is_dark()),
+            None => warn!("Cannot access GTK settings to apply the color scheme"),
+        }
+        self.tray.set_color_scheme(scheme);
+    }
+    /// Handle the completion of a QR scan by letting the user pick which URLs to import.
+    fn on_qr_scanned(&mut self, res: Result<Vec<String>, String>) {
+        match res {
+            Ok(urls) if urls.is_empty() => {
+                info!("No ss:// QR code found on screen");
+                let text_2 = "No ss:// QR code found on screen";
+                self.notify(Level::Warn, "QR Scan Finished", text_2);
+            }
+            Ok(urls) => show_urls_import_dialog(self.events_tx.clone(), "Import from QR Codes", urls),
+            Err(err) => {
+                error!("QR scan has failed: {}", err);
+                self.notify(Level::Error, "QR Scan Failed", err);
+            }
+        }
+    }
+    /// Start or stop watching the clipboard for `ss://` URLs.
+    fn set_clipboard_monitor(&mut self, enable: bool) {
+        info!("Setting clipboard monitor to {}", enable);
+        self.clipboard_monitor.set_enabled(enable);
+        self.save_state();
+    }
+    /// Enable or disable keeping the system awake while connected.
+    fn set_keep_awake(&mut self, enable: bool) {
+        info!("Setting keep awake while connected to {}", enable);
+        let connected = util::rwlock_read(&self.profile_manager).is_active();
+        if let Err(err) = self.keep_awake.set_enabled(enable, connected) {
+            error!("Cannot keep the system awake: {}", err);
+            self.notify(Level::Error, "Cannot Keep Awake", err.to_string());
+        }
+        self.save_state();
+    }
+    /// Offer to import `ss://` URLs newly copied to the clipboard.
+    ///
+    /// With toast notifications, the import dialog is opened from the notification;
+    /// with prompts, it is opened right away.
+    fn on_clipboard_urls_found(&mut self, urls: Vec<String>) {
+        let text_2 = match &urls[..] {
+            [url] => format!("Copied {}; click to import it", describe_ss_url(url)),
+            _ => format!("Copied {} servers; click to import them", urls.len()),
+        };
+        let method = match self.notify_method {
+            NotifyMethod::Prompt if self.tray_only => NotifyMethod::Toast,
+            method => method,
+        };
+        if !self.settings.notify_level.allows(Level::Info) {
+            notify_log(Level::Info, "ss:// Link Copied", &text_2);
+            return;
+        }
+        match method {
+            NotifyMethod::Toast => {
+                let events_tx = self.events_tx.clone();
+                let res = notify_toast_with_action("ss:// Link Copied", &text_2, "Import…", move || {
+                    if events_tx.send(AppEvent::ClipboardImportShow(urls)).is_err() {
+                        error!("Trying to send ClipboardImportShow event, but all receivers have hung up.");
+                    }
+                });
+                if let Err(err) = res {
+                    error!("Failed to show toast notification: {}", err);
+                }
+            }
+            NotifyMethod::Prompt => show_urls_import_dialog(self.events_tx.clone(), "Import from Clipboard", urls),
+            NotifyMethod::Log => notify_log(Level::Info, "ss:// Link Copied", &text_2),
+            NotifyMethod::Disable => {}
+        }
+    }
+    /// Show the QR code of the currently running profile.
+    ///
+    /// Only "Proxy"-type profiles can be exported.
+    fn show_qr_export(&mut self) {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let profile = match current {
+            Some(p) => p,
+            None => {
+                warn!("No profile is running; cannot show QR code");
+                let text_2 = "No profile is running";
+                self.notify(Level::Warn, "Cannot Show QR Code", text_2);
+                return;
+            }
+        };
+        let name = &profile.metadata.display_name;
+        let config = match profile.config().resolve_passwords() {
+            Ok(config) => config,
+            Err(err) => {
+                error!("Cannot look up password of profile \"{}\": {}", name, err);
+                self.notify(Level::Error, "Cannot Show QR Code", err.to_string());
+                return;
+            }
+        };
+        let url = match config.to_ss_url(name) {
+            Some(url) => url,
+            None => {
+                warn!("Profile \"{}\" is not in proxy mode; cannot show QR code", name);
+                let text_2 = format!("Profile \"{}\" is not in proxy mode", name);
+                self.notify(Level::Warn, "Cannot Show QR Code", text_2);
+                return;
+            }
+        };
+        if let Err(err) = show_qr_export_dialog(name, &url) {
+            error!("Cannot generate QR code for profile \"{}\": {}", name, err);
+            self.notify(Level::Error, "Cannot Show QR Code", err.to_string());
+        }
+    }
+    /// Show what the currently running profile is launched with, with passwords masked.
+    fn show_launch_preview(&mut self) {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let profile = match current {
+            Some(p) => p,
+            None => {
+                warn!("No profile is running; cannot show launch command");
+                let text_2 = "No profile is running";
+                self.notify(Level::Warn, "Cannot Show Launch Command", text_2);
+                return;
+            }
+        };
+        let name = &profile.metadata.display_name;
+        match profile.launch_preview(false) {
+            Ok(preview) => show_launch_preview_dialog(&profile, &preview),
+            Err(err) => {
+                error!("Cannot get launch command of profile \"{}\": {}", name, err);
+                self.notify(Level::Error, "Cannot Show Launch Command", err.to_string());
+            }
+        }
+    }
+    /// Enable or disable writing `sslocal`'s output into log files.
+    fn set_log_file(&mut self, enable: bool) {
+        info!("Setting log files to {}", enable);
+        util::rwlock_write(&self.profile_manager).set_log_file_enabled(enable);
+    }
+    /// Open the log file of the running profile with the default application.
+    fn open_log_file(&mut self) {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let path = match current {
+            Some(p) => log_file_path(&p.metadata.display_name),
+            None => {
+                warn!("No profile is running; cannot open log file");
+                let text_2 = "No profile is running";
+                self.notify(Level::Warn, "Cannot Open Log File", text_2);
+                return;
+            }
+        };
+        if !path.exists() {
+            warn!("Log file {:?} does not exist", path);
+            let text_2 = "No log file has been written yet; enable \"Write Logs to File\" first";
+            self.notify(Level::Warn, "Cannot Open Log File", text_2);
+            return;
+        }
+        debug!("Opening log file {:?}", path);
+        let res = glib::filename_to_uri(&path, None)
+            .and_then(|uri| gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>));
+        if let Err(err) = res {
+            error!("Cannot open log file {:?}: {}", path, err);
+            self.notify(Level::Error, "Cannot Open Log File", err.to_string());
+        }
+    }
+    /// Show the profile editor, either for a new profile,
+    /// or for the profile of the currently running instance.
+    fn show_profile_editor(&mut self, edit_current: bool) {
+        if !edit_current {
+            show_profile_editor(self.events_tx.clone(), &self.profiles_dir, None);
+            return;
+        }
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        match current {
+            // the editor only has fields for a single server
+            Some(p) if matches!(p.config(), ProfileConfig::Balanced { .. }) => {
+                let text_2 = format!(
+                    "Profile \"{}\" has multiple servers; please edit its config file by hand",
+                    p.metadata.display_name
+                );
+                self.notify(Level::Error, "Cannot Edit Profile", text_2);
+            }
+            Some(p) => match &p.metadata.config_dir {
+                Some(dir) => show_profile_editor(
+                    self.events_tx.clone(),
+                    &self.profiles_dir,
+                    Some((dir.clone(), p.config())),
+                ),
+                None => {
+                    let text_2 = format!("Profile \"{}\" is not editable", p.metadata.display_name);
+                    self.notify(Level::Error, "Cannot Edit Profile", text_2);
+                }
+            },
+            None => {
+                warn!("Cannot edit current profile because no sslocal instance is running");
+                let text_2 = "No profile is currently running".to_string();
+                self.notify(Level::Error, "Cannot Edit Profile", text_2);
+            }
+        }
+    }
+    /// Write a profile saved by the profile editor to disk, then reload all profiles.
+    fn save_profile(&mut self, dir: PathBuf, config: ProfileConfig, is_new: bool) {
+        let dir_name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let display_name = config.display_name().unwrap_or(&dir_name).to_string();
+        let conflict = self
+            .profile_folder
+            .lookup(&display_name)
+            .filter(|p| is_new || p.metadata.config_dir.as_ref() != Some(&dir));
+        if conflict.is_some() {
+            error!(
+                "Cannot save profile: a profile named \"{}\" already exists",
+                display_name
+            );
+            let text_2 = format!("A profile named \"{}\" already exists", display_name);
+            self.notify(Level::Error, "Save Failed", text_2);
+            return;
+        }
+        if is_new && dir.exists() {
+            error!("Cannot save profile: {:?} already exists", dir);
+            let text_2 = format!("Directory \"{}\" already exists", dir_name);
+            self.notify(Level::Error, "Save Failed", text_2);
+            return;
+        }
+        let write_res = fs::create_dir_all(&dir).and_then(|_| config.write_to_dir(&dir));
+        match write_res {
+            Ok(_) => {
+                info!("Saved profile \"{}\" into {:?}", display_name, dir);
+                let text_2 = format!("Saved profile \"{}\"", display_name);
+                self.notify(Level::Info, "Profile Saved", text_2);
+                self.reload_profiles();
+            }
+            Err(err) => {
+                error!("Cannot write profile \"{}\": {}", display_name, err);
+                self.notify(Level::Error, "Save Failed", err.to_string());
+            }
+        }
+    }
+    /// Show the dialog for copying the current profile, or one of the templates if `from_template` is set.
+    fn show_profile_duplicate(&mut self, from_template: bool) {
+        if !from_template {
+            let current = util::rwlock_read(&self.profile_manager).current_profile();
+            match current {
+                Some(p) => show_profile_duplicate_dialog(self.events_tx.clone(), "Duplicate Profile", vec![p]),
+                None => {
+                    warn!("Cannot duplicate current profile because no sslocal instance is running");
+                    let text_2 = "No profile is currently running".to_string();
+                    self.notify(Level::Error, "Cannot Duplicate Profile", text_2);
+                }
+            }
+            return;
+        }
+        if !self.templates_dir.is_dir() {
+            let text_2 = format!(
+                "Put profile directories into {:?} to use them as templates",
+                self.templates_dir
+            );
+            self.notify(Level::Warn, "No Templates", text_2);
+            return;
+        }
+        match load_profile_folder(&self.templates_dir, &self.load_opts, false) {
+            Ok((templates, _)) => {
+                let templates = templates.get_profiles().into_iter().cloned().collect();
+                show_profile_duplicate_dialog(self.events_tx.clone(), "New from Template", templates);
+            }
+            Err(err) => {
+                error!("Failed to load templates from {:?}: {}", self.templates_dir, err);
+                self.notify(Level::Error, "Cannot Load Templates", err.to_string());
+            }
+        }
+    }
+    /// Show the dialog for deleting a profile, with the current profile chosen initially.
+    ///
+    /// Only profiles with a directory of their own can be deleted.
+    fn show_profile_delete(&mut self) {
+        let profiles: Vec<_> = (self.profile_folder.get_profiles().into_iter())
+            .filter(|p| p.own_dir().is_some())
+            .cloned()
+            .collect();
+        if profiles.is_empty() {
+            let text_2 = "Profiles from manifests and subscriptions cannot be deleted here";
+            self.notify(Level::Warn, "No Profiles to Delete", text_2);
+            return;
+        }
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let current_name = current.as_ref().map(|p| p.metadata.display_name.as_str());
+        show_profile_delete_dialog(self.events_tx.clone(), profiles, current_name);
+    }
+    /// Delete a profile by moving its directory to the trash or into the archive,
+    /// stopping it first if running, then reload all profiles.
+    fn delete_profile(&mut self, profile: Profile, archive: bool) {
+        let name = profile.metadata.display_name.clone();
+        let dir = match profile.own_dir() {
+            Some(dir) => dir.to_path_buf(),
+            None => {
+                warn!("Cannot delete profile \"{}\": it has no directory of its own", name);
+                let text_2 = format!("Profile \"{}\" has no directory of its own", name);
+                self.notify(Level::Error, "Delete Failed", text_2);
+                return;
+            }
+        };
+        let is_current = util::rwlock_read(&self.profile_manager)
+            .current_profile()
+            .is_some_and(|p| p.metadata.display_name == name);
+        if is_current {
+            info!("Stopping profile \"{}\" to delete it", name);
+            self.stop();
+            self.tray.notify_sslocal_stop();
+        }
+        if self.alongside.contains_key(&name) {
+            self.set_alongside(profile, false);
+            self.tray.notify_alongside_change(&name, false);
+        }
+        let res = match archive {
+            true => profile_loader::archive_profile_dir(&dir, &self.profiles_dir)
+                .map(|to| format!("Archived profile \"{}\" into {}", name, to.display())),
+            false => (gio::File::for_path(&dir).trash(None::<&gio::Cancellable>))
+                .map(|_| format!("Moved profile \"{}\" to trash", name))
+                .map_err(io::Error::other),
+        };
+        match res {
+            Ok(text_2) => {
+                info!("{}", text_2);
+                self.notify(Level::Info, "Profile Deleted", text_2);
+                self.reload_profiles();
+            }
+            Err(err) => {
+                error!("Cannot delete profile \"{}\" in {:?}: {}", name, dir, err);
+                self.notify(Level::Error, "Delete Failed", err.to_string());
+            }
+        }
+    }
+    /// Copy a profile into a new profile directory, reload all profiles,
+    /// then open the copy in the profile editor if possible.
+    fn duplicate_profile(&mut self, source: Profile, display_name: String) {
+        if display_name.is_empty() {
+            self.notify(Level::Error, "Duplicate Failed", "The name of the new profile is empty");
+            return;
+        }
+        if self.profile_folder.lookup(&display_name).is_some() {
+            error!(
+                "Cannot duplicate profile: a profile named \"{}\" already exists",
+                display_name
+            );
+            let text_2 = format!("A profile named \"{}\" already exists", display_name);
+            self.notify(Level::Error, "Duplicate Failed", text_2);
+            return;
+        }
+        let dir = match source.duplicate_into(&self.profiles_dir, &display_name) {
+            Ok(dir) => dir,
+            Err(err) => {
+                error!(
+                    "Cannot copy profile \"{}\" as \"{}\": {}",
+                    source.metadata.display_name, display_name, err
+                );
+                self.notify(Level::Error, "Duplicate Failed", err.to_string());
+                return;
+            }
+        };
+        info!(
+            "Copied profile \"{}\" as \"{}\" into {:?}",
+            source.metadata.display_name, display_name, dir
+        );
+        let text_2 = format!("Created profile \"{}\"", display_name);
+        self.notify(Level::Info, "Profile Created", text_2);
+        self.reload_profiles();
+
+        // most copies need their server changed right away
+        match self.profile_folder.lookup(&display_name) {
+            Some(p) if !matches!(p.config(), ProfileConfig::Balanced { .. }) => {
+                show_profile_editor(self.events_tx.clone(), &self.profiles_dir, Some((dir, p.config())))
+            }
+            _ => {}
+        }
+    }
+    /// Reload all profiles from the profiles directory and rebuild the tray menu.
+    ///
+    /// The running `sslocal` instance (if any) is unaffected.
+    fn reload_profiles(&mut self) {
+        let (mut profile_folder, mut load_problems) =
+            match load_profile_folder(&self.profiles_dir, &self.load_opts, true) {
+                Ok(res) => res,
+                Err(err) => {
+                    error!("Failed to reload profiles: {}", err);
+                    self.notify(Level::Error, "Reload Failed", err.to_string());
+                    return;
+                }
+            };
+        // keep the groups opened so far loaded
+        self.loaded_groups.retain(|dir| {
+            match profile_folder.load_group(dir, &self.profiles_dir, &self.load_opts, &mut load_problems) {
+                Ok(found) => found,
+                Err(err) => {
+                    warn!("Failed to reload group {:?}: {}", dir, err);
+                    false
+                }
+            }
+        });
+        debug!(
+            "Successfully reloaded {} profiles in total",
+            profile_folder.profile_count()
+        );
+        self.profile_folder = profile_folder;
+        self.rebuild_tray_menu();
+        self.set_load_problems(load_problems);
+
+        #[cfg(feature = "dbus-api")]
+        if let Some(service) = self.dbus_service.as_ref() {
+            let profile_names = self
+                .profile_folder
+                .get_profiles()
+                .into_iter()
+                .map(|p| p.metadata.display_name.clone())
+                .collect();
+            service.set_profiles(profile_names);
+        }
+        self.sync_gfwlist_wanted();
+    }
+    /// Rebuild the tray menu from the loaded profiles, restoring its state.
+    fn rebuild_tray_menu(&mut self) {
+        self.tray.reload_profiles(&self.profile_folder, &self.snapshot());
+        self.tray.show_latencies(&self.latencies);
+        match util::rwlock_read(&self.profile_manager).current_profile() {
+            Some(p) => self.tray.notify_profile_switch(p.metadata.display_name),
+            None => self.tray.notify_sslocal_stop(),
+        }
+        self.tray.show_load_problems(self.load_problems.len());
+    }
+    /// Load the content of the unloaded group in `dir`, when it is opened in the tray menu.
+    fn load_group(&mut self, dir: PathBuf) {
+        let mut problems = vec![];
+        match (self.profile_folder).load_group(&dir, &self.profiles_dir, &self.load_opts, &mut problems) {
+            Ok(true) => {
+                debug!(
+                    "Successfully loaded group {:?}; {} profiles in total",
+                    dir,
+                    self.profile_folder.profile_count()
+                );
+                self.loaded_groups.insert(dir);
+            }
+            Ok(false) => return, // the menu may be opened again before it is rebuilt
+            Err(err) => {
+                error!("Failed to load group {:?}: {}", dir, err);
+                self.notify(Level::Error, "Loading Group Failed", err.to_string());
+                return;
+            }
+        }
+        self.rebuild_tray_menu();
+        self.add_load_problems(problems);
+
+        #[cfg(feature = "dbus-api")]
+        if let Some(service) = self.dbus_service.as_ref() {
+            let profile_names = self
+                .profile_folder
+                .get_profiles()
+                .into_iter()
+                .map(|p| p.metadata.display_name.clone())
+                .collect();
+            service.set_profiles(profile_names);
+        }
+        self.sync_gfwlist_wanted();
+    }
+    /// Replace the list of profiles that cannot be loaded, notifying the user if any of them are new.
+    fn set_load_problems(&mut self, problems: Vec<LoadProblem>) {
+        let has_new = problems.iter().any(|problem| {
+            let description = problem.to_string();
+            !self.load_problems.iter().any(|p| p.to_string() == description)
+        });
+        self.load_problems = problems;
+        self.on_load_problems_changed(has_new);
+    }
+    /// Add to the list of profiles that cannot be loaded, notifying the user if there are any.
+    fn add_load_problems(&mut self, problems: Vec<LoadProblem>) {
+        let has_new = !problems.is_empty();
+        self.load_problems.extend(problems);
+        self.on_load_problems_changed(has_new);
+    }
+    /// Show the number of load problems in the tray menu, and notify the user if `has_new` is set.
+    fn on_load_problems_changed(&mut self, has_new: bool) {
+        self.tray.show_load_problems(self.load_problems.len());
+        if has_new {
+            let text_2 = format!(
+                "{} profiles or groups cannot be loaded and are skipped; see \"Show Load Problems\" in the tray menu",
+                self.load_problems.len()
+            );
+            self.notify(Level::Warn, "Some Profiles Not Loaded", text_2);
+        }
+    }
+    /// Only list profiles with `tag` in the tray menu, or all of them if `None`.
+    fn set_tag_filter(&mut self, tag: Option<String>) {
+        if self.tag_filter == tag {
+            return;
+        }
+        debug!("Filtering tray menu by tag: {:?}", tag);
+        self.tag_filter = tag;
+        self.rebuild_tray_menu();
+    }
+    /// Tell the gfwlist updater whether gfwlist is used, i.e. by the PAC server or the routing rules of any profile.
+    fn sync_gfwlist_wanted(&self) {
+        let wanted = self.pac_server.is_some()
+            || self
+                .profile_folder
+                .get_profiles()
+                .iter()
+                .any(|p| p.config().uses_gfwlist());
+        self.gfwlist_updater.set_wanted(wanted);
+    }
+    /// Reload the rules that use gfwlist after it has been updated,
+    /// and notify the user if the update has been requested.
+    ///
+    /// Running profiles pick up the new rules the next time they are started.
+    fn on_gfwlist_updated(&mut self, result: Result<usize, String>, forced: bool) {
+        if let (Ok(_), Some(server)) = (&result, self.pac_server.as_ref()) {
+            server.reload_rules();
+        }
+        match (result, forced) {
+            (Ok(count), true) => {
+                let text_2 = format!("gfwlist now lists {} domains", count);
+                self.notify(Level::Info, "Routing Rules Updated", text_2);
+            }
+            (Err(err), true) => self.notify(Level::Error, "Routing Rules Update Failed", err),
+            (_, false) => {}
+        }
+    }
+    /// Start testing the latency of all profiles in the background.
+    fn test_latencies(&mut self) {
+        info!("Testing latency of all profiles");
+        let profiles = self.profile_folder.get_profiles().into_iter().cloned().collect();
+        if let Err(err) = latency::spawn_test_all(profiles, self.events_tx.clone()) {
+            error!("Cannot start latency test: {}", err);
+            self.notify(Level::Error, "Latency Test Failed", err.to_string());
+        }
+    }
+    /// Handle the completion of a latency test.
+    fn on_latencies_tested(&mut self, latencies: HashMap<String, Latency>) {
+        let fastest = latencies
+            .iter()
+            .filter_map(|(name, l)| l.as_ref().ok().map(|d| (name, d)))
+            .min_by_key(|(_, d)| **d);
+        let text_2 = match fastest {
+            Some((name, d)) => format!(
+                "Tested {} profiles; fastest is \"{}\" at {} ms",
+                latencies.len(),
+                name,
+                d.as_millis()
+            ),
+            None => format!("Tested {} profiles; none are reachable", latencies.len()),
+        };
+        info!("{}", text_2);
+        self.notify(Level::Info, "Latency Test Finished", text_2);
+        self.tray.show_latencies(&latencies);
+        if let Some(server) = self.metrics_server.as_ref() {
+            server.set_latencies(&latencies);
+        }
+        self.latencies = latencies;
+    }
+    /// Compare the external IP address seen directly and through the current profile in the background.
+    fn check_ip(&mut self) {
+        let (name, addr) = match self.diagnostics_target("Cannot Check IP") {
+            Some(target) => target,
+            None => return,
+        };
+        info!("Checking external IP address through profile \"{}\"", name);
+        if let Err(err) = diagnostics::spawn_ip_check(name, addr, self.events_tx.clone()) {
+            error!("Cannot start IP check: {}", err);
+            self.notify(Level::Error, "IP Check Failed", err.to_string());
+        }
+    }
+    /// Get the name and local proxy address of the current profile, to run diagnostics through.
+    ///
+    /// Notifies the user with `summary` if there is none.
+    fn diagnostics_target(&self, summary: &str) -> Option<(String, SocketAddr)> {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let target = current.and_then(|p| Some((p.metadata.display_name.clone(), p.config().local_proxy_addr()?)));
+        if target.is_none() {
+            self.notify(
+                Level::Warn,
+                summary,
+                "Connect to a profile with a local SOCKS5 proxy first",
+            );
+        }
+        target
+    }
+    /// Look for DNS lookups through the current profile that are made by the system resolver, in the background.
+    fn check_dns(&mut self) {
+        let (name, addr) = match self.diagnostics_target("Cannot Check DNS") {
+            Some(target) => target,
+            None => return,
+        };
+        info!("Checking DNS leaks through profile \"{}\"", name);
+        if let Err(err) = diagnostics::spawn_dns_check(name, addr, self.events_tx.clone()) {
+            error!("Cannot start DNS check: {}", err);
+            self.notify(Level::Error, "DNS Check Failed", err.to_string());
+        }
+    }
+    /// Show the resolvers found by a DNS check, warning if any of them is also used by the system.
+    fn on_dns_checked(&mut self, profile_name: String, result: Result<DnsReport, String>) {
+        let report = match result {
+            Ok(r) => r,
+            Err(err) => {
+                error!("DNS check through profile \"{}\" has failed: {}", profile_name, err);
+                self.notify_profile(Level::Error, &profile_name, "DNS Check Failed", |label| {
+                    format!("\"{}\": {}", label, err)
+                });
+                return;
+            }
+        };
+        let resolvers = match report.proxied.is_empty() {
+            true => "none seen".to_string(),
+            false => report
+                .proxied
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        info!(
+            "DNS lookups through profile \"{}\" are made by: {}",
+            profile_name,
+            resolvers.replace('\n', ", ")
+        );
+        if let Err(err) = &report.system {
+            warn!("Cannot find the system resolver for comparison: {}", err);
+        }
+        let leaking = report.leaking_resolvers();
+        let (level, summary) = match leaking.is_empty() {
+            true => (Level::Info, "DNS Check Finished"),
+            false => {
+                let leaking = leaking.iter().map(|r| r.ip.as_str()).collect::<Vec<_>>().join(", ");
+                warn!(
+                    "DNS lookups through the proxy are made by the system resolver: {}",
+                    leaking
+                );
+                (Level::Warn, "Possible DNS Leak")
+            }
+        };
+        self.notify_profile(level, &profile_name, summary, |label| {
+            format!("Resolvers used through \"{}\":\n{}", label, resolvers)
+        });
+    }
+    /// Show the external IP addresses found by an IP check, warning if the proxy is leaking.
+    fn on_ip_checked(&mut self, profile_name: String, result: Result<IpReport, String>) {
+        let report = match result {
+            Ok(r) => r,
+            Err(err) => {
+                error!("IP check through profile \"{}\" has failed: {}", profile_name, err);
+                self.notify_profile(Level::Error, &profile_name, "IP Check Failed", |label| {
+                    format!("\"{}\": {}", label, err)
+                });
+                return;
+            }
+        };
+        let direct = match &report.direct {
+            Ok(ip) => ip.to_string(),
+            Err(err) => format!("unknown ({})", err),
+        };
+        info!(
+            "External IP address is {} through profile \"{}\", and {} directly",
+            report.proxied, profile_name, direct
+        );
+        let (level, summary) = match report.is_leaking() {
+            true => {
+                warn!("The proxy exposes the same IP address as a direct connection");
+                (Level::Warn, "Possible IP Leak")
+            }
+            false => (Level::Info, "IP Check Finished"),
+        };
+        self.notify_profile(level, &profile_name, summary, |label| {
+            format!("Through \"{}\": {}\nDirect: {}", label, report.proxied, direct)
+        });
+    }
+    /// Handle the completion of a subscription refresh.
+    fn on_subscriptions_refreshed(&mut self, updated: usize, errors: Vec<String>) {
+        if updated > 0 {
+            info!("{} subscriptions refreshed; reloading profiles", updated);
+            self.reload_profiles();
+        }
+        if errors.is_empty() {
+            let text_2 = format!("{} subscriptions refreshed", updated);
+            self.notify(Level::Info, "Subscriptions Refreshed", text_2);
+        } else {
+            self.notify(Level::Error, "Subscription Refresh Failed", errors.join("\n"));
+        }
+    }
+    /// Show the preferences dialog with the current settings.
+    fn show_preferences(&mut self) {
+        let profile_names: Vec<_> = self
+            .profile_folder
+            .get_profiles()
+            .into_iter()
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        let autostart = autostart::is_enabled();
+        show_preferences_dialog(self.events_tx.clone(), &self.settings, autostart, &profile_names);
+    }
+    /// Save new settings to disk, and apply them where possible.
+    /// Also install or remove the autostart entry.
+    ///
+    /// The auto-connect profile only takes effect on the next launch.
+    fn save_preferences(&mut self, mut settings: AppSettings, autostart: bool) {
+        // rules and how profiles are loaded are not editable from the dialog
+        settings.schedule = self.settings.schedule.clone();
+        settings.network_rules = self.settings.network_rules.clone();
+        settings.max_load_depth = self.settings.max_load_depth;
+        settings.lazy_load = self.settings.lazy_load;
+        settings.systemd_scope = self.settings.systemd_scope.clone();
+        if let Err(err) = self.set_autostart(autostart) {
+            error!("Cannot change autostart entry: {}", err);
+            self.notify(Level::Error, "Cannot Change Autostart", err.to_string());
+        }
+        if let Err(err) = settings.write_to_file(&self.app_settings_path) {
+            error!("Cannot save app settings: {}", err);
+            self.notify(Level::Error, "Save Failed", err.to_string());
+            return;
+        }
+        info!("App settings saved to {:?}", self.app_settings_path);
+        {
+            let mut pm = util::rwlock_write(&self.profile_manager);
+            let mut backlog = mutex_lock(&pm.backlog);
+            let backlog_limit = settings.backlog_limit(backlog.config());
+            backlog.set_config(backlog_limit);
+            drop(backlog);
+            if let Some(limit) = settings.restart_limit {
+                pm.restart_limit = limit.into();
+            }
+            if let Some(secs) = settings.stop_grace_secs {
+                pm.stop_grace_period = Duration::from_secs(secs);
+            }
+        }
+        let needs_reload =
+            settings.bin_path != self.settings.bin_path || settings.name_conflicts != self.settings.name_conflicts;
+        self.settings = settings;
+        if needs_reload {
+            self.load_opts.default_bin_path = self.settings.bin_path.clone();
+            self.load_opts.name_conflicts = self.settings.name_conflicts;
+            self.reload_profiles();
+        }
+    }
+    /// Install or remove the autostart entry, launching with the same paths as this instance.
+    ///
+    /// An existing entry is rewritten, in case this instance has been launched differently.
+    fn set_autostart(&self, enable: bool) -> io::Result<()> {
+        if !enable {
+            return autostart::disable();
+        }
+        let mut args = vec!["--minimized".into(), "--auto-connect".into()];
+        args.extend(autostart::path_arg(
+            "--profiles-dir",
+            &self.profiles_dir,
+            &PROFILES_DIR_PATH_DEFAULT,
+        ));
+        args.extend(autostart::path_arg(
+            "--app-state",
+            &self.app_state_path,
+            &STATE_FILE_PATH_DEFAULT,
+        ));
+        args.extend(autostart::path_arg(
+            "--app-settings",
+            &self.app_settings_path,
+            &SETTINGS_FILE_PATH_DEFAULT,
+        ));
+        autostart::enable(&args)
+    }
+    /// Send a notification using the current method,
+    /// or only log it if it is less severe than the configured level.
+    fn notify(&self, level: Level, text_1: impl AsRef<str>, text_2: impl AsRef<str>) {
+        self.notify_with_icon(level, None, text_1, text_2)
+    }
+    /// Like `notify`, but about a profile: `text_2` is given the profile's label,
+    /// and the profile's icon is used if set.
+    fn notify_profile(
+        &self,
+        level: Level,
+        profile_name: &str,
+        text_1: impl AsRef<str>,
+        text_2: impl FnOnce(&str) -> String,
+    ) {
+        let metadata = self.profile_folder.lookup(profile_name).map(|p| &p.metadata);
+        let label = metadata.map_or_else(|| profile_name.to_string(), |m| m.label());
+        let icon = metadata.and_then(|m| m.icon.as_deref());
+        self.notify_with_icon(level, icon, text_1, text_2(&label))
+    }
+    fn notify_with_icon(&self, level: Level, icon: Option<&str>, text_1: impl AsRef<str>, text_2: impl AsRef<str>) {
+        let method = match self.notify_method {
+            NotifyMethod::Prompt if self.tray_only => NotifyMethod::Toast,
+            method => method,
+        };
+        if !self.settings.notify_level.allows(level) {
+            notify_log(level, text_1.as_ref(), text_2.as_ref());
+        } else {
+            notify(method, level, icon, text_1, text_2);
+        }
+    }
+    /// Set the notification method.
+    fn set_notify_method(&mut self, method: NotifyMethod) {
+        info!("Setting notify method to {}", method);
+        self.notify_method = method;
+    }
+    /// Restart the `sslocal` instance with the current profile.
+    fn restart(&mut self) {
+        match util::rwlock_read(&self.profile_manager).current_profile() {
+            Some(p) => {
+                let name = p.metadata.display_name.clone();
+                info!("Restarting profile \"{}\"", name);
+                let switch_res = util::rwlock_write(&self.profile_manager).switch_to(p);
+                if let Err(err) = switch_res {
+                    error!("Failed to restart profile \"{}\": {}", name, err);
+                }
+            }
+            None => warn!("Cannot restart because no sslocal instance is running"),
+        }
+    }
+    /// Start or stop running a profile alongside the current one.
+    fn set_alongside(&mut self, profile: Profile, enable: bool) {
+        let name = profile.metadata.display_name.clone();
+        if !enable {
+            if let Some(pm) = self.alongside.remove(&name) {
+                info!("Stopping profile \"{}\" running alongside", name);
+                drop(pm);
+                self.save_state();
+                self.sync_kill_switch();
+            }
+            return;
+        }
+        if self.alongside.contains_key(&name) {
+            return;
+        }
+        let is_current = util::rwlock_read(&self.profile_manager)
+            .current_profile()
+            .is_some_and(|p| p.metadata.display_name == name);
+        if is_current {
+            self.tray.notify_alongside_change(&name, false);
+            let text_2 = format!("\"{}\" is already the current profile", name);
+            self.notify(Level::Warn, "Already Connected", text_2);
+            return;
+        }
+
+        info!("Starting profile \"{}\" alongside the current one", name);
+        let mut pm = {
+            let main = util::rwlock_read(&self.profile_manager);
+            let mut pm = ProfileManager::new(
+                main.restart_limit,
+                main.restart_backoff,
+                EventSink::new(self.alongside_events_tx.clone()),
+            );
+            pm.stop_grace_period = main.stop_grace_period;
+            pm.log_file_config = main.log_file_config;
+            pm.scope = main.scope.clone();
+            pm
+        };
+        let switch_res = profile.preflight().map_err(|err| err.to_string()).and_then(|_| {
+            pm.switch_to(profile).map_err(|err| match err.kind() {
+                io::ErrorKind::AddrInUse => format!("Port in use: {}", err),
+                _ => err.to_string(),
+            })
+        });
+        match switch_res {
+            Ok(_) => {
+                self.alongside.insert(name, pm);
+                self.save_state();
+                self.sync_kill_switch();
+            }
+            Err(err) => {
+                error!("Cannot run profile \"{}\" alongside: {}", name, err);
+                self.tray.notify_alongside_change(&name, false);
+                self.notify(Level::Error, "Cannot Connect", format!("\"{}\": {}", name, err));
+            }
+        }
+    }
+    /// Handle the queued events of profiles running alongside the current one,
+    /// and forget those that have stopped.
+    fn handle_alongside_events(&mut self) {
+        use AppEvent::*;
+        while let Some(event) = self.alongside_events_rx.try_iter().next() {
+            trace!("Received an AppEvent from a profile running alongside: {:?}", event);
+            match event {
+                ErrorStop { instance_name, err } => {
+                    let text_2 = format!(
+                        "An instance has errored: {}\n{}",
+                        instance_name.unwrap_or("None".into()),
+                        err
+                    );
+                    self.notify(Level::Error, "Auto-restart Stopped", text_2);
+                }
+                RestartScheduled {
+                    profile_name,
+                    attempt,
+                    delay,
+                } => self.on_restart_scheduled(profile_name, attempt, delay),
+                SslocalError {
+                    profile_name,
+                    line,
+                    kind,
+                } => self.on_sslocal_error(profile_name, line, kind),
+                _ => {} // stops are handled below
+            }
+        }
+
+        let stopped: Vec<_> = self
+            .alongside
+            .iter()
+            .filter(|(_, pm)| !pm.is_active())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stopped.iter() {
+            debug!("Profile \"{}\" running alongside has stopped", name);
+            self.alongside.remove(name);
+            self.tray.notify_alongside_change(name, false);
+        }
+        if !stopped.is_empty() {
+            self.save_state();
+        }
+    }
+    /// Notify the user that `sslocal` has failed and will be restarted, if enabled.
+    fn on_restart_scheduled(&self, profile_name: String, attempt: usize, delay: Duration) {
+        #[cfg(feature = "runtime-api")]
+        self.publish_state_event(APIStateEvent::Restarting {
+            profile: profile_name.clone(),
+            attempt,
+        });
+        let text_2 = format!(
+            "Profile \"{}\" has failed; restart attempt {} in {:.1}s",
+            profile_name,
+            attempt,
+            delay.as_secs_f64()
+        );
+        if self.settings.notify_events.restart {
+            self.notify(Level::Warn, "Restarting sslocal", text_2);
+        }
+    }
+    /// Switch to the specified profile.
+    ///
+    /// If the connectivity check is enabled, the switch is only committed once it passes;
+    /// see `Self::on_health_checked`.
+    fn switch_profile(&mut self, profile: Profile) {
+        self.failover_tried.clear();
+        let previous = util::rwlock_read(&self.profile_manager).current_profile();
+        self.switch_profile_impl(profile, previous);
+    }
+    /// Switch to the specified profile, rolling back to `previous` if the connectivity check fails.
+    fn switch_profile_impl(&mut self, profile: Profile, previous: Option<Profile>) {
+        let name = profile.metadata.display_name.clone();
+        info!("Switching profile to \"{}\"", name);
+        self.health_check_failures = 0;
+        if let Err(err) = profile.preflight() {
+            error!("Cannot switch to profile \"{}\": {}", name, err);
+            let text_2 = format!("\"{}\": {}", name, err);
+            self.notify(Level::Error, "Invalid Profile", text_2);
+            // the tray has already selected it
+            match util::rwlock_read(&self.profile_manager).current_profile() {
+                Some(p) => self.tray.notify_profile_switch(p.metadata.display_name),
+                None => self.tray.notify_sslocal_stop(),
+            }
+            return;
+        }
+        if let Some(warning) = profile.sslocal_info().and_then(|info| info.take_warning()) {
+            warn!("{}", warning);
+            self.notify(Level::Warn, "Unsupported sslocal", warning);
+        }
+        // it cannot run twice on the same port
+        if self.alongside.remove(&name).is_some() {
+            info!("Stopped profile \"{}\" running alongside, to switch to it", name);
+            self.tray.notify_alongside_change(&name, false);
+        }
+        // only `proxy` mode profiles can be checked
+        let probe_addr = profile
+            .config()
+            .local_proxy_addr()
+            .filter(|_| self.health_check.enabled);
+        let switch_res = util::rwlock_write(&self.profile_manager).switch_to(profile);
+        if let Err(err) = switch_res {
+            error!("Cannot switch to profile \"{}\": {}", name, err);
+            let summary = match err.kind() {
+                io::ErrorKind::AddrInUse => "Port In Use",
+                _ => "Cannot Connect",
+            };
+            self.notify(Level::Error, summary, format!("\"{}\": {}", name, err));
+            self.tray.notify_sslocal_stop();
+            self.sync_system_proxy();
+            self.sync_keep_awake();
+            return;
+        }
+        // the switch may not have come from the tray, e.g. from the profile filter
+        self.tray.notify_profile_switch(&name);
+        self.last_profile = Some(name.clone());
+        // remember the profile even if the app is not quit cleanly, e.g. on power loss
+        self.save_state();
+        self.sync_kill_switch();
+        if let Some(addr) = probe_addr {
+            if self.spawn_health_check(name.clone(), addr, previous) {
+                return; // committed when checked
+            }
+        }
+        self.sync_system_proxy();
+        self.sync_keep_awake();
+        self.notify_connected(&name);
+    }
+    /// Notify the user that a profile has been connected to, if enabled.
+    fn notify_connected(&self, profile_name: &str) {
+        #[cfg(feature = "runtime-api")]
+        self.publish_state_event(APIStateEvent::Connected {
+            profile: profile_name.into(),
+        });
+        if self.settings.notify_events.connect {
+            self.notify_profile(Level::Info, profile_name, "Connected", |label| {
+                format!("Profile \"{}\"", label)
+            });
+        }
+    }
+    /// Notify the user of an error logged by `sslocal`, if enabled,
+    /// at most once every `SSLOCAL_ERROR_NOTIFY_INTERVAL`.
+    ///
+    /// If the kind of error is recognised, the likely cause is shown in the title.
+    fn on_sslocal_error(&mut self, profile_name: String, line: String, kind: LogEventKind) {
+        #[cfg(feature = "runtime-api")]
+        self.publish_state_event(APIStateEvent::Error {
+            profile: Some(profile_name.clone()),
+            message: line.clone(),
+        });
+        if !self.settings.notify_events.sslocal_errors {
+            return;
+        }
+        let throttled = self
+            .last_sslocal_error_notified
+            .is_some_and(|at| at.elapsed() < SSLOCAL_ERROR_NOTIFY_INTERVAL);
+        if throttled {
+            trace!("sslocal error notification throttled: {}", line);
+            return;
+        }
+        self.last_sslocal_error_notified = Some(Instant::now());
+        let title = match kind.hint() {
+            Some(hint) => format!("sslocal Error: {}", hint),
+            None => "sslocal Error".into(),
+        };
+        self.notify_profile(Level::Error, &profile_name, title, |label| {
+            format!("Profile \"{}\": {}", label, line)
+        });
+    }
+    /// Start checking the connectivity of a profile in the background.
+    ///
+    /// Returns whether the check has been started.
+    fn spawn_health_check(&self, profile_name: String, addr: SocketAddr, previous: Option<Profile>) -> bool {
+        info!("Verifying connectivity of profile \"{}\"", profile_name);
+        let config = self.health_check.clone();
+        match health_check::spawn_check(profile_name, addr, config, previous, self.events_tx.clone()) {
+            Ok(_) => true,
+            Err(err) => {
+                error!("Cannot start connectivity check; skipped: {}", err);
+                false
+            }
+        }
+    }
+    /// If the failed profile belongs to a failover group, switch to the next profile in it
+    /// that has not failed yet, and notify the user.
+    ///
+    /// Returns whether a failover has happened.
+    fn try_failover(&mut self, failed_name: &str, reason: &str, previous: Option<Profile>) -> bool {
+        self.failover_tried.insert(failed_name.into());
+        let candidates = match self.profile_folder.failover_candidates(failed_name) {
+            Some(c) => c,
+            None => return false,
+        };
+        let next = match candidates
+            .into_iter()
+            .find(|p| !self.failover_tried.contains(&p.metadata.display_name))
+        {
+            Some(p) => p.clone(),
+            None => {
+                warn!("All profiles in the failover group of \"{}\" have failed", failed_name);
+                self.failover_tried.clear();
+                return false;
+            }
+        };
+        let next_name = next.metadata.display_name.clone();
+        info!("Failing over from profile \"{}\" to \"{}\"", failed_name, next_name);
+        self.switch_profile_impl(next, previous);
+        self.tray.notify_profile_switch(&next_name);
+        self.notify_profile(Level::Warn, &next_name, "Failed Over", |label| {
+            format!("\"{}\": {}\nSwitched to \"{}\"", failed_name, reason, label)
+        });
+        true
+    }
+    /// Commit a profile switch if the connectivity check has passed,
+    /// otherwise roll back to the previous profile.
+    fn on_health_checked(&mut self, profile_name: String, previous: Option<Profile>, result: Result<Duration, String>) {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let current_addr = current.as_ref().and_then(|p| p.config().local_proxy_addr());
+        if current.map(|p| p.metadata.display_name).as_ref() != Some(&profile_name) {
+            debug!(
+                "Profile \"{}\" is no longer active; connectivity check result ignored",
+                profile_name
+            );
+            return;
+        }
+        let err = match result {
+            Ok(elapsed) => {
+                info!("Profile \"{}\" verified in {} ms", profile_name, elapsed.as_millis());
+                self.sync_system_proxy();
+                self.sync_keep_awake();
+                self.notify_connected(&profile_name);
+                return;
+            }
+            Err(err) => err,
+        };
+        warn!("Connectivity check of profile \"{}\" has failed: {}", profile_name, err);
+        if self.profile_folder.failover_candidates(&profile_name).is_some() {
+            self.health_check_failures += 1;
+            if self.health_check_failures < self.health_check.max_failures {
+                if let Some(addr) = current_addr {
+                    if self.spawn_health_check(profile_name.clone(), addr, previous.clone()) {
+                        return; // retry before failing over
+                    }
+                }
+            }
+            if self.try_failover(&profile_name, &err, previous.clone()) {
+                return;
+            }
+        }
+        let text_2 = match previous {
+            Some(prev) => {
+                let prev_name = prev.metadata.display_name.clone();
+                info!("Rolling back to profile \"{}\"", prev_name);
+                if let Err(err) = util::rwlock_write(&self.profile_manager).switch_to(prev) {
+                    error!("Cannot roll back to profile \"{}\": {}", prev_name, err);
+                }
+                self.tray.notify_profile_switch(&prev_name);
+                self.sync_kill_switch();
+                format!("\"{}\": {}\nRolled back to \"{}\"", profile_name, err, prev_name)
+            }
+            None => {
+                let _ = util::rwlock_write(&self.profile_manager).try_stop();
+                self.kill_switch.release();
+                self.tray.notify_sslocal_stop();
+                format!("\"{}\": {}\nStopped", profile_name, err)
+            }
+        };
+        self.sync_system_proxy();
+        self.sync_keep_awake();
+        self.notify(Level::Error, "Connectivity Check Failed", text_2);
+    }
+    /// Stop the current `sslocal` instance.
+    fn stop(&mut self) {
+        {
+            let mut pm_inner = util::rwlock_write(&self.profile_manager);
+            if pm_inner.is_active() {
+                info!("Sending stop signal to sslocal");
+                let _ = pm_inner.try_stop();
+            } else {
+                info!("sslocal is not running; nothing to stop");
+            }
+        }
+        // forget the profile even if the app is not quit cleanly
+        self.save_state();
+        self.sync_system_proxy();
+        self.sync_keep_awake();
+        self.kill_switch.release();
+    }
+    /// What the kill switch should let through for the current profile and those running alongside it;
+    /// `None` if no profile is running.
+    fn kill_switch_allow(&self) -> Option<KillSwitchAllow> {
+        let current = util::rwlock_read(&self.profile_manager).current_profile()?;
+        let mut allow = KillSwitchAllow::for_config(current.config());
+        for p in self.alongside.values().filter_map(|pm| pm.current_profile()) {
+            let other = KillSwitchAllow::for_config(p.config());
+            allow.hosts.extend(other.hosts);
+            allow.interfaces.extend(other.interfaces);
+        }
+        Some(allow)
+    }
+    /// Engage the kill switch for the running profiles, if enabled.
+    ///
+    /// Nothing is released if no profile is running, since `sslocal` may have died unexpectedly;
+    /// the rules are only released on a clean disconnect.
+    fn sync_kill_switch(&mut self) {
+        if let Some(allow) = self.kill_switch_allow() {
+            self.kill_switch.engage(allow);
+        }
+    }
+    /// Enable or disable the kill switch, engaging it right away if a profile is running.
+    fn set_kill_switch(&mut self, enable: bool) {
+        info!("Setting kill switch to {}", enable);
+        let allow = self.kill_switch_allow();
+        if enable && allow.as_ref().is_some_and(|a| a.hosts.is_empty()) {
+            warn!("The current profile has no known servers; all traffic but its interfaces will be blocked");
+            self.notify(
+                Level::Warn,
+                "Kill Switch",
+                "The servers of the current profile are unknown, so traffic to them is blocked too",
+            );
+        }
+        self.kill_switch.set_enabled(enable, allow);
+        self.save_state();
+    }
+    /// Perform the action bound to a global hotkey.
+    fn on_hotkey(&mut self, action: HotkeyAction) {
+        debug!("Performing hotkey action {:?}", action);
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        let profiles = self.profile_folder.get_profiles();
+        match action {
+            HotkeyAction::ToggleConnection => match (current, &self.last_profile) {
+                (Some(_), _) => {
+                    self.stop();
+                    self.tray.notify_sslocal_stop();
+                }
+                (None, Some(name)) => match self.profile_folder.lookup(name).cloned() {
+                    Some(p) => self.switch_profile(p),
+                    None => {
+                        warn!("Profile \"{}\" no longer exists; cannot reconnect", name);
+                        let text_2 = format!("Profile \"{}\" no longer exists", name);
+                        self.notify(Level::Warn, "Cannot Reconnect", text_2);
+                    }
+                },
+                (None, None) => {
+                    warn!("No profile has been connected to; cannot reconnect");
+                    self.notify(Level::Warn, "Cannot Reconnect", "No profile has been connected to");
+                }
+            },
+            HotkeyAction::NextProfile => {
+                let current_name = current.map(|p| p.metadata.display_name);
+                let next = match profiles
+                    .iter()
+                    .position(|p| Some(&p.metadata.display_name) == current_name.as_ref())
+                {
+                    Some(i) => profiles.get(i + 1).or_else(|| profiles.first()),
+                    None => profiles.first(),
+                };
+                match next.map(|p| (*p).clone()) {
+                    Some(p) => self.switch_profile(p),
+                    None => warn!("No profile is loaded; cannot switch"),
+                }
+            }
+            HotkeyAction::ShowBacklog => self.show_log_viewer(),
+        }
+    }
+    /// Enable or disable setting the system proxy for profiles that do not override it.
+    fn set_system_proxy(&mut self, enable: bool) {
+        info!("Setting system proxy auto-configuration to {}", enable);
+        self.system_proxy_enabled = enable;
+        self.sync_system_proxy();
+    }
+    /// Start or stop the PAC server.
+    fn set_pac_server(&mut self, enable: bool) {
+        info!("Setting PAC server to {}", enable);
+        self.pac_config.enabled = enable;
+        // stop first, in case it is restarted on the same port
+        drop(self.pac_server.take());
+        if enable {
+            match PacServer::start(self.pac_config.clone(), None) {
+                Ok(server) => self.pac_server = Some(server),
+                Err(err) => {
+                    error!("Cannot start PAC server: {}", err);
+                    self.notify(Level::Error, "Cannot Start PAC Server", err.to_string());
+                }
+            }
+        }
+        self.sync_gfwlist_wanted();
+        self.sync_system_proxy();
+    }
+    /// Set whether the system proxy should point to the PAC file rather than `sslocal`.
+    fn set_system_proxy_pac(&mut self, enable: bool) {
+        info!("Setting PAC for system proxy to {}", enable);
+        self.pac_config.use_for_system_proxy = enable;
+        self.sync_system_proxy();
+    }
+    /// Keep the system awake while a profile is running, if enabled.
+    fn sync_keep_awake(&mut self) {
+        let connected = util::rwlock_read(&self.profile_manager).is_active();
+        if let Err(err) = self.keep_awake.sync(connected) {
+            warn!("Cannot keep the system awake: {}", err);
+        }
+    }
+    /// Set or restore the system proxy to match the currently active profile,
+    /// and point the PAC file to it.
+    ///
+    /// Only "Proxy"-type profiles can be used as a system proxy.
+    fn sync_system_proxy(&mut self) {
+        let (local_addr, use_system_proxy) = match util::rwlock_read(&self.profile_manager).current_profile() {
+            Some(p) => (
+                p.config().local_proxy_addr(),
+                p.metadata.system_proxy.unwrap_or(self.system_proxy_enabled),
+            ),
+            None => (None, false),
+        };
+        if let Some(server) = self.pac_server.as_ref() {
+            server.set_proxy(local_addr);
+        }
+
+        let desired = local_addr.filter(|_| use_system_proxy).map(|addr| {
+            match self.pac_server.as_ref().filter(|s| s.config().use_for_system_proxy) {
+                Some(server) => ProxyTarget::Pac(server.config().url()),
+                None => ProxyTarget::Socks(addr),
+            }
+        });
+        if self.system_proxy.as_ref().map(SystemProxy::target) == desired.as_ref() {
+            return;
+        }
+
+        // restore the original settings before applying new ones
+        drop(self.system_proxy.take());
+        if let Some(target) = desired {
+            match SystemProxy::apply(target) {
+                Ok(proxy) => self.system_proxy = Some(proxy),
+                Err(err) => {
+                    error!("Cannot set system proxy: {}", err);
+                    self.notify(Level::Error, "Cannot Set System Proxy", err.to_string());
+                }
+            }
+        }
+    }
+    /// Quit the application.
+    fn quit(&mut self) {
+        info!("Quit");
+
+        // cleanup
+        self.write_state(AppState {
+            clean_exit: true,
+            ..self.snapshot()
+        });
+        // stop any running `sslocal` process
+        let _ = util::rwlock_write(&self.profile_manager).try_stop();
+        // restore system proxy
+        drop(self.system_proxy.take());
+        drop(self.pac_server.take());
+        // remove firewall rules; this waits for authentication, as the app may not be dropped before exiting
+        self.kill_switch.release();
+        self.kill_switch.halt();
+
+        // drop all optional windows
+        debug!("Closing all optional windows");
+        drop(self.log_viewer_window.take());
+        drop(self.stats_window.take());
+
+        gtk::main_quit();
+    }
+
+    /// Handles the queued incoming app events.
+    fn handle_app_events(&mut self) {
+        use AppEvent::*;
+        // using `while let` rather than `for` due to borrow checker issue
+        while let Some(event) = self.events_rx.try_iter().next() {
+            trace!("Received an AppEvent: {:?}", event);
+            if self.tray_only && event.opens_window() {
+                debug!("A window is opened; leaving tray-only mode");
+                self.tray_only = false;
+            }
+            match event {
+                LogViewerShow => self.show_log_viewer(),
+                LogViewerHide => self.drop_log_viewer(),
+                SaveBacklog(path) => self.save_backlog(path),
+                StatsWindowShow => self.show_stats_window(),
+                StatsWindowHide => self.drop_stats_window(),
+                UrlImportShow => show_url_import_dialog(self.events_tx.clone()),
+                ImportFromUrl(url) => self.import_from_url(url),
+                FileImportShow => show_file_import_dialog(self.events_tx.clone()),
+                ImportFromFile(path) => self.import_from_file(path),
+                DropReceived { paths, text } => self.on_drop_received(paths, text),
+                ImportProfileDir(src) => self.import_profile_dir(src),
+                ScanQrFromScreen => self.scan_qr_from_screen(),
+                ClipboardImportShow(urls) => {
+                    show_urls_import_dialog(self.events_tx.clone(), "Import from Clipboard", urls)
+                }
+                QrExportShow => self.show_qr_export(),
+                LaunchPreviewShow => self.show_launch_preview(),
+                ProfileExportShow => show_profile_export_dialog(self.events_tx.clone()),
+                PreferencesShow => self.show_preferences(),
+                ProfileFilterShow => {
+                    let profiles = self.profile_folder.get_profiles().into_iter().cloned().collect();
+                    show_profile_filter_dialog(self.events_tx.clone(), profiles);
+                }
+                LoadProblemsShow => show_load_problems_dialog(&self.load_problems),
+                SavePreferences { settings, autostart } => self.save_preferences(settings, autostart),
+                ExportProfiles { path, strip_passwords } => self.export_profiles(path, strip_passwords),
+                ProfileEditorShow { edit_current } => self.show_profile_editor(edit_current),
+                SaveProfile { dir, config, is_new } => self.save_profile(dir, config, is_new),
+                ProfileDeleteShow => self.show_profile_delete(),
+                DeleteProfile { profile, archive } => self.delete_profile(profile, archive),
+                ProfileDuplicateShow { from_template } => self.show_profile_duplicate(from_template),
+                DuplicateProfile { source, display_name } => self.duplicate_profile(source, display_name),
+                TestLatencies => self.test_latencies(),
+                CheckIp => self.check_ip(),
+                CheckDns => self.check_dns(),
+                RefreshSubscriptions => {
+                    info!("Refreshing all subscriptions");
+                    self.subscription_updater.refresh_now();
+                }
+                UpdateRules => {
+                    info!("Updating gfwlist");
+                    self.gfwlist_updater.update_now();
+                }
+                SwitchProfile(p) => self.switch_profile(p),
+                SetProfileAlongside { profile, enable } => self.set_alongside(profile, enable),
+                ManualStop => self.stop(),
+                SetNotify(method) => self.set_notify_method(method),
+                SetCollectStats(enable) => self.set_collect_stats(enable),
+                SetVerboseLogs(enable) => self.set_verbose_logs(enable),
+                SetLogFile(enable) => self.set_log_file(enable),
+                OpenLogFile => self.open_log_file(),
+                SetSystemProxy(enable) => self.set_system_proxy(enable),
+                SetPacServer(enable) => self.set_pac_server(enable),
+                SetSystemProxyPac(enable) => self.set_system_proxy_pac(enable),
+                SetHealthCheck(enable) => {
+                    info!("Setting connectivity check before switching to {}", enable);
+                    self.health_check.enabled = enable;
+                }
+                SetKillSwitch(enable) => self.set_kill_switch(enable),
+                SetClipboardMonitor(enable) => self.set_clipboard_monitor(enable),
+                SetKeepAwake(enable) => self.set_keep_awake(enable),
+                SetTagFilter(tag) => self.set_tag_filter(tag),
+                Hotkey(action) => self.on_hotkey(action),
+                Quit => self.quit(),
+
+                OkStop { instance_name } => {
+                    // this event could be received because an old instance is stopped
+                    // and a new one is started, therefore we first check for active instance
+                    if !util::rwlock_read(&self.profile_manager).is_active() {
+                        self.tray.notify_sslocal_stop();
+                        self.sync_system_proxy();
+                        self.sync_keep_awake();
+                        #[cfg(feature = "runtime-api")]
+                        self.publish_state_event(APIStateEvent::Disconnected);
+                        let text_2 = format!("An instance has stopped: {}", instance_name.unwrap_or("None".into()));
+                        if self.settings.notify_events.disconnect {
+                            self.notify(Level::Warn, "Auto-restart Stopped", text_2);
+                        }
+                    }
+                }
+                ErrorStop { instance_name, err } => {
+                    let failed_over = instance_name
+                        .as_ref()
+                        .is_some_and(|name| self.try_failover(name, &err, None));
+                    if failed_over {
+                        continue;
+                    }
+                    self.tray.notify_sslocal_stop();
+                    // the instance may not have been cleared yet, but it is dead for sure
+                    drop(self.system_proxy.take());
+                    self.sync_keep_awake();
+                    #[cfg(feature = "runtime-api")]
+                    self.publish_state_event(APIStateEvent::Error {
+                        profile: instance_name.clone(),
+                        message: err.to_string(),
+                    });
+                    #[cfg(feature = "runtime-api")]
+                    self.publish_state_event(APIStateEvent::Disconnected);
+                    let text_2 = format!(
+                        "An instance has errored: {}\n{}",
+                        instance_name.unwrap_or("None".into()),
+                        err
+                    );
+                    self.notify(Level::Error, "Auto-restart Stopped", text_2);
+                }
+                RestartScheduled {
+                    profile_name,
+                    attempt,
+                    delay,
+                } => self.on_restart_scheduled(profile_name, attempt, delay),
+                HealthChecked {
+                    profile_name,
+                    previous,
+                    result,
+                } => self.on_health_checked(profile_name, previous, result),
+                LatenciesTested(latencies) => self.on_latencies_tested(latencies),
+                IpChecked { profile_name, result } => self.on_ip_checked(profile_name, result),
+                DnsChecked { profile_name, result } => self.on_dns_checked(profile_name, result),
+                ProfilesChanged => {
+                    info!("Profiles directory has changed; reloading profiles");
+                    self.reload_profiles();
+                }
+                LoadGroup(dir) => self.load_group(dir),
+                SubscriptionsRefreshed { updated, errors } => self.on_subscriptions_refreshed(updated, errors),
+                GfwlistUpdated { result, forced } => self.on_gfwlist_updated(result, forced),
+                QrScanned(res) => self.on_qr_scanned(res),
+                ClipboardUrlsFound(urls) => self.on_clipboard_urls_found(urls),
+                SslocalError {
+                    profile_name,
+                    line,
+                    kind,
+                } => self.on_sslocal_error(profile_name, line, kind),
+                KillSwitchFailed(err) => self.notify(Level::Error, "Kill Switch Failed", err),
+                HotkeysFailed(err) => {
+                    warn!("Global hotkeys are unavailable: {}", err);
+                    self.notify(Level::Warn, "Hotkeys Unavailable", err);
+                }
+                ColorSchemeChanged(scheme) => self.apply_color_scheme(scheme),
+                NetworkChanged { network, initial } => self.on_network_changed(network, initial),
+                PrepareForSleep { done_tx } => {
+                    self.prepare_for_sleep();
+                    // the sleep monitor only waits for so long, so it may have given up already
+                    let _ = done_tx.send(());
+                }
+                ResumedFromSleep => self.resume_from_sleep(),
+
+                ConnectByName(name) => match self.profile_folder.lookup(&name).cloned() {
+                    Some(p) => {
+                        self.switch_profile(p);
+                        self.tray.notify_profile_switch(&name);
+                    }
+                    None => error!("Cannot find a profile named \"{}\"; did nothing", name),
+                },
+                Disconnect => {
+                    self.stop();
+                    self.tray.notify_sslocal_stop();
+                }
+                Restart => self.restart(),
+            }
+        }
+    }
+
+    /// Publishes the running profile to D-Bus subscribers, if it has changed.
+    #[cfg(feature = "dbus-api")]
+    fn update_dbus_state(&mut self) {
+        if let Some(service) = self.dbus_service.as_mut() {
+            let active_profile = {
+                let pm = util::rwlock_read(&self.profile_manager);
+                pm.current_profile()
+                    .filter(|_| pm.is_active())
+                    .map(|p| p.metadata.display_name)
+            };
+            service.set_active_profile(active_profile);
+        }
+    }
+
+    /// Handles the queued incoming runtime API commands.
+    #[cfg(feature = "runtime-api")]
+    fn handle_api_commands(&mut self) {
+        use serde_json::Value::Null;
+        use APICommand::*;
+
+        // using `while let` rather than `for` due to borrow checker issue
+        while let Some(APIRequest { cmd, reply_tx }) = self.api_cmds_rx.try_iter().next() {
+            let reply: APIReply = match cmd {
+                LogViewerShow => {
+                    self.show_log_viewer();
+                    Ok(Null)
+                }
+                LogViewerHide => {
+                    self.close_log_viewer();
+                    Ok(Null)
+                }
+                SetNotify(method) => {
+                    self.set_notify_method(method);
+                    self.tray.notify_notify_method_change(method);
+                    Ok(Null)
+                }
+                GetStatus | Subscribe => serde_json::to_value(self.api_status()).map_err(|err| err.to_string()),
+                GetBacklog(lines) | FollowBacklog(lines) => Ok(self.backlog_tail(lines).into()),
+                ListProfiles => serde_json::to_value(self.api_profile_tree()).map_err(|err| err.to_string()),
+                GetLaunchCommand(selector) => match self.profile_folder.lookup_selector(&selector) {
+                    Some(p) => p
+                        .launch_preview(false)
+                        .map_err(|err| err.to_string())
+                        .and_then(|preview| {
+                            serde_json::to_value(runtime_api::to_api_launch_command(preview))
+                                .map_err(|err| err.to_string())
+                        }),
+                    None => Err(format!("no profile is named or numbered \"{}\"", selector)),
+                },
+
+                Restart => {
+                    self.restart();
+                    Ok(Null)
+                }
+                SwitchProfile(name) => match self.profile_folder.lookup(&name).cloned() {
+                    Some(p) => {
+                        self.switch_profile(p);
+                        self.tray.notify_profile_switch(&name);
+                        Ok(Null)
+                    }
+                    None => {
+                        error!("Cannot find a profile named \"{}\"; did nothing", name);
+                        Err(format!("no profile is named \"{}\"", name))
+                    }
+                },
+                Connect(selector) => {
+                    let profile = self.profile_folder.lookup_selector(&selector).cloned();
+                    match profile {
+                        Some(p) => {
+                            self.switch_profile(p);
+                            Ok(Null)
+                        }
+                        None => {
+                            error!("Cannot find a profile named or numbered \"{}\"; did nothing", selector);
+                            Err(format!("no profile is named or numbered \"{}\"", selector))
+                        }
+                    }
+                }
+                Stop => {
+                    self.stop();
+                    self.tray.notify_sslocal_stop();
+                    Ok(Null)
+                }
+                Quit => {
+                    self.quit();
+                    Ok(Null)
+                }
+            };
+            if let Some(tx) = reply_tx {
+                // error messages may contain passwords
+                let reply = reply.map_err(|msg| crate::redact::scrub(&msg).into_owned());
+                // the client may have timed out already, which is fine
+                let _ = tx.send(reply);
+            }
+        }
+    }
+
+    /// Tell runtime API subscribers about a change of state.
+    ///
+    /// Subscribers that have fallen too far behind miss the event rather than block the GUI.
+    #[cfg(feature = "runtime-api")]
+    fn publish_state_event(&self, event: APIStateEvent) {
+        if let Err(event) = mutex_lock(&self.api_events_brd).try_broadcast(event) {
+            trace!("A runtime API subscriber is lagging; dropped {:?}", event);
+        }
+    }
+
+    /// The last `lines` lines of the backlog.
+    #[cfg(feature = "runtime-api")]
+    fn backlog_tail(&self, lines: usize) -> Vec<String> {
+        runtime_api::backlog_tail(&util::rwlock_read(&self.profile_manager), lines)
+    }
+
+    /// The current status of the application, as reported to runtime API clients.
+    #[cfg(feature = "runtime-api")]
+    fn api_status(&self) -> APIStatus {
+        let alongside = self.alongside.keys().cloned().collect();
+        runtime_api::api_status(&util::rwlock_read(&self.profile_manager), alongside)
+    }
+
+    /// All profiles in tray menu order, as reported to runtime API clients.
+    #[cfg(feature = "runtime-api")]
+    fn api_profile_tree(&self) -> Vec<APIProfileTree> {
+        let pm = util::rwlock_read(&self.profile_manager);
+        runtime_api::api_profile_tree(&self.profile_folder, &pm)
+    }
+}
+
+/// Load all profiles from the profiles directory, including those
+/// defined in the manifest, and optionally those materialized from subscriptions.
+///
+/// Also returns the profiles that cannot be loaded, which are skipped.
+/// If none can be loaded, the first problem is returned as the error.
+pub fn load_profile_folder(
+    profiles_dir: impl AsRef<Path>,
+    opts: &LoadOptions,
+    with_subscriptions: bool,
+) -> Result<(ProfileFolder, Vec<LoadProblem>), ProfileLoadError> {
+    let profiles_dir = profiles_dir.as_ref();
+    let subs = if with_subscriptions {
+        Subscription::load_all(profiles_dir).unwrap_or_else(|err| {
+            error!("Cannot load subscription file: {}", err);
+            vec![]
+        })
+    } else {
+        vec![]
+    };
+
+    let has_manifest = profiles_dir.join(PROFILE_MANIFEST_FILE_NAME).is_file();
+
+    let mut problems = vec![];
+    let mut profile_folder = match ProfileFolder::from_path_recurse(profiles_dir, opts, &mut problems) {
+        // a directory with nothing but subscriptions or a manifest is fine
+        Err(ProfileLoadError::EmptyGroup(_)) if !subs.is_empty() || has_manifest => {
+            ProfileFolder::Group(ProfileGroup {
+                display_name: profiles_dir.to_string_lossy().into(),
+                content: vec![],
+                failover: false,
+                unloaded: None,
+            })
+        }
+        // more telling than the directory being empty
+        Err(ProfileLoadError::EmptyGroup(_)) if !problems.is_empty() => return Err(problems.remove(0).error),
+        res => res?,
+    };
+    if let Err(error) = profile_folder.merge_manifest(profiles_dir, opts, &mut problems) {
+        let path = profiles_dir.join(PROFILE_MANIFEST_FILE_NAME);
+        warn!("Skipped the manifest {:?}, which cannot be loaded: {}", path, error);
+        problems.push(LoadProblem {
+            path,
+            name: None,
+            error,
+        });
+    }
+    let default_bin_path = opts.default_bin_path.as_deref();
+    subscription::merge_subscriptions(&mut profile_folder, &subs, profiles_dir, default_bin_path);
+    Ok((profile_folder, problems))
+}
+
+/// Initialize all components and start the GTK main loop.
+pub fn run(args: &CliArgs) -> Result<(), AppStartError> {
+    // register as a `GtkApplication`; not fatal if unavailable
+    // instances started with their own state file are meant to run side by side
+    let unique = STATE_FILE_PATH_DEFAULT.eq(&args.app_state_path);
+    let registered = match application::register(unique) {
+        Ok(a) if a.is_remote() => {
+            info!("Another instance is already running; activating it");
+            a.activate();
+            return Ok(());
+        }
+        Ok(a) => Some(a),
+        Err(err) => {
+            warn!("Cannot register the application on the session bus: {}", err);
+            None
+        }
+    };
+
+    // init app
+    let mut app = GTKApp::new(args)?;
+    app.sync_system_proxy(); // in case a profile has been resumed
+    app.sync_keep_awake();
+    app.sync_kill_switch();
+    if let Some(a) = &registered {
+        application::add_actions(a, app.events_tx.clone());
+    }
+
+    // catch signals for soft shutdown
+    let shutdown_trigger_count = Arc::new(Mutex::new(0usize));
+    let events_tx = app.events_tx.clone();
+    ctrlc::set_handler(move || {
+        let mut count = util::mutex_lock(&shutdown_trigger_count);
+        match *count {
+            0 => {
+                info!("Signal received, sending Quit event");
+                if let Err(_) = events_tx.send(AppEvent::Quit) {
+                    error!("Trying to send Quit event for soft shutdown, but all receivers have hung up");
+                    error!("Performing hard shutdown; the app state may be unsaved");
+                    process::exit(0);
+                }
+            }
+            1 => warn!("Send one more signal for hard shutdown"),
+            _ => {
+                warn!("Performing hard shutdown; the app state may be unsaved");
+                process::exit(0);
+            }
+        }
+        *count += 1;
+    })?;
+
+    // starts looping event listeners
+    let mut stats_label_updated_at = Instant::now();
+    let loop_action_id = glib::timeout_add_local(
+        Duration::from_millis(10), // 100fps
+        move || {
+            app.handle_app_events();
+            app.handle_alongside_events();
+
+            #[cfg(feature = "runtime-api")]
+            app.handle_api_commands();
+
+            #[cfg(feature = "dbus-api")]
+            app.update_dbus_state();
+
+            if stats_label_updated_at.elapsed() >= STATS_LABEL_UPDATE_INTERVAL {
+                app.update_stats_label();
+                app.update_tray_tooltip();
+                stats_label_updated_at = Instant::now();
+            }
 
             Continue(true)
         },
@@ -0,0 +1,74 @@
+//! This module contains code that registers the app as a `GtkApplication`,
+//! which exports its actions on the session bus for desktop integration,
+//! e.g. `gapplication action io.github.shadowsocks_gtk_rs.ssgtk disconnect`.
+
+use crossbeam_channel::Sender;
+use gtk::{
+    gio::{self, ApplicationFlags, Menu, SimpleAction},
+    glib::{self, VariantTy},
+    prelude::*,
+};
+use log::{debug, error};
+use shadowsocks_gtk_rs::consts::APP_ID;
+
+use crate::event::AppEvent;
+
+/// The actions without a parameter, as (name, label in the app menu, event sent).
+const ACTIONS: [(&str, &str, AppEvent); 4] = [
+    ("show-backlog", "Show sslocal Output", AppEvent::LogViewerShow),
+    ("restart", "Restart", AppEvent::Restart),
+    ("disconnect", "Disconnect", AppEvent::Disconnect),
+    ("quit", "Quit", AppEvent::Quit),
+];
+
+/// Register the application on the session bus.
+///
+/// If `unique` is unset, another instance may be registered with the same ID,
+/// and nothing is exported on the bus.
+///
+/// The returned application is remote if another instance is already registered.
+pub fn register(unique: bool) -> Result<gtk::Application, glib::Error> {
+    let flags = match unique {
+        true => ApplicationFlags::empty(),
+        false => ApplicationFlags::NON_UNIQUE,
+    };
+    let application = gtk::Application::new(Some(APP_ID), flags);
+    application.register(None::<&gio::Cancellable>)?;
+    if !application.is_remote() {
+        debug!("Registered as {}", APP_ID);
+    }
+    Ok(application)
+}
+
+/// Add the actions and app menu of a registered application, which send the corresponding events,
+/// and keep it running without any window.
+pub fn add_actions(application: &gtk::Application, events_tx: Sender<AppEvent>) {
+    let send = move |event: AppEvent| {
+        if events_tx.send(event).is_err() {
+            error!("Trying to send an event from an action, but all receivers have hung up.");
+        }
+    };
+    let app_menu = Menu::new();
+    for (name, label, event) in ACTIONS {
+        let action = SimpleAction::new(name, None);
+        let send = send.clone();
+        action.connect_activate(move |_, _| send(event.clone()));
+        application.add_action(&action);
+        app_menu.append(Some(label), Some(&format!("app.{}", name)));
+    }
+    // connect to a profile by name
+    let connect_action = SimpleAction::new("connect", Some(VariantTy::STRING));
+    let connect_send = send.clone();
+    connect_action.connect_activate(move |_, param| match param.and_then(|p| p.str()) {
+        Some(name) => connect_send(AppEvent::ConnectByName(name.into())),
+        None => error!("The connect action needs a profile name"),
+    });
+    application.add_action(&connect_action);
+    application.set_app_menu(Some(&app_menu));
+
+    // activated by another instance, or by D-Bus activation
+    application.connect_activate(move |_| send(AppEvent::LogViewerShow));
+
+    // keep running without any window
+    application.hold();
+}
@@ -0,0 +1,100 @@
+//! This module contains code that detects the display server the GUI runs on,
+//! so that behaviors which only work under X11 can be avoided under Wayland.
+
+use std::{env, fmt};
+
+use gtk::{gdk, prelude::*};
+
+/// The display server (windowing system) the GUI is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayServer {
+    X11,
+    Wayland,
+    Unknown,
+}
+
+impl fmt::Display for DisplayServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use DisplayServer::*;
+        let name = match self {
+            X11 => "X11",
+            Wayland => "Wayland",
+            Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl DisplayServer {
+    /// Detect the display server at runtime.
+    ///
+    /// The backend GDK has actually opened takes precedence, because a Wayland session
+    /// can still run the app under XWayland (e.g. with `GDK_BACKEND=x11`).
+    /// If GTK is not initialized yet, the session's environment variables are used instead.
+    pub fn detect() -> Self {
+        let from_gdk = gdk::Display::default()
+            .map(|display| Self::from_gdk_type_name(display.type_().name()))
+            .unwrap_or(Self::Unknown);
+        match from_gdk {
+            Self::Unknown => Self::from_env(
+                env::var("XDG_SESSION_TYPE").ok().as_deref(),
+                env::var_os("WAYLAND_DISPLAY").is_some(),
+                env::var_os("DISPLAY").is_some(),
+            ),
+            server => server,
+        }
+    }
+
+    /// Whether windows can position themselves, which Wayland does not allow.
+    pub fn can_position_windows(&self) -> bool {
+        *self != Self::Wayland
+    }
+
+    /// Whether a legacy XEmbed tray icon can be shown, which requires X11.
+    pub fn supports_xembed_tray(&self) -> bool {
+        *self == Self::X11
+    }
+
+    /// Map the type name of a `GdkDisplay`, e.g. `GdkWaylandDisplay`.
+    fn from_gdk_type_name(name: &str) -> Self {
+        if name.contains("Wayland") {
+            Self::Wayland
+        } else if name.contains("X11") {
+            Self::X11
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Guess from the session type reported by the login manager,
+    /// then from which display sockets are advertised.
+    fn from_env(session_type: Option<&str>, wayland_display: bool, x11_display: bool) -> Self {
+        match session_type.map(str::to_lowercase).as_deref() {
+            Some("wayland") => Self::Wayland,
+            Some("x11") => Self::X11,
+            _ if wayland_display => Self::Wayland,
+            _ if x11_display => Self::X11,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_from_env() {
+        use DisplayServer::*;
+        assert_eq!(DisplayServer::from_env(Some("wayland"), true, true), Wayland);
+        assert_eq!(DisplayServer::from_env(Some("x11"), false, true), X11);
+        // e.g. in a nested session or when launched over SSH
+        assert_eq!(DisplayServer::from_env(Some("tty"), true, false), Wayland);
+        assert_eq!(DisplayServer::from_env(None, false, true), X11);
+        assert_eq!(DisplayServer::from_env(None, false, false), Unknown);
+
+        assert_eq!(DisplayServer::from_gdk_type_name("GdkWaylandDisplay"), Wayland);
+        assert_eq!(DisplayServer::from_gdk_type_name("GdkX11Display"), X11);
+        assert_eq!(DisplayServer::from_gdk_type_name("GdkBroadwayDisplay"), Unknown);
+    }
+}
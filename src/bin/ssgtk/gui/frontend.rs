@@ -0,0 +1,218 @@
+//! This module contains code for the tray icon shown while `ssgtk --service` is running,
+//! which attaches to the service as a thin frontend over the runtime API.
+//!
+//! Quitting the frontend leaves the service, and so `sslocal`, running.
+
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{unbounded as unbounded_channel, Receiver, Sender};
+use derivative::Derivative;
+use gtk::{glib, prelude::*, Menu, MenuItem, SeparatorMenuItem};
+use libappindicator::{AppIndicator, AppIndicatorStatus};
+use log::{debug, error, info, warn};
+use notify_rust::Urgency;
+use shadowsocks_gtk_rs::{
+    consts::*,
+    runtime_api_msg::{APICommand, APIProfileTree, APIStatus},
+};
+
+use crate::{
+    clap_def::CliArgs,
+    gui::{app::AppStartError, application, notification::notify_toast},
+    io::single_instance::{request, subscribe},
+};
+
+/// How long to wait before attaching again after the service has gone away.
+const REATTACH_INTERVAL: Duration = Duration::from_secs(3);
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Frontend {
+    #[derivative(Debug(format_with = "shadowsocks_gtk_rs::util::hacks::omit_ai"))]
+    ai: AppIndicator,
+    socket_path: PathBuf,
+    /// Whether the service is attached to, sent on each change of state.
+    changes_rx: Receiver<bool>,
+}
+
+impl Frontend {
+    /// Rebuild the menu from the status and profiles of the service.
+    fn rebuild_menu(&mut self, attached: bool) {
+        let menu = Menu::new();
+        let status = attached
+            .then(|| request(&self.socket_path, &APICommand::GetStatus))
+            .and_then(|res| {
+                res.map_err(|err| warn!("Cannot get the status of the service: {}", err))
+                    .ok()
+            })
+            .and_then(|value| serde_json::from_value::<APIStatus>(value).ok());
+        let label = match &status {
+            Some(APIStatus {
+                running: true,
+                profile: Some(name),
+                ..
+            }) => format!("Service: connected to \"{}\"", name),
+            Some(_) => "Service: disconnected".into(),
+            None => "Service: not running".into(),
+        };
+        self.ai.set_title(&label);
+        add_label(&menu, &label);
+        menu.append(&SeparatorMenuItem::new());
+
+        if status.is_some() {
+            let profiles = request(&self.socket_path, &APICommand::ListProfiles)
+                .map_err(|err| warn!("Cannot list the profiles of the service: {}", err))
+                .ok()
+                .and_then(|value| serde_json::from_value::<Vec<APIProfileTree>>(value).ok())
+                .unwrap_or_default();
+            for tree in profiles {
+                menu.append(&self.profile_item(tree));
+            }
+            menu.append(&SeparatorMenuItem::new());
+            self.add_command_item(&menu, "Restart", APICommand::Restart);
+            self.add_command_item(&menu, "Stop", APICommand::Stop);
+            self.add_command_item(&menu, "Quit Service", APICommand::Quit);
+            menu.append(&SeparatorMenuItem::new());
+        }
+        let quit_item = MenuItem::with_label("Quit Frontend");
+        quit_item.connect_activate(|_| {
+            info!("Quit frontend; the service keeps running");
+            gtk::main_quit();
+        });
+        menu.append(&quit_item);
+
+        menu.show_all();
+        self.ai.set_menu(&mut menu.clone());
+    }
+
+    /// The menu item of a profile, which connects to it, or the submenu of a group.
+    fn profile_item(&self, tree: APIProfileTree) -> MenuItem {
+        match tree {
+            APIProfileTree::Group { group, content } => {
+                let item = MenuItem::with_label(&group);
+                let submenu = Menu::new();
+                for child in content {
+                    submenu.append(&self.profile_item(child));
+                }
+                item.set_submenu(Some(&submenu));
+                item
+            }
+            APIProfileTree::Profile { profile, active, .. } => {
+                let label = match active {
+                    true => format!("● {}", profile),
+                    false => profile.clone(),
+                };
+                let item = MenuItem::with_label(&label);
+                let socket_path = self.socket_path.clone();
+                item.connect_activate(move |_| send_command(&socket_path, APICommand::SwitchProfile(profile.clone())));
+                item
+            }
+        }
+    }
+
+    fn add_command_item(&self, menu: &Menu, label: &str, cmd: APICommand) {
+        let item = MenuItem::with_label(label);
+        let socket_path = self.socket_path.clone();
+        item.connect_activate(move |_| send_command(&socket_path, cmd.clone()));
+        menu.append(&item);
+    }
+}
+
+fn add_label(menu: &Menu, label: &str) {
+    let item = MenuItem::with_label(label);
+    item.set_sensitive(false);
+    menu.append(&item);
+}
+
+/// Send a command to the service in the background, notifying the user if it fails.
+fn send_command(socket_path: &Path, cmd: APICommand) {
+    let socket_path = socket_path.to_path_buf();
+    thread::spawn(move || {
+        debug!("Sending to the service: {}", cmd);
+        if let Err(err) = request(&socket_path, &cmd) {
+            error!("The service cannot {}: {}", cmd, err);
+            let _ = notify_toast(Urgency::Critical, None, "Command Failed", &err.to_string());
+        }
+    });
+}
+
+/// Subscribe to changes of state of the service for as long as the frontend runs,
+/// attaching again whenever it restarts.
+fn spawn_subscriber(socket_path: PathBuf, changes_tx: Sender<bool>) {
+    let res = thread::Builder::new()
+        .name("Service subscriber".into())
+        .spawn(move || loop {
+            match subscribe(&socket_path) {
+                Ok((status, reader)) => {
+                    debug!("Attached to the service, which is running: {}", status.running);
+                    if changes_tx.send(true).is_err() {
+                        return;
+                    }
+                    for line in reader.lines() {
+                        if line.is_err() || changes_tx.send(true).is_err() {
+                            break;
+                        }
+                    }
+                    warn!("The service has gone away");
+                }
+                Err(err) => debug!("Cannot attach to the service: {}", err),
+            }
+            if changes_tx.send(false).is_err() {
+                return;
+            }
+            thread::sleep(REATTACH_INTERVAL);
+        });
+    if let Err(err) = res {
+        error!("Cannot subscribe to the service: {}", err);
+    }
+}
+
+/// Show the frontend tray icon until it is quit.
+pub fn run(args: &CliArgs) -> Result<(), AppStartError> {
+    gtk::init()?;
+    info!("ssgtk --service is running; starting as its frontend");
+
+    // only one frontend is needed; kept registered until it quits
+    let _registered = match application::register(true) {
+        Ok(a) if a.is_remote() => {
+            info!("Another frontend is already running");
+            return Ok(());
+        }
+        Ok(a) => Some(a),
+        Err(err) => {
+            warn!("Cannot register the application on the session bus: {}", err);
+            None
+        }
+    };
+
+    let ai = match &args.icon_theme_dir {
+        // UTF-8 guaranteed by clap validator
+        Some(dir) => AppIndicator::with_path(APP_NAME, &args.tray_icon_filename, dir.to_str().unwrap()),
+        None => AppIndicator::new(APP_NAME, &args.tray_icon_filename),
+    };
+    let (changes_tx, changes_rx) = unbounded_channel();
+    let mut frontend = Frontend {
+        ai,
+        socket_path: args.runtime_api_socket_path.clone(),
+        changes_rx,
+    };
+    frontend.ai.set_status(AppIndicatorStatus::Active);
+    frontend.rebuild_menu(true);
+    spawn_subscriber(frontend.socket_path.clone(), changes_tx);
+
+    let loop_action_id = glib::timeout_add_local(Duration::from_millis(100), move || {
+        // only the latest change matters
+        if let Some(attached) = frontend.changes_rx.try_iter().last() {
+            frontend.rebuild_menu(attached);
+        }
+        Continue(true)
+    });
+    gtk::main();
+    loop_action_id.remove();
+    Ok(())
+}
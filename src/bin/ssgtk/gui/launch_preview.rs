@@ -0,0 +1,86 @@
+//! This module contains code that creates a dialog showing what a profile
+//! is launched with, for debugging.
+
+use gtk::{
+    prelude::*, CheckButton, Dialog, Expander, Label, ResponseType, ScrolledWindow, TextBuffer, TextView, WrapMode,
+};
+use itertools::Itertools;
+use log::{debug, error};
+
+use crate::io::profile_loader::{LaunchPreview, Profile};
+
+/// Create a read-only, monospace text view with `text`.
+fn text_view(text: &str) -> TextView {
+    let buffer = TextBuffer::builder().text(text).build();
+    TextView::builder()
+        .buffer(&buffer)
+        .editable(false)
+        .monospace(true)
+        .wrap_mode(WrapMode::WordChar)
+        .margin(6)
+        .build()
+}
+
+/// Show a non-blocking dialog with the launch command of `profile` in `preview`,
+/// which has its passwords masked until the user reveals them.
+pub fn show_launch_preview_dialog(profile: &Profile, preview: &LaunchPreview) {
+    let profile_name = &profile.metadata.display_name;
+    debug!("Showing launch command of profile \"{}\"", profile_name);
+
+    let hint = match preview.as_root {
+        true => "This profile is run as root via pkexec, which resets the environment.",
+        false => "Passwords are masked. Arguments for traffic stats are added at launch.",
+    };
+    let hint = Label::builder().label(hint).wrap(true).xalign(0.0).margin(6).build();
+    let masked_command = preview.to_shell_command();
+    let command = text_view(&masked_command);
+    let reveal_button = CheckButton::builder().label("Reveal Passwords").margin(6).build();
+    let profile = profile.clone();
+    let command_buffer = command.buffer().expect("text view is created with a buffer");
+    reveal_button.connect_toggled(move |button| {
+        let text = match button.is_active() {
+            true => match profile.launch_preview(true) {
+                Ok(preview) => preview.to_shell_command(),
+                Err(err) => {
+                    error!(
+                        "Cannot reveal passwords of profile \"{}\": {}",
+                        profile.metadata.display_name, err
+                    );
+                    button.set_active(false);
+                    return;
+                }
+            },
+            false => masked_command.clone(),
+        };
+        command_buffer.set_text(&text);
+    });
+
+    let env = (preview.env.iter())
+        .map(|(key, value)| format!("{}={}", key.to_string_lossy(), value.to_string_lossy()))
+        .join("\n");
+    let env_scrolled = ScrolledWindow::builder()
+        .min_content_height(200)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    env_scrolled.add(&text_view(&env));
+    let env_expander = Expander::builder()
+        .label(&format!("Environment ({} variables)", preview.env.len()))
+        .margin(6)
+        .build();
+    env_expander.add(&env_scrolled);
+
+    let dialog = Dialog::builder()
+        .default_width(600)
+        .deletable(true)
+        .title(&format!("Launch Command of {}", profile_name))
+        .build();
+    dialog.content_area().add(&hint);
+    dialog.content_area().add(&command);
+    dialog.content_area().add(&reveal_button);
+    dialog.content_area().add(&env_expander);
+    dialog.add_button("Close", ResponseType::Close);
+
+    dialog.connect_response(|dialog, _| dialog.close()); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
@@ -0,0 +1,54 @@
+//! This module contains code that creates a dialog listing the profiles
+//! that could not be loaded, and why.
+
+use gtk::{prelude::*, Dialog, Label, ListBox, ResponseType, ScrolledWindow, SelectionMode};
+use log::debug;
+
+use crate::io::profile_loader::LoadProblem;
+
+/// Show a non-blocking dialog with a row for each of `problems`.
+pub fn show_load_problems_dialog(problems: &[LoadProblem]) {
+    debug!("Showing {} profile load problems", problems.len());
+
+    let list = ListBox::builder().selection_mode(SelectionMode::None).build();
+    for problem in problems {
+        let location = match &problem.name {
+            Some(name) => format!("\"{}\" in {}", name, problem.path.display()),
+            None => problem.path.display().to_string(),
+        };
+        let label = Label::builder()
+            .label(&format!("{}\n{}", location, problem.error))
+            .selectable(true)
+            .wrap(true)
+            .wrap_mode(gtk::pango::WrapMode::WordChar)
+            .xalign(0.0)
+            .margin(6)
+            .build();
+        list.add(&label);
+    }
+
+    let hint = Label::builder()
+        .label("These profiles are skipped until fixed. The others are loaded as usual.")
+        .wrap(true)
+        .xalign(0.0)
+        .margin(6)
+        .build();
+    let scrolled = ScrolledWindow::builder()
+        .min_content_height(300)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    scrolled.add(&list);
+
+    let dialog = Dialog::builder()
+        .default_width(500)
+        .deletable(true)
+        .title("Profile Load Problems")
+        .build();
+    dialog.content_area().add(&hint);
+    dialog.content_area().add(&scrolled);
+    dialog.add_button("Close", ResponseType::Close);
+
+    dialog.connect_response(|dialog, _| dialog.close()); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
@@ -0,0 +1,80 @@
+//! This module contains code that shows the progress of loading profiles on startup.
+
+use std::{
+    panic,
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use gtk::{prelude::*, Label, ProgressBar, Window, WindowPosition};
+use log::debug;
+
+use crate::io::profile_loader::LoadProgress;
+
+/// Loading faster than this does not show a window at all, so that it does not flash by.
+const SHOW_AFTER: Duration = Duration::from_millis(500);
+/// How often the window is updated.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Run `load` on another thread, showing a window with the loading progress if it takes a while.
+///
+/// Blocks until `load` is done, while keeping GTK responsive. Must be called after GTK is initialised,
+/// and before the main loop is started.
+pub fn load_with_progress<T: Send>(progress: &Arc<LoadProgress>, load: impl FnOnce() -> T + Send) -> T {
+    let start = Instant::now();
+    thread::scope(|s| {
+        let handle = s.spawn(load);
+        let mut window: Option<(Window, ProgressBar)> = None;
+        while !handle.is_finished() {
+            thread::sleep(POLL_INTERVAL);
+            if window.is_none() && start.elapsed() > SHOW_AFTER {
+                debug!("Loading profiles is taking a while; showing progress");
+                window = Some(build_window());
+            }
+            if let Some((_, bar)) = &window {
+                let done = progress.done.load(Ordering::Relaxed);
+                let total = progress.total.load(Ordering::Relaxed);
+                match progress.fraction() {
+                    // still looking for profiles
+                    None => bar.pulse(),
+                    Some(fraction) => {
+                        bar.set_fraction(fraction);
+                        bar.set_text(Some(&format!("{} of {} profiles", done, total)));
+                    }
+                }
+            }
+            while gtk::events_pending() {
+                gtk::main_iteration();
+            }
+        }
+        if let Some((window, _)) = window {
+            window.close();
+        }
+        handle.join().unwrap_or_else(|err| panic::resume_unwind(err))
+    })
+}
+
+fn build_window() -> (Window, ProgressBar) {
+    let label = Label::builder()
+        .label("Loading profiles…")
+        .xalign(0.0)
+        .margin(6)
+        .build();
+    let bar = ProgressBar::builder().show_text(true).margin(6).build();
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    content.add(&label);
+    content.add(&bar);
+    let window = Window::builder()
+        .title("Loading Profiles")
+        .default_width(300)
+        .deletable(false)
+        .resizable(false)
+        .window_position(WindowPosition::Center)
+        .border_width(6)
+        .build();
+    window.add(&content);
+    window.show_all(); // render
+    window.present(); // bring to foreground
+    (window, bar)
+}
@@ -1,24 +1,361 @@
 //! This module contains code that creates a window for showing
-//! the logs emitted by `sslocal`.
+//! the logs emitted by `sslocal`, which can be searched and filtered by severity,
+//! copied, and saved to a file.
 
-use std::{rc::Rc, sync::mpsc::TryRecvError, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    str::FromStr,
+    sync::mpsc::TryRecvError,
+    time::Duration,
+};
 
 use bus::BusReader;
 use crossbeam_channel::Sender;
-use glib::SourceId;
+use glib::{translate::IntoGlib, SourceId};
 use gtk::{
-    prelude::*, ApplicationWindow, CheckButton, Frame, Grid, PolicyType, ScrolledWindow, TextBuffer, TextView, WrapMode,
+    gdk, gio, pango, prelude::*, ApplicationWindow, Button, CheckButton, Clipboard, ComboBoxText, DestDefaults,
+    FileChooserAction, FileChooserDialog, Frame, Grid, IconSize, Label, Orientation, PolicyType, ResponseType,
+    ScrolledWindow, SearchEntry, TextBuffer, TextTag, TextView, WrapMode,
+};
+use log::{debug, error, trace, Level, LevelFilter};
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    event::AppEvent,
+    gui::track_geometry,
+    io::app_state::WindowGeometry,
+    log_parser::{self, SgrStyle},
 };
-use log::{error, trace};
 
-use crate::event::AppEvent;
+/// The choices of the severity filter, as (level, label).
+const LEVEL_CHOICES: [(LevelFilter, &str); 5] = [
+    (LevelFilter::Trace, "All levels"),
+    (LevelFilter::Debug, "Debug and above"),
+    (LevelFilter::Info, "Info and above"),
+    (LevelFilter::Warn, "Warn and above"),
+    (LevelFilter::Error, "Error only"),
+];
+
+/// The text tags used to filter and highlight logs.
+#[derive(Debug)]
+struct Tags {
+    /// Applied to lines hidden by the severity filter.
+    hidden: TextTag,
+    /// Applied to all search matches.
+    found: TextTag,
+    /// Applied to the selected search match.
+    selected: TextTag,
+    /// Applied to lines by severity; info lines are left as is.
+    error: TextTag,
+    warn: TextTag,
+    verbose: TextTag,
+}
+
+impl Tags {
+    /// The color tag for lines of a level.
+    fn severity(&self, level: Level) -> Option<&TextTag> {
+        match level {
+            Level::Error => Some(&self.error),
+            Level::Warn => Some(&self.warn),
+            Level::Info => None,
+            Level::Debug | Level::Trace => Some(&self.verbose),
+        }
+    }
+}
+
+/// The state of the search bar and severity filter.
+#[derive(Debug)]
+struct Filter {
+    /// `None` if the search text is empty or invalid.
+    regex: Option<Regex>,
+    /// Lines less severe than this are hidden.
+    max_level: LevelFilter,
+    /// The level of the last line processed, which is inherited by lines without a level,
+    /// e.g. the continuation of a multi-line message.
+    last_level: Option<Level>,
+    /// The (start, end) char offsets of all visible matches, in order.
+    matches: Vec<(i32, i32)>,
+    /// The index of the selected match in `matches`.
+    selected: Option<usize>,
+}
+
+/// The log buffer, together with the search and filter state.
+///
+/// Since the buffer is append-only, filters are applied to new logs incrementally.
+#[derive(Debug)]
+struct LogSearch {
+    buffer: TextBuffer,
+    text_view: TextView,
+    match_label: Label,
+    auto_scroll: CheckButton,
+    tags: Tags,
+    filter: RefCell<Filter>,
+    /// Whether to drop the ANSI colors in logs instead of rendering them.
+    strip_colors: bool,
+    /// The ANSI style in effect at the end of the buffer.
+    ansi_style: Cell<SgrStyle>,
+    /// The tags rendering ANSI styles, created as they are first used.
+    ansi_tags: RefCell<HashMap<SgrStyle, TextTag>>,
+}
+
+impl LogSearch {
+    fn new(text_view: TextView, match_label: Label, auto_scroll: CheckButton, strip_colors: bool) -> Self {
+        let buffer = text_view.buffer().unwrap(); // `TextView::new` creates buffer
+        let tags = Tags {
+            hidden: TextTag::builder().invisible(true).build(),
+            found: TextTag::builder().background("#fce94f").build(),
+            selected: TextTag::builder().background("#fcaf3e").build(),
+            error: TextTag::builder().foreground("#cc0000").build(),
+            warn: TextTag::builder().foreground("#ce5c00").build(),
+            verbose: TextTag::builder().foreground("#888a85").build(),
+        };
+        let tag_table = buffer.tag_table().unwrap(); // `TextBuffer::new` creates tag table
+        for tag in [
+            &tags.hidden,
+            &tags.found,
+            &tags.selected,
+            &tags.error,
+            &tags.warn,
+            &tags.verbose,
+        ] {
+            tag_table.add(tag);
+        }
+        Self {
+            buffer,
+            text_view,
+            match_label,
+            auto_scroll,
+            tags,
+            filter: RefCell::new(Filter {
+                regex: None,
+                max_level: LevelFilter::Trace,
+                last_level: None,
+                matches: vec![],
+                selected: None,
+            }),
+            strip_colors,
+            ansi_style: Cell::new(SgrStyle::default()),
+            ansi_tags: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Get the tag rendering an ANSI style, creating it if necessary.
+    ///
+    /// Returns `None` for the plain style.
+    fn ansi_tag(&self, style: SgrStyle) -> Option<TextTag> {
+        if style.is_plain() {
+            return None;
+        }
+        let mut ansi_tags = self.ansi_tags.borrow_mut();
+        let tag = ansi_tags.entry(style).or_insert_with(|| {
+            let hex = |(r, g, b): (u8, u8, u8)| format!("#{:02x}{:02x}{:02x}", r, g, b);
+            let mut builder = TextTag::builder();
+            if style.bold {
+                builder = builder.weight(pango::Weight::Bold.into_glib());
+            }
+            if style.italic {
+                builder = builder.style(pango::Style::Italic);
+            }
+            if style.underline {
+                builder = builder.underline(pango::Underline::Single);
+            }
+            if let Some(color) = style.foreground {
+                builder = builder.foreground(&hex(color));
+            }
+            if let Some(color) = style.background {
+                builder = builder.background(&hex(color));
+            }
+            let tag = builder.build();
+            self.buffer.tag_table().unwrap().add(&tag); // `TextBuffer::new` creates tag table
+                                                        // take precedence over severity colors, but not over search highlights
+            tag.set_priority(self.tags.found.priority());
+            tag
+        });
+        Some(tag.clone())
+    }
+
+    /// Append new logs, rendering or stripping their ANSI colors, then filter and search them.
+    fn append(&self, text: &str) {
+        let end = self.buffer.end_iter();
+        let (from_line, from_offset) = (end.line(), end.offset());
+        if self.strip_colors {
+            self.buffer.place_cursor(&end);
+            self.buffer.insert_at_cursor(&log_parser::strip_ansi(text));
+        } else {
+            let mut style = self.ansi_style.get();
+            for (segment, segment_style) in log_parser::parse_ansi(text, &mut style) {
+                let start_offset = self.buffer.end_iter().offset();
+                self.buffer.insert(&mut self.buffer.end_iter(), segment);
+                if let Some(tag) = self.ansi_tag(segment_style) {
+                    let start = self.buffer.iter_at_offset(start_offset);
+                    self.buffer.apply_tag(&tag, &start, &self.buffer.end_iter());
+                }
+            }
+            self.ansi_style.set(style);
+        }
+
+        let mut filter = self.filter.borrow_mut();
+        self.apply_level_filter(&mut filter, from_line);
+        self.apply_search(&mut filter, from_offset);
+        self.update_match_label(&filter);
+    }
+
+    /// Set the search text, which is a case-insensitive regex.
+    ///
+    /// Returns `false` if it is not a valid regex.
+    fn set_search(&self, text: &str) -> bool {
+        let regex = match text {
+            "" => Ok(None),
+            text => RegexBuilder::new(text).case_insensitive(true).build().map(Some),
+        };
+        let valid = regex.is_ok();
+        self.filter.borrow_mut().regex = regex.unwrap_or(None);
+        self.refresh();
+        valid
+    }
+
+    /// Set the least severe level to show.
+    fn set_max_level(&self, level: LevelFilter) {
+        self.filter.borrow_mut().max_level = level;
+        self.refresh();
+    }
+
+    /// Clear all tags, then filter and search all logs again.
+    fn refresh(&self) {
+        let (start, end) = self.buffer.bounds();
+        for tag in [&self.tags.hidden, &self.tags.found, &self.tags.selected] {
+            self.buffer.remove_tag(tag, &start, &end);
+        }
+        let mut filter = self.filter.borrow_mut();
+        filter.last_level = None;
+        filter.matches.clear();
+        filter.selected = None;
+        self.apply_level_filter(&mut filter, 0);
+        self.apply_search(&mut filter, 0);
+        self.update_match_label(&filter);
+    }
+
+    /// Color lines by severity, and hide lines less severe than the filter, starting from `from_line`.
+    fn apply_level_filter(&self, filter: &mut Filter, from_line: i32) {
+        for line in from_line..self.buffer.line_count() {
+            let start = self.buffer.iter_at_line(line);
+            let mut end = start;
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            let text = self
+                .buffer
+                .text(&start, &end, true)
+                .map(String::from)
+                .unwrap_or_default();
+            if let Some(event) = log_parser::parse_line(&text) {
+                filter.last_level = Some(event.level);
+            }
+            if let Some(tag) = filter.last_level.and_then(|level| self.tags.severity(level)) {
+                self.buffer.apply_tag(tag, &start, &end);
+            }
+            if filter.last_level.is_some_and(|level| level > filter.max_level) {
+                end.forward_line(); // include the line break
+                self.buffer.apply_tag(&self.tags.hidden, &start, &end);
+            }
+        }
+    }
+
+    /// Highlight all visible matches, starting from `from_offset`.
+    fn apply_search(&self, filter: &mut Filter, from_offset: i32) {
+        let regex = match &filter.regex {
+            Some(r) => r,
+            None => return,
+        };
+        let start = self.buffer.iter_at_offset(from_offset);
+        let text = self
+            .buffer
+            .text(&start, &self.buffer.end_iter(), true)
+            .map(String::from)
+            .unwrap_or_default();
+
+        // regex works with byte indices, but `TextBuffer` works with char offsets
+        let (mut last_byte, mut last_char) = (0, from_offset);
+        for m in regex.find_iter(&text).filter(|m| !m.as_str().is_empty()) {
+            last_char += text[last_byte..m.start()].chars().count() as i32;
+            last_byte = m.start();
+            let (match_start, match_end) = (last_char, last_char + m.as_str().chars().count() as i32);
+            let match_start_iter = self.buffer.iter_at_offset(match_start);
+            if match_start_iter.has_tag(&self.tags.hidden) {
+                continue;
+            }
+            let match_end_iter = self.buffer.iter_at_offset(match_end);
+            self.buffer
+                .apply_tag(&self.tags.found, &match_start_iter, &match_end_iter);
+            filter.matches.push((match_start, match_end));
+        }
+    }
+
+    /// Select the next (or previous) match, and scroll to it.
+    ///
+    /// Also disables auto-scroll, so that it stays in view.
+    fn jump(&self, forward: bool) {
+        let mut filter = self.filter.borrow_mut();
+        let count = filter.matches.len();
+        if count == 0 {
+            return;
+        }
+        let selected = match (filter.selected, forward) {
+            (None, true) => 0,
+            (None, false) => count - 1,
+            (Some(i), true) => (i + 1) % count,
+            (Some(i), false) => (i + count - 1) % count,
+        };
+        filter.selected = Some(selected);
+
+        let (start, end) = self.buffer.bounds();
+        self.buffer.remove_tag(&self.tags.selected, &start, &end);
+        let (match_start, match_end) = filter.matches[selected];
+        let mut match_start_iter = self.buffer.iter_at_offset(match_start);
+        let match_end_iter = self.buffer.iter_at_offset(match_end);
+        self.buffer
+            .apply_tag(&self.tags.selected, &match_start_iter, &match_end_iter);
+        self.auto_scroll.set_active(false);
+        self.text_view
+            .scroll_to_iter(&mut match_start_iter, 0.0, true, 0.0, 0.5);
+        self.update_match_label(&filter);
+    }
+
+    /// Copy the selected logs to the clipboard, or all logs if `all` is set.
+    ///
+    /// Lines hidden by the severity filter are left out.
+    fn copy(&self, all: bool) {
+        let (start, end) = match all {
+            true => self.buffer.bounds(),
+            false => match self.buffer.selection_bounds() {
+                Some(bounds) => bounds,
+                None => return,
+            },
+        };
+        if let Some(text) = self.buffer.text(&start, &end, false) {
+            Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text);
+        }
+    }
+
+    fn update_match_label(&self, filter: &Filter) {
+        let text = match (&filter.regex, filter.matches.len(), filter.selected) {
+            (None, _, _) => String::new(),
+            (Some(_), 0, _) => "No matches".into(),
+            (Some(_), count, None) => format!("{} matches", count),
+            (Some(_), count, Some(i)) => format!("{}/{}", i + 1, count),
+        };
+        self.match_label.set_text(&text);
+    }
+}
 
 #[derive(Debug)]
 pub struct LogViewerWindow {
     window: ApplicationWindow,
+    geometry: Rc<Cell<Option<WindowGeometry>>>,
     scroll: Rc<ScrolledWindow>,
-    buffer: Rc<TextBuffer>,
-    auto_scroll: Rc<CheckButton>,
+    search: Rc<LogSearch>,
 
     scheduled_fn_ids: Vec<SourceId>,
 }
@@ -35,7 +372,18 @@ impl Drop for LogViewerWindow {
 
 impl LogViewerWindow {
     /// Create a new `LogViewerWindow`, fill with existing backlog, and set up piping for new logs.
-    pub fn new(events_tx: Sender<AppEvent>, backlog: impl AsRef<str>, mut log_listener: BusReader<String>) -> Self {
+    ///
+    /// `dropped_lines` is the number of lines dropped from the backlog, which is shown if non-zero.
+    /// If `strip_colors` is set, ANSI colors in logs are dropped instead of rendered.
+    /// The window is placed at `geometry` if set.
+    pub fn new(
+        events_tx: Sender<AppEvent>,
+        backlog: impl AsRef<str>,
+        dropped_lines: usize,
+        mut log_listener: BusReader<String>,
+        strip_colors: bool,
+        geometry: Option<WindowGeometry>,
+    ) -> Self {
         // compose window
         let text_view = TextView::builder()
             .cursor_visible(false)
@@ -54,7 +402,10 @@ impl LogViewerWindow {
         let frame = Frame::builder()
             .child(&scroll_box)
             .expand(true)
-            .label("sslocal Logs")
+            .label(&match dropped_lines {
+                0 => "sslocal Logs".into(),
+                n => format!("sslocal Logs ({} earlier lines dropped)", n),
+            })
             .label_xalign(0.1)
             .margin(12)
             .margin_bottom(0)
@@ -63,12 +414,57 @@ impl LogViewerWindow {
             .active(true)
             .hexpand(true)
             .label("Auto-scroll to the newest logs")
-            .margin(12)
+            .tooltip_text("Stops when you scroll up")
+            .build();
+        let copy_button = Button::with_label("Copy Selection");
+        let copy_all_button = Button::with_label("Copy All");
+        copy_all_button.set_tooltip_text(Some("Lines hidden by the severity filter are left out"));
+        let save_button = Button::with_label("Save to File…");
+        let bottom_bar = {
+            let bottom_bar = gtk::Box::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(6)
+                .margin(12)
+                .build();
+            bottom_bar.add(&scroll_checkbox);
+            bottom_bar.add(&copy_button);
+            bottom_bar.add(&copy_all_button);
+            bottom_bar.add(&save_button);
+            bottom_bar
+        };
+        let search_entry = SearchEntry::builder()
+            .hexpand(true)
+            .placeholder_text("Search (regex)")
             .build();
+        let prev_button = Button::from_icon_name(Some("go-up-symbolic"), IconSize::Button);
+        prev_button.set_tooltip_text(Some("Previous match (Ctrl+Shift+G)"));
+        let next_button = Button::from_icon_name(Some("go-down-symbolic"), IconSize::Button);
+        next_button.set_tooltip_text(Some("Next match (Enter or Ctrl+G)"));
+        let match_label = Label::builder().width_chars(10).build();
+        let level_selector = ComboBoxText::new();
+        for (level, label) in LEVEL_CHOICES {
+            level_selector.append(Some(level.as_str()), label);
+        }
+        level_selector.set_active(Some(0));
+        let search_bar = {
+            let search_bar = gtk::Box::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(6)
+                .margin(12)
+                .margin_bottom(0)
+                .build();
+            search_bar.add(&search_entry);
+            search_bar.add(&prev_button);
+            search_bar.add(&next_button);
+            search_bar.add(&match_label);
+            search_bar.add(&level_selector);
+            search_bar
+        };
         let grid = {
             let grid = Grid::new();
-            grid.attach(&frame, 0, 0, 1, 1);
-            grid.attach(&scroll_checkbox, 0, 1, 1, 1);
+            grid.attach(&search_bar, 0, 0, 1, 1);
+            grid.attach(&frame, 0, 1, 1, 1);
+            grid.attach(&bottom_bar, 0, 2, 1, 1);
             grid
         };
         let window = ApplicationWindow::builder()
@@ -78,24 +474,83 @@ impl LogViewerWindow {
             .title("Log Viewer")
             .build();
 
+        let geometry = track_geometry(&window, geometry);
         let mut ret = Self {
             window,
+            geometry,
             scroll: scroll_box.into(),
-            buffer: text_view.buffer().unwrap().into(), // `TextView::new` creates buffer
-            auto_scroll: scroll_checkbox.into(),
+            search: LogSearch::new(text_view, match_label, scroll_checkbox.clone(), strip_colors).into(),
             scheduled_fn_ids: vec![],
         };
 
         // insert backlog
-        ret.buffer.place_cursor(&ret.buffer.end_iter());
-        ret.buffer.insert_at_cursor(backlog.as_ref());
+        ret.search.append(backlog.as_ref());
+
+        // handle search and filter
+        let search = Rc::clone(&ret.search);
+        search_entry.connect_search_changed(move |entry| {
+            // mark invalid regex
+            match search.set_search(&entry.text()) {
+                true => entry.style_context().remove_class("error"),
+                false => entry.style_context().add_class("error"),
+            }
+        });
+        let search = Rc::clone(&ret.search);
+        search_entry.connect_activate(move |_| search.jump(true));
+        let search = Rc::clone(&ret.search);
+        search_entry.connect_next_match(move |_| search.jump(true));
+        let search = Rc::clone(&ret.search);
+        search_entry.connect_previous_match(move |_| search.jump(false));
+        let search = Rc::clone(&ret.search);
+        next_button.connect_clicked(move |_| search.jump(true));
+        let search = Rc::clone(&ret.search);
+        prev_button.connect_clicked(move |_| search.jump(false));
+        let search = Rc::clone(&ret.search);
+        level_selector.connect_changed(move |selector| {
+            let level = selector
+                .active_id()
+                .and_then(|id| LevelFilter::from_str(&id).ok())
+                .unwrap_or(LevelFilter::Trace);
+            search.set_max_level(level);
+        });
+
+        // handle copy and save
+        ret.search
+            .buffer
+            .bind_property("has-selection", &copy_button, "sensitive")
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
+        let search = Rc::clone(&ret.search);
+        copy_button.connect_clicked(move |_| search.copy(false));
+        let search = Rc::clone(&ret.search);
+        copy_all_button.connect_clicked(move |_| search.copy(true));
+        let window = ret.window.clone();
+        let save_events_tx = events_tx.clone();
+        save_button.connect_clicked(move |_| show_save_dialog(&window, save_events_tx.clone()));
+
+        // import what is dropped onto the window, after confirmation;
+        // the log itself would otherwise take text drops, despite being read-only
+        ret.search.text_view.drag_dest_unset();
+        ret.window.drag_dest_set(DestDefaults::ALL, &[], gdk::DragAction::COPY);
+        ret.window.drag_dest_add_uri_targets();
+        ret.window.drag_dest_add_text_targets();
+        let drop_events_tx = events_tx.clone();
+        ret.window.connect_drag_data_received(move |_, _, _, _, data, _, _| {
+            let paths: Vec<_> = (data.uris().iter())
+                .filter_map(|uri| gio::File::for_uri(uri).path())
+                .collect();
+            // a list of URIs is not text
+            let text = data.text().map(String::from).filter(|_| paths.is_empty());
+            if drop_events_tx.send(AppEvent::DropReceived { paths, text }).is_err() {
+                error!("Trying to send DropReceived event, but all receivers have hung up.");
+            }
+        });
 
         // pipe incoming new logs
-        let buffer = Rc::clone(&ret.buffer);
+        let search = Rc::clone(&ret.search);
         let id = glib::source::timeout_add_local(Duration::from_millis(100), move || match log_listener.try_recv() {
             Ok(s) => {
-                buffer.place_cursor(&buffer.end_iter());
-                buffer.insert_at_cursor(&s);
+                search.append(&s);
                 Continue(true)
             }
             Err(TryRecvError::Empty) => Continue(true),
@@ -106,9 +561,22 @@ impl LogViewerWindow {
         });
         ret.scheduled_fn_ids.push(id);
 
+        // stop auto-scrolling when the user scrolls up;
+        // auto-scroll only ever moves down, and new logs only extend the bottom
+        let last_value = Cell::new(ret.scroll.vadjustment().value());
+        let auto_scroll = scroll_checkbox.clone();
+        ret.scroll.vadjustment().connect_value_changed(move |adj| {
+            let value = adj.value();
+            let at_bottom = value >= adj.upper() - adj.page_size() - 1.0;
+            if value < last_value.get() && !at_bottom {
+                auto_scroll.set_active(false);
+            }
+            last_value.set(value);
+        });
+
         // handle auto-scroll
         let scroll = Rc::clone(&ret.scroll);
-        let auto_scroll = Rc::clone(&ret.auto_scroll);
+        let auto_scroll = scroll_checkbox;
         let id = glib::source::timeout_add_local(
             Duration::from_millis(100), // 10fps
             move || {
@@ -141,6 +609,43 @@ impl LogViewerWindow {
     pub fn close(&self) {
         self.window.close();
     }
+
+    /// The latest position and size of the window.
+    pub fn geometry(&self) -> Option<WindowGeometry> {
+        self.geometry.get()
+    }
+}
+
+/// Show a non-blocking dialog prompting the user for where to save the logs to.
+///
+/// A `SaveBacklog` event is sent if the user confirms.
+fn show_save_dialog(parent: &ApplicationWindow, events_tx: Sender<AppEvent>) {
+    debug!("Showing backlog save dialog");
+    let dialog = FileChooserDialog::with_buttons(
+        Some("Save sslocal Logs"),
+        Some(parent),
+        FileChooserAction::Save,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Accept)],
+    );
+    dialog.set_current_name("sslocal.log");
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_default_response(ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            match dialog.file().and_then(|f| f.path()) {
+                Some(path) => {
+                    if events_tx.send(AppEvent::SaveBacklog(path)).is_err() {
+                        error!("Trying to send SaveBacklog event, but all receivers have hung up.");
+                    }
+                }
+                None => error!("The selected file is not a local file"),
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
 }
 
 #[cfg(test)]
@@ -156,7 +661,15 @@ mod test {
         gtk::init().unwrap();
         let log_listener = Bus::new(BUS_BUFFER_SIZE).add_rx();
         let (events_tx, _) = unbounded_channel();
-        LogViewerWindow::new(events_tx, "Mock backlog", log_listener).show();
+        LogViewerWindow::new(
+            events_tx,
+            "Mock backlog \x1b[1;31mERROR\x1b[0m in \x1b[38;5;33mcolor\x1b[0m",
+            0,
+            log_listener,
+            false,
+            None,
+        )
+        .show();
         gtk::main();
     }
 }
@@ -2,8 +2,30 @@
 
 // public members
 pub mod app;
+pub mod application;
+#[cfg(feature = "runtime-api")]
+pub mod frontend;
+pub mod launch_preview;
+pub mod load_problems;
+pub mod load_progress;
 pub mod log_viewer;
 pub mod notification;
+pub mod orphan_prompt;
+#[cfg(feature = "encryption")]
+pub mod passphrase_prompt;
+pub mod preferences;
+pub mod profile_delete;
+pub mod profile_duplicate;
+pub mod profile_editor;
+pub mod profile_export;
+pub mod profile_filter;
+pub mod qr_export;
+pub mod stats_window;
 pub mod tray;
+pub mod url_import;
 
 // private members with re-export
+mod display_server;
+pub use display_server::DisplayServer;
+mod window_geometry;
+pub use window_geometry::track_geometry;
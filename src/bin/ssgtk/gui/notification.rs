@@ -1,16 +1,50 @@
+use std::{io, thread};
+
 use gtk::{prelude::*, ButtonsType, MessageDialog, MessageType};
 use log::{debug, error, info, warn};
 use notify_rust::{error as notify_error, Hint, Notification, NotificationHandle, Timeout, Urgency};
+use serde::{Deserialize, Serialize};
 use shadowsocks_gtk_rs::notify_method::NotifyMethod;
 
+use crate::redact;
+
 /// Unifies logging levels from `log` crate's macros,
 /// `gtk::MessageType` (for prompt) and `notify_rust::Urgency` (for toast).
-#[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+///
+/// Ordered by severity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Level {
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+/// The least severe notifications to show; less severe ones are only logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyLevel {
+    #[default]
+    #[serde(alias = "all")]
     Info,
     Warn,
+    #[serde(alias = "errors-only")]
     Error,
+    /// Only log all notifications.
+    Silent,
+}
+
+impl NotifyLevel {
+    /// Whether notifications of `level` should be shown.
+    pub fn allows(&self, level: Level) -> bool {
+        match self {
+            NotifyLevel::Info => true,
+            NotifyLevel::Warn => level >= Level::Warn,
+            NotifyLevel::Error => level >= Level::Error,
+            NotifyLevel::Silent => false,
+        }
+    }
 }
 
 impl Into<MessageType> for Level {
@@ -35,14 +69,24 @@ impl Into<Urgency> for Level {
 }
 
 /// Send a simple text notification, using the specified method.
-pub fn notify(method: NotifyMethod, level: Level, text_1: impl AsRef<str>, text_2: impl AsRef<str>) {
+///
+/// `icon` (an icon name or a path to an image) replaces the app's icon, where supported.
+pub fn notify(
+    method: NotifyMethod,
+    level: Level,
+    icon: Option<&str>,
+    text_1: impl AsRef<str>,
+    text_2: impl AsRef<str>,
+) {
     use NotifyMethod::*;
+    // error messages may contain passwords
+    let (text_1, text_2) = (redact::scrub(text_1.as_ref()), redact::scrub(text_2.as_ref()));
     match method {
         Disable => {} // do nothing
-        Log => notify_log(level, text_1.as_ref(), text_2.as_ref()),
-        Prompt => notify_nonblocking_prompt(level.into(), text_1.as_ref(), text_2.as_ref()),
+        Log => notify_log(level, &text_1, &text_2),
+        Prompt => notify_nonblocking_prompt(level.into(), &text_1, &text_2),
         Toast => {
-            let res = notify_toast(level.into(), text_1.as_ref(), text_2.as_ref());
+            let res = notify_toast(level.into(), icon, &text_1, &text_2);
             if let Err(err) = res {
                 error!("Failed to show toast notification: {}", err);
             }
@@ -80,10 +124,19 @@ pub fn notify_nonblocking_prompt(level: MessageType, text_1: &str, text_2: &str)
 }
 
 /// Notification impl for `NotifyMethod::Toast`.
-pub fn notify_toast(urgency: Urgency, text_1: &str, text_2: &str) -> notify_error::Result<NotificationHandle> {
+pub fn notify_toast(
+    urgency: Urgency,
+    icon: Option<&str>,
+    text_1: &str,
+    text_2: &str,
+) -> notify_error::Result<NotificationHandle> {
     debug!("Sending system notification: urgency: {:?}, title: {}", urgency, text_1);
-    Notification::new()
-        .auto_icon()
+    let mut notification = Notification::new();
+    match icon {
+        Some(icon) => notification.icon(icon),
+        None => notification.auto_icon(),
+    };
+    notification
         .body(text_2)
         .hint(Hint::Category("network".into()))
         .summary(text_1)
@@ -91,3 +144,39 @@ pub fn notify_toast(urgency: Urgency, text_1: &str, text_2: &str) -> notify_erro
         .urgency(urgency)
         .show()
 }
+
+/// Send a toast notification with a button labelled `action_label` in the background,
+/// calling `on_action` if the user clicks either the button or the notification.
+pub fn notify_toast_with_action(
+    text_1: &str,
+    text_2: &str,
+    action_label: &str,
+    on_action: impl FnOnce() + Send + 'static,
+) -> io::Result<()> {
+    let (text_1, text_2) = (redact::scrub(text_1).into_owned(), redact::scrub(text_2).into_owned());
+    let action_label = action_label.to_string();
+    thread::Builder::new()
+        .name("Notification action listener".into())
+        .spawn(move || {
+            debug!("Sending system notification with action: title: {}", text_1);
+            let res = Notification::new()
+                .auto_icon()
+                .body(&text_2)
+                .hint(Hint::Category("network".into()))
+                .summary(&text_1)
+                .action("default", &action_label) // clicking the notification itself
+                .action("accept", &action_label)
+                .timeout(Timeout::Default)
+                .urgency(Urgency::Low)
+                .show();
+            match res {
+                Ok(handle) => handle.wait_for_action(|action| {
+                    if action == "default" || action == "accept" {
+                        on_action();
+                    }
+                }),
+                Err(err) => error!("Failed to show toast notification: {}", err),
+            }
+        })?;
+    Ok(())
+}
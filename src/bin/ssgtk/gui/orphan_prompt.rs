@@ -0,0 +1,57 @@
+//! This module contains code that offers to terminate the `sslocal` processes
+//! left running by a previous session on startup, e.g. after a crash.
+
+use gtk::{prelude::*, ButtonsType, MessageDialog, MessageType, ResponseType};
+use log::{debug, error, warn};
+
+use crate::io::sslocal_pids;
+
+/// If any `sslocal` launched by a previous session is still running,
+/// ask whether to terminate them, since they would hold on to their ports.
+///
+/// Blocks until the dialog is closed. They are not asked about again either way.
+pub fn terminate_orphans_prompt() {
+    let orphans = sslocal_pids::find_orphans();
+    if orphans.is_empty() {
+        return;
+    }
+    warn!(
+        "Found {} sslocal processes left running by a previous session",
+        orphans.len()
+    );
+
+    let list = orphans
+        .iter()
+        .map(|o| format!("• \"{}\" (PID {})", o.record.profile, o.record.pid))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let dialog = MessageDialog::builder()
+        .title("Leftover sslocal Processes")
+        .message_type(MessageType::Question)
+        .buttons(ButtonsType::None)
+        .text("sslocal is still running from a previous session")
+        .secondary_text(&format!(
+            "These processes were not stopped when ssgtk last exited, and may keep their ports in use:\n{}",
+            list
+        ))
+        .build();
+    dialog.add_button("Leave Running", ResponseType::Cancel);
+    let terminate_button = dialog.add_button("Terminate", ResponseType::Accept);
+    terminate_button.style_context().add_class("destructive-action");
+    dialog.set_default_response(ResponseType::Accept);
+    dialog.show_all();
+
+    let response = dialog.run();
+    dialog.close();
+    for orphan in orphans {
+        if response == ResponseType::Accept {
+            let pid = orphan.record.pid;
+            if let Err(err) = orphan.terminate() {
+                error!("Cannot terminate sslocal {}: {}", pid, err);
+            }
+        } else {
+            debug!("Leaving sslocal {} running", orphan.record.pid);
+            orphan.forget();
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! This module contains code that prompts for the master passphrase
+//! of encrypted profiles on startup.
+
+use std::path::Path;
+
+use gtk::{prelude::*, Dialog, Entry, InputPurpose, Label, ResponseType};
+use log::{debug, info, warn};
+
+use crate::io::encryption;
+
+/// If any profile under `profiles_dir` is encrypted and the master passphrase is not
+/// in the keyring, prompt for it until it decrypts that profile or the user gives up.
+///
+/// Blocks until the dialog is closed. Encrypted profiles are skipped if no passphrase is given.
+pub fn unlock_encrypted_profiles(profiles_dir: &Path) {
+    let sample = match encryption::find_encrypted_file(profiles_dir) {
+        Some(path) => path,
+        None => return,
+    };
+    if encryption::has_passphrase() {
+        debug!("Using the master passphrase from the keyring");
+        return;
+    }
+
+    let hint = Label::builder()
+        .label("Some profiles are encrypted. Enter the master passphrase to load them.")
+        .wrap(true)
+        .xalign(0.0)
+        .margin(6)
+        .build();
+    let entry = Entry::builder()
+        .visibility(false)
+        .input_purpose(InputPurpose::Password)
+        .activates_default(true)
+        .margin(6)
+        .build();
+    let error_label = Label::builder().no_show_all(true).xalign(0.0).margin(6).build();
+
+    let dialog = Dialog::builder()
+        .default_width(400)
+        .deletable(true)
+        .title("Unlock Encrypted Profiles")
+        .build();
+    dialog.content_area().add(&hint);
+    dialog.content_area().add(&entry);
+    dialog.content_area().add(&error_label);
+    dialog.add_button("Skip", ResponseType::Cancel);
+    dialog.add_button("Unlock", ResponseType::Ok);
+    dialog.set_default_response(ResponseType::Ok);
+    dialog.show_all();
+
+    while dialog.run() == ResponseType::Ok {
+        let passphrase = entry.text().to_string();
+        match encryption::check_passphrase(&passphrase, &sample) {
+            Ok(()) => {
+                info!("Unlocked encrypted profiles");
+                encryption::set_passphrase(passphrase);
+                break;
+            }
+            Err(err) => {
+                warn!("Cannot unlock encrypted profiles: {}", err);
+                error_label.set_label("Wrong passphrase, please try again.");
+                error_label.show();
+                entry.set_text("");
+            }
+        }
+    }
+    dialog.close();
+}
@@ -0,0 +1,332 @@
+//! This module contains code that creates a dialog for editing the app settings.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crossbeam_channel::Sender;
+use gtk::{prelude::*, CheckButton, ComboBoxText, Dialog, Entry, Grid, Label, ResponseType, Widget};
+use log::{debug, error};
+
+use crate::{
+    event::AppEvent,
+    gui::notification::NotifyLevel,
+    hotkey::HotkeyAction,
+    io::{
+        app_settings::{AppSettings, NotifyEvents, RestartLimit},
+        profile_loader::NameConflictPolicy,
+    },
+};
+
+/// The notification levels selectable in the dialog, as `(id, label, level)`.
+const NOTIFY_LEVELS: &[(&str, &str, NotifyLevel)] = &[
+    ("info", "All", NotifyLevel::Info),
+    ("warn", "Warnings and errors", NotifyLevel::Warn),
+    ("error", "Errors only", NotifyLevel::Error),
+    ("silent", "None (only logged)", NotifyLevel::Silent),
+];
+
+/// The ways to handle duplicate profile names selectable in the dialog, as `(id, label, policy)`.
+const NAME_CONFLICT_POLICIES: &[(&str, &str, NameConflictPolicy)] = &[
+    ("append-group", "Append the group name", NameConflictPolicy::AppendGroup),
+    ("append-number", "Append a number", NameConflictPolicy::AppendNumber),
+    ("fail", "Skip the later profile", NameConflictPolicy::Fail),
+];
+
+/// All input widgets of the dialog.
+#[derive(Debug, Clone)]
+struct PreferencesFields {
+    backlog_lines: Entry,
+    strip_log_colors: CheckButton,
+    notify_level: ComboBoxText,
+    notify_connect: CheckButton,
+    notify_disconnect: CheckButton,
+    notify_restart: CheckButton,
+    notify_sslocal_errors: CheckButton,
+    restart_times: Entry,
+    restart_within_secs: Entry,
+    stop_grace_secs: Entry,
+    bin_path: Entry,
+    start_minimized: CheckButton,
+    connect_on_startup: CheckButton,
+    auto_connect: ComboBoxText,
+    autostart: CheckButton,
+    group_imports_by_region: CheckButton,
+    name_conflicts: ComboBoxText,
+    restart_on_network_change: CheckButton,
+    hotkeys: Vec<(HotkeyAction, Entry)>,
+}
+
+impl PreferencesFields {
+    fn new(profile_names: &[String]) -> Self {
+        let notify_level = ComboBoxText::new();
+        for (id, label, _) in NOTIFY_LEVELS {
+            notify_level.append(Some(id), label);
+        }
+        let name_conflicts = ComboBoxText::new();
+        for (id, label, _) in NAME_CONFLICT_POLICIES {
+            name_conflicts.append(Some(id), label);
+        }
+
+        // choosing a profile only makes sense when connecting on startup
+        let connect_on_startup = CheckButton::with_label("Can be overridden with --auto-connect or --no-auto-connect");
+        let auto_connect = ComboBoxText::new();
+        connect_on_startup
+            .bind_property("active", &auto_connect, "sensitive")
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
+        // the empty ID stands for unset
+        auto_connect.append(Some(""), "The most recent profile");
+        for name in profile_names {
+            auto_connect.append(Some(name), name);
+        }
+
+        Self {
+            backlog_lines: placeholder_entry("Optional, e.g. 10000"),
+            strip_log_colors: CheckButton::with_label("Strip colors from sslocal output"),
+            notify_level,
+            notify_connect: CheckButton::with_label("Connected to a profile"),
+            notify_disconnect: CheckButton::with_label("sslocal has stopped"),
+            notify_restart: CheckButton::with_label("sslocal is restarting"),
+            notify_sslocal_errors: CheckButton::with_label("sslocal has logged an error (at most once a minute)"),
+            restart_times: placeholder_entry("Optional, e.g. 5"),
+            restart_within_secs: placeholder_entry("Optional, e.g. 30"),
+            stop_grace_secs: placeholder_entry("Optional, e.g. 3"),
+            bin_path: placeholder_entry("Defaults to looking up sslocal in $PATH"),
+            start_minimized: CheckButton::with_label("Show only the tray icon; can be forced with --minimized"),
+            connect_on_startup,
+            auto_connect,
+            autostart: CheckButton::with_label("Connects on startup, regardless of the setting above"),
+            group_imports_by_region: CheckButton::with_label(
+                "Put servers imported from a file into a group per region",
+            ),
+            name_conflicts,
+            restart_on_network_change: CheckButton::with_label("Restart sslocal when the network changes"),
+            hotkeys: enum_iterator::all::<HotkeyAction>()
+                .map(|action| (action, placeholder_entry("Optional, e.g. CTRL+ALT+S")))
+                .collect(),
+        }
+    }
+
+    /// Fill all fields with the current settings.
+    fn fill(&self, settings: &AppSettings) {
+        let number = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_default();
+        self.backlog_lines
+            .set_text(&number(settings.backlog_lines.map(|n| n as u64)));
+        self.strip_log_colors.set_active(settings.strip_log_colors);
+        let level_id = NOTIFY_LEVELS.iter().find(|(_, _, l)| *l == settings.notify_level);
+        self.notify_level.set_active_id(level_id.map(|(id, _, _)| *id));
+        let events = settings.notify_events;
+        self.notify_connect.set_active(events.connect);
+        self.notify_disconnect.set_active(events.disconnect);
+        self.notify_restart.set_active(events.restart);
+        self.notify_sslocal_errors.set_active(events.sslocal_errors);
+        let limit = settings.restart_limit;
+        self.restart_times.set_text(&number(limit.map(|l| l.times as u64)));
+        self.restart_within_secs.set_text(&number(limit.map(|l| l.within_secs)));
+        self.stop_grace_secs.set_text(&number(settings.stop_grace_secs));
+        if let Some(path) = &settings.bin_path {
+            self.bin_path.set_text(&path.to_string_lossy());
+        }
+        self.start_minimized.set_active(settings.start_minimized);
+        self.connect_on_startup.set_active(settings.connect_on_startup);
+        self.group_imports_by_region
+            .set_active(settings.group_imports_by_region);
+        let policy_id = (NAME_CONFLICT_POLICIES.iter()).find(|(_, _, p)| *p == settings.name_conflicts);
+        self.name_conflicts.set_active_id(policy_id.map(|(id, _, _)| *id));
+        self.restart_on_network_change
+            .set_active(settings.restart_on_network_change);
+        // a profile that no longer exists is shown as unset
+        let auto_connect = settings.auto_connect.as_deref().unwrap_or_default();
+        if !self.auto_connect.set_active_id(Some(auto_connect)) {
+            self.auto_connect.set_active_id(Some(""));
+        }
+        for (action, entry) in self.hotkeys.iter() {
+            entry.set_text(settings.hotkeys.get(action).map_or("", |s| s.as_str()));
+        }
+    }
+
+    /// Build the settings from all fields.
+    ///
+    /// Returns all problems found on failure.
+    fn to_settings(&self) -> Result<AppSettings, Vec<String>> {
+        let mut problems = vec![];
+        let optional = |entry: &Entry| Some(entry.text().trim().to_string()).filter(|s| !s.is_empty());
+        let number = |entry: &Entry, name: &str, problems: &mut Vec<String>| {
+            optional(entry).and_then(|text| match text.parse::<u64>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    problems.push(format!("{} must be a positive integer", name));
+                    None
+                }
+            })
+        };
+
+        let backlog_lines = number(&self.backlog_lines, "Log viewer lines", &mut problems);
+        let notify_level = self
+            .notify_level
+            .active_id()
+            .and_then(|id| NOTIFY_LEVELS.iter().find(|(i, _, _)| *i == id.as_str()))
+            .map(|(_, _, level)| *level)
+            .unwrap_or_default();
+        let name_conflicts = self
+            .name_conflicts
+            .active_id()
+            .and_then(|id| NAME_CONFLICT_POLICIES.iter().find(|(i, _, _)| *i == id.as_str()))
+            .map(|(_, _, policy)| *policy)
+            .unwrap_or_default();
+        let restart_times = number(&self.restart_times, "Restart limit", &mut problems);
+        let restart_within_secs = number(&self.restart_within_secs, "Restart limit period", &mut problems);
+        let restart_limit = match (restart_times, restart_within_secs) {
+            (Some(times), Some(within_secs)) => Some(RestartLimit {
+                times: times as usize,
+                within_secs,
+            }),
+            (None, None) => None,
+            _ => {
+                problems.push("Restart limit and its period must be set together".into());
+                None
+            }
+        };
+        let stop_grace_secs = number(&self.stop_grace_secs, "Stop grace period", &mut problems);
+        let bin_path = optional(&self.bin_path).map(PathBuf::from);
+        let auto_connect = self
+            .auto_connect
+            .active_id()
+            .map(|id| id.to_string())
+            .filter(|id| !id.is_empty());
+        let hotkeys: BTreeMap<_, _> = self
+            .hotkeys
+            .iter()
+            .filter_map(|(action, entry)| optional(entry).map(|trigger| (*action, trigger)))
+            .collect();
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(AppSettings {
+            backlog_lines: backlog_lines.map(|n| n as usize),
+            strip_log_colors: self.strip_log_colors.is_active(),
+            notify_level,
+            notify_events: NotifyEvents {
+                connect: self.notify_connect.is_active(),
+                disconnect: self.notify_disconnect.is_active(),
+                restart: self.notify_restart.is_active(),
+                sslocal_errors: self.notify_sslocal_errors.is_active(),
+            },
+            restart_limit,
+            stop_grace_secs,
+            bin_path,
+            start_minimized: self.start_minimized.is_active(),
+            connect_on_startup: self.connect_on_startup.is_active(),
+            auto_connect,
+            group_imports_by_region: self.group_imports_by_region.is_active(),
+            name_conflicts,
+            // not editable here; kept by the app on save
+            max_load_depth: None,
+            lazy_load: false,
+            hotkeys,
+            schedule: vec![],
+            restart_on_network_change: self.restart_on_network_change.is_active(),
+            network_rules: vec![],
+            systemd_scope: None,
+        })
+    }
+}
+
+/// Show a dialog for editing the app settings.
+///
+/// Sends `AppEvent::SavePreferences` when the user saves.
+pub fn show_preferences_dialog(
+    events_tx: Sender<AppEvent>,
+    settings: &AppSettings,
+    autostart: bool,
+    profile_names: &[String],
+) {
+    debug!("Showing preferences dialog");
+
+    let fields = PreferencesFields::new(profile_names);
+    fields.fill(settings);
+    fields.autostart.set_active(autostart);
+
+    // layout
+    let grid = Grid::builder().column_spacing(12).margin(12).row_spacing(6).build();
+    let mut row = 0;
+    let mut add_row = |label: &str, widget: &Widget| {
+        let label = Label::builder().label(label).xalign(1.0).build();
+        widget.set_hexpand(true);
+        grid.attach(&label, 0, row, 1, 1);
+        grid.attach(widget, 1, row, 1, 1);
+        row += 1;
+    };
+    add_row("Log viewer lines", fields.backlog_lines.upcast_ref());
+    add_row("", fields.strip_log_colors.upcast_ref());
+    add_row("Notifications", fields.notify_level.upcast_ref());
+    add_row("Notify when", fields.notify_connect.upcast_ref());
+    add_row("", fields.notify_disconnect.upcast_ref());
+    add_row("", fields.notify_restart.upcast_ref());
+    add_row("", fields.notify_sslocal_errors.upcast_ref());
+    add_row("Restart limit (times)", fields.restart_times.upcast_ref());
+    add_row("Restart limit period (s)", fields.restart_within_secs.upcast_ref());
+    add_row("Stop grace period (s)", fields.stop_grace_secs.upcast_ref());
+    add_row("sslocal binary", fields.bin_path.upcast_ref());
+    add_row("Start minimized", fields.start_minimized.upcast_ref());
+    add_row("Connect on startup", fields.connect_on_startup.upcast_ref());
+    add_row("Startup profile", fields.auto_connect.upcast_ref());
+    add_row("Start on login", fields.autostart.upcast_ref());
+    add_row("Import", fields.group_imports_by_region.upcast_ref());
+    add_row("Duplicate profile names", fields.name_conflicts.upcast_ref());
+    add_row("Network", fields.restart_on_network_change.upcast_ref());
+    for (action, entry) in fields.hotkeys.iter() {
+        add_row(&format!("Hotkey: {}", action.description()), entry.upcast_ref());
+    }
+
+    let error_label = Label::builder()
+        .label("Unset fields fall back to what was last used. Hotkeys take effect on the next launch.")
+        .wrap(true)
+        .xalign(0.0)
+        .margin(12)
+        .build();
+
+    let dialog = Dialog::builder()
+        .default_width(450)
+        .deletable(true)
+        .title("Preferences")
+        .build();
+    dialog.content_area().add(&grid);
+    dialog.content_area().add(&error_label);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Save", ResponseType::Accept);
+
+    // handle save
+    dialog.connect_response(move |dialog, response| {
+        if response != ResponseType::Accept {
+            dialog.close();
+            return;
+        }
+        match fields.to_settings() {
+            Ok(settings) => {
+                let autostart = fields.autostart.is_active();
+                if events_tx
+                    .send(AppEvent::SavePreferences { settings, autostart })
+                    .is_err()
+                {
+                    error!("Trying to send SavePreferences event, but all receivers have hung up.");
+                }
+                dialog.close();
+            }
+            Err(problems) => {
+                debug!("Preferences validation failed: {:?}", problems);
+                error_label.set_markup(&format!(
+                    "<span foreground=\"red\">{}</span>",
+                    glib::markup_escape_text(&problems.join("\n"))
+                ));
+            }
+        }
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
+
+/// Create an `Entry` with placeholder text.
+fn placeholder_entry(placeholder: &str) -> Entry {
+    Entry::builder().placeholder_text(placeholder).build()
+}
@@ -0,0 +1,64 @@
+//! This module contains code that creates a dialog for deleting a profile,
+//! either by moving it to the trash or by archiving it.
+
+use crossbeam_channel::Sender;
+use gtk::{prelude::*, ComboBoxText, Dialog, Label, RadioButton, ResponseType};
+use log::{debug, error};
+
+use crate::{event::AppEvent, io::profile_loader::Profile};
+
+/// Show a non-blocking dialog asking the user to confirm deleting one of `profiles`,
+/// with the one named `selected` chosen initially.
+///
+/// A `DeleteProfile` event is sent if the user confirms.
+pub fn show_profile_delete_dialog(events_tx: Sender<AppEvent>, profiles: Vec<Profile>, selected: Option<&str>) {
+    debug!("Showing profile delete dialog for {} profiles", profiles.len());
+    let profile_combo = ComboBoxText::builder().margin(6).build();
+    for profile in profiles.iter() {
+        profile_combo.append_text(&profile.metadata.label());
+    }
+    let selected = selected.and_then(|name| profiles.iter().position(|p| p.metadata.display_name == name));
+    profile_combo.set_active(Some(selected.unwrap_or(0) as u32));
+    let label = Label::builder()
+        .label("Profile to delete:")
+        .margin(6)
+        .xalign(0.0)
+        .build();
+    let trash_button = RadioButton::builder().label("Move to trash").margin(6).build();
+    let archive_button = RadioButton::builder()
+        .label("Archive, keeping it in the \"archived\" directory without loading it")
+        .margin(6)
+        .build();
+    archive_button.join_group(Some(&trash_button));
+    let dialog = Dialog::builder()
+        .default_width(400)
+        .deletable(true)
+        .title("Delete Profile")
+        .build();
+    dialog.content_area().add(&label);
+    dialog.content_area().add(&profile_combo);
+    dialog.content_area().add(&trash_button);
+    dialog.content_area().add(&archive_button);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    let delete_button = dialog.add_button("Delete", ResponseType::Accept);
+    delete_button.style_context().add_class("destructive-action");
+    dialog.set_default_response(ResponseType::Cancel);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let profile = profile_combo.active().and_then(|i| profiles.get(i as usize));
+            if let Some(profile) = profile {
+                let event = AppEvent::DeleteProfile {
+                    profile: profile.clone(),
+                    archive: archive_button.is_active(),
+                };
+                if events_tx.send(event).is_err() {
+                    error!("Trying to send DeleteProfile event, but all receivers have hung up.");
+                }
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
@@ -0,0 +1,76 @@
+//! This module contains code that creates a dialog for creating
+//! a new profile as a copy of an existing one or of a template.
+
+use crossbeam_channel::Sender;
+use gtk::{prelude::*, ComboBoxText, Dialog, Entry, Label, ResponseType};
+use log::{debug, error};
+
+use crate::{event::AppEvent, io::profile_loader::Profile};
+
+/// The name suggested for a copy of a profile.
+fn suggested_name(source: &Profile) -> String {
+    format!("{} (copy)", source.metadata.display_name)
+}
+
+/// Show a non-blocking dialog prompting the user for the name of a copy of one of `sources`.
+///
+/// The user can choose between the sources only if there are several.
+/// A `DuplicateProfile` event is sent if the user confirms.
+pub fn show_profile_duplicate_dialog(events_tx: Sender<AppEvent>, title: &str, sources: Vec<Profile>) {
+    debug!("Showing profile duplicate dialog for {} sources", sources.len());
+    let source_combo = ComboBoxText::builder().margin(6).build();
+    for source in sources.iter() {
+        source_combo.append_text(&source.metadata.display_name);
+    }
+    source_combo.set_active(Some(0));
+    let label = Label::builder()
+        .label("Name of the new profile:")
+        .margin(6)
+        .xalign(0.0)
+        .build();
+    let entry = Entry::builder()
+        .activates_default(true)
+        .hexpand(true)
+        .margin(6)
+        .text(&sources.first().map(suggested_name).unwrap_or_default())
+        .build();
+    let dialog = Dialog::builder()
+        .default_width(400)
+        .deletable(true)
+        .title(title)
+        .build();
+    if sources.len() > 1 {
+        dialog.content_area().add(&source_combo);
+    }
+    dialog.content_area().add(&label);
+    dialog.content_area().add(&entry);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Create", ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Accept);
+
+    let sources_mv = sources.clone();
+    let entry_mv = entry.clone();
+    source_combo.connect_changed(move |combo| {
+        if let Some(source) = combo.active().and_then(|i| sources_mv.get(i as usize)) {
+            entry_mv.set_text(&suggested_name(source));
+        }
+    });
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let source = source_combo.active().and_then(|i| sources.get(i as usize));
+            if let Some(source) = source {
+                let event = AppEvent::DuplicateProfile {
+                    source: source.clone(),
+                    display_name: entry.text().trim().to_string(),
+                };
+                if events_tx.send(event).is_err() {
+                    error!("Trying to send DuplicateProfile event, but all receivers have hung up.");
+                }
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
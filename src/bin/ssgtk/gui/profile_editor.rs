@@ -0,0 +1,792 @@
+//! This module contains code that creates a dialog for creating
+//! and editing profiles.
+//!
+//! The editor works with the on-disk schema of `profile.yaml`,
+//! so that validation is performed by the same deserializer used when loading.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossbeam_channel::Sender;
+use gtk::{
+    prelude::*, Button, CheckButton, ComboBoxText, Dialog, Entry, FileChooserAction, FileChooserDialog, Grid,
+    InputPurpose, Label, PolicyType, ResponseType, ScrolledWindow, TextView, Widget, Window,
+};
+use log::{debug, error, info};
+use serde_yaml::{Mapping, Value};
+
+use crate::{acl::AclRules, event::AppEvent, io::profile_loader::ProfileConfig, pac::PacRules};
+
+/// Encryption methods offered in the drop-down.
+/// The user can still type in any other method.
+const COMMON_ENCRYPT_METHODS: &[&str] = &[
+    "aes-128-gcm",
+    "aes-256-gcm",
+    "chacha20-ietf-poly1305",
+    "2022-blake3-aes-128-gcm",
+    "2022-blake3-aes-256-gcm",
+    "2022-blake3-chacha20-poly1305",
+];
+
+/// The modes selectable in the editor, as `(mode, label)`.
+const MODES: &[(&str, &str)] = &[("proxy", "Proxy"), ("tun", "Tun"), ("config-file", "Config file")];
+
+/// The backends selectable in the editor, as `(backend, label)`.
+const BACKENDS: &[(&str, &str)] = &[
+    ("shadowsocks-rust", "shadowsocks-rust (sslocal)"),
+    ("shadowsocks-libev", "shadowsocks-libev (ss-local)"),
+    ("go-shadowsocks2", "go-shadowsocks2"),
+    ("embedded", "Embedded (no binary needed)"),
+];
+
+/// The routing modes selectable in the editor, as `(mode, label)`.
+const ACL_MODES: &[(&str, &str)] = &[
+    ("proxy-all", "Proxy all but the bypass list"),
+    ("bypass-all", "Bypass all but the proxy list"),
+];
+
+/// The color tags selectable in the editor, as `(color, label)`; the empty ID means none.
+const COLORS: &[(&str, &str)] = &[
+    ("", "None"),
+    ("red", "Red"),
+    ("orange", "Orange"),
+    ("yellow", "Yellow"),
+    ("green", "Green"),
+    ("blue", "Blue"),
+    ("purple", "Purple"),
+    ("brown", "Brown"),
+    ("black", "Black"),
+    ("white", "White"),
+];
+
+/// The fields the editor has no inputs for, which are written back unchanged when editing a profile.
+const PRESERVED_KEYS: &[&str] = &["system_proxy", "env", "log_level", "hooks"];
+
+/// Take the fields listed in `PRESERVED_KEYS` from an existing config.
+fn preserved_fields(config: &ProfileConfig) -> Mapping {
+    let value = match serde_yaml::to_value(config) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("Cannot serialize profile config for editing: {}", err);
+            return Mapping::new();
+        }
+    };
+    PRESERVED_KEYS
+        .iter()
+        .filter_map(|&key| value.get(key).map(|v| (key.into(), v.clone())))
+        .collect()
+}
+
+/// All input widgets of the editor.
+#[derive(Debug, Clone)]
+struct EditorFields {
+    mode: ComboBoxText,
+    /// Only present when creating a new profile.
+    dir_name: Option<Entry>,
+    // metadata
+    display_name: Entry,
+    pwd: Entry,
+    bin_path: Entry,
+    backend: ComboBoxText,
+    requires_privilege: CheckButton,
+    icon: Entry,
+    color: ComboBoxText,
+    tags: Entry,
+    // connection
+    local_addr: Entry,
+    local_port: Entry,
+    server_addr: Entry,
+    server_port: Entry,
+    password: Entry,
+    password_keyring: Entry,
+    encrypt_method: ComboBoxText,
+    // proxy
+    plugin: Entry,
+    plugin_opts: Entry,
+    http_port: Entry,
+    // config file
+    config_path: Entry,
+    // tun
+    if_name: Entry,
+    if_addr: Entry,
+    if_destination: Entry,
+    routes: Entry,
+    route_table: Entry,
+    // advanced
+    extra_args: TextView,
+    acl_mode: ComboBoxText,
+    acl_proxy: TextView,
+    acl_bypass: TextView,
+    acl_gfwlist: CheckButton,
+}
+
+impl EditorFields {
+    fn new(is_new: bool) -> Self {
+        let mode = ComboBoxText::new();
+        for (id, label) in MODES {
+            mode.append(Some(id), label);
+        }
+        mode.set_active_id(Some("proxy"));
+
+        let backend = ComboBoxText::new();
+        for (id, label) in BACKENDS {
+            // unavailable unless built with it
+            if *id != "embedded" || cfg!(feature = "embedded") {
+                backend.append(Some(id), label);
+            }
+        }
+        backend.set_active_id(Some(BACKENDS[0].0));
+
+        let color = ComboBoxText::new();
+        for (id, label) in COLORS {
+            color.append(Some(id), label);
+        }
+        color.set_active_id(Some(COLORS[0].0));
+
+        let acl_mode = ComboBoxText::new();
+        for (id, label) in ACL_MODES {
+            acl_mode.append(Some(id), label);
+        }
+        acl_mode.set_active_id(Some(ACL_MODES[0].0));
+
+        let encrypt_method = ComboBoxText::with_entry();
+        for method in COMMON_ENCRYPT_METHODS {
+            encrypt_method.append_text(method);
+        }
+
+        let password = Entry::builder()
+            .input_purpose(InputPurpose::Password)
+            .visibility(false)
+            .build();
+        password.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, Some("view-reveal-symbolic"));
+        password.connect_icon_press(|entry, _, _| entry.set_visibility(!EntryExt::is_visible(entry)));
+
+        Self {
+            mode,
+            dir_name: is_new.then(Entry::new),
+            display_name: placeholder_entry("Defaults to the directory name"),
+            pwd: placeholder_entry("Defaults to the profile directory"),
+            bin_path: placeholder_entry("Defaults to looking up the backend's binary in $PATH"),
+            backend,
+            requires_privilege: CheckButton::with_label("e.g. to bind a port below 1024; asks for your password"),
+            icon: placeholder_entry("Optional, an icon name or a path to an image"),
+            color,
+            tags: placeholder_entry("Optional, comma-separated, e.g. jp, fast"),
+            local_addr: placeholder_entry("e.g. 127.0.0.1 or ::"),
+            local_port: placeholder_entry("e.g. 1080"),
+            server_addr: Entry::new(),
+            server_port: Entry::new(),
+            password,
+            password_keyring: placeholder_entry("Optional, use instead of the password"),
+            encrypt_method,
+            plugin: placeholder_entry("Optional, e.g. v2ray-plugin"),
+            plugin_opts: placeholder_entry("Optional, e.g. tls;host=example.org"),
+            http_port: placeholder_entry("Optional, also serves an HTTP proxy, e.g. 8118"),
+            config_path: placeholder_entry("e.g. ./ss.json5"),
+            if_name: placeholder_entry("Optional"),
+            if_addr: placeholder_entry("Optional, e.g. 10.13.37.1/24"),
+            if_destination: placeholder_entry("Optional, e.g. 10.13.37.2/24"),
+            routes: placeholder_entry("Optional, comma-separated, e.g. 0.0.0.0/1, 128.0.0.0/1"),
+            route_table: placeholder_entry("Optional, defaults to the main table"),
+            extra_args: TextView::builder().monospace(true).build(),
+            acl_mode,
+            acl_proxy: TextView::builder().monospace(true).build(),
+            acl_bypass: TextView::builder().monospace(true).build(),
+            acl_gfwlist: CheckButton::with_label("Also proxy the domains in gfwlist, which is kept up to date"),
+        }
+    }
+
+    /// The currently selected mode.
+    fn mode(&self) -> String {
+        self.mode.active_id().map(|s| s.to_string()).unwrap_or_default()
+    }
+
+    /// Fill all fields with the values of an existing config.
+    fn fill(&self, config: &ProfileConfig) {
+        let value = match serde_yaml::to_value(config) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Cannot serialize profile config for editing: {}", err);
+                return;
+            }
+        };
+        let get_str = |key: &str| value.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        let get_pair = |key: &str| {
+            let pair = value.get(key);
+            let first = pair.and_then(|p| p.get(0)).and_then(Value::as_str).unwrap_or_default();
+            let second = pair.and_then(|p| p.get(1)).and_then(Value::as_u64);
+            (first.to_string(), second.map(|n| n.to_string()).unwrap_or_default())
+        };
+
+        self.mode.set_active_id(Some(&get_str("mode")));
+        self.display_name.set_text(&get_str("display_name"));
+        self.pwd.set_text(&get_str("pwd"));
+        self.bin_path.set_text(&get_str("bin_path"));
+        let backend = value.get("backend").and_then(Value::as_str).unwrap_or(BACKENDS[0].0);
+        self.backend.set_active_id(Some(backend));
+        let requires_privilege = value.get("requires_privilege").and_then(Value::as_bool);
+        self.requires_privilege.set_active(requires_privilege.unwrap_or(false));
+        self.icon.set_text(&get_str("icon"));
+        self.color.set_active_id(Some(&get_str("color")));
+        let tags = value
+            .get("tags")
+            .and_then(Value::as_sequence)
+            .map(|seq| seq.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "))
+            .unwrap_or_default();
+        self.tags.set_text(&tags);
+        let (local_addr, local_port) = get_pair("local_addr");
+        self.local_addr.set_text(&local_addr);
+        self.local_port.set_text(&local_port);
+        let (server_addr, server_port) = get_pair("server_addr");
+        self.server_addr.set_text(&server_addr);
+        self.server_port.set_text(&server_port);
+        self.password.set_text(&get_str("password"));
+        let keyring = value
+            .get("password")
+            .and_then(|p| p.get("keyring"))
+            .and_then(Value::as_str);
+        self.password_keyring.set_text(keyring.unwrap_or_default());
+        if let Some(entry) = self.encrypt_method.child().and_then(|c| c.downcast::<Entry>().ok()) {
+            entry.set_text(&get_str("encrypt_method"));
+        }
+        self.plugin.set_text(&get_str("plugin"));
+        self.plugin_opts.set_text(&get_str("plugin_opts"));
+        let http_port = value.get("http_port").and_then(Value::as_u64);
+        self.http_port
+            .set_text(&http_port.map(|p| p.to_string()).unwrap_or_default());
+        self.config_path.set_text(&get_str("config_path"));
+        self.if_name.set_text(&get_str("if_name"));
+        self.if_addr.set_text(&get_str("if_addr"));
+        self.if_destination.set_text(&get_str("if_destination"));
+        let routes = value
+            .get("routes")
+            .and_then(Value::as_sequence)
+            .map(|seq| seq.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "))
+            .unwrap_or_default();
+        self.routes.set_text(&routes);
+        let route_table = value.get("route_table").and_then(Value::as_u64);
+        self.route_table
+            .set_text(&route_table.map(|t| t.to_string()).unwrap_or_default());
+        let extra_args = value
+            .get("extra_args")
+            .and_then(Value::as_sequence)
+            .map(|seq| seq.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n"))
+            .unwrap_or_default();
+        if let Some(buffer) = self.extra_args.buffer() {
+            buffer.set_text(&extra_args);
+        }
+        let acl = value.get("acl");
+        let acl_mode = acl.and_then(|a| a.get("mode")).and_then(Value::as_str);
+        self.acl_mode.set_active_id(Some(acl_mode.unwrap_or(ACL_MODES[0].0)));
+        let acl_gfwlist = acl.and_then(|a| a.get("gfwlist")).and_then(Value::as_bool);
+        self.acl_gfwlist.set_active(acl_gfwlist.unwrap_or(false));
+        for (key, view) in [("proxy", &self.acl_proxy), ("bypass", &self.acl_bypass)] {
+            let entries = acl
+                .and_then(|a| a.get(key))
+                .and_then(Value::as_sequence)
+                .map(|seq| seq.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n"))
+                .unwrap_or_default();
+            if let Some(buffer) = view.buffer() {
+                buffer.set_text(&entries);
+            }
+        }
+    }
+
+    /// Add the domains of a gfwlist-style rule file (optionally base64-encoded) to the routing lists,
+    /// skipping those already listed, and bypass everything else.
+    fn import_gfwlist(&self, path: &Path) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        let pac_rules = PacRules::parse_base64(&text).unwrap_or_else(|| PacRules::parse(&text));
+        let mut imported = AclRules::default();
+        imported.import_pac_rules(pac_rules);
+        info!(
+            "Importing {} proxied and {} bypassed domains from {:?}",
+            imported.proxy.len(),
+            imported.bypass.len(),
+            path
+        );
+        for (view, entries) in [(&self.acl_proxy, imported.proxy), (&self.acl_bypass, imported.bypass)] {
+            let mut lines = text_lines(view);
+            for entry in entries.into_iter().map(String::from) {
+                if !lines.contains(&entry) {
+                    lines.push(entry);
+                }
+            }
+            if let Some(buffer) = view.buffer() {
+                buffer.set_text(&lines.join("\n"));
+            }
+        }
+        self.acl_mode.set_active_id(Some("bypass-all"));
+        Ok(())
+    }
+
+    /// Validate all fields, and build a config from them,
+    /// keeping the fields without inputs from the `original` config if editing.
+    ///
+    /// Returns a list of human-readable problems on failure.
+    fn to_config(&self, original: Option<&ProfileConfig>) -> Result<ProfileConfig, Vec<String>> {
+        let mode = self.mode();
+        let mut problems = vec![];
+        let mut map = original.map(preserved_fields).unwrap_or_default();
+        let mut insert = |key: &str, value: Value| {
+            map.insert(key.into(), value);
+        };
+
+        insert("mode", mode.clone().into());
+
+        // optional string fields are only written when non-empty
+        let optional = |entry: &Entry| Some(entry.text().trim().to_string()).filter(|s| !s.is_empty());
+        let required = |entry: &Entry, name: &str, problems: &mut Vec<String>| {
+            let text = optional(entry);
+            if text.is_none() {
+                problems.push(format!("{} is required", name));
+            }
+            text.unwrap_or_default()
+        };
+        let port = |entry: &Entry, name: &str, problems: &mut Vec<String>| {
+            let text = required(entry, name, problems);
+            match text.parse::<u16>() {
+                Ok(p) => p,
+                Err(_) if text.is_empty() => 0,
+                Err(_) => {
+                    problems.push(format!("{} must be a number between 0 and 65535", name));
+                    0
+                }
+            }
+        };
+
+        if let Some(entry) = &self.dir_name {
+            let name = required(entry, "Directory name", &mut problems);
+            if name.starts_with('.') || name.contains('/') {
+                problems.push("Directory name cannot start with '.' or contain '/'".into());
+            }
+        }
+        for (key, entry) in [
+            ("display_name", &self.display_name),
+            ("pwd", &self.pwd),
+            ("bin_path", &self.bin_path),
+            ("icon", &self.icon),
+        ] {
+            if let Some(text) = optional(entry) {
+                insert(key, text.into());
+            }
+        }
+        // the default backend is omitted
+        if let Some(backend) = self.backend.active_id().filter(|id| id != BACKENDS[0].0) {
+            insert("backend", backend.to_string().into());
+        }
+        if self.requires_privilege.is_active() {
+            insert("requires_privilege", true.into());
+        }
+        if let Some(color) = self.color.active_id().filter(|id| !id.is_empty()) {
+            insert("color", color.to_string().into());
+        }
+        if let Some(text) = optional(&self.tags) {
+            let tags: Vec<Value> = text
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(Value::from)
+                .collect();
+            insert("tags", tags.into());
+        }
+
+        match mode.as_str() {
+            "proxy" | "tun" => {
+                let local_addr = required(&self.local_addr, "Local address", &mut problems);
+                let local_port = port(&self.local_port, "Local port", &mut problems);
+                let server_addr = required(&self.server_addr, "Server address", &mut problems);
+                let server_port = port(&self.server_port, "Server port", &mut problems);
+                let password: Value = match optional(&self.password_keyring) {
+                    Some(entry) => {
+                        let mut mapping = Mapping::new();
+                        mapping.insert("keyring".into(), entry.into());
+                        mapping.into()
+                    }
+                    None => {
+                        let password = self.password.text().to_string();
+                        if password.is_empty() {
+                            problems.push("Password or keyring entry is required".into());
+                        }
+                        password.into()
+                    }
+                };
+                let encrypt_method = self
+                    .encrypt_method
+                    .active_text()
+                    .map(|s| s.trim().to_string())
+                    .unwrap_or_default();
+                if encrypt_method.is_empty() {
+                    problems.push("Encrypt method is required".into());
+                }
+                insert("local_addr", vec![Value::from(local_addr), local_port.into()].into());
+                insert("server_addr", vec![Value::from(server_addr), server_port.into()].into());
+                insert("password", password);
+                insert("encrypt_method", encrypt_method.into());
+                if mode == "proxy" {
+                    for (key, entry) in [("plugin", &self.plugin), ("plugin_opts", &self.plugin_opts)] {
+                        if let Some(text) = optional(entry) {
+                            insert(key, text.into());
+                        }
+                    }
+                    if optional(&self.http_port).is_some() {
+                        let http_port = port(&self.http_port, "HTTP proxy port", &mut problems);
+                        if http_port == local_port {
+                            problems.push("HTTP proxy port must differ from the local port".into());
+                        }
+                        insert("http_port", http_port.into());
+                    }
+                }
+                if mode == "tun" {
+                    for (key, entry) in [
+                        ("if_name", &self.if_name),
+                        ("if_addr", &self.if_addr),
+                        ("if_destination", &self.if_destination),
+                    ] {
+                        if let Some(text) = optional(entry) {
+                            insert(key, text.into());
+                        }
+                    }
+                    if let Some(text) = optional(&self.routes) {
+                        let routes: Vec<Value> = text
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|r| !r.is_empty())
+                            .map(Value::from)
+                            .collect();
+                        insert("routes", routes.into());
+                    }
+                    if let Some(text) = optional(&self.route_table) {
+                        match text.parse::<u32>() {
+                            Ok(table) => insert("route_table", table.into()),
+                            Err(_) => problems.push("Route table must be a non-negative number".into()),
+                        }
+                    }
+                }
+            }
+            "config-file" => {
+                let config_path = required(&self.config_path, "Config path", &mut problems);
+                insert("config_path", config_path.into());
+            }
+            other => problems.push(format!("Unknown mode \"{}\"", other)),
+        }
+
+        let extra_args: Vec<Value> = text_lines(&self.extra_args).into_iter().map(Value::from).collect();
+        if !extra_args.is_empty() {
+            insert("extra_args", extra_args.into());
+        }
+
+        // routing rules are only written if they change anything
+        let acl_mode = self.acl_mode.active_id().map(|s| s.to_string()).unwrap_or_default();
+        let acl_proxy = text_lines(&self.acl_proxy);
+        let acl_bypass = text_lines(&self.acl_bypass);
+        let acl_gfwlist = self.acl_gfwlist.is_active();
+        if acl_mode != ACL_MODES[0].0 || !acl_proxy.is_empty() || !acl_bypass.is_empty() || acl_gfwlist {
+            let mut acl = Mapping::new();
+            acl.insert("mode".into(), acl_mode.into());
+            if acl_gfwlist {
+                acl.insert("gfwlist".into(), true.into());
+            }
+            for (key, lines) in [("proxy", acl_proxy), ("bypass", acl_bypass)] {
+                if !lines.is_empty() {
+                    acl.insert(
+                        key.into(),
+                        lines.into_iter().map(Value::from).collect::<Vec<_>>().into(),
+                    );
+                }
+            }
+            insert("acl", acl.into());
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        // let the deserializer catch everything else, e.g. bad IP addresses
+        serde_yaml::from_value(Value::Mapping(map)).map_err(|err| vec![err.to_string()])
+    }
+
+    /// Show only the fields relevant to the selected mode.
+    fn update_visibility(&self, sections: &EditorSections) {
+        let mode = self.mode();
+        let is_conn = mode == "proxy" || mode == "tun";
+        sections.conn.iter().for_each(|w| w.set_visible(is_conn));
+        sections.proxy.iter().for_each(|w| w.set_visible(mode == "proxy"));
+        sections.tun.iter().for_each(|w| w.set_visible(mode == "tun"));
+        sections
+            .config_file
+            .iter()
+            .for_each(|w| w.set_visible(mode == "config-file"));
+    }
+}
+
+/// Widgets (labels and inputs) grouped by the modes they are relevant to.
+#[derive(Debug, Clone, Default)]
+struct EditorSections {
+    conn: Vec<Widget>,
+    proxy: Vec<Widget>,
+    tun: Vec<Widget>,
+    config_file: Vec<Widget>,
+}
+
+/// Show a non-blocking dialog for creating a new profile under `profiles_dir`,
+/// or for editing the profile in `existing` if set.
+///
+/// A `SaveProfile` event is sent when the user saves a valid profile.
+pub fn show_profile_editor(
+    events_tx: Sender<AppEvent>,
+    profiles_dir: impl AsRef<Path>,
+    existing: Option<(PathBuf, &ProfileConfig)>,
+) {
+    let is_new = existing.is_none();
+    debug!("Showing profile editor; new profile: {}", is_new);
+
+    let fields = EditorFields::new(is_new);
+    if let Some((_, config)) = &existing {
+        fields.fill(config);
+    }
+
+    // layout
+    let grid = Grid::builder().column_spacing(12).margin(12).row_spacing(6).build();
+    let mut sections = EditorSections::default();
+    let mut row = 0;
+    let mut add_row = |label: &str, widget: &Widget, section: Option<&mut Vec<Widget>>| {
+        let label = Label::builder().label(label).xalign(1.0).build();
+        widget.set_hexpand(true);
+        grid.attach(&label, 0, row, 1, 1);
+        grid.attach(widget, 1, row, 1, 1);
+        if let Some(section) = section {
+            section.push(label.upcast());
+            section.push(widget.clone());
+        }
+        row += 1;
+    };
+    add_row("Mode", fields.mode.upcast_ref(), None);
+    if let Some(entry) = &fields.dir_name {
+        add_row("Directory name", entry.upcast_ref(), None);
+    }
+    add_row("Display name", fields.display_name.upcast_ref(), None);
+    add_row("Working directory", fields.pwd.upcast_ref(), None);
+    add_row("Backend", fields.backend.upcast_ref(), None);
+    add_row("Backend binary", fields.bin_path.upcast_ref(), None);
+    add_row("Run as root", fields.requires_privilege.upcast_ref(), None);
+    add_row("Icon", fields.icon.upcast_ref(), None);
+    add_row("Color tag", fields.color.upcast_ref(), None);
+    add_row("Tags", fields.tags.upcast_ref(), None);
+    add_row(
+        "Local address",
+        fields.local_addr.upcast_ref(),
+        Some(&mut sections.conn),
+    );
+    add_row("Local port", fields.local_port.upcast_ref(), Some(&mut sections.conn));
+    add_row(
+        "Server address",
+        fields.server_addr.upcast_ref(),
+        Some(&mut sections.conn),
+    );
+    add_row("Server port", fields.server_port.upcast_ref(), Some(&mut sections.conn));
+    add_row("Password", fields.password.upcast_ref(), Some(&mut sections.conn));
+    add_row(
+        "Keyring entry",
+        fields.password_keyring.upcast_ref(),
+        Some(&mut sections.conn),
+    );
+    add_row(
+        "Encrypt method",
+        fields.encrypt_method.upcast_ref(),
+        Some(&mut sections.conn),
+    );
+    add_row("Plugin", fields.plugin.upcast_ref(), Some(&mut sections.proxy));
+    add_row(
+        "Plugin options",
+        fields.plugin_opts.upcast_ref(),
+        Some(&mut sections.proxy),
+    );
+    add_row(
+        "HTTP proxy port",
+        fields.http_port.upcast_ref(),
+        Some(&mut sections.proxy),
+    );
+    add_row(
+        "Config path",
+        fields.config_path.upcast_ref(),
+        Some(&mut sections.config_file),
+    );
+    add_row(
+        "Tun interface name",
+        fields.if_name.upcast_ref(),
+        Some(&mut sections.tun),
+    );
+    add_row(
+        "Tun interface address",
+        fields.if_addr.upcast_ref(),
+        Some(&mut sections.tun),
+    );
+    add_row(
+        "Tun interface destination",
+        fields.if_destination.upcast_ref(),
+        Some(&mut sections.tun),
+    );
+    add_row("Tun routes", fields.routes.upcast_ref(), Some(&mut sections.tun));
+    add_row(
+        "Tun route table",
+        fields.route_table.upcast_ref(),
+        Some(&mut sections.tun),
+    );
+    let extra_args_scroll = ScrolledWindow::builder()
+        .child(&fields.extra_args)
+        .hscrollbar_policy(PolicyType::Never)
+        .min_content_height(60)
+        .build();
+    add_row("Extra arguments\n(one per line)", extra_args_scroll.upcast_ref(), None);
+    add_row("Routing mode", fields.acl_mode.upcast_ref(), None);
+    for (label, view) in [
+        ("Proxy list\n(domains or networks,\none per line)", &fields.acl_proxy),
+        ("Bypass list\n(domains or networks,\none per line)", &fields.acl_bypass),
+    ] {
+        let scroll = ScrolledWindow::builder()
+            .child(view)
+            .hscrollbar_policy(PolicyType::Never)
+            .min_content_height(60)
+            .build();
+        add_row(label, scroll.upcast_ref(), None);
+    }
+    add_row("Use gfwlist", fields.acl_gfwlist.upcast_ref(), None);
+    let import_button = Button::with_label("Import gfwlist…");
+    add_row("", import_button.upcast_ref(), None);
+
+    let error_label = Label::builder().wrap(true).xalign(0.0).margin(12).build();
+    if !is_new {
+        error_label.set_text("Note: saving will discard any comments in the existing config file");
+    }
+
+    let dialog = Dialog::builder()
+        .default_width(500)
+        .deletable(true)
+        .title(if is_new { "New Profile" } else { "Edit Profile" })
+        .build();
+    dialog.content_area().add(&grid);
+    dialog.content_area().add(&error_label);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Save", ResponseType::Accept);
+
+    // import routing rules
+    let fields_mv = fields.clone();
+    let error_label_mv = error_label.clone();
+    import_button.connect_clicked(move |_| show_gfwlist_import_dialog(fields_mv.clone(), error_label_mv.clone()));
+
+    // show relevant fields only
+    let fields_mv = fields.clone();
+    let sections_mv = sections.clone();
+    fields
+        .mode
+        .connect_changed(move |_| fields_mv.update_visibility(&sections_mv));
+
+    // handle save
+    let profiles_dir = profiles_dir.as_ref().to_path_buf();
+    let existing_config = existing.as_ref().map(|(_, config)| (*config).clone());
+    let existing_dir = existing.map(|(dir, _)| dir);
+    let fields_mv = fields.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response != ResponseType::Accept {
+            dialog.close();
+            return;
+        }
+        match fields_mv.to_config(existing_config.as_ref()) {
+            Ok(config) => {
+                let dir = match (&existing_dir, &fields_mv.dir_name) {
+                    (Some(dir), _) => dir.clone(),
+                    (None, Some(entry)) => profiles_dir.join(entry.text().trim()),
+                    (None, None) => unreachable!("new profiles always have a directory name entry"),
+                };
+                let event = AppEvent::SaveProfile { dir, config, is_new };
+                if events_tx.send(event).is_err() {
+                    error!("Trying to send SaveProfile event, but all receivers have hung up.");
+                }
+                dialog.close();
+            }
+            Err(problems) => {
+                debug!("Profile editor validation failed: {:?}", problems);
+                error_label.set_markup(&format!(
+                    "<span foreground=\"red\">{}</span>",
+                    glib::markup_escape_text(&problems.join("\n"))
+                ));
+            }
+        }
+    }); // handle close
+    dialog.show_all(); // render
+    fields.update_visibility(&sections);
+    dialog.present(); // bring to foreground
+}
+
+/// Show a non-blocking dialog prompting the user for a gfwlist-style rule file to import into `fields`.
+fn show_gfwlist_import_dialog(fields: EditorFields, error_label: Label) {
+    debug!("Showing gfwlist import dialog");
+    let dialog = FileChooserDialog::with_buttons(
+        Some("Import gfwlist"),
+        None::<&Window>,
+        FileChooserAction::Open,
+        &[("Cancel", ResponseType::Cancel), ("Import", ResponseType::Accept)],
+    );
+    dialog.set_default_response(ResponseType::Accept);
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            match dialog.file().and_then(|f| f.path()) {
+                Some(path) => {
+                    if let Err(err) = fields.import_gfwlist(&path) {
+                        error!("Cannot import gfwlist from {:?}: {}", path, err);
+                        error_label.set_markup(&format!(
+                            "<span foreground=\"red\">{}</span>",
+                            glib::markup_escape_text(&format!("Cannot import gfwlist: {}", err))
+                        ));
+                    }
+                }
+                None => error!("The selected file is not a local file"),
+            }
+        }
+        dialog.close();
+    });
+    dialog.show_all();
+    dialog.present();
+}
+
+/// Get the non-empty lines of a `TextView`, trimmed.
+fn text_lines(view: &TextView) -> Vec<String> {
+    view.buffer()
+        .and_then(|b| b.text(&b.start_iter(), &b.end_iter(), false))
+        .map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Create an `Entry` with placeholder text.
+fn placeholder_entry(placeholder: &str) -> Entry {
+    Entry::builder().placeholder_text(placeholder).build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preserve_fields_without_inputs() {
+        let yaml = "mode: \"config-file\"\nconfig_path: \"./ss.json\"\nsystem_proxy: false\n\
+            env:\n  RUST_LOG: \"debug\"\nlog_level: \"trace\"\n\
+            hooks:\n  post_start: \"./up.sh\"\n";
+        let config: ProfileConfig = serde_yaml::from_str(yaml).unwrap();
+        let preserved = Value::Mapping(preserved_fields(&config));
+        assert_eq!(preserved["system_proxy"], Value::from(false));
+        assert_eq!(preserved["env"]["RUST_LOG"], Value::from("debug"));
+        assert_eq!(preserved["log_level"], Value::from("trace"));
+        assert_eq!(preserved["hooks"]["post_start"], Value::from("./up.sh"));
+        // fields with inputs are left to the editor
+        assert!(preserved.get("mode").is_none());
+        assert!(preserved.get("config_path").is_none());
+    }
+}
@@ -0,0 +1,50 @@
+//! This module contains code that creates a dialog for exporting
+//! all profiles into a single manifest file.
+
+use crossbeam_channel::Sender;
+use gtk::{prelude::*, CheckButton, FileChooserAction, FileChooserDialog, ResponseType, Window};
+use log::{debug, error};
+use shadowsocks_gtk_rs::consts::PROFILE_MANIFEST_FILE_NAME;
+
+use crate::event::AppEvent;
+
+/// Show a non-blocking dialog prompting the user for where to export profiles to.
+///
+/// An `ExportProfiles` event is sent if the user confirms the export.
+pub fn show_profile_export_dialog(events_tx: Sender<AppEvent>) {
+    debug!("Showing profile export dialog");
+    let dialog = FileChooserDialog::with_buttons(
+        Some("Export Profiles"),
+        None::<&Window>,
+        FileChooserAction::Save,
+        &[("Cancel", ResponseType::Cancel), ("Export", ResponseType::Accept)],
+    );
+    dialog.set_current_name(PROFILE_MANIFEST_FILE_NAME);
+    dialog.set_do_overwrite_confirmation(true);
+    let strip_check = CheckButton::builder()
+        .label("Strip passwords")
+        .tooltip_text("Empty all passwords, e.g. if you are sharing the file")
+        .build();
+    dialog.set_extra_widget(&strip_check);
+    dialog.set_default_response(ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            match dialog.file().and_then(|f| f.path()) {
+                Some(path) => {
+                    let strip_passwords = strip_check.is_active();
+                    if events_tx
+                        .send(AppEvent::ExportProfiles { path, strip_passwords })
+                        .is_err()
+                    {
+                        error!("Trying to send ExportProfiles event, but all receivers have hung up.");
+                    }
+                }
+                None => error!("The selected file is not a local file"),
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
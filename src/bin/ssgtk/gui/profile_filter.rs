@@ -0,0 +1,113 @@
+//! This module contains code that creates a dialog for quickly finding
+//! a profile by name and switching to it, which is easier than navigating
+//! the tray menu when there are many profiles.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crossbeam_channel::Sender;
+use gtk::{prelude::*, Dialog, Label, ListBox, ResponseType, ScrolledWindow, SearchEntry};
+use log::{debug, error};
+
+use crate::{event::AppEvent, io::profile_loader::Profile};
+
+/// Show a non-blocking dialog with a search box filtering the list of `profiles`.
+///
+/// Sends `AppEvent::SwitchProfile` if the user chooses one.
+pub fn show_profile_filter_dialog(events_tx: Sender<AppEvent>, profiles: Vec<Profile>) {
+    debug!("Showing profile filter dialog for {} profiles", profiles.len());
+
+    let search = SearchEntry::builder()
+        .placeholder_text("Type to filter profiles")
+        .margin(6)
+        .build();
+    let list = ListBox::new();
+    for profile in profiles.iter() {
+        let label = Label::builder()
+            .label(&profile.metadata.display_name)
+            .xalign(0.0)
+            .margin(6)
+            .build();
+        list.add(&label);
+    }
+    let names: Vec<_> = profiles.iter().map(|p| p.metadata.display_name.clone()).collect();
+    let query = Rc::new(RefCell::new(String::new()));
+    let query_mv = Rc::clone(&query);
+    list.set_filter_func(Some(Box::new(move |row| {
+        let name = &names[row.index() as usize];
+        matches_query(name, &query_mv.borrow())
+    })));
+    select_first_visible(&list);
+
+    // refilter as the user types
+    let list_mv = list.clone();
+    search.connect_search_changed(move |search| {
+        *query.borrow_mut() = search.text().to_string();
+        list_mv.invalidate_filter();
+        select_first_visible(&list_mv);
+    });
+
+    let scrolled = ScrolledWindow::builder()
+        .min_content_height(300)
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+    scrolled.add(&list);
+
+    let dialog = Dialog::builder()
+        .default_width(400)
+        .deletable(true)
+        .title("Filter Profiles")
+        .build();
+    dialog.content_area().add(&search);
+    dialog.content_area().add(&scrolled);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Connect", ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Accept);
+
+    // Enter in the search box or double-clicking a row connects
+    let dialog_mv = dialog.clone();
+    search.connect_activate(move |_| dialog_mv.response(ResponseType::Accept));
+    let dialog_mv = dialog.clone();
+    list.connect_row_activated(move |list, row| {
+        list.select_row(Some(row));
+        dialog_mv.response(ResponseType::Accept);
+    });
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let selected = list
+                .selected_row()
+                .filter(|row| row.is_child_visible())
+                .and_then(|row| profiles.get(row.index() as usize));
+            match selected {
+                Some(profile) => {
+                    if events_tx.send(AppEvent::SwitchProfile(profile.clone())).is_err() {
+                        error!("Trying to send SwitchProfile event, but all receivers have hung up.");
+                    }
+                }
+                None => {
+                    debug!("No profile matches the filter; keep showing the dialog");
+                    return;
+                }
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
+
+/// Whether `name` contains every whitespace-separated term of `query`, ignoring case.
+fn matches_query(name: &str, query: &str) -> bool {
+    let name = name.to_lowercase();
+    query.split_whitespace().all(|term| name.contains(&term.to_lowercase()))
+}
+
+/// Select the first row not hidden by the filter, if any.
+fn select_first_visible(list: &ListBox) {
+    let first = list
+        .children()
+        .into_iter()
+        .filter_map(|child| child.downcast::<gtk::ListBoxRow>().ok())
+        .find(|row| row.is_child_visible());
+    list.select_row(first.as_ref());
+}
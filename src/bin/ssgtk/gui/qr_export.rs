@@ -0,0 +1,71 @@
+//! This module contains code that creates a dialog showing a profile
+//! as a SIP002 `ss://` QR code, so that it can be scanned by another device.
+
+use gtk::{
+    gdk_pixbuf::{Colorspace, InterpType, Pixbuf},
+    prelude::*,
+    Dialog, Image, Label, ResponseType,
+};
+use log::debug;
+use qrcode::{types::QrError, Color, QrCode};
+
+/// The width of the blank border around the code, in modules, as required by the QR spec.
+const QUIET_ZONE: usize = 4;
+
+/// The size of each module in the rendered image, in pixels.
+const MODULE_SIZE: usize = 6;
+
+/// Render `data` as a QR code.
+fn render_qr_code(data: &str) -> Result<Pixbuf, QrError> {
+    let code = QrCode::new(data)?;
+    let code_width = code.width();
+    let width = code_width + 2 * QUIET_ZONE;
+
+    // draw at one pixel per module, then scale up
+    let pixbuf =
+        Pixbuf::new(Colorspace::Rgb, false, 8, width as i32, width as i32).expect("cannot allocate pixbuf for QR code");
+    pixbuf.fill(0xffffffff);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (x, y) = (i % code_width + QUIET_ZONE, i / code_width + QUIET_ZONE);
+            pixbuf.put_pixel(x as u32, y as u32, 0, 0, 0, 0xff);
+        }
+    }
+    let scaled = (width * MODULE_SIZE) as i32;
+    Ok(pixbuf
+        .scale_simple(scaled, scaled, InterpType::Nearest)
+        .expect("cannot allocate pixbuf for QR code"))
+}
+
+/// Show a non-blocking dialog with the QR code of `url`.
+pub fn show_qr_export_dialog(profile_name: &str, url: &str) -> Result<(), QrError> {
+    debug!("Showing QR code of profile \"{}\"", profile_name);
+    let image = Image::builder().pixbuf(&render_qr_code(url)?).margin(6).build();
+    let label = Label::builder()
+        .label("Scan this code with another device to import this profile.\nIt contains your password, so keep it private!")
+        .justify(gtk::Justification::Center)
+        .margin(6)
+        .build();
+    let url_label = Label::builder()
+        .label(url)
+        .selectable(true)
+        .wrap(true)
+        .wrap_mode(gtk::pango::WrapMode::Char)
+        .max_width_chars(50)
+        .margin(6)
+        .build();
+    let dialog = Dialog::builder()
+        .deletable(true)
+        .resizable(false)
+        .title(&format!("QR Code of {}", profile_name))
+        .build();
+    dialog.content_area().add(&image);
+    dialog.content_area().add(&label);
+    dialog.content_area().add(&url_label);
+    dialog.add_button("Close", ResponseType::Close);
+
+    dialog.connect_response(|dialog, _| dialog.close()); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+    Ok(())
+}
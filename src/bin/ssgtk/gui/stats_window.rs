@@ -0,0 +1,132 @@
+//! This module contains code that creates a window for showing
+//! the traffic statistics of `sslocal`.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use crossbeam_channel::Sender;
+use glib::SourceId;
+use gtk::{prelude::*, ApplicationWindow, Grid, Label};
+use log::{error, trace};
+use shadowsocks_gtk_rs::util;
+
+use crate::{
+    event::AppEvent,
+    gui::track_geometry,
+    io::app_state::WindowGeometry,
+    stats::{format_bytes, TrafficStats},
+};
+
+#[derive(Debug)]
+pub struct StatsWindow {
+    window: ApplicationWindow,
+    geometry: Rc<Cell<Option<WindowGeometry>>>,
+
+    scheduled_fn_ids: Vec<SourceId>,
+}
+
+impl Drop for StatsWindow {
+    fn drop(&mut self) {
+        trace!("StatsWindow getting dropped.");
+        // stop all scheduled functions
+        for id in self.scheduled_fn_ids.drain(..) {
+            id.remove();
+        }
+    }
+}
+
+impl StatsWindow {
+    /// Create a new `StatsWindow`, which periodically refreshes from `stats`.
+    ///
+    /// `collecting` is shown as a hint if statistics collection is disabled.
+    /// The window is placed at `geometry` if set.
+    pub fn new(
+        events_tx: Sender<AppEvent>,
+        stats: Arc<RwLock<TrafficStats>>,
+        collecting: bool,
+        geometry: Option<WindowGeometry>,
+    ) -> Self {
+        // compose window
+        let grid = Grid::builder().column_spacing(24).margin(12).row_spacing(6).build();
+        let new_value_label = |row| {
+            let value = Label::builder().xalign(1.0).selectable(true).build();
+            grid.attach(&value, 1, row, 1, 1);
+            value
+        };
+        for (row, name) in ["Upload speed", "Download speed", "Total sent", "Total received"]
+            .into_iter()
+            .enumerate()
+        {
+            let label = Label::builder().label(name).xalign(0.0).build();
+            grid.attach(&label, 0, row as i32, 1, 1);
+        }
+        let value_labels = [
+            new_value_label(0),
+            new_value_label(1),
+            new_value_label(2),
+            new_value_label(3),
+        ];
+        let hint = Label::builder().wrap(true).xalign(0.0).build();
+        if !collecting {
+            hint.set_text("Traffic statistics collection is disabled. Enable it in the tray menu.");
+        }
+        grid.attach(&hint, 0, 4, 2, 1);
+        let window = ApplicationWindow::builder()
+            .child(&grid)
+            .default_width(300)
+            .resizable(false)
+            .title("Traffic Statistics")
+            .build();
+
+        let geometry = track_geometry(&window, geometry);
+        let mut ret = Self {
+            window,
+            geometry,
+            scheduled_fn_ids: vec![],
+        };
+
+        // refresh values
+        let refresh = move || {
+            let stats = *util::rwlock_read(&stats);
+            let values = [
+                format!("{}/s", format_bytes(stats.tx_rate as u64)),
+                format!("{}/s", format_bytes(stats.rx_rate as u64)),
+                format_bytes(stats.tx_bytes),
+                format_bytes(stats.rx_bytes),
+            ];
+            for (label, value) in value_labels.iter().zip(values) {
+                label.set_text(&value);
+            }
+        };
+        refresh();
+        let id = glib::source::timeout_add_local(Duration::from_millis(500), move || {
+            refresh();
+            Continue(true)
+        });
+        ret.scheduled_fn_ids.push(id);
+
+        // send event on window destroy
+        ret.window.connect_destroy(move |_| {
+            if events_tx.send(AppEvent::StatsWindowHide).is_err() {
+                error!("Trying to send StatsWindowHide event, but all receivers have hung up.");
+            }
+        });
+
+        ret
+    }
+
+    /// Simple alias function to show the `StatsWindow`.
+    pub fn show(&self) {
+        self.window.show_all(); // render
+        self.window.present(); // bring to foreground
+    }
+
+    /// The latest position and size of the window.
+    pub fn geometry(&self) -> Option<WindowGeometry> {
+        self.geometry.get()
+    }
+}
@@ -1,15 +1,27 @@
 //! This module contains code that creates a tray item.
 
-use std::{path::Path, rc::Rc, sync::RwLock};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::RwLock,
+};
 
 use crossbeam_channel::Sender;
 use derivative::Derivative;
-use gtk::{prelude::*, Menu, MenuItem, RadioMenuItem, SeparatorMenuItem};
+use gtk::{prelude::*, CheckMenuItem, IconTheme, Menu, MenuItem, RadioMenuItem, SeparatorMenuItem};
 use libappindicator::{AppIndicator, AppIndicatorStatus};
 use log::{debug, error, warn};
 use shadowsocks_gtk_rs::{consts::*, notify_method::NotifyMethod, util};
+use zbus::blocking::{fdo::DBusProxy, Connection};
 
-use crate::{event::AppEvent, io::profile_loader::ProfileFolder};
+use crate::{
+    color_scheme::ColorScheme,
+    event::AppEvent,
+    io::{app_state::AppState, profile_loader::ProfileFolder},
+    latency::{self, Latency},
+};
 
 /// A `RadioMenuItem` with its listen enable flag.
 ///
@@ -20,6 +32,9 @@ use crate::{event::AppEvent, io::profile_loader::ProfileFolder};
 /// from emitting an extraneous event when we programmatically set it to active.
 type ListeningRadioMenuItem = (RadioMenuItem, Rc<RwLock<bool>>);
 
+/// A `CheckMenuItem` with its listen enable flag; see `ListeningRadioMenuItem`.
+type ListeningCheckMenuItem = (CheckMenuItem, Rc<RwLock<bool>>);
+
 #[derive(Debug, Clone)]
 enum ProfileMenuItem {
     Profile(ListeningRadioMenuItem),
@@ -31,13 +46,28 @@ enum ProfileMenuItem {
 pub struct TrayItem {
     #[derivative(Debug(format_with = "shadowsocks_gtk_rs::util::hacks::omit_ai"))]
     ai: AppIndicator,
+    /// The base name of the tray icon, which variants are derived from.
+    icon_name: String,
+    icon_theme_dir: Option<PathBuf>,
     menu: Menu,
+    /// Kept so that the menu can be rebuilt at any time.
+    events_tx: Sender<AppEvent>,
     /// The `ListeningRadioMenuItem` for the stop button.
     manual_stop_item: ListeningRadioMenuItem,
     /// The `ListeningRadioMenuItem`s for the list of profiles.
     profile_items: Vec<ListeningRadioMenuItem>,
+    /// The names of profiles pinned to the top of their groups.
+    pinned_profiles: HashSet<String>,
+    /// The labels of profiles by name, i.e. with their color tags.
+    profile_labels: HashMap<String, String>,
     /// The `ListeningRadioMenuItem`s for the list of notify methods.
     notify_method_items: Vec<ListeningRadioMenuItem>,
+    /// The `ListeningCheckMenuItem`s for running profiles alongside the current one.
+    alongside_items: Vec<ListeningCheckMenuItem>,
+    /// The entry for showing profiles that cannot be loaded, hidden if there are none.
+    load_problems_item: MenuItem,
+    /// The currently shown tooltip, to avoid setting it repeatedly.
+    tooltip: String,
 }
 
 impl TrayItem {
@@ -49,27 +79,11 @@ impl TrayItem {
         icon_theme_dir: Option<impl AsRef<Path>>,
         events_tx: Sender<AppEvent>,
         profile_folder: &ProfileFolder,
-        notify_method: NotifyMethod,
+        state: &AppState,
     ) -> Self {
-        // create stop button up top because `TrayItem` has a mandatory field
-        let manual_stop_item = {
-            let events_tx = events_tx.clone();
-            let enable_flag = Rc::new(RwLock::new(true));
-            let enable_flag_mv = Rc::clone(&enable_flag);
-            let menu_item = RadioMenuItem::with_label("Stop sslocal");
-            menu_item.connect_toggled(move |item| {
-                if item.is_active() && *util::rwlock_read(&enable_flag_mv) {
-                    if let Err(_) = events_tx.send(AppEvent::ManualStop) {
-                        error!("Trying to send ManualStop event, but all receivers have hung up.");
-                    }
-                }
-            });
-            (menu_item, enable_flag)
-        };
-
         // create tray with icon
         let mut tray = Self {
-            ai: match icon_theme_dir {
+            ai: match &icon_theme_dir {
                 // BUG: For some reason the title is not set?
                 Some(dir) => {
                     let dir_str = dir.as_ref().to_str().unwrap(); // UTF-8 guaranteed by clap validator.
@@ -77,45 +91,314 @@ impl TrayItem {
                 }
                 None => AppIndicator::new(APP_NAME, icon_name),
             },
+            icon_name: icon_name.into(),
+            icon_theme_dir: icon_theme_dir.map(|dir| dir.as_ref().to_path_buf()),
             menu: Menu::new(),
-            manual_stop_item,
-            profile_items: vec![],       // will be populated when adding dynamic profiles
-            notify_method_items: vec![], // will be replaced when adding the selector
+            manual_stop_item: generate_manual_stop_item(events_tx.clone()),
+            events_tx,
+            profile_items: vec![], // will be populated when adding dynamic profiles
+            pinned_profiles: HashSet::new(),
+            profile_labels: HashMap::new(), // will be populated when adding dynamic profiles
+            notify_method_items: vec![],    // will be replaced when adding the selector
+            alongside_items: vec![],        // will be populated when adding dynamic profiles
+            load_problems_item: MenuItem::new(), // will be replaced when adding dynamic profiles
+            tooltip: String::new(),
         };
         tray.ai.set_status(AppIndicatorStatus::Active);
 
+        tray.build_menu(profile_folder, state);
+        tray
+    }
+
+    /// Check whether a StatusNotifierItem host is running on the session bus.
+    ///
+    /// `AppIndicator` registers as a StatusNotifierItem if a host is available,
+    /// and silently falls back to a legacy XEmbed icon otherwise, which is
+    /// invisible on GNOME without an extension and under most Wayland compositors.
+    pub fn sni_host_available() -> zbus::Result<bool> {
+        let conn = Connection::session()?;
+        let has_watcher = DBusProxy::new(&conn)?.name_has_owner(SNI_WATCHER_NAME.try_into()?)?;
+        Ok(has_watcher)
+    }
+
+    /// Discard the current menu and rebuild it with a new set of profiles.
+    ///
+    /// The tray is left in stopped state; use `notify_profile_switch`
+    /// afterwards to restore the active profile if necessary.
+    ///
+    /// `state` is used to initialize the setting entries.
+    pub fn reload_profiles(&mut self, profile_folder: &ProfileFolder, state: &AppState) {
+        debug!("Rebuilding tray menu");
+        self.menu = Menu::new();
+        self.manual_stop_item = generate_manual_stop_item(self.events_tx.clone());
+        self.build_menu(profile_folder, state);
+    }
+
+    /// Populate the (empty) menu with all entries, then set it as the tray's menu.
+    fn build_menu(&mut self, profile_folder: &ProfileFolder, state: &AppState) {
+        let events_tx = self.events_tx.clone();
+
         // add dynamic profiles
-        tray.add_label("Profiles");
-        tray.add_separator();
-        tray.load_profiles(profile_folder, events_tx.clone());
-        tray.add_separator();
+        self.add_label("Profiles");
+        self.add_separator();
+        // the active profile is always listed, so that the menu reflects the actual state
+        let shown_folder =
+            (state.tag_filter.as_ref()).and_then(|tag| profile_folder.filter_by_tag(tag, &state.most_recent_profile));
+        self.load_profiles(shown_folder.as_ref().unwrap_or(profile_folder), events_tx.clone());
+        let filter_tx = events_tx.clone();
+        self.add_menu_item("Filter Profiles…", move || {
+            if filter_tx.send(AppEvent::ProfileFilterShow).is_err() {
+                error!("Trying to send ProfileFilterShow event, but all receivers have hung up.");
+            }
+        });
+        let tags = profile_folder.tags();
+        if !tags.is_empty() {
+            let active = shown_folder.and(state.tag_filter.as_deref());
+            let tag_selector_item = generate_tag_selector(&tags, active, events_tx.clone());
+            self.menu.append(&tag_selector_item);
+        }
+        self.add_alongside_menu(profile_folder, state);
+        let load_problems_tx = events_tx.clone();
+        self.load_problems_item = MenuItem::new();
+        self.load_problems_item.set_no_show_all(true); // shown by `show_load_problems`
+        self.load_problems_item.connect_activate(move |_| {
+            if load_problems_tx.send(AppEvent::LoadProblemsShow).is_err() {
+                error!("Trying to send LoadProblemsShow event, but all receivers have hung up.");
+            }
+        });
+        self.menu.append(&self.load_problems_item);
+        self.add_separator();
 
         // add stop button (previously created)
-        tray.menu.append(&tray.manual_stop_item.0);
+        self.menu.append(&self.manual_stop_item.0);
 
         // add notify method selector
         let (notify_selector_item, notify_method_items) =
-            generate_notify_method_selector(notify_method, events_tx.clone());
-        tray.notify_method_items = notify_method_items;
-        tray.menu.append(&notify_selector_item);
+            generate_notify_method_selector(state.notify_method, events_tx.clone());
+        self.notify_method_items = notify_method_items;
+        self.menu.append(&notify_selector_item);
+
+        // add toggles
+        self.add_check_item("Set System Proxy", state.system_proxy, AppEvent::SetSystemProxy);
+        self.add_check_item("Serve PAC File", state.pac.enabled, AppEvent::SetPacServer);
+        self.add_check_item(
+            "Use PAC for System Proxy",
+            state.pac.use_for_system_proxy,
+            AppEvent::SetSystemProxyPac,
+        );
+        self.add_check_item(
+            "Verify Before Switching",
+            state.health_check.enabled,
+            AppEvent::SetHealthCheck,
+        );
+        self.add_check_item("Kill Switch", state.kill_switch, AppEvent::SetKillSwitch);
+        self.add_check_item(
+            "Watch Clipboard for ss:// Links",
+            state.clipboard_monitor,
+            AppEvent::SetClipboardMonitor,
+        );
+        self.add_check_item("Keep Awake While Connected", state.keep_awake, AppEvent::SetKeepAwake);
+        self.add_check_item("Collect Traffic Stats", state.collect_stats, AppEvent::SetCollectStats);
+        self.add_check_item("Write Logs to File", state.log_file.enabled, AppEvent::SetLogFile);
+        // not saved, since it is only meant for debugging
+        self.add_check_item("Verbose sslocal Logs", false, AppEvent::SetVerboseLogs);
 
         // add other static menu entries
+        let url_import_tx = events_tx.clone();
+        self.add_menu_item("Import from URL", move || {
+            if url_import_tx.send(AppEvent::UrlImportShow).is_err() {
+                error!("Trying to send UrlImportShow event, but all receivers have hung up.");
+            }
+        });
+        let file_import_tx = events_tx.clone();
+        self.add_menu_item("Import Profiles…", move || {
+            if file_import_tx.send(AppEvent::FileImportShow).is_err() {
+                error!("Trying to send FileImportShow event, but all receivers have hung up.");
+            }
+        });
+        let export_tx = events_tx.clone();
+        self.add_menu_item("Export Profiles…", move || {
+            if export_tx.send(AppEvent::ProfileExportShow).is_err() {
+                error!("Trying to send ProfileExportShow event, but all receivers have hung up.");
+            }
+        });
+        let qr_scan_tx = events_tx.clone();
+        self.add_menu_item("Scan QR from Screen", move || {
+            if qr_scan_tx.send(AppEvent::ScanQrFromScreen).is_err() {
+                error!("Trying to send ScanQrFromScreen event, but all receivers have hung up.");
+            }
+        });
+        let new_profile_tx = events_tx.clone();
+        self.add_menu_item("New Profile…", move || {
+            let event = AppEvent::ProfileEditorShow { edit_current: false };
+            if new_profile_tx.send(event).is_err() {
+                error!("Trying to send ProfileEditorShow event, but all receivers have hung up.");
+            }
+        });
+        let edit_profile_tx = events_tx.clone();
+        self.add_menu_item("Edit Current Profile…", move || {
+            let event = AppEvent::ProfileEditorShow { edit_current: true };
+            if edit_profile_tx.send(event).is_err() {
+                error!("Trying to send ProfileEditorShow event, but all receivers have hung up.");
+            }
+        });
+        let duplicate_tx = events_tx.clone();
+        self.add_menu_item("Duplicate Current Profile…", move || {
+            let event = AppEvent::ProfileDuplicateShow { from_template: false };
+            if duplicate_tx.send(event).is_err() {
+                error!("Trying to send ProfileDuplicateShow event, but all receivers have hung up.");
+            }
+        });
+        let template_tx = events_tx.clone();
+        self.add_menu_item("New from Template…", move || {
+            let event = AppEvent::ProfileDuplicateShow { from_template: true };
+            if template_tx.send(event).is_err() {
+                error!("Trying to send ProfileDuplicateShow event, but all receivers have hung up.");
+            }
+        });
+        let delete_tx = events_tx.clone();
+        self.add_menu_item("Delete Profile…", move || {
+            if delete_tx.send(AppEvent::ProfileDeleteShow).is_err() {
+                error!("Trying to send ProfileDeleteShow event, but all receivers have hung up.");
+            }
+        });
+        let qr_export_tx = events_tx.clone();
+        self.add_menu_item("Show QR Code of Current Profile", move || {
+            if qr_export_tx.send(AppEvent::QrExportShow).is_err() {
+                error!("Trying to send QrExportShow event, but all receivers have hung up.");
+            }
+        });
+        let launch_preview_tx = events_tx.clone();
+        self.add_menu_item("Show Launch Command of Current Profile", move || {
+            if launch_preview_tx.send(AppEvent::LaunchPreviewShow).is_err() {
+                error!("Trying to send LaunchPreviewShow event, but all receivers have hung up.");
+            }
+        });
+        let latency_tx = events_tx.clone();
+        self.add_menu_item("Test All Latencies", move || {
+            if latency_tx.send(AppEvent::TestLatencies).is_err() {
+                error!("Trying to send TestLatencies event, but all receivers have hung up.");
+            }
+        });
+        let check_ip_tx = events_tx.clone();
+        self.add_menu_item("Check My IP", move || {
+            if check_ip_tx.send(AppEvent::CheckIp).is_err() {
+                error!("Trying to send CheckIp event, but all receivers have hung up.");
+            }
+        });
+        let check_dns_tx = events_tx.clone();
+        self.add_menu_item("Check DNS Leaks", move || {
+            if check_dns_tx.send(AppEvent::CheckDns).is_err() {
+                error!("Trying to send CheckDns event, but all receivers have hung up.");
+            }
+        });
+        let refresh_subs_tx = events_tx.clone();
+        self.add_menu_item("Refresh Subscriptions", move || {
+            if refresh_subs_tx.send(AppEvent::RefreshSubscriptions).is_err() {
+                error!("Trying to send RefreshSubscriptions event, but all receivers have hung up.");
+            }
+        });
+        let update_rules_tx = events_tx.clone();
+        self.add_menu_item("Update Routing Rules", move || {
+            if update_rules_tx.send(AppEvent::UpdateRules).is_err() {
+                error!("Trying to send UpdateRules event, but all receivers have hung up.");
+            }
+        });
         let log_viewer_tx = events_tx.clone();
-        tray.add_menu_item("Show sslocal Output", move || {
+        self.add_menu_item("Show sslocal Output", move || {
             if let Err(_) = log_viewer_tx.send(AppEvent::LogViewerShow) {
                 error!("Trying to send LogViewerShow event, but all receivers have hung up.");
             }
         });
-        let quit_tx = events_tx.clone();
-        tray.add_menu_item("Quit", move || {
+        let open_log_file_tx = events_tx.clone();
+        self.add_menu_item("Open Log File", move || {
+            if open_log_file_tx.send(AppEvent::OpenLogFile).is_err() {
+                error!("Trying to send OpenLogFile event, but all receivers have hung up.");
+            }
+        });
+        let stats_window_tx = events_tx.clone();
+        self.add_menu_item("Show Traffic Stats", move || {
+            if stats_window_tx.send(AppEvent::StatsWindowShow).is_err() {
+                error!("Trying to send StatsWindowShow event, but all receivers have hung up.");
+            }
+        });
+        let preferences_tx = events_tx.clone();
+        self.add_menu_item("Preferences…", move || {
+            if preferences_tx.send(AppEvent::PreferencesShow).is_err() {
+                error!("Trying to send PreferencesShow event, but all receivers have hung up.");
+            }
+        });
+        let quit_tx = events_tx;
+        self.add_menu_item("Quit", move || {
             if let Err(_) = quit_tx.send(AppEvent::Quit) {
                 error!("Trying to send Quit event, but all receivers have hung up.");
             }
         });
 
         // Wrap up
-        tray.finalize();
-        tray
+        self.finalize();
+    }
+
+    /// Switch to the icon variant for a color scheme, i.e. `<icon>-dark` or `<icon>-light`,
+    /// falling back to the base icon if the variant is not installed.
+    pub fn set_color_scheme(&mut self, scheme: ColorScheme) {
+        let variant = match scheme {
+            ColorScheme::PreferDark => Some(format!("{}-dark", self.icon_name)),
+            ColorScheme::PreferLight => Some(format!("{}-light", self.icon_name)),
+            ColorScheme::NoPreference => None,
+        };
+        let icon = match variant {
+            Some(variant) if self.has_icon(&variant) => variant,
+            Some(variant) => {
+                debug!("Icon \"{}\" not found; using \"{}\"", variant, self.icon_name);
+                self.icon_name.clone()
+            }
+            None => self.icon_name.clone(),
+        };
+        debug!("Setting tray icon to \"{}\"", icon);
+        self.ai.set_icon_full(&icon, APP_NAME);
+    }
+
+    /// Check whether an icon can be found, either in the custom directory or in the icon theme.
+    fn has_icon(&self, name: &str) -> bool {
+        if let Some(dir) = &self.icon_theme_dir {
+            if ["png", "svg"]
+                .iter()
+                .any(|ext| dir.join(format!("{}.{}", name, ext)).is_file())
+            {
+                return true;
+            }
+        }
+        match IconTheme::default() {
+            Some(theme) => theme.has_icon(name),
+            None => false,
+        }
+    }
+
+    /// Show a short text next to the tray icon, e.g. the current throughput.
+    ///
+    /// Pass an empty string to hide it.
+    /// Note that not all desktop environments display this.
+    pub fn set_label(&mut self, label: &str) {
+        self.ai.set_label(label, "");
+    }
+
+    /// Show a summary of the connection when hovering over the tray icon.
+    ///
+    /// This sets the indicator's title, which SNI hosts show as its tooltip.
+    pub fn set_tooltip(&mut self, tooltip: &str) {
+        if self.tooltip != tooltip {
+            self.ai.set_title(tooltip);
+            self.tooltip = tooltip.into();
+        }
+    }
+
+    /// Show how many profiles cannot be loaded, with an entry listing them,
+    /// or hide that entry if there are none.
+    pub fn show_load_problems(&mut self, count: usize) {
+        self.load_problems_item
+            .set_label(&format!("Show Load Problems ({})…", count));
+        self.load_problems_item.set_visible(count > 0);
     }
 
     /// Notify the tray about sslocal stoppage (primarily, due to error),
@@ -130,13 +413,10 @@ impl TrayItem {
     /// Notify the tray about sslocal switching to a another,
     /// without emitting a `SwitchProfile` event.
     pub fn notify_profile_switch(&mut self, name: impl AsRef<str>) {
-        let profile_item = self.profile_items.iter().find(|(item, _)| {
-            let item_name = item
-                .label()
-                .expect("A profile's RadioMenuItem has no label")
-                .to_string();
-            name.as_ref() == item_name
-        });
+        let profile_item = self
+            .profile_items
+            .iter()
+            .find(|(item, _)| name.as_ref() == item.widget_name());
         match profile_item {
             Some((item, listen_enable)) => {
                 debug!("Setting tray to active state with profile \"{}\"", name.as_ref());
@@ -148,6 +428,78 @@ impl TrayItem {
         }
     }
 
+    /// Notify the tray about a profile starting or stopping alongside the current one,
+    /// without emitting a `SetProfileAlongside` event.
+    pub fn notify_alongside_change(&mut self, name: &str, running: bool) {
+        match self.alongside_items.iter().find(|(item, _)| item.widget_name() == name) {
+            Some((item, listen_enable)) => {
+                *util::rwlock_write(listen_enable) = false; // set listen disable
+                item.set_active(running);
+                *util::rwlock_write(listen_enable) = true; // set listen enable
+            }
+            None => warn!("Cannot find CheckMenuItem for profile named \"{}\"", name),
+        }
+    }
+
+    /// Annotate each profile with its latency, and sort profiles
+    /// within each group from fastest to slowest, after the pinned ones.
+    ///
+    /// Groups and other menu entries stay in place.
+    pub fn show_latencies(&mut self, latencies: &HashMap<String, Latency>) {
+        // annotate
+        for (item, _) in self.profile_items.iter() {
+            let name = item.widget_name();
+            let base_label = self
+                .profile_labels
+                .get(name.as_str())
+                .map_or(name.as_str(), |l| l.as_str());
+            let label = match latencies.get(name.as_str()) {
+                Some(l) => format!("{} ({})", base_label, latency::describe(l)),
+                None => base_label.to_string(),
+            };
+            item.set_label(&label);
+        }
+
+        // group by parent menu
+        let mut menus: Vec<(Menu, Vec<RadioMenuItem>)> = vec![];
+        for (item, _) in self.profile_items.iter() {
+            let menu = match item.parent().and_then(|p| p.downcast::<Menu>().ok()) {
+                Some(m) => m,
+                None => continue,
+            };
+            match menus.iter_mut().find(|(m, _)| *m == menu) {
+                Some((_, items)) => items.push(item.clone()),
+                None => menus.push((menu, vec![item.clone()])),
+            }
+        }
+
+        // sort by reusing the positions currently occupied by profiles
+        for (menu, mut items) in menus {
+            let children = menu.children();
+            let mut positions: Vec<_> = items
+                .iter()
+                .filter_map(|item| children.iter().position(|c| c == item.upcast_ref::<gtk::Widget>()))
+                .collect();
+            positions.sort_unstable();
+            items.sort_by(|a, b| {
+                let (a, b) = (a.widget_name(), b.widget_name());
+                // pinned profiles stay on top, in their original order
+                match (
+                    self.pinned_profiles.contains(a.as_str()),
+                    self.pinned_profiles.contains(b.as_str()),
+                ) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Less,
+                    (false, true) => Ordering::Greater,
+                    (false, false) => latency::compare(latencies.get(a.as_str()), latencies.get(b.as_str())),
+                }
+            });
+            for (item, pos) in items.iter().zip(positions) {
+                menu.reorder_child(item, pos as i32);
+            }
+        }
+    }
+
     /// Notify the tray about notification method change,
     /// without emitting a `SetNotify` event.
     #[cfg(feature = "runtime-api")]
@@ -191,6 +543,57 @@ impl TrayItem {
         item.connect_activate(move |_| action());
         self.menu.append(&item);
     }
+    /// Append a check item to the tray item's menu,
+    /// which will send the event created by `to_event` when toggled.
+    fn add_check_item(&mut self, label: &str, active: bool, to_event: fn(bool) -> AppEvent) {
+        let item = CheckMenuItem::with_label(label);
+        item.set_active(active);
+        let events_tx = self.events_tx.clone();
+        item.connect_toggled(move |item| {
+            let event = to_event(item.is_active());
+            if let Err(err) = events_tx.send(event) {
+                error!("Trying to send {:?} event, but all receivers have hung up.", err.0);
+            }
+        });
+        self.menu.append(&item);
+    }
+    /// Append a submenu with a `CheckMenuItem` for each profile, which runs it
+    /// alongside the current one when checked.
+    ///
+    /// Also replaces `Self::alongside_items` with the new list of `CheckMenuItem`s.
+    fn add_alongside_menu(&mut self, profile_folder: &ProfileFolder, state: &AppState) {
+        let submenu = Menu::new();
+        self.alongside_items = profile_folder
+            .get_profiles()
+            .into_iter()
+            .map(|p| {
+                let profile = p.clone();
+                let name = &p.metadata.display_name;
+                let enable_flag = Rc::new(RwLock::new(true));
+                let enable_flag_mv = Rc::clone(&enable_flag);
+                let events_tx = self.events_tx.clone();
+                let item = CheckMenuItem::with_label(&p.metadata.label());
+                item.set_widget_name(name);
+                item.set_active(state.alongside_profiles.contains(name));
+                item.connect_toggled(move |item| {
+                    if *util::rwlock_read(&enable_flag_mv) {
+                        let event = AppEvent::SetProfileAlongside {
+                            profile: profile.clone(),
+                            enable: item.is_active(),
+                        };
+                        if events_tx.send(event).is_err() {
+                            error!("Trying to send SetProfileAlongside event, but all receivers have hung up.");
+                        }
+                    }
+                });
+                submenu.append(&item);
+                (item, enable_flag)
+            })
+            .collect();
+        let parent = MenuItem::with_label("Run Alongside");
+        parent.set_submenu(Some(&submenu));
+        self.menu.append(&parent);
+    }
     /// Load all `Profiles` from the root `ProfileFolder`,
     /// automatically generate the nested menu structure using `generate_profile_tree`,
     /// and append them all to the tray item's menu as `RadioMenuItem`s.
@@ -229,6 +632,17 @@ impl TrayItem {
         }
         // reset `self.profile_items` with temp `Vec`
         self.profile_items = radio_menu_item_list;
+        self.pinned_profiles = profile_folder
+            .get_profiles()
+            .into_iter()
+            .filter(|p| p.metadata.pinned)
+            .map(|p| p.metadata.display_name.clone())
+            .collect();
+        self.profile_labels = profile_folder
+            .get_profiles()
+            .into_iter()
+            .map(|p| (p.metadata.display_name.clone(), p.metadata.label()))
+            .collect();
     }
 
     /// Compose the menu to make ready for display.
@@ -238,6 +652,22 @@ impl TrayItem {
     }
 }
 
+/// Constructs the stop button, which also serves as the reference
+/// used to group all profile `RadioMenuItem`s.
+fn generate_manual_stop_item(events_tx: Sender<AppEvent>) -> ListeningRadioMenuItem {
+    let enable_flag = Rc::new(RwLock::new(true));
+    let enable_flag_mv = Rc::clone(&enable_flag);
+    let menu_item = RadioMenuItem::with_label("Stop sslocal");
+    menu_item.connect_toggled(move |item| {
+        if item.is_active() && *util::rwlock_read(&enable_flag_mv) {
+            if let Err(_) = events_tx.send(AppEvent::ManualStop) {
+                error!("Trying to send ManualStop event, but all receivers have hung up.");
+            }
+        }
+    });
+    (menu_item, enable_flag)
+}
+
 /// Recursively constructs a nested menu structure from a `ProfileFolder`,
 /// attaching the corresponding profile-switch action to each leaf `Profile`.
 ///
@@ -255,7 +685,9 @@ fn generate_profile_tree(
             let profile = p.clone();
             let enable_flag = Rc::new(RwLock::new(true));
             let enable_flag_mv = Rc::clone(&enable_flag);
-            let menu_item = RadioMenuItem::with_label_from_widget(group, Some(&p.metadata.display_name));
+            let menu_item = RadioMenuItem::with_label_from_widget(group, Some(&p.metadata.label()));
+            // the label may be annotated later, so also store the name here for lookups
+            menu_item.set_widget_name(&p.metadata.display_name);
             menu_item.set_sensitive(true);
             menu_item.connect_toggled(move |item| {
                 if item.is_active() && *util::rwlock_read(&enable_flag_mv) {
@@ -268,6 +700,19 @@ fn generate_profile_tree(
         }
         ProfileFolder::Group(g) => {
             let submenu = Menu::new();
+            let parent = MenuItem::with_label(&g.display_name);
+            if let Some(dir) = &g.unloaded {
+                // loaded when opened, after which the menu is rebuilt
+                let placeholder = MenuItem::with_label("Loading…");
+                placeholder.set_sensitive(false);
+                submenu.append(&placeholder);
+                let (dir, events_tx) = (dir.clone(), events_tx.clone());
+                parent.connect_activate(move |_| {
+                    if events_tx.send(AppEvent::LoadGroup(dir.clone())).is_err() {
+                        error!("Trying to send LoadGroup event, but all receivers have hung up.");
+                    }
+                });
+            }
             for cf in g.content.iter() {
                 match generate_profile_tree(cf, group, events_tx.clone(), radio_menu_item_list) {
                     ProfileMenuItem::Profile(radio_item) => {
@@ -278,7 +723,6 @@ fn generate_profile_tree(
                 }
             }
 
-            let parent = MenuItem::with_label(&g.display_name);
             parent.set_sensitive(true);
             parent.set_submenu(Some(&submenu));
             ProfileMenuItem::Group(parent)
@@ -286,6 +730,35 @@ fn generate_profile_tree(
     }
 }
 
+/// Constructs the selection menu for filtering profiles by tag,
+/// with an entry for each tag in `tags` plus one to show all profiles.
+///
+/// The menu is rebuilt whenever the filter changes, so the `RadioMenuItem`s are not kept.
+fn generate_tag_selector(tags: &[String], active: Option<&str>, events_tx: Sender<AppEvent>) -> MenuItem {
+    let submenu = Menu::new();
+    let all_item = RadioMenuItem::with_label("All Tags");
+    let entries = std::iter::once((all_item.clone(), None))
+        .chain(tags.iter().map(|tag| {
+            let item = RadioMenuItem::with_label_from_widget(&all_item, Some(tag));
+            (item, Some(tag.clone()))
+        }))
+        .collect::<Vec<_>>();
+    for (item, tag) in entries {
+        item.set_active(tag.as_deref() == active);
+        let events_tx = events_tx.clone();
+        item.connect_toggled(move |item| {
+            if item.is_active() && events_tx.send(AppEvent::SetTagFilter(tag.clone())).is_err() {
+                error!("Trying to send SetTagFilter event, but all receivers have hung up.");
+            }
+        });
+        submenu.append(&item);
+    }
+
+    let parent = MenuItem::with_label("Filter by Tag");
+    parent.set_submenu(Some(&submenu));
+    parent
+}
+
 /// Constructs the selection menu for `NotifyMethod` by enumerating its variants.
 ///
 /// Returns the constructed `MenuItem` and all the generated `RadioMenuItem`s
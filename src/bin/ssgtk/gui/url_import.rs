@@ -0,0 +1,161 @@
+//! This module contains code that creates dialogs for importing
+//! profiles from SIP002 `ss://` URLs, and from files exported by other clients.
+
+use crossbeam_channel::Sender;
+use gtk::{
+    prelude::*, CheckButton, Dialog, Entry, FileChooserAction, FileChooserDialog, FileFilter, Label, ResponseType,
+    Window,
+};
+use log::{debug, error};
+
+use crate::{event::AppEvent, io::profile_loader::ProfileConfig};
+
+/// Show a non-blocking dialog prompting the user for a `ss://` URL.
+///
+/// An `ImportFromUrl` event is sent if the user confirms the import.
+pub fn show_url_import_dialog(events_tx: Sender<AppEvent>) {
+    debug!("Showing URL import dialog");
+    let label = Label::builder()
+        .label("Paste a SIP002 ss:// URL to import it as a new profile:")
+        .margin(6)
+        .xalign(0.0)
+        .build();
+    let entry = Entry::builder()
+        .activates_default(true)
+        .hexpand(true)
+        .margin(6)
+        .placeholder_text("ss://")
+        .build();
+    let dialog = Dialog::builder()
+        .default_width(500)
+        .deletable(true)
+        .title("Import from URL")
+        .build();
+    dialog.content_area().add(&label);
+    dialog.content_area().add(&entry);
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Import", ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Accept);
+
+    let entry_mv = entry.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            let url = entry_mv.text().to_string();
+            if events_tx.send(AppEvent::ImportFromUrl(url)).is_err() {
+                error!("Trying to send ImportFromUrl event, but all receivers have hung up.");
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
+
+/// Show a non-blocking dialog prompting the user for a file exported by another client.
+///
+/// An `ImportFromFile` event is sent if the user confirms the import.
+pub fn show_file_import_dialog(events_tx: Sender<AppEvent>) {
+    debug!("Showing file import dialog");
+    let dialog = FileChooserDialog::with_buttons(
+        Some("Import Profiles"),
+        None::<&Window>,
+        FileChooserAction::Open,
+        &[("Cancel", ResponseType::Cancel), ("Import", ResponseType::Accept)],
+    );
+    let json_filter = FileFilter::new();
+    json_filter.set_name(Some("shadowsocks-android or Outline exports (*.json)"));
+    json_filter.add_pattern("*.json");
+    dialog.add_filter(&json_filter);
+    let clash_filter = FileFilter::new();
+    clash_filter.set_name(Some("Clash configs (*.yaml, *.yml)"));
+    clash_filter.add_pattern("*.yaml");
+    clash_filter.add_pattern("*.yml");
+    dialog.add_filter(&clash_filter);
+    let all_filter = FileFilter::new();
+    all_filter.set_name(Some("All files"));
+    all_filter.add_pattern("*");
+    dialog.add_filter(&all_filter);
+    dialog.set_default_response(ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            match dialog.file().and_then(|f| f.path()) {
+                Some(path) => {
+                    if events_tx.send(AppEvent::ImportFromFile(path)).is_err() {
+                        error!("Trying to send ImportFromFile event, but all receivers have hung up.");
+                    }
+                }
+                None => error!("The selected file is not a local file"),
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
+
+/// Describe the server of a `ss://` URL without revealing its password.
+pub fn describe_ss_url(url: &str) -> String {
+    match ProfileConfig::from_ss_url(url) {
+        Ok(config) => match (config.display_name(), config.server_addr()) {
+            (Some(name), Some((host, port))) => format!("{} ({}:{})", name, host, port),
+            (None, Some((host, port))) => format!("{}:{}", host, port),
+            _ => config.suggested_dir_name(),
+        },
+        Err(err) => format!("Invalid URL: {}", err),
+    }
+}
+
+/// Show a non-blocking dialog titled `title`, listing `ss://` URLs found elsewhere
+/// (e.g. in QR codes or the clipboard), prompting the user to choose which ones to import.
+///
+/// An `ImportFromUrl` event is sent for each URL selected, if the user confirms the import.
+pub fn show_urls_import_dialog(events_tx: Sender<AppEvent>, title: &str, urls: Vec<String>) {
+    let choices = (urls.into_iter())
+        .map(|url| (describe_ss_url(&url), AppEvent::ImportFromUrl(url)))
+        .collect();
+    show_import_choices_dialog(events_tx, title, choices)
+}
+
+/// Show a non-blocking dialog titled `title`, listing things found to import as (description, event),
+/// prompting the user to choose which ones to import.
+///
+/// The event of each choice selected is sent, if the user confirms the import.
+pub fn show_import_choices_dialog(events_tx: Sender<AppEvent>, title: &str, choices: Vec<(String, AppEvent)>) {
+    debug!("Showing \"{}\" dialog with {} choices", title, choices.len());
+    let label = Label::builder()
+        .label("The following are found. Select the ones to import as new profiles:")
+        .margin(6)
+        .xalign(0.0)
+        .build();
+    let dialog = Dialog::builder()
+        .default_width(500)
+        .deletable(true)
+        .title(title)
+        .build();
+    dialog.content_area().add(&label);
+    let checks: Vec<_> = choices
+        .into_iter()
+        .map(|(desc, event)| {
+            let check = CheckButton::builder().label(&desc).active(true).margin(6).build();
+            dialog.content_area().add(&check);
+            (event, check)
+        })
+        .collect();
+    dialog.add_button("Cancel", ResponseType::Cancel);
+    dialog.add_button("Import", ResponseType::Accept);
+    dialog.set_default_response(ResponseType::Accept);
+
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            for (event, _) in checks.iter().filter(|(_, check)| check.is_active()) {
+                if events_tx.send(event.clone()).is_err() {
+                    error!("Trying to send an import event, but all receivers have hung up.");
+                }
+            }
+        }
+        dialog.close();
+    }); // handle close
+    dialog.show_all(); // render
+    dialog.present(); // bring to foreground
+}
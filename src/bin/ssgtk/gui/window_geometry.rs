@@ -0,0 +1,34 @@
+//! This module contains code that remembers and restores the position and size of windows.
+
+use std::{cell::Cell, rc::Rc};
+
+use gtk::{prelude::*, ApplicationWindow};
+
+use crate::{gui::DisplayServer, io::app_state::WindowGeometry};
+
+/// Move and resize a window to `initial` if set, then keep track of its geometry as it changes.
+///
+/// The returned cell always holds the latest geometry, and can still be read after the window is destroyed.
+pub fn track_geometry(window: &ApplicationWindow, initial: Option<WindowGeometry>) -> Rc<Cell<Option<WindowGeometry>>> {
+    // Wayland neither lets clients place windows nor reports their position,
+    // so only the size is restored and the last known position is kept as is
+    let can_position = DisplayServer::detect().can_position_windows();
+    if let Some(geometry) = initial {
+        if can_position {
+            window.move_(geometry.x, geometry.y);
+        }
+        window.resize(geometry.width, geometry.height);
+    }
+    let geometry = Rc::new(Cell::new(initial));
+    let geometry_ref = Rc::clone(&geometry);
+    window.connect_configure_event(move |window, _| {
+        let (x, y) = match (can_position, geometry_ref.get()) {
+            (false, Some(previous)) => (previous.x, previous.y),
+            _ => window.position(),
+        };
+        let (width, height) = window.size();
+        geometry_ref.set(Some(WindowGeometry { x, y, width, height }));
+        false // propagate
+    });
+    geometry
+}
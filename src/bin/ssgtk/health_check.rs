@@ -0,0 +1,102 @@
+//! This module contains code that verifies a newly started `sslocal` instance,
+//! by sending a HTTP HEAD request to a probe URL through its local proxy.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+use log::{debug, error, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::{event::AppEvent, io::profile_loader::Profile};
+
+/// How long to wait for `sslocal` to start listening.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often to check whether `sslocal` has started listening.
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// User-configurable settings of the health check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckConfig {
+    /// Whether to verify connectivity before committing a profile switch.
+    pub enabled: bool,
+    /// The URL to send a HEAD request to.
+    pub probe_url: String,
+    /// Give up on the request after this long.
+    pub timeout: Duration,
+    /// In a failover group, move on to the next profile
+    /// once the check has failed this many times in a row.
+    pub max_failures: u32,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_url: "http://www.gstatic.com/generate_204".into(),
+            timeout: Duration::from_secs(10),
+            max_failures: 2,
+        }
+    }
+}
+
+/// Wait for the SOCKS5 proxy at `proxy_addr` to come up, then request the probe URL through it.
+///
+/// Any HTTP response counts as success, since it proves that the proxy works.
+/// Returns the time taken by the request.
+pub fn check(proxy_addr: SocketAddr, config: &HealthCheckConfig) -> Result<Duration, String> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while let Err(err) = TcpStream::connect_timeout(&proxy_addr, STARTUP_POLL_INTERVAL) {
+        if Instant::now() >= deadline {
+            return Err(format!("sslocal is not listening on {}: {}", proxy_addr, err));
+        }
+        trace!("Waiting for sslocal to listen on {}", proxy_addr);
+        thread::sleep(STARTUP_POLL_INTERVAL);
+    }
+
+    let proxy = ureq::Proxy::new(format!("socks5://{}", proxy_addr)).map_err(|err| err.to_string())?;
+    let agent = ureq::AgentBuilder::new()
+        .proxy(proxy)
+        .timeout_connect(config.timeout) // also covers the SOCKS handshake
+        .timeout(config.timeout)
+        .build();
+    let start = Instant::now();
+    match agent.head(&config.probe_url).call() {
+        Ok(_) | Err(ureq::Error::Status(..)) => Ok(start.elapsed()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Check the profile named `profile_name` in the background,
+/// then send the result as an `AppEvent::HealthChecked` event.
+///
+/// `previous` is passed along, so that the switch can be rolled back.
+pub fn spawn_check(
+    profile_name: String,
+    proxy_addr: SocketAddr,
+    config: HealthCheckConfig,
+    previous: Option<Profile>,
+    events_tx: Sender<AppEvent>,
+) -> io::Result<()> {
+    thread::Builder::new()
+        .name("Health check daemon".into())
+        .spawn(move || {
+            debug!("Checking profile \"{}\" with {}", profile_name, config.probe_url);
+            let result = check(proxy_addr, &config);
+            let event = AppEvent::HealthChecked {
+                profile_name,
+                previous,
+                result,
+            };
+            if events_tx.send(event).is_err() {
+                error!("Trying to send HealthChecked event, but all receivers have hung up.");
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! This module contains code that registers global hotkeys via xdg-desktop-portal,
+//! and turns their activations into `AppEvent`s.
+//!
+//! See https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.GlobalShortcuts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io, process, thread,
+};
+
+use crossbeam_channel::Sender;
+use enum_iterator::Sequence;
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::event::AppEvent;
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+
+/// An action that can be bound to a global hotkey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Sequence, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HotkeyAction {
+    /// Stop `sslocal` if running, otherwise reconnect to the most recent profile.
+    ToggleConnection,
+    /// Switch to the profile after the current one, in tray menu order.
+    NextProfile,
+    /// Show the `sslocal` output.
+    ShowBacklog,
+}
+
+impl HotkeyAction {
+    /// The shortcut ID registered with the portal.
+    pub fn id(&self) -> &'static str {
+        use HotkeyAction::*;
+        match self {
+            ToggleConnection => "toggle-connection",
+            NextProfile => "next-profile",
+            ShowBacklog => "show-backlog",
+        }
+    }
+    /// The description shown to the user, e.g. in the desktop's shortcut settings.
+    pub fn description(&self) -> &'static str {
+        use HotkeyAction::*;
+        match self {
+            ToggleConnection => "Toggle connection",
+            NextProfile => "Switch to next profile",
+            ShowBacklog => "Show sslocal output",
+        }
+    }
+    fn from_id(id: &str) -> Option<Self> {
+        enum_iterator::all::<Self>().find(|action| action.id() == id)
+    }
+}
+
+#[derive(Debug)]
+pub enum HotkeyError {
+    /// Communicating with xdg-desktop-portal has failed, e.g. it does not support global shortcuts.
+    DBusError(zbus::Error),
+    /// The user or the portal has refused the request.
+    Rejected,
+    /// The portal responded with something unexpected.
+    BadResponse(String),
+}
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HotkeyError::*;
+        let prefix = "HotkeyError";
+        match self {
+            DBusError(e) => write!(f, "{}-DBusError: {}", prefix, e),
+            Rejected => write!(f, "{}-Rejected: global shortcuts were refused", prefix),
+            BadResponse(msg) => write!(f, "{}-BadResponse: {}", prefix, msg),
+        }
+    }
+}
+
+impl From<zbus::Error> for HotkeyError {
+    fn from(err: zbus::Error) -> Self {
+        Self::DBusError(err)
+    }
+}
+impl From<zbus::zvariant::Error> for HotkeyError {
+    fn from(err: zbus::zvariant::Error) -> Self {
+        Self::DBusError(err.into())
+    }
+}
+
+/// Make a portal request with `call`, then block until the portal responds, returning its results.
+///
+/// `token` must be passed to `call` as `handle_token`, so that the request object path can be
+/// predicted and subscribed to in advance.
+fn portal_request(
+    conn: &Connection,
+    token: &str,
+    call: impl FnOnce() -> zbus::Result<OwnedObjectPath>,
+) -> Result<HashMap<String, OwnedValue>, HotkeyError> {
+    // see https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.Request
+    let sender = conn
+        .unique_name()
+        .ok_or_else(|| HotkeyError::BadResponse("connection has no unique name".into()))?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let request_path = format!("{}/request/{}/{}", PORTAL_PATH, sender, token);
+    let request = Proxy::new(
+        conn,
+        PORTAL_DESTINATION,
+        request_path.as_str(),
+        "org.freedesktop.portal.Request",
+    )?;
+    let mut responses = request.receive_signal("Response")?;
+
+    let handle = call()?;
+    if handle.as_str() != request_path {
+        warn!(
+            "Unexpected portal request handle {}, expected {}",
+            handle.as_str(),
+            request_path
+        );
+    }
+
+    let msg = responses
+        .next()
+        .ok_or_else(|| HotkeyError::BadResponse("connection closed before response".into()))?;
+    let (response, results): (u32, HashMap<String, OwnedValue>) = msg.body()?;
+    trace!("Global shortcuts portal responded with {}: {:?}", response, results);
+    if response != 0 {
+        return Err(HotkeyError::Rejected);
+    }
+    Ok(results)
+}
+
+/// Create a global shortcuts session and bind all actions in `bindings`
+/// with their preferred triggers, returning the session handle.
+///
+/// The desktop may ask the user to confirm or change the triggers.
+fn bind(
+    conn: &Connection,
+    portal: &Proxy,
+    bindings: &BTreeMap<HotkeyAction, String>,
+) -> Result<OwnedObjectPath, HotkeyError> {
+    let token = format!("ssgtk_hotkey_{}", process::id());
+
+    let session_token = format!("{}_session", token);
+    let options: HashMap<&str, Value> = [
+        ("handle_token", session_token.as_str().into()),
+        ("session_handle_token", token.as_str().into()),
+    ]
+    .into_iter()
+    .collect();
+    let results = portal_request(conn, &session_token, || portal.call("CreateSession", &(options,)))?;
+    // older portals return an object path instead of a string
+    let session = match results.get("session_handle").map(|v| &**v) {
+        Some(Value::Str(s)) => OwnedObjectPath::try_from(s.as_str())?,
+        Some(Value::ObjectPath(p)) => p.to_owned().into(),
+        _ => return Err(HotkeyError::BadResponse("response has no session_handle".into())),
+    };
+    debug!("Global shortcuts session created at {}", session.as_str());
+
+    let bind_token = format!("{}_bind", token);
+    let shortcuts: Vec<(&str, HashMap<&str, Value>)> = bindings
+        .iter()
+        .map(|(action, trigger)| {
+            let props = [
+                ("description", action.description().into()),
+                ("preferred_trigger", trigger.as_str().into()),
+            ]
+            .into_iter()
+            .collect();
+            (action.id(), props)
+        })
+        .collect();
+    let options: HashMap<&str, Value> = [("handle_token", bind_token.as_str().into())].into_iter().collect();
+    let session_path: &ObjectPath = &session;
+    let results = portal_request(conn, &bind_token, || {
+        portal.call("BindShortcuts", &(session_path, shortcuts, "", options))
+    })?;
+    debug!("Global shortcuts bound: {:?}", results.get("shortcuts"));
+    Ok(session)
+}
+
+/// Bind the hotkeys, then send an `AppEvent::Hotkey` each time one is pressed.
+///
+/// Only returns on error, or when the connection is closed.
+fn listen(bindings: &BTreeMap<HotkeyAction, String>, events_tx: &Sender<AppEvent>) -> Result<(), HotkeyError> {
+    let conn = Connection::session()?;
+    let portal = Proxy::new(&conn, PORTAL_DESTINATION, PORTAL_PATH, PORTAL_INTERFACE)?;
+    // subscribe before binding, so that no activation is missed
+    let activations = portal.receive_signal("Activated")?;
+    let session = bind(&conn, &portal, bindings)?;
+    info!("Registered {} global hotkeys", bindings.len());
+
+    for msg in activations {
+        let (session_handle, id, _, _): (OwnedObjectPath, String, u64, HashMap<String, OwnedValue>) = msg.body()?;
+        if session_handle != session {
+            continue;
+        }
+        match HotkeyAction::from_id(&id) {
+            Some(action) => {
+                debug!("Global hotkey \"{}\" pressed", id);
+                if events_tx.send(AppEvent::Hotkey(action)).is_err() {
+                    error!("Trying to send Hotkey event, but all receivers have hung up.");
+                    break;
+                }
+            }
+            None => debug!("Ignoring unknown global hotkey \"{}\"", id),
+        }
+    }
+    Ok(())
+}
+
+/// Register the hotkeys in the background, then keep listening for them.
+///
+/// If registration fails, the reason is sent as an `AppEvent::HotkeysFailed` event.
+pub fn spawn_listener(bindings: BTreeMap<HotkeyAction, String>, events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new().name("Hotkey daemon".into()).spawn(move || {
+        if let Err(err) = listen(&bindings, &events_tx) {
+            if events_tx.send(AppEvent::HotkeysFailed(err.to_string())).is_err() {
+                error!("Trying to send HotkeysFailed event, but all receivers have hung up.");
+            }
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! This module defines the application settings, which are read from disk
+//! when the application is starting, and written when changed from the GUI.
+//!
+//! Unlike `AppState`, this file is meant to be edited by the user.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use shadowsocks_gtk_rs::util::{leaky_bucket::NaiveLeakyBucketConfig, ring_buffer::LineRingBufferConfig};
+
+use crate::{
+    gui::notification::NotifyLevel,
+    hotkey::HotkeyAction,
+    io::{app_state::AppState, profile_loader::NameConflictPolicy, systemd_scope::ScopeConfig},
+    network_monitor::NetworkRule,
+    scheduler::ScheduleRule,
+};
+
+#[derive(Debug)]
+pub enum AppSettingsError {
+    ParseError(serde_yaml::Error),
+    IOError(io::Error),
+}
+
+impl fmt::Display for AppSettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AppSettingsError::*;
+        match self {
+            ParseError(e) => write!(f, "AppSettingsError-ParseError: {}", e),
+            IOError(e) => write!(f, "AppSettingsError-IOError: {}", e),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for AppSettingsError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+impl From<io::Error> for AppSettingsError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+/// Stop restarting `sslocal` if it has stopped unexpectedly this many times within some seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestartLimit {
+    pub times: usize,
+    pub within_secs: u64,
+}
+
+impl From<RestartLimit> for NaiveLeakyBucketConfig {
+    fn from(limit: RestartLimit) -> Self {
+        NaiveLeakyBucketConfig::new(limit.times, Duration::from_secs(limit.within_secs))
+    }
+}
+
+/// Toggles for notifications about the connection.
+///
+/// Notifications about other events, e.g. failed imports, are always shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyEvents {
+    /// A profile has been connected to.
+    pub connect: bool,
+    /// `sslocal` has stopped and will not be restarted.
+    pub disconnect: bool,
+    /// `sslocal` has failed and will be restarted.
+    pub restart: bool,
+    /// `sslocal` has output an error, at most once a minute.
+    pub sslocal_errors: bool,
+}
+
+impl Default for NotifyEvents {
+    fn default() -> Self {
+        Self {
+            connect: false,
+            disconnect: true,
+            restart: true,
+            sslocal_errors: false,
+        }
+    }
+}
+
+/// Describes the user's preferences.
+///
+/// Unset fields fall back to what was last used, as remembered in `AppState`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppSettings {
+    /// Keep at most this many lines of `sslocal` output for the log viewer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backlog_lines: Option<usize>,
+    /// Whether to drop the ANSI colors in `sslocal` output, instead of rendering them in the log viewer.
+    #[serde(default)]
+    pub strip_log_colors: bool,
+    /// Notifications less severe than this are only logged.
+    #[serde(default)]
+    pub notify_level: NotifyLevel,
+    /// Which events to notify about, in addition to `notify_level`.
+    #[serde(default)]
+    pub notify_events: NotifyEvents,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_limit: Option<RestartLimit>,
+    /// How many seconds to wait for `sslocal` to exit gracefully when stopping it, before killing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_grace_secs: Option<u64>,
+    /// The `sslocal` binary used by profiles that do not set `bin_path`,
+    /// instead of looking it up in $PATH.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_path: Option<PathBuf>,
+    /// Whether to start with only the tray icon; see `--minimized`.
+    #[serde(default)]
+    pub start_minimized: bool,
+    /// Whether to connect on startup at all.
+    #[serde(default = "default_true")]
+    pub connect_on_startup: bool,
+    /// The profile to connect to on startup, instead of the most recent one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_connect: Option<String>,
+    /// Whether to put servers imported from a file into a directory per region,
+    /// as inferred from their names.
+    #[serde(default)]
+    pub group_imports_by_region: bool,
+    /// How to tell apart profiles that have the same name.
+    #[serde(default)]
+    pub name_conflicts: NameConflictPolicy,
+    /// How many levels of groups to load below the profiles directory.
+    ///
+    /// Only editable in the settings file, and only takes effect on the next launch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_load_depth: Option<usize>,
+    /// Whether to only load the content of a group in the profiles directory when it is opened in the tray menu.
+    ///
+    /// Only editable in the settings file, and only takes effect on the next launch.
+    #[serde(default)]
+    pub lazy_load: bool,
+    /// The preferred trigger of each global hotkey, e.g. `CTRL+ALT+S`.
+    ///
+    /// Only takes effect on the next launch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hotkeys: BTreeMap<HotkeyAction, String>,
+    /// Rules to connect or disconnect at set times.
+    ///
+    /// Only editable in the settings file, and only takes effect on the next launch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduleRule>,
+    /// Whether to restart `sslocal` when the active network changes, unless a network rule applies.
+    #[serde(default = "default_true")]
+    pub restart_on_network_change: bool,
+    /// Rules to connect or disconnect when a network becomes active.
+    ///
+    /// Only editable in the settings file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network_rules: Vec<NetworkRule>,
+    /// Run `sslocal` in a transient systemd scope, optionally with resource limits.
+    ///
+    /// Only editable in the settings file, and only takes effect on the next launch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_scope: Option<ScopeConfig>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            backlog_lines: None,
+            strip_log_colors: false,
+            notify_level: NotifyLevel::default(),
+            notify_events: NotifyEvents::default(),
+            restart_limit: None,
+            stop_grace_secs: None,
+            bin_path: None,
+            start_minimized: false,
+            connect_on_startup: true,
+            auto_connect: None,
+            group_imports_by_region: false,
+            name_conflicts: NameConflictPolicy::default(),
+            max_load_depth: None,
+            lazy_load: false,
+            hotkeys: BTreeMap::new(),
+            schedule: vec![],
+            restart_on_network_change: true,
+            network_rules: vec![],
+            systemd_scope: None,
+        }
+    }
+}
+
+/// Helper function for `serde(default)`.
+fn default_true() -> bool {
+    true
+}
+
+impl AppSettings {
+    /// Like `from_file`, but falls back to the defaults if the file is missing or broken.
+    pub fn from_file_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match Self::from_file(path) {
+            Ok(settings) => settings,
+            Err(AppSettingsError::IOError(err)) if err.kind() == io::ErrorKind::NotFound => {
+                debug!("No app settings file at {:?}; using defaults", path);
+                Self::default()
+            }
+            Err(err) => {
+                warn!("Failed to load app settings: {}", err);
+                Self::default()
+            }
+        }
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AppSettingsError> {
+        let content = fs::read_to_string(path)?;
+        // an empty file is valid, but not a valid YAML document
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let settings = serde_yaml::from_str(&content)?;
+        Ok(settings)
+    }
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), AppSettingsError> {
+        let content = serde_yaml::to_string(self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Override the remembered app state with the settings that are set.
+    pub fn apply_to(&self, state: &mut AppState) {
+        state.backlog_limit = self.backlog_limit(state.backlog_limit);
+        if let Some(limit) = self.restart_limit {
+            state.restart_limit = limit.into();
+        }
+        if let Some(secs) = self.stop_grace_secs {
+            state.stop_grace_period = Duration::from_secs(secs);
+        }
+        if let Some(name) = &self.auto_connect {
+            state.most_recent_profile = name.clone();
+        }
+    }
+
+    /// The backlog limit with these settings applied.
+    pub fn backlog_limit(&self, current: LineRingBufferConfig) -> LineRingBufferConfig {
+        LineRingBufferConfig {
+            max_lines: self.backlog_lines.unwrap_or(current.max_lines),
+            ..current
+        }
+    }
+}
@@ -1,10 +1,25 @@
-//! This module defines the application state, read from and saved to disk
-//! when the application in starting and stopping respectively.
+//! This module defines the application state, read from disk when the application is starting,
+//! and saved to disk whenever it changes and when the application is stopping.
 
 use std::{fmt, fs, io, path::Path, time::Duration};
 
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use shadowsocks_gtk_rs::{notify_method::NotifyMethod, util::leaky_bucket::NaiveLeakyBucketConfig};
+use shadowsocks_gtk_rs::{
+    notify_method::NotifyMethod,
+    util::{
+        backoff::ExponentialBackoffConfig, leaky_bucket::NaiveLeakyBucketConfig, mutex_lock,
+        ring_buffer::LineRingBufferConfig,
+    },
+};
+
+use crate::{
+    health_check::HealthCheckConfig,
+    io::{
+        log_file::LogFileConfig, pac_server::PacServerConfig, profile_loader::ProfileFolder, systemd_scope::ScopeConfig,
+    },
+    profile_manager::{EventSink, ProfileManager, STOP_GRACE_PERIOD_DEFAULT},
+};
 
 #[derive(Debug)]
 pub enum AppStateError {
@@ -33,13 +48,66 @@ impl From<io::Error> for AppStateError {
     }
 }
 
+/// The position and size of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
 /// Describes the state of the application.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppState {
     /// `""` indicates none.
     pub most_recent_profile: String,
     pub restart_limit: NaiveLeakyBucketConfig,
+    #[serde(default)]
+    pub restart_backoff: ExponentialBackoffConfig,
     pub notify_method: NotifyMethod,
+    #[serde(default)]
+    pub collect_stats: bool,
+    #[serde(default)]
+    pub system_proxy: bool,
+    #[serde(default)]
+    pub pac: PacServerConfig,
+    #[serde(default)]
+    pub log_file: LogFileConfig,
+    #[serde(default)]
+    pub backlog_limit: LineRingBufferConfig,
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+    /// Whether to block all traffic except to the current profile's servers while connected.
+    #[serde(default)]
+    pub kill_switch: bool,
+    /// Whether to offer importing `ss://` URLs copied to the clipboard.
+    #[serde(default)]
+    pub clipboard_monitor: bool,
+    /// Whether to keep the system from idling or sleeping while connected.
+    #[serde(default)]
+    pub keep_awake: bool,
+    /// Profiles running alongside the current one, each on its own local port.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alongside_profiles: Vec<String>,
+    /// Only list profiles with this tag in the tray menu.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_filter: Option<String>,
+    /// How long to wait for `sslocal` to exit after asking it to, before killing it.
+    #[serde(default = "default_stop_grace_period")]
+    pub stop_grace_period: Duration,
+    /// Whether the application has quit normally.
+    ///
+    /// This is `false` while it is running, so that an unclean exit can be detected on the next startup.
+    #[serde(default = "default_true")]
+    pub clean_exit: bool,
+    /// Whether the log viewer is open, which is restored after an unclean exit.
+    #[serde(default)]
+    pub log_viewer_open: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_viewer_geometry: Option<WindowGeometry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats_window_geometry: Option<WindowGeometry>,
 }
 
 impl Default for AppState {
@@ -47,20 +115,79 @@ impl Default for AppState {
         Self {
             most_recent_profile: String::new(),
             restart_limit: NaiveLeakyBucketConfig::new(5, Duration::from_secs(30)),
+            restart_backoff: ExponentialBackoffConfig::default(),
             notify_method: NotifyMethod::Toast,
+            collect_stats: false,
+            system_proxy: false,
+            pac: PacServerConfig::default(),
+            log_file: LogFileConfig::default(),
+            backlog_limit: LineRingBufferConfig::default(),
+            health_check: HealthCheckConfig::default(),
+            kill_switch: false,
+            clipboard_monitor: false,
+            keep_awake: false,
+            alongside_profiles: vec![],
+            tag_filter: None,
+            stop_grace_period: default_stop_grace_period(),
+            clean_exit: true,
+            log_viewer_open: false,
+            log_viewer_geometry: None,
+            stats_window_geometry: None,
         }
     }
 }
 
+/// Helper function for `serde(default)`.
+fn default_stop_grace_period() -> Duration {
+    STOP_GRACE_PERIOD_DEFAULT
+}
+
+/// Helper function for `serde(default)`.
+fn default_true() -> bool {
+    true
+}
+
 impl AppState {
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AppStateError> {
         let content = fs::read_to_string(path)?;
         let state = serde_yaml::from_str(&content)?;
         Ok(state)
     }
+    /// Write to a temporary file first, then rename it over `path`,
+    /// so that the saved state is never left half-written by a crash.
     pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), AppStateError> {
+        let path = path.as_ref();
         let content = serde_yaml::to_string(self)?;
-        fs::write(path, content)?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)?;
         Ok(())
     }
+
+    /// Create a `ProfileManager` resuming from this state, running `sslocal` in `scope` if set.
+    pub fn resume_profile_manager(
+        &self,
+        scope: Option<ScopeConfig>,
+        profiles: &ProfileFolder,
+        events_tx: EventSink,
+    ) -> ProfileManager {
+        let mut pm = ProfileManager::new(self.restart_limit, self.restart_backoff, events_tx);
+        pm.scope = scope;
+        pm.collect_stats = self.collect_stats;
+        pm.stop_grace_period = self.stop_grace_period;
+        pm.log_file_config = self.log_file;
+        *mutex_lock(&pm.backlog) = self.backlog_limit.into();
+        match self.most_recent_profile.as_str() {
+            "" => debug!("Most recent profile is none; will not attempt to resume"),
+            name => match profiles.lookup(name) {
+                Some(p) => match pm.switch_to(p.clone()) {
+                    Ok(_) => info!("Successfully resumed with profile \"{}\"", name),
+                    Err(err) => error!("Cannot resume - switch to profile \"{}\" failed: {}", name, err),
+                },
+                None => warn!("Cannot resume - profile \"{}\" not found", name),
+            },
+        };
+        pm
+    }
 }
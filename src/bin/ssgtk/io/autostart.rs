@@ -0,0 +1,104 @@
+//! This module contains code that installs and removes a desktop entry
+//! in the XDG autostart directory, so that `ssgtk` is launched on login.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use shadowsocks_gtk_rs::consts::*;
+
+/// Check whether the autostart entry is installed.
+pub fn is_enabled() -> bool {
+    AUTOSTART_FILE_PATH.is_file()
+}
+
+/// Install the autostart entry, launching this `ssgtk` binary with `args`.
+///
+/// Replaces the existing entry if present.
+pub fn enable(args: &[OsString]) -> io::Result<()> {
+    let exe = env::current_exe()?;
+    let exec = std::iter::once(exe.into_os_string())
+        .chain(args.iter().cloned())
+        .map(|arg| quote_exec_arg(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let content = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Shadowsocks GTK\n\
+         Comment=Start shadowsocks-gtk-rs on login\n\
+         Icon=shadowsocks-gtk-rs\n\
+         Exec={}\n\
+         Terminal=false\n\
+         X-GNOME-Autostart-enabled=true\n",
+        exec
+    );
+    let path: &Path = &AUTOSTART_FILE_PATH;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)?;
+    info!("Autostart entry written to {:?}", path);
+    Ok(())
+}
+
+/// Remove the autostart entry, if present.
+pub fn disable() -> io::Result<()> {
+    let path: &Path = &AUTOSTART_FILE_PATH;
+    match fs::remove_file(path) {
+        Ok(_) => info!("Autostart entry {:?} removed", path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => debug!("Autostart entry {:?} is absent", path),
+        Err(err) => return Err(err),
+    }
+    Ok(())
+}
+
+/// The launch arguments for a path option, unless it has its default value.
+pub fn path_arg(flag: &str, path: &Path, default: &Path) -> Vec<OsString> {
+    if path == default {
+        return vec![];
+    }
+    // the autostarted instance may be launched from a different working directory
+    let path = path.canonicalize().unwrap_or_else(|_| PathBuf::from(path));
+    vec![flag.into(), path.into()]
+}
+
+/// Quote an argument of the `Exec` key if necessary, then escape it as a string value.
+///
+/// See https://specifications.freedesktop.org/desktop-entry-spec/latest/ar01s07.html.
+fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`',
+    ];
+    let escaped = arg.replace('%', "%%");
+    if !escaped.contains(RESERVED) {
+        return escaped;
+    }
+    let mut quoted = String::with_capacity(escaped.len() + 2);
+    quoted.push('"');
+    for c in escaped.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted.replace('\\', "\\\\")
+}
+
+#[cfg(test)]
+mod test {
+    use super::quote_exec_arg;
+
+    #[test]
+    fn exec_arg_quoting() {
+        assert_eq!(quote_exec_arg("/usr/bin/ssgtk"), "/usr/bin/ssgtk");
+        assert_eq!(quote_exec_arg("100%"), "100%%");
+        assert_eq!(quote_exec_arg("/home/me/My Profiles"), r#""/home/me/My Profiles""#);
+        assert_eq!(quote_exec_arg("$HOME"), r#""\\$HOME""#);
+    }
+}
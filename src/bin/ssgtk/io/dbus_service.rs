@@ -0,0 +1,165 @@
+//! This module defines a D-Bus service to interact with the application in runtime,
+//! enabled behind the "dbus-api" feature.
+//!
+//! Unlike the runtime API, this is convenient for scripts and desktop extensions,
+//! which can also subscribe to state changes.
+//!
+//! ```sh
+//! busctl --user call io.github.shadowsocks_gtk_rs /io/github/shadowsocks_gtk_rs \
+//!     io.github.shadowsocks_gtk_rs Connect s "My Profile"
+//! ```
+
+use crossbeam_channel::Sender;
+use log::{debug, error, trace, warn};
+use zbus::{
+    block_on,
+    blocking::{Connection, ConnectionBuilder},
+    dbus_interface, fdo, SignalContext,
+};
+
+use crate::event::AppEvent;
+
+/// The well-known bus name, also used as the interface name.
+pub const DBUS_NAME: &str = "io.github.shadowsocks_gtk_rs";
+
+/// The object path at which the interface is served.
+pub const DBUS_PATH: &str = "/io/github/shadowsocks_gtk_rs";
+
+/// The interface exposed on the session bus.
+#[derive(Debug)]
+struct ControlInterface {
+    events_tx: Sender<AppEvent>,
+    /// The display names of all loaded profiles.
+    profiles: Vec<String>,
+    /// The display name of the running profile, if any.
+    active_profile: Option<String>,
+}
+
+impl ControlInterface {
+    fn send(&self, event: AppEvent) -> fdo::Result<()> {
+        self.events_tx
+            .send(event)
+            .map_err(|_| fdo::Error::Failed("application is shutting down".into()))
+    }
+}
+
+#[dbus_interface(name = "io.github.shadowsocks_gtk_rs")]
+impl ControlInterface {
+    /// Switch to the profile with the specified display name.
+    fn connect(&self, profile_name: String) -> fdo::Result<()> {
+        debug!("D-Bus received Connect(\"{}\")", profile_name);
+        if !self.profiles.contains(&profile_name) {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "no profile is named \"{}\"",
+                profile_name
+            )));
+        }
+        self.send(AppEvent::ConnectByName(profile_name))
+    }
+
+    /// Stop the running profile.
+    fn disconnect(&self) -> fdo::Result<()> {
+        debug!("D-Bus received Disconnect");
+        self.send(AppEvent::Disconnect)
+    }
+
+    /// Restart the running profile.
+    fn restart(&self) -> fdo::Result<()> {
+        debug!("D-Bus received Restart");
+        self.send(AppEvent::Restart)
+    }
+
+    /// The display names of all loaded profiles.
+    fn list_profiles(&self) -> Vec<String> {
+        self.profiles.clone()
+    }
+
+    /// The display name of the running profile, or an empty string if stopped.
+    #[dbus_interface(property)]
+    fn active_profile(&self) -> String {
+        self.active_profile.clone().unwrap_or_default()
+    }
+
+    /// Emitted when a profile is started or stopped.
+    ///
+    /// `profile_name` is empty if stopped.
+    #[dbus_interface(signal)]
+    async fn state_changed(ctxt: &SignalContext<'_>, running: bool, profile_name: &str) -> zbus::Result<()>;
+
+    /// Emitted when the profiles are reloaded.
+    #[dbus_interface(signal)]
+    async fn profiles_changed(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+/// An active connection to the session bus, serving the control interface.
+///
+/// The bus name is released when dropped.
+#[derive(Debug)]
+pub struct DBusService {
+    conn: Connection,
+    /// The running profile as last published, to avoid locking the interface when unchanged.
+    active_profile: Option<String>,
+}
+
+impl DBusService {
+    /// Claim `DBUS_NAME` on the session bus and start serving.
+    pub fn start(events_tx: Sender<AppEvent>, profiles: Vec<String>) -> zbus::Result<Self> {
+        let iface = ControlInterface {
+            events_tx,
+            profiles,
+            active_profile: None,
+        };
+        let conn = ConnectionBuilder::session()?
+            .serve_at(DBUS_PATH, iface)?
+            .name(DBUS_NAME)?
+            .build()?;
+        debug!("D-Bus service started as {}", DBUS_NAME);
+        Ok(Self {
+            conn,
+            active_profile: None,
+        })
+    }
+
+    /// Update the list of profiles, and notify subscribers.
+    pub fn set_profiles(&self, profiles: Vec<String>) {
+        let res = self
+            .conn
+            .object_server()
+            .interface::<_, ControlInterface>(DBUS_PATH)
+            .and_then(|iface| {
+                iface.get_mut().profiles = profiles;
+                block_on(ControlInterface::profiles_changed(iface.signal_context()))
+            });
+        if let Err(err) = res {
+            warn!("Cannot update profiles of D-Bus service: {}", err);
+        }
+    }
+
+    /// Update the running profile, and notify subscribers if it has changed.
+    pub fn set_active_profile(&mut self, profile_name: Option<String>) {
+        if self.active_profile == profile_name {
+            return;
+        }
+        trace!("D-Bus active profile changed to {:?}", profile_name);
+        self.active_profile = profile_name.clone();
+        let res = self
+            .conn
+            .object_server()
+            .interface::<_, ControlInterface>(DBUS_PATH)
+            .and_then(|iface| {
+                let mut inner = iface.get_mut();
+                inner.active_profile = profile_name;
+                let ctxt = iface.signal_context();
+                let name = inner.active_profile.as_deref();
+                block_on(ControlInterface::state_changed(
+                    ctxt,
+                    name.is_some(),
+                    name.unwrap_or_default(),
+                ))?;
+                block_on(inner.active_profile_changed(ctxt))
+            });
+        if let Err(err) = res {
+            error!("Cannot update state of D-Bus service: {}", err);
+        }
+    }
+}
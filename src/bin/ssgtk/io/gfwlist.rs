@@ -0,0 +1,124 @@
+//! This module contains code that keeps the copy of gfwlist in the XDG cache directory fresh in the background.
+//!
+//! See https://github.com/gfwlist/gfwlist.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_channel::{unbounded as unbounded_channel, RecvTimeoutError, Sender};
+use log::{debug, error, info, trace, warn};
+pub use shadowsocks_gtk_rs_core::io::gfwlist::cached_rules;
+use shadowsocks_gtk_rs_core::io::gfwlist::{fetch, is_stale};
+
+use crate::event::AppEvent;
+
+/// How often the updater daemon checks whether the cached gfwlist is due for a refresh.
+const UPDATER_POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A daemon that keeps the cached gfwlist fresh.
+///
+/// Terminates the underlying daemon thread when dropped.
+#[derive(Debug)]
+pub struct GfwlistUpdater {
+    /// Whether anything uses gfwlist, so that it is not fetched periodically for nothing.
+    wanted: Arc<AtomicBool>,
+    /// Send `true` to update regardless of the cache's age, or `false` to update only if it is stale.
+    /// Dropped to halt the daemon.
+    trigger_tx: Option<Sender<bool>>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for GfwlistUpdater {
+    fn drop(&mut self) {
+        trace!("GfwlistUpdater is getting dropped");
+
+        // notify daemon halt
+        drop(self.trigger_tx.take());
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("gfwlist updater daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl GfwlistUpdater {
+    /// Start the updater daemon, which fetches gfwlist from `urls`, trying each in order.
+    ///
+    /// Results are sent as `AppEvent::GfwlistUpdated` events, but failures only if the update is forced.
+    pub fn start(urls: Vec<String>, events_tx: Sender<AppEvent>) -> io::Result<Self> {
+        let wanted = Arc::new(AtomicBool::new(false));
+        let (trigger_tx, trigger_rx) = unbounded_channel();
+
+        let wanted_mv = Arc::clone(&wanted);
+        let handle = thread::Builder::new()
+            .name("gfwlist updater daemon".into())
+            .spawn(move || loop {
+                let forced = match trigger_rx.recv_timeout(UPDATER_POLL_INTERVAL) {
+                    Ok(forced) => forced,
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        trace!("gfwlist updater trigger has hung up; daemon exiting");
+                        break;
+                    }
+                };
+                let is_due = forced || (wanted_mv.load(Ordering::Relaxed) && is_stale());
+                if !is_due {
+                    continue;
+                }
+
+                let result = fetch(&urls);
+                match &result {
+                    Ok(count) => info!("gfwlist updated with {} domains", count),
+                    Err(err) => error!("Failed to update gfwlist: {}", err),
+                }
+                if (forced || result.is_ok())
+                    && events_tx
+                        .send(AppEvent::GfwlistUpdated {
+                            result: result.map_err(|err| err.to_string()),
+                            forced,
+                        })
+                        .is_err()
+                {
+                    error!("Trying to send GfwlistUpdated event, but all receivers have hung up.");
+                }
+            })?;
+
+        Ok(Self {
+            wanted,
+            trigger_tx: Some(trigger_tx),
+            daemon_handle: Some(handle),
+        })
+    }
+
+    /// Set whether anything uses gfwlist, updating it right away if wanted and stale.
+    pub fn set_wanted(&self, wanted: bool) {
+        if self.wanted.swap(wanted, Ordering::Relaxed) != wanted {
+            debug!("gfwlist is now wanted: {}", wanted);
+        }
+        self.trigger(false);
+    }
+
+    /// Request an update regardless of the cache's age.
+    pub fn update_now(&self) {
+        self.trigger(true);
+    }
+
+    fn trigger(&self, forced: bool) {
+        if let Some(tx) = &self.trigger_tx {
+            if tx.send(forced).is_err() {
+                error!("gfwlist updater daemon has exited unexpectedly; cannot update");
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! This module contains a tiny embedded HTTP server that exposes metrics
+//! in the Prometheus text format, e.g. for home-lab monitoring.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{info, trace, warn};
+use shadowsocks_gtk_rs::util;
+
+use crate::{latency::Latency, profile_manager::ProfileManager};
+
+/// How often the server daemon checks for new connections, or whether it should halt.
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for a client to send its request.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The path at which metrics are served.
+const METRICS_PATH: &str = "/metrics";
+
+/// A running metrics server. Stops when dropped.
+#[derive(Debug)]
+pub struct MetricsServer {
+    /// The result of the latest latency test of each profile; `None` if unreachable.
+    latencies: Arc<RwLock<BTreeMap<String, Option<Duration>>>>,
+    halt: Arc<AtomicBool>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        trace!("MetricsServer is getting dropped");
+
+        // notify daemon halt
+        self.halt.store(true, Ordering::Relaxed);
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Metrics server daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl MetricsServer {
+    /// Start serving the metrics of the instances run by `profile_manager` at `addr`.
+    pub fn start(addr: SocketAddr, profile_manager: Arc<RwLock<ProfileManager>>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        info!("Serving metrics at http://{}{}", addr, METRICS_PATH);
+
+        let latencies = Arc::new(RwLock::new(BTreeMap::new()));
+        let halt = Arc::new(AtomicBool::new(false));
+
+        let latencies_mv = Arc::clone(&latencies);
+        let halt_mv = Arc::clone(&halt);
+        let handle = thread::Builder::new()
+            .name("Metrics server daemon".into())
+            .spawn(move || {
+                while !halt_mv.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, peer)) => {
+                            trace!("Metrics request from {}", peer);
+                            let snapshot = || Snapshot::take(&profile_manager, &latencies_mv);
+                            if let Err(err) = serve(stream, snapshot) {
+                                warn!("Error serving metrics request from {}: {}", peer, err);
+                            }
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(SERVER_POLL_INTERVAL),
+                        Err(err) => {
+                            warn!("Error accepting metrics request: {}", err);
+                            thread::sleep(SERVER_POLL_INTERVAL);
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            latencies,
+            halt,
+            daemon_handle: Some(handle),
+        })
+    }
+
+    /// Replace the latency test results, after a new test has finished.
+    pub fn set_latencies(&self, latencies: &HashMap<String, Latency>) {
+        *util::rwlock_write(&self.latencies) = latencies
+            .iter()
+            .map(|(name, latency)| (name.clone(), latency.as_ref().ok().copied()))
+            .collect();
+    }
+}
+
+/// The values of all metrics at the time of a request.
+#[derive(Debug, Clone, Default)]
+struct Snapshot {
+    /// The display name of the current profile, if any.
+    profile: Option<String>,
+    running: bool,
+    uptime: Option<Duration>,
+    connected_for: Option<Duration>,
+    restarts: Option<u32>,
+    connections: Option<usize>,
+    /// Bytes sent and received, if being collected.
+    traffic: Option<(u64, u64)>,
+    latencies: BTreeMap<String, Option<Duration>>,
+}
+
+impl Snapshot {
+    fn take(profile_manager: &RwLock<ProfileManager>, latencies: &RwLock<BTreeMap<String, Option<Duration>>>) -> Self {
+        let pm = util::rwlock_read(profile_manager);
+        let instance = pm.instance_status();
+        let traffic = pm.collect_stats.then(|| {
+            let stats = util::rwlock_read(&pm.stats);
+            (stats.tx_bytes, stats.rx_bytes)
+        });
+        Self {
+            profile: pm.current_profile().map(|p| p.metadata.display_name),
+            running: pm.is_active(),
+            uptime: instance.as_ref().map(|i| i.uptime),
+            connected_for: instance.as_ref().map(|i| i.connected_for),
+            restarts: instance.as_ref().map(|i| i.restarts),
+            connections: instance.and_then(|i| i.connections),
+            traffic,
+            latencies: util::rwlock_read(latencies).clone(),
+        }
+    }
+
+    /// Render in the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(Option<&str>, f64)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (profile, value) in samples {
+                match profile {
+                    Some(profile) => {
+                        let _ = writeln!(out, "{}{{profile=\"{}\"}} {}", name, escape_label(profile), value);
+                    }
+                    None => {
+                        let _ = writeln!(out, "{} {}", name, value);
+                    }
+                }
+            }
+        };
+
+        let profile = self.profile.as_deref();
+        let running = if self.running { 1.0 } else { 0.0 };
+        metric(
+            "ssgtk_connected",
+            "gauge",
+            "Whether sslocal is running with the current profile.",
+            &[(profile, running)],
+        );
+        let current = |value: Option<f64>| value.map(|v| vec![(profile, v)]).unwrap_or_default();
+        metric(
+            "ssgtk_uptime_seconds",
+            "gauge",
+            "Seconds since sslocal has started, which is reset on automatic restarts.",
+            &current(self.uptime.map(|d| d.as_secs_f64())),
+        );
+        metric(
+            "ssgtk_connected_seconds",
+            "gauge",
+            "Seconds since the current profile has been connected to.",
+            &current(self.connected_for.map(|d| d.as_secs_f64())),
+        );
+        metric(
+            "ssgtk_restarts_total",
+            "counter",
+            "How many times the current profile has been restarted automatically.",
+            &current(self.restarts.map(f64::from)),
+        );
+        metric(
+            "ssgtk_open_connections",
+            "gauge",
+            "How many connections are open, if known.",
+            &current(self.connections.map(|n| n as f64)),
+        );
+        metric(
+            "ssgtk_sent_bytes_total",
+            "counter",
+            "Bytes sent by sslocal since it has started, if traffic stats are collected.",
+            &current(self.traffic.map(|(tx, _)| tx as f64)),
+        );
+        metric(
+            "ssgtk_received_bytes_total",
+            "counter",
+            "Bytes received by sslocal since it has started, if traffic stats are collected.",
+            &current(self.traffic.map(|(_, rx)| rx as f64)),
+        );
+
+        let latencies: Vec<_> = self
+            .latencies
+            .iter()
+            .filter_map(|(name, l)| l.map(|d| (Some(name.as_str()), d.as_secs_f64())))
+            .collect();
+        metric(
+            "ssgtk_latency_seconds",
+            "gauge",
+            "The result of the latest latency test of each reachable profile.",
+            &latencies,
+        );
+        let reachable: Vec<_> = self
+            .latencies
+            .iter()
+            .map(|(name, l)| (Some(name.as_str()), if l.is_some() { 1.0 } else { 0.0 }))
+            .collect();
+        metric(
+            "ssgtk_latency_reachable",
+            "gauge",
+            "Whether each profile was reachable in the latest latency test.",
+            &reachable,
+        );
+
+        out
+    }
+}
+
+/// Escape a label value, as required by the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+/// Respond to a single HTTP request.
+///
+/// The metrics are served for `GET /metrics`; anything else is 404.
+fn serve(mut stream: TcpStream, snapshot: impl FnOnce() -> Snapshot) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let is_metrics = parts.next() == Some("GET") && parts.next() == Some(METRICS_PATH);
+    let response = if is_metrics {
+        let body = snapshot().render();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into()
+    };
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let snapshot = Snapshot {
+            profile: Some("Say \"hi\"".into()),
+            running: true,
+            uptime: Some(Duration::from_secs(60)),
+            restarts: Some(2),
+            latencies: [
+                ("A".to_string(), Some(Duration::from_millis(150))),
+                ("B".to_string(), None),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let out = snapshot.render();
+        assert!(out.contains("# TYPE ssgtk_restarts_total counter\n"));
+        assert!(out.contains("ssgtk_connected{profile=\"Say \\\"hi\\\"\"} 1\n"));
+        assert!(out.contains("ssgtk_uptime_seconds{profile=\"Say \\\"hi\\\"\"} 60\n"));
+        assert!(out.contains("ssgtk_latency_seconds{profile=\"A\"} 0.15\n"));
+        assert!(!out.contains("ssgtk_latency_seconds{profile=\"B\"}"));
+        assert!(out.contains("ssgtk_latency_reachable{profile=\"B\"} 0\n"));
+        // no samples without traffic stats
+        assert!(!out.contains("ssgtk_sent_bytes_total{"));
+    }
+}
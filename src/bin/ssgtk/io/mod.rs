@@ -1,9 +1,30 @@
 //! This module contains code relating to IO.
 
 // public members
+pub mod app_settings;
 pub mod app_state;
-pub mod profile_loader;
+pub mod autostart;
+#[cfg(feature = "dbus-api")]
+pub mod dbus_service;
+pub mod gfwlist;
+pub mod metrics_server;
+pub mod pac_server;
+pub mod profile_import;
+pub mod profile_watcher;
 #[cfg(feature = "runtime-api")]
-pub mod runtime_api;
+pub mod single_instance;
+pub mod subscription;
+pub mod system_proxy;
+#[cfg(feature = "runtime-api")]
+pub mod user_service;
+
+// members of the core crate
+#[cfg(feature = "encryption")]
+pub use shadowsocks_gtk_rs_core::io::encryption;
+#[cfg(feature = "runtime-api")]
+pub use shadowsocks_gtk_rs_core::io::runtime_api;
+pub use shadowsocks_gtk_rs_core::io::{
+    ignore_file, keyring, legacy_profile, log_file, privileged, profile_loader, sslocal_pids, systemd_scope,
+};
 
 // private members with re-export
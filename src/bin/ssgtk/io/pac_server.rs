@@ -0,0 +1,211 @@
+//! This module contains a tiny embedded HTTP server that serves a PAC file
+//! pointing at the active profile.
+//!
+//! The rules are generated from the cached gfwlist (see `gfwlist`), and from an optional user rule file.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+use shadowsocks_gtk_rs::{consts::*, util};
+
+use crate::{io::gfwlist, pac::PacRules};
+
+/// How often the server daemon checks for new connections, or whether it should halt.
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for a client to send its request.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// User-configurable settings of the PAC server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacServerConfig {
+    /// Whether to run the PAC server.
+    pub enabled: bool,
+    /// The port to listen on. The server only listens on localhost.
+    pub port: u16,
+    /// Where to fetch gfwlist from; `None` disables gfwlist.
+    pub gfwlist_url: Option<String>,
+    /// Where else to fetch gfwlist from, in order, if `gfwlist_url` fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gfwlist_mirrors: Vec<String>,
+    /// An optional file with extra rules, in the same syntax as gfwlist (but not base64-encoded).
+    pub user_rules_path: Option<PathBuf>,
+    /// Whether the system proxy should use the PAC file instead of the SOCKS proxy directly.
+    pub use_for_system_proxy: bool,
+}
+
+impl Default for PacServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 1089,
+            gfwlist_url: Some("https://raw.githubusercontent.com/gfwlist/gfwlist/master/gfwlist.txt".into()),
+            gfwlist_mirrors: vec![],
+            user_rules_path: None,
+            use_for_system_proxy: false,
+        }
+    }
+}
+
+impl PacServerConfig {
+    /// The URL at which the PAC file is served.
+    pub fn url(&self) -> String {
+        format!("http://{}:{}/{}", Ipv4Addr::LOCALHOST, self.port, PAC_FILE_NAME)
+    }
+
+    /// Load all rules from the cached gfwlist and the user rule file.
+    ///
+    /// Missing or broken sources are skipped with a warning.
+    fn load_rules(&self) -> PacRules {
+        let mut rules = PacRules::default();
+        if let Some(r) = self.gfwlist_url.as_ref().and_then(|_| gfwlist::cached_rules()) {
+            rules.extend(r);
+        }
+        if let Some(path) = &self.user_rules_path {
+            match fs::read_to_string(path) {
+                Ok(s) => rules.extend(PacRules::parse(&s)),
+                Err(err) => warn!("Cannot read PAC user rules from {:?}: {}", path, err),
+            }
+        }
+        rules
+    }
+
+    /// The URLs to fetch gfwlist from, in order; empty if gfwlist is disabled.
+    pub fn gfwlist_urls(&self) -> Vec<String> {
+        match &self.gfwlist_url {
+            Some(url) => [url.clone()].into_iter().chain(self.gfwlist_mirrors.clone()).collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// State shared between the server handle and its daemons.
+#[derive(Debug, Default)]
+struct PacState {
+    rules: PacRules,
+    /// The SOCKS5 proxy to direct matching requests to; `None` if inactive.
+    proxy: Option<SocketAddr>,
+}
+
+/// A running PAC server. Stops when dropped.
+#[derive(Debug)]
+pub struct PacServer {
+    config: PacServerConfig,
+    state: Arc<RwLock<PacState>>,
+    halt: Arc<AtomicBool>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for PacServer {
+    fn drop(&mut self) {
+        trace!("PacServer is getting dropped");
+
+        // notify daemon halt
+        self.halt.store(true, Ordering::Relaxed);
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("PAC server daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl PacServer {
+    /// Start serving with the specified config.
+    pub fn start(config: PacServerConfig, proxy: Option<SocketAddr>) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, config.port))?;
+        listener.set_nonblocking(true)?;
+        info!("Serving PAC file at {}", config.url());
+
+        let state = Arc::new(RwLock::new(PacState {
+            rules: config.load_rules(),
+            proxy,
+        }));
+        let halt = Arc::new(AtomicBool::new(false));
+
+        let state_mv = Arc::clone(&state);
+        let halt_mv = Arc::clone(&halt);
+        let handle = thread::Builder::new().name("PAC server daemon".into()).spawn(move || {
+            while !halt_mv.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        trace!("PAC request from {}", peer);
+                        if let Err(err) = serve(stream, &state_mv) {
+                            warn!("Error serving PAC request from {}: {}", peer, err);
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(SERVER_POLL_INTERVAL),
+                    Err(err) => {
+                        warn!("Error accepting PAC request: {}", err);
+                        thread::sleep(SERVER_POLL_INTERVAL);
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            config,
+            state,
+            halt,
+            daemon_handle: Some(handle),
+        })
+    }
+
+    /// The config this server is started with.
+    pub fn config(&self) -> &PacServerConfig {
+        &self.config
+    }
+
+    /// Reload the rules, e.g. after gfwlist has been updated.
+    pub fn reload_rules(&self) {
+        util::rwlock_write(&self.state).rules = self.config.load_rules();
+    }
+
+    /// Change the proxy that matching requests are directed to.
+    pub fn set_proxy(&self, proxy: Option<SocketAddr>) {
+        util::rwlock_write(&self.state).proxy = proxy;
+    }
+}
+
+/// Respond to a single HTTP request.
+///
+/// The PAC file is served for `GET /<PAC_FILE_NAME>`; anything else is 404.
+fn serve(mut stream: TcpStream, state: &RwLock<PacState>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let is_pac = parts.next() == Some("GET") && parts.next() == Some(&format!("/{}", PAC_FILE_NAME));
+    let response = if is_pac {
+        let body = {
+            let state = util::rwlock_read(state);
+            state.rules.to_pac(state.proxy)
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/x-ns-proxy-autoconfig\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into()
+    };
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
@@ -0,0 +1,425 @@
+//! This module contains code that imports profiles from files exported by other clients.
+//!
+//! Currently supported are the JSON exports of shadowsocks-android, and Outline's
+//! dynamic access keys, both of which share the server fields of SIP008;
+//! as well as the shadowsocks proxies in Clash configs.
+//! `sslocal` configs are read as the former, since they share the same server fields.
+//!
+//! It also works out what files and text dropped onto the app can be imported as.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use log::warn;
+use serde::Deserialize;
+
+use crate::io::{
+    profile_loader::{ConfigFormat, ProfileConfig},
+    subscription::Sip008Server,
+};
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file is not in any supported format.
+    ParseError(serde_json::Error),
+    /// The file is not a valid Clash config.
+    ClashParseError(serde_yaml::Error),
+    /// The file is in a supported format, but contains no servers.
+    NoServers,
+    /// The directory is not a profile directory.
+    NotProfileDir,
+    /// The filesystem encountered an IOError.
+    IOError(io::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ImportError::*;
+
+        let prefix = "ImportError";
+        match self {
+            ParseError(e) => write!(f, "{}-ParseError: {}", prefix, e),
+            ClashParseError(e) => write!(f, "{}-ClashParseError: {}", prefix, e),
+            NoServers => write!(f, "{}-NoServers: the file contains no servers", prefix),
+            NotProfileDir => write!(f, "{}-NotProfileDir: the directory contains no profile", prefix),
+            IOError(e) => write!(f, "{}-IOError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::ParseError(err)
+    }
+}
+impl From<serde_yaml::Error> for ImportError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::ClashParseError(err)
+    }
+}
+impl From<io::Error> for ImportError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+/// The JSON documents exported by other clients. Fields irrelevant to us are ignored.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    /// shadowsocks-android exports a list of servers.
+    List(Vec<Sip008Server>),
+    /// A SIP008 document, which shadowsocks-android can also export.
+    Document { servers: Vec<Sip008Server> },
+    /// An Outline dynamic access key contains a single server.
+    Single(Sip008Server),
+}
+
+/// A Clash config. Fields irrelevant to us are ignored.
+#[derive(Debug, Clone, Deserialize)]
+struct ClashConfig {
+    #[serde(default)]
+    proxies: Vec<serde_yaml::Value>,
+}
+
+/// A proxy of type `ss` in a Clash config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ClashSsProxy {
+    name: String,
+    server: String,
+    port: u16,
+    cipher: String,
+    password: String,
+    #[serde(default)]
+    plugin: Option<String>,
+    #[serde(default)]
+    plugin_opts: HashMap<String, serde_yaml::Value>,
+}
+
+impl ClashSsProxy {
+    /// Convert to a SIP008 server, translating the plugin into its SIP003 form.
+    ///
+    /// Returns the reason of failure if the plugin is unsupported.
+    fn to_sip008_server(&self) -> Result<Sip008Server, String> {
+        let opt = |key: &str| self.plugin_opts.get(key);
+        let opt_str = |key: &str| opt(key).and_then(|v| v.as_str());
+        let (plugin, plugin_opts) = match self.plugin.as_deref() {
+            None | Some("") => (None, None),
+            Some("obfs") => {
+                let opts = [
+                    opt_str("mode").map(|m| format!("obfs={}", m)),
+                    opt_str("host").map(|h| format!("obfs-host={}", h)),
+                ];
+                (Some("obfs-local"), Some(opts.into_iter().flatten().join(";")))
+            }
+            Some("v2ray-plugin") => {
+                let opts = [
+                    opt_str("mode")
+                        .filter(|m| *m != "websocket")
+                        .map(|m| format!("mode={}", m)),
+                    opt("tls")
+                        .and_then(|v| v.as_bool())
+                        .filter(|tls| *tls)
+                        .map(|_| "tls".into()),
+                    opt_str("host").map(|h| format!("host={}", h)),
+                    opt_str("path").map(|p| format!("path={}", p)),
+                ];
+                (Some("v2ray-plugin"), Some(opts.into_iter().flatten().join(";")))
+            }
+            Some(other) => return Err(format!("unsupported plugin \"{}\"", other)),
+        };
+        Ok(Sip008Server {
+            remarks: Some(self.name.clone()),
+            server: self.server.clone(),
+            server_port: self.port,
+            password: self.password.clone(),
+            method: self.cipher.clone(),
+            plugin: plugin.map(Into::into),
+            plugin_opts: plugin_opts.filter(|o| !o.is_empty()),
+        })
+    }
+}
+
+/// Parse the servers in an exported JSON document as proxy-mode profile configs.
+pub fn parse_json_export(content: &str) -> Result<Vec<ProfileConfig>, ImportError> {
+    let servers = match serde_json::from_str(content)? {
+        JsonExport::List(servers) | JsonExport::Document { servers } => servers,
+        JsonExport::Single(server) => vec![server],
+    };
+    to_profile_configs(servers)
+}
+
+/// Extract the shadowsocks proxies in a Clash config as SIP008 servers.
+///
+/// Proxies of other types, or that cannot be converted, are skipped with a warning.
+pub fn parse_clash_config(content: &str) -> Result<Vec<Sip008Server>, serde_yaml::Error> {
+    let config: ClashConfig = serde_yaml::from_str(content)?;
+    let servers = config
+        .proxies
+        .into_iter()
+        .filter(|proxy| proxy.get("type").and_then(|t| t.as_str()) == Some("ss"))
+        .filter_map(|proxy| {
+            let converted = serde_yaml::from_value::<ClashSsProxy>(proxy.clone())
+                .map_err(|err| err.to_string())
+                .and_then(|p| p.to_sip008_server());
+            match converted {
+                Ok(server) => Some(server),
+                Err(err) => {
+                    let name = proxy.get("name").and_then(|n| n.as_str()).unwrap_or("<unnamed>");
+                    warn!("Skipped Clash proxy \"{}\": {}", name, err);
+                    None
+                }
+            }
+        })
+        .collect();
+    Ok(servers)
+}
+
+fn to_profile_configs(servers: Vec<Sip008Server>) -> Result<Vec<ProfileConfig>, ImportError> {
+    if servers.is_empty() {
+        return Err(ImportError::NoServers);
+    }
+    Ok(servers.iter().map(Sip008Server::to_profile_config).collect())
+}
+
+/// Read an exported file, and parse its servers as proxy-mode profile configs.
+///
+/// Files with a `.yaml` or `.yml` extension are read as Clash configs, and others as JSON.
+pub fn import_file(path: impl AsRef<Path>) -> Result<Vec<ProfileConfig>, ImportError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => to_profile_configs(parse_clash_config(&content)?),
+        _ => parse_json_export(&content),
+    }
+}
+
+/// Find all valid `ss://` URLs in `text`, in order and without duplicates.
+///
+/// URLs may be surrounded by whitespace, quotes or brackets, e.g. when copied from a chat or a config file.
+pub fn find_ss_urls(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || "\"'`<>()[]{},;".contains(c))
+        .filter(|word| word.starts_with("ss://"))
+        .filter(|word| ProfileConfig::from_ss_url(word).is_ok())
+        .unique()
+        .map(String::from)
+        .collect()
+}
+
+/// What a file or directory dropped onto the app can be imported as.
+#[derive(Debug, Clone)]
+pub enum Dropped {
+    /// A profile directory, given either directly or by its profile config file.
+    ProfileDir(PathBuf),
+    /// A file with servers that `import_file` can read, and how many.
+    Servers(PathBuf, usize),
+    /// The `ss://` URLs found in any other file.
+    Urls(Vec<String>),
+}
+
+/// Work out what a dropped file or directory can be imported as.
+pub fn inspect_dropped(path: impl AsRef<Path>) -> Result<Dropped, ImportError> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return match ConfigFormat::find_all(path).is_empty() {
+            true => Err(ImportError::NotProfileDir),
+            false => Ok(Dropped::ProfileDir(path.to_path_buf())),
+        };
+    }
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let is_profile_config = ConfigFormat::ALL
+        .into_iter()
+        .any(|f| file_name == f.file_name() || file_name == f.encrypted_file_name());
+    match (is_profile_config, path.parent()) {
+        (true, Some(dir)) => return Ok(Dropped::ProfileDir(dir.to_path_buf())),
+        (true, None) => return Err(ImportError::NotProfileDir),
+        (false, _) => {}
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json" | "yaml" | "yml") => Ok(Dropped::Servers(path.to_path_buf(), import_file(path)?.len())),
+        _ => match find_ss_urls(&fs::read_to_string(path)?)[..] {
+            [] => Err(ImportError::NoServers),
+            ref urls => Ok(Dropped::Urls(urls.to_vec())),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::{find_ss_urls, inspect_dropped, parse_clash_config, parse_json_export, Dropped};
+
+    #[test]
+    fn android_export() {
+        let content = r#"[
+            {
+                "server": "198.51.100.1",
+                "server_port": 8388,
+                "password": "foobar",
+                "method": "aes-256-gcm",
+                "remarks": "Foo",
+                "route": "all",
+                "remote_dns": "dns.google",
+                "ipv6": true,
+                "metered": false,
+                "proxy_apps": {"enabled": false, "bypass": false, "android_list": []},
+                "udpdns": false
+            },
+            {
+                "server": "198.51.100.2",
+                "server_port": 8389,
+                "password": "foobar",
+                "method": "chacha20-ietf-poly1305",
+                "plugin": "obfs-local",
+                "plugin_opts": "obfs=http",
+                "remarks": ""
+            }
+        ]"#;
+        let configs = parse_json_export(content).unwrap();
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].display_name(), Some("Foo"));
+        assert_eq!(configs[1].display_name(), None);
+        assert_eq!(configs[1].server_addr(), Some(("198.51.100.2", 8389)));
+    }
+    #[test]
+    fn outline_access_key() {
+        let content = r#"{
+            "server": "198.51.100.1",
+            "server_port": 443,
+            "password": "foobar",
+            "method": "chacha20-ietf-poly1305"
+        }"#;
+        let configs = parse_json_export(content).unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].server_addr(), Some(("198.51.100.1", 443)));
+    }
+    #[test]
+    fn sslocal_config() {
+        let content = r#"{
+            "local_address": "127.0.0.1",
+            "local_port": 1080,
+            "servers": [
+                {"address": "198.51.100.1", "server_port": 8388, "password": "foobar", "method": "aes-256-gcm"},
+                {"server": "198.51.100.2", "server_port": 8389, "password": "foobar", "method": "aes-256-gcm"}
+            ]
+        }"#;
+        let configs = parse_json_export(content).unwrap();
+        assert_eq!(configs[0].server_addr(), Some(("198.51.100.1", 8388)));
+        assert_eq!(configs[1].server_addr(), Some(("198.51.100.2", 8389)));
+    }
+    #[test]
+    fn empty_export() {
+        assert!(parse_json_export("[]").is_err());
+        assert!(parse_json_export(r#"{"version": 1}"#).is_err());
+    }
+    #[test]
+    fn clash_config() {
+        let content = r#"
+port: 7890
+proxies:
+  - name: "ss1"
+    type: ss
+    server: 198.51.100.1
+    port: 8388
+    cipher: aes-256-gcm
+    password: "foobar"
+    udp: true
+  - name: "ss2"
+    type: ss
+    server: 198.51.100.2
+    port: 443
+    cipher: chacha20-ietf-poly1305
+    password: "foobar"
+    plugin: obfs
+    plugin-opts:
+      mode: tls
+      host: bing.com
+  - name: "ss3"
+    type: ss
+    server: 198.51.100.3
+    port: 443
+    cipher: chacha20-ietf-poly1305
+    password: "foobar"
+    plugin: v2ray-plugin
+    plugin-opts:
+      mode: websocket
+      tls: true
+      host: example.org
+      path: /ws
+  - name: "ss4"
+    type: ss
+    server: 198.51.100.4
+    port: 443
+    cipher: chacha20-ietf-poly1305
+    password: "foobar"
+    plugin: shadow-tls
+  - name: "vmess1"
+    type: vmess
+    server: 198.51.100.5
+    port: 443
+    uuid: 00000000-0000-0000-0000-000000000000
+proxy-groups: []
+"#;
+        let servers = parse_clash_config(content).unwrap();
+        let names: Vec<_> = servers.iter().map(|s| s.remarks.as_deref().unwrap()).collect();
+        assert_eq!(names, vec!["ss1", "ss2", "ss3"]);
+        assert_eq!(servers[0].plugin, None);
+        assert_eq!(servers[1].plugin.as_deref(), Some("obfs-local"));
+        assert_eq!(servers[1].plugin_opts.as_deref(), Some("obfs=tls;obfs-host=bing.com"));
+        assert_eq!(servers[2].plugin.as_deref(), Some("v2ray-plugin"));
+        assert_eq!(servers[2].plugin_opts.as_deref(), Some("tls;host=example.org;path=/ws"));
+    }
+    #[test]
+    fn find_urls_in_text() {
+        let url_1 = "ss://YWVzLTEyOC1nY206dGVzdA@192.168.100.1:8888#Example1";
+        let url_2 = "ss://cmM0LW1kNTpwYXNzd2Q@192.168.100.1:8888/?plugin=obfs-local%3Bobfs%3Dhttp#Example2";
+        let text = format!(
+            "Try this: \"{}\"\nor ({}), again {}; not ss://garbage or https://example.com",
+            url_1, url_2, url_1
+        );
+        assert_eq!(find_ss_urls(&text), vec![url_1, url_2]);
+        assert!(find_ss_urls("nothing to see here").is_empty());
+    }
+    #[test]
+    fn inspect_dropped_kinds() {
+        let root = env::temp_dir().join(format!("ssgtk-dropped-test-{}", process::id()));
+        let profile_dir = root.join("Profile");
+        fs::create_dir_all(&profile_dir).unwrap();
+        fs::write(
+            profile_dir.join("profile.yaml"),
+            "mode: config-file\nconfig_path: ss.json\n",
+        )
+        .unwrap();
+        let export = root.join("export.json");
+        fs::write(
+            &export,
+            r#"{"server": "198.51.100.1", "server_port": 443, "password": "foobar", "method": "aes-256-gcm"}"#,
+        )
+        .unwrap();
+        let note = root.join("note.txt");
+        fs::write(&note, "ss://YWVzLTEyOC1nY206dGVzdA@192.168.100.1:8888#Example1").unwrap();
+        let empty_dir = root.join("Empty");
+        fs::create_dir_all(&empty_dir).unwrap();
+
+        let res = [
+            inspect_dropped(&profile_dir),
+            inspect_dropped(profile_dir.join("profile.yaml")),
+            inspect_dropped(&export),
+            inspect_dropped(&note),
+            inspect_dropped(&empty_dir),
+        ];
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(matches!(&res[0], Ok(Dropped::ProfileDir(dir)) if *dir == profile_dir));
+        assert!(matches!(&res[1], Ok(Dropped::ProfileDir(dir)) if *dir == profile_dir));
+        assert!(matches!(&res[2], Ok(Dropped::Servers(path, 1)) if *path == export));
+        assert!(matches!(&res[3], Ok(Dropped::Urls(urls)) if urls.len() == 1));
+        assert!(res[4].is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! This module contains code that watches the profiles directory for changes,
+//! so that profiles can be reloaded without restarting the application.
+
+use std::{
+    path::Path,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_channel::{unbounded as unbounded_channel, RecvTimeoutError, Sender};
+use log::{debug, error, trace, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use shadowsocks_gtk_rs::consts::*;
+
+use crate::{event::AppEvent, io::ignore_file};
+
+/// How long the directory has to stay quiet before a reload is requested.
+///
+/// Editors and file managers often emit a burst of events for a single save,
+/// so this prevents reloading more than once.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the profiles directory recursively, and sends `AppEvent::ProfilesChanged`
+/// whenever its content changes.
+#[derive(Debug)]
+pub struct ProfileWatcher {
+    /// Owns the sender of the raw event channel.
+    /// Dropped to halt the daemon.
+    watcher: Option<RecommendedWatcher>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for ProfileWatcher {
+    fn drop(&mut self) {
+        trace!("ProfileWatcher is getting dropped");
+
+        // notify daemon halt
+        drop(self.watcher.take());
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Profile watcher daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl ProfileWatcher {
+    /// Start watching `profiles_dir`.
+    pub fn start(profiles_dir: impl AsRef<Path>, events_tx: Sender<AppEvent>) -> notify::Result<Self> {
+        let profiles_dir = profiles_dir.as_ref();
+        let (raw_tx, raw_rx) = unbounded_channel::<notify::Result<Event>>();
+
+        let mut watcher = notify::recommended_watcher(raw_tx)?;
+        watcher.watch(profiles_dir, RecursiveMode::Recursive)?;
+        debug!("Watching {:?} for profile changes", profiles_dir);
+        let root = profiles_dir.to_path_buf();
+
+        let handle = thread::Builder::new()
+            .name("Profile watcher daemon".into())
+            .spawn(move || loop {
+                // wait for the first relevant event
+                match raw_rx.recv() {
+                    Ok(Ok(event)) if is_relevant(&root, &event) => trace!("Profile change detected: {:?}", event),
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(err)) => {
+                        warn!("Error watching profiles directory: {}", err);
+                        continue;
+                    }
+                    Err(_) => {
+                        trace!("Profile watcher has hung up; daemon exiting");
+                        break;
+                    }
+                }
+
+                // then wait for the burst to settle
+                loop {
+                    match raw_rx.recv_timeout(DEBOUNCE_INTERVAL) {
+                        Ok(_) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                if events_tx.send(AppEvent::ProfilesChanged).is_err() {
+                    error!("Trying to send ProfilesChanged event, but all receivers have hung up.");
+                }
+            })
+            .map_err(notify::Error::io)?;
+
+        Ok(Self {
+            watcher: Some(watcher),
+            daemon_handle: Some(handle),
+        })
+    }
+}
+
+/// Whether a filesystem event may affect the loaded profiles.
+///
+/// Access events are ignored, since loading profiles generates them.
+/// So are events on hidden files (e.g. editor swap files), except for the ignore file
+/// and the failover marker, and events on files ignored by the ignore files under `root`.
+fn is_relevant(root: &Path, event: &Event) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    event.paths.iter().any(|path| match path.file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            match name.starts_with('.') {
+                true => name == PROFILE_IGNORE_FILE_NAME || name == PROFILE_FAILOVER_FILE_NAME,
+                false => !ignore_file::is_ignored(root, path),
+            }
+        }
+        None => true,
+    })
+}
@@ -0,0 +1,131 @@
+//! This module contains code that detects an already running instance of the app
+//! via its runtime API socket, and forwards the invocation to it instead of starting another.
+//!
+//! It also lets the GUI talk to `ssgtk --service` when attached to it as a frontend.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+use log::{debug, info};
+use serde_json::Value;
+use shadowsocks_gtk_rs::runtime_api_msg::{jsonrpc, APICommand, APIStatus};
+
+/// The command that forwards an invocation with these arguments:
+/// connect to the profile given by `--connect`, or else show the log viewer,
+/// unless started with `--minimized` (e.g. by autostart), in which case nothing happens.
+pub fn forwarded_command(connect: Option<&str>, minimized: bool) -> APICommand {
+    match (connect, minimized) {
+        (Some(selector), _) => APICommand::Connect(selector.into()),
+        (None, false) => APICommand::LogViewerShow,
+        // a no-op, which is only sent to complete the request
+        (None, true) => APICommand::GetStatus,
+    }
+}
+
+/// If another instance is listening on the runtime API socket, send it `cmd`.
+///
+/// Returns `None` if there is no other instance, in which case this one should start.
+pub fn forward_to_running(socket_path: impl AsRef<Path>, cmd: &APICommand) -> Option<io::Result<()>> {
+    // a socket left behind by a crashed instance refuses connections
+    let stream = match UnixStream::connect(socket_path) {
+        Ok(s) => s,
+        Err(err) => {
+            debug!("No running instance found: {}", err);
+            return None;
+        }
+    };
+    info!("Another instance is already running; forwarding to it: {}", cmd);
+    Some(send(stream, cmd).map(drop))
+}
+
+/// Send `cmd` to the instance listening on `socket_path`, and return the result.
+pub fn request(socket_path: impl AsRef<Path>, cmd: &APICommand) -> io::Result<Value> {
+    send(UnixStream::connect(socket_path)?, cmd)
+}
+
+/// Subscribe to changes of state of the instance listening on `socket_path`,
+/// returning its status and the stream of `APIStateEvent` notifications that follow.
+pub fn subscribe(socket_path: impl AsRef<Path>) -> io::Result<(APIStatus, BufReader<UnixStream>)> {
+    let stream = UnixStream::connect(socket_path)?;
+    stream.set_write_timeout(Some(Duration::from_secs(3)))?;
+    let req = jsonrpc::Request::new(&APICommand::Subscribe, 1);
+    let req_str = serde_json::to_string(&req).expect("serialising a JSON-RPC request is infallible");
+    writeln!(&stream, "{}", req_str)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let res: jsonrpc::Response =
+        serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if let Some(err) = res.error {
+        return Err(io::Error::other(err.message));
+    }
+    let status = serde_json::from_value(res.result.unwrap_or(Value::Null))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok((status, reader))
+}
+
+/// Whether the instance listening on the runtime API socket, if any, is `ssgtk --service`.
+pub fn is_service_running(socket_path: impl AsRef<Path>) -> bool {
+    request(socket_path, &APICommand::GetStatus)
+        .and_then(|value| serde_json::from_value::<APIStatus>(value).map_err(io::Error::other))
+        .is_ok_and(|status| status.headless)
+}
+
+/// Send a command as a JSON-RPC request, and wait for its result.
+fn send(stream: UnixStream, cmd: &APICommand) -> io::Result<Value> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(3)))?;
+    let req = jsonrpc::Request::new(cmd, 1);
+    let req_str = serde_json::to_string(&req).expect("serialising a JSON-RPC request is infallible");
+    writeln!(&stream, "{}", req_str)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let res: jsonrpc::Response =
+        serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    match res.error {
+        Some(err) => Err(io::Error::other(err.message)),
+        None => Ok(res.result.unwrap_or(Value::Null)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, os::unix::net::UnixListener, process, thread};
+
+    use super::*;
+
+    #[test]
+    fn forward_connect() {
+        let socket_path = env::temp_dir().join(format!("ssgtk-single-instance-test-{}.sock", process::id()));
+        let _ = fs::remove_file(&socket_path);
+        let cmd = forwarded_command(Some("2"), false);
+
+        // nothing is running
+        assert!(forward_to_running(&socket_path, &cmd).is_none());
+
+        // a running instance receives the command
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line).unwrap();
+            let req: jsonrpc::Request = serde_json::from_str(&line).unwrap();
+            let res = jsonrpc::Response::new(req.id.clone().unwrap(), Ok(Value::Null));
+            writeln!(&stream, "{}", serde_json::to_string(&res).unwrap()).unwrap();
+            req.to_command().unwrap()
+        });
+        let res = forward_to_running(&socket_path, &cmd);
+        assert!(matches!(res, Some(Ok(()))));
+        assert!(matches!(server.join().unwrap(), APICommand::Connect(s) if s == "2"));
+
+        assert!(matches!(forwarded_command(None, false), APICommand::LogViewerShow));
+        assert!(matches!(forwarded_command(None, true), APICommand::GetStatus));
+        fs::remove_file(&socket_path).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+//! This module contains code that handles SIP008 online config subscriptions.
+//!
+//! Subscribed servers are fetched into the XDG cache directory, and are
+//! materialized as a `ProfileGroup` every time profiles are loaded.
+//!
+//! See https://shadowsocks.org/guide/sip008.html.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use crossbeam_channel::{unbounded as unbounded_channel, RecvTimeoutError, Sender};
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use shadowsocks_gtk_rs::consts::*;
+
+use crate::{
+    event::AppEvent,
+    io::{
+        profile_import,
+        profile_loader::{Profile, ProfileConfig, ProfileFolder, ProfileGroup, ProfileLoadError},
+    },
+    region,
+};
+
+/// How often the updater daemon checks whether any subscription is due for a refresh.
+const UPDATER_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug)]
+pub enum SubscriptionError {
+    /// The subscription file cannot be parsed.
+    ConfigParseError(serde_yaml::Error),
+    /// The HTTP request failed.
+    FetchError(Box<ureq::Error>),
+    /// The fetched document is not a valid SIP008 document.
+    DocumentParseError(serde_json::Error),
+    /// The fetched document is not a valid Clash config.
+    ClashParseError(serde_yaml::Error),
+    /// A server in the document cannot be turned into a profile.
+    ProfileLoadError(ProfileLoadError),
+    /// The filesystem encountered an IOError.
+    IOError(io::Error),
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SubscriptionError::*;
+
+        let prefix = "SubscriptionError";
+        match self {
+            ConfigParseError(e) => write!(f, "{}-ConfigParseError: {}", prefix, e),
+            FetchError(e) => write!(f, "{}-FetchError: {}", prefix, e),
+            DocumentParseError(e) => write!(f, "{}-DocumentParseError: {}", prefix, e),
+            ClashParseError(e) => write!(f, "{}-ClashParseError: {}", prefix, e),
+            ProfileLoadError(e) => write!(f, "{}-ProfileLoadError: {}", prefix, e),
+            IOError(e) => write!(f, "{}-IOError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for SubscriptionError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::ConfigParseError(err)
+    }
+}
+impl From<ureq::Error> for SubscriptionError {
+    fn from(err: ureq::Error) -> Self {
+        Self::FetchError(err.into())
+    }
+}
+impl From<serde_json::Error> for SubscriptionError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::DocumentParseError(err)
+    }
+}
+impl From<ProfileLoadError> for SubscriptionError {
+    fn from(err: ProfileLoadError) -> Self {
+        Self::ProfileLoadError(err)
+    }
+}
+impl From<io::Error> for SubscriptionError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+/// A single entry in the subscription file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    /// The display name of the group the servers are materialized into.
+    pub name: String,
+    /// The SIP008 endpoint.
+    pub url: String,
+    /// Automatically refresh this subscription every this many minutes.
+    ///
+    /// `None` means the subscription is only refreshed manually.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_mins: Option<u64>,
+    /// Whether the materialized group is a failover set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failover: bool,
+    /// The format of the document served at `url`.
+    #[serde(default, skip_serializing_if = "SubscriptionFormat::is_default")]
+    pub format: SubscriptionFormat,
+    /// Whether to split the materialized group into a nested group per region,
+    /// as inferred from the servers' names.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub group_by_region: bool,
+}
+
+/// A supported format of subscription documents.
+///
+/// Either way, the fetched servers are cached as a SIP008 document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubscriptionFormat {
+    #[default]
+    Sip008,
+    /// A Clash config, whose shadowsocks proxies are used.
+    Clash,
+}
+impl SubscriptionFormat {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A server entry in a SIP008 document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sip008Server {
+    #[serde(default)]
+    pub remarks: Option<String>,
+    /// `sslocal` configs may call this `address`.
+    #[serde(alias = "address")]
+    pub server: String,
+    pub server_port: u16,
+    pub password: String,
+    pub method: String,
+    #[serde(default)]
+    pub plugin: Option<String>,
+    #[serde(default)]
+    pub plugin_opts: Option<String>,
+}
+
+/// A SIP008 document. Fields irrelevant to us are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sip008Document {
+    servers: Vec<Sip008Server>,
+}
+
+impl Sip008Server {
+    pub fn to_profile_config(&self) -> ProfileConfig {
+        let plugin = self.plugin.clone().filter(|p| !p.is_empty());
+        let plugin_opts = self.plugin_opts.clone().filter(|o| !o.is_empty());
+        let display_name = self.remarks.clone().filter(|r| !r.is_empty());
+        ProfileConfig::new_proxy(
+            display_name,
+            (self.server.clone(), self.server_port),
+            self.password.clone(),
+            self.method.clone(),
+            plugin,
+            plugin_opts,
+        )
+    }
+}
+
+impl Subscription {
+    /// Load all subscriptions defined in the base profiles directory.
+    ///
+    /// Returns an empty list if there is no subscription file.
+    pub fn load_all(profiles_dir: impl AsRef<Path>) -> Result<Vec<Self>, SubscriptionError> {
+        let path = profiles_dir.as_ref().join(SUBSCRIPTION_FILE_NAME);
+        if !path.is_file() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(path)?;
+        let subs = serde_yaml::from_str(&content)?;
+        Ok(subs)
+    }
+
+    /// The path relative to the XDG cache directory at which
+    /// the fetched document of this subscription is cached.
+    fn cache_file(&self) -> PathBuf {
+        let file_name = format!("{}.json", self.name.replace(['/', '\0'], "-"));
+        Path::new(SUBSCRIPTION_CACHE_DIR_NAME).join(file_name)
+    }
+    /// The absolute path of `Self::cache_file`.
+    fn cache_path(&self) -> PathBuf {
+        XDG_DIRS.get_cache_home().join(self.cache_file())
+    }
+
+    /// Whether this subscription should be automatically refreshed now.
+    fn is_due(&self) -> bool {
+        let interval = match self.refresh_interval_mins {
+            Some(mins) => Duration::from_secs(mins * 60),
+            None => return false,
+        };
+        let last_fetch = fs::metadata(self.cache_path()).and_then(|m| m.modified());
+        match last_fetch {
+            Ok(t) => SystemTime::now().duration_since(t).unwrap_or_default() >= interval,
+            Err(_) => true, // never fetched
+        }
+    }
+
+    /// Fetch the document and store it in cache as a SIP008 document.
+    ///
+    /// The document is validated before it is cached, so that a bad response
+    /// never replaces a good cache.
+    pub fn fetch(&self) -> Result<(), SubscriptionError> {
+        debug!("Fetching subscription \"{}\"", self.name);
+        let body = ureq::get(&self.url).timeout(HTTP_TIMEOUT).call()?.into_string()?;
+        let doc: Sip008Document = match self.format {
+            SubscriptionFormat::Sip008 => serde_json::from_str(&body)?,
+            SubscriptionFormat::Clash => Sip008Document {
+                servers: profile_import::parse_clash_config(&body).map_err(SubscriptionError::ClashParseError)?,
+            },
+        };
+
+        let cache_path = XDG_DIRS.place_cache_file(self.cache_file())?;
+        fs::write(&cache_path, serde_json::to_string_pretty(&doc)?)?;
+        info!(
+            "Fetched {} servers for subscription \"{}\"",
+            doc.servers.len(),
+            self.name
+        );
+        Ok(())
+    }
+
+    /// Turn the cached servers of this subscription into a `ProfileGroup`.
+    ///
+    /// Returns `Ok(None)` if this subscription has never been fetched.
+    pub fn materialize(
+        &self,
+        pwd: impl AsRef<Path>,
+        default_bin_path: Option<&Path>,
+    ) -> Result<Option<ProfileGroup>, SubscriptionError> {
+        let content = match fs::read_to_string(self.cache_path()) {
+            Ok(c) => c,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let doc: Sip008Document = serde_json::from_str(&content)?;
+
+        let content = doc
+            .servers
+            .iter()
+            .filter_map(|server| {
+                let default_name = format!("{}:{}", server.server, server.server_port);
+                let config = server.to_profile_config();
+                match Profile::from_config(config, default_name.clone(), pwd.as_ref().into(), default_bin_path) {
+                    Ok(profile) => Some(Ok(ProfileFolder::Profile(profile))),
+                    // one server's missing plugin should not break the others
+                    Err(ProfileLoadError::BadPlugin(err)) => {
+                        warn!(
+                            "Skipped server {} of subscription \"{}\": missing plugin {}",
+                            default_name, self.name, err
+                        );
+                        None
+                    }
+                    Err(err) => Some(Err(err.into())),
+                }
+            })
+            .collect::<Result<_, SubscriptionError>>()?;
+        Ok(Some(ProfileGroup {
+            display_name: self.name.clone(),
+            content,
+            failover: self.failover,
+            unloaded: None,
+        }))
+    }
+}
+
+/// Append a group for each subscription's cached servers to `profile_folder`.
+///
+/// Servers whose names conflict with existing profiles are skipped.
+pub fn merge_subscriptions(
+    profile_folder: &mut ProfileFolder,
+    subs: &[Subscription],
+    pwd: impl AsRef<Path>,
+    default_bin_path: Option<&Path>,
+) {
+    for sub in subs {
+        let mut group = match sub.materialize(&pwd, default_bin_path) {
+            Ok(Some(g)) => g,
+            Ok(None) => {
+                debug!("Subscription \"{}\" has not been fetched yet", sub.name);
+                continue;
+            }
+            Err(err) => {
+                error!("Cannot load subscription \"{}\": {}", sub.name, err);
+                continue;
+            }
+        };
+        group.content.retain(|pf| match pf {
+            ProfileFolder::Profile(p) if profile_folder.lookup(&p.metadata.display_name).is_some() => {
+                warn!(
+                    "Subscription \"{}\" has a server named \"{}\", which conflicts with an existing profile; skipped",
+                    sub.name, p.metadata.display_name
+                );
+                false
+            }
+            _ => true,
+        });
+        if sub.group_by_region {
+            group.content = region::group_by_region(group.content, group.failover);
+        }
+        profile_folder.push(ProfileFolder::Group(group));
+    }
+}
+
+/// A daemon that periodically refreshes subscriptions that are due,
+/// and refreshes all subscriptions when requested.
+///
+/// Terminates the underlying daemon thread when dropped.
+#[derive(Debug)]
+pub struct SubscriptionUpdater {
+    /// Send `true` to refresh all subscriptions, or `false` to refresh only those that are due.
+    /// Dropped to halt the daemon.
+    trigger_tx: Option<Sender<bool>>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for SubscriptionUpdater {
+    fn drop(&mut self) {
+        trace!("SubscriptionUpdater is getting dropped");
+
+        // notify daemon halt
+        drop(self.trigger_tx.take());
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Subscription updater daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl SubscriptionUpdater {
+    /// Start the updater daemon, which reads subscriptions from `profiles_dir`.
+    pub fn start(profiles_dir: impl AsRef<Path>, events_tx: Sender<AppEvent>) -> io::Result<Self> {
+        let profiles_dir = profiles_dir.as_ref().to_path_buf();
+        let (trigger_tx, trigger_rx) = unbounded_channel();
+
+        let handle = thread::Builder::new()
+            .name("Subscription updater daemon".into())
+            .spawn(move || loop {
+                let forced = match trigger_rx.recv_timeout(UPDATER_POLL_INTERVAL) {
+                    Ok(forced) => forced,
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        trace!("Subscription updater trigger has hung up; daemon exiting");
+                        break;
+                    }
+                };
+
+                // reread every time so that edits take effect
+                let subs = match Subscription::load_all(&profiles_dir) {
+                    Ok(subs) => subs,
+                    Err(err) => {
+                        error!("Cannot load subscription file: {}", err);
+                        continue;
+                    }
+                };
+
+                let mut updated = 0;
+                let mut errors = vec![];
+                for sub in subs.iter().filter(|s| forced || s.is_due()) {
+                    match sub.fetch() {
+                        Ok(_) => updated += 1,
+                        Err(err) => {
+                            error!("Failed to refresh subscription \"{}\": {}", sub.name, err);
+                            errors.push(format!("{}: {}", sub.name, err));
+                        }
+                    }
+                }
+
+                if (forced || updated > 0 || !errors.is_empty())
+                    && events_tx
+                        .send(AppEvent::SubscriptionsRefreshed { updated, errors })
+                        .is_err()
+                {
+                    error!("Trying to send SubscriptionsRefreshed event, but all receivers have hung up.");
+                }
+            })?;
+
+        let updater = Self {
+            trigger_tx: Some(trigger_tx),
+            daemon_handle: Some(handle),
+        };
+        // check for due subscriptions right away instead of after the first poll interval
+        updater.trigger(false);
+        Ok(updater)
+    }
+
+    /// Request a refresh of all subscriptions.
+    pub fn refresh_now(&self) {
+        self.trigger(true);
+    }
+
+    fn trigger(&self, forced: bool) {
+        if let Some(tx) = &self.trigger_tx {
+            if tx.send(forced).is_err() {
+                error!("Subscription updater daemon has exited unexpectedly; cannot refresh");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sip008Document;
+
+    #[test]
+    fn parse_sip008_document() {
+        let doc = r#"{
+            "version": 1,
+            "servers": [
+                {
+                    "id": "27b8a625-4f4b-4428-9f0f-8a2317db7c79",
+                    "remarks": "Name of the server",
+                    "server": "example.com",
+                    "server_port": 8388,
+                    "password": "example",
+                    "method": "chacha20-ietf-poly1305",
+                    "plugin": "xxx",
+                    "plugin_opts": "xxxxx"
+                },
+                {
+                    "server": "10.0.0.1",
+                    "server_port": 443,
+                    "password": "foobar",
+                    "method": "aes-256-gcm"
+                }
+            ],
+            "bytes_used": 274877906944,
+            "bytes_remaining": 824633720832
+        }"#;
+        let doc: Sip008Document = serde_json::from_str(doc).unwrap();
+        assert_eq!(doc.servers.len(), 2);
+        assert_eq!(doc.servers[0].remarks.as_deref(), Some("Name of the server"));
+        assert_eq!(doc.servers[0].plugin_opts.as_deref(), Some("xxxxx"));
+        assert!(doc.servers[1].plugin.is_none());
+        assert_eq!(
+            doc.servers[0].to_profile_config().display_name(),
+            Some("Name of the server")
+        );
+        assert_eq!(doc.servers[1].to_profile_config().suggested_dir_name(), "10.0.0.1-443");
+    }
+}
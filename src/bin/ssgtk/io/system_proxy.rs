@@ -0,0 +1,225 @@
+//! This module contains code that sets the desktop environment's system proxy
+//! to point to `sslocal`, and restores the original settings afterwards.
+//!
+//! GNOME (and derivatives) are configured via `gsettings`;
+//! KDE Plasma is configured via `kwriteconfig5`/`kwriteconfig6`.
+
+use std::{env, fmt, io, net::SocketAddr};
+
+use duct::cmd;
+use log::{debug, info, warn};
+
+#[derive(Debug)]
+pub enum SystemProxyError {
+    /// The current desktop environment is not supported.
+    UnsupportedDesktop(String),
+    /// A required command is not installed.
+    MissingCommand(&'static str),
+    /// Running a command has failed.
+    CommandError(io::Error),
+}
+
+impl fmt::Display for SystemProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SystemProxyError::*;
+        let prefix = "SystemProxyError";
+        match self {
+            UnsupportedDesktop(name) => write!(f, "{}-UnsupportedDesktop: \"{}\"", prefix, name),
+            MissingCommand(name) => write!(f, "{}-MissingCommand: {}", prefix, name),
+            CommandError(e) => write!(f, "{}-CommandError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<io::Error> for SystemProxyError {
+    fn from(err: io::Error) -> Self {
+        Self::CommandError(err)
+    }
+}
+
+/// The desktop environments whose system proxy we know how to set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Desktop {
+    Gnome,
+    Kde,
+}
+
+impl Desktop {
+    /// Detect the current desktop environment from `$XDG_CURRENT_DESKTOP`.
+    pub fn detect() -> Result<Self, SystemProxyError> {
+        let current = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        current
+            .split(':')
+            .find_map(|name| match name.to_ascii_uppercase().as_str() {
+                "GNOME" | "UNITY" | "BUDGIE" | "PANTHEON" | "CINNAMON" | "X-CINNAMON" => Some(Self::Gnome),
+                "KDE" => Some(Self::Kde),
+                _ => None,
+            })
+            .ok_or(SystemProxyError::UnsupportedDesktop(current))
+    }
+
+    /// The settings to change, and the values to set them to,
+    /// in order to use `target` as the system proxy.
+    fn proxy_settings(&self, target: &ProxyTarget) -> Vec<(&'static str, &'static str, String)> {
+        let addr = match target {
+            ProxyTarget::Socks(addr) => *addr,
+            ProxyTarget::Pac(url) => {
+                return match self {
+                    Self::Gnome => vec![
+                        ("org.gnome.system.proxy", "autoconfig-url", format!("'{}'", url)),
+                        ("org.gnome.system.proxy", "mode", "'auto'".into()),
+                    ],
+                    Self::Kde => vec![
+                        ("Proxy Settings", "Proxy Config Script", url.clone()),
+                        ("Proxy Settings", "ProxyType", "2".into()),
+                    ],
+                }
+            }
+        };
+        match self {
+            Self::Gnome => vec![
+                ("org.gnome.system.proxy.socks", "host", format!("'{}'", addr.ip())),
+                ("org.gnome.system.proxy.socks", "port", addr.port().to_string()),
+                ("org.gnome.system.proxy", "mode", "'manual'".into()),
+            ],
+            Self::Kde => vec![
+                (
+                    "Proxy Settings",
+                    "socksProxy",
+                    match addr {
+                        SocketAddr::V4(v4) => format!("socks://{} {}", v4.ip(), v4.port()),
+                        SocketAddr::V6(v6) => format!("socks://[{}] {}", v6.ip(), v6.port()),
+                    },
+                ),
+                ("Proxy Settings", "ProxyType", "1".into()),
+            ],
+        }
+    }
+
+    /// Read the raw value of a setting.
+    fn read(&self, group: &str, key: &str) -> Result<String, SystemProxyError> {
+        let output = match self {
+            Self::Gnome => cmd!("gsettings", "get", group, key),
+            Self::Kde => {
+                let bin = kde_command("kreadconfig")?;
+                cmd!(bin, "--file", "kioslaverc", "--group", group, "--key", key)
+            }
+        }
+        .stderr_null()
+        .read()?;
+        Ok(output.trim().to_string())
+    }
+
+    /// Write the raw value of a setting.
+    fn write(&self, group: &str, key: &str, value: &str) -> Result<(), SystemProxyError> {
+        match self {
+            Self::Gnome => cmd!("gsettings", "set", group, key, value),
+            Self::Kde => {
+                let bin = kde_command("kwriteconfig")?;
+                cmd!(bin, "--file", "kioslaverc", "--group", group, "--key", key, value)
+            }
+        }
+        .stdout_null()
+        .stderr_null()
+        .run()?;
+        Ok(())
+    }
+
+    /// Make running applications pick up the changes, if necessary.
+    fn notify_change(&self) {
+        if *self == Self::Kde {
+            let res = cmd!(
+                "dbus-send",
+                "--type=signal",
+                "/KIO/Scheduler",
+                "org.kde.KIO.Scheduler.reparseSlaveConfiguration",
+                "string:"
+            )
+            .stdout_null()
+            .stderr_null()
+            .run();
+            if let Err(err) = res {
+                warn!("Cannot notify KDE of proxy settings change: {}", err);
+            }
+        }
+    }
+}
+
+/// Find the KDE config tool for the installed Plasma version.
+fn kde_command(prefix: &'static str) -> Result<String, SystemProxyError> {
+    ["6", "5"]
+        .iter()
+        .map(|ver| format!("{}{}", prefix, ver))
+        .find(|bin| which::which(bin).is_ok())
+        .ok_or(SystemProxyError::MissingCommand(prefix))
+}
+
+/// What the system proxy can be pointed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyTarget {
+    /// A SOCKS proxy, i.e. `sslocal` itself.
+    Socks(SocketAddr),
+    /// The URL of a PAC file.
+    Pac(String),
+}
+
+impl fmt::Display for ProxyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Socks(addr) => write!(f, "socks://{}", addr),
+            Self::Pac(url) => write!(f, "PAC {}", url),
+        }
+    }
+}
+
+/// The system proxy, while it is set to point to `sslocal`.
+///
+/// The original settings are restored when dropped.
+#[derive(Debug)]
+pub struct SystemProxy {
+    desktop: Desktop,
+    target: ProxyTarget,
+    /// The original raw values of all changed settings.
+    original: Vec<(&'static str, &'static str, String)>,
+}
+
+impl Drop for SystemProxy {
+    fn drop(&mut self) {
+        info!("Restoring original system proxy settings");
+        // restore in reverse order so that the mode is restored first
+        for (group, key, value) in self.original.iter().rev() {
+            if let Err(err) = self.desktop.write(group, key, value) {
+                warn!("Cannot restore system proxy setting {} {}: {}", group, key, err);
+            }
+        }
+        self.desktop.notify_change();
+    }
+}
+
+impl SystemProxy {
+    /// Set the system proxy to use `target`.
+    ///
+    /// If any setting fails to apply, the ones already applied are restored.
+    pub fn apply(target: ProxyTarget) -> Result<Self, SystemProxyError> {
+        let desktop = Desktop::detect()?;
+        info!("Setting system proxy of {:?} desktop to {}", desktop, target);
+        let mut proxy = Self {
+            desktop,
+            target: target.clone(),
+            original: vec![],
+        };
+        for (group, key, value) in desktop.proxy_settings(&target) {
+            let original = desktop.read(group, key)?;
+            debug!("System proxy setting {} {}: {} -> {}", group, key, original, value);
+            desktop.write(group, key, &value)?;
+            proxy.original.push((group, key, original));
+        }
+        desktop.notify_change();
+        Ok(proxy)
+    }
+
+    /// What the system proxy is currently set to.
+    pub fn target(&self) -> &ProxyTarget {
+        &self.target
+    }
+}
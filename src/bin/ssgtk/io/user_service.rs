@@ -0,0 +1,115 @@
+//! This module contains code that installs a systemd user unit running `ssgtk --service`,
+//! so that `sslocal` keeps running when the GUI exits or crashes, e.g. on logout.
+
+use std::{env, ffi::OsString, fs, io, path::Path, process::Command};
+
+use log::{debug, info};
+use shadowsocks_gtk_rs::consts::*;
+
+use crate::{clap_def::CliArgs, io::autostart::path_arg};
+
+/// Write the user unit for `ssgtk --service` with the same paths as `args`,
+/// then enable and start it with `systemctl --user`.
+///
+/// Replaces the existing unit if present.
+pub fn install(args: &CliArgs) -> io::Result<()> {
+    let mut service_args: Vec<OsString> = vec!["--service".into()];
+    service_args.extend(path_arg(
+        "--profiles-dir",
+        &args.profiles_dir,
+        &PROFILES_DIR_PATH_DEFAULT,
+    ));
+    service_args.extend(path_arg("--app-state", &args.app_state_path, &STATE_FILE_PATH_DEFAULT));
+    service_args.extend(path_arg(
+        "--app-settings",
+        &args.app_settings_path,
+        &SETTINGS_FILE_PATH_DEFAULT,
+    ));
+    service_args.extend(path_arg(
+        "--api-socket",
+        &args.runtime_api_socket_path,
+        &RUNTIME_API_SOCKET_PATH_DEFAULT,
+    ));
+    if let Some(addr) = args.metrics_addr {
+        service_args.extend(["--metrics-addr".into(), addr.to_string().into()]);
+    }
+
+    let exe = env::current_exe()?;
+    let path: &Path = &USER_SERVICE_FILE_PATH;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, unit_content(exe.as_os_str(), &service_args))?;
+    info!("User service unit written to {:?}", path);
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", USER_SERVICE_FILE_NAME])?;
+    info!("Enabled and started {}", USER_SERVICE_FILE_NAME);
+    Ok(())
+}
+
+/// The content of the unit file, running `exe` with `args`.
+fn unit_content(exe: &std::ffi::OsStr, args: &[OsString]) -> String {
+    let exec = std::iter::once(exe)
+        .chain(args.iter().map(OsString::as_os_str))
+        .map(|arg| quote_exec_arg(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "[Unit]\n\
+         Description=Shadowsocks GTK core (sslocal manager)\n\
+         Documentation=https://github.com/spyophobia/shadowsocks-gtk-rs/blob/master/res/QnA.md\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exec
+    )
+}
+
+/// Run `systemctl --user` with `args`, erroring if it exits unsuccessfully.
+fn systemctl(args: &[&str]) -> io::Result<()> {
+    debug!("Running systemctl --user {}", args.join(" "));
+    let status = Command::new("systemctl").arg("--user").args(args).status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "systemctl --user {} has failed with {}",
+            args.join(" "),
+            status
+        ))),
+    }
+}
+
+/// Quote an argument of `ExecStart` if necessary, escaping specifiers and variables.
+///
+/// See https://www.freedesktop.org/software/systemd/man/systemd.service.html#Command%20lines.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.contains([' ', '\t', '"', '\'', '\\', ';']) {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unit_exec_start() {
+        let args = [
+            "--service".into(),
+            "--profiles-dir".into(),
+            "/home/me/My $Profiles".into(),
+        ];
+        let unit = unit_content("/usr/bin/ssgtk".as_ref(), &args);
+        assert!(unit.contains("\nExecStart=/usr/bin/ssgtk --service --profiles-dir \"/home/me/My $$Profiles\"\n"));
+        assert!(unit.contains("\nWantedBy=default.target\n"));
+    }
+}
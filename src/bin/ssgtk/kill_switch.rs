@@ -0,0 +1,176 @@
+//! This module contains code that engages and releases the kill switch,
+//! which blocks all traffic except to the current profile's servers while it is supposed to be connected,
+//! so that nothing leaks if `sslocal` dies.
+//!
+//! The firewall rules are installed by the privileged helper, one request at a time in a daemon thread,
+//! since authenticating via `pkexec` can take a while.
+
+use std::{
+    io,
+    net::{IpAddr, ToSocketAddrs},
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::{unbounded as unbounded_channel, Sender};
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+    event::AppEvent,
+    io::{privileged, profile_loader::ProfileConfig},
+};
+
+/// What the kill switch lets through, besides loopback, DHCP and DNS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KillSwitchAllow {
+    /// Server addresses, as they may be named by hostname in profiles.
+    pub hosts: Vec<String>,
+    /// Interfaces, i.e. the tun interface of a "Tun"-type profile.
+    pub interfaces: Vec<String>,
+}
+
+impl KillSwitchAllow {
+    /// What to let through for `config`.
+    pub fn for_config(config: &ProfileConfig) -> Self {
+        Self {
+            hosts: config
+                .server_addrs()
+                .into_iter()
+                .map(|(host, _)| host.to_string())
+                .collect(),
+            interfaces: config.tun_if_name().into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// Resolve `hosts` into addresses.
+    fn resolve(&self) -> io::Result<Vec<IpAddr>> {
+        let mut addrs = vec![];
+        for host in self.hosts.iter() {
+            match host.parse() {
+                Ok(ip) => addrs.push(ip),
+                Err(_) => addrs.extend((host.as_str(), 0).to_socket_addrs()?.map(|a| a.ip())),
+            }
+        }
+        addrs.sort_unstable();
+        addrs.dedup();
+        Ok(addrs)
+    }
+}
+
+/// Handle to the kill switch daemon.
+///
+/// Pending requests are carried out before the daemon exits, which happens when dropped.
+#[derive(Debug)]
+pub struct KillSwitch {
+    /// Whether the kill switch should be engaged while connected.
+    enabled: bool,
+    /// The last request sent to the daemon: `Some` to engage, `None` to release.
+    ///
+    /// Unknown (`None`) until the first request, as rules may be left from a crash.
+    requested: Option<Option<KillSwitchAllow>>,
+    /// Dropped to halt the daemon.
+    request_tx: Option<Sender<Option<KillSwitchAllow>>>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for KillSwitch {
+    fn drop(&mut self) {
+        trace!("KillSwitch is getting dropped");
+        self.halt();
+    }
+}
+
+impl KillSwitch {
+    /// Start the kill switch daemon, which reports failures as `AppEvent::KillSwitchFailed` events.
+    pub fn start(enabled: bool, events_tx: Sender<AppEvent>) -> io::Result<Self> {
+        let (request_tx, request_rx) = unbounded_channel::<Option<KillSwitchAllow>>();
+
+        let handle = thread::Builder::new()
+            .name("Kill switch daemon".into())
+            .spawn(move || {
+                for request in request_rx {
+                    let res = match &request {
+                        Some(allow) => allow
+                            .resolve()
+                            .and_then(|addrs| privileged::set_kill_switch(Some((&addrs, &allow.interfaces)))),
+                        None => privileged::set_kill_switch(None),
+                    };
+                    match res {
+                        Ok(()) if request.is_some() => info!("Kill switch engaged"),
+                        Ok(()) => info!("Kill switch released"),
+                        Err(err) => {
+                            error!("Cannot set kill switch: {}", err);
+                            if events_tx.send(AppEvent::KillSwitchFailed(err.to_string())).is_err() {
+                                error!("Trying to send KillSwitchFailed event, but all receivers have hung up.");
+                            }
+                        }
+                    }
+                }
+                trace!("Kill switch requests have hung up; daemon exiting");
+            })?;
+
+        Ok(Self {
+            enabled,
+            requested: None,
+            request_tx: Some(request_tx),
+            daemon_handle: Some(handle),
+        })
+    }
+
+    /// Carry out pending requests, then halt the daemon.
+    ///
+    /// Requests made afterwards are not carried out.
+    pub fn halt(&mut self) {
+        // notify daemon halt
+        drop(self.request_tx.take());
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Kill switch daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the kill switch, engaging it right away if `current` is connected.
+    pub fn set_enabled(&mut self, enabled: bool, current: Option<KillSwitchAllow>) {
+        self.enabled = enabled;
+        match current {
+            Some(allow) if enabled => self.request(Some(allow)),
+            _ if enabled => {}
+            _ => self.request(None),
+        }
+    }
+
+    /// Block all traffic except what `allow` lets through, if enabled.
+    pub fn engage(&mut self, allow: KillSwitchAllow) {
+        if self.enabled {
+            self.request(Some(allow));
+        }
+    }
+
+    /// Remove the firewall rules, e.g. on a clean disconnect.
+    ///
+    /// Nothing is done if the kill switch is disabled and has never been engaged,
+    /// so that the user is not asked to authenticate for nothing.
+    pub fn release(&mut self) {
+        if self.enabled || matches!(self.requested, Some(Some(_))) {
+            self.request(None);
+        }
+    }
+
+    fn request(&mut self, request: Option<KillSwitchAllow>) {
+        if self.requested.as_ref() == Some(&request) {
+            return;
+        }
+        debug!("Requesting kill switch change: {:?}", request);
+        match &self.request_tx {
+            Some(tx) if tx.send(request.clone()).is_ok() => self.requested = Some(request),
+            _ => error!("Kill switch daemon has exited unexpectedly; cannot change kill switch"),
+        }
+    }
+}
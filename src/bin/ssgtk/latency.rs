@@ -0,0 +1,146 @@
+//! This module contains code that tests the latency of profiles' servers,
+//! by timing a TCP connection to each of them.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io,
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+use log::{debug, error, trace};
+
+use crate::{event::AppEvent, io::profile_loader::Profile};
+
+/// Give up connecting to a server after this long.
+const LATENCY_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of testing a single profile.
+///
+/// The error is stored as a string so that results can be cloned and passed around.
+pub type Latency = Result<Duration, String>;
+
+/// Time a TCP connection to `host:port`.
+///
+/// The time spent on name resolution is not included.
+pub fn test_server(host: &str, port: u16) -> io::Result<Duration> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {}", host)))?;
+    let start = Instant::now();
+    TcpStream::connect_timeout(&addr, LATENCY_TEST_TIMEOUT)?;
+    Ok(start.elapsed())
+}
+
+/// Test all servers of a profile one by one, returning the fastest result.
+///
+/// If all servers fail, the last error is returned.
+fn test_servers(servers: &[(String, u16)]) -> Latency {
+    let mut best: Latency = Err("no server to test".into());
+    for (host, port) in servers {
+        match (test_server(host, *port), &best) {
+            (Ok(elapsed), Ok(fastest)) if elapsed >= *fastest => {}
+            (Ok(elapsed), _) => best = Ok(elapsed),
+            (Err(err), Err(_)) => best = Err(err.to_string()),
+            (Err(_), Ok(_)) => {}
+        }
+    }
+    best
+}
+
+/// Concurrently test all profiles that have known server addresses,
+/// then send the results as an `AppEvent::LatenciesTested` event.
+///
+/// Profiles with multiple servers are represented by their fastest one.
+/// Profiles without a known server address (i.e. those using a config file)
+/// are left out of the results.
+pub fn spawn_test_all(profiles: Vec<Profile>, events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("Latency test daemon".into())
+        .spawn(move || {
+            let handles: Vec<_> = profiles
+                .into_iter()
+                .filter_map(|p| {
+                    let name = p.metadata.display_name.clone();
+                    let servers: Vec<_> = p
+                        .config()
+                        .server_addrs()
+                        .into_iter()
+                        .map(|(host, port)| (host.to_string(), port))
+                        .collect();
+                    if servers.is_empty() {
+                        return None;
+                    }
+                    let spawn_res = thread::Builder::new()
+                        .name(format!("Latency test for {}", name))
+                        .spawn(move || test_servers(&servers));
+                    match spawn_res {
+                        Ok(handle) => Some((name, handle)),
+                        Err(err) => {
+                            error!("Cannot start latency test for profile \"{}\": {}", name, err);
+                            None
+                        }
+                    }
+                })
+                .collect();
+            debug!("Testing latency of {} profiles", handles.len());
+
+            let results: HashMap<_, _> = handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    let latency = handle
+                        .join()
+                        .unwrap_or_else(|_| Err("latency test panicked unexpectedly".into()));
+                    trace!("Latency of profile \"{}\": {:?}", name, latency);
+                    (name, latency)
+                })
+                .collect();
+
+            if events_tx.send(AppEvent::LatenciesTested(results)).is_err() {
+                error!("Trying to send LatenciesTested event, but all receivers have hung up.");
+            }
+        })?;
+    Ok(())
+}
+
+/// Order latencies from fastest to slowest, followed by failures, then by untested.
+pub fn compare(a: Option<&Latency>, b: Option<&Latency>) -> Ordering {
+    match (a, b) {
+        (Some(Ok(a)), Some(Ok(b))) => a.cmp(b),
+        (Some(Ok(_)), _) => Ordering::Less,
+        (_, Some(Ok(_))) => Ordering::Greater,
+        (Some(Err(_)), None) => Ordering::Less,
+        (None, Some(Err(_))) => Ordering::Greater,
+        _ => Ordering::Equal,
+    }
+}
+
+/// A short human-readable representation, e.g. for annotating menu items.
+pub fn describe(latency: &Latency) -> String {
+    match latency {
+        Ok(d) => format!("{} ms", d.as_millis()),
+        Err(_) => "failed".into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cmp::Ordering, time::Duration};
+
+    use super::{compare, Latency};
+
+    #[test]
+    fn compare_order() {
+        let fast: Latency = Ok(Duration::from_millis(10));
+        let slow: Latency = Ok(Duration::from_millis(200));
+        let failed: Latency = Err("timed out".into());
+        let mut list = vec![None, Some(&failed), Some(&slow), Some(&fast)];
+        list.sort_by(|a, b| compare(*a, *b));
+        assert_eq!(list, vec![Some(&fast), Some(&slow), Some(&failed), None]);
+        assert_eq!(compare(Some(&failed), Some(&failed)), Ordering::Equal);
+    }
+}
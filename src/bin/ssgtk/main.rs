@@ -1,22 +1,109 @@
+use std::{path::Path, process};
+
 use gui::app::{self, AppStartError};
 use log::{error, SetLoggerError};
 use notify_rust::Urgency;
 use shadowsocks_gtk_rs::consts::*;
+use shadowsocks_gtk_rs_core::{acl, log_parser, pac, profile_manager, redact, stats};
 
 use crate::gui::notification::notify_toast;
 
 mod clap_def;
+mod clipboard_monitor;
+mod color_scheme;
+mod diagnostics;
 mod event;
 mod gui;
+mod health_check;
+mod hotkey;
 mod io;
-mod profile_manager;
+mod kill_switch;
+mod latency;
+mod network_monitor;
+mod qr_scan;
+mod region;
+mod scheduler;
+#[cfg(feature = "runtime-api")]
+mod service;
+mod sleep_monitor;
+mod validate;
 
 fn main() -> Result<(), AppStartError> {
     // init clap app
     let args = clap_def::parse_and_validate();
 
     // init logger
-    logger_init(args.verbose as i32 - args.quiet as i32).unwrap(); // never produces error on first call of init
+    // reports go to stdout, so keep logs apart from them
+    let log_to_stderr = args.validate.is_some() || args.sub_cmd.is_some();
+    logger_init(args.verbose as i32 - args.quiet as i32, log_to_stderr).unwrap(); // never produces error on first call of init
+
+    // store a secret instead of starting the app
+    if let Some(entry) = &args.store_secret {
+        return Ok(io::keyring::store(entry)?);
+    }
+
+    // encrypt or decrypt a profile instead of starting the app
+    #[cfg(feature = "encryption")]
+    {
+        let convert = (args.encrypt_profile.as_ref().map(|dir| (dir, true)))
+            .or_else(|| args.decrypt_profile.as_ref().map(|dir| (dir, false)));
+        if let Some((dir, encrypt)) = convert {
+            return Ok(io::encryption::convert_profile(dir, encrypt)?);
+        }
+    }
+
+    // migrate legacy profiles instead of starting the app
+    if let Some(clap_def::SubCmd::MigrateProfiles { dir, dry_run }) = &args.sub_cmd {
+        let dir = dir.as_ref().unwrap_or(&args.profiles_dir);
+        let all_migrated = migrate_profiles(dir, *dry_run)?;
+        process::exit(if all_migrated { 0 } else { 1 });
+    }
+
+    // check profiles instead of starting the app
+    if let Some(dir) = &args.validate {
+        let dir = dir.as_ref().unwrap_or(&args.profiles_dir);
+        let is_valid = validate::validate(dir, &validate::load_options(&args));
+        process::exit(if is_valid { 0 } else { 1 });
+    }
+
+    #[cfg(feature = "runtime-api")]
+    {
+        use io::single_instance::{is_service_running, request};
+        use shadowsocks_gtk_rs::runtime_api_msg::APICommand;
+
+        // install the user service instead of starting the app
+        if args.install_service {
+            return Ok(io::user_service::install(&args)?);
+        }
+
+        // run only the core, e.g. as a user service
+        if args.service || args.headless {
+            return service::run(&args);
+        }
+
+        // attach to the running service as a frontend
+        if is_service_running(&args.runtime_api_socket_path) {
+            if let Some(selector) = &args.connect {
+                if let Err(err) = request(&args.runtime_api_socket_path, &APICommand::Connect(selector.clone())) {
+                    error!("Cannot connect to profile \"{}\": {}", selector, err);
+                }
+            }
+            return gui::frontend::run(&args);
+        }
+    }
+
+    // forward to the running instance instead, if any
+    #[cfg(feature = "runtime-api")]
+    {
+        use io::single_instance::{forward_to_running, forwarded_command};
+        let cmd = forwarded_command(args.connect.as_deref(), args.minimized);
+        if let Some(res) = forward_to_running(&args.runtime_api_socket_path, &cmd) {
+            if let Err(ref err) = res {
+                error!("Cannot forward to the running instance: {}", err);
+            }
+            return Ok(res?);
+        }
+    }
 
     // start app
     let start_res = app::run(&args);
@@ -24,15 +111,37 @@ fn main() -> Result<(), AppStartError> {
         error!("ssgtk failed to load, sending notification");
         let text_2 = format!("Error: {}", err);
         // if this fails, too bad
-        let _ = notify_toast(Urgency::Critical, "Failed to start", &text_2);
+        let _ = notify_toast(Urgency::Critical, None, "Failed to start", &text_2);
     }
     start_res
 }
 
-fn logger_init(relative_verbosity: i32) -> Result<(), SetLoggerError> {
+/// Migrate all legacy profiles in `dir`, printing what has been done to stdout.
+///
+/// Returns whether all of them have been migrated.
+fn migrate_profiles(dir: &Path, dry_run: bool) -> std::io::Result<bool> {
+    let report = io::legacy_profile::migrate_all(dir, dry_run)?;
+    for (path, backup_path) in &report.migrated {
+        match dry_run {
+            true => println!("Would migrate {:?}", path),
+            false => println!("Migrated {:?}; the original is kept as {:?}", path, backup_path),
+        }
+    }
+    for (path, err) in &report.failed {
+        println!("Cannot migrate {:?}: {}", path, err);
+    }
+    if report.migrated.is_empty() && report.failed.is_empty() {
+        println!("No profiles in the legacy format found in {:?}", dir);
+    }
+    Ok(report.failed.is_empty())
+}
+
+fn logger_init(relative_verbosity: i32, to_stderr: bool) -> Result<(), SetLoggerError> {
     use log::LevelFilter::*;
     use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
 
+    use crate::redact::RedactingLogger;
+
     let level_filter = match DEFAULT_LOG_LEVEL + relative_verbosity {
         0 => Error,
         1 => Warn,
@@ -45,6 +154,16 @@ fn logger_init(relative_verbosity: i32) -> Result<(), SetLoggerError> {
     let logger_config = ConfigBuilder::new()
         .add_filter_allow_str("shadowsocks-gtk-rs") // crate lib
         .add_filter_allow_str("ssgtk") // crate bin
+        .add_filter_allow_str("shadowsocks_gtk_rs_core") // crate core
         .build();
-    TermLogger::init(level_filter, logger_config, TerminalMode::Stdout, ColorChoice::Auto)
+    let mode = if to_stderr {
+        TerminalMode::Stderr
+    } else {
+        TerminalMode::Stdout
+    };
+    // passwords are masked before anything is logged
+    let logger = TermLogger::new(level_filter, logger_config, mode, ColorChoice::Auto);
+    log::set_boxed_logger(Box::new(RedactingLogger(logger)))?;
+    log::set_max_level(level_filter);
+    Ok(())
 }
@@ -0,0 +1,109 @@
+//! This module contains code that watches NetworkManager for changes of the active network,
+//! so that `sslocal` can be restarted, or profiles switched, when moving between networks.
+//!
+//! See https://networkmanager.dev/docs/api/latest/spec.html.
+
+use std::{io, thread};
+
+use crossbeam_channel::Sender;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
+
+use crate::{event::AppEvent, scheduler::ScheduleAction};
+
+const NM_DESTINATION: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const NM_ACTIVE_CONNECTION_INTERFACE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// A rule that carries out an action when a network becomes active, e.g. connect on the office Wi-Fi.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkRule {
+    /// The name of the NetworkManager connection, which is usually the Wi-Fi network's SSID.
+    pub network: String,
+    #[serde(flatten)]
+    pub action: ScheduleAction,
+}
+
+/// Read a property of a NetworkManager object.
+fn get_property<T: TryFrom<OwnedValue>>(conn: &Connection, path: &str, interface: &str, name: &str) -> zbus::Result<T> {
+    let props = Proxy::new(conn, NM_DESTINATION, path, PROPERTIES_INTERFACE)?;
+    let value: OwnedValue = props.call("Get", &(interface, name))?;
+    T::try_from(value).map_err(|_| zbus::Error::InvalidReply)
+}
+
+/// Get the name of the network used for the default route, if any.
+fn primary_network(conn: &Connection) -> zbus::Result<Option<String>> {
+    let path: OwnedObjectPath = get_property(conn, NM_PATH, NM_INTERFACE, "PrimaryConnection")?;
+    // the root path stands for no connection
+    if path.as_str() == "/" {
+        return Ok(None);
+    }
+    let name: String = get_property(conn, path.as_str(), NM_ACTIVE_CONNECTION_INTERFACE, "Id")?;
+    Ok(Some(name))
+}
+
+/// Send the current network, then keep sending its changes as `AppEvent::NetworkChanged` events.
+fn listen(events_tx: &Sender<AppEvent>) -> zbus::Result<()> {
+    let conn = Connection::system()?;
+    let nm = Proxy::new(&conn, NM_DESTINATION, NM_PATH, PROPERTIES_INTERFACE)?;
+    // subscribe before reading to avoid missing a change in between
+    let changes = nm.receive_signal("PropertiesChanged")?;
+
+    let mut network = primary_network(&conn)?;
+    debug!("Active network is {:?}", network);
+    let event = AppEvent::NetworkChanged {
+        network: network.clone(),
+        initial: true,
+    };
+    if events_tx.send(event).is_err() {
+        error!("Trying to send NetworkChanged event, but all receivers have hung up.");
+        return Ok(());
+    }
+
+    for _ in changes {
+        // the signal carries all sorts of properties; only act if the network has actually changed
+        let current = match primary_network(&conn) {
+            Ok(current) => current,
+            Err(err) => {
+                // e.g. the connection has gone away while being read
+                debug!("Cannot read the active network: {}", err);
+                continue;
+            }
+        };
+        if current == network {
+            continue;
+        }
+        debug!("Active network changed from {:?} to {:?}", network, current);
+        network = current;
+        let event = AppEvent::NetworkChanged {
+            network: network.clone(),
+            initial: false,
+        };
+        if events_tx.send(event).is_err() {
+            error!("Trying to send NetworkChanged event, but all receivers have hung up.");
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Watch the active network in the background.
+///
+/// If NetworkManager is not running, network changes are simply not acted upon,
+/// so the failure is only logged.
+pub fn spawn_watcher(events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("Network monitor daemon".into())
+        .spawn(move || {
+            if let Err(err) = listen(&events_tx) {
+                debug!("Cannot watch the active network: {}", err);
+            }
+        })?;
+    Ok(())
+}
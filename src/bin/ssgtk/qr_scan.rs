@@ -0,0 +1,176 @@
+//! This module contains code that takes a screenshot via xdg-desktop-portal,
+//! and decodes any `ss://` QR codes found in it.
+//!
+//! See https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.Screenshot.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process, thread,
+};
+
+use crossbeam_channel::Sender;
+use gtk::gdk_pixbuf::{Pixbuf, PixbufError};
+use log::{debug, error, trace, warn};
+use url::Url;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::event::AppEvent;
+
+#[derive(Debug)]
+pub enum QrScanError {
+    /// Communicating with xdg-desktop-portal has failed.
+    DBusError(zbus::Error),
+    /// The user has cancelled the screenshot, or the portal has refused it.
+    Cancelled,
+    /// The portal responded with something unexpected.
+    BadResponse(String),
+    /// The screenshot cannot be loaded.
+    ImageError(glib::Error),
+}
+
+impl fmt::Display for QrScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use QrScanError::*;
+        let prefix = "QrScanError";
+        match self {
+            DBusError(e) => write!(f, "{}-DBusError: {}", prefix, e),
+            Cancelled => write!(f, "{}-Cancelled: screenshot was cancelled", prefix),
+            BadResponse(msg) => write!(f, "{}-BadResponse: {}", prefix, msg),
+            ImageError(e) => write!(f, "{}-ImageError: {}", prefix, e),
+        }
+    }
+}
+
+impl From<zbus::Error> for QrScanError {
+    fn from(err: zbus::Error) -> Self {
+        Self::DBusError(err)
+    }
+}
+impl From<glib::Error> for QrScanError {
+    fn from(err: glib::Error) -> Self {
+        Self::ImageError(err)
+    }
+}
+
+/// Take a full screenshot using xdg-desktop-portal, and return the path it is saved to.
+///
+/// Blocks until the portal responds, which may involve a confirmation from the user.
+pub fn take_screenshot() -> Result<PathBuf, QrScanError> {
+    let conn = Connection::session()?;
+
+    // subscribe to the response before making the request to avoid a race,
+    // which means the request object path must be predicted
+    // see https://flatpak.github.io/xdg-desktop-portal/#gdbus-org.freedesktop.portal.Request
+    let sender = conn
+        .unique_name()
+        .ok_or_else(|| QrScanError::BadResponse("connection has no unique name".into()))?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let token = format!("ssgtk_{}", process::id());
+    let request_path = format!("/org/freedesktop/portal/desktop/request/{}/{}", sender, token);
+    let request = Proxy::new(
+        &conn,
+        "org.freedesktop.portal.Desktop",
+        request_path.as_str(),
+        "org.freedesktop.portal.Request",
+    )?;
+    let mut responses = request.receive_signal("Response")?;
+
+    let screenshot = Proxy::new(
+        &conn,
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Screenshot",
+    )?;
+    let options: HashMap<&str, Value> = [("handle_token", token.as_str().into()), ("interactive", false.into())]
+        .into_iter()
+        .collect();
+    let handle: OwnedObjectPath = screenshot.call("Screenshot", &("", options))?;
+    if handle.as_str() != request_path {
+        // only happens with very old portals; the response may have been missed already
+        warn!(
+            "Unexpected portal request handle {}, expected {}",
+            handle.as_str(),
+            request_path
+        );
+    }
+
+    let msg = responses
+        .next()
+        .ok_or_else(|| QrScanError::BadResponse("connection closed before response".into()))?;
+    let (response, results): (u32, HashMap<String, OwnedValue>) = msg.body()?;
+    trace!("Screenshot portal responded with {}: {:?}", response, results);
+    if response != 0 {
+        return Err(QrScanError::Cancelled);
+    }
+    let uri = results
+        .get("uri")
+        .and_then(|v| v.downcast_ref::<str>())
+        .ok_or_else(|| QrScanError::BadResponse("response has no uri".into()))?;
+    Url::parse(uri)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| QrScanError::BadResponse(format!("{} is not a local file", uri)))
+}
+
+/// Decode all QR codes in an image file.
+///
+/// Codes that fail to decode are skipped.
+pub fn decode_qr_codes(path: &Path) -> Result<Vec<String>, QrScanError> {
+    let pixbuf = Pixbuf::from_file(path)?;
+    let (width, height) = (pixbuf.width() as usize, pixbuf.height() as usize);
+    let (channels, rowstride) = (pixbuf.n_channels() as usize, pixbuf.rowstride() as usize);
+    let pixels = pixbuf
+        .read_pixel_bytes()
+        .ok_or_else(|| glib::Error::new(PixbufError::CorruptImage, "image has no pixel data"))?;
+
+    let mut image = rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| {
+        let i = y * rowstride + x * channels;
+        let (r, g, b) = (pixels[i] as u32, pixels[i + 1] as u32, pixels[i + 2] as u32);
+        ((r * 299 + g * 587 + b * 114) / 1000) as u8 // ITU-R 601 luma
+    });
+    let codes = image
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, content)) => Some(content),
+            Err(err) => {
+                debug!("Cannot decode a QR code: {}", err);
+                None
+            }
+        })
+        .collect();
+    Ok(codes)
+}
+
+/// Take a screenshot, decode all QR codes in it,
+/// and return those that are SIP002 `ss://` URLs.
+///
+/// The screenshot is deleted afterwards.
+pub fn scan_screen() -> Result<Vec<String>, QrScanError> {
+    let path = take_screenshot()?;
+    debug!("Screenshot saved to {:?}", path);
+    let codes = decode_qr_codes(&path);
+    if let Err(err) = fs::remove_file(&path) {
+        warn!("Cannot remove screenshot {:?}: {}", path, err);
+    }
+    let urls: Vec<_> = codes?.into_iter().filter(|code| code.starts_with("ss://")).collect();
+    debug!("Found {} ss:// QR codes on screen", urls.len());
+    Ok(urls)
+}
+
+/// Scan the screen in the background, then send the results as an `AppEvent::QrScanned` event.
+pub fn spawn_scan(events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new().name("QR scan daemon".into()).spawn(move || {
+        let res = scan_screen().map_err(|err| err.to_string());
+        if events_tx.send(AppEvent::QrScanned(res)).is_err() {
+            error!("Trying to send QrScanned event, but all receivers have hung up.");
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,284 @@
+//! This module contains code that infers the region of a server from its name,
+//! so that large lists of servers (e.g. from subscriptions) can be grouped by region.
+//!
+//! Only the name is used; looking up server addresses in an online GeoIP service
+//! would disclose the whole server list to a third party.
+
+use crate::io::profile_loader::{ProfileFolder, ProfileGroup};
+
+/// The name of the group for servers whose region cannot be inferred.
+const UNKNOWN_REGION_GROUP: &str = "Other";
+
+/// A region servers are commonly located in.
+struct Region {
+    /// The ISO 3166-1 alpha-2 code, which is also used in flag emojis.
+    code: &'static str,
+    /// The name of the group.
+    name: &'static str,
+    /// Other codes, names and cities commonly used in server names, in lowercase.
+    ///
+    /// ASCII keywords only match whole words, others match anywhere.
+    keywords: &'static [&'static str],
+}
+
+const REGIONS: &[Region] = &[
+    Region {
+        code: "HK",
+        name: "Hong Kong",
+        keywords: &["hong kong", "hongkong", "香港"],
+    },
+    Region {
+        code: "TW",
+        name: "Taiwan",
+        keywords: &["taiwan", "taipei", "台湾", "台灣", "台北"],
+    },
+    Region {
+        code: "JP",
+        name: "Japan",
+        keywords: &["japan", "tokyo", "osaka", "日本", "东京", "東京", "大阪"],
+    },
+    Region {
+        code: "KR",
+        name: "South Korea",
+        keywords: &["korea", "seoul", "韩国", "韓國", "首尔"],
+    },
+    Region {
+        code: "SG",
+        name: "Singapore",
+        keywords: &["singapore", "新加坡", "狮城"],
+    },
+    Region {
+        code: "US",
+        name: "United States",
+        keywords: &[
+            "usa",
+            "united states",
+            "america",
+            "los angeles",
+            "san jose",
+            "seattle",
+            "new york",
+            "chicago",
+            "dallas",
+            "美国",
+            "美國",
+            "洛杉矶",
+            "硅谷",
+        ],
+    },
+    Region {
+        code: "CA",
+        name: "Canada",
+        keywords: &["canada", "toronto", "vancouver", "montreal", "加拿大"],
+    },
+    Region {
+        code: "GB",
+        name: "United Kingdom",
+        keywords: &["uk", "united kingdom", "britain", "london", "英国", "英國", "伦敦"],
+    },
+    Region {
+        code: "DE",
+        name: "Germany",
+        keywords: &["germany", "frankfurt", "berlin", "德国", "德國"],
+    },
+    Region {
+        code: "FR",
+        name: "France",
+        keywords: &["france", "paris", "法国", "法國"],
+    },
+    Region {
+        code: "NL",
+        name: "Netherlands",
+        keywords: &["netherlands", "amsterdam", "荷兰", "荷蘭"],
+    },
+    Region {
+        code: "RU",
+        name: "Russia",
+        keywords: &["russia", "moscow", "俄罗斯", "俄羅斯"],
+    },
+    Region {
+        code: "IN",
+        name: "India",
+        keywords: &["india", "mumbai", "印度"],
+    },
+    Region {
+        code: "AU",
+        name: "Australia",
+        keywords: &["australia", "sydney", "melbourne", "澳大利亚", "澳洲"],
+    },
+    Region {
+        code: "TR",
+        name: "Turkey",
+        keywords: &["turkey", "türkiye", "istanbul", "土耳其"],
+    },
+    Region {
+        code: "BR",
+        name: "Brazil",
+        keywords: &["brazil", "são paulo", "sao paulo", "巴西"],
+    },
+    Region {
+        code: "AR",
+        name: "Argentina",
+        keywords: &["argentina", "阿根廷"],
+    },
+    Region {
+        code: "MY",
+        name: "Malaysia",
+        keywords: &["malaysia", "kuala lumpur", "马来西亚"],
+    },
+    Region {
+        code: "TH",
+        name: "Thailand",
+        keywords: &["thailand", "bangkok", "泰国"],
+    },
+    Region {
+        code: "VN",
+        name: "Vietnam",
+        keywords: &["vietnam", "viet nam", "越南"],
+    },
+    Region {
+        code: "PH",
+        name: "Philippines",
+        keywords: &["philippines", "manila", "菲律宾"],
+    },
+];
+
+/// Codes that are also common English words, which are only recognized in flag emojis.
+const AMBIGUOUS_CODES: &[&str] = &["IN", "MY"];
+
+/// Decode the first flag emoji in a name, i.e. a pair of regional indicator symbols, into its code.
+fn flag_code(name: &str) -> Option<String> {
+    let chars: Vec<char> = name.chars().collect();
+    chars.windows(2).find_map(|pair| {
+        let letters: Option<String> = pair
+            .iter()
+            .map(|&c| match c as u32 {
+                n @ 0x1f1e6..=0x1f1ff => char::from_u32(n - 0x1f1e6 + 'A' as u32),
+                _ => None,
+            })
+            .collect();
+        letters
+    })
+}
+
+/// Infer the region of a server from its name, returning the region's name.
+///
+/// Flag emojis are most reliable, so they are checked first, then codes (in uppercase,
+/// optionally followed by a number, e.g. `HK01`), then names of regions and cities.
+pub fn infer_region(name: &str) -> Option<&'static str> {
+    if let Some(code) = flag_code(name) {
+        if let Some(region) = REGIONS.iter().find(|r| r.code == code) {
+            return Some(region.name);
+        }
+    }
+
+    let words: Vec<&str> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let has_code = |code: &str| {
+        words
+            .iter()
+            .any(|w| w.trim_end_matches(|c: char| c.is_ascii_digit()) == code)
+    };
+    let unambiguous = |r: &&Region| !AMBIGUOUS_CODES.contains(&r.code);
+    if let Some(region) = REGIONS.iter().filter(unambiguous).find(|r| has_code(r.code)) {
+        return Some(region.name);
+    }
+
+    let lowercase = name.to_lowercase();
+    let padded = format!(" {} ", words.join(" ").to_lowercase());
+    let has_keyword = |keyword: &str| match keyword.is_ascii() {
+        true => padded.contains(&format!(" {} ", keyword)),
+        false => lowercase.contains(keyword),
+    };
+    REGIONS
+        .iter()
+        .find(|r| r.keywords.iter().any(|k| has_keyword(k)))
+        .map(|r| r.name)
+}
+
+/// Infer the regions of a batch of servers by their names, with `"Other"` for unknown ones.
+///
+/// Returns `None` if fewer than two regions are found, since grouping would only add a level of nesting.
+pub fn assign_regions<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<Vec<&'static str>> {
+    let regions: Vec<_> = names
+        .into_iter()
+        .map(|name| infer_region(name).unwrap_or(UNKNOWN_REGION_GROUP))
+        .collect();
+    let mut known: Vec<_> = regions.iter().filter(|r| **r != UNKNOWN_REGION_GROUP).collect();
+    known.sort_unstable();
+    known.dedup();
+    (known.len() >= 2).then_some(regions)
+}
+
+/// Sort profiles into a group per region, in order of first appearance,
+/// with those of unknown region in a last group.
+///
+/// Groups inherit `failover` from the group being split. Nothing is changed
+/// if `assign_regions` finds too few regions, or if `folders` contains groups.
+pub fn group_by_region(folders: Vec<ProfileFolder>, failover: bool) -> Vec<ProfileFolder> {
+    let names: Option<Vec<&str>> = folders
+        .iter()
+        .map(|folder| match folder {
+            ProfileFolder::Profile(p) => Some(p.metadata.display_name.as_str()),
+            ProfileFolder::Group(_) => None,
+        })
+        .collect();
+    let assigned = match names.and_then(assign_regions) {
+        Some(regions) => regions,
+        None => return folders,
+    };
+    let mut groups: Vec<(&str, Vec<ProfileFolder>)> = vec![];
+    for (region, folder) in assigned.into_iter().zip(folders) {
+        match groups.iter_mut().find(|(name, _)| *name == region) {
+            Some((_, content)) => content.push(folder),
+            None => groups.push((region, vec![folder])),
+        }
+    }
+    // the unknown region goes last
+    groups.sort_by_key(|(name, _)| *name == UNKNOWN_REGION_GROUP);
+    groups
+        .into_iter()
+        .map(|(name, content)| {
+            ProfileFolder::Group(ProfileGroup {
+                display_name: name.into(),
+                content,
+                failover,
+                unloaded: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assign_regions, infer_region};
+
+    #[test]
+    fn infer_regions() {
+        assert_eq!(infer_region("🇯🇵 Tokyo 01"), Some("Japan"));
+        assert_eq!(infer_region("🇺🇸 Relay via HK"), Some("United States"));
+        assert_eq!(infer_region("HK01 | IPLC"), Some("Hong Kong"));
+        assert_eq!(infer_region("[SG-2] premium"), Some("Singapore"));
+        assert_eq!(infer_region("香港 02"), Some("Hong Kong"));
+        assert_eq!(infer_region("Los Angeles #3"), Some("United States"));
+        assert_eq!(infer_region("London"), Some("United Kingdom"));
+        // codes must be uppercase words, and names whole words
+        assert_eq!(infer_region("login server"), None);
+        assert_eq!(infer_region("Fuk 1"), None);
+        assert_eq!(infer_region("MY SERVER"), None);
+        assert_eq!(infer_region("🇲🇾 MY SERVER"), Some("Malaysia"));
+        assert_eq!(infer_region("example.com:8388"), None);
+    }
+
+    #[test]
+    fn assign_batch() {
+        assert_eq!(
+            assign_regions(["JP 1", "HK 1", "backup", "JP 2"]),
+            Some(vec!["Japan", "Hong Kong", "Other", "Japan"])
+        );
+        // a single region is not worth a group
+        assert_eq!(assign_regions(["JP 1", "JP 2", "backup"]), None);
+    }
+}
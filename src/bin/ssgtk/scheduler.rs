@@ -0,0 +1,242 @@
+//! This module contains code that connects and disconnects at scheduled times,
+//! according to rules set in the app settings.
+
+use std::{
+    fmt, io,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use glib::DateTime;
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::event::AppEvent;
+
+/// A day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    /// Map an ISO 8601 day number, where Monday is 1.
+    fn from_iso(n: i32) -> Option<Self> {
+        use Weekday::*;
+        [Mon, Tue, Wed, Thu, Fri, Sat, Sun]
+            .get(usize::try_from(n - 1).ok()?)
+            .copied()
+    }
+}
+
+/// A local time of day in minutes, written as `HH:MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("\"{}\" is not a time of day in HH:MM format", s);
+        let (hour, minute) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let hour: u8 = hour.parse().map_err(|_| invalid())?;
+        let minute: u8 = minute.parse().map_err(|_| invalid())?;
+        if hour > 23 || minute > 59 {
+            return Err(invalid());
+        }
+        Ok(Self { hour, minute })
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+/// What a schedule rule does when it is due.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum ScheduleAction {
+    /// Connect to the profile with this display name.
+    Connect {
+        profile: String,
+    },
+    Disconnect,
+}
+
+impl ScheduleAction {
+    /// The event that carries out this action.
+    pub fn to_event(&self) -> AppEvent {
+        match self {
+            Self::Connect { profile } => AppEvent::ConnectByName(profile.clone()),
+            Self::Disconnect => AppEvent::Disconnect,
+        }
+    }
+}
+
+/// A rule that carries out an action at a time of day, e.g. connect at 09:00 on weekdays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleRule {
+    pub at: TimeOfDay,
+    /// The days on which the rule applies; every day if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    #[serde(flatten)]
+    pub action: ScheduleAction,
+}
+
+impl ScheduleRule {
+    /// Whether this rule is due at `time` on `day`.
+    fn is_due(&self, day: Weekday, time: TimeOfDay) -> bool {
+        self.at == time && (self.days.is_empty() || self.days.contains(&day))
+    }
+}
+
+/// Get the current local day of the week and time of day,
+/// and how long it is until the next minute.
+fn now() -> Option<(Weekday, TimeOfDay, Duration)> {
+    let now = DateTime::now_local().ok()?;
+    let day = Weekday::from_iso(now.day_of_week())?;
+    let time = TimeOfDay {
+        hour: now.hour() as u8,
+        minute: now.minute() as u8,
+    };
+    let until_next_minute = Duration::from_secs_f64((60.0 - now.seconds()).max(0.0));
+    Some((day, time, until_next_minute))
+}
+
+/// A daemon that carries out schedule rules when they are due.
+///
+/// Terminates the underlying daemon thread when dropped.
+#[derive(Debug)]
+pub struct Scheduler {
+    /// Dropped to halt the daemon.
+    halt_tx: Option<Sender<()>>,
+    /// Wrapped in `Option` so that it can be joined on drop.
+    daemon_handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        trace!("Scheduler is getting dropped");
+
+        // notify daemon halt
+        drop(self.halt_tx.take());
+
+        // wait for daemon thread to finish
+        if let Some(handle) = self.daemon_handle.take() {
+            if let Err(err) = handle.join() {
+                warn!("Scheduler daemon has panicked unexpectedly: {:?}", err);
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    /// Start the scheduler daemon, which checks `rules` at the start of every minute.
+    ///
+    /// No thread is started if there are no rules.
+    pub fn start(rules: Vec<ScheduleRule>, events_tx: Sender<AppEvent>) -> io::Result<Self> {
+        if rules.is_empty() {
+            return Ok(Self {
+                halt_tx: None,
+                daemon_handle: None,
+            });
+        }
+        debug!("Starting scheduler with {} rules", rules.len());
+        let (halt_tx, halt_rx) = bounded::<()>(0);
+
+        let handle = thread::Builder::new().name("Scheduler daemon".into()).spawn(move || {
+            // the minute last checked, so that no rule is carried out twice if woken early
+            let mut last_checked = None;
+            loop {
+                let (day, time, until_next_minute) = match now() {
+                    Some(now) => now,
+                    None => {
+                        error!("Cannot get the local time; scheduler exiting");
+                        break;
+                    }
+                };
+                if last_checked != Some((day, time)) {
+                    last_checked = Some((day, time));
+                    for rule in rules.iter().filter(|r| r.is_due(day, time)) {
+                        info!("Scheduled at {}: {:?}", rule.at, rule.action);
+                        if events_tx.send(rule.action.to_event()).is_err() {
+                            error!("Trying to send scheduled event, but all receivers have hung up.");
+                        }
+                    }
+                }
+                // wake up just after the minute has turned
+                match halt_rx.recv_timeout(until_next_minute + Duration::from_millis(100)) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+                        trace!("Scheduler halt signal received; daemon exiting");
+                        break;
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            halt_tx: Some(halt_tx),
+            daemon_handle: Some(handle),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_schedule_rules() {
+        let yaml = r#"
+- at: "09:00"
+  days: [mon, tue, wed, thu, fri]
+  action: connect
+  profile: "My-profile"
+- at: "23:30"
+  action: disconnect
+"#;
+        let rules: Vec<ScheduleRule> = serde_yaml::from_str(yaml).unwrap();
+        let nine = TimeOfDay::try_from("09:00".to_string()).unwrap();
+        assert_eq!(
+            rules[0].action,
+            ScheduleAction::Connect {
+                profile: "My-profile".into()
+            }
+        );
+        assert!(rules[0].is_due(Weekday::Fri, nine));
+        assert!(!rules[0].is_due(Weekday::Sat, nine));
+        let half_past_eleven = TimeOfDay::try_from("23:30".to_string()).unwrap();
+        assert!(rules[1].is_due(Weekday::Sun, half_past_eleven));
+        assert!(!rules[1].is_due(Weekday::Sun, nine));
+        assert_eq!(
+            serde_yaml::from_str::<Vec<ScheduleRule>>(&serde_yaml::to_string(&rules).unwrap()).unwrap(),
+            rules
+        );
+
+        assert!(TimeOfDay::try_from("24:00".to_string()).is_err());
+        assert!(TimeOfDay::try_from("9am".to_string()).is_err());
+        assert_eq!(Weekday::from_iso(7), Some(Weekday::Sun));
+        assert_eq!(Weekday::from_iso(0), None);
+    }
+}
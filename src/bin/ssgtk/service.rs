@@ -0,0 +1,441 @@
+//! This module contains code that runs the core of the app without any GUI, as `ssgtk --service`,
+//! e.g. as a systemd user service installed with `ssgtk --install-service`, or as `ssgtk --headless`.
+//!
+//! It is controlled over the runtime API only, by `ssgtkctl` or by `ssgtk` attached to it as a frontend.
+//! GTK is never initialized, so it also runs without a display.
+//! Desktop integrations (system proxy, PAC server, kill switch, etc.) are left to the GUI;
+//! with `--headless`, notifications are sent as toasts via libnotify.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bus::Bus;
+use crossbeam_channel::{unbounded as unbounded_channel, Receiver};
+use derivative::Derivative;
+use log::{debug, error, info, trace, warn};
+use shadowsocks_gtk_rs::{
+    consts::*,
+    notify_method::NotifyMethod,
+    runtime_api_msg::{APICommand, APIStateEvent, APIStatus},
+    util::{self, mutex_lock},
+};
+
+use crate::{
+    clap_def::CliArgs,
+    event::AppEvent,
+    gui::{
+        app::{load_profile_folder, AppStartError, SSLOCAL_ERROR_NOTIFY_INTERVAL},
+        notification::{notify, notify_log, Level},
+    },
+    io::{
+        app_settings::{AppSettings, NotifyEvents},
+        app_state::AppState,
+        metrics_server::MetricsServer,
+        profile_loader::ProfileFolder,
+        runtime_api::{self, APIListener, APIReply, APIRequest, StateEventsBroadcast, TcpAPIConfig},
+    },
+    profile_manager::{EventSink, ProfileManager},
+    validate,
+};
+
+/// How often queued events and commands are handled.
+const LOOP_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Service {
+    app_state_path: PathBuf,
+    profile_folder: ProfileFolder,
+    profile_manager: Arc<RwLock<ProfileManager>>,
+    events_rx: Receiver<AppEvent>,
+    /// The state loaded on startup, which the settings the service does not manage are kept from.
+    previous_state: AppState,
+
+    #[allow(dead_code)]
+    api_listener: APIListener, // this needs to be stored to be kept alive
+    api_cmds_rx: Receiver<APIRequest>,
+    #[derivative(Debug(format_with = "shadowsocks_gtk_rs::util::hacks::omit_bus"))]
+    api_events_brd: StateEventsBroadcast,
+
+    #[allow(dead_code)]
+    metrics_server: Option<MetricsServer>, // this needs to be stored to be kept alive
+
+    /// How to notify the user; only `NotifyMethod::Toast` and below, since there is no GUI.
+    notify_method: NotifyMethod,
+    settings: AppSettings,
+    /// When the user was last notified of an error logged by `sslocal`.
+    last_sslocal_error_notified: Option<Instant>,
+    /// Set once the service should exit.
+    quit: Arc<AtomicBool>,
+}
+
+impl Service {
+    /// Load profiles and the app state, then resume the core and start the runtime API.
+    fn new(args: &CliArgs) -> Result<Self, AppStartError> {
+        let settings = AppSettings::from_file_or_default(&args.app_settings_path);
+        let load_opts = validate::load_options(args);
+        let (profile_folder, load_problems) = load_profile_folder(&args.profiles_dir, &load_opts, true)?;
+        for problem in load_problems {
+            warn!("Skipped profile at {:?}: {}", problem.path, problem.error);
+        }
+        debug!(
+            "Successfully loaded {} profiles in total",
+            profile_folder.profile_count()
+        );
+
+        // load app state, overridden by settings and then by the CLI flags
+        let previous_state = {
+            let state_res = AppState::from_file(&args.app_state_path);
+            if let Err(ref err) = state_res {
+                warn!("Failed to load saved app state: {}", err);
+            }
+            let mut state = state_res.unwrap_or_default();
+            settings.apply_to(&mut state);
+            let connect_profile = args.connect.as_ref().and_then(|selector| {
+                let profile = profile_folder.lookup_selector(selector);
+                if profile.is_none() {
+                    warn!("Cannot find a profile named or numbered \"{}\"; ignored", selector);
+                }
+                profile.map(|p| p.metadata.display_name.clone())
+            });
+            if let Some(name) = &connect_profile {
+                state.most_recent_profile = name.clone();
+            }
+            // a service is restarted rather than relaunched, so it always reconnects unless told not to
+            let connect_on_startup = match (args.auto_connect || connect_profile.is_some(), args.no_auto_connect) {
+                (true, _) => true,
+                (_, true) => false,
+                _ => settings.connect_on_startup || !state.clean_exit,
+            };
+            if !connect_on_startup {
+                debug!("Connecting on startup is disabled");
+                state.most_recent_profile.clear();
+            }
+            state
+        };
+
+        // resume core
+        let (events_tx, events_rx) = unbounded_channel();
+        let pm_arc = {
+            let scope = settings.systemd_scope.clone();
+            let pm = previous_state.resume_profile_manager(scope, &profile_folder, EventSink::new(events_tx));
+            Arc::new(RwLock::new(pm))
+        };
+
+        // start metrics server; not fatal if the address is taken
+        let metrics_server = args
+            .metrics_addr
+            .map(|addr| MetricsServer::start(addr, Arc::clone(&pm_arc)))
+            .and_then(|res| res.map_err(|err| error!("Cannot start metrics server: {}", err)).ok());
+
+        // start runtime API
+        let (api_cmds_tx, api_cmds_rx) = unbounded_channel();
+        let tcp_config = TcpAPIConfig::load(
+            args.runtime_api_tcp_addr,
+            args.runtime_api_tcp_token_file.as_deref(),
+            &args.runtime_api_tcp_allowlist,
+        )?;
+        let logs_brd = Arc::clone(&util::rwlock_read(&pm_arc).logs_brd);
+        let api_events_brd: StateEventsBroadcast = Mutex::new(Bus::new(BUS_BUFFER_SIZE)).into();
+        let api_listener = APIListener::start(
+            &args.runtime_api_socket_path,
+            tcp_config,
+            api_cmds_tx,
+            logs_brd,
+            Arc::clone(&api_events_brd),
+        )?;
+
+        // a service has no one to notify but the journal
+        let notify_method = match (args.headless, previous_state.notify_method) {
+            (false, _) => NotifyMethod::Log,
+            (true, NotifyMethod::Prompt) => NotifyMethod::Toast,
+            (true, method) => method,
+        };
+
+        let service = Self {
+            app_state_path: args.app_state_path.clone(),
+            profile_folder,
+            profile_manager: pm_arc,
+            events_rx,
+            previous_state,
+            api_listener,
+            api_cmds_rx,
+            api_events_brd,
+            metrics_server,
+            notify_method,
+            settings,
+            last_sslocal_error_notified: None,
+            quit: Arc::new(AtomicBool::new(false)),
+        };
+        // mark the service as running, so that an unclean exit can be detected on the next startup
+        service.save_state(false);
+        Ok(service)
+    }
+
+    /// Save the current profile to disk, keeping all other state as loaded.
+    fn save_state(&self, clean_exit: bool) {
+        let pm = util::rwlock_read(&self.profile_manager);
+        let state = AppState {
+            most_recent_profile: pm.current_profile().map_or("".into(), |p| p.metadata.display_name),
+            collect_stats: pm.collect_stats,
+            clean_exit,
+            ..self.previous_state.clone()
+        };
+        if let Err(err) = state.write_to_file(&self.app_state_path) {
+            error!("Failed to save app state: {}", err);
+        }
+    }
+
+    /// Stop the running `sslocal`, if any, and forget the profile.
+    fn stop(&mut self) {
+        {
+            let mut pm = util::rwlock_write(&self.profile_manager);
+            if pm.is_active() {
+                info!("Sending stop signal to sslocal");
+                let _ = pm.try_stop();
+            } else {
+                info!("sslocal is not running; nothing to stop");
+            }
+        }
+        self.save_state(false);
+    }
+
+    /// Connect to the profile selected by name or index.
+    fn connect(&mut self, selector: &str) -> APIReply {
+        let profile = match self.profile_folder.lookup_selector(selector) {
+            Some(p) => p.clone(),
+            None => {
+                error!("Cannot find a profile named or numbered \"{}\"; did nothing", selector);
+                return Err(format!("no profile is named or numbered \"{}\"", selector));
+            }
+        };
+        let name = profile.metadata.display_name.clone();
+        info!("Switching profile to \"{}\"", name);
+        profile.preflight().map_err(|err| err.to_string())?;
+        if let Err(err) = util::rwlock_write(&self.profile_manager).switch_to(profile) {
+            error!("Cannot switch to profile \"{}\": {}", name, err);
+            self.publish_state_event(APIStateEvent::Disconnected);
+            return Err(err.to_string());
+        }
+        // remember the profile even if the service is not stopped cleanly
+        self.save_state(false);
+        if self.settings.notify_events.connect {
+            self.notify(Level::Info, "Connected", format!("Profile \"{}\"", name));
+        }
+        self.publish_state_event(APIStateEvent::Connected { profile: name });
+        Ok(serde_json::Value::Null)
+    }
+
+    /// Restart the current profile, if any.
+    fn restart(&mut self) -> APIReply {
+        let current = util::rwlock_read(&self.profile_manager).current_profile();
+        match current {
+            Some(p) => self.connect(&p.metadata.display_name),
+            None => Err("no profile is running".into()),
+        }
+    }
+
+    /// Exit the main loop, after which the service is shut down.
+    fn quit(&self) {
+        info!("Quit");
+        self.quit.store(true, Ordering::SeqCst);
+    }
+
+    /// Save the state, then stop `sslocal`.
+    fn shutdown(&mut self) {
+        self.save_state(true);
+        let _ = util::rwlock_write(&self.profile_manager).try_stop();
+    }
+
+    /// Handles the queued events of the profile manager.
+    fn handle_app_events(&mut self) {
+        use AppEvent::*;
+        while let Some(event) = self.events_rx.try_iter().next() {
+            trace!("Received an AppEvent: {:?}", event);
+            match event {
+                OkStop { .. } => {
+                    // an old instance may have been stopped for a new one
+                    if !util::rwlock_read(&self.profile_manager).is_active() {
+                        self.publish_state_event(APIStateEvent::Disconnected);
+                        if self.settings.notify_events.disconnect {
+                            self.notify(Level::Warn, "Auto-restart Stopped", "sslocal has stopped");
+                        }
+                    }
+                }
+                ErrorStop { instance_name, err } => {
+                    let text_2 = format!(
+                        "An instance has errored: {}\n{}",
+                        instance_name.as_deref().unwrap_or("None"),
+                        err
+                    );
+                    self.notify(Level::Error, "Auto-restart Stopped", text_2);
+                    self.publish_state_event(APIStateEvent::Error {
+                        profile: instance_name,
+                        message: err.to_string(),
+                    });
+                    self.publish_state_event(APIStateEvent::Disconnected);
+                }
+                RestartScheduled {
+                    profile_name,
+                    attempt,
+                    delay,
+                } => {
+                    if self.settings.notify_events.restart {
+                        let text_2 = format!(
+                            "Profile \"{}\" has failed; restart attempt {} in {:.1}s",
+                            profile_name,
+                            attempt,
+                            delay.as_secs_f64()
+                        );
+                        self.notify(Level::Warn, "Restarting sslocal", text_2);
+                    }
+                    self.publish_state_event(APIStateEvent::Restarting {
+                        profile: profile_name,
+                        attempt,
+                    });
+                }
+                SslocalError {
+                    profile_name,
+                    line,
+                    kind,
+                } => {
+                    self.on_sslocal_error(&profile_name, &line, kind.hint());
+                    self.publish_state_event(APIStateEvent::Error {
+                        profile: Some(profile_name),
+                        message: line,
+                    });
+                }
+                Quit => self.quit(),
+                event => debug!("Ignored {:?}, which only the GUI handles", event),
+            }
+        }
+    }
+
+    /// Handles the queued incoming runtime API commands.
+    fn handle_api_commands(&mut self) {
+        use serde_json::Value::Null;
+        use APICommand::*;
+
+        while let Some(APIRequest { cmd, reply_tx }) = self.api_cmds_rx.try_iter().next() {
+            let reply: APIReply = match cmd {
+                LogViewerShow | LogViewerHide => Err("not supported without the GUI".into()),
+                SetNotify(NotifyMethod::Prompt) => Err("prompts are not supported without the GUI".into()),
+                SetNotify(method) => {
+                    info!("Setting notify method to {}", method);
+                    self.notify_method = method;
+                    Ok(Null)
+                }
+                GetStatus | Subscribe => {
+                    let pm = util::rwlock_read(&self.profile_manager);
+                    let status = APIStatus {
+                        headless: true,
+                        ..runtime_api::api_status(&pm, vec![])
+                    };
+                    serde_json::to_value(status).map_err(|err| err.to_string())
+                }
+                GetBacklog(lines) | FollowBacklog(lines) => {
+                    Ok(runtime_api::backlog_tail(&util::rwlock_read(&self.profile_manager), lines).into())
+                }
+                ListProfiles => {
+                    let pm = util::rwlock_read(&self.profile_manager);
+                    let tree = runtime_api::api_profile_tree(&self.profile_folder, &pm);
+                    serde_json::to_value(tree).map_err(|err| err.to_string())
+                }
+                GetLaunchCommand(selector) => match self.profile_folder.lookup_selector(&selector) {
+                    Some(p) => p
+                        .launch_preview(false)
+                        .map_err(|err| err.to_string())
+                        .and_then(|preview| {
+                            serde_json::to_value(runtime_api::to_api_launch_command(preview))
+                                .map_err(|err| err.to_string())
+                        }),
+                    None => Err(format!("no profile is named or numbered \"{}\"", selector)),
+                },
+                Restart => self.restart(),
+                SwitchProfile(name) => match self.profile_folder.lookup(&name) {
+                    Some(_) => self.connect(&name),
+                    None => Err(format!("no profile is named \"{}\"", name)),
+                },
+                Connect(selector) => self.connect(&selector),
+                Stop => {
+                    self.stop();
+                    Ok(Null)
+                }
+                Quit => {
+                    self.quit();
+                    Ok(Null)
+                }
+            };
+            if let Some(tx) = reply_tx {
+                // error messages may contain passwords
+                let reply = reply.map_err(|msg| crate::redact::scrub(&msg).into_owned());
+                let _ = tx.send(reply);
+            }
+        }
+    }
+
+    /// Send a notification using the current method,
+    /// or only log it if it is less severe than the configured level.
+    fn notify(&self, level: Level, text_1: impl AsRef<str>, text_2: impl AsRef<str>) {
+        match self.settings.notify_level.allows(level) {
+            true => notify(self.notify_method, level, None, text_1, text_2),
+            false => notify_log(level, text_1.as_ref(), text_2.as_ref()),
+        }
+    }
+
+    /// Notify the user of an error logged by `sslocal`, if enabled,
+    /// at most once every `SSLOCAL_ERROR_NOTIFY_INTERVAL`.
+    fn on_sslocal_error(&mut self, profile_name: &str, line: &str, hint: Option<&str>) {
+        let NotifyEvents { sslocal_errors, .. } = self.settings.notify_events;
+        let throttled = self
+            .last_sslocal_error_notified
+            .is_some_and(|at| at.elapsed() < SSLOCAL_ERROR_NOTIFY_INTERVAL);
+        if !sslocal_errors || throttled {
+            return;
+        }
+        self.last_sslocal_error_notified = Some(Instant::now());
+        let title = match hint {
+            Some(hint) => format!("sslocal Error: {}", hint),
+            None => "sslocal Error".into(),
+        };
+        self.notify(Level::Error, title, format!("Profile \"{}\": {}", profile_name, line));
+    }
+
+    /// Tell runtime API subscribers about a change of state.
+    fn publish_state_event(&self, event: APIStateEvent) {
+        if let Err(event) = mutex_lock(&self.api_events_brd).try_broadcast(event) {
+            trace!("A runtime API subscriber is lagging; dropped {:?}", event);
+        }
+    }
+}
+
+/// Run the core without any GUI until told to quit, over the runtime API or by a signal.
+pub fn run(args: &CliArgs) -> Result<(), AppStartError> {
+    let mut service = Service::new(args)?;
+
+    let quit = Arc::clone(&service.quit);
+    ctrlc::set_handler(move || {
+        info!("Signal received, quitting");
+        quit.store(true, Ordering::SeqCst);
+    })?;
+
+    match args.headless {
+        true => info!("Started headless"),
+        false => info!("Service started"),
+    }
+    while !service.quit.load(Ordering::SeqCst) {
+        service.handle_app_events();
+        service.handle_api_commands();
+        thread::sleep(LOOP_INTERVAL);
+    }
+    // reached on a signal too, e.g. `systemctl --user stop`
+    service.shutdown();
+    Ok(())
+}
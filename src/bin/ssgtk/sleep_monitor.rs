@@ -0,0 +1,146 @@
+//! This module contains code that watches logind for system suspend and resume,
+//! so that `sslocal` can be stopped cleanly before sleeping and restored after waking up,
+//! and that optionally keeps the system awake while connected.
+//!
+//! See https://www.freedesktop.org/software/systemd/man/org.freedesktop.login1.html.
+
+use std::{io, thread, time::Duration};
+
+use crossbeam_channel::{bounded, Sender};
+use log::{debug, error, info, warn};
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::OwnedFd,
+};
+
+use crate::event::AppEvent;
+
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Give up waiting for the app to get ready for sleep after this long.
+///
+/// logind itself only waits for `InhibitDelayMaxSec`, which defaults to 5 seconds.
+const PREPARE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Take a delay inhibitor lock, which holds off sleep until it is released (i.e. closed).
+fn inhibit_sleep(logind: &Proxy) -> zbus::Result<OwnedFd> {
+    logind.call(
+        "Inhibit",
+        &("sleep", "shadowsocks-gtk-rs", "Stopping sslocal cleanly", "delay"),
+    )
+}
+
+/// Take a block inhibitor lock, which prevents idle and sleep until it is released (i.e. closed).
+fn block_sleep() -> zbus::Result<OwnedFd> {
+    let conn = Connection::system()?;
+    let logind = Proxy::new(&conn, LOGIND_DESTINATION, LOGIND_PATH, LOGIND_INTERFACE)?;
+    logind.call(
+        "Inhibit",
+        &(
+            "sleep:idle",
+            "shadowsocks-gtk-rs",
+            "Keeping awake while connected",
+            "block",
+        ),
+    )
+}
+
+/// Keeps the system from idling or sleeping while connected, if enabled,
+/// e.g. so that long downloads through the proxy are not interrupted.
+#[derive(Debug)]
+pub struct KeepAwake {
+    enabled: bool,
+    /// Held while the system is kept awake; released on drop.
+    lock: Option<OwnedFd>,
+}
+
+impl KeepAwake {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, lock: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable keeping awake, taking or releasing the lock right away.
+    pub fn set_enabled(&mut self, enable: bool, connected: bool) -> zbus::Result<()> {
+        self.enabled = enable;
+        self.sync(connected)
+    }
+
+    /// Take the lock if enabled and `connected`, or else release it.
+    ///
+    /// Errors if logind is unavailable, e.g. on systems without systemd.
+    pub fn sync(&mut self, connected: bool) -> zbus::Result<()> {
+        match (self.enabled && connected, self.lock.is_some()) {
+            (true, false) => {
+                self.lock = Some(block_sleep()?);
+                info!("Keeping the system awake while connected");
+            }
+            (false, true) => {
+                drop(self.lock.take());
+                info!("No longer keeping the system awake");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Keep turning logind's `PrepareForSleep` signals into events,
+/// holding off sleep until the app has handled them.
+fn listen(events_tx: &Sender<AppEvent>) -> zbus::Result<()> {
+    let conn = Connection::system()?;
+    let logind = Proxy::new(&conn, LOGIND_DESTINATION, LOGIND_PATH, LOGIND_INTERFACE)?;
+    let signals = logind.receive_signal("PrepareForSleep")?;
+    let mut inhibitor = Some(inhibit_sleep(&logind)?);
+
+    for msg in signals {
+        let sleeping: bool = msg.body()?;
+        if sleeping {
+            debug!("System is about to sleep");
+            let (done_tx, done_rx) = bounded(1);
+            if events_tx.send(AppEvent::PrepareForSleep { done_tx }).is_err() {
+                error!("Trying to send PrepareForSleep event, but all receivers have hung up.");
+                break;
+            }
+            if done_rx.recv_timeout(PREPARE_TIMEOUT).is_err() {
+                warn!(
+                    "Not ready for sleep after {:?}; letting the system sleep anyway",
+                    PREPARE_TIMEOUT
+                );
+            }
+            drop(inhibitor.take()); // allow sleep
+        } else {
+            debug!("System has woken up");
+            // hold off the next sleep again
+            match inhibit_sleep(&logind) {
+                Ok(fd) => inhibitor = Some(fd),
+                Err(err) => warn!("Cannot hold off sleep; sslocal may not stop cleanly next time: {}", err),
+            }
+            if events_tx.send(AppEvent::ResumedFromSleep).is_err() {
+                error!("Trying to send ResumedFromSleep event, but all receivers have hung up.");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Watch for system suspend and resume in the background.
+///
+/// If logind is unavailable, e.g. on systems without systemd, sleep is simply not acted upon,
+/// so the failure is only logged.
+pub fn spawn_watcher(events_tx: Sender<AppEvent>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("Sleep monitor daemon".into())
+        .spawn(move || {
+            if let Err(err) = listen(&events_tx) {
+                debug!("Cannot watch for system sleep: {}", err);
+            }
+        })?;
+    Ok(())
+}
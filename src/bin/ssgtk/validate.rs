@@ -0,0 +1,141 @@
+//! This module contains code that checks a profiles directory without starting the app,
+//! e.g. in the CI of a dotfiles repository.
+
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::Serialize;
+
+#[cfg(feature = "encryption")]
+use crate::io::encryption;
+use crate::{
+    clap_def::CliArgs,
+    gui::app::load_profile_folder,
+    io::{
+        app_settings::AppSettings,
+        profile_loader::{Backend, LoadOptions, LoadProblem, ProfileFolder},
+    },
+};
+
+/// A profile that can be loaded, and what it would be launched with.
+#[derive(Debug, Serialize)]
+struct ProfileReport {
+    name: String,
+    /// The groups containing this profile, outermost first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<String>,
+    mode: &'static str,
+    backend: Backend,
+    binary: PathBuf,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    runs_as_root: bool,
+    /// Why this profile cannot be launched, if it cannot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A profile or group that cannot be loaded.
+#[derive(Debug, Serialize)]
+struct ProblemReport {
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    error: String,
+}
+
+impl From<LoadProblem> for ProblemReport {
+    fn from(problem: LoadProblem) -> Self {
+        Self {
+            path: problem.path,
+            name: problem.name,
+            error: problem.error.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ValidationReport {
+    profiles: Vec<ProfileReport>,
+    problems: Vec<ProblemReport>,
+}
+
+/// Get the options the app would load profiles with, as set by the CLI arguments and the app settings.
+pub fn load_options(args: &CliArgs) -> LoadOptions {
+    let settings = AppSettings::from_file_or_default(&args.app_settings_path);
+    LoadOptions {
+        follow_symlinks: args.follow_symlinks,
+        default_bin_path: settings.bin_path,
+        name_conflicts: settings.name_conflicts,
+        max_depth: settings.max_load_depth,
+        progress: None,
+        // every profile is validated
+        lazy: false,
+    }
+}
+
+/// Load all profiles in `dir` like the app would, except for subscriptions,
+/// and print a YAML report of each profile and each problem found to stdout.
+///
+/// Returns whether all profiles can be loaded and launched.
+pub fn validate(dir: &Path, opts: &LoadOptions) -> bool {
+    // the master passphrase can be piped in if it is not in the keyring
+    #[cfg(feature = "encryption")]
+    if encryption::find_encrypted_file(dir).is_some() && !encryption::has_passphrase() {
+        match encryption::prompt_tty(false) {
+            Ok(passphrase) => encryption::set_passphrase(passphrase),
+            Err(err) => warn!("Encrypted profiles cannot be loaded: {}", err),
+        }
+    }
+    let (profiles, problems) = match load_profile_folder(dir, opts, false) {
+        Ok((profile_folder, problems)) => {
+            let mut profiles = vec![];
+            // the topmost layer is not shown as a group
+            match &profile_folder {
+                ProfileFolder::Group(g) => g
+                    .content
+                    .iter()
+                    .for_each(|pf| collect_profiles(pf, &mut vec![], &mut profiles)),
+                profile => collect_profiles(profile, &mut vec![], &mut profiles),
+            }
+            (profiles, problems.into_iter().map(ProblemReport::from).collect())
+        }
+        Err(err) => {
+            let problem = ProblemReport {
+                path: dir.into(),
+                name: None,
+                error: err.to_string(),
+            };
+            (vec![], vec![problem])
+        }
+    };
+
+    let is_valid = problems.is_empty() && profiles.iter().all(|p| p.error.is_none());
+    let report = ValidationReport { profiles, problems };
+    match serde_yaml::to_string(&report) {
+        Ok(yaml) => print!("{}", yaml),
+        Err(err) => eprintln!("Cannot serialize the validation report: {}", err),
+    }
+    is_valid
+}
+
+/// Recursively add a report of each profile in `profile_folder` to `reports`.
+///
+/// `groups` are the names of the groups containing `profile_folder`, outermost first.
+fn collect_profiles(profile_folder: &ProfileFolder, groups: &mut Vec<String>, reports: &mut Vec<ProfileReport>) {
+    match profile_folder {
+        ProfileFolder::Profile(p) => reports.push(ProfileReport {
+            name: p.metadata.display_name.clone(),
+            groups: groups.clone(),
+            mode: p.config().mode(),
+            backend: p.metadata.backend,
+            binary: p.metadata.bin_path().into(),
+            runs_as_root: p.needs_privilege(),
+            error: p.preflight().err().map(String::from),
+        }),
+        ProfileFolder::Group(g) => {
+            groups.push(g.display_name.clone());
+            g.content.iter().for_each(|pf| collect_profiles(pf, groups, reports));
+            groups.pop();
+        }
+    }
+}
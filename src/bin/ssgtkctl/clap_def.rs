@@ -21,6 +21,20 @@ pub struct CliArgs {
     #[clap(short = 'a', long = "api-socket", value_name = "PATH", default_value_os = RUNTIME_API_SOCKET_PATH_DEFAULT.as_os_str())]
     pub runtime_api_socket_path: PathBuf,
 
+    /// Send command to an instance listening on TCP instead, e.g. `htpc.local:1090`.
+    ///
+    /// See `ssgtk --help` for how to enable the TCP listener.
+    #[clap(long = "tcp", value_name = "HOST:PORT", requires = "token-file")]
+    pub tcp_addr: Option<String>,
+
+    /// Read the shared token for the TCP listener from this file.
+    #[clap(long = "token-file", value_name = "PATH", requires = "tcp-addr")]
+    pub token_file: Option<PathBuf>,
+
+    /// Print the raw JSON result instead of a human-readable summary.
+    #[clap(long = "json")]
+    pub json: bool,
+
     /// Print examples of how to interface with the Unix socket directly.
     #[clap(long = "print-socket-examples")]
     pub print_socket_examples: bool,
@@ -55,11 +69,71 @@ pub enum SubCmd {
         profile_name: String,
     },
 
+    /// Switch to a profile by its display name, or by its number shown by `list-profiles`.
+    ///
+    /// Useful for launchers such as rofi or dmenu.
+    Connect {
+        /// The display name (CASE SENSITIVE) or number of the profile.
+        #[clap(index = 1, value_name = "NAME|INDEX")]
+        profile: String,
+    },
+
     /// Stop the currently running sslocal instance.
+    #[clap(alias = "disconnect")]
     Stop,
 
     /// Quit the application.
     Quit,
+
+    /// Show whether sslocal is running, with which profile, its PID, uptime and restarts,
+    /// and its traffic if collected.
+    Status {
+        /// Print the JSON that waybar custom modules expect (`text`, `tooltip` and `class`),
+        /// with `"return-type": "json"`. Also usable from polybar scripts.
+        #[clap(long = "waybar")]
+        waybar: bool,
+
+        /// With `--waybar`, keep printing a line each time the state changes, until interrupted.
+        #[clap(short = 'f', long = "follow", requires = "waybar")]
+        follow: bool,
+    },
+
+    /// List all profiles as a numbered tree, marking the active one with `*`.
+    ListProfiles {
+        /// Only list profiles with this tag, keeping their numbers.
+        ///
+        /// Ignored with `--json`, which includes the tags of every profile.
+        #[clap(long, value_name = "TAG")]
+        tag: Option<String>,
+    },
+
+    /// Print the command a profile would be launched with, with passwords masked, without launching it.
+    ///
+    /// Useful to find out why sslocal misbehaves.
+    LaunchCommand {
+        /// The display name (CASE SENSITIVE) or number of the profile.
+        #[clap(index = 1, value_name = "NAME|INDEX")]
+        profile: String,
+
+        /// Also print the environment variables sslocal would inherit.
+        #[clap(long = "env")]
+        env: bool,
+    },
+
+    /// Print the most recent lines of the backlog.
+    Backlog {
+        /// The number of lines to print.
+        #[clap(short = 'n', long = "lines", value_name = "N", default_value_t = 50)]
+        lines: usize,
+
+        /// Keep printing new lines as sslocal outputs them, until interrupted.
+        #[clap(short = 'f', long = "follow")]
+        follow: bool,
+    },
+
+    /// Print the status as JSON, then keep printing a line of JSON for each change of state,
+    /// such as connecting, disconnecting, restarting and errors, until interrupted.
+    Subscribe,
 }
 
 impl From<SubCmd> for APICommand {
@@ -70,8 +144,16 @@ impl From<SubCmd> for APICommand {
             SubCmd::SetNotify { notify_method } => APICommand::SetNotify(notify_method),
             SubCmd::Restart => APICommand::Restart,
             SubCmd::SwitchProfile { profile_name } => APICommand::SwitchProfile(profile_name),
+            SubCmd::Connect { profile } => APICommand::Connect(profile),
             SubCmd::Stop => APICommand::Stop,
             SubCmd::Quit => APICommand::Quit,
+            SubCmd::Status { follow: false, .. } => APICommand::GetStatus,
+            SubCmd::Status { follow: true, .. } => APICommand::Subscribe,
+            SubCmd::ListProfiles { .. } => APICommand::ListProfiles,
+            SubCmd::LaunchCommand { profile, .. } => APICommand::GetLaunchCommand(profile),
+            SubCmd::Backlog { lines, follow: false } => APICommand::GetBacklog(lines),
+            SubCmd::Backlog { lines, follow: true } => APICommand::FollowBacklog(lines),
+            SubCmd::Subscribe => APICommand::Subscribe,
         }
     }
 }
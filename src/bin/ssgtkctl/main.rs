@@ -1,6 +1,7 @@
 use std::{
-    io::{self, Write},
-    net,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
     os::unix::net::UnixStream,
     path::Path,
     time::Duration,
@@ -8,14 +9,24 @@ use std::{
 
 use clap::{IntoApp, Parser};
 use clap_def::CliArgs;
-use shadowsocks_gtk_rs::{notify_method::NotifyMethod, runtime_api_msg::APICommand};
+use serde_json::Value;
+use shadowsocks_gtk_rs::{
+    notify_method::NotifyMethod,
+    runtime_api_msg::{jsonrpc, tcp::*, APICommand, APILaunchCommand, APIProfileTree, APIStateEvent, APIStatus},
+    util::format_duration,
+};
+use waybar::BarState;
 
 mod clap_def;
+mod waybar;
 
 fn main() -> io::Result<()> {
     // init clap app
     let CliArgs {
         runtime_api_socket_path,
+        tcp_addr,
+        token_file,
+        json,
         sub_cmd,
         print_socket_examples,
     } = CliArgs::parse();
@@ -35,14 +46,224 @@ fn main() -> io::Result<()> {
     };
 
     // send
-    let send_res = send_cmd(runtime_api_socket_path, sub_cmd.into());
-    match &send_res {
-        Ok(_) => println!("Command sent successfully"),
-        Err(_) => println!("Failed to send command"),
+    let tag_filter = match &sub_cmd {
+        clap_def::SubCmd::ListProfiles { tag } => tag.clone(),
+        _ => None,
+    };
+    let show_env = matches!(sub_cmd, clap_def::SubCmd::LaunchCommand { env: true, .. });
+    let waybar = matches!(sub_cmd, clap_def::SubCmd::Status { waybar: true, .. });
+    let cmd: APICommand = sub_cmd.into();
+    let send_res = match (tcp_addr, token_file) {
+        (Some(addr), Some(token_file)) => send_cmd_tcp(addr, token_file, &cmd),
+        _ => send_cmd(runtime_api_socket_path, &cmd),
+    };
+    let (result, reader) = match send_res {
+        Ok(res) => res,
+        Err(err) => {
+            println!("Failed to send command");
+            return Err(err);
+        }
+    };
+    if waybar {
+        let status = serde_json::from_value::<APIStatus>(result)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        return follow_waybar(BarState::new(status), reader, matches!(cmd, APICommand::Subscribe));
+    }
+    match json {
+        true => println!("{}", result),
+        false => print_result(&cmd, result, tag_filter.as_deref(), show_env),
+    }
+    match cmd {
+        APICommand::FollowBacklog(_) => follow(reader, json, jsonrpc::BACKLOG_LINE_METHOD, |param| {
+            if let Some(line) = param.as_str() {
+                println!("{}", line);
+            }
+        })?,
+        // one JSON object per line, so that it can be piped into `jq` and the like
+        APICommand::Subscribe => follow(reader, json, jsonrpc::STATE_EVENT_METHOD, |param| {
+            println!("{}", param);
+        })?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Print each parameter of the `method` notifications streamed after a `FollowBacklog`
+/// or `Subscribe` command with `print_param`, until the server disconnects.
+///
+/// With `json`, the notifications are printed as they are.
+fn follow(reader: Box<dyn BufRead>, json: bool, method: &str, print_param: fn(&Value)) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if json {
+            println!("{}", line);
+            continue;
+        }
+        match serde_json::from_str::<jsonrpc::Request>(&line) {
+            Ok(req) if req.method == method => {
+                req.params
+                    .iter()
+                    .filter_map(Value::as_array)
+                    .flatten()
+                    .for_each(print_param);
+            }
+            _ => eprintln!("Unexpected message: {}", line),
+        }
+    }
+    Ok(())
+}
+
+/// Print the state for waybar, then if `follow`, print it again after each state event
+/// streamed after a `Subscribe` command, until the server disconnects.
+fn follow_waybar(mut state: BarState, reader: Box<dyn BufRead>, follow: bool) -> io::Result<()> {
+    let print = |state: &BarState| -> io::Result<()> {
+        let json = serde_json::to_string(&state.output()).expect("serialising a waybar output is infallible");
+        // waybar reads line by line, and stdout is not line buffered when piped
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", json)?;
+        stdout.flush()
+    };
+    print(&state)?;
+    if !follow {
+        return Ok(());
+    }
+    for line in reader.lines() {
+        let line = line?;
+        let event = serde_json::from_str::<jsonrpc::Request>(&line)
+            .ok()
+            .filter(|req| req.method == jsonrpc::STATE_EVENT_METHOD)
+            .and_then(|req| req.params?.as_array()?.first().cloned())
+            .and_then(|param| serde_json::from_value::<APIStateEvent>(param).ok());
+        match event {
+            Some(event) => {
+                state.apply(event);
+                print(&state)?;
+            }
+            None => eprintln!("Unexpected message: {}", line),
+        }
+    }
+    Ok(())
+}
+
+/// Print the result of a command in a human-readable form.
+///
+/// `tag_filter` limits the profiles listed by `ListProfiles`,
+/// and `show_env` prints the environment of `GetLaunchCommand`.
+fn print_result(cmd: &APICommand, result: Value, tag_filter: Option<&str>, show_env: bool) {
+    match cmd {
+        APICommand::GetStatus => match serde_json::from_value::<APIStatus>(result) {
+            Ok(status) => {
+                let state = if status.running { "running" } else { "stopped" };
+                match status.profile {
+                    Some(profile) => println!("sslocal is {} with profile \"{}\"", state, profile),
+                    None => println!("sslocal is {}", state),
+                }
+                if let Some(uptime_secs) = status.uptime_secs {
+                    let restarts = status.restarts.unwrap_or_default();
+                    let pid = match status.pid {
+                        Some(pid) => format!("PID {}", pid),
+                        None => "In-process".into(),
+                    };
+                    println!(
+                        "{}, up for {}, restarted {} times",
+                        pid,
+                        format_duration(uptime_secs),
+                        restarts
+                    );
+                }
+                if let Some(connections) = status.connections {
+                    println!("Open connections: {}", connections);
+                }
+                if let Some(connected_secs) = status.connected_secs {
+                    println!("Connected for {}", format_duration(connected_secs));
+                }
+                if let Some(err) = status.last_error {
+                    println!("Last error: {}", err);
+                }
+                if !status.alongside.is_empty() {
+                    println!("Also running: {}", status.alongside.join(", "));
+                }
+                if let Some(stats) = status.stats {
+                    println!(
+                        "Sent {} bytes ({:.0} B/s), received {} bytes ({:.0} B/s)",
+                        stats.tx_bytes, stats.tx_rate, stats.rx_bytes, stats.rx_rate
+                    );
+                }
+            }
+            Err(err) => println!("Unexpected status: {}", err),
+        },
+        APICommand::Subscribe => println!("{}", result),
+        APICommand::GetBacklog(_) | APICommand::FollowBacklog(_) => {
+            for line in result.as_array().into_iter().flatten().filter_map(Value::as_str) {
+                println!("{}", line);
+            }
+        }
+        APICommand::ListProfiles => match serde_json::from_value::<Vec<APIProfileTree>>(result) {
+            Ok(trees) => {
+                let mut index = 0;
+                trees
+                    .iter()
+                    .for_each(|tree| print_profile_tree(tree, 0, &mut index, tag_filter));
+            }
+            Err(err) => println!("Unexpected profile list: {}", err),
+        },
+        APICommand::GetLaunchCommand(_) => match serde_json::from_value::<APILaunchCommand>(result) {
+            Ok(launch) => {
+                if launch.as_root {
+                    println!("# run as root via pkexec, which resets the environment");
+                }
+                if show_env {
+                    for (key, value) in launch.env.iter() {
+                        println!("{}={}", key, value);
+                    }
+                }
+                println!("{}", launch.shell_command);
+            }
+            Err(err) => println!("Unexpected launch command: {}", err),
+        },
+        _ => println!("Command sent successfully"),
+    }
+}
+
+/// Print a profile tree with one entry per line, indenting the content of groups.
+///
+/// Profiles are numbered from `index + 1`, which is advanced past them,
+/// including those hidden by `tag_filter` so that the numbers work with `Connect`.
+/// Groups without any profile shown are hidden too.
+fn print_profile_tree(tree: &APIProfileTree, depth: usize, index: &mut usize, tag_filter: Option<&str>) {
+    let indent = "  ".repeat(depth);
+    match tree {
+        APIProfileTree::Group { group, content } => {
+            if has_tag(tree, tag_filter) {
+                println!("       {}{}/", indent, group);
+            }
+            content
+                .iter()
+                .for_each(|child| print_profile_tree(child, depth + 1, index, tag_filter));
+        }
+        APIProfileTree::Profile { profile, active, tags } => {
+            *index += 1;
+            if !has_tag(tree, tag_filter) {
+                return;
+            }
+            let marker = if *active { '*' } else { ' ' };
+            match tags.is_empty() {
+                true => println!("{} {:>3}  {}{}", marker, index, indent, profile),
+                false => println!("{} {:>3}  {}{} [{}]", marker, index, indent, profile, tags.join(", ")),
+            }
+        }
+    }
+}
+
+/// Whether a profile tree contains a profile with `tag`, or anything at all if `None`.
+fn has_tag(tree: &APIProfileTree, tag: Option<&str>) -> bool {
+    match tree {
+        APIProfileTree::Group { content, .. } => content.iter().any(|child| has_tag(child, tag)),
+        APIProfileTree::Profile { tags, .. } => tag.is_none_or(|tag| tags.iter().any(|t| t == tag)),
     }
-    send_res
 }
 
+/// Format a duration in seconds like `1d 2h 3m 4s`, omitting leading zero units.
 fn print_socket_egs() {
     use APICommand::*;
     let egs = vec![
@@ -51,20 +272,29 @@ fn print_socket_egs() {
         SetNotify(NotifyMethod::Toast),
         Restart,
         SwitchProfile("Example Profile".into()),
+        Connect("3".into()),
         Stop,
         Quit,
+        GetStatus,
+        GetBacklog(50),
+        FollowBacklog(50),
+        Subscribe,
+        ListProfiles,
+        GetLaunchCommand("3".into()),
     ];
     println!("{}", "-".repeat(50));
     println!("Here are some of the commands you can issue (CASE SENSITIVE):");
-    for cmd in egs.into_iter() {
-        let cmd_str = json5::to_string(&cmd).expect("Manually created, shouldn't error");
-        println!("\t`echo \'{}\' | nc -U /path/to/shadowsocks-gtk-rs.sock`", cmd_str);
+    for (id, cmd) in egs.into_iter().enumerate() {
+        let req = jsonrpc::Request::new(&cmd, id + 1);
+        let req_str = serde_json::to_string(&req).expect("Manually created, shouldn't error");
+        println!("\t`echo \'{}\' | nc -U /path/to/shadowsocks-gtk-rs.sock`", req_str);
     }
     println!(
         "Note 0: you likely need the BSD variant of netcat to be able to connect \
         to Unix sockets (see https://unix.stackexchange.com/a/26781/375550)\n\
-        Note 1: due to technical limitations and my laziness (mainly the latter) \
-        the JSON5 command string must be a single line"
+        Note 1: each request must be a single line of JSON-RPC 2.0, \
+        and is answered with a single line; omit `id` if you don't need an answer\n\
+        Note 2: plain JSON5 commands such as `\"restart\"` are still accepted, but get no answer"
     );
     println!(
         "For the default socket path and how to manually set a different one, see\n\
@@ -73,14 +303,80 @@ fn print_socket_egs() {
     println!("{}", "-".repeat(50));
 }
 
-fn send_cmd(destination: impl AsRef<Path>, cmd: APICommand) -> io::Result<()> {
-    let mut socket = UnixStream::connect(destination)?;
+/// Send a JSON-RPC request for `cmd` over `stream`, and return the result,
+/// along with the reader for anything the server sends afterwards.
+fn request(stream: impl io::Read + Write + 'static, cmd: &APICommand) -> io::Result<(Value, Box<dyn BufRead>)> {
+    let mut reader = BufReader::new(stream);
+    let req = jsonrpc::Request::new(cmd, 1);
+    let req_str = serde_json::to_string(&req).expect("serialising a JSON-RPC request is infallible");
+    writeln!(reader.get_mut(), "{}", req_str)?;
+    reader.get_mut().flush()?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let res: jsonrpc::Response =
+        serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    match (res.result, res.error) {
+        (_, Some(err)) if err.code == jsonrpc::FORBIDDEN => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "command is not allowed over TCP by the instance",
+        )),
+        (_, Some(err)) => Err(io::Error::other(format!("{} ({})", err.message, err.code))),
+        (result, None) => Ok((result.unwrap_or(Value::Null), Box::new(reader))),
+    }
+}
+
+/// How long to wait for a response to `cmd`; `None` if more is streamed indefinitely after it.
+fn read_timeout(cmd: &APICommand) -> Option<Duration> {
+    match cmd {
+        APICommand::FollowBacklog(_) | APICommand::Subscribe => None,
+        _ => Some(Duration::from_secs(5)),
+    }
+}
+
+fn send_cmd(destination: impl AsRef<Path>, cmd: &APICommand) -> io::Result<(Value, Box<dyn BufRead>)> {
+    let socket = UnixStream::connect(destination)?;
+    socket.set_read_timeout(read_timeout(cmd))?;
     socket.set_write_timeout(Some(Duration::from_secs(3)))?;
-    socket.write_all(
-        json5::to_string(&cmd)
-            .expect("serialising APICommand to json5 is infallible")
-            .as_bytes(),
-    )?;
-    socket.flush()?;
-    socket.shutdown(net::Shutdown::Both)
+    request(socket, cmd)
+}
+
+fn send_cmd_tcp(
+    destination: impl ToSocketAddrs,
+    token_file: impl AsRef<Path>,
+    cmd: &APICommand,
+) -> io::Result<(Value, Box<dyn BufRead>)> {
+    let token = fs::read_to_string(token_file)?.trim().to_string();
+    let addr = destination
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot resolve address"))?;
+    let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(3))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(3)))?;
+    let mut writer = &stream;
+    let mut reader = BufReader::new(&stream);
+    let mut read_line = || -> io::Result<String> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        Ok(line.trim().to_string())
+    };
+    let protocol_error = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    // handshake
+    let greeting = read_line()?;
+    let nonce = match greeting.split_once(' ') {
+        Some((TCP_GREETING, nonce)) => nonce.to_string(),
+        _ => return Err(protocol_error(format!("unexpected greeting \"{}\"", greeting))),
+    };
+    writeln!(writer, "{}", tcp_auth_response(&token, &nonce))?;
+    match read_line()?.as_str() {
+        TCP_REPLY_OK => {}
+        TCP_REPLY_DENIED => return Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong token")),
+        other => return Err(protocol_error(format!("unexpected reply \"{}\"", other))),
+    }
+
+    // command
+    stream.set_read_timeout(read_timeout(cmd))?;
+    request(stream, cmd)
 }
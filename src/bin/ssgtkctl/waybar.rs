@@ -0,0 +1,88 @@
+//! This module contains code that formats the status for status bars,
+//! in the JSON format of waybar custom modules, which polybar scripts can also consume.
+
+use serde::Serialize;
+use shadowsocks_gtk_rs::runtime_api_msg::{APIStateEvent, APIStatus};
+
+/// One line of output for a waybar custom module with `"return-type": "json"`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WaybarOutput {
+    pub text: String,
+    pub tooltip: String,
+    /// One of `connected`, `disconnected`, `restarting` or `error`, for styling.
+    pub class: &'static str,
+}
+
+/// The state of `ssgtk` as tracked from its status and the state events after it.
+///
+/// Durations and traffic are left out, since they are not streamed.
+#[derive(Debug, Clone)]
+pub struct BarState {
+    status: APIStatus,
+    /// The attempt number, if `sslocal` is being restarted.
+    restarting: Option<usize>,
+}
+
+impl BarState {
+    pub fn new(status: APIStatus) -> Self {
+        Self {
+            status,
+            restarting: None,
+        }
+    }
+
+    /// Update the state with an event streamed by `subscribe`.
+    pub fn apply(&mut self, event: APIStateEvent) {
+        match event {
+            APIStateEvent::Connected { profile } => {
+                self.status.running = true;
+                self.status.profile = Some(profile);
+                self.status.last_error = None;
+                self.restarting = None;
+            }
+            APIStateEvent::Disconnected => {
+                self.status.running = false;
+                self.restarting = None;
+            }
+            // restarts and errors of profiles running alongside are not shown
+            APIStateEvent::Restarting { profile, attempt } => {
+                if Some(profile) == self.status.profile {
+                    self.restarting = Some(attempt);
+                }
+            }
+            APIStateEvent::Error { profile, message } => {
+                if profile.is_none() || profile == self.status.profile {
+                    self.status.last_error = Some(message);
+                }
+            }
+        }
+    }
+
+    pub fn output(&self) -> WaybarOutput {
+        let status = &self.status;
+        let text = match (&status.profile, status.running || self.restarting.is_some()) {
+            (Some(profile), true) => profile.clone(),
+            _ => "off".into(),
+        };
+        let class = match (self.restarting, status.running, &status.last_error) {
+            (Some(_), _, _) => "restarting",
+            (None, true, _) => "connected",
+            (None, false, Some(_)) => "error",
+            (None, false, None) => "disconnected",
+        };
+
+        let mut tooltip = match (&status.profile, self.restarting) {
+            (Some(profile), Some(attempt)) => format!("Restarting \"{}\", attempt {}", profile, attempt),
+            (Some(profile), None) if status.running => format!("Connected to \"{}\"", profile),
+            _ => "Disconnected".into(),
+        };
+        if let Some(err) = &status.last_error {
+            tooltip.push_str(&format!("\nLast error: {}", err));
+        }
+        if !status.alongside.is_empty() {
+            tooltip.push_str(&format!("\nAlso running: {}", status.alongside.join(", ")));
+        }
+
+        WaybarOutput { text, tooltip, class }
+    }
+}
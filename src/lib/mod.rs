@@ -1,8 +1,11 @@
+//! The GUI-side helpers shared by the binaries.
+//!
+//! Everything that does not depend on GTK lives in `shadowsocks_gtk_rs_core`, and is re-exported here.
+
 // public members
-pub mod consts;
-pub mod notify_method;
 #[cfg(feature = "runtime-api")]
-pub mod runtime_api_msg;
+pub use shadowsocks_gtk_rs_core::runtime_api_msg;
+pub use shadowsocks_gtk_rs_core::{consts, notify_method};
 pub mod util;
 
 // private members with re-export
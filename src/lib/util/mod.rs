@@ -1,12 +1,6 @@
 //! This module contains miscellaneous helper structs and functions.
 
+pub use shadowsocks_gtk_rs_core::util::*;
+
 // public members
 pub mod hacks;
-pub mod leaky_bucket;
-
-// private members with re-export
-mod output_kind;
-pub use output_kind::*;
-
-mod sync;
-pub use sync::*;
@@ -0,0 +1,200 @@
+//! This module contains the state of the TUI, and how it reacts to keys and to events from the core.
+
+use crossbeam_channel::Receiver;
+use log::{error, info};
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    widgets::ListState,
+};
+use shadowsocks_gtk_rs_core::{
+    io::profile_loader::{Profile, ProfileFolder},
+    profile_manager::{ManagerEvent, ProfileManager},
+};
+
+/// A line in the profile list, which is either a profile or the heading of a group.
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// How many groups this row is nested in.
+    pub depth: usize,
+    pub label: String,
+    /// `None` if this row is a group.
+    pub profile: Option<Profile>,
+}
+
+/// Flatten the profiles in `folder` into rows, with each group followed by its content.
+///
+/// The base group itself is not listed.
+pub fn rows_of(folder: &ProfileFolder) -> Vec<Row> {
+    fn push_rows(folder: &ProfileFolder, depth: usize, rows: &mut Vec<Row>) {
+        match folder {
+            ProfileFolder::Profile(p) => rows.push(Row {
+                depth,
+                label: p.metadata.label(),
+                profile: Some(p.clone()),
+            }),
+            ProfileFolder::Group(g) => {
+                rows.push(Row {
+                    depth,
+                    label: g.display_name.clone(),
+                    profile: None,
+                });
+                g.content.iter().for_each(|pf| push_rows(pf, depth + 1, rows));
+            }
+        }
+    }
+
+    let mut rows = vec![];
+    match folder {
+        ProfileFolder::Group(g) => g.content.iter().for_each(|pf| push_rows(pf, 0, &mut rows)),
+        profile => push_rows(profile, 0, &mut rows),
+    }
+    rows
+}
+
+#[derive(Debug)]
+pub struct App {
+    pub rows: Vec<Row>,
+    pub list_state: ListState,
+    pub profile_manager: ProfileManager,
+    events_rx: Receiver<ManagerEvent>,
+    /// The latest outcome worth telling the user about, shown in place of the key help.
+    pub message: Option<String>,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(profiles: &ProfileFolder, profile_manager: ProfileManager, events_rx: Receiver<ManagerEvent>) -> Self {
+        let mut app = Self {
+            rows: rows_of(profiles),
+            list_state: ListState::default(),
+            profile_manager,
+            events_rx,
+            message: None,
+            quit: false,
+        };
+        app.select_active();
+        app
+    }
+
+    /// Select the row of the active profile if any, otherwise the first profile.
+    pub fn select_active(&mut self) {
+        let active = self.profile_manager.current_profile().map(|p| p.metadata.display_name);
+        let selected = self
+            .rows
+            .iter()
+            .position(|row| match (&row.profile, &active) {
+                (Some(p), Some(name)) => &p.metadata.display_name == name,
+                _ => false,
+            })
+            .or_else(|| self.rows.iter().position(|row| row.profile.is_some()));
+        self.list_state.select(selected);
+    }
+
+    /// The profile of the selected row, if it is not a group.
+    fn selected_profile(&self) -> Option<Profile> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.rows.get(i))
+            .and_then(|row| row.profile.clone())
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.list_state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.list_state.select_next(),
+            KeyCode::Home => self.list_state.select_first(),
+            KeyCode::End => self.list_state.select_last(),
+            KeyCode::Enter => match self.selected_profile() {
+                Some(profile) => self.connect(profile),
+                None => self.message = Some("Select a profile to connect to".into()),
+            },
+            KeyCode::Char('d') => self.disconnect(),
+            KeyCode::Char('r') => match self.profile_manager.current_profile() {
+                Some(profile) => self.connect(profile),
+                None => self.message = Some("Not connected".into()),
+            },
+            _ => {}
+        }
+    }
+
+    pub fn connect(&mut self, profile: Profile) {
+        let name = profile.metadata.display_name.clone();
+        self.message = match self.profile_manager.switch_to(profile) {
+            Ok(_) => {
+                info!("Switched to profile \"{}\"", name);
+                Some(format!("Connected to \"{}\"", name))
+            }
+            Err(err) => {
+                error!("Failed to switch to profile \"{}\": {}", name, err);
+                Some(format!("Cannot connect to \"{}\": {}", name, err))
+            }
+        };
+    }
+
+    fn disconnect(&mut self) {
+        self.message = match self.profile_manager.try_stop() {
+            Ok(_) => Some("Disconnected".into()),
+            Err(_) => Some("Not connected".into()),
+        };
+    }
+
+    /// Show what the core has done on its own since the last call.
+    pub fn handle_manager_events(&mut self) {
+        for event in self.events_rx.try_iter() {
+            use ManagerEvent::*;
+            self.message = Some(match event {
+                OkStop { .. } => "sslocal has stopped".into(),
+                ErrorStop { err, .. } => format!("sslocal has stopped: {}", err),
+                RestartScheduled {
+                    profile_name,
+                    attempt,
+                    delay,
+                } => format!(
+                    "Restarting \"{}\" in {:.1}s (attempt {})",
+                    profile_name,
+                    delay.as_secs_f64(),
+                    attempt
+                ),
+                SslocalError { line, .. } => line,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn profile_rows() {
+        let root = env::temp_dir().join(format!("ssgtk-tui-rows-test-{}", process::id()));
+        let yaml = "mode: \"proxy\"\nbin_path: \"/bin/sh\"\n\
+            local_addr: [\"127.0.0.1\", 1080]\nserver_addr: [\"example.org\", 443]\n\
+            password: \"foobar\"\nencrypt_method: \"aes-256-gcm\"\n";
+        for dir in ["Home", "Provider/Osaka", "Provider/Tokyo"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("profile.yaml"), yaml).unwrap();
+        }
+
+        let folder = ProfileFolder::from_path_recurse(&root, &Default::default(), &mut vec![]).unwrap();
+        let rows = rows_of(&folder)
+            .into_iter()
+            .map(|row| (row.depth, row.label, row.profile.is_some()))
+            .collect::<Vec<_>>();
+        // the base group is not listed, and each group is followed by its content
+        assert_eq!(
+            rows,
+            [
+                (0, "Home".into(), true),
+                (0, "Provider".into(), false),
+                (1, "Osaka".into(), true),
+                (1, "Tokyo".into(), true),
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
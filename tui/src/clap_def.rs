@@ -0,0 +1,31 @@
+//! This module contains code that define the CLI API.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use shadowsocks_gtk_rs_core::consts::*;
+
+#[derive(Debug, Clone, Parser)]
+#[clap(
+    name = "ssgtk-tui",
+    author,
+    version,
+    about = "A terminal UI for shadowsocks-rust client, sharing its profiles with ssgtk."
+)]
+pub struct CliArgs {
+    /// Load profiles from this directory.
+    #[clap(short = 'p', long = "profiles-dir", value_name = "PATH", default_value_os = PROFILES_DIR_PATH_DEFAULT.as_os_str())]
+    pub profiles_dir: PathBuf,
+
+    /// Connect to this profile on start, by name or by its 1-based position in the list.
+    #[clap(short = 'c', long = "connect", value_name = "PROFILE")]
+    pub connect: Option<String>,
+
+    /// Do not ask `sslocal` to report its traffic.
+    #[clap(long = "no-stats")]
+    pub no_stats: bool,
+
+    /// Write logs to this file, since they cannot be shown in the terminal.
+    #[clap(long = "log-file", value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+}
@@ -0,0 +1,100 @@
+//! A terminal UI for `sslocal`, sharing its profiles with `ssgtk`.
+//!
+//! Unlike `ssgtk`, it runs `sslocal` itself for as long as it is open, so it can be used over SSH
+//! or on machines without a desktop. Quitting it stops `sslocal`.
+
+use std::{fs::File, io, time::Duration};
+
+use clap::Parser;
+use crossbeam_channel::unbounded as unbounded_channel;
+use log::{error, info, warn, LevelFilter};
+use ratatui::{
+    crossterm::event::{self, Event, KeyEventKind},
+    DefaultTerminal,
+};
+use shadowsocks_gtk_rs_core::{
+    io::profile_loader::ProfileFolder,
+    profile_manager::{EventSink, ProfileManager},
+    redact::RedactingLogger,
+    util::leaky_bucket::NaiveLeakyBucketConfig,
+};
+use simplelog::{ConfigBuilder, WriteLogger};
+
+use crate::{app::App, clap_def::CliArgs};
+
+mod app;
+mod clap_def;
+mod ui;
+
+/// How long to wait for a key press before redrawing, so that the backlog and stats stay live.
+const TICK_INTERVAL: Duration = Duration::from_millis(250);
+
+fn main() -> io::Result<()> {
+    let args = CliArgs::parse();
+
+    // logs would garble the screen, so they are only written to a file if asked to
+    if let Some(path) = &args.log_file {
+        let logger_config = ConfigBuilder::new()
+            .add_filter_allow_str("ssgtk_tui") // crate bin
+            .add_filter_allow_str("shadowsocks_gtk_rs_core") // crate core
+            .build();
+        let logger = WriteLogger::new(LevelFilter::Info, logger_config, File::create(path)?);
+        // passwords are masked before anything is logged
+        if let Err(err) = log::set_boxed_logger(Box::new(RedactingLogger(logger))) {
+            eprintln!("Cannot initialise logger: {}", err);
+        }
+        log::set_max_level(LevelFilter::Info);
+    }
+
+    // load profiles
+    let mut problems = vec![];
+    let profiles = ProfileFolder::from_path_recurse(&args.profiles_dir, &Default::default(), &mut problems)
+        .map_err(|err| io::Error::other(format!("Cannot load profiles from {:?}: {}", args.profiles_dir, err)))?;
+    for problem in &problems {
+        warn!("Skipped {}", problem);
+    }
+
+    // start core
+    let (events_tx, events_rx) = unbounded_channel();
+    // the same restart limit as the default of `ssgtk`
+    let restart_limit = NaiveLeakyBucketConfig::new(5, Duration::from_secs(30));
+    let mut pm = ProfileManager::new(restart_limit, Default::default(), EventSink::new(events_tx));
+    pm.collect_stats = !args.no_stats;
+    let mut app = App::new(&profiles, pm, events_rx);
+    if !problems.is_empty() {
+        app.message = Some(format!("{} profile(s) cannot be loaded", problems.len()));
+    }
+    if let Some(selector) = &args.connect {
+        match profiles.lookup_selector(selector) {
+            Some(p) => {
+                app.connect(p.clone());
+                app.select_active();
+            }
+            None => app.message = Some(format!("Profile \"{}\" not found", selector)),
+        }
+    }
+
+    // run until quit; the terminal is restored even on error
+    let mut terminal = ratatui::init();
+    let res = run(&mut terminal, &mut app);
+    ratatui::restore();
+    if let Err(err) = &res {
+        error!("The terminal UI has failed: {}", err);
+    }
+    info!("Quitting; sslocal will be stopped");
+    res
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        if event::poll(TICK_INTERVAL)? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key),
+                _ => {}
+            }
+        }
+        app.handle_manager_events();
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! This module contains code that draws the TUI.
+
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, Paragraph, Wrap},
+    Frame,
+};
+use shadowsocks_gtk_rs_core::{
+    log_parser,
+    stats::format_bytes,
+    util::{format_duration, mutex_lock, rwlock_read},
+};
+
+use crate::app::App;
+
+const KEY_HELP: &str = "↑↓ select · Enter connect · d disconnect · r restart · q quit";
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [body, bottom] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(body);
+    let [status_area, backlog_area] = Layout::vertical([Constraint::Length(7), Constraint::Min(0)]).areas(right);
+
+    // profiles
+    let active = app.profile_manager.current_profile().map(|p| p.metadata.display_name);
+    let items = app.rows.iter().map(|row| {
+        let indent = "  ".repeat(row.depth);
+        match &row.profile {
+            Some(p) if Some(&p.metadata.display_name) == active.as_ref() => {
+                ListItem::new(format!("{}● {}", indent, row.label)).green()
+            }
+            Some(_) => ListItem::new(format!("{}  {}", indent, row.label)),
+            None => ListItem::new(format!("{}{}/", indent, row.label)).bold(),
+        }
+    });
+    let list = List::new(items)
+        .block(Block::bordered().title("Profiles"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, left, &mut app.list_state);
+
+    // status & stats
+    let pm = &app.profile_manager;
+    let mut status = vec![];
+    match (&active, pm.instance_status()) {
+        (Some(name), Some(inst)) => {
+            status.push(Line::from(format!("Connected to \"{}\"", name)).green());
+            status.push(Line::from(format!(
+                "Connected for {}, restarted {} time(s)",
+                format_duration(inst.connected_for.as_secs()),
+                inst.restarts
+            )));
+            let stats = rwlock_read(&pm.stats);
+            match (pm.collect_stats, stats.updated_at) {
+                (true, Some(_)) => {
+                    status.push(Line::from(format!(
+                        "Sent {} · Received {}",
+                        format_bytes(stats.tx_bytes),
+                        format_bytes(stats.rx_bytes)
+                    )));
+                    status.push(Line::from(stats.rate_summary()));
+                }
+                (true, None) => status.push(Line::from("Traffic not reported yet").dim()),
+                (false, _) => status.push(Line::from("Traffic not collected").dim()),
+            }
+            if let Some(err) = inst.last_error {
+                status.push(Line::from(format!("Last error: {}", err)).red());
+            }
+        }
+        _ => status.push(Line::from("Disconnected").dim()),
+    }
+    let status = Paragraph::new(status)
+        .block(Block::bordered().title("Status"))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(status, status_area);
+
+    // backlog, newest at the bottom
+    let height = backlog_area.height.saturating_sub(2) as usize;
+    let lines = mutex_lock(&pm.backlog)
+        .tail(height)
+        .map(|line| Line::from(log_parser::strip_ansi(line.trim_end()).into_owned()))
+        .collect::<Vec<_>>();
+    let backlog = Paragraph::new(lines).block(Block::bordered().title("Backlog"));
+    frame.render_widget(backlog, backlog_area);
+
+    // message or key help
+    let bottom_line = match &app.message {
+        Some(msg) => Line::from(format!("{}  ({})", msg, KEY_HELP)),
+        None => Line::from(KEY_HELP).dim(),
+    };
+    frame.render_widget(Paragraph::new(bottom_line), bottom);
+}